@@ -69,6 +69,8 @@ if startupMessageActive != "false" {
 	// Upload Routews
 	mux.HandleFunc("POST /upload", handlers.UploadHandler)
 	mux.HandleFunc("DELETE /upload/delete", handlers.DeleteAPIHandler)
+	mux.HandleFunc("GET /upload/stat", handlers.StatAPIHandler)
+	mux.HandleFunc("GET /upload/list", handlers.ListAPIHandler)
 
 	if config.AppConfig.Cache.Enabled {
 		InitConsoleUI(mux)
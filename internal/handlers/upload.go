@@ -10,6 +10,7 @@ import (
 	_ "image/png"  // Support PNG
 	"io"
 	"net/http"
+	"strconv"
 	"strings"
 	"time"
 
@@ -248,8 +249,120 @@ func DeleteAPIHandler(w http.ResponseWriter, r *http.Request) {
 	})
 }
 
+// StatAPIHandler returns the metadata of one asset, by key or id, without its data.
+// GET /upload/stat?key=... | ?id=...
+func StatAPIHandler(w http.ResponseWriter, r *http.Request) {
+	if !hasUploadSecret(w, r) {
+		return
+	}
+
+	targetKey := r.URL.Query().Get("key")
+	assetID := r.URL.Query().Get("id")
+	if targetKey == "" && assetID == "" {
+		utils.WriteError(w, http.StatusBadRequest, utils.ErrRequestInvalid, "Parameter 'key' or 'id' is required.")
+		return
+	}
+
+	if assetID == "" {
+		var mapping database.KeyMapping
+		if err := database.DB.Where("key = ?", targetKey).First(&mapping).Error; err != nil {
+			utils.WriteError(w, http.StatusNotFound, utils.ErrResourceNotFound, "Key not found.")
+			return
+		}
+		assetID = mapping.ImageID
+	}
+
+	var img database.Image
+	if err := database.DB.Select("id, width, height, format, size, created_at, updated_at").First(&img, "id = ?", assetID).Error; err != nil {
+		utils.WriteError(w, http.StatusNotFound, utils.ErrResourceNotFound, "Asset not found.")
+		return
+	}
+
+	keys := []string{}
+	database.DB.Table("key_mappings").Where("image_id = ?", assetID).Order("created_at").Pluck("key", &keys)
+
+	urlKey := assetID
+	if len(keys) > 0 {
+		urlKey = keys[0]
+	}
+
+	utils.WriteJSON(w, http.StatusOK, map[string]interface{}{
+		"status":     "success",
+		"avatar_id":  img.ID,
+		"keys":       keys,
+		"width":      img.Width,
+		"height":     img.Height,
+		"format":     img.Format,
+		"size":       img.Size,
+		"created_at": img.CreatedAt,
+		"updated_at": img.UpdatedAt,
+		"url":        config.AppConfig.GetBaseUrl() + "/u/" + urlKey,
+	})
+}
+
+// ListAPIHandler lists keys in key order, optionally under a prefix.
+// Paged by key: pass the returned 'next' as 'after' for the following page.
+// GET /upload/list?prefix=team/&after=...&limit=100
+func ListAPIHandler(w http.ResponseWriter, r *http.Request) {
+	if !hasUploadSecret(w, r) {
+		return
+	}
+
+	prefix := r.URL.Query().Get("prefix")
+	after := r.URL.Query().Get("after")
+	limit, _ := strconv.Atoi(r.URL.Query().Get("limit"))
+	if limit < 1 || limit > 1000 {
+		limit = 100
+	}
+
+	type listItem struct {
+		Key       string    `json:"key"`
+		AvatarID  string    `json:"avatar_id"`
+		Size      int64     `json:"size"`
+		Format    string    `json:"format"`
+		UpdatedAt time.Time `json:"updated_at"`
+	}
+	items := []listItem{}
+
+	// '%' and '_' in the prefix are literal
+	escaped := strings.NewReplacer(`\`, `\\`, "%", `\%`, "_", `\_`).Replace(prefix)
+	query := database.DB.Table("key_mappings AS k").
+		Select("k.key AS key, k.image_id AS avatar_id, i.size, i.format, i.updated_at").
+		Joins("JOIN images i ON i.id = k.image_id").
+		Where(`k.key LIKE ? ESCAPE '\'`, escaped+"%")
+	if after != "" {
+		query = query.Where("k.key > ?", after)
+	}
+	if err := query.Order("k.key").Limit(limit).Scan(&items).Error; err != nil {
+		utils.WriteError(w, http.StatusInternalServerError, utils.ErrServerInternal, "Listing failed.")
+		return
+	}
+
+	next := ""
+	if len(items) == limit {
+		next = items[len(items)-1].Key
+	}
+
+	utils.WriteJSON(w, http.StatusOK, map[string]interface{}{
+		"status": "success",
+		"items":  items,
+		"next":   next,
+	})
+}
+
 // --- Helpers ---
 
+// hasUploadSecret checks 'X-Secret-Key' and writes the 403 itself when it does not match.
+func hasUploadSecret(w http.ResponseWriter, r *http.Request) bool {
+	clientSecret := r.Header.Get("X-Secret-Key")
+	serverSecret := config.AppConfig.Security.UploadSecret
+	if subtle.ConstantTimeCompare([]byte(clientSecret), []byte(serverSecret)) != 1 {
+		utils.WriteError(w, http.StatusForbidden, utils.ErrAuthInvalid, "Invalid secret key.")
+		return false
+	}
+	return true
+}
+
 type ImageMeta struct {
 	Width, Height int
 	Format        string
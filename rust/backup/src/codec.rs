@@ -0,0 +1,36 @@
+use octa_warden_core::encryption::Key;
+
+/// How objects are stored: zstd, then AES-256-GCM (nonce, ciphertext, tag,
+/// the layout of `warden.encryption`) when the repository is encrypted.
+pub struct Codec {
+    level: i32,
+    key: Option<Key>,
+}
+
+impl Codec {
+    pub fn new(level: i32, key: Option<Key>) -> Self {
+        Self { level, key }
+    }
+
+    pub fn encrypted(&self) -> bool {
+        self.key.is_some()
+    }
+
+    pub fn pack(&self, plain: &[u8]) -> Result<Vec<u8>, String> {
+        let compressed = zstd::encode_all(plain, self.level).map_err(|e| e.to_string())?;
+        match &self.key {
+            Some(key) => key.seal(&compressed),
+            None => Ok(compressed),
+        }
+    }
+
+    pub fn unpack(&self, stored: &[u8]) -> Result<Vec<u8>, String> {
+        let compressed = match &self.key {
+            Some(key) => key
+                .open(stored)
+                .map_err(|e| format!("does not decrypt: {}", e))?,
+            None => stored.to_vec(),
+        };
+        zstd::decode_all(compressed.as_slice()).map_err(|e| format!("does not decompress: {}", e))
+    }
+}
@@ -0,0 +1,182 @@
+use crate::pool;
+use crate::repository::{self, Manifest, Repository};
+use chrono::Utc;
+use octa_warden_core::cas;
+use octa_warden_core::db::{self, OpenOptions};
+use octa_warden_core::export::sha256_hex;
+use rusqlite::types::ValueRef;
+use rusqlite::Connection;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::info;
+
+/// Maps each stripped `images` row to its BLOB in the repository. Only ever
+/// exists in backed-up copies; restore drops it.
+pub const BLOB_TABLE: &str = "octa_backup_blobs";
+
+/// Maps each stripped `blobs` row of a content-addressed database to its
+/// BLOB in the repository, as [`BLOB_TABLE`] does for inline images.
+pub const CAS_TABLE: &str = "octa_backup_cas";
+
+/// Backs `db_path` up as a new generation: a snapshot with every image BLOB
+/// moved out into `blobs/`, stored once per content. BLOBs the repository
+/// already has are not written again unless `full`.
+pub fn create(
+    repo: &Repository,
+    db_path: &str,
+    open: &OpenOptions,
+    full: bool,
+    jobs: usize,
+) -> Result<Manifest, String> {
+    let generation = repo.new_generation()?;
+    let snapshot = db::take_snapshot(db_path, open).map_err(|e| e.to_string())?;
+    let conn = Connection::open(snapshot.path()).map_err(|e| e.to_string())?;
+    conn.prepare("SELECT data FROM images LIMIT 0")
+        .map_err(|e| format!("not an Octa database: {}", e))?;
+
+    let stored = if full { HashSet::new() } else { repo.blobs()? };
+    info!(
+        tag = "→",
+        generation = %generation,
+        stored = stored.len(),
+        "Snapshot taken, writing blobs"
+    );
+
+    let content_addressed = cas::enabled(&conn).map_err(|e| e.to_string())?;
+    let written = AtomicU64::new(0);
+    let stored_bytes = AtomicU64::new(0);
+    let mut assets = 0u64;
+    let mut blob_bytes = 0u64;
+    let mut hashes: Vec<String> = Vec::new();
+    pool::fan_out(
+        jobs,
+        |send| {
+            conn.execute_batch(&format!(
+                "CREATE TABLE {} (image_rowid INTEGER PRIMARY KEY, sha256 TEXT NOT NULL)",
+                BLOB_TABLE
+            ))
+            .map_err(|e| e.to_string())?;
+            let mut insert = conn
+                .prepare(&format!(
+                    "INSERT INTO {} (image_rowid, sha256) VALUES (?1, ?2)",
+                    BLOB_TABLE
+                ))
+                .map_err(|e| e.to_string())?;
+            let mut seen = HashSet::new();
+            // Queues a BLOB the first time its content comes up; false once
+            // the writers have stopped.
+            let mut queue = |sha: String, data: &[u8]| {
+                if !seen.insert(sha.clone()) {
+                    return true;
+                }
+                blob_bytes += data.len() as u64;
+                hashes.push(sha.clone());
+                stored.contains(&sha) || send((sha, data.to_vec()))
+            };
+            let mut stmt = conn
+                .prepare("SELECT rowid, data FROM images")
+                .map_err(|e| e.to_string())?;
+            let mut rows = stmt.query([]).map_err(|e| e.to_string())?;
+            while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+                assets += 1;
+                // NULL (a content-addressed asset, its blob follows) or TEXT
+                // (e.g. base64) stays in the database as it is.
+                let ValueRef::Blob(data) = row.get_ref(1).map_err(|e| e.to_string())? else {
+                    continue;
+                };
+                let rowid: i64 = row.get(0).map_err(|e| e.to_string())?;
+                let sha = sha256_hex(data);
+                insert
+                    .execute(rusqlite::params![rowid, sha])
+                    .map_err(|e| e.to_string())?;
+                if !queue(sha, data) {
+                    return Ok(());
+                }
+            }
+            if !content_addressed {
+                return Ok(());
+            }
+
+            conn.execute_batch(&format!(
+                "CREATE TABLE {} (hash TEXT PRIMARY KEY, sha256 TEXT NOT NULL)",
+                CAS_TABLE
+            ))
+            .map_err(|e| e.to_string())?;
+            let mut insert = conn
+                .prepare(&format!(
+                    "INSERT INTO {} (hash, sha256) VALUES (?1, ?2)",
+                    CAS_TABLE
+                ))
+                .map_err(|e| e.to_string())?;
+            let mut stmt = conn
+                .prepare("SELECT hash, data FROM blobs")
+                .map_err(|e| e.to_string())?;
+            let mut rows = stmt.query([]).map_err(|e| e.to_string())?;
+            while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+                let ValueRef::Blob(data) = row.get_ref(1).map_err(|e| e.to_string())? else {
+                    continue;
+                };
+                let hash: String = row.get(0).map_err(|e| e.to_string())?;
+                // Hashed again: a corrupt blob is backed up as it is, not
+                // under the name it should match.
+                let sha = sha256_hex(data);
+                insert
+                    .execute(rusqlite::params![hash, sha])
+                    .map_err(|e| e.to_string())?;
+                if !queue(sha, data) {
+                    break;
+                }
+            }
+            Ok(())
+        },
+        |(sha, data): (String, Vec<u8>)| {
+            let packed = repo.codec.pack(&data)?;
+            repo.store.put(&repository::blob_name(&sha), &packed)?;
+            written.fetch_add(1, Ordering::Relaxed);
+            stored_bytes.fetch_add(packed.len() as u64, Ordering::Relaxed);
+            Ok(())
+        },
+    )?;
+
+    let mut strip = format!(
+        "UPDATE images SET data = zeroblob(0) WHERE rowid IN (SELECT image_rowid FROM {});",
+        BLOB_TABLE
+    );
+    if content_addressed {
+        strip += &format!(
+            "UPDATE blobs SET data = zeroblob(0) WHERE hash IN (SELECT hash FROM {});",
+            CAS_TABLE
+        );
+    }
+    conn.execute_batch(&(strip + "VACUUM;"))
+        .map_err(|e| e.to_string())?;
+    drop(conn);
+    let database = std::fs::read(snapshot.path()).map_err(|e| e.to_string())?;
+    let packed = repo.codec.pack(&database)?;
+    repo.store
+        .put(&repository::database_name(&generation), &packed)?;
+    repo.put_blob_list(&generation, &hashes)?;
+
+    let blobs_written = written.into_inner();
+    let manifest = Manifest {
+        format: repository::FORMAT,
+        name: generation.clone(),
+        created_at: Utc::now(),
+        tool: format!("octa-backup {}", env!("CARGO_PKG_VERSION")),
+        source: db_path.to_string(),
+        kind: if blobs_written == hashes.len() as u64 {
+            "full"
+        } else {
+            "incremental"
+        }
+        .to_string(),
+        assets,
+        blobs: hashes.len() as u64,
+        blobs_written,
+        bytes: database.len() as u64 + blob_bytes,
+        stored_bytes: stored_bytes.into_inner() + packed.len() as u64,
+        database_sha256: sha256_hex(&database),
+    };
+    repo.put_json(&repository::manifest_name(&generation), &manifest)?;
+    Ok(manifest)
+}
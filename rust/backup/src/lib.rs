@@ -0,0 +1,440 @@
+use clap::{Parser, Subcommand};
+use octa_config::{ConfigError, DatabaseConfig, Validate};
+use octa_errors::{Error, Kind};
+use octa_logging::LogFormat;
+use octa_warden_core::db::OpenOptions;
+use octa_warden_core::encryption::{EncryptionConfig, Key};
+use octa_warden_core::growth::format_bytes;
+use repository::Repository;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+use store::{S3Settings, Store};
+use tracing::{error, info, Level};
+
+mod codec;
+mod create;
+mod pool;
+mod repository;
+mod restore;
+mod store;
+mod verify;
+
+/*
+OCTA-BACKUP: Backup lifecycle for the Octa database
+=============================================
+Mission: Keep restorable generations of the asset store on disk or in S3.
+         Each generation is a snapshot whose image BLOBs are stored once per
+         content (zstd, optionally AES-256-GCM), so later runs only write
+         what changed. Restore, verify and retention of N generations.
+Safety:  Reads the live database through a snapshot. Restore assembles the
+         database next to its destination and only replaces an existing
+         file with --force. One writer per repository at a time.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Back up, restore and verify the Octa database"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long, global = true)]
+    config: Option<String>,
+
+    /// Repository: a directory or s3://bucket/prefix (overrides backup.target)
+    #[arg(long, global = true, env = "OCTA_BACKUP_TARGET")]
+    target: Option<String>,
+
+    /// Objects transferred in parallel (overrides backup.jobs)
+    #[arg(long, global = true)]
+    jobs: Option<usize>,
+
+    /// Log format
+    #[arg(
+        long,
+        env = "OCTA_LOG_FORMAT",
+        global = true,
+        value_enum,
+        default_value_t = LogFormat::Pretty
+    )]
+    log_format: LogFormat,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Back the database up as a new generation, then apply retention
+    Create {
+        /// SQLite database to back up (overrides database.path)
+        #[arg(long = "db", env = "OCTA_DB_PATH", value_name = "PATH")]
+        db_path: Option<String>,
+
+        /// Milliseconds to wait on a locked database before giving up (SQLITE_BUSY)
+        #[arg(long, default_value_t = 5000)]
+        busy_timeout: u64,
+
+        /// Write every blob again, even those the repository already has
+        #[arg(long)]
+        full: bool,
+
+        /// Generations to keep (overrides backup.keep)
+        #[arg(long)]
+        keep: Option<usize>,
+    },
+    /// List the generations in the repository
+    List,
+    /// Rebuild a generation's database at a path
+    Restore {
+        /// Generation name, or `latest`
+        #[arg(default_value = "latest")]
+        generation: String,
+
+        /// Where to write the database (stop the server first if it is the live one)
+        #[arg(long, value_name = "PATH")]
+        to: PathBuf,
+
+        /// Replace the file at --to if it exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check generations can be restored, without restoring them
+    Verify {
+        /// Generation name, or `latest`
+        #[arg(default_value = "latest", conflicts_with = "all")]
+        generation: String,
+
+        /// Verify every generation
+        #[arg(long)]
+        all: bool,
+
+        /// Only check that every blob is stored, without reading it
+        #[arg(long)]
+        quick: bool,
+    },
+    /// Apply retention: delete old generations and the blobs only they needed
+    Prune {
+        /// Generations to keep (overrides backup.keep)
+        #[arg(long)]
+        keep: Option<usize>,
+
+        /// Actually delete (default: list what would be deleted)
+        #[arg(long)]
+        execute: bool,
+    },
+}
+
+/// The parts of config.yaml octa-backup reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    database: DatabaseConfig,
+    backup: BackupConfig,
+}
+
+/// `backup:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct BackupConfig {
+    /// A directory, or `s3://bucket/prefix`.
+    target: String,
+    /// Generations kept by retention.
+    keep: usize,
+    /// zstd level, 1-22.
+    level: i32,
+    jobs: usize,
+    /// Whether a new repository is encrypted; an existing one keeps its setting.
+    encrypt: bool,
+    /// Environment variable holding the key, base64 or hex.
+    key_env: String,
+    /// Command that prints the key instead, run without a shell.
+    key_command: Option<Vec<String>>,
+    s3: S3Settings,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            target: String::new(),
+            keep: 7,
+            level: 3,
+            jobs: 8,
+            encrypt: false,
+            key_env: "OCTA_BACKUP_KEY".to_string(),
+            key_command: None,
+            s3: S3Settings::default(),
+        }
+    }
+}
+
+impl BackupConfig {
+    fn key(&self) -> Result<Key, String> {
+        Key::load(&EncryptionConfig {
+            key_env: self.key_env.clone(),
+            key_command: self.key_command.clone(),
+        })
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        let backup = &self.backup;
+        if backup.keep == 0 {
+            problems.push(("backup.keep".to_string(), "must be at least 1".to_string()));
+        }
+        if !(1..=22).contains(&backup.level) {
+            problems.push(("backup.level".to_string(), "must be 1-22".to_string()));
+        }
+        if backup.jobs == 0 {
+            problems.push(("backup.jobs".to_string(), "must be at least 1".to_string()));
+        }
+        if backup
+            .key_command
+            .as_ref()
+            .is_some_and(|argv| argv.is_empty())
+        {
+            problems.push((
+                "backup.key_command".to_string(),
+                "must name a program".to_string(),
+            ));
+        }
+        problems.extend(octa_config::check_url(
+            "backup.s3.endpoint",
+            backup.s3.endpoint.as_deref(),
+        ));
+        problems
+    }
+}
+
+/// Without a file, the environment and arguments alone configure backups.
+fn load_config(path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    match octa_config::discover(path.map(Path::new)) {
+        Some(path) => octa_config::load(&path),
+        None => {
+            let config: FileConfig = octa_config::from_env()?;
+            octa_config::check(Path::new("<environment>"), config)
+        }
+    }
+}
+
+/// The octa-backup command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa backup` the arguments it was given.
+pub fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, Level::INFO, false, false);
+
+    let config = match load_config(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let target = args.target.as_deref().unwrap_or(&config.backup.target);
+    if target.trim().is_empty() {
+        error!(
+            tag = "FATAL",
+            "No repository (pass --target or set backup.target)"
+        );
+        return Kind::Config.exit_code();
+    }
+
+    match run(&args, &config, target) {
+        Ok(code) => code,
+        Err(e) => {
+            error!(tag = "FATAL", repository = %target, reason = %e, "Backup command failed");
+            e.exit_code()
+        }
+    }
+}
+
+fn run(args: &Args, config: &FileConfig, target: &str) -> Result<ExitCode, Error> {
+    let backup = &config.backup;
+    let jobs = args.jobs.unwrap_or(backup.jobs).max(1);
+    let store = Store::open(target, &backup.s3).map_err(|e| Error::new(Kind::Config, e))?;
+    // Failures past this point are the repository's.
+    let kind = store.failure();
+    let failed = |e: String| Error::new(kind, e);
+    // Only `create` starts a repository.
+    let init = matches!(args.command, Command::Create { .. }).then_some(backup.encrypt);
+    let repo = Repository::open(store, backup.level, init, || backup.key()).map_err(failed)?;
+
+    match &args.command {
+        Command::Create {
+            db_path,
+            busy_timeout,
+            full,
+            keep,
+        } => {
+            let db_path = db_path.as_deref().unwrap_or(&config.database.path);
+            if db_path.trim().is_empty() {
+                return Err(Error::new(
+                    Kind::Config,
+                    "no database (pass --db or set database.path)",
+                ));
+            }
+            if !Path::new(db_path).exists() {
+                return Err(Error::new(
+                    Kind::NoInput,
+                    format!("database file not found: {}", db_path),
+                ));
+            }
+            let open = OpenOptions {
+                busy_timeout: Duration::from_millis(*busy_timeout),
+                immutable: false,
+            };
+            info!(
+                tag = "→",
+                database = %db_path,
+                repository = %repo.store.describe(),
+                encrypted = repo.codec.encrypted(),
+                "Backing up"
+            );
+            let manifest = create::create(&repo, db_path, &open, *full, jobs).map_err(failed)?;
+            info!(
+                tag = "OK",
+                generation = %manifest.name,
+                kind = %manifest.kind,
+                assets = manifest.assets,
+                blobs = manifest.blobs,
+                written = manifest.blobs_written,
+                size = %format_bytes(manifest.bytes as f64),
+                stored = %format_bytes(manifest.stored_bytes as f64),
+                "Generation written"
+            );
+            prune(&repo, keep.unwrap_or(backup.keep), false, true)
+        }
+        Command::List => {
+            let generations = repo.generations().map_err(failed)?;
+            for generation in &generations {
+                let m = repo.manifest(generation).map_err(failed)?;
+                info!(
+                    tag = "GEN",
+                    generation = %m.name,
+                    created_at = %m.created_at.to_rfc3339(),
+                    kind = %m.kind,
+                    assets = m.assets,
+                    size = %format_bytes(m.bytes as f64),
+                    stored = %format_bytes(m.stored_bytes as f64),
+                    "Generation"
+                );
+            }
+            info!(
+                tag = "OK",
+                generations = generations.len(),
+                encrypted = repo.info.encrypted,
+                "Repository listed"
+            );
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Restore {
+            generation,
+            to,
+            force,
+        } => {
+            let manifest = repo
+                .manifest(&repo.resolve(generation).map_err(failed)?)
+                .map_err(failed)?;
+            info!(
+                tag = "→",
+                generation = %manifest.name,
+                to = %to.display(),
+                assets = manifest.assets,
+                "Restoring"
+            );
+            let restored = restore::restore(&repo, &manifest, to, *force, jobs).map_err(failed)?;
+            info!(
+                tag = "OK",
+                generation = %manifest.name,
+                to = %to.display(),
+                assets = restored.assets,
+                size = %format_bytes(restored.bytes as f64),
+                "Database restored"
+            );
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Verify {
+            generation,
+            all,
+            quick,
+        } => {
+            let generations = if *all {
+                repo.generations().map_err(failed)?
+            } else {
+                vec![repo.resolve(generation).map_err(failed)?]
+            };
+            let mut damaged = 0;
+            for generation in &generations {
+                let manifest = repo.manifest(generation).map_err(failed)?;
+                match verify::verify(&repo, &manifest, *quick, jobs) {
+                    Ok(verified) if verified.ok() => info!(
+                        tag = "OK",
+                        generation = %generation,
+                        blobs = verified.blobs,
+                        "Generation verified"
+                    ),
+                    Ok(verified) => {
+                        damaged += 1;
+                        error!(
+                            tag = "FAIL",
+                            generation = %generation,
+                            blobs = verified.blobs,
+                            missing = verified.missing,
+                            damaged = verified.damaged,
+                            "Generation cannot be fully restored"
+                        );
+                    }
+                    Err(e) => {
+                        damaged += 1;
+                        error!(tag = "FAIL", generation = %generation, reason = %e, "Generation cannot be restored");
+                    }
+                }
+            }
+            // Generations that cannot be restored are damaged input.
+            Ok(if damaged == 0 {
+                ExitCode::SUCCESS
+            } else {
+                Kind::Data.exit_code()
+            })
+        }
+        Command::Prune { keep, execute } => {
+            prune(&repo, keep.unwrap_or(backup.keep), true, *execute)
+        }
+    }
+}
+
+fn prune(repo: &Repository, keep: usize, sweep: bool, execute: bool) -> Result<ExitCode, Error> {
+    if keep == 0 {
+        return Err(Error::new(Kind::Usage, "--keep must be at least 1"));
+    }
+    let pruned = repo
+        .prune(keep, sweep, execute)
+        .map_err(|e| Error::new(repo.store.failure(), e))?;
+    if !execute {
+        for generation in &pruned.generations {
+            info!(tag = "PRUNE", generation = %generation, "Would delete");
+        }
+        info!(
+            tag = "OK",
+            generations = pruned.generations.len(),
+            blobs = pruned.blobs,
+            "Dry run: nothing deleted. Re-run with --execute to apply retention"
+        );
+    } else if !pruned.generations.is_empty() || pruned.blobs > 0 {
+        info!(
+            tag = "OK",
+            generations = pruned.generations.len(),
+            blobs = pruned.blobs,
+            keep,
+            "Retention applied"
+        );
+    } else {
+        info!(tag = "→", keep, "Nothing to prune");
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,92 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+
+/// Runs `work` on `jobs` threads over what `produce` sends from the calling
+/// thread (e.g. rows of an open statement). The channel is bounded, so a slow
+/// upload holds back reading instead of buffering the database. Stops at the
+/// first error; `send` then returns false.
+pub fn fan_out<T: Send>(
+    jobs: usize,
+    produce: impl FnOnce(&dyn Fn(T) -> bool) -> Result<(), String>,
+    work: impl Fn(T) -> Result<(), String> + Sync,
+) -> Result<(), String> {
+    let failed: Mutex<Option<String>> = Mutex::new(None);
+    let stop = AtomicBool::new(false);
+    let (tx, rx) = mpsc::sync_channel::<T>(jobs * 2);
+    let rx = Mutex::new(rx);
+
+    let produced = thread::scope(|scope| {
+        for _ in 0..jobs {
+            let (rx, work, failed, stop) = (&rx, &work, &failed, &stop);
+            scope.spawn(move || loop {
+                let Ok(item) = rx.lock().unwrap().recv() else {
+                    break;
+                };
+                // After a failure, keep draining so the producer never blocks.
+                if stop.load(Ordering::Relaxed) {
+                    continue;
+                }
+                if let Err(e) = work(item) {
+                    failed.lock().unwrap().get_or_insert(e);
+                    stop.store(true, Ordering::Relaxed);
+                }
+            });
+        }
+        let send = |item: T| !stop.load(Ordering::Relaxed) && tx.send(item).is_ok();
+        let produced = produce(&send);
+        // Lets the workers drain the channel and exit.
+        drop(tx);
+        produced
+    });
+
+    match failed.into_inner().unwrap() {
+        Some(e) => Err(e),
+        None => produced,
+    }
+}
+
+/// Runs `work` on `jobs` threads over `items` and hands each result to
+/// `consume` on the calling thread (e.g. to write it to a connection), in
+/// completion order. Stops at the first error of either.
+pub fn fan_in<T: Sync, R: Send>(
+    items: &[T],
+    jobs: usize,
+    work: impl Fn(&T) -> Result<R, String> + Sync,
+    mut consume: impl FnMut(R) -> Result<(), String>,
+) -> Result<(), String> {
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (tx, rx) = mpsc::sync_channel(jobs * 2);
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let tx = tx.clone();
+            let (next, stop, work) = (&next, &stop, &work);
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let Some(item) = items.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        break;
+                    };
+                    if tx.send(work(item)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        let mut result = Ok(());
+        for outcome in &rx {
+            if let Err(e) = outcome.and_then(&mut consume) {
+                result = Err(e);
+                break;
+            }
+        }
+        stop.store(true, Ordering::Relaxed);
+        // Unblocks workers waiting to send, so the scope can join them.
+        drop(rx);
+        result
+    })
+}
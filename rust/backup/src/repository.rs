@@ -0,0 +1,322 @@
+use crate::codec::Codec;
+use crate::store::Store;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use octa_warden_core::encryption::Key;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use tracing::info;
+
+/// Layout version of `repository.json` and the manifests.
+pub const FORMAT: u32 = 1;
+
+const INFO: &str = "repository.json";
+const GENERATIONS: &str = "generations/";
+const BLOBS: &str = "blobs/";
+
+/// Sealed into `repository.json`, so a run with another key stops before
+/// it mixes blobs nobody can read back.
+const KEY_CHECK: &[u8] = b"octa-backup";
+
+/// `repository.json`: written by the first backup, fixed afterwards.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Info {
+    pub format: u32,
+    pub created_at: DateTime<Utc>,
+    pub encrypted: bool,
+    /// [`KEY_CHECK`] sealed with the key, base64.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_check: Option<String>,
+}
+
+/// `generations/<name>/manifest.json`, written last: a generation without
+/// one is incomplete and never restored.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: u32,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub tool: String,
+    /// The database that was backed up.
+    pub source: String,
+    /// `full` when this run wrote every blob, else `incremental`.
+    pub kind: String,
+    /// Rows in `images`.
+    pub assets: u64,
+    /// Distinct image BLOBs the generation needs.
+    pub blobs: u64,
+    /// Of those, the ones this run wrote; the rest were already stored.
+    pub blobs_written: u64,
+    /// Plain bytes of the database and all its BLOBs.
+    pub bytes: u64,
+    /// Stored bytes this run added to the repository.
+    pub stored_bytes: u64,
+    /// SHA-256 of the plain database file, BLOBs stripped.
+    pub database_sha256: String,
+}
+
+/// Object names of one generation.
+pub fn manifest_name(generation: &str) -> String {
+    format!("{}{}/manifest.json", GENERATIONS, generation)
+}
+
+pub fn database_name(generation: &str) -> String {
+    format!("{}{}/database.db", GENERATIONS, generation)
+}
+
+/// The SHA-256 of each BLOB the generation needs, one per line, zstd.
+pub fn blob_list_name(generation: &str) -> String {
+    format!("{}{}/blobs.zst", GENERATIONS, generation)
+}
+
+/// BLOBs are stored once per content, fanned out by their first hex byte.
+pub fn blob_name(sha256: &str) -> String {
+    format!("{}{}/{}", BLOBS, &sha256[..2], sha256)
+}
+
+#[derive(Debug, Default)]
+pub struct Pruned {
+    pub generations: Vec<String>,
+    pub blobs: u64,
+}
+
+pub struct Repository {
+    pub store: Store,
+    pub codec: Codec,
+    pub info: Info,
+}
+
+impl Repository {
+    /// Opens the repository in `store`. Without one there, `init` creates it
+    /// (encrypted when `init` is `Some(true)`); otherwise that is an error.
+    /// `key` is only called for encrypted repositories.
+    pub fn open(
+        store: Store,
+        level: i32,
+        init: Option<bool>,
+        key: impl FnOnce() -> Result<Key, String>,
+    ) -> Result<Self, String> {
+        let info = match store.get(INFO)? {
+            Some(data) => {
+                serde_json::from_slice::<Info>(&data).map_err(|e| format!("{}: {}", INFO, e))?
+            }
+            None => {
+                let Some(encrypted) = init else {
+                    return Err(format!("no backup repository at {}", store.describe()));
+                };
+                let key = encrypted.then(key).transpose()?;
+                let info = Info {
+                    format: FORMAT,
+                    created_at: Utc::now(),
+                    encrypted,
+                    key_check: match &key {
+                        Some(key) => Some(STANDARD.encode(key.seal(KEY_CHECK)?)),
+                        None => None,
+                    },
+                };
+                store.put(INFO, &to_json(&info)?)?;
+                info!(tag = "OK", repository = %store.describe(), encrypted, "Repository created");
+                return Ok(Self {
+                    codec: Codec::new(level, key),
+                    store,
+                    info,
+                });
+            }
+        };
+        if info.format > FORMAT {
+            return Err(format!(
+                "repository format {} is newer than this octa-backup understands ({})",
+                info.format, FORMAT
+            ));
+        }
+        if init == Some(true) && !info.encrypted {
+            return Err(format!(
+                "{} is not encrypted; start a new repository to encrypt backups",
+                store.describe()
+            ));
+        }
+
+        let key = if info.encrypted {
+            let key = key()?;
+            let check = info
+                .key_check
+                .as_deref()
+                .and_then(|check| STANDARD.decode(check).ok())
+                .ok_or("repository.json has no key check")?;
+            if key.open(&check).ok().as_deref() != Some(KEY_CHECK) {
+                return Err(
+                    "the key does not match the one the repository was created with".into(),
+                );
+            }
+            Some(key)
+        } else {
+            None
+        };
+        Ok(Self {
+            codec: Codec::new(level, key),
+            store,
+            info,
+        })
+    }
+
+    /// Complete generations, oldest first.
+    pub fn generations(&self) -> Result<Vec<String>, String> {
+        Ok(self
+            .scan()?
+            .into_iter()
+            .filter(|(_, complete)| *complete)
+            .map(|(name, _)| name)
+            .collect())
+    }
+
+    /// Every generation with an object, oldest first, and whether it has its manifest.
+    fn scan(&self) -> Result<BTreeMap<String, bool>, String> {
+        let mut generations = BTreeMap::new();
+        for name in self.store.list(GENERATIONS)? {
+            let Some((generation, object)) = name
+                .strip_prefix(GENERATIONS)
+                .and_then(|rest| rest.split_once('/'))
+            else {
+                continue;
+            };
+            let complete = generations.entry(generation.to_string()).or_insert(false);
+            *complete |= object == "manifest.json";
+        }
+        Ok(generations)
+    }
+
+    /// `latest`, or a generation name.
+    pub fn resolve(&self, generation: &str) -> Result<String, String> {
+        let generations = self.generations()?;
+        let found = if generation == "latest" {
+            generations.last()
+        } else {
+            generations.iter().find(|g| *g == generation)
+        };
+        found.cloned().ok_or_else(|| {
+            format!(
+                "no complete generation '{}' in {}",
+                generation,
+                self.store.describe()
+            )
+        })
+    }
+
+    pub fn manifest(&self, generation: &str) -> Result<Manifest, String> {
+        let name = manifest_name(generation);
+        let data = self
+            .store
+            .get(&name)?
+            .ok_or_else(|| format!("{} is missing", name))?;
+        serde_json::from_slice(&data).map_err(|e| format!("{}: {}", name, e))
+    }
+
+    /// A name for a generation started now; later generations sort after it.
+    pub fn new_generation(&self) -> Result<String, String> {
+        let base = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let taken = self.scan()?;
+        let mut name = base.clone();
+        let mut n = 1;
+        while taken.contains_key(&name) {
+            n += 1;
+            name = format!("{}-{}", base, n);
+        }
+        Ok(name)
+    }
+
+    /// SHA-256 of every stored BLOB.
+    pub fn blobs(&self) -> Result<HashSet<String>, String> {
+        Ok(self
+            .store
+            .list(BLOBS)?
+            .into_iter()
+            .filter_map(|name| name.rsplit('/').next().map(str::to_string))
+            .filter(|sha| sha.len() == 64)
+            .collect())
+    }
+
+    pub fn put_json<T: Serialize>(&self, name: &str, value: &T) -> Result<(), String> {
+        self.store.put(name, &to_json(value)?)
+    }
+
+    pub fn put_blob_list(&self, generation: &str, hashes: &[String]) -> Result<(), String> {
+        let text = hashes.join("\n");
+        let data = zstd::encode_all(text.as_bytes(), 3).map_err(|e| e.to_string())?;
+        self.store.put(&blob_list_name(generation), &data)
+    }
+
+    fn blob_list(&self, generation: &str) -> Result<Vec<String>, String> {
+        let name = blob_list_name(generation);
+        let data = self
+            .store
+            .get(&name)?
+            .ok_or_else(|| format!("{} is missing", name))?;
+        let text = zstd::decode_all(data.as_slice()).map_err(|e| format!("{}: {}", name, e))?;
+        Ok(String::from_utf8_lossy(&text)
+            .lines()
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Removes all but the newest `keep` generations, incomplete ones older
+    /// than the newest complete one, and then every BLOB no remaining
+    /// generation needs. The BLOB sweep lists the whole repository, so it
+    /// only runs when a generation went, or with `sweep`. Without `execute`,
+    /// only counts.
+    pub fn prune(&self, keep: usize, sweep: bool, execute: bool) -> Result<Pruned, String> {
+        let generations = self.scan()?;
+        let complete: Vec<&String> = generations
+            .iter()
+            .filter(|(_, complete)| **complete)
+            .map(|(name, _)| name)
+            .collect();
+        let newest = complete.last().copied();
+        let kept: Vec<&String> = complete.iter().rev().take(keep).copied().collect();
+        let mut pruned = Pruned::default();
+        for (name, complete) in &generations {
+            let expired = *complete && !kept.contains(&name);
+            let abandoned = !*complete && newest.is_some_and(|newest| name < newest);
+            if expired || abandoned {
+                pruned.generations.push(name.clone());
+            }
+        }
+
+        if execute {
+            for generation in &pruned.generations {
+                // The manifest goes first, so an interrupted prune leaves an
+                // incomplete generation, not a broken one.
+                self.store.delete(&manifest_name(generation))?;
+                for name in self
+                    .store
+                    .list(&format!("{}{}/", GENERATIONS, generation))?
+                {
+                    self.store.delete(&name)?;
+                }
+                info!(tag = "PRUNE", generation = %generation, "Generation deleted");
+            }
+        }
+        if pruned.generations.is_empty() && !sweep {
+            return Ok(pruned);
+        }
+
+        let mut needed = HashSet::new();
+        for generation in kept {
+            needed.extend(self.blob_list(generation)?);
+        }
+        for sha in self.blobs()? {
+            if needed.contains(&sha) {
+                continue;
+            }
+            if execute {
+                self.store.delete(&blob_name(&sha))?;
+            }
+            pruned.blobs += 1;
+        }
+        Ok(pruned)
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<Vec<u8>, String> {
+    serde_json::to_vec_pretty(value).map_err(|e| e.to_string())
+}
@@ -0,0 +1,168 @@
+use crate::create::{BLOB_TABLE, CAS_TABLE};
+use crate::pool;
+use crate::repository::{self, Manifest, Repository};
+use octa_warden_core::export::sha256_hex;
+use rusqlite::{params, Connection};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default)]
+pub struct Restored {
+    pub assets: u64,
+    pub bytes: u64,
+}
+
+/// Rebuilds the generation's database at `dest`. It is assembled next to
+/// `dest` and renamed into place once every BLOB is back and checked, so
+/// `dest` is never half-restored. An existing `dest` is only replaced with
+/// `force`.
+pub fn restore(
+    repo: &Repository,
+    manifest: &Manifest,
+    dest: &Path,
+    force: bool,
+    jobs: usize,
+) -> Result<Restored, String> {
+    if dest.exists() && !force {
+        return Err(format!(
+            "{} exists; pass --force to replace it",
+            dest.display()
+        ));
+    }
+    let partial = suffixed(dest, ".restoring");
+    let _ = fs::remove_file(&partial);
+
+    let database = database(repo, manifest)?;
+    fs::write(&partial, &database).map_err(|e| format!("{}: {}", partial.display(), e))?;
+    let restored = fill(repo, &partial, jobs).inspect_err(|_| {
+        let _ = fs::remove_file(&partial);
+    })?;
+
+    // A WAL left by the replaced database would be applied to this one.
+    for side in ["-wal", "-shm"] {
+        let _ = fs::remove_file(suffixed(dest, side));
+    }
+    fs::rename(&partial, dest).map_err(|e| format!("{}: {}", dest.display(), e))?;
+    Ok(restored)
+}
+
+/// The generation's database with its BLOBs stripped, checked against the manifest.
+pub fn database(repo: &Repository, manifest: &Manifest) -> Result<Vec<u8>, String> {
+    let name = repository::database_name(&manifest.name);
+    let stored = repo
+        .store
+        .get(&name)?
+        .ok_or_else(|| format!("{} is missing", name))?;
+    let database = repo
+        .codec
+        .unpack(&stored)
+        .map_err(|e| format!("{} {}", name, e))?;
+    if sha256_hex(&database) != manifest.database_sha256 {
+        return Err(format!("{} does not match its manifest checksum", name));
+    }
+    Ok(database)
+}
+
+/// One stored BLOB, unpacked and checked against its name.
+pub fn blob(repo: &Repository, sha: &str) -> Result<Vec<u8>, String> {
+    let name = repository::blob_name(sha);
+    let stored = repo
+        .store
+        .get(&name)?
+        .ok_or_else(|| format!("{} is missing", name))?;
+    let data = repo
+        .codec
+        .unpack(&stored)
+        .map_err(|e| format!("{} {}", name, e))?;
+    if sha256_hex(&data) != sha {
+        return Err(format!("{} does not match its checksum", name));
+    }
+    Ok(data)
+}
+
+/// Puts every BLOB back into the database at `path`, in one transaction:
+/// into `images` rows and, for a content-addressed database, `blobs`.
+fn fill(repo: &Repository, path: &Path, jobs: usize) -> Result<Restored, String> {
+    let mut conn = Connection::open(path).map_err(|e| e.to_string())?;
+    let rows: HashMap<String, Vec<i64>> = mapping(&conn, BLOB_TABLE, "image_rowid")?;
+    // Only a content-addressed database was backed up with a CAS_TABLE.
+    let content_addressed = conn
+        .prepare(&format!("SELECT 1 FROM {} LIMIT 0", CAS_TABLE))
+        .is_ok();
+    let blobs: HashMap<String, Vec<String>> = match content_addressed {
+        true => mapping(&conn, CAS_TABLE, "hash")?,
+        false => HashMap::new(),
+    };
+    let mut hashes: Vec<&String> = rows.keys().collect();
+    hashes.extend(blobs.keys().filter(|sha| !rows.contains_key(*sha)));
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let mut restored = Restored::default();
+    {
+        let mut update = tx
+            .prepare("UPDATE images SET data = ?1 WHERE rowid = ?2")
+            .map_err(|e| e.to_string())?;
+        pool::fan_in(
+            &hashes,
+            jobs,
+            |sha| Ok((*sha, blob(repo, sha)?)),
+            |(sha, data)| {
+                for rowid in rows.get(sha).into_iter().flatten() {
+                    update
+                        .execute(params![data, rowid])
+                        .map_err(|e| e.to_string())?;
+                    restored.assets += 1;
+                    restored.bytes += data.len() as u64;
+                }
+                for hash in blobs.get(sha).into_iter().flatten() {
+                    tx.prepare_cached("UPDATE blobs SET data = ?1 WHERE hash = ?2")
+                        .and_then(|mut refill| refill.execute(params![data, hash]))
+                        .map_err(|e| e.to_string())?;
+                    let assets: i64 = tx
+                        .prepare_cached("SELECT COUNT(*) FROM images WHERE blob_hash = ?1")
+                        .and_then(|mut sharing| sharing.query_row([hash], |row| row.get(0)))
+                        .map_err(|e| e.to_string())?;
+                    restored.assets += assets as u64;
+                    restored.bytes += data.len() as u64;
+                }
+                Ok(())
+            },
+        )?;
+    }
+    tx.execute_batch(&format!("DROP TABLE {}", BLOB_TABLE))
+        .map_err(|e| e.to_string())?;
+    if content_addressed {
+        tx.execute_batch(&format!("DROP TABLE {}", CAS_TABLE))
+            .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(restored)
+}
+
+/// `sha256` -> the `column` values of the rows of `table` that hold it.
+fn mapping<T: rusqlite::types::FromSql>(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+) -> Result<HashMap<String, Vec<T>>, String> {
+    let mut stmt = conn
+        .prepare(&format!("SELECT {}, sha256 FROM {}", column, table))
+        .map_err(|e| e.to_string())?;
+    let mapped = stmt
+        .query_map([], |row| Ok((row.get::<_, T>(0)?, row.get::<_, String>(1)?)))
+        .map_err(|e| e.to_string())?;
+    let mut rows: HashMap<String, Vec<T>> = HashMap::new();
+    for row in mapped {
+        let (value, sha) = row.map_err(|e| e.to_string())?;
+        rows.entry(sha).or_default().push(value);
+    }
+    Ok(rows)
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
@@ -0,0 +1,194 @@
+use octa_errors::Kind;
+use octa_warden_core::s3::{Client, S3Config};
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// `backup.s3`: how to reach the bucket of an `s3://` target. Unset fields
+/// fall back to AWS_ENDPOINT_URL, AWS_REGION and the AWS_* credentials.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct S3Settings {
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    /// `endpoint/bucket/key` URLs instead of `bucket.endpoint/key`.
+    pub path_style: Option<bool>,
+}
+
+/// Where a repository lives: a directory, or a prefix of a bucket. Objects
+/// are named by `/`-separated paths relative to it.
+pub enum Store {
+    Local(PathBuf),
+    S3 {
+        client: Box<Client>,
+        /// Empty, or ends with `/`.
+        prefix: String,
+        url: String,
+    },
+}
+
+impl Store {
+    /// `s3://bucket/prefix` is a bucket; anything else a directory, created
+    /// on the first write.
+    pub fn open(target: &str, s3: &S3Settings) -> Result<Self, String> {
+        let Some(rest) = target.strip_prefix("s3://") else {
+            return Ok(Store::Local(PathBuf::from(target)));
+        };
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(format!("'{}' names no bucket", target));
+        }
+        let prefix = match prefix.trim_matches('/') {
+            "" => String::new(),
+            prefix => format!("{}/", prefix),
+        };
+
+        let env = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let cfg = S3Config {
+            endpoint: s3
+                .endpoint
+                .clone()
+                .or_else(|| env("AWS_ENDPOINT_URL"))
+                .ok_or("no backup.s3.endpoint configured and AWS_ENDPOINT_URL is not set")?,
+            region: s3
+                .region
+                .clone()
+                .or_else(|| env("AWS_REGION"))
+                .unwrap_or_else(|| "us-east-1".to_string()),
+            bucket: bucket.to_string(),
+            prefix: prefix.clone(),
+            access_key_id: s3.access_key_id.clone(),
+            secret_access_key: s3.secret_access_key.clone(),
+            path_style: s3.path_style.unwrap_or(true),
+            // Parallelism comes from backup.jobs.
+            concurrency: 1,
+            retries: 3,
+        };
+        Ok(Store::S3 {
+            client: Box::new(Client::new(&cfg)?),
+            url: cfg.describe(),
+            prefix,
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Store::Local(root) => root.display().to_string(),
+            Store::S3 { url, .. } => url.clone(),
+        }
+    }
+
+    /// What a failed read or write means: the bucket could not be reached,
+    /// or the directory could not be used.
+    pub fn failure(&self) -> Kind {
+        match self {
+            Store::Local(_) => Kind::Io,
+            Store::S3 { .. } => Kind::Unavailable,
+        }
+    }
+
+    /// Writes `name`, replacing it. Local files appear whole or not at all.
+    pub fn put(&self, name: &str, data: &[u8]) -> Result<(), String> {
+        match self {
+            Store::Local(root) => {
+                let path = root.join(name);
+                let mut partial = path.clone().into_os_string();
+                partial.push(".partial");
+                let write = || -> io::Result<()> {
+                    if let Some(dir) = path.parent() {
+                        fs::create_dir_all(dir)?;
+                    }
+                    fs::write(&partial, data)?;
+                    fs::File::open(&partial)?.sync_all()?;
+                    fs::rename(&partial, &path)
+                };
+                write().map_err(|e| format!("{}: {}", path.display(), e))
+            }
+            Store::S3 { client, prefix, .. } => client
+                .put(&format!("{}{}", prefix, name), data)
+                .map_err(|e| format!("{}: {}", name, e)),
+        }
+    }
+
+    /// `None` when `name` does not exist.
+    pub fn get(&self, name: &str) -> Result<Option<Vec<u8>>, String> {
+        match self {
+            Store::Local(root) => match fs::read(root.join(name)) {
+                Ok(data) => Ok(Some(data)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(format!("{}: {}", root.join(name).display(), e)),
+            },
+            Store::S3 { client, prefix, .. } => client
+                .get(&format!("{}{}", prefix, name))
+                .map_err(|e| format!("{}: {}", name, e)),
+        }
+    }
+
+    /// Every object whose name starts with `dir` (e.g. `blobs/`).
+    pub fn list(&self, dir: &str) -> Result<Vec<String>, String> {
+        match self {
+            Store::Local(root) => {
+                let mut names = Vec::new();
+                walk(root, &root.join(dir), &mut names)
+                    .map_err(|e| format!("{}: {}", root.join(dir).display(), e))?;
+                Ok(names)
+            }
+            Store::S3 { client, prefix, .. } => Ok(client
+                .list_under(&format!("{}{}", prefix, dir))?
+                .into_iter()
+                .filter_map(|key| key.strip_prefix(prefix.as_str()).map(str::to_string))
+                .collect()),
+        }
+    }
+
+    /// Deletes `name`; a missing object is not an error.
+    pub fn delete(&self, name: &str) -> Result<(), String> {
+        match self {
+            Store::Local(root) => {
+                let path = root.join(name);
+                match fs::remove_file(&path) {
+                    Ok(()) => {
+                        // Drops the generation or fan-out directory once it is empty.
+                        if let Some(dir) = path.parent() {
+                            let _ = fs::remove_dir(dir);
+                        }
+                        Ok(())
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                    Err(e) => Err(format!("{}: {}", path.display(), e)),
+                }
+            }
+            Store::S3 { client, prefix, .. } => client
+                .delete(&format!("{}{}", prefix, name))
+                .map_err(|e| format!("{}: {}", name, e)),
+        }
+    }
+}
+
+/// Files under `dir`, relative to `root`; a missing `dir` has none.
+/// Leftovers of interrupted writes are skipped.
+fn walk(root: &Path, dir: &Path, names: &mut Vec<String>) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(root, &path, names)?;
+        } else if path.extension().is_none_or(|ext| ext != "partial") {
+            if let Ok(relative) = path.strip_prefix(root) {
+                let parts: Vec<String> = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                    .collect();
+                names.push(parts.join("/"));
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use crate::create::{BLOB_TABLE, CAS_TABLE};
+use crate::pool;
+use crate::repository::{self, Manifest, Repository};
+use crate::restore;
+use rusqlite::Connection;
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use tracing::error;
+
+#[derive(Debug, Default)]
+pub struct Verified {
+    pub blobs: u64,
+    pub missing: u64,
+    pub damaged: u64,
+}
+
+impl Verified {
+    pub fn ok(&self) -> bool {
+        self.missing == 0 && self.damaged == 0
+    }
+}
+
+/// Checks one generation without restoring it: the database decrypts,
+/// matches its manifest and passes SQLite's integrity check, and every BLOB
+/// it names is stored and (unless `quick`) unpacks to its checksum.
+/// Problems with single BLOBs are logged and counted; a database that fails
+/// is an error.
+pub fn verify(
+    repo: &Repository,
+    manifest: &Manifest,
+    quick: bool,
+    jobs: usize,
+) -> Result<Verified, String> {
+    let database = restore::database(repo, manifest)?;
+    let hashes = needed(&database)?;
+
+    let mut verified = Verified {
+        blobs: hashes.len() as u64,
+        ..Verified::default()
+    };
+    if quick {
+        let stored = repo.blobs()?;
+        for sha in hashes.iter().filter(|sha| !stored.contains(*sha)) {
+            error!(tag = "MISSING", generation = %manifest.name, blob = %sha, "Blob not stored");
+            verified.missing += 1;
+        }
+        return Ok(verified);
+    }
+
+    pool::fan_in(
+        &hashes,
+        jobs,
+        |sha| Ok((sha.clone(), restore::blob(repo, sha))),
+        |(sha, checked)| {
+            if let Err(reason) = checked {
+                if repo.store.get(&repository::blob_name(&sha))?.is_none() {
+                    error!(tag = "MISSING", generation = %manifest.name, blob = %sha, "Blob not stored");
+                    verified.missing += 1;
+                } else {
+                    error!(tag = "DAMAGED", generation = %manifest.name, blob = %sha, reason = %reason, "Blob unusable");
+                    verified.damaged += 1;
+                }
+            }
+            Ok(())
+        },
+    )?;
+    Ok(verified)
+}
+
+/// The BLOBs a backed-up database refers to, after SQLite's integrity check.
+fn needed(database: &[u8]) -> Result<Vec<String>, String> {
+    let scratch =
+        Scratch(std::env::temp_dir().join(format!("octa_backup_verify_{}.db", std::process::id())));
+    fs::write(&scratch.0, database).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&scratch.0).map_err(|e| e.to_string())?;
+    let check: String = conn
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if check != "ok" {
+        return Err(format!("database fails the integrity check: {}", check));
+    }
+    let mut select = format!("SELECT sha256 FROM {}", BLOB_TABLE);
+    // Blobs of a content-addressed database, which only it was backed up with.
+    if conn
+        .prepare(&format!("SELECT 1 FROM {} LIMIT 0", CAS_TABLE))
+        .is_ok()
+    {
+        select += &format!(" UNION SELECT sha256 FROM {}", CAS_TABLE);
+    }
+    let hashes: HashSet<String> = conn
+        .prepare(&select)
+        .and_then(|mut stmt| {
+            stmt.query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(hashes.into_iter().collect())
+}
+
+/// A temporary file, removed on drop.
+struct Scratch(PathBuf);
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
@@ -0,0 +1,460 @@
+use console::style;
+use indicatif::{ProgressBar, ProgressStyle};
+use reqwest::{multipart, Client};
+use octa_config::{ConfigError, Validate};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use serde::Deserialize;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, Semaphore};
+use tracing::{error, info, info_span, warn, Instrument, Level};
+use uuid::Uuid;
+use comfy_table::Table;
+
+
+// resolved settings the run uses
+#[derive(Debug, Clone)]
+struct BenchConfig {
+    base_url: String,
+    total_req: usize,
+    worker: usize,      // Concurrency
+    upload_secret: String,
+    protocols: Vec<Protocol>,
+    telemetry: octa_config::TelemetryConfig,
+    run: String,        // marks this run's request spans
+}
+
+// how the phases talk to the server; `both` runs every phase over each
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Protocol {
+    Http,
+    Grpc,
+}
+
+impl Protocol {
+    fn label(&self) -> &'static str {
+        match self { Protocol::Http => "HTTP", Protocol::Grpc => "gRPC" }
+    }
+}
+
+// what pulse reads from the shared config.yaml
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+struct FileConfig {
+    server: octa_config::ServerConfig,
+    security: octa_config::SecurityConfig,
+    base_url: Option<String>,
+    pulse: PulseConfig,
+    telemetry: octa_config::TelemetryConfig,
+}
+
+// `pulse:` section, every key optional
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct PulseConfig {
+    base_url: Option<String>, // defaults to the server's base_url
+    total_req: usize,
+    worker: usize,
+    protocol: String,         // http, grpc (octa-server only) or both
+}
+
+impl Default for PulseConfig {
+    fn default() -> Self {
+        Self { base_url: None, total_req: 20000, worker: 200, protocol: "http".into() }
+    }
+}
+
+impl PulseConfig {
+    fn protocols(&self) -> Option<Vec<Protocol>> {
+        match self.protocol.as_str() {
+            "http" => Some(vec![Protocol::Http]),
+            "grpc" => Some(vec![Protocol::Grpc]),
+            "both" => Some(vec![Protocol::Http, Protocol::Grpc]),
+            _ => None,
+        }
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = self.server.validate();
+        if self.security.upload_secret.trim().is_empty() {
+            problems.push(("security.upload_secret".into(), "is required for the write test".into()));
+        }
+        if self.pulse.worker == 0 {
+            problems.push(("pulse.worker".into(), "must be at least 1".into()));
+        }
+        if self.pulse.total_req == 0 {
+            problems.push(("pulse.total_req".into(), "must be at least 1".into()));
+        }
+        if self.pulse.protocols().is_none() {
+            problems.push(("pulse.protocol".into(), format!("'{}' is not one of http, grpc, both", self.pulse.protocol)));
+        }
+        problems.extend(octa_config::check_url("base_url", self.base_url.as_deref()));
+        problems.extend(octa_config::check_url("pulse.base_url", self.pulse.base_url.as_deref()));
+        problems.extend(self.telemetry.validate());
+        problems
+    }
+}
+
+struct BenchStats {
+    success: AtomicU64,
+    failed: AtomicU64,
+    latencies: Mutex<Vec<Duration>>,
+}
+
+// generate a fresh key (legal by the servers' rules, so no write is dropped)
+fn generate_key() -> String {
+    octa_key::parse(&format!("rust-bench/{}", Uuid::new_v4())).expect("Generated key is not legal")
+}
+
+// generate fake image (an upload the server accepts, checked with its own rules)
+fn generate_valid_jpeg() -> Vec<u8> {
+    let img = octa_image::image::DynamicImage::new_rgb8(100, 100);
+    let bytes = octa_image::encode_jpeg(&img, 80).expect("Failed to generate image");
+    octa_image::validate(&bytes, octa_image::DEFAULT_MAX_UPLOAD).expect("Generated image is not uploadable");
+    bytes
+}
+
+// same lookup as the server: --config / $OCTA_CONFIG, then config.yaml upwards from here
+fn load_config(args: &[String]) -> Result<BenchConfig, ConfigError> {
+    let explicit = args.iter().skip_while(|a| *a != "--config").nth(1);
+    let path = octa_config::discover(explicit.map(Path::new)).ok_or(ConfigError::NotFound)?;
+    let file: FileConfig = octa_config::load(&path)?;
+    info!(tag = "OK", path = %path.display(), "Loaded config");
+
+    let base_url = file.pulse.base_url.as_deref().or(file.base_url.as_deref());
+    Ok(BenchConfig {
+        base_url: octa_config::base_url(base_url, &file.server),
+        total_req: file.pulse.total_req,
+        worker: file.pulse.worker,
+        upload_secret: file.security.upload_secret,
+        protocols: file.pulse.protocols().unwrap_or_default(),
+        telemetry: file.telemetry,
+        run: Uuid::new_v4().to_string(),
+    })
+}
+
+// the octa-pulse command line, on `args` with the program name first (only --config is read):
+// the binary passes std::env::args_os(), `octa bench` the arguments it was given
+#[tokio::main]
+pub async fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args: Vec<String> = args.into_iter().map(|a| a.to_string_lossy().into_owned()).collect();
+    octa_logging::init(LogFormat::Pretty, Level::INFO, false, false);
+    print_banner();
+
+    let config = match load_config(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return octa_errors::Error::from(e).exit_code();
+        }
+    };
+
+    // every request becomes a trace of its own, continued by the server
+    let _telemetry = match config.telemetry.endpoint() {
+        None => None,
+        Some(endpoint) => match octa_logging::otel::start("octa-pulse", endpoint, config.telemetry.sample_ratio) {
+            Ok(telemetry) => { info!(tag = "OK", endpoint, run = %config.run, "Exporting traces"); Some(telemetry) }
+            Err(e) => { warn!(tag = "WARN", endpoint, reason = %e, "Tracing disabled"); None }
+        },
+    };
+
+    let client = match Client::builder()
+        .pool_max_idle_per_host(config.worker + 50)
+        .tcp_keepalive(Duration::from_secs(90))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => { error!(tag = "FATAL", reason = %e, "Could not create the HTTP client"); return Kind::Internal.exit_code(); }
+    };
+
+    if !check_health(&client, &config.base_url).await { return Kind::Unavailable.exit_code(); }
+
+    println!("Generating valid JPEG asset for benchmark...");
+    let valid_img_data = generate_valid_jpeg(); 
+
+    let grpc = match config.protocols.contains(&Protocol::Grpc) {
+        false => None,
+        true => match grpc_client(&config) {
+            Ok(grpc) => Some(grpc),
+            Err(e) => { error!(tag = "FATAL", reason = %e, "Could not create the gRPC client"); return Kind::Config.exit_code(); }
+        },
+    };
+
+    let mut results = vec![];
+    for protocol in config.protocols.clone() {
+        let (read, write) = match (protocol, &grpc) {
+            (Protocol::Grpc, Some(grpc)) => (
+                grpc_read(&config, grpc).await,
+                grpc_write(&config, grpc, valid_img_data.clone()).await,
+            ),
+            _ => (
+                http_read(&config, &client).await,
+                http_write(&config, &client, valid_img_data.clone()).await,
+            ),
+        };
+        results.push((protocol, read, write));
+    }
+
+    if results.len() > 1 { print_comparison(&results); }
+
+    ExitCode::SUCCESS
+}
+
+//  PHASE 1: READ STRESS TEST (generated avatars, no database lookup)
+async fn http_read(config: &BenchConfig, client: &Client) -> Option<Summary> {
+    println!("\n{}", style("PHASE 1: Starting Read Test (HTTP)...").yellow());
+
+    let read_client = client.clone();
+    let read_url = config.base_url.clone(); 
+
+    run_benchmark(config, "🔥 READ STRESS TEST", move || {
+        let url_base = read_url.clone();
+        let c = read_client.clone();
+        async move {
+            let url = format!("{}/avatar/{}", url_base, Uuid::new_v4());
+            traced(c.get(url)).send().await.map(|r| r.status().as_u16())
+        }
+    }).await
+}
+
+async fn grpc_read(config: &BenchConfig, client: &octa_client::Client) -> Option<Summary> {
+    println!("\n{}", style("PHASE 1: Starting Read Test (gRPC)...").yellow());
+
+    let c = client.clone();
+    run_benchmark(config, "🔥 READ STRESS TEST (gRPC)", move || {
+        let c = c.clone();
+        async move {
+            traced_grpc(&c).generated_avatar(&Uuid::new_v4().to_string()).await.map(|_| 200)
+        }
+    }).await
+}
+
+// PHASE 2: WRITE STRESS TEST 
+async fn http_write(config: &BenchConfig, client: &Client, valid_img_data: Vec<u8>) -> Option<Summary> {
+    println!("\n{}", style("PHASE 2: Starting Write Test (HTTP)...").yellow());
+
+    let write_client = client.clone();
+    let write_config = config.clone();
+
+    run_benchmark(config, "⚡ WRITE STRESS TEST", move || {
+        let c = write_client.clone();
+        let cfg = write_config.clone();
+        let data = valid_img_data.clone();
+        
+        async move {
+            let form = multipart::Form::new()
+                .text("keys", generate_key())
+                .text("mode", "square")
+                .part("avatar", multipart::Part::bytes(data)
+                    .file_name("bench.jpg")
+                    .mime_str("image/jpeg")?);
+
+            traced(c.post(format!("{}/upload", cfg.base_url)))
+                .header("X-Secret-Key", cfg.upload_secret)
+                .multipart(form)
+                .send()
+                .await
+                .map(|r| r.status().as_u16())
+        }
+    }).await
+}
+
+async fn grpc_write(config: &BenchConfig, client: &octa_client::Client, valid_img_data: Vec<u8>) -> Option<Summary> {
+    println!("\n{}", style("PHASE 2: Starting Write Test (gRPC)...").yellow());
+
+    let c = client.clone();
+    let data = bytes::Bytes::from(valid_img_data);
+    run_benchmark(config, "⚡ WRITE STRESS TEST (gRPC)", move || {
+        let c = c.clone();
+        let data = data.clone();
+        async move {
+            let options = octa_client::UploadOptions::new().mode(octa_client::Mode::Square).file_name("bench.jpg");
+            traced_grpc(&c).upload_avatar(&generate_key(), data, options).await.map(|_| 200)
+        }
+    }).await
+}
+
+// gRPC rides one HTTP/2 connection per host (no TLS for http:// URLs), so
+// there is no pool to size as for HTTP/1.1
+fn grpc_client(config: &BenchConfig) -> Result<octa_client::Client, octa_client::Error> {
+    octa_client::Client::builder(&config.base_url)
+        .secret(config.upload_secret.clone())
+        .http_client(reqwest::Client::builder().http2_prior_knowledge().tcp_keepalive(Duration::from_secs(90)).build()?)
+        .grpc()
+        .build()
+}
+
+// trace-context headers of the request span, so the server's spans join its trace
+fn traced(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    octa_logging::otel::context_headers().into_iter().fold(request, |request, (name, value)| request.header(name, value))
+}
+
+fn traced_grpc(client: &octa_client::Client) -> octa_client::Client {
+    client.with_headers(octa_logging::otel::context_headers())
+}
+
+// To run benchmark tests, run_benchmark should be used. What it does is simple:
+
+// Based on the requests and worker values it gets from the config file,
+// it executes the given operation function and logs it.
+async fn run_benchmark<F, Fut, E>(config: &BenchConfig, name: &str, mut operation: F) -> Option<Summary>
+where 
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<u16, E>> + Send + 'static,
+    E: Send + 'static
+{
+    let stats = Arc::new(BenchStats {
+        success: AtomicU64::new(0),
+        failed: AtomicU64::new(0),
+        latencies: Mutex::new(Vec::with_capacity(config.total_req)),
+    });
+
+    let pb = ProgressBar::new(config.total_req as u64);
+    pb.set_style(ProgressStyle::default_bar()
+        .template(&format!("{{spinner:.green}} {}: [{{elapsed_precise}}] [{{bar:40.cyan/blue}}] {{pos}}/{{len}}", name))
+        .unwrap());
+
+    // Get the number of workers from Config
+    let semaphore = Arc::new(Semaphore::new(config.worker));
+    let start_time = Instant::now();
+    let mut workers = vec![];
+
+    for _ in 0..config.total_req {
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let stats = stats.clone();
+        let fut = operation();
+        let pb = pb.clone();
+        let span = info_span!(parent: None, "pulse.request", phase = name, pulse.run = %config.run, otel.kind = "client", status = tracing::field::Empty);
+
+        workers.push(tokio::spawn(async move {
+            let _permit = permit;
+            let start = Instant::now();
+            let result = fut.instrument(span.clone()).await;
+            let duration = start.elapsed();
+            if let Ok(code) = &result { span.record("status", code); }
+
+            let mut lats = stats.latencies.lock().await;
+            lats.push(duration);
+            
+            match result {
+                Ok(code) if (200..300).contains(&code) => {
+                    stats.success.fetch_add(1, Ordering::Relaxed);
+                }
+                _ => {
+                    stats.failed.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            pb.inc(1);
+        }));
+    }
+
+    for worker in workers { let _ = worker.await; }
+    pb.finish_and_clear();
+
+    let summary = summarize(&stats, start_time.elapsed()).await;
+    if let Some(summary) = &summary { print_report(summary); }
+    summary
+}
+
+// what one phase measured
+struct Summary {
+    throughput: f64,    // Req/sec
+    success_rate: f64,  // %
+    p50: Duration,
+    p95: Duration,
+    p99: Duration,
+}
+
+async fn summarize(stats: &Arc<BenchStats>, total_time: Duration) -> Option<Summary> {
+    let mut lats = stats.latencies.lock().await;
+    if lats.is_empty() { return None; }
+    lats.sort();
+    
+    let success = stats.success.load(Ordering::Relaxed);
+    let failed = stats.failed.load(Ordering::Relaxed);
+    let total = success + failed;
+
+    Some(Summary {
+        throughput: total as f64 / total_time.as_secs_f64(),
+        success_rate: (success as f64 / total as f64) * 100.0,
+        p50: lats[lats.len() / 2],
+        p95: lats[(lats.len() as f64 * 0.95) as usize],
+        p99: lats[(lats.len() as f64 * 0.99) as usize],
+    })
+}
+
+fn print_report(summary: &Summary) {
+    let mut table = Table::new();
+    table.set_header(vec!["Metric", "Value"]);
+
+    table.add_row(vec![
+        "Throughput".to_string(), 
+        format!("{:.2} Req/sec", summary.throughput)
+    ]);
+    table.add_row(vec![
+        "Success Rate".to_string(), 
+        format!("{:.2}%", summary.success_rate)
+    ]);
+    table.add_row(vec![
+        "Avg Latency (P50)".to_string(), 
+        format!("{:?}", summary.p50)
+    ]);
+    table.add_row(vec![
+        "P95 Latency".to_string(), 
+        format!("{:?}", summary.p95)
+    ]);
+    table.add_row(vec![
+        "P99 Latency".to_string(), 
+        format!("{:?}", summary.p99)
+    ]);
+
+    println!("{}", table);
+}
+
+// one cell of the comparison
+type Show = fn(&Summary) -> String;
+
+// `protocol: both`: each phase side by side, same backend and load
+fn print_comparison(results: &[(Protocol, Option<Summary>, Option<Summary>)]) {
+    println!("\n{}", style("HTTP vs gRPC").bold().cyan());
+    let mut table = Table::new();
+    let mut header = vec!["Phase".to_string(), "Metric".to_string()];
+    header.extend(results.iter().map(|(protocol, _, _)| protocol.label().to_string()));
+    table.set_header(header);
+
+    for (phase, pick) in [("Read", 0), ("Write", 1)] {
+        let metrics: [(&str, Show); 4] = [
+            ("Throughput", |s| format!("{:.2} Req/sec", s.throughput)),
+            ("Success Rate", |s| format!("{:.2}%", s.success_rate)),
+            ("P50 Latency", |s| format!("{:?}", s.p50)),
+            ("P99 Latency", |s| format!("{:?}", s.p99)),
+        ];
+        for (metric, show) in metrics {
+            let mut row = vec![phase.to_string(), metric.to_string()];
+            for (_, read, write) in results {
+                let summary = if pick == 0 { read } else { write };
+                row.push(summary.as_ref().map(show).unwrap_or_else(|| "-".to_string()));
+            }
+            table.add_row(row);
+        }
+    }
+    println!("{}", table);
+}
+
+fn print_banner() {
+    println!("{}", style("OCTA-PULSE BENCHMARK TOOL").bold().cyan());
+    println!("{}\n", style("==========================").dim());
+}
+
+async fn check_health(client: &Client, base_url: &str) -> bool {
+    match client.get(base_url).send().await {
+        Ok(_) => { info!(tag = "OK", url = %base_url, "Server is up"); true }
+        Err(e) => { error!(tag = "FATAL", url = %base_url, reason = %e, "Server is down"); false }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,299 @@
+//! Edge cache invalidation for Octa: the `cdn` section of `config.yaml` and
+//! purge requests against the Cloudflare, Fastly and Bunny APIs and
+//! octa-edge, so a key that octa-ctl or octa-warden changed stops being
+//! served stale.
+//!
+//! ```no_run
+//! use octa_cdn::{CdnConfig, Purger};
+//! use std::time::Duration;
+//!
+//! # fn example(cdn: &CdnConfig) -> Result<(), octa_cdn::Error> {
+//! if let Some(purger) = Purger::new(cdn, Duration::from_secs(30))? {
+//!     let purged = purger.purge_keys(&["alice".to_string()])?;
+//!     println!("{} URLs purged", purged.urls);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+mod provider;
+
+pub use provider::Provider;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+use ureq::http::Response;
+use ureq::Body;
+
+/// Attempts per request; rate limits (429) and 5xx are retried.
+const ATTEMPTS: u32 = 3;
+
+/// Longest wait honoured from a `Retry-After`.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// `cdn:` of config.yaml.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CdnConfig {
+    /// Who runs the edge; unset disables purging.
+    pub provider: Option<Provider>,
+    /// Public URL the CDN serves the instance under (`https://cdn.example.com`).
+    pub base_url: String,
+    /// Environment variable holding the API token (Cloudflare, octa-edge),
+    /// key (Fastly) or access key (Bunny).
+    pub token_env: String,
+    /// Cloudflare zone of `base_url`.
+    pub zone_id: String,
+    /// Query strings cached per key, as in `warm.variants`; `""` is the
+    /// stored image.
+    pub variants: Vec<String>,
+    /// Fastly: mark content stale instead of evicting it.
+    pub soft: bool,
+    /// Provider API root, for proxies and tests (default: the provider's;
+    /// for octa-edge, `base_url`).
+    pub api_url: Option<String>,
+}
+
+impl Default for CdnConfig {
+    fn default() -> Self {
+        Self {
+            provider: None,
+            base_url: String::new(),
+            token_env: "OCTA_CDN_TOKEN".to_string(),
+            zone_id: String::new(),
+            variants: vec![String::new()],
+            soft: false,
+            api_url: None,
+        }
+    }
+}
+
+impl CdnConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.provider.is_some()
+    }
+
+    /// `(field, problem)` pairs, as `octa_config::Validate` reports them.
+    /// Nothing is checked while purging is disabled.
+    pub fn validate(&self) -> Vec<(String, String)> {
+        let Some(provider) = self.provider else {
+            return Vec::new();
+        };
+        let mut problems = Vec::new();
+        let mut problem = |field: &str, reason: &str| {
+            problems.push((format!("cdn.{}", field), reason.to_string()))
+        };
+        if !is_http_url(&self.base_url) {
+            problem(
+                "base_url",
+                "must be the http(s) URL the CDN serves Octa under",
+            );
+        }
+        if let Some(api_url) = &self.api_url {
+            if !is_http_url(api_url) {
+                problem("api_url", "is not an http(s) URL");
+            }
+        }
+        if self.token_env.trim().is_empty() {
+            problem("token_env", "must name the variable holding the API token");
+        }
+        if provider == Provider::Cloudflare && self.zone_id.trim().is_empty() {
+            problem("zone_id", "is required for cloudflare");
+        }
+        if self.variants.is_empty() {
+            problem(
+                "variants",
+                "needs at least one entry (\"\" for the stored image)",
+            );
+        }
+        problems
+    }
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+/// Why a purge failed.
+#[derive(Debug)]
+pub enum Error {
+    /// Purging is configured but cannot start (no token, bad URL).
+    Config(String),
+    /// No response: connection refused, DNS, timeout.
+    Transport(String),
+    /// The provider refused the purge.
+    Api { status: u16, message: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(e) => write!(f, "{}", e),
+            Error::Transport(e) => write!(f, "request failed: {}", e),
+            Error::Api { status, message } => write!(f, "{}: {}", status, message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// What a purge did.
+#[derive(Debug, Default, Serialize)]
+pub struct Purged {
+    /// URLs (or prefixes) invalidated.
+    pub urls: usize,
+    /// API requests it took.
+    pub requests: usize,
+}
+
+/// Sends purges for one configured CDN.
+pub struct Purger {
+    provider: Provider,
+    base_url: String,
+    host_path: String,
+    api_url: String,
+    token: String,
+    zone_id: String,
+    variants: Vec<String>,
+    soft: bool,
+    agent: ureq::Agent,
+}
+
+impl Purger {
+    /// `None` when `cdn.provider` is unset. The token is read from
+    /// `cdn.token_env` here, so a missing one fails before anything changes.
+    pub fn new(cfg: &CdnConfig, timeout: Duration) -> Result<Option<Self>, Error> {
+        let Some(provider) = cfg.provider else {
+            return Ok(None);
+        };
+        let token = std::env::var(&cfg.token_env)
+            .ok()
+            .filter(|t| !t.trim().is_empty())
+            .ok_or_else(|| {
+                Error::Config(format!(
+                    "cdn: no API token in ${} (cdn.token_env)",
+                    cfg.token_env
+                ))
+            })?;
+        let base_url = cfg.base_url.trim_end_matches('/').to_string();
+        let host_path = base_url
+            .split_once("://")
+            .map(|(_, rest)| rest.to_string())
+            .ok_or_else(|| Error::Config(format!("cdn.base_url: '{}' is not a URL", base_url)))?;
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(timeout))
+            // Error bodies carry the provider's reason.
+            .http_status_as_error(false)
+            .build()
+            .into();
+        let api_url = cfg
+            .api_url
+            .as_deref()
+            .or(provider.api_url())
+            .unwrap_or(&base_url)
+            .trim_end_matches('/')
+            .to_string();
+        Ok(Some(Self {
+            provider,
+            base_url,
+            host_path,
+            api_url,
+            token,
+            zone_id: cfg.zone_id.clone(),
+            variants: cfg.variants.clone(),
+            soft: cfg.soft,
+            agent,
+        }))
+    }
+
+    pub fn provider(&self) -> Provider {
+        self.provider
+    }
+
+    /// Whether [`Purger::purge_prefix`] works; otherwise the keys under a
+    /// prefix have to be listed and purged one by one.
+    pub fn supports_prefix(&self) -> bool {
+        self.provider.supports_prefix()
+    }
+
+    /// The edge URLs of a key: `<base_url>/u/<key>` in every variant.
+    pub fn urls(&self, key: &str) -> Vec<String> {
+        self.variants
+            .iter()
+            .map(|variant| match variant.trim_start_matches('?') {
+                "" => format!("{}/u/{}", self.base_url, key),
+                query => format!("{}/u/{}?{}", self.base_url, key, query),
+            })
+            .collect()
+    }
+
+    /// Purges every variant URL of `keys`, in as few requests as the
+    /// provider allows. Stops at the first refused request.
+    pub fn purge_keys(&self, keys: &[String]) -> Result<Purged, Error> {
+        let urls: Vec<String> = keys.iter().flat_map(|key| self.urls(key)).collect();
+        let mut purged = Purged::default();
+        for batch in urls.chunks(self.provider.batch_size()) {
+            self.provider.purge_urls(self, batch)?;
+            purged.urls += batch.len();
+            purged.requests += 1;
+        }
+        Ok(purged)
+    }
+
+    /// Purges everything cached under `<base_url>/u/<prefix>`, query
+    /// variants included.
+    pub fn purge_prefix(&self, prefix: &str) -> Result<Purged, Error> {
+        if !self.supports_prefix() {
+            return Err(Error::Config(format!(
+                "{} cannot purge by prefix",
+                self.provider.as_str()
+            )));
+        }
+        self.provider.purge_prefix(self, prefix)?;
+        Ok(Purged {
+            urls: 1,
+            requests: 1,
+        })
+    }
+
+    /// Sends one request, retrying rate limits and server errors.
+    fn send(
+        &self,
+        request: impl Fn() -> Result<Response<Body>, ureq::Error>,
+    ) -> Result<Response<Body>, Error> {
+        let mut attempt = 1;
+        loop {
+            let response = request().map_err(|e| Error::Transport(e.to_string()))?;
+            let status = response.status().as_u16();
+            let retryable = status == 429 || status >= 500;
+            if response.status().is_success() || !retryable || attempt == ATTEMPTS {
+                return check(response);
+            }
+            let wait = response
+                .headers()
+                .get("retry-after")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(Duration::from_secs(1 << attempt))
+                .min(MAX_RETRY_AFTER);
+            thread::sleep(wait);
+            attempt += 1;
+        }
+    }
+}
+
+/// Turns a non-2xx response into [`Error::Api`].
+fn check(mut response: Response<Body>) -> Result<Response<Body>, Error> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let text = response.body_mut().read_to_string().unwrap_or_default();
+    Err(Error::Api {
+        status: status.as_u16(),
+        message: text.trim().to_string(),
+    })
+}
@@ -0,0 +1,171 @@
+use crate::{Error, Purger};
+use serde::Deserialize;
+use serde_json::json;
+
+/// Cloudflare accepts this many files (or prefixes) per purge request on
+/// every plan.
+const CLOUDFLARE_BATCH: usize = 30;
+
+/// URLs per request to octa-edge.
+const EDGE_BATCH: usize = 100;
+
+/// The edge in front of an instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    Cloudflare,
+    Fastly,
+    Bunny,
+    /// octa-edge, Octa's own caching proxy.
+    Edge,
+}
+
+/// Body of Cloudflare's API responses; a 200 can still report failure.
+#[derive(Deserialize)]
+struct CloudflareResponse {
+    success: bool,
+    #[serde(default)]
+    errors: Vec<CloudflareError>,
+}
+
+#[derive(Deserialize)]
+struct CloudflareError {
+    message: String,
+}
+
+impl Provider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Provider::Cloudflare => "cloudflare",
+            Provider::Fastly => "fastly",
+            Provider::Bunny => "bunny",
+            Provider::Edge => "edge",
+        }
+    }
+
+    /// `None` for octa-edge, which takes purges where it serves.
+    pub(crate) fn api_url(&self) -> Option<&'static str> {
+        match self {
+            Provider::Cloudflare => Some("https://api.cloudflare.com/client/v4"),
+            Provider::Fastly => Some("https://api.fastly.com"),
+            Provider::Bunny => Some("https://api.bunny.net"),
+            Provider::Edge => None,
+        }
+    }
+
+    /// Fastly purges by URL or surrogate key, and Octa sends no surrogate
+    /// keys.
+    pub(crate) fn supports_prefix(&self) -> bool {
+        !matches!(self, Provider::Fastly)
+    }
+
+    /// URLs per request.
+    pub(crate) fn batch_size(&self) -> usize {
+        match self {
+            Provider::Cloudflare => CLOUDFLARE_BATCH,
+            Provider::Edge => EDGE_BATCH,
+            Provider::Fastly | Provider::Bunny => 1,
+        }
+    }
+
+    pub(crate) fn purge_urls(&self, purger: &Purger, urls: &[String]) -> Result<(), Error> {
+        match self {
+            Provider::Cloudflare => cloudflare(purger, json!({ "files": urls })),
+            Provider::Fastly => urls.iter().try_for_each(|url| fastly(purger, url)),
+            Provider::Bunny => urls.iter().try_for_each(|url| bunny(purger, url)),
+            Provider::Edge => edge(purger, json!({ "urls": urls })),
+        }
+    }
+
+    pub(crate) fn purge_prefix(&self, purger: &Purger, prefix: &str) -> Result<(), Error> {
+        match self {
+            // Prefixes are host and path, without the scheme.
+            Provider::Cloudflare => cloudflare(
+                purger,
+                json!({ "prefixes": [format!("{}/u/{}", purger.host_path, prefix)] }),
+            ),
+            Provider::Bunny => bunny(purger, &format!("{}/u/{}*", purger.base_url, prefix)),
+            Provider::Edge => edge(purger, json!({ "prefixes": [format!("/u/{}", prefix)] })),
+            Provider::Fastly => unreachable!("checked by Purger::purge_prefix"),
+        }
+    }
+}
+
+fn cloudflare(purger: &Purger, body: serde_json::Value) -> Result<(), Error> {
+    let url = format!("{}/zones/{}/purge_cache", purger.api_url, purger.zone_id);
+    let body = body.to_string();
+    let mut response = purger.send(|| {
+        purger
+            .agent
+            .post(&url)
+            .header("Authorization", &format!("Bearer {}", purger.token))
+            .header("Content-Type", "application/json")
+            .send(body.as_bytes())
+    })?;
+    let text = response
+        .body_mut()
+        .read_to_string()
+        .map_err(|e| Error::Transport(e.to_string()))?;
+    match serde_json::from_str::<CloudflareResponse>(&text) {
+        Ok(answer) if answer.success => Ok(()),
+        Ok(answer) => Err(Error::Api {
+            status: 200,
+            message: answer
+                .errors
+                .into_iter()
+                .map(|e| e.message)
+                .collect::<Vec<_>>()
+                .join("; "),
+        }),
+        Err(e) => Err(Error::Transport(format!("unexpected response: {}", e))),
+    }
+}
+
+/// `POST /purge/<host>/<path>`, one URL per request. The query string is
+/// passed on as is, the way Fastly's own clients send it.
+fn fastly(purger: &Purger, url: &str) -> Result<(), Error> {
+    let target = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let endpoint = format!("{}/purge/{}", purger.api_url, target);
+    purger.send(|| {
+        let request = purger
+            .agent
+            .post(&endpoint)
+            .header("Fastly-Key", &purger.token);
+        if purger.soft {
+            request.header("Fastly-Soft-Purge", "1").send_empty()
+        } else {
+            request.send_empty()
+        }
+    })?;
+    Ok(())
+}
+
+/// `POST /purge?url=...`; a trailing `*` purges everything under the URL.
+fn bunny(purger: &Purger, url: &str) -> Result<(), Error> {
+    let endpoint = format!("{}/purge", purger.api_url);
+    purger.send(|| {
+        purger
+            .agent
+            .post(&endpoint)
+            .header("AccessKey", &purger.token)
+            .query("url", url)
+            .query("async", "false")
+            .send_empty()
+    })?;
+    Ok(())
+}
+
+/// `POST /edge/purge` with URLs or path prefixes.
+fn edge(purger: &Purger, body: serde_json::Value) -> Result<(), Error> {
+    let endpoint = format!("{}/edge/purge", purger.api_url);
+    let body = body.to_string();
+    purger.send(|| {
+        purger
+            .agent
+            .post(&endpoint)
+            .header("Authorization", &format!("Bearer {}", purger.token))
+            .header("Content-Type", "application/json")
+            .send(body.as_bytes())
+    })?;
+    Ok(())
+}
@@ -0,0 +1,164 @@
+use octa_warden_core::growth::parse_bytes;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// A `chaos.profiles` entry: the faults injected into every connection.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// Added to every chunk, each way.
+    pub latency_ms: u64,
+    /// Up to this much more or less than `latency_ms`, drawn per chunk.
+    pub jitter_ms: u64,
+    /// Bytes per second, each way and per connection (`64KB`); unset for no cap.
+    pub bandwidth: Option<String>,
+    /// Share of responses cut by resetting (RST) their connection.
+    pub reset: f64,
+    /// Share of responses cut by closing their connection cleanly, before
+    /// all of the response is sent.
+    pub truncate: f64,
+}
+
+impl Profile {
+    /// The problems of the profile named `name`, as `(field, problem)`.
+    pub fn validate(&self, name: &str) -> Vec<(String, String)> {
+        let field = |key: &str| format!("chaos.profiles.{}.{}", name, key);
+        let mut problems = Vec::new();
+        if let Some(Err(e)) = self.bandwidth.as_deref().map(parse_bytes) {
+            problems.push((field("bandwidth"), e));
+        }
+        for (key, share) in [("reset", self.reset), ("truncate", self.truncate)] {
+            if !(0.0..=1.0).contains(&share) {
+                problems.push((field(key), format!("{} is not within 0-1", share)));
+            }
+        }
+        if self.reset + self.truncate > 1.0 {
+            problems.push((
+                field("truncate"),
+                "reset and truncate add up to more than 1".to_string(),
+            ));
+        }
+        problems
+    }
+
+    /// The cap in bytes per second; `None` when unset or 0.
+    pub fn bandwidth(&self) -> Option<u64> {
+        self.bandwidth
+            .as_deref()
+            .and_then(|b| parse_bytes(b).ok())
+            .filter(|&b| b > 0)
+    }
+}
+
+/// The profiles every config has; `chaos.profiles` adds to them and may
+/// redefine them.
+pub fn builtin() -> BTreeMap<String, Profile> {
+    BTreeMap::from([
+        ("none".to_string(), Profile::default()),
+        (
+            // A congested mobile link.
+            "slow".to_string(),
+            Profile {
+                latency_ms: 300,
+                jitter_ms: 100,
+                bandwidth: Some("64KB".to_string()),
+                ..Profile::default()
+            },
+        ),
+        (
+            "flaky".to_string(),
+            Profile {
+                latency_ms: 50,
+                jitter_ms: 25,
+                reset: 0.05,
+                truncate: 0.05,
+                ..Profile::default()
+            },
+        ),
+        (
+            "broken".to_string(),
+            Profile {
+                latency_ms: 100,
+                jitter_ms: 100,
+                reset: 0.25,
+                truncate: 0.25,
+                ..Profile::default()
+            },
+        ),
+    ])
+}
+
+/// What happens to a connection at its cut.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    Reset,
+    Truncate,
+}
+
+impl Fault {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Fault::Reset => "reset",
+            Fault::Truncate => "truncate",
+        }
+    }
+}
+
+/// The faults of one connection, drawn from the run's seed and the
+/// connection's number, so the same seed replays the same faults.
+pub struct Plan {
+    rng: StdRng,
+    latency: Duration,
+    jitter: Duration,
+    pub bandwidth: Option<u64>,
+    reset: f64,
+    truncate: f64,
+}
+
+impl Plan {
+    pub fn new(profile: &Profile, seed: u64, connection: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed ^ connection.wrapping_mul(0x9E37_79B9_7F4A_7C15)),
+            latency: Duration::from_millis(profile.latency_ms),
+            jitter: Duration::from_millis(profile.jitter_ms),
+            bandwidth: profile.bandwidth(),
+            reset: profile.reset,
+            truncate: profile.truncate,
+        }
+    }
+
+    /// A second plan for the other direction, drawing its own delays.
+    pub fn fork(&mut self) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(self.rng.random()),
+            ..*self
+        }
+    }
+
+    /// The delay of the next chunk.
+    pub fn delay(&mut self) -> Duration {
+        if self.jitter.is_zero() {
+            return self.latency;
+        }
+        let jitter = self.jitter.as_millis() as i64;
+        let offset = self.rng.random_range(-jitter..=jitter);
+        Duration::from_millis((self.latency.as_millis() as i64 + offset).max(0) as u64)
+    }
+
+    /// The fault of the next response, if any, and how far into it (0-1)
+    /// it hits.
+    pub fn fault(&mut self) -> Option<(Fault, f64)> {
+        let roll: f64 = self.rng.random();
+        let fault = if roll < self.reset {
+            Fault::Reset
+        } else if roll < self.reset + self.truncate {
+            Fault::Truncate
+        } else {
+            return None;
+        };
+        Some((fault, self.rng.random()))
+    }
+}
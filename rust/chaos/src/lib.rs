@@ -0,0 +1,330 @@
+use clap::Parser;
+use faults::{Fault, Plan, Profile};
+use octa_config::{ConfigError, ServerConfig, Validate};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::{debug, error, info, Level};
+
+mod faults;
+mod proxy;
+
+/*
+OCTA-CHAOS: Fault-injecting proxy for resilience tests
+=============================================
+Mission: Sit between a client (octa-pulse, an SDK, a browser) and an Octa
+         server and degrade the network between them: latency and jitter,
+         a bandwidth cap, connections reset or responses cut short, as a
+         named profile. The same seed replays the same faults.
+Safety:  A test tool: it listens on 127.0.0.1 by default and must never
+         front production traffic. It only forwards bytes and never
+         changes them; a fault drops them or closes the connection.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Proxy to an Octa server that injects latency, bandwidth caps, resets and truncated responses"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Address to accept clients on (overrides chaos.listen)
+    #[arg(long, env = "OCTA_CHAOS_LISTEN")]
+    listen: Option<String>,
+
+    /// Server to forward to, as host:port (overrides chaos.upstream)
+    #[arg(long, env = "OCTA_CHAOS_UPSTREAM")]
+    upstream: Option<String>,
+
+    /// Fault profile: none, slow, flaky, broken or one of chaos.profiles (overrides chaos.profile)
+    #[arg(short, long)]
+    profile: Option<String>,
+
+    /// Seed of the faults; the same seed replays them (overrides chaos.seed)
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Milliseconds added to every chunk, each way (overrides the profile)
+    #[arg(long)]
+    latency_ms: Option<u64>,
+
+    /// Milliseconds of random variation of the latency (overrides the profile)
+    #[arg(long)]
+    jitter_ms: Option<u64>,
+
+    /// Bytes per second per connection and direction, e.g. 64KB (overrides the profile)
+    #[arg(long)]
+    bandwidth: Option<String>,
+
+    /// Share of connections reset mid-response, 0-1 (overrides the profile)
+    #[arg(long)]
+    reset: Option<f64>,
+
+    /// Share of connections whose first response is cut short, 0-1 (overrides the profile)
+    #[arg(long)]
+    truncate: Option<f64>,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+
+    /// Minimum level to log; `debug` adds one line per injected fault
+    #[arg(long, default_value_t = Level::INFO)]
+    log_level: Level,
+}
+
+/// The parts of config.yaml octa-chaos reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    server: ServerConfig,
+    chaos: ChaosConfig,
+}
+
+/// `chaos:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ChaosConfig {
+    listen: String,
+    /// host:port of the server; `localhost:<server.port>` when unset.
+    upstream: Option<String>,
+    profile: String,
+    /// Unset draws one per run, which is logged.
+    seed: Option<u64>,
+    /// Added to, or replacing, the built-in profiles.
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            listen: "127.0.0.1:9981".to_string(),
+            upstream: None,
+            profile: "flaky".to_string(),
+            seed: None,
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = self.server.validate();
+        let chaos = &self.chaos;
+        if chaos.listen.parse::<SocketAddr>().is_err() {
+            problems.push((
+                "chaos.listen".to_string(),
+                format!("'{}' is not an address like 127.0.0.1:9981", chaos.listen),
+            ));
+        }
+        if let Some(upstream) = &chaos.upstream {
+            let port = upstream
+                .rsplit_once(':')
+                .map(|(_, port)| port.parse::<u16>());
+            if !matches!(port, Some(Ok(_))) {
+                problems.push((
+                    "chaos.upstream".to_string(),
+                    format!("'{}' is not a host:port like localhost:9980", upstream),
+                ));
+            }
+        }
+        if !chaos.profiles.contains_key(&chaos.profile) {
+            problems.push((
+                "chaos.profile".to_string(),
+                format!(
+                    "'{}' is not one of {}",
+                    chaos.profile,
+                    chaos
+                        .profiles
+                        .keys()
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            ));
+        }
+        for (name, profile) in &chaos.profiles {
+            problems.extend(profile.validate(name));
+        }
+        problems
+    }
+}
+
+/// Without a file, the environment and arguments alone configure it. The
+/// built-in profiles and the arguments are applied before the config is
+/// checked.
+fn load_config(args: &Args) -> Result<FileConfig, ConfigError> {
+    let found = octa_config::discover(args.config.as_deref().map(Path::new));
+    let mut config: FileConfig = match &found {
+        Some(found) => octa_config::read(found)?,
+        None => octa_config::from_env()?,
+    };
+    let chaos = &mut config.chaos;
+    let mut profiles = faults::builtin();
+    profiles.append(&mut chaos.profiles);
+    chaos.profiles = profiles;
+    if let Some(listen) = &args.listen {
+        chaos.listen = listen.clone();
+    }
+    if let Some(upstream) = &args.upstream {
+        chaos.upstream = Some(upstream.clone());
+    }
+    if let Some(profile) = &args.profile {
+        chaos.profile = profile.clone();
+    }
+    if let Some(seed) = args.seed {
+        chaos.seed = Some(seed);
+    }
+    if let Some(profile) = chaos.profiles.get_mut(&chaos.profile) {
+        if let Some(latency_ms) = args.latency_ms {
+            profile.latency_ms = latency_ms;
+        }
+        if let Some(jitter_ms) = args.jitter_ms {
+            profile.jitter_ms = jitter_ms;
+        }
+        if let Some(bandwidth) = &args.bandwidth {
+            profile.bandwidth = Some(bandwidth.clone());
+        }
+        if let Some(reset) = args.reset {
+            profile.reset = reset;
+        }
+        if let Some(truncate) = args.truncate {
+            profile.truncate = truncate;
+        }
+    }
+    let origin = found.unwrap_or_else(|| "<environment>".into());
+    octa_config::check(&origin, config)
+}
+
+/// Connections seen and what happened to them.
+#[derive(Default)]
+struct Stats {
+    connections: AtomicU64,
+    resets: AtomicU64,
+    truncated: AtomicU64,
+    failed: AtomicU64,
+}
+
+/// The octa-chaos command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa chaos` the arguments it was given.
+#[tokio::main]
+pub async fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, args.log_level, false, false);
+
+    let config = match load_config(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let mut chaos = config.chaos;
+    let upstream = chaos
+        .upstream
+        .unwrap_or_else(|| format!("localhost:{}", config.server.port));
+    let seed = chaos.seed.unwrap_or_else(rand::random);
+    let profile = Arc::new(chaos.profiles.remove(&chaos.profile).unwrap_or_default());
+    let upstream: Arc<str> = upstream.into();
+
+    let listener = match tokio::net::TcpListener::bind(&chaos.listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(tag = "FATAL", addr = %chaos.listen, reason = %e, "Could not bind");
+            return Kind::Unavailable.exit_code();
+        }
+    };
+    info!(
+        tag = "OK",
+        addr = %chaos.listen,
+        upstream = %upstream,
+        profile = %chaos.profile,
+        seed,
+        latency_ms = profile.latency_ms,
+        jitter_ms = profile.jitter_ms,
+        bandwidth = profile.bandwidth.as_deref().unwrap_or("unlimited"),
+        reset = profile.reset,
+        truncate = profile.truncate,
+        "Chaos proxy listening"
+    );
+
+    let stats = Arc::new(Stats::default());
+    let accept = async {
+        loop {
+            let (client, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    error!(tag = "WARN", reason = %e, "Could not accept a connection");
+                    continue;
+                }
+            };
+            let number = stats.connections.fetch_add(1, Ordering::Relaxed);
+            let plan = Plan::new(&profile, seed, number);
+            let (upstream, stats) = (upstream.clone(), stats.clone());
+            tokio::spawn(async move {
+                match proxy::run(client, &upstream, plan).await {
+                    Ok(None) => {}
+                    Ok(Some(fault)) => {
+                        let counter = match fault {
+                            Fault::Reset => &stats.resets,
+                            Fault::Truncate => &stats.truncated,
+                        };
+                        counter.fetch_add(1, Ordering::Relaxed);
+                        debug!(tag = "CHAOS", connection = number, peer = %peer, fault = fault.as_str(), "Injected");
+                    }
+                    Err(e) => {
+                        stats.failed.fetch_add(1, Ordering::Relaxed);
+                        debug!(tag = "WARN", connection = number, peer = %peer, reason = %e, "Connection failed");
+                    }
+                }
+            });
+        }
+    };
+    tokio::select! {
+        _ = accept => {},
+        _ = shutdown() => {},
+    }
+    info!(
+        tag = "OK",
+        connections = stats.connections.load(Ordering::Relaxed),
+        resets = stats.resets.load(Ordering::Relaxed),
+        truncated = stats.truncated.load(Ordering::Relaxed),
+        failed = stats.failed.load(Ordering::Relaxed),
+        seed,
+        "Shut down"
+    );
+    ExitCode::SUCCESS
+}
+
+/// Resolves on Ctrl-C or SIGTERM.
+async fn shutdown() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
@@ -0,0 +1,5 @@
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    octa_chaos::cli(std::env::args_os())
+}
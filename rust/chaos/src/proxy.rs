@@ -0,0 +1,260 @@
+use crate::faults::{Fault, Plan};
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// Largest chunk read at once.
+const CHUNK: usize = 16 * 1024;
+/// Chunks read ahead of a delayed or capped writer.
+const QUEUE: usize = 64;
+/// Longest response head searched for its end; past it (or a protocol that
+/// is not HTTP), the connection is passed through without faults.
+const MAX_HEAD: usize = 64 * 1024;
+
+/// Proxies one client connection to `upstream` until either side closes
+/// or a response is cut, returning the fault that cut it.
+pub async fn run(client: TcpStream, upstream: &str, mut plan: Plan) -> io::Result<Option<Fault>> {
+    let server = TcpStream::connect(upstream).await?;
+    client.set_nodelay(true)?;
+    server.set_nodelay(true)?;
+    let requests_plan = plan.fork();
+    let (client_read, client_write) = client.into_split();
+    let (server_read, server_write) = server.into_split();
+
+    let requests = pump(client_read, server_write, requests_plan, None);
+    let responses = pump(server_read, client_write, plan, Some(Responses::default()));
+    tokio::pin!(requests, responses);
+    // A fault drops the other direction too, closing both sockets.
+    tokio::select! {
+        fault = &mut responses => match fault? {
+            Some(fault) => Ok(Some(fault)),
+            None => requests.await,
+        },
+        sent = &mut requests => {
+            sent?;
+            responses.await
+        }
+    }
+}
+
+/// Copies `from` into `to`, each chunk delayed by the plan from the moment
+/// it was read (so delays overlap rather than add up) and capped to the
+/// plan's bandwidth. With `responses`, the plan's faults cut them.
+async fn pump(
+    mut from: OwnedReadHalf,
+    mut to: OwnedWriteHalf,
+    mut plan: Plan,
+    mut responses: Option<Responses>,
+) -> io::Result<Option<Fault>> {
+    let (tx, mut rx) = mpsc::channel::<(Instant, Vec<u8>)>(QUEUE);
+    let mut reader = Aborting(tokio::spawn(async move {
+        let mut buf = vec![0; CHUNK];
+        loop {
+            let n = from.read(&mut buf).await?;
+            if n == 0 || tx.send((Instant::now(), buf[..n].to_vec())).await.is_err() {
+                return Ok::<_, io::Error>(());
+            }
+        }
+    }));
+    let mut throttle = plan.bandwidth.map(Throttle::new);
+
+    while let Some((read_at, chunk)) = rx.recv().await {
+        tokio::time::sleep_until(read_at + plan.delay()).await;
+        let Some(responses) = &mut responses else {
+            write(&mut to, &chunk, &mut throttle).await?;
+            continue;
+        };
+        let (pass, fault) = responses.feed(&mut plan, &chunk);
+        write(&mut to, &pass, &mut throttle).await?;
+        match fault {
+            Some(Fault::Truncate) => to.shutdown().await?,
+            // Without lingering, closing sends RST. Dropping the half would
+            // send FIN first, so the socket closes with the read half.
+            Some(Fault::Reset) => {
+                to.as_ref().set_zero_linger()?;
+                to.forget();
+            }
+            None => continue,
+        }
+        return Ok(fault);
+    }
+    (&mut reader.0).await.map_err(io::Error::other)??;
+    if let Some(responses) = &mut responses {
+        write(&mut to, &responses.held(), &mut throttle).await?;
+    }
+    to.shutdown().await?;
+    Ok(None)
+}
+
+async fn write(
+    to: &mut OwnedWriteHalf,
+    chunk: &[u8],
+    throttle: &mut Option<Throttle>,
+) -> io::Result<()> {
+    let Some(throttle) = throttle else {
+        return to.write_all(chunk).await;
+    };
+    throttle.resume();
+    for part in chunk.chunks(throttle.slice()) {
+        to.write_all(part).await?;
+        throttle.sent += part.len() as u64;
+        tokio::time::sleep_until(throttle.due()).await;
+    }
+    Ok(())
+}
+
+/// Aborts the task when dropped, so a reader blocked on a socket lets go of
+/// it once its pump is done.
+struct Aborting<T>(JoinHandle<T>);
+
+impl<T> Drop for Aborting<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Paces writes to `rate` bytes per second.
+struct Throttle {
+    rate: u64,
+    start: Instant,
+    sent: u64,
+}
+
+impl Throttle {
+    fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            start: Instant::now(),
+            sent: 0,
+        }
+    }
+
+    /// When everything sent so far is paid for.
+    fn due(&self) -> Instant {
+        self.start + Duration::from_secs_f64(self.sent as f64 / self.rate as f64)
+    }
+
+    /// Restarts the count after an idle spell, which earns no burst.
+    fn resume(&mut self) {
+        let now = Instant::now();
+        if self.due() < now {
+            self.start = now;
+            self.sent = 0;
+        }
+    }
+
+    /// Writes of 1/20 s, so the cap holds within a chunk.
+    fn slice(&self) -> usize {
+        ((self.rate / 20) as usize).clamp(1, CHUNK)
+    }
+}
+
+/// Follows the responses of a connection, framed by their Content-Length,
+/// to cut those the plan picks.
+#[derive(Default)]
+struct Responses {
+    /// The head of the current response, held back until complete.
+    head: Vec<u8>,
+    /// Body bytes of the current response still to come, once its head is in.
+    body: Option<usize>,
+    /// The current response's fault and the bytes still to pass before it.
+    cut: Option<(Fault, usize)>,
+    /// No longer framed (chunked, not HTTP): the rest passes as is.
+    lost: bool,
+}
+
+impl Responses {
+    /// What of `chunk` to send now, and the fault to apply after it.
+    fn feed(&mut self, plan: &mut Plan, mut chunk: &[u8]) -> (Vec<u8>, Option<Fault>) {
+        let mut pass = Vec::with_capacity(chunk.len() + self.head.len());
+        while !chunk.is_empty() {
+            if self.lost {
+                pass.extend_from_slice(chunk);
+                break;
+            }
+            let Some(left) = self.body else {
+                let before = self.head.len();
+                self.head.extend_from_slice(chunk);
+                let Some(end) = self.head.windows(4).position(|w| w == b"\r\n\r\n") else {
+                    if self.head.len() > MAX_HEAD {
+                        self.lost = true;
+                        pass.append(&mut self.head);
+                    }
+                    break;
+                };
+                let head = end + 4;
+                chunk = &chunk[head - before..];
+                self.head.truncate(head);
+                let length = content_length(&self.head[..end]);
+                self.lost = length.is_none() && has_body(&self.head);
+                let body = length.unwrap_or(0);
+                self.cut = plan
+                    .fault()
+                    .map(|(fault, at)| (fault, ((head + body) as f64 * at) as usize));
+                let head = std::mem::take(&mut self.head);
+                if let Some(fault) = self.advance(&head, &mut pass) {
+                    return (pass, Some(fault));
+                }
+                self.body = Some(body);
+                continue;
+            };
+            let n = left.min(chunk.len());
+            if let Some(fault) = self.advance(&chunk[..n], &mut pass) {
+                return (pass, Some(fault));
+            }
+            chunk = &chunk[n..];
+            self.body = Some(left - n);
+            if left == n {
+                self.body = None;
+                self.cut = None;
+            }
+        }
+        (pass, None)
+    }
+
+    /// Passes `bytes` of the current response, up to its cut.
+    fn advance(&mut self, bytes: &[u8], pass: &mut Vec<u8>) -> Option<Fault> {
+        match &mut self.cut {
+            Some((fault, at)) if *at < bytes.len() => {
+                pass.extend_from_slice(&bytes[..*at]);
+                Some(*fault)
+            }
+            Some((_, at)) => {
+                *at -= bytes.len();
+                pass.extend_from_slice(bytes);
+                None
+            }
+            None => {
+                pass.extend_from_slice(bytes);
+                None
+            }
+        }
+    }
+
+    /// A head still held when the server closed.
+    fn held(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.head)
+    }
+}
+
+/// Whether a response without Content-Length still has a body: all but
+/// 1xx, 204 and 304 do, chunked or up to the close.
+fn has_body(head: &[u8]) -> bool {
+    let status = head.split(|&b| b == b' ').nth(1).unwrap_or_default();
+    !(status.starts_with(b"1") || status == b"204" || status == b"304")
+}
+
+fn content_length(head: &[u8]) -> Option<usize> {
+    String::from_utf8_lossy(head).lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("content-length")
+            .then(|| value.trim().parse().ok())
+            .flatten()
+    })
+}
@@ -0,0 +1,97 @@
+//! The checks octa-server runs on an upload before it decodes anything, to
+//! run before sending one: the same [`octa_key`] and [`octa_image`] code, so
+//! what passes here fails on the server only for reasons found later (a
+//! corrupt image, a full disk). Needs neither reqwest nor tokio, and builds
+//! for `wasm32` (see the `wasm` feature).
+//!
+//! ```
+//! use octa_client::check::{self, Limits, Rejected};
+//!
+//! let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+//! let keys = check::upload("Alice, team/alice", png, &Limits::default()).unwrap();
+//! assert_eq!(keys, ["alice", "team/alice"]);
+//!
+//! let gif = b"GIF89a";
+//! assert!(matches!(
+//!     check::upload("alice", gif, &Limits::default()),
+//!     Err(Rejected::Image(_))
+//! ));
+//! ```
+
+use std::fmt;
+
+pub use octa_image::Error as ImageError;
+pub use octa_key::Invalid;
+
+/// The server's `image:` limits an upload is checked against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// `image.max_upload_size`, in bytes.
+    pub max_upload: u64,
+    /// `image.max_key_limit`.
+    pub max_keys: usize,
+}
+
+impl Default for Limits {
+    /// octa-server's defaults: 5 MB, 7 keys.
+    fn default() -> Self {
+        Self {
+            max_upload: octa_image::DEFAULT_MAX_UPLOAD,
+            max_keys: 7,
+        }
+    }
+}
+
+/// Why the server would refuse an upload. The messages are the server's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejected {
+    /// No key of the `keys` field is valid.
+    NoKeys,
+    /// More valid keys than [`Limits::max_keys`].
+    TooManyKeys(usize),
+    /// Too large, or not JPEG or PNG.
+    Image(ImageError),
+}
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejected::NoKeys => f.write_str("At least one valid key is required."),
+            Rejected::TooManyKeys(_) => f.write_str("Too many keys provided."),
+            Rejected::Image(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// `raw` as the server stores it, or why it is no key.
+pub fn key(raw: &str) -> Result<String, Invalid> {
+    octa_key::parse(raw)
+}
+
+/// The keys of a comma-separated `keys` field the server keeps: valid
+/// ones, normalized, unique, in the order given. Invalid ones are dropped
+/// silently, as the server does; check them one by one with [`key`] to say
+/// why.
+pub fn keys(raw: &str, limits: &Limits) -> Result<Vec<String>, Rejected> {
+    let keys = octa_key::parse_list(raw);
+    match keys.len() {
+        0 => Err(Rejected::NoKeys),
+        n if n > limits.max_keys => Err(Rejected::TooManyKeys(n)),
+        _ => Ok(keys),
+    }
+}
+
+/// The format of an image file (`jpeg` or `png`), judged by content, when
+/// it is within [`Limits::max_upload`] and of a format the server takes.
+pub fn image(data: &[u8], limits: &Limits) -> Result<String, ImageError> {
+    octa_image::validate(data, limits.max_upload).map(octa_image::format_name)
+}
+
+/// Both checks of an upload, returning the keys the server would store.
+pub fn upload(raw_keys: &str, data: &[u8], limits: &Limits) -> Result<Vec<String>, Rejected> {
+    let keys = keys(raw_keys, limits)?;
+    image(data, limits).map_err(Rejected::Image)?;
+    Ok(keys)
+}
@@ -0,0 +1,274 @@
+//! The HTTP and gRPC calls of [`Client`].
+
+mod grpc;
+
+use crate::error::{self, Error};
+use crate::types::{self, Asset, ListPage, Upload, UploadOptions};
+use bytes::Bytes;
+use reqwest::multipart::{Form, Part};
+use reqwest::{Method, RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+
+/// The upload endpoints take the upload secret in this header.
+const SECRET_HEADER: &str = "X-Secret-Key";
+
+/// Requests without an explicit timeout give up after this long.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Builds a [`Client`]; see [`Client::builder`].
+#[derive(Debug)]
+pub struct ClientBuilder {
+    base_url: String,
+    secret: Option<String>,
+    timeout: Duration,
+    user_agent: Option<String>,
+    http: Option<reqwest::Client>,
+    grpc: bool,
+}
+
+impl ClientBuilder {
+    /// `security.upload_secret` of the server. Needed by everything but
+    /// [`Client::get_avatar`].
+    pub fn secret(mut self, secret: impl Into<String>) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+
+    /// Per-request timeout (default 30s). Ignored with [`Self::http_client`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Ignored with [`Self::http_client`].
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Shares an existing reqwest client (and its connection pool). With
+    /// [`Self::grpc`] and an `http://` URL, it must speak HTTP/2 with prior
+    /// knowledge.
+    pub fn http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = Some(http);
+        self
+    }
+
+    /// Makes every call over gRPC (`rust/grpc/octa.proto`), which only
+    /// octa-server serves: HTTP/2 on its usual port, without TLS for
+    /// `http://` URLs. Results and errors are the same as over HTTP.
+    pub fn grpc(mut self) -> Self {
+        self.grpc = true;
+        self
+    }
+
+    pub fn build(self) -> Result<Client, Error> {
+        let base_url = self.base_url.trim().trim_end_matches('/').to_string();
+        if !base_url.starts_with("http://") && !base_url.starts_with("https://") {
+            return Err(Error::Config(format!(
+                "base URL '{}' is not an http(s) URL",
+                base_url
+            )));
+        }
+        let http = match self.http {
+            Some(http) => http,
+            None => {
+                let builder = reqwest::Client::builder().timeout(self.timeout).user_agent(
+                    self.user_agent
+                        .unwrap_or_else(|| format!("octa-client/{}", env!("CARGO_PKG_VERSION"))),
+                );
+                if self.grpc {
+                    builder.http2_prior_knowledge().build()?
+                } else {
+                    builder.build()?
+                }
+            }
+        };
+        Ok(Client {
+            http,
+            base_url,
+            secret: self.secret,
+            grpc: self.grpc,
+            headers: Vec::new(),
+        })
+    }
+}
+
+/// A handle to one Octa server. Cheap to clone; clones share connections.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    secret: Option<String>,
+    /// Calls go over gRPC.
+    grpc: bool,
+    /// Sent with every call, see [`Client::with_headers`].
+    headers: Vec<(String, String)>,
+}
+
+impl Client {
+    /// `base_url` is the server's root, e.g. `http://localhost:9980`.
+    pub fn builder(base_url: impl Into<String>) -> ClientBuilder {
+        ClientBuilder {
+            base_url: base_url.into(),
+            secret: None,
+            timeout: DEFAULT_TIMEOUT,
+            user_agent: None,
+            http: None,
+            grpc: false,
+        }
+    }
+
+    /// This client, sending `headers` with every call as well, over HTTP and
+    /// gRPC alike: trace context (`traceparent`), a request ID.
+    pub fn with_headers(&self, headers: impl IntoIterator<Item = (String, String)>) -> Client {
+        let mut client = self.clone();
+        client.headers.extend(headers);
+        client
+    }
+
+    /// Stores `image` under `key` (`POST /upload`), replacing the image if
+    /// `key` already has one; aliases come from [`UploadOptions::alias`].
+    pub async fn upload_avatar(
+        &self,
+        key: &str,
+        image: impl Into<Bytes>,
+        options: UploadOptions,
+    ) -> Result<Upload, Error> {
+        if self.grpc {
+            return self.grpc_upload(key, image.into(), options).await;
+        }
+        let keys = std::iter::once(key.to_string())
+            .chain(options.aliases)
+            .collect::<Vec<_>>()
+            .join(",");
+        let file_name = options.file_name.unwrap_or_else(|| "avatar".to_string());
+        let mut form = Form::new()
+            .text("keys", keys)
+            .part("avatar", Part::stream(image.into()).file_name(file_name));
+        if let Some(mode) = options.mode {
+            form = form.text("mode", mode.as_str());
+        }
+        if let Some(size) = options.size {
+            form = form.text("size", size.to_string());
+        }
+        if let Some(scale) = options.scale {
+            form = form.text("scale", scale.to_string());
+        }
+        let request = self.authed(self.request(Method::POST, "/upload"))?;
+        json(request.multipart(form).send().await?).await
+    }
+
+    /// The image served for `key` (`GET /u/<key>`). The server answers keys
+    /// it does not store with a generated avatar, not a 404; use
+    /// [`Self::stat`] to tell the two apart.
+    pub async fn get_avatar(&self, key: &str) -> Result<Bytes, Error> {
+        if self.grpc {
+            return self.grpc_get_avatar(key).await;
+        }
+        let response = self
+            .request(Method::GET, &format!("/u/{}", key))
+            .send()
+            .await?;
+        Ok(check(response).await?.bytes().await?)
+    }
+
+    /// The generated avatar for `seed` (`GET /avatar/<seed>`), whether or
+    /// not a key of that name is stored.
+    pub async fn generated_avatar(&self, seed: &str) -> Result<Bytes, Error> {
+        if self.grpc {
+            return self.grpc_generated_avatar(seed).await;
+        }
+        let response = self
+            .request(Method::GET, &format!("/avatar/{}", seed))
+            .send()
+            .await?;
+        Ok(check(response).await?.bytes().await?)
+    }
+
+    /// Metadata and every key of the asset behind `key` (`GET /upload/stat`).
+    pub async fn stat(&self, key: &str) -> Result<Asset, Error> {
+        if self.grpc {
+            return self.grpc_stat(key).await;
+        }
+        let request = self.authed(self.request(Method::GET, "/upload/stat"))?;
+        json(request.query(&[("key", key)]).send().await?).await
+    }
+
+    /// Deletes the asset behind `key`, with all its keys
+    /// (`DELETE /upload/delete`). Returns the asset id.
+    pub async fn delete(&self, key: &str) -> Result<String, Error> {
+        self.delete_by(("key", key)).await
+    }
+
+    /// Like [`Self::delete`], by asset id.
+    pub async fn delete_id(&self, id: &str) -> Result<String, Error> {
+        self.delete_by(("id", id)).await
+    }
+
+    async fn delete_by(&self, target: (&str, &str)) -> Result<String, Error> {
+        if self.grpc {
+            let (key, id) = match target {
+                ("id", id) => (String::new(), id.to_string()),
+                (_, key) => (key.to_string(), String::new()),
+            };
+            return self.grpc_delete(octa_grpc::pb::Target { key, id }).await;
+        }
+        let request = self.authed(self.request(Method::DELETE, "/upload/delete"))?;
+        let deleted: types::Deleted = json(request.query(&[target]).send().await?).await?;
+        Ok(deleted.target)
+    }
+
+    /// One page of keys starting with `prefix`, in key order
+    /// (`GET /upload/list`). Pass the previous page's `next` as `after`.
+    pub async fn list(
+        &self,
+        prefix: &str,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<ListPage, Error> {
+        if self.grpc {
+            return self.grpc_list(prefix, after, limit).await;
+        }
+        let mut query = vec![("prefix", prefix.to_string()), ("limit", limit.to_string())];
+        if let Some(after) = after {
+            query.push(("after", after.to_string()));
+        }
+        let request = self.authed(self.request(Method::GET, "/upload/list"))?;
+        json(request.query(&query).send().await?).await
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.headers
+            .iter()
+            .fold(self.http.request(method, self.url(path)), |request, (name, value)| {
+                request.header(name, value)
+            })
+    }
+
+    fn authed(&self, request: RequestBuilder) -> Result<RequestBuilder, Error> {
+        match &self.secret {
+            Some(secret) => Ok(request.header(SECRET_HEADER, secret)),
+            None => Err(Error::Config(
+                "this endpoint needs the upload secret (ClientBuilder::secret)".to_string(),
+            )),
+        }
+    }
+}
+
+async fn check(response: Response) -> Result<Response, Error> {
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        Err(error::from_response(response).await)
+    }
+}
+
+async fn json<T: DeserializeOwned>(response: Response) -> Result<T, Error> {
+    Ok(check(response).await?.json().await?)
+}
@@ -0,0 +1,151 @@
+//! The calls of [`Client`] over gRPC (`octa.proto`), for
+//! [`ClientBuilder::grpc`](crate::ClientBuilder::grpc).
+
+use crate::error::{self, Error};
+use crate::types::{Action, Asset, ListItem, ListPage, Upload, UploadOptions};
+use super::Client;
+use bytes::Bytes;
+use http_body_util::BodyExt;
+use octa_grpc::{pb, Status};
+use reqwest::header::CONTENT_TYPE;
+
+impl Client {
+    /// One unary call; `authed` sends the upload secret.
+    async fn call<Req, Rep>(&self, method: &str, request: &Req, authed: bool) -> Result<Rep, Error>
+    where
+        Req: prost::Message,
+        Rep: prost::Message + Default,
+    {
+        let mut builder = self
+            .request(reqwest::Method::POST, &octa_grpc::path(method))
+            .header(CONTENT_TYPE, octa_grpc::CONTENT_TYPE)
+            .header("te", "trailers")
+            .body(octa_grpc::encode(request));
+        if authed {
+            builder = self.authed(builder)?;
+        }
+        let response = builder.send().await?;
+        // Not a gRPC answer: a proxy, or a server without the routes.
+        if !response.status().is_success() {
+            return Err(error::from_response(response).await);
+        }
+        let (parts, body) = http::Response::<reqwest::Body>::from(response).into_parts();
+        let collected = body.collect().await?;
+        // A failure comes trailers-only, its status in the headers.
+        let outcome = match Status::from_trailers(&parts.headers) {
+            Some(outcome) => Some(outcome),
+            None => collected.trailers().and_then(Status::from_trailers),
+        };
+        match outcome {
+            Some(Ok(())) => octa_grpc::decode(&collected.to_bytes())
+                .map_err(|e| Error::Protocol(format!("invalid {} reply: {}", method, e.message))),
+            Some(Err(status)) => Err(error::from_status(status)),
+            None => Err(Error::Protocol(format!(
+                "{} answered without grpc-status (not octa-server?)",
+                method
+            ))),
+        }
+    }
+
+    pub(crate) async fn grpc_upload(
+        &self,
+        key: &str,
+        image: Bytes,
+        options: UploadOptions,
+    ) -> Result<Upload, Error> {
+        let request = pb::UploadRequest {
+            keys: std::iter::once(key.to_string())
+                .chain(options.aliases)
+                .collect(),
+            image,
+            mode: options
+                .mode
+                .map(|m| m.as_str().to_string())
+                .unwrap_or_default(),
+            size: options.size.unwrap_or_default(),
+            scale: options.scale.unwrap_or_default(),
+        };
+        let reply: pb::UploadReply = self.call("Upload", &request, true).await?;
+        Ok(Upload {
+            action: match reply.action.as_str() {
+                "created" => Action::Created,
+                _ => Action::Updated,
+            },
+            id: reply.avatar_id,
+            keys: reply.keys,
+            url: reply.url,
+            size_kb: reply.size_kb,
+        })
+    }
+
+    pub(crate) async fn grpc_get_avatar(&self, key: &str) -> Result<Bytes, Error> {
+        let request = pb::AvatarRequest {
+            key: key.to_string(),
+            ..Default::default()
+        };
+        let reply: pb::Avatar = self.call("GetAvatar", &request, false).await?;
+        Ok(reply.data)
+    }
+
+    pub(crate) async fn grpc_generated_avatar(&self, seed: &str) -> Result<Bytes, Error> {
+        let request = pb::AvatarRequest {
+            key: seed.to_string(),
+            generated: true,
+            ..Default::default()
+        };
+        let reply: pb::Avatar = self.call("GetAvatar", &request, false).await?;
+        Ok(reply.data)
+    }
+
+    pub(crate) async fn grpc_stat(&self, key: &str) -> Result<Asset, Error> {
+        let request = pb::Target {
+            key: key.to_string(),
+            id: String::new(),
+        };
+        let asset: pb::Asset = self.call("Stat", &request, true).await?;
+        Ok(Asset {
+            id: asset.avatar_id,
+            keys: asset.keys,
+            width: asset.width,
+            height: asset.height,
+            format: asset.format,
+            size: asset.size,
+            created_at: asset.created_at,
+            updated_at: asset.updated_at,
+            url: asset.url,
+        })
+    }
+
+    pub(crate) async fn grpc_delete(&self, target: pb::Target) -> Result<String, Error> {
+        let reply: pb::DeleteReply = self.call("Delete", &target, true).await?;
+        Ok(reply.target)
+    }
+
+    pub(crate) async fn grpc_list(
+        &self,
+        prefix: &str,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<ListPage, Error> {
+        let request = pb::ListRequest {
+            prefix: prefix.to_string(),
+            after: after.unwrap_or_default().to_string(),
+            limit,
+        };
+        let reply: pb::ListReply = self.call("List", &request, true).await?;
+        Ok(ListPage {
+            items: reply
+                .items
+                .into_iter()
+                .map(|item| ListItem {
+                    key: item.key,
+                    id: item.avatar_id,
+                    size: item.size,
+                    format: item.format,
+                    updated_at: item.updated_at,
+                })
+                .collect(),
+            next: (!reply.next.is_empty()).then_some(reply.next),
+        })
+    }
+}
@@ -0,0 +1,163 @@
+use octa_errors::Kind;
+use serde::Deserialize;
+use std::fmt;
+use std::time::Duration;
+
+/// Why a call failed.
+#[derive(Debug)]
+pub enum Error {
+    /// The upload secret is missing or wrong (401, or the server's 403
+    /// `auth/invalid_credentials`).
+    Unauthorized { message: String },
+    /// No asset for the key or id (404).
+    NotFound { message: String },
+    /// The per-IP rate limit was hit (429). `retry_after` is set when the
+    /// server sent a `Retry-After` in seconds.
+    RateLimited {
+        retry_after: Option<Duration>,
+        message: String,
+    },
+    /// Any other error response; `code` as in the server's JSON
+    /// (`request/body_too_large`), when the body had one.
+    Api {
+        status: u16,
+        code: Option<String>,
+        message: String,
+    },
+    /// No usable response: connection, TLS, timeout, or a body that could
+    /// not be read or decoded.
+    Http(reqwest::Error),
+    /// A gRPC answer that breaks the protocol: no status, or a message that
+    /// does not decode.
+    Protocol(String),
+    /// The client was built with an invalid option.
+    Config(String),
+}
+
+impl Error {
+    /// Worth retrying later, unchanged: rate limits, 5xx, timeouts and
+    /// connection failures.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::RateLimited { .. } => true,
+            Error::Api { status, .. } => *status >= 500,
+            Error::Http(e) => e.is_timeout() || e.is_connect(),
+            _ => false,
+        }
+    }
+
+    /// What a tool failing on it exits with: a server that cannot be
+    /// reached or fails is [`Kind::Unavailable`], a wrong secret or option
+    /// [`Kind::Config`], a missing asset [`Kind::NoInput`], and anything
+    /// else the server refused [`Kind::Data`].
+    pub fn kind(&self) -> Kind {
+        match self {
+            Error::Unauthorized { .. } | Error::Config(_) => Kind::Config,
+            Error::NotFound { .. } => Kind::NoInput,
+            Error::Api { status, .. } if *status < 500 => Kind::Data,
+            Error::RateLimited { .. } | Error::Api { .. } | Error::Http(_) | Error::Protocol(_) => {
+                Kind::Unavailable
+            }
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Unauthorized { message } => write!(f, "unauthorized: {}", message),
+            Error::NotFound { message } => write!(f, "not found: {}", message),
+            Error::RateLimited {
+                retry_after: Some(after),
+                message,
+            } => write!(
+                f,
+                "rate limited (retry after {}s): {}",
+                after.as_secs(),
+                message
+            ),
+            Error::RateLimited { message, .. } => write!(f, "rate limited: {}", message),
+            Error::Api {
+                status,
+                code: Some(code),
+                message,
+            } => write!(f, "{} {}: {}", status, code, message),
+            Error::Api {
+                status, message, ..
+            } => write!(f, "{}: {}", status, message),
+            Error::Http(e) => write!(f, "request failed: {}", e),
+            Error::Protocol(e) => write!(f, "invalid gRPC response: {}", e),
+            Error::Config(e) => write!(f, "invalid client configuration: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+/// Body of the server's error responses.
+#[derive(Deserialize)]
+struct ErrorBody {
+    code: String,
+    message: String,
+}
+
+/// Turns a non-2xx response into the matching [`Error`].
+pub(crate) async fn from_response(response: reqwest::Response) -> Error {
+    let status = response.status().as_u16();
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .map(Duration::from_secs);
+    let text = response.text().await.unwrap_or_default();
+    let (code, message) = match serde_json::from_str::<ErrorBody>(&text) {
+        Ok(body) => (Some(body.code), body.message),
+        Err(_) => (None, text.trim().to_string()),
+    };
+    from_parts(status, code, message, retry_after)
+}
+
+/// A failed gRPC call, as the [`Error`] of the same failure over HTTP.
+pub(crate) fn from_status(status: octa_grpc::Status) -> Error {
+    let http_status = status.http_status.unwrap_or_else(|| status.code.to_http());
+    from_parts(http_status, status.octa_code, status.message, None)
+}
+
+fn from_parts(
+    status: u16,
+    code: Option<String>,
+    message: String,
+    retry_after: Option<Duration>,
+) -> Error {
+    match status {
+        401 => Error::Unauthorized { message },
+        // The upload endpoints answer a wrong secret with 403.
+        403 if code.as_deref().is_some_and(|c| c.starts_with("auth/")) => {
+            Error::Unauthorized { message }
+        }
+        404 => Error::NotFound { message },
+        429 => Error::RateLimited {
+            retry_after,
+            message,
+        },
+        _ => Error::Api {
+            status,
+            code,
+            message,
+        },
+    }
+}
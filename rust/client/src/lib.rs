@@ -0,0 +1,52 @@
+//! Async client for the Octa avatar server: upload, fetch, inspect, list and
+//! delete assets with typed results and errors.
+//!
+//! ```no_run
+//! use octa_client::{Client, Error, Mode, UploadOptions};
+//!
+//! # async fn example(image: Vec<u8>) -> Result<(), Error> {
+//! let octa = Client::builder("https://avatars.example.com")
+//!     .secret(std::env::var("OCTA_UPLOAD_SECRET").unwrap_or_default())
+//!     .build()?;
+//!
+//! let upload = octa
+//!     .upload_avatar("alice", image, UploadOptions::new().mode(Mode::Fit).size(512))
+//!     .await?;
+//! println!("{:?} {} -> {}", upload.action, upload.id, upload.url);
+//!
+//! match octa.delete("bob").await {
+//!     Ok(_) | Err(Error::NotFound { .. }) => {}
+//!     Err(e) => return Err(e),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`ClientBuilder::grpc`] makes the same calls over octa-server's gRPC API
+//! instead, without the multipart and JSON encoding.
+//!
+//! [`check`] runs the server's key and image checks on an upload before it
+//! is sent. Without the default `http` feature that is all there is, and the
+//! crate builds for `wasm32-unknown-unknown`; the `wasm` feature exports it
+//! to JavaScript, for browsers to refuse an upload the server would:
+//!
+//! ```sh
+//! wasm-pack build rust/client --target web --no-default-features --features wasm
+//! ```
+
+pub mod check;
+#[cfg(feature = "http")]
+mod client;
+#[cfg(feature = "http")]
+mod error;
+#[cfg(feature = "http")]
+mod types;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+#[cfg(feature = "http")]
+pub use client::{Client, ClientBuilder};
+#[cfg(feature = "http")]
+pub use error::Error;
+#[cfg(feature = "http")]
+pub use types::{Action, Asset, ListItem, ListPage, Mode, Upload, UploadOptions};
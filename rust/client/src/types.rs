@@ -0,0 +1,147 @@
+use serde::Deserialize;
+
+/// How the server stores an uploaded image (the `mode` form field).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    /// Cropped to a `size`×`size` square, re-encoded as JPEG.
+    #[default]
+    Square,
+    /// Shrunk to fit within `size`×`size`, re-encoded as JPEG.
+    Fit,
+    /// Resized to `scale` percent, re-encoded as JPEG.
+    Scale,
+    /// Stored byte for byte.
+    Original,
+}
+
+impl Mode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mode::Square => "square",
+            Mode::Fit => "fit",
+            Mode::Scale => "scale",
+            Mode::Original => "original",
+        }
+    }
+}
+
+/// Options of [`Client::upload_avatar`](crate::Client::upload_avatar).
+/// Unset values are left to the server (square, 256px, 75%).
+///
+/// ```
+/// use octa_client::{Mode, UploadOptions};
+///
+/// let options = UploadOptions::new().mode(Mode::Fit).size(512).alias("alice@example.com");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct UploadOptions {
+    pub(crate) mode: Option<Mode>,
+    pub(crate) size: Option<u32>,
+    pub(crate) scale: Option<u32>,
+    pub(crate) aliases: Vec<String>,
+    pub(crate) file_name: Option<String>,
+}
+
+impl UploadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Edge length in pixels for [`Mode::Square`] and [`Mode::Fit`] (16-2048).
+    pub fn size(mut self, size: u32) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Percentage for [`Mode::Scale`] (1-100).
+    pub fn scale(mut self, percent: u32) -> Self {
+        self.scale = Some(percent);
+        self
+    }
+
+    /// Another key for the same asset. Keys already taken by another asset
+    /// are skipped by the server; [`Upload::keys`] lists the ones assigned.
+    pub fn alias(mut self, key: impl Into<String>) -> Self {
+        self.aliases.push(key.into());
+        self
+    }
+
+    /// File name sent with the upload (the server sniffs the type from the
+    /// bytes, so this is cosmetic).
+    pub fn file_name(mut self, name: impl Into<String>) -> Self {
+        self.file_name = Some(name.into());
+        self
+    }
+}
+
+/// Whether an upload created an asset or replaced the image of an existing one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Created,
+    Updated,
+}
+
+/// Answer of `POST /upload`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Upload {
+    pub action: Action,
+    #[serde(rename = "avatar_id")]
+    pub id: String,
+    /// The keys now mapped to the asset, normalized (lowercase, trimmed).
+    pub keys: Vec<String>,
+    pub url: String,
+    pub size_kb: u64,
+}
+
+/// Metadata of a stored asset (`GET /upload/stat`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct Asset {
+    #[serde(rename = "avatar_id")]
+    pub id: String,
+    pub keys: Vec<String>,
+    pub width: u32,
+    pub height: u32,
+    pub format: String,
+    pub size: u64,
+    /// RFC 3339.
+    pub created_at: String,
+    pub updated_at: String,
+    pub url: String,
+}
+
+/// One key of a listing.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListItem {
+    pub key: String,
+    #[serde(rename = "avatar_id")]
+    pub id: String,
+    pub size: u64,
+    pub format: String,
+    pub updated_at: String,
+}
+
+/// One page of `GET /upload/list`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListPage {
+    pub items: Vec<ListItem>,
+    /// Pass to the next [`Client::list`](crate::Client::list) call; `None`
+    /// on the last page.
+    #[serde(deserialize_with = "empty_as_none")]
+    pub next: Option<String>,
+}
+
+fn empty_as_none<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+    let s = String::deserialize(d)?;
+    Ok((!s.is_empty()).then_some(s))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct Deleted {
+    pub target: String,
+}
@@ -0,0 +1,58 @@
+//! [`check`](crate::check) for JavaScript, through wasm-bindgen. Every
+//! function throws an `Error` with the server's message where the server
+//! would refuse; limits left `undefined` are the server's defaults.
+//!
+//! ```js
+//! import init, { checkUpload } from "./pkg/octa_client.js";
+//!
+//! await init();
+//! const data = new Uint8Array(await file.arrayBuffer());
+//! try {
+//!   const keys = checkUpload("alice, team/alice", data, 5 * 1024 * 1024);
+//! } catch (e) {
+//!   showError(e.message); // "file exceeds size limit (5242880 bytes)"
+//! }
+//! ```
+
+use crate::check::{self, Limits};
+use wasm_bindgen::prelude::*;
+
+/// Limits from JavaScript numbers; `max_upload` is no `u64`, which would
+/// take a BigInt.
+fn limits(max_upload: Option<f64>, max_keys: Option<u32>) -> Limits {
+    let defaults = Limits::default();
+    Limits {
+        max_upload: max_upload.map_or(defaults.max_upload, |bytes| bytes.max(0.0) as u64),
+        max_keys: max_keys.map_or(defaults.max_keys, |keys| keys as usize),
+    }
+}
+
+/// The key as the server stores it (`" /Team//Alice/ "` is `"team/alice"`).
+#[wasm_bindgen(js_name = normalizeKey)]
+pub fn normalize_key(raw: &str) -> Result<String, JsError> {
+    check::key(raw).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// The keys of a comma-separated `keys` field the server keeps.
+#[wasm_bindgen(js_name = parseKeys)]
+pub fn parse_keys(raw: &str, max_keys: Option<u32>) -> Result<Vec<String>, JsError> {
+    check::keys(raw, &limits(None, max_keys)).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// The format of an image file, `"jpeg"` or `"png"`.
+#[wasm_bindgen(js_name = checkImage)]
+pub fn check_image(data: &[u8], max_upload: Option<f64>) -> Result<String, JsError> {
+    check::image(data, &limits(max_upload, None)).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// The keys an upload would be stored under.
+#[wasm_bindgen(js_name = checkUpload)]
+pub fn check_upload(
+    keys: &str,
+    data: &[u8],
+    max_upload: Option<f64>,
+    max_keys: Option<u32>,
+) -> Result<Vec<String>, JsError> {
+    check::upload(keys, data, &limits(max_upload, max_keys))
+        .map_err(|e| JsError::new(&e.to_string()))
+}
@@ -0,0 +1,131 @@
+use serde_yaml::{Mapping, Value};
+
+/// `database.path` is overridden by `OCTA_DATABASE_PATH`, like the Go
+/// server's `AutomaticEnv`.
+const PREFIX: &str = "OCTA_";
+
+/// Names the Go server binds to keys explicitly (`BindEnv`). `OCTA_*` wins
+/// when both are set, as in Viper.
+const ALIASES: [(&str, &str); 3] = [
+    ("database.path", "AVATAR_DATABASE_PATH"),
+    ("security.upload_secret", "AVATAR_SECURITY_UPLOAD_SECRET"),
+    ("server.port", "APP_PORT"),
+];
+
+/// Shared keys that can be set from the environment even when the file does
+/// not mention them, and whether their value is text. Other keys have to be
+/// in the file (with any value) to be overridable, as with Viper.
+const SHARED_KEYS: [(&str, bool); 7] = [
+    ("server.port", false),
+    ("server.env", true),
+    ("database.path", true),
+    ("security.upload_secret", true),
+    ("security.signing_secret", true),
+    ("base_url", true),
+    ("telemetry.endpoint", true),
+];
+
+/// One key set from the environment.
+pub(crate) struct Override {
+    /// Dotted path, as serde_yaml names fields in its errors.
+    pub key: String,
+    pub var: String,
+}
+
+/// The variables that may override a key: `OCTA_*` and the aliases.
+pub(crate) fn variables() -> Vec<(String, String)> {
+    std::env::vars()
+        .filter(|(name, _)| {
+            name.starts_with(PREFIX) || ALIASES.iter().any(|(_, alias)| alias == name)
+        })
+        .collect()
+}
+
+/// Sets every key of `document` (and every shared key) that has a variable.
+/// Values become numbers or booleans where the file has one there, otherwise
+/// text, so a numeric secret stays a string.
+pub(crate) fn apply(document: &mut Value, variables: &[(String, String)]) -> Vec<Override> {
+    let mut keys: Vec<(String, bool)> = Vec::new();
+    collect_leaves(document, "", &mut keys);
+    for (key, text) in SHARED_KEYS {
+        if !keys.iter().any(|(k, _)| k == key) {
+            keys.push((key.to_string(), text));
+        }
+    }
+
+    let lookup = |name: &str| {
+        variables
+            .iter()
+            .find(|(var, _)| var == name)
+            .map(|(_, value)| value.clone())
+    };
+    let mut applied = Vec::new();
+    for (key, text) in keys {
+        let automatic = format!("{}{}", PREFIX, key.to_uppercase().replace('.', "_"));
+        let alias = ALIASES
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, alias)| *alias);
+        let found = lookup(&automatic)
+            .map(|value| (automatic.clone(), value))
+            .or_else(|| alias.and_then(|a| lookup(a).map(|value| (a.to_string(), value))));
+        let Some((var, raw)) = found else {
+            continue;
+        };
+
+        let value = match serde_yaml::from_str::<Value>(&raw) {
+            Ok(parsed @ (Value::Number(_) | Value::Bool(_))) if !text => parsed,
+            _ => Value::String(raw),
+        };
+        if set(document, &key, value) {
+            applied.push(Override { key, var });
+        }
+    }
+    applied
+}
+
+/// Dotted paths of the scalar values under `value`, and whether each is text
+/// (or empty). Sequences are not descended into.
+fn collect_leaves(value: &Value, prefix: &str, out: &mut Vec<(String, bool)>) {
+    let Value::Mapping(map) = value else {
+        return;
+    };
+    for (key, child) in map {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        let path = if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match child {
+            Value::Mapping(_) => collect_leaves(child, &path, out),
+            Value::Sequence(_) | Value::Tagged(_) => {}
+            Value::Number(_) | Value::Bool(_) => out.push((path, false)),
+            Value::String(_) | Value::Null => out.push((path, true)),
+        }
+    }
+}
+
+/// Sets `key`, creating the sections on the way. Returns false when a
+/// section on the way is not a mapping.
+fn set(document: &mut Value, key: &str, value: Value) -> bool {
+    let mut node = document;
+    let mut parts = key.split('.').peekable();
+    while let Some(part) = parts.next() {
+        if node.is_null() {
+            *node = Value::Mapping(Mapping::new());
+        }
+        let Value::Mapping(map) = node else {
+            return false;
+        };
+        let part = Value::String(part.to_string());
+        if parts.peek().is_none() {
+            map.insert(part, value);
+            return true;
+        }
+        node = map.entry(part).or_insert(Value::Null);
+    }
+    false
+}
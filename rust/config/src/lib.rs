@@ -0,0 +1,453 @@
+//! The shared `config.yaml` of the Octa server and its Rust tools
+//! (octa-warden, octa-pulse, octa-ctl, octa-server, octa-migrate, octa-gc,
+//! octa-warm, octa-sync, octa-backup, octa-seed, octa-exporter, octa-logs,
+//! octa-testkit, octa-gateway, octa-identicon, octa-sign, octa-keys, octa-quota,
+//! octa-moderate, octa-gravatar, octa-chaos, octa-transcode, octa-drill, octa-edge,
+//! octa-hooksink, octa-rekey, octa-tail, octa-ratecheck, octa-mock, and `octa`,
+//! which runs them all): where it is found, how environment variables override
+//! it, the sections every tool reads the same way, and errors that name the
+//! offending field.
+//!
+//! Each tool deserializes its own view of the file: the shared sections it
+//! needs plus its own section (`warden:`, `pulse:`). Sections a tool does not
+//! know are ignored, as the Go server ignores the tool sections.
+//!
+//! ```no_run
+//! use octa_config::{DatabaseConfig, ServerConfig, Validate};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Config {
+//!     #[serde(default)]
+//!     server: ServerConfig,
+//!     #[serde(default)]
+//!     database: DatabaseConfig,
+//! }
+//!
+//! impl Validate for Config {
+//!     fn validate(&self) -> Vec<(String, String)> {
+//!         self.server.validate()
+//!     }
+//! }
+//!
+//! let path = octa_config::discover(None).expect("a config.yaml");
+//! let config: Config = octa_config::load(&path).unwrap();
+//! ```
+//!
+//! Daemons [`watch`] the file they were started with and take the reloaded
+//! config between units of work.
+
+mod env;
+mod watch;
+
+pub use watch::{watch, Watch, POLL_INTERVAL};
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Names the config file explicitly, ahead of the directory search.
+pub const PATH_ENV: &str = "OCTA_CONFIG";
+
+/// File name the server and the tools look for.
+pub const FILE_NAME: &str = "config.yaml";
+
+/// Searched after the working directory and its parents.
+const SYSTEM_PATH: &str = "/etc/octa/config.yaml";
+
+/// Finds the config file: `explicit` (e.g. `--config`) as given, then
+/// `$OCTA_CONFIG`, then `config.yaml` in the working directory or the
+/// nearest parent (so tools started from `rust/<tool>` find the repository's
+/// file), then `/etc/octa/config.yaml`. An explicit path is returned even
+/// when it does not exist, so the caller reports it instead of silently
+/// using another file.
+pub fn discover(explicit: Option<&Path>) -> Option<PathBuf> {
+    if let Some(path) = explicit {
+        return Some(path.to_path_buf());
+    }
+    if let Some(path) = std::env::var_os(PATH_ENV).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    let cwd = std::env::current_dir().ok()?;
+    cwd.ancestors()
+        .map(|dir| dir.join(FILE_NAME))
+        .chain(std::iter::once(PathBuf::from(SYSTEM_PATH)))
+        .find(|path| path.is_file())
+}
+
+/// Checks values serde accepts but the tool cannot work with.
+pub trait Validate {
+    /// `(field, problem)` pairs, `field` as a dotted path
+    /// (`warden.retention[0].max_age`); empty means the config is usable.
+    fn validate(&self) -> Vec<(String, String)>;
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub path: PathBuf,
+    /// Dotted path of the field, when the error is about one.
+    pub field: Option<String>,
+    /// Position in the file; `None` once environment variables changed it.
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// The variable that set the field, when one did.
+    pub env: Option<String>,
+    pub reason: String,
+}
+
+/// Why a config could not be loaded.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// No explicit path and nothing found by [`discover`].
+    NotFound,
+    Read {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// The YAML does not parse or does not fit the expected types.
+    Parse(Box<ParseError>),
+    /// Parsed, but [`Validate`] found problems.
+    Invalid {
+        path: PathBuf,
+        problems: Vec<(String, String)>,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::NotFound => write!(
+                f,
+                "no {} found in this or any parent directory (pass --config or set {})",
+                FILE_NAME, PATH_ENV
+            ),
+            ConfigError::Read { path, source } => {
+                write!(f, "could not read {}: {}", path.display(), source)
+            }
+            ConfigError::Parse(e) => {
+                write!(f, "{}", e.path.display())?;
+                if let (Some(line), Some(column)) = (e.line, e.column) {
+                    write!(f, ":{}:{}", line, column)?;
+                }
+                if let Some(field) = &e.field {
+                    write!(f, ": {}", field)?;
+                }
+                write!(f, ": {}", e.reason)?;
+                if let Some(env) = &e.env {
+                    write!(f, " (set by {})", env)?;
+                }
+                Ok(())
+            }
+            ConfigError::Invalid { path, problems } => {
+                write!(f, "{}:", path.display())?;
+                for (field, reason) in problems {
+                    write!(f, " {}: {};", field, reason)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Reads `path`, applies the environment overrides and validates the result.
+pub fn load<T: DeserializeOwned + Validate>(path: &Path) -> Result<T, ConfigError> {
+    let config: T = read(path)?;
+    check(path, config)
+}
+
+/// Like [`load`], without validating: for tools that adjust the config (e.g.
+/// from flags) first, then call [`check`].
+pub fn read<T: DeserializeOwned>(path: &Path) -> Result<T, ConfigError> {
+    let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse(path, &text, &env::variables())
+}
+
+/// Returns `config` if [`Validate`] finds no problems; `path` names it in the error.
+pub fn check<T: Validate>(path: &Path, config: T) -> Result<T, ConfigError> {
+    let problems = config.validate();
+    if !problems.is_empty() {
+        return Err(ConfigError::Invalid {
+            path: path.to_path_buf(),
+            problems,
+        });
+    }
+    Ok(config)
+}
+
+/// The config a tool gets without a file: defaults plus the environment.
+pub fn from_env<T: DeserializeOwned>() -> Result<T, ConfigError> {
+    parse(Path::new("<environment>"), "{}", &env::variables())
+}
+
+fn parse<T: DeserializeOwned>(
+    path: &Path,
+    text: &str,
+    variables: &[(String, String)],
+) -> Result<T, ConfigError> {
+    let error = |e: serde_yaml::Error, positioned: bool, applied: &[env::Override]| {
+        let (field, reason) = split_path(&e);
+        let location = e.location().filter(|_| positioned);
+        let env = field.as_ref().and_then(|field| {
+            applied
+                .iter()
+                .find(|o| o.key == *field)
+                .map(|o| o.var.clone())
+        });
+        ConfigError::Parse(Box::new(ParseError {
+            path: path.to_path_buf(),
+            field,
+            line: location.as_ref().map(|l| l.line()),
+            column: location.as_ref().map(|l| l.column()),
+            env,
+            reason,
+        }))
+    };
+
+    let mut document: serde_yaml::Value = match serde_yaml::from_str(text) {
+        // Empty, or only comments: every section takes its defaults.
+        Ok(serde_yaml::Value::Null) => return parse(path, "{}", variables),
+        Ok(document) => document,
+        Err(e) => return Err(error(e, true, &[])),
+    };
+    let applied = env::apply(&mut document, variables);
+    if applied.is_empty() {
+        // Parsed from the text itself, so errors point at the right line.
+        return serde_yaml::from_str(text).map_err(|e| error(e, true, &[]));
+    }
+    let merged = serde_yaml::to_string(&document).map_err(|e| error(e, false, &applied))?;
+    serde_yaml::from_str(&merged).map_err(|e| error(e, false, &applied))
+}
+
+/// serde_yaml prefixes errors with the dotted path of the field
+/// (`warden.health.critical: unknown field ...`); errors about the document
+/// itself have none.
+fn split_path(e: &serde_yaml::Error) -> (Option<String>, String) {
+    let message = e.to_string();
+    // The location is reported separately.
+    let message = match message.rfind(" at line ") {
+        Some(at) => message[..at].to_string(),
+        None => message,
+    };
+    match message.split_once(": ") {
+        Some((field, reason)) if !field.contains(char::is_whitespace) => {
+            (Some(field.to_string()), reason.to_string())
+        }
+        _ => (None, message),
+    }
+}
+
+/// `server:`, as the Go server reads it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub port: u16,
+    /// `development`, `staging` or `production`.
+    pub env: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            port: 9980,
+            env: "development".to_string(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        if self.port == 0 {
+            problems.push(("server.port".to_string(), "must not be 0".to_string()));
+        }
+        if !["development", "staging", "production"].contains(&self.env.as_str()) {
+            problems.push((
+                "server.env".to_string(),
+                format!(
+                    "'{}' is not one of development, staging, production",
+                    self.env
+                ),
+            ));
+        }
+        problems
+    }
+}
+
+/// `database:`; only the path is shared, the server's pruning keys are its own.
+/// Not validated here: tools on another storage backend, or given `--db`,
+/// do without it.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DatabaseConfig {
+    #[serde(default)]
+    pub path: String,
+}
+
+/// `security:`; the upload secret is sent as `X-Secret-Key`. Keys under a
+/// private prefix are only served on links signed with the signing secret
+/// (see octa-sign); the Go server does not check them.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SecurityConfig {
+    #[serde(default)]
+    pub upload_secret: String,
+    #[serde(default)]
+    pub signing_secret: String,
+    #[serde(default)]
+    pub private_prefixes: Vec<String>,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+}
+
+/// `security.rate_limit`: the Go server's token bucket per client IP,
+/// refilled with `requests` tokens per `window` and holding up to `burst`.
+/// The defaults are the Go server's; octa-server does not limit.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub requests: u32,
+    /// A Go duration: `1s`, `1m`, `1m30s`, `500ms`.
+    pub window: String,
+    pub burst: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            requests: 20,
+            window: "1s".to_string(),
+            burst: 50,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Tokens refilled per second, as the Go server computes them: a
+    /// `requests` of 0 means 20 and a `burst` of 0 means 50 there too.
+    pub fn per_second(&self) -> Result<f64, String> {
+        let window = go_duration(&self.window)?;
+        if window <= 0.0 {
+            return Err(format!("window '{}' must be positive", self.window));
+        }
+        let requests = if self.requests == 0 { 20 } else { self.requests };
+        Ok(f64::from(requests) / window)
+    }
+
+    /// Requests accepted at once from a full bucket.
+    pub fn burst(&self) -> u32 {
+        if self.burst == 0 {
+            50
+        } else {
+            self.burst
+        }
+    }
+}
+
+/// Seconds of a Go `time.ParseDuration` string.
+fn go_duration(value: &str) -> Result<f64, String> {
+    let invalid = || format!("'{}' is not a duration like 1s, 1m or 500ms", value);
+    let mut rest = value.trim();
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    let mut seconds = 0.0;
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .ok_or_else(invalid)?;
+        let number: f64 = rest[..split].parse().map_err(|_| invalid())?;
+        rest = &rest[split..];
+        let unit = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let scale = match &rest[..unit] {
+            "ns" => 1e-9,
+            "us" | "µs" => 1e-6,
+            "ms" => 1e-3,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return Err(invalid()),
+        };
+        seconds += number * scale;
+        rest = &rest[unit..];
+    }
+    Ok(seconds)
+}
+
+impl SecurityConfig {
+    /// Whether `key` lies under one of `private_prefixes`.
+    pub fn is_private(&self, key: &str) -> bool {
+        self.private_prefixes
+            .iter()
+            .any(|prefix| !prefix.is_empty() && key.starts_with(prefix.as_str()))
+    }
+}
+
+/// `telemetry:`, OpenTelemetry traces of the tools that emit them
+/// (octa-pulse, octa-warden). Off while `endpoint` is unset.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    /// OTLP/HTTP collector, e.g. `http://localhost:4318` (Jaeger, Tempo and
+    /// the OpenTelemetry Collector listen there); `/v1/traces` is appended.
+    pub endpoint: Option<String>,
+    /// Share of traces kept, from 0 to 1. A trace started by the server's
+    /// caller keeps the caller's decision.
+    pub sample_ratio: f64,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            sample_ratio: 1.0,
+        }
+    }
+}
+
+impl TelemetryConfig {
+    /// The collector, unless unset or empty.
+    pub fn endpoint(&self) -> Option<&str> {
+        self.endpoint.as_deref().map(str::trim).filter(|e| !e.is_empty())
+    }
+
+    pub fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        problems.extend(check_url("telemetry.endpoint", self.endpoint()));
+        if !(0.0..=1.0).contains(&self.sample_ratio) {
+            problems.push((
+                "telemetry.sample_ratio".to_string(),
+                format!("{} is not between 0 and 1", self.sample_ratio),
+            ));
+        }
+        problems
+    }
+}
+
+/// The server's public root URL: `base_url` when set, otherwise
+/// `http://localhost:<server.port>` (as the Go server derives it).
+pub fn base_url(base_url: Option<&str>, server: &ServerConfig) -> String {
+    match base_url.map(str::trim).filter(|url| !url.is_empty()) {
+        Some(url) => url.trim_end_matches('/').to_string(),
+        None => format!("http://localhost:{}", server.port),
+    }
+}
+
+/// `(field, problem)` when `url` is set and not an http(s) URL.
+pub fn check_url(field: &str, url: Option<&str>) -> Option<(String, String)> {
+    let url = url?;
+    (!url.starts_with("http://") && !url.starts_with("https://")).then(|| {
+        (
+            field.to_string(),
+            format!("'{}' is not an http(s) URL", url),
+        )
+    })
+}
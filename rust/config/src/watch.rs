@@ -0,0 +1,112 @@
+//! Reloading the config file of a running daemon (octa-warden watch,
+//! octa-exporter, octa-edge). A thread polls the file's modification time,
+//! size and inode, so edits in place, editors' rename-over-save and a
+//! ConfigMap's symlink swap are all seen, and loads it again when one of
+//! them changes. The daemon takes the new config at a point where applying
+//! it cannot tear a unit of work (between audit cycles, between samples),
+//! and keeps the config it has when the new one does not load.
+
+use crate::ConfigError;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often the file is checked.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// What identifies one version of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+    inode: u64,
+}
+
+impl Stamp {
+    /// Follows symlinks: a Kubernetes ConfigMap changes its target.
+    fn of(path: &Path) -> Option<Self> {
+        let meta = std::fs::metadata(path).ok()?;
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(&meta);
+        #[cfg(not(unix))]
+        let inode = 0;
+        Some(Self {
+            modified: meta.modified().ok(),
+            len: meta.len(),
+            inode,
+        })
+    }
+}
+
+/// The configs loaded from a changed file, in order; see [`watch`].
+pub struct Watch<T> {
+    path: PathBuf,
+    rx: Receiver<Result<T, ConfigError>>,
+}
+
+/// Loads `path` with `load` every time it changes from now on. `load` is
+/// what the daemon loaded it with at start (reading, its own overrides,
+/// validation), so a reloaded config is checked the same way. The polling
+/// thread ends at the first change after the [`Watch`] is dropped.
+pub fn watch<T, F>(path: &Path, load: F) -> Watch<T>
+where
+    T: Send + 'static,
+    F: Fn(&Path) -> Result<T, ConfigError> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    let watched = path.to_path_buf();
+    thread::spawn(move || {
+        let mut seen = Stamp::of(&watched);
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let stamp = Stamp::of(&watched);
+            // A file gone for a moment (replaced by a non-atomic writer) is
+            // not a change to apply; its reappearance is.
+            if stamp.is_none() || stamp == seen {
+                continue;
+            }
+            seen = stamp;
+            if tx.send(load(&watched)).is_err() {
+                return;
+            }
+        }
+    });
+    Watch {
+        path: path.to_path_buf(),
+        rx,
+    }
+}
+
+impl<T> Watch<T> {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The newest reload since the last call, if the file changed: a
+    /// daemon's check at a safe point. A failed load that a later one
+    /// superseded is skipped.
+    pub fn changed(&self) -> Option<Result<T, ConfigError>> {
+        let mut newest = None;
+        loop {
+            match self.rx.try_recv() {
+                Ok(reload) => newest = Some(reload),
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => return newest,
+            }
+        }
+    }
+
+    /// Like [`Watch::changed`], waiting up to `timeout` for a change: the
+    /// sleep between a daemon's runs, cut short to apply a new config.
+    pub fn wait(&self, timeout: Duration) -> Option<Result<T, ConfigError>> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(reload) => Some(self.changed().unwrap_or(reload)),
+            Err(RecvTimeoutError::Timeout) => None,
+            // The thread only stops with the Watch; sleep as asked.
+            Err(RecvTimeoutError::Disconnected) => {
+                thread::sleep(timeout);
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,313 @@
+use octa_errors::Kind;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use ureq::http::Response;
+use ureq::Body;
+
+/// The upload endpoints take the upload secret in this header.
+const SECRET_HEADER: &str = "X-Secret-Key";
+
+/// Why a request failed.
+#[derive(Debug)]
+pub enum ApiError {
+    /// No response: connection refused, DNS, timeout.
+    Transport(String),
+    /// The server answered with an error; `code` as in its JSON
+    /// (`auth/invalid_credentials`), when the body had one.
+    Status {
+        status: u16,
+        code: Option<String>,
+        message: String,
+    },
+    /// A 2xx whose body was not what the endpoint returns.
+    Response(String),
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Transport(e) => write!(f, "request failed: {}", e),
+            ApiError::Status {
+                status,
+                code: Some(code),
+                message,
+            } => write!(f, "{} {}: {}", status, code, message),
+            ApiError::Status {
+                status, message, ..
+            } => write!(f, "{}: {}", status, message),
+            ApiError::Response(e) => write!(f, "unexpected response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl ApiError {
+    /// A server that cannot be reached, or fails, is unavailable; a wrong
+    /// upload secret is config; anything else it refused was bad input.
+    pub fn kind(&self) -> Kind {
+        match self {
+            ApiError::Transport(_) | ApiError::Response(_) => Kind::Unavailable,
+            ApiError::Status { status, .. } if *status >= 500 => Kind::Unavailable,
+            ApiError::Status {
+                status: 401 | 403, ..
+            } => Kind::Config,
+            ApiError::Status { .. } => Kind::Data,
+        }
+    }
+}
+
+/// Body of the server's error responses.
+#[derive(Deserialize)]
+struct ErrorBody {
+    code: String,
+    message: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Uploaded {
+    pub action: String,
+    pub avatar_id: String,
+    pub keys: Vec<String>,
+    pub url: String,
+    pub size_kb: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Deleted {
+    pub target: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Stat {
+    pub avatar_id: String,
+    pub keys: Vec<String>,
+    pub width: u32,
+    pub height: u32,
+    pub format: String,
+    pub size: u64,
+    pub created_at: String,
+    pub updated_at: String,
+    pub url: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ListItem {
+    pub key: String,
+    pub avatar_id: String,
+    pub size: u64,
+    pub format: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListPage {
+    pub items: Vec<ListItem>,
+    /// Pass as `after` for the next page; empty on the last one.
+    pub next: String,
+}
+
+/// How an upload is stored; mirrors the `mode`/`size` form fields.
+#[derive(Debug, Default)]
+pub struct UploadOptions {
+    /// Store the file as sent instead of cropping and re-encoding it.
+    pub original: bool,
+    /// Edge length of the re-encoded square (the server defaults to 256).
+    pub size: Option<u32>,
+}
+
+/// A running Octa server.
+pub struct Client {
+    agent: ureq::Agent,
+    base_url: String,
+    secret: String,
+}
+
+impl Client {
+    pub fn new(base_url: &str, secret: &str, timeout: Duration) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(timeout))
+            // Error bodies carry the server's code and message.
+            .http_status_as_error(false)
+            .build()
+            .into();
+        Self {
+            agent,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            secret: secret.to_string(),
+        }
+    }
+
+    /// POST /upload. The first key is the one the asset is updated by.
+    pub fn upload(
+        &self,
+        file_name: &str,
+        data: &[u8],
+        keys: &[String],
+        options: &UploadOptions,
+    ) -> Result<Uploaded, ApiError> {
+        let mut form = Multipart::new();
+        form.text("keys", &keys.join(","));
+        if options.original {
+            form.text("mode", "original");
+        }
+        if let Some(size) = options.size {
+            form.text("size", &size.to_string());
+        }
+        form.file("avatar", file_name, data);
+        let (content_type, body) = form.finish();
+
+        let response = self
+            .agent
+            .post(format!("{}/upload", self.base_url))
+            .header(SECRET_HEADER, &self.secret)
+            .header("Content-Type", &content_type)
+            .send(&body[..])
+            .map_err(|e| ApiError::Transport(e.to_string()))?;
+        json(response)
+    }
+
+    /// GET /upload/stat.
+    pub fn stat(&self, key: &str) -> Result<Stat, ApiError> {
+        let response = self
+            .agent
+            .get(format!("{}/upload/stat", self.base_url))
+            .header(SECRET_HEADER, &self.secret)
+            .query("key", key)
+            .call()
+            .map_err(|e| ApiError::Transport(e.to_string()))?;
+        json(response)
+    }
+
+    /// GET /upload/list, one page.
+    pub fn list(&self, prefix: &str, after: &str, limit: u32) -> Result<ListPage, ApiError> {
+        let mut request = self
+            .agent
+            .get(format!("{}/upload/list", self.base_url))
+            .header(SECRET_HEADER, &self.secret)
+            .query("prefix", prefix)
+            .query("limit", limit.to_string());
+        if !after.is_empty() {
+            request = request.query("after", after);
+        }
+        let response = request
+            .call()
+            .map_err(|e| ApiError::Transport(e.to_string()))?;
+        json(response)
+    }
+
+    /// The stored bytes, as served on `/u/<key>`. That route answers unknown
+    /// keys with a generated avatar, so the key is looked up first.
+    pub fn get(&self, key: &str) -> Result<(Stat, Vec<u8>), ApiError> {
+        let stat = self.stat(key)?;
+        let response = self
+            .agent
+            .get(format!("{}/u/{}", self.base_url, key))
+            .call()
+            .map_err(|e| ApiError::Transport(e.to_string()))?;
+        let mut response = check(response)?;
+        let data = response
+            .body_mut()
+            .with_config()
+            .limit(u64::MAX)
+            .read_to_vec()
+            .map_err(|e| ApiError::Transport(e.to_string()))?;
+        Ok((stat, data))
+    }
+
+    /// DELETE /upload/delete. Removes the asset and every key mapped to it.
+    pub fn delete(&self, key: &str) -> Result<Deleted, ApiError> {
+        let response = self
+            .agent
+            .delete(format!("{}/upload/delete", self.base_url))
+            .header(SECRET_HEADER, &self.secret)
+            .query("key", key)
+            .call()
+            .map_err(|e| ApiError::Transport(e.to_string()))?;
+        json(response)
+    }
+}
+
+/// Turns a non-2xx response into [`ApiError::Status`].
+fn check(mut response: Response<Body>) -> Result<Response<Body>, ApiError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let text = response.body_mut().read_to_string().unwrap_or_default();
+    let (code, message) = match serde_json::from_str::<ErrorBody>(&text) {
+        Ok(body) => (Some(body.code), body.message),
+        Err(_) => (None, text.trim().to_string()),
+    };
+    Err(ApiError::Status {
+        status: status.as_u16(),
+        code,
+        message,
+    })
+}
+
+fn json<T: DeserializeOwned>(response: Response<Body>) -> Result<T, ApiError> {
+    let mut response = check(response)?;
+    let text = response
+        .body_mut()
+        .read_to_string()
+        .map_err(|e| ApiError::Transport(e.to_string()))?;
+    serde_json::from_str(&text).map_err(|e| ApiError::Response(e.to_string()))
+}
+
+/// multipart/form-data body. ureq has no form builder, and the upload needs
+/// only text fields and one file.
+struct Multipart {
+    boundary: String,
+    body: Vec<u8>,
+}
+
+impl Multipart {
+    fn new() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        Self {
+            boundary: format!("----octa-ctl-{:x}", nanos),
+            body: Vec::new(),
+        }
+    }
+
+    fn text(&mut self, name: &str, value: &str) {
+        self.body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                self.boundary, name, value
+            )
+            .as_bytes(),
+        );
+    }
+
+    /// The server sniffs the type from the bytes, so none is claimed here.
+    fn file(&mut self, name: &str, file_name: &str, data: &[u8]) {
+        self.body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+                self.boundary,
+                name,
+                file_name.replace('"', "")
+            )
+            .as_bytes(),
+        );
+        self.body.extend_from_slice(data);
+        self.body.extend_from_slice(b"\r\n");
+    }
+
+    /// `(content type, body)`.
+    fn finish(mut self) -> (String, Vec<u8>) {
+        self.body
+            .extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        (
+            format!("multipart/form-data; boundary={}", self.boundary),
+            self.body,
+        )
+    }
+}
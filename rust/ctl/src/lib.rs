@@ -0,0 +1,684 @@
+//! octa-ctl: day-to-day operations against a running Octa server, using the
+//! upload secret (`X-Secret-Key`) from the shared `config.yaml`. Uploads,
+//! deletes and purges are appended to the ledger (`ledger.path`), which
+//! `ledger verify` checks for tampering. `check-tls` checks the TLS of the
+//! public endpoints, for scheduled runs.
+
+mod api;
+mod tls;
+
+use api::{Client, UploadOptions};
+use clap::{Parser, Subcommand};
+use console::style;
+use octa_cdn::{CdnConfig, Purger};
+use octa_config::{ConfigError, SecurityConfig, ServerConfig, Validate};
+use octa_errors::{Error, Kind};
+use octa_ledger::{Ledger, LedgerConfig};
+use serde::Deserialize;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(name = "octa-ctl", version, about = "Administer a running Octa server")]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long, global = true)]
+    config: Option<String>,
+
+    /// Server to talk to (overrides base_url)
+    #[arg(long, global = true, env = "OCTA_CTL_URL")]
+    url: Option<String>,
+
+    /// Seconds before a request is abandoned
+    #[arg(long, global = true, default_value_t = 30)]
+    timeout: u64,
+
+    /// Print the server's answer as JSON
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Upload an image under one or more keys (the first key decides create vs update)
+    Upload {
+        file: PathBuf,
+        /// Key to map the image to; repeat for more
+        #[arg(short, long = "key", required = true)]
+        keys: Vec<String>,
+        /// Store the file as is instead of cropping and re-encoding it
+        #[arg(long)]
+        original: bool,
+        /// Edge length of the re-encoded square, 16-2048 (server default: 256)
+        #[arg(long, conflicts_with = "original")]
+        size: Option<u32>,
+    },
+    /// Download the stored image of a key
+    Get {
+        key: String,
+        /// Output file, `-` for stdout (default: the key, with the stored format as extension)
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Delete the asset of a key, together with all its other keys
+    Delete { key: String },
+    /// List keys in key order
+    List {
+        /// Only keys starting with this
+        #[arg(long, default_value = "")]
+        prefix: String,
+        /// Stop after this many keys
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Show an asset's metadata and all its keys
+    Stat { key: String },
+    /// Purge keys, or everything under a prefix, from the CDN in front of the server (cdn section)
+    Purge(PurgeArgs),
+    /// Check or read the ledger of uploads, deletes, purges and repairs (ledger section)
+    Ledger {
+        #[command(subcommand)]
+        command: LedgerCommand,
+    },
+    /// Check certificates, protocol versions, HSTS and the HTTP redirect of the public endpoints; exits 1 on a warning, 2 on a failure
+    CheckTls {
+        /// https URLs to check (default: base_url, and cdn.base_url when set)
+        urls: Vec<String>,
+        /// Days before expiry from which a certificate is a warning
+        #[arg(long, default_value_t = 30)]
+        warn_days: i64,
+        /// Days before expiry from which a certificate is a failure
+        #[arg(long, default_value_t = 7)]
+        fail_days: i64,
+    },
+}
+
+#[derive(clap::Args)]
+struct PurgeArgs {
+    /// Keys whose URLs to purge, in every cdn.variants variant
+    #[arg(required_unless_present = "prefix")]
+    keys: Vec<String>,
+    /// Purge every key starting with this (listed from the server when the CDN cannot purge by prefix)
+    #[arg(long, conflicts_with = "keys")]
+    prefix: Option<String>,
+    /// Print the URLs instead of purging them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Subcommand)]
+enum LedgerCommand {
+    /// Check the hash chain; exits 1 when an entry was edited, removed, inserted or reordered
+    Verify {
+        /// Hash of the head an earlier verify printed; it must still be in the ledger
+        #[arg(long)]
+        head: Option<String>,
+    },
+    /// Print entries, oldest first
+    Show {
+        /// Only entries acting on this key or asset id
+        #[arg(long)]
+        target: Option<String>,
+        /// Only entries of this action, e.g. delete
+        #[arg(long)]
+        action: Option<String>,
+        /// Only the last this many
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+}
+
+/// What ctl reads from the shared config.yaml.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    server: ServerConfig,
+    security: SecurityConfig,
+    base_url: Option<String>,
+    cdn: CdnConfig,
+    ledger: LedgerConfig,
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = self.server.validate();
+        if self.security.upload_secret.trim().is_empty() {
+            problems.push((
+                "security.upload_secret".to_string(),
+                "is required (or set OCTA_SECURITY_UPLOAD_SECRET)".to_string(),
+            ));
+        }
+        problems.extend(octa_config::check_url("base_url", self.base_url.as_deref()));
+        problems.extend(self.cdn.validate());
+        problems.extend(self.ledger.validate());
+        problems
+    }
+}
+
+/// Without a file, the environment alone can configure ctl (e.g. in CI).
+fn load_config(path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    match octa_config::discover(path.map(Path::new)) {
+        Some(path) => octa_config::load(&path),
+        None => {
+            let config: FileConfig = octa_config::from_env()?;
+            octa_config::check(Path::new("<environment>"), config)
+        }
+    }
+}
+
+/// The octa-ctl command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa ctl` the arguments it was given.
+pub fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+
+    let config = match load_config(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{} {}", style("[ERR]").red(), e);
+            return Kind::Config.exit_code();
+        }
+    };
+    if let Some(problem) = octa_config::check_url("--url", args.url.as_deref()) {
+        eprintln!("{} {}: {}", style("[ERR]").red(), problem.0, problem.1);
+        return Kind::Usage.exit_code();
+    }
+    let base_url = octa_config::base_url(
+        args.url.as_deref().or(config.base_url.as_deref()),
+        &config.server,
+    );
+    let timeout = Duration::from_secs(args.timeout);
+    let client = Client::new(&base_url, &config.security.upload_secret, timeout);
+    // Read only: opening it to append would recreate a deleted ledger.
+    if let Command::Ledger { command } = args.command {
+        return match ledger_command(&config.ledger, command, args.json) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("{} {}", style("[ERR]").red(), e);
+                kind(e.as_ref()).exit_code()
+            }
+        };
+    }
+    if let Command::CheckTls {
+        urls,
+        warn_days,
+        fail_days,
+    } = args.command
+    {
+        let urls = match urls.is_empty() {
+            false => urls,
+            true => std::iter::once(base_url)
+                .chain(Some(config.cdn.base_url.trim_end_matches('/').to_string()))
+                .filter(|url| !url.is_empty())
+                .collect(),
+        };
+        let opts = tls::Options {
+            warn_days,
+            fail_days,
+            timeout,
+        };
+        return check_tls(&urls, &opts, args.json);
+    }
+    // Opened before a change, so an unwritable ledger stops ctl first.
+    let changes = match &args.command {
+        Command::Upload { .. } | Command::Delete { .. } => true,
+        Command::Purge(purge_args) => !purge_args.dry_run,
+        _ => false,
+    };
+    let ledger = match changes.then(|| Ledger::open(&config.ledger, "octa-ctl")) {
+        Some(Ok(ledger)) => ledger,
+        Some(Err(e)) => {
+            eprintln!("{} ledger: {}", style("[ERR]").red(), e);
+            return kind(&e).exit_code();
+        }
+        None => None,
+    };
+
+    let result = match args.command {
+        Command::Purge(purge_args) => purge(
+            &client,
+            &config.cdn,
+            ledger.as_ref(),
+            timeout,
+            purge_args,
+            args.json,
+        ),
+        command => run(&client, ledger.as_ref(), command, args.json),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{} {}", style("[ERR]").red(), e);
+            kind(e.as_ref()).exit_code()
+        }
+    }
+}
+
+/// What kind of failure `e` is, for the exit code: an unreachable server
+/// or CDN is [`Kind::Unavailable`], a missing setting [`Kind::Config`].
+fn kind(e: &(dyn std::error::Error + 'static)) -> Kind {
+    if let Some(e) = e.downcast_ref::<Error>() {
+        return e.kind();
+    }
+    if let Some(e) = e.downcast_ref::<api::ApiError>() {
+        return e.kind();
+    }
+    if let Some(e) = e.downcast_ref::<octa_cdn::Error>() {
+        return match e {
+            octa_cdn::Error::Config(_) => Kind::Config,
+            octa_cdn::Error::Transport(_) | octa_cdn::Error::Api { .. } => Kind::Unavailable,
+        };
+    }
+    if let Some(e) = e.downcast_ref::<octa_ledger::Error>() {
+        return match e {
+            octa_ledger::Error::Io { source, .. } if source.kind() == io::ErrorKind::NotFound => {
+                Kind::NoInput
+            }
+            octa_ledger::Error::Io { .. } => Kind::Io,
+            octa_ledger::Error::Corrupt { .. } => Kind::Data,
+        };
+    }
+    if e.is::<io::Error>() {
+        return Kind::Io;
+    }
+    Kind::Internal
+}
+
+/// `e` from reading `path`: [`Kind::NoInput`] when it does not exist.
+fn read_error(path: &Path, e: io::Error) -> Error {
+    let kind = match e.kind() {
+        io::ErrorKind::NotFound => Kind::NoInput,
+        _ => Kind::Io,
+    };
+    Error::new(kind, format!("could not read {}: {}", path.display(), e))
+}
+
+fn run(
+    client: &Client,
+    ledger: Option<&Ledger>,
+    command: Command,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Command::Upload {
+            file,
+            keys,
+            original,
+            size,
+        } => {
+            // Refused here rather than silently dropped by the server.
+            let keys = keys
+                .iter()
+                .map(|k| {
+                    octa_key::parse(k)
+                        .map_err(|e| Error::new(Kind::Usage, format!("invalid key '{}': {}", k, e)))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let data = fs::read(&file).map_err(|e| read_error(&file, e))?;
+            let name = file
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "avatar".to_string());
+            let uploaded = client.upload(&name, &data, &keys, &UploadOptions { original, size })?;
+            record(
+                ledger,
+                "upload",
+                &uploaded.keys,
+                &[
+                    ("asset", uploaded.avatar_id.clone()),
+                    ("result", uploaded.action.clone()),
+                    ("file", name),
+                    ("size_kb", uploaded.size_kb.to_string()),
+                ],
+            )?;
+            if json {
+                return print_json(&uploaded);
+            }
+            println!(
+                "{} {} {} ({} KB)",
+                style("[OK]").green(),
+                uploaded.action,
+                uploaded.avatar_id,
+                uploaded.size_kb
+            );
+            println!("  keys : {}", uploaded.keys.join(", "));
+            println!("  url  : {}", uploaded.url);
+            // The server drops keys already taken by another asset.
+            let skipped: Vec<_> = keys.iter().filter(|k| !uploaded.keys.contains(k)).collect();
+            if !skipped.is_empty() {
+                eprintln!(
+                    "{} not mapped (taken): {}",
+                    style("[WARN]").yellow(),
+                    skipped
+                        .iter()
+                        .map(|k| k.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
+        Command::Get { key, output } => {
+            let (stat, data) = client.get(&key)?;
+            let output =
+                output.unwrap_or_else(|| format!("{}.{}", octa_key::file_name(&key), stat.format));
+            if output == "-" {
+                io::stdout().write_all(&data)?;
+                return Ok(());
+            }
+            fs::write(&output, &data)
+                .map_err(|e| Error::new(Kind::Io, format!("could not write {}: {}", output, e)))?;
+            if json {
+                return print_json(&stat);
+            }
+            println!(
+                "{} {} -> {} ({} bytes, {}x{} {})",
+                style("[OK]").green(),
+                key,
+                output,
+                data.len(),
+                stat.width,
+                stat.height,
+                stat.format
+            );
+        }
+        Command::Delete { key } => {
+            let deleted = client.delete(&key)?;
+            record(
+                ledger,
+                "delete",
+                std::slice::from_ref(&key),
+                &[("asset", deleted.target.clone())],
+            )?;
+            if json {
+                return print_json(&deleted);
+            }
+            println!(
+                "{} deleted {} (asset {})",
+                style("[OK]").green(),
+                key,
+                deleted.target
+            );
+        }
+        Command::List { prefix, limit } => {
+            let mut items = Vec::new();
+            let mut after = String::new();
+            loop {
+                let page = client.list(&prefix, &after, 1000)?;
+                items.extend(page.items);
+                if let Some(limit) = limit.filter(|&limit| items.len() >= limit) {
+                    items.truncate(limit);
+                    break;
+                }
+                if page.next.is_empty() {
+                    break;
+                }
+                after = page.next;
+            }
+            if json {
+                return print_json(&items);
+            }
+            let width = items.iter().map(|i| i.key.len()).max().unwrap_or(0);
+            for item in &items {
+                println!(
+                    "{:<width$}  {:>9}  {:<5}  {}  {}",
+                    item.key,
+                    item.size,
+                    item.format,
+                    item.updated_at,
+                    style(&item.avatar_id).dim(),
+                );
+            }
+            eprintln!("{} key(s)", items.len());
+        }
+        Command::Stat { key } => {
+            let stat = client.stat(&key)?;
+            if json {
+                return print_json(&stat);
+            }
+            println!("Asset   : {}", stat.avatar_id);
+            println!("Keys    : {}", stat.keys.join(", "));
+            println!("Image   : {}x{} {}", stat.width, stat.height, stat.format);
+            println!("Size    : {} bytes", stat.size);
+            println!("Created : {}", stat.created_at);
+            println!("Updated : {}", stat.updated_at);
+            println!("URL     : {}", stat.url);
+        }
+        Command::Purge(_) | Command::Ledger { .. } | Command::CheckTls { .. } => {
+            unreachable!("handled in main")
+        }
+    }
+    Ok(())
+}
+
+/// Invalidates keys at the edge. A prefix goes out as one purge when the
+/// provider supports it; otherwise its keys are listed from the server.
+fn purge(
+    client: &Client,
+    cdn: &CdnConfig,
+    ledger: Option<&Ledger>,
+    timeout: Duration,
+    args: PurgeArgs,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let PurgeArgs {
+        mut keys,
+        prefix,
+        dry_run,
+    } = args;
+    let purger = Purger::new(cdn, timeout)?.ok_or_else(|| {
+        Error::new(
+            Kind::Config,
+            "no CDN configured: set cdn.provider and cdn.base_url",
+        )
+    })?;
+    let by_prefix = prefix.as_ref().filter(|_| purger.supports_prefix());
+
+    if let (Some(prefix), None) = (&prefix, by_prefix) {
+        let mut after = String::new();
+        loop {
+            let page = client.list(prefix, &after, 1000)?;
+            keys.extend(page.items.into_iter().map(|item| item.key));
+            if page.next.is_empty() {
+                break;
+            }
+            after = page.next;
+        }
+        if keys.is_empty() {
+            eprintln!("{} no keys under '{}'", style("[WARN]").yellow(), prefix);
+            return Ok(());
+        }
+    }
+
+    if dry_run {
+        match by_prefix {
+            Some(prefix) => println!("{}/u/{}*", cdn.base_url.trim_end_matches('/'), prefix),
+            None => keys
+                .iter()
+                .flat_map(|key| purger.urls(key))
+                .for_each(|url| println!("{}", url)),
+        }
+        return Ok(());
+    }
+
+    let purged = match by_prefix {
+        Some(prefix) => purger.purge_prefix(prefix)?,
+        None => purger.purge_keys(&keys)?,
+    };
+    let (targets, scope) = match by_prefix {
+        Some(prefix) => (vec![prefix.clone()], "prefix"),
+        None => (keys.clone(), "keys"),
+    };
+    record(
+        ledger,
+        "purge",
+        &targets,
+        &[
+            ("provider", purger.provider().as_str().to_string()),
+            ("scope", scope.to_string()),
+            ("urls", purged.urls.to_string()),
+        ],
+    )?;
+    if json {
+        return print_json(&purged);
+    }
+    let what = match by_prefix {
+        Some(prefix) => format!("prefix '{}'", prefix),
+        None => format!("{} key(s), {} URL(s)", keys.len(), purged.urls),
+    };
+    println!(
+        "{} purged {} from {} ({} request(s))",
+        style("[OK]").green(),
+        what,
+        purger.provider().as_str(),
+        purged.requests
+    );
+    Ok(())
+}
+
+/// Every check of every endpoint; the exit code is the worst status, as
+/// 0, 1 or 2 like octa-warden's verdicts, or 69 when an endpoint did not
+/// answer.
+fn check_tls(urls: &[String], opts: &tls::Options, json: bool) -> ExitCode {
+    let endpoints: Vec<tls::Endpoint> = urls.iter().map(|url| tls::check(url, opts)).collect();
+    if json {
+        if let Err(e) = print_json(&endpoints) {
+            eprintln!("{} {}", style("[ERR]").red(), e);
+            return Kind::Io.exit_code();
+        }
+    } else {
+        for endpoint in &endpoints {
+            println!("{}", style(&endpoint.url).bold());
+            for check in &endpoint.checks {
+                let tag = format!("{:<6}", format!("[{}]", check.status.label()));
+                let tag = match check.status {
+                    tls::Status::Ok => style(tag).green(),
+                    tls::Status::Warn => style(tag).yellow(),
+                    tls::Status::Fail => style(tag).red(),
+                };
+                println!("  {} {:<9}  {}", tag, check.name, check.detail);
+            }
+        }
+    }
+    if endpoints.iter().any(|e| !e.reachable) {
+        return Kind::Unavailable.exit_code();
+    }
+    endpoints
+        .iter()
+        .map(|e| e.status)
+        .max()
+        .unwrap_or(tls::Status::Ok)
+        .exit_code()
+}
+
+/// Appends a change that already happened to the ledger, if there is one.
+fn record(
+    ledger: Option<&Ledger>,
+    action: &str,
+    targets: &[String],
+    detail: &[(&str, String)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(ledger) = ledger {
+        ledger.record(action, targets, detail).map_err(|e| {
+            Error::new(
+                Kind::Io,
+                format!("{} done, but not recorded in the ledger: {}", action, e),
+            )
+        })?;
+    }
+    Ok(())
+}
+
+fn ledger_command(
+    cfg: &LedgerConfig,
+    command: LedgerCommand,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let path = cfg
+        .path
+        .as_deref()
+        .ok_or_else(|| Error::new(Kind::Config, "no ledger configured: set ledger.path"))?;
+    match command {
+        LedgerCommand::Verify { head } => {
+            let verification = octa_ledger::verify(path, head.as_deref())?;
+            if json {
+                print_json(&verification)?;
+            } else {
+                for found in &verification.breaks {
+                    let at = match found.line {
+                        0 => "end".to_string(),
+                        line => format!("line {}", line),
+                    };
+                    eprintln!("{} {}: {}", style("[BREAK]").red(), at, found.reason);
+                }
+                if let Some(head) = &verification.head {
+                    println!("Entries : {}", verification.entries);
+                    println!("Head    : {} (seq {}, {})", head.hash, head.seq, head.at);
+                }
+            }
+            if !verification.is_intact() {
+                return Err(Error::new(
+                    Kind::Data,
+                    format!(
+                        "{}: {} break(s) in the chain",
+                        path.display(),
+                        verification.breaks.len()
+                    ),
+                )
+                .into());
+            }
+            if !json {
+                println!("{} {} intact", style("[OK]").green(), path.display());
+            }
+        }
+        LedgerCommand::Show {
+            target,
+            action,
+            limit,
+        } => {
+            let mut entries: Vec<_> = octa_ledger::read(path)?
+                .into_iter()
+                .filter(|e| {
+                    target
+                        .as_ref()
+                        .is_none_or(|t| e.targets.contains(t) || e.detail.values().any(|v| v == t))
+                })
+                .filter(|e| action.as_ref().is_none_or(|a| e.action == *a))
+                .collect();
+            if let Some(limit) = limit {
+                entries.drain(..entries.len().saturating_sub(limit));
+            }
+            if json {
+                return print_json(&entries);
+            }
+            for entry in &entries {
+                let detail: Vec<String> = entry
+                    .detail
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect();
+                println!(
+                    "{:>5}  {}  {:<24}  {:<12}  {:<18}  {}  {}",
+                    entry.seq,
+                    entry.at,
+                    entry.actor,
+                    entry.tool,
+                    entry.action,
+                    entry.targets.join(","),
+                    style(detail.join(" ")).dim(),
+                );
+            }
+            eprintln!("{} entr(ies)", entries.len());
+        }
+    }
+    Ok(())
+}
+
+fn print_json<T: serde::Serialize>(value: &T) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
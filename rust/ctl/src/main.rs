@@ -0,0 +1,5 @@
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    octa_ctl::cli(std::env::args_os())
+}
@@ -0,0 +1,615 @@
+//! `check-tls`: what a browser or CDN would find wrong with the TLS of a
+//! public endpoint before users do. Per endpoint: the chain against the
+//! Mozilla roots, the days left on the shortest-lived certificate served,
+//! the protocol versions accepted, HSTS, and the redirect from plain HTTP.
+//!
+//! The handshakes record the chain and go on even when it does not verify,
+//! so an expired certificate still gets its expiry reported. TLS 1.0 and
+//! 1.1, which rustls cannot speak, are probed with a bare ClientHello.
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{
+    ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, SignatureScheme,
+    SupportedProtocolVersion,
+};
+use serde::Serialize;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use ureq::http::Uri;
+
+/// HSTS shorter than this (180 days) is reported; browsers' preload list
+/// asks for a year.
+const HSTS_MIN_AGE: u64 = 180 * 86400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+impl Status {
+    pub fn label(self) -> &'static str {
+        match self {
+            Status::Ok => "OK",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+        }
+    }
+
+    /// 0, 1 or 2, as octa-warden's verdicts.
+    pub fn exit_code(self) -> ExitCode {
+        ExitCode::from(self as u8)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Endpoint {
+    pub url: String,
+    /// Whether it answered on TCP at all; when not, there are no verdicts.
+    pub reachable: bool,
+    /// The worst of its checks.
+    pub status: Status,
+    /// Expiry of the shortest-lived certificate served, when one was.
+    pub expires: Option<String>,
+    pub days_left: Option<i64>,
+    pub checks: Vec<Check>,
+}
+
+pub struct Options {
+    /// Certificates expiring within this many days are a warning...
+    pub warn_days: i64,
+    /// ...and within this many a failure.
+    pub fail_days: i64,
+    pub timeout: Duration,
+}
+
+/// Runs every check against `url`, an https URL.
+pub fn check(url: &str, opts: &Options) -> Endpoint {
+    let mut endpoint = Endpoint {
+        url: url.to_string(),
+        reachable: true,
+        status: Status::Ok,
+        expires: None,
+        days_left: None,
+        checks: Vec::new(),
+    };
+    let target = match Target::parse(url) {
+        Ok(target) => target,
+        Err(e) => {
+            endpoint.push("url", Status::Fail, e);
+            return endpoint;
+        }
+    };
+    if let Err(e) = target.connect(opts) {
+        endpoint.reachable = false;
+        endpoint.push("connect", Status::Fail, e.to_string());
+        return endpoint;
+    }
+
+    match handshake(&target, &[&rustls::version::TLS13, &rustls::version::TLS12], opts) {
+        Ok(handshake) => {
+            let served = handshake.chain.len();
+            match &handshake.verdict {
+                Ok(()) => endpoint.push(
+                    "chain",
+                    Status::Ok,
+                    format!("trusted, {} certificate(s) served", served),
+                ),
+                Err(e) => endpoint.push("chain", Status::Fail, e.clone()),
+            }
+            endpoint.expiry(&handshake.chain, opts);
+        }
+        Err(e) => {
+            // Without a handshake there is nothing more to learn over TLS.
+            endpoint.push("chain", Status::Fail, format!("no TLS handshake: {}", e));
+            return endpoint;
+        }
+    }
+    endpoint.protocols(&target, opts);
+    endpoint.hsts(&target, opts);
+    endpoint.redirect(&target, opts);
+    endpoint
+}
+
+impl Endpoint {
+    fn push(&mut self, name: &'static str, status: Status, detail: String) {
+        self.status = self.status.max(status);
+        self.checks.push(Check {
+            name,
+            status,
+            detail,
+        });
+    }
+
+    fn expiry(&mut self, chain: &[CertificateDer<'static>], opts: &Options) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let Some((position, not_before, not_after)) = chain
+            .iter()
+            .enumerate()
+            .filter_map(|(i, cert)| validity(cert).map(|(from, to)| (i, from, to)))
+            .min_by_key(|(_, _, not_after)| *not_after)
+        else {
+            self.push(
+                "expiry",
+                Status::Fail,
+                "no certificate validity could be read".to_string(),
+            );
+            return;
+        };
+        let days = (not_after - now).div_euclid(86400);
+        let which = match position {
+            0 => "certificate".to_string(),
+            i => format!("chain certificate #{}", i + 1),
+        };
+        self.expires = Some(date(not_after));
+        self.days_left = Some(days);
+        let (status, detail) = if now < not_before {
+            (
+                Status::Fail,
+                format!("{} not valid before {}", which, date(not_before)),
+            )
+        } else if now >= not_after {
+            (
+                Status::Fail,
+                format!("{} expired on {}", which, date(not_after)),
+            )
+        } else {
+            let status = if days < opts.fail_days {
+                Status::Fail
+            } else if days < opts.warn_days {
+                Status::Warn
+            } else {
+                Status::Ok
+            };
+            (
+                status,
+                format!("{} expires {} ({} days)", which, date(not_after), days),
+            )
+        };
+        self.push("expiry", status, detail);
+    }
+
+    /// TLS 1.2 or 1.3 must work, 1.0 and 1.1 must not; no 1.3 is a warning.
+    fn protocols(&mut self, target: &Target, opts: &Options) {
+        let tls13 = handshake(target, &[&rustls::version::TLS13], opts).is_ok();
+        let tls12 = handshake(target, &[&rustls::version::TLS12], opts).is_ok();
+        let tls11 = legacy_hello(target, 0x0302, opts).unwrap_or(false);
+        let tls10 = legacy_hello(target, 0x0301, opts).unwrap_or(false);
+        let list = |versions: &[(&str, bool)], accepted: bool| {
+            versions
+                .iter()
+                .filter(|(_, on)| *on == accepted)
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let versions = [
+            ("TLS 1.3", tls13),
+            ("TLS 1.2", tls12),
+            ("TLS 1.1", tls11),
+            ("TLS 1.0", tls10),
+        ];
+        let (accepted, refused) = (list(&versions, true), list(&versions, false));
+        let status = if tls10 || tls11 || !(tls12 || tls13) {
+            Status::Fail
+        } else if !tls13 {
+            Status::Warn
+        } else {
+            Status::Ok
+        };
+        let detail = match refused.is_empty() {
+            true => format!("accepts {}", accepted),
+            false if accepted.is_empty() => format!("refuses {}", refused),
+            false => format!("accepts {}; refuses {}", accepted, refused),
+        };
+        self.push("protocols", status, detail);
+    }
+
+    fn hsts(&mut self, target: &Target, opts: &Options) {
+        let response = match agent(opts).get(&target.url).call() {
+            Ok(response) => response,
+            Err(e) => {
+                self.push("hsts", Status::Warn, format!("not checked: {}", e));
+                return;
+            }
+        };
+        let header = response
+            .headers()
+            .get("strict-transport-security")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let Some(header) = header else {
+            self.push(
+                "hsts",
+                Status::Warn,
+                "no Strict-Transport-Security header".to_string(),
+            );
+            return;
+        };
+        let max_age = header
+            .split(';')
+            .filter_map(|part| part.trim().strip_prefix("max-age="))
+            .find_map(|age| age.trim_matches('"').parse::<u64>().ok());
+        let status = match max_age {
+            Some(age) if age >= HSTS_MIN_AGE => Status::Ok,
+            _ => Status::Warn,
+        };
+        self.push("hsts", status, header);
+    }
+
+    /// `http://` on port 80 should redirect, permanently, to https on the
+    /// same host.
+    fn redirect(&mut self, target: &Target, opts: &Options) {
+        if target.port != 443 {
+            self.push(
+                "redirect",
+                Status::Ok,
+                format!("skipped: port {} has no plain-HTTP twin", target.port),
+            );
+            return;
+        }
+        let http_url = format!("http://{}{}", target.host, target.path);
+        let response = match agent(opts).get(&http_url).call() {
+            Ok(response) => response,
+            Err(e) if refused(&e) => {
+                self.push(
+                    "redirect",
+                    Status::Ok,
+                    "no plain-HTTP listener".to_string(),
+                );
+                return;
+            }
+            Err(e) => {
+                self.push("redirect", Status::Warn, format!("not checked: {}", e));
+                return;
+            }
+        };
+        let status = response.status().as_u16();
+        let location = response
+            .headers()
+            .get("location")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let secure = location.starts_with(&format!("https://{}", target.host));
+        let (result, detail) = match status {
+            301 | 308 if secure => (Status::Ok, format!("{} to {}", status, location)),
+            302 | 303 | 307 if secure => (
+                Status::Warn,
+                format!("{} to {}: temporary, use 301 or 308", status, location),
+            ),
+            300..=399 => (
+                Status::Fail,
+                format!("{} to {}: not https on the same host", status, location),
+            ),
+            200..=299 => (
+                Status::Fail,
+                format!("{}: served over plain HTTP", status),
+            ),
+            _ => (Status::Warn, format!("{} without a redirect", status)),
+        };
+        self.push("redirect", result, detail);
+    }
+}
+
+/// Plain requests for the HTTP checks: no redirects followed and no
+/// certificate check, which the chain check does better.
+fn agent(opts: &Options) -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .timeout_global(Some(opts.timeout))
+        .http_status_as_error(false)
+        .max_redirects(0)
+        .max_redirects_will_error(false)
+        .tls_config(
+            ureq::tls::TlsConfig::builder()
+                .disable_verification(true)
+                .build(),
+        )
+        .build()
+        .into()
+}
+
+fn refused(e: &ureq::Error) -> bool {
+    match e {
+        ureq::Error::ConnectionFailed => true,
+        ureq::Error::Io(e) => e.kind() == std::io::ErrorKind::ConnectionRefused,
+        _ => false,
+    }
+}
+
+struct Target {
+    url: String,
+    host: String,
+    port: u16,
+    /// Path and query, `/` at least.
+    path: String,
+}
+
+impl Target {
+    fn parse(url: &str) -> Result<Self, String> {
+        let uri: Uri = url
+            .parse()
+            .map_err(|e| format!("'{}' is not a URL: {}", url, e))?;
+        if uri.scheme_str() != Some("https") {
+            return Err(format!("'{}' is not an https URL", url));
+        }
+        let host = uri
+            .host()
+            .ok_or_else(|| format!("'{}' has no host", url))?
+            .to_string();
+        Ok(Self {
+            url: url.to_string(),
+            port: uri.port_u16().unwrap_or(443),
+            path: uri
+                .path_and_query()
+                .map_or("/".to_string(), |p| p.as_str().to_string()),
+            host,
+        })
+    }
+
+    fn connect(&self, opts: &Options) -> std::io::Result<TcpStream> {
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| std::io::Error::other(format!("{} has no address", self.host)))?;
+        let tcp = TcpStream::connect_timeout(&addr, opts.timeout)?;
+        tcp.set_read_timeout(Some(opts.timeout))?;
+        tcp.set_write_timeout(Some(opts.timeout))?;
+        Ok(tcp)
+    }
+}
+
+struct Handshake {
+    chain: Vec<CertificateDer<'static>>,
+    /// Whether the chain verifies for the host, as a browser would judge it.
+    verdict: Result<(), String>,
+}
+
+/// A complete handshake offering only `versions`.
+fn handshake(
+    target: &Target,
+    versions: &[&'static SupportedProtocolVersion],
+    opts: &Options,
+) -> Result<Handshake, String> {
+    let provider = Arc::new(ring::default_provider());
+    let capture = Arc::new(Capture::new(provider.clone())?);
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_protocol_versions(versions)
+        .map_err(|e| e.to_string())?
+        .dangerous()
+        .with_custom_certificate_verifier(capture.clone())
+        .with_no_client_auth();
+    let name = ServerName::try_from(target.host.clone()).map_err(|e| e.to_string())?;
+    let mut conn = ClientConnection::new(Arc::new(config), name).map_err(|e| e.to_string())?;
+    let mut tcp = target.connect(opts).map_err(|e| e.to_string())?;
+    while conn.is_handshaking() {
+        conn.complete_io(&mut tcp).map_err(|e| e.to_string())?;
+    }
+    let captured = capture.seen.lock().unwrap_or_else(|e| e.into_inner()).take();
+    let (chain, verdict) = captured.ok_or("the server sent no certificate")?;
+    Ok(Handshake { chain, verdict })
+}
+
+/// The chain a server presented and webpki's verdict on it.
+type Seen = (Vec<CertificateDer<'static>>, Result<(), String>);
+
+/// Verifies as webpki does, records the chain and the verdict, and lets
+/// the handshake finish either way.
+#[derive(Debug)]
+struct Capture {
+    inner: Arc<WebPkiServerVerifier>,
+    seen: Mutex<Option<Seen>>,
+}
+
+impl Capture {
+    fn new(provider: Arc<CryptoProvider>) -> Result<Self, String> {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let inner = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider)
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            inner,
+            seen: Mutex::new(None),
+        })
+    }
+}
+
+impl ServerCertVerifier for Capture {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verdict = self
+            .inner
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+            .map(|_| ())
+            .map_err(|e| e.to_string());
+        let chain = std::iter::once(end_entity)
+            .chain(intermediates)
+            .map(|cert| cert.clone().into_owned())
+            .collect();
+        *self.seen.lock().unwrap_or_else(|e| e.into_inner()) = Some((chain, verdict));
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Whether the server answers a TLS 1.0 (`0x0301`) or 1.1 (`0x0302`)
+/// ClientHello with a ServerHello of that version, rather than an alert.
+fn legacy_hello(target: &Target, version: u16, opts: &Options) -> std::io::Result<bool> {
+    let mut hello = Vec::new();
+    hello.extend_from_slice(&version.to_be_bytes());
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    hello.extend_from_slice(&seed.to_be_bytes()); // random: 16 bytes...
+    hello.extend_from_slice(&seed.to_le_bytes()); // ...and 16 more
+    hello.push(0); // no session id
+    // ECDHE and RSA key exchange with AES-CBC, what 1.0 and 1.1 servers offer.
+    let suites: [u16; 6] = [0xc013, 0xc014, 0xc009, 0xc00a, 0x002f, 0x0035];
+    hello.extend_from_slice(&((suites.len() * 2) as u16).to_be_bytes());
+    suites
+        .iter()
+        .for_each(|suite| hello.extend_from_slice(&suite.to_be_bytes()));
+    hello.extend_from_slice(&[1, 0]); // compression: null
+
+    let mut extensions = Vec::new();
+    let host = target.host.as_bytes();
+    let mut extension = |kind: u16, body: &[u8]| {
+        extensions.extend_from_slice(&kind.to_be_bytes());
+        extensions.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        extensions.extend_from_slice(body);
+    };
+    let mut sni = Vec::new();
+    sni.extend_from_slice(&((host.len() + 3) as u16).to_be_bytes());
+    sni.push(0); // host_name
+    sni.extend_from_slice(&(host.len() as u16).to_be_bytes());
+    sni.extend_from_slice(host);
+    extension(0x0000, &sni); // server_name
+    extension(0x000a, &[0, 4, 0x00, 0x17, 0x00, 0x18]); // groups: P-256, P-384
+    extension(0x000b, &[1, 0]); // point formats: uncompressed
+    hello.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    hello.extend_from_slice(&extensions);
+
+    let mut record = vec![0x16, 0x03, 0x01];
+    record.extend_from_slice(&((hello.len() + 4) as u16).to_be_bytes());
+    record.push(1); // client_hello
+    record.extend_from_slice(&(hello.len() as u32).to_be_bytes()[1..]);
+    record.extend_from_slice(&hello);
+
+    let mut tcp = target.connect(opts)?;
+    tcp.write_all(&record)?;
+    let mut header = [0u8; 5];
+    if tcp.read_exact(&mut header).is_err() || header[0] != 0x16 {
+        // Closed, or an alert: refused.
+        return Ok(false);
+    }
+    let mut body = [0u8; 6];
+    if tcp.read_exact(&mut body).is_err() {
+        return Ok(false);
+    }
+    // ServerHello, then its 3-byte length, then the chosen version.
+    Ok(body[0] == 2 && u16::from_be_bytes([body[4], body[5]]) == version)
+}
+
+/// `(not_before, not_after)` of a DER certificate, in Unix seconds.
+fn validity(der: &[u8]) -> Option<(i64, i64)> {
+    let (_, cert, _) = der_item(der)?;
+    let (_, mut tbs, _) = der_item(cert)?;
+    // The version is an optional [0]; serial, signature and issuer follow.
+    if tbs.first() == Some(&0xa0) {
+        tbs = der_item(tbs)?.2;
+    }
+    for _ in 0..3 {
+        tbs = der_item(tbs)?.2;
+    }
+    let (_, validity, _) = der_item(tbs)?;
+    let (tag, before, rest) = der_item(validity)?;
+    let not_before = der_time(tag, before)?;
+    let (tag, after, _) = der_item(rest)?;
+    Some((not_before, der_time(tag, after)?))
+}
+
+/// `(tag, content, rest)` of the DER item at the start of `data`.
+fn der_item(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, data) = data.split_first()?;
+    let (&first, mut data) = data.split_first()?;
+    let len = match first {
+        0..=0x7f => first as usize,
+        0x81..=0x84 => {
+            let n = (first & 0x7f) as usize;
+            let (bytes, rest) = data.split_at_checked(n)?;
+            data = rest;
+            bytes.iter().fold(0, |len, &b| (len << 8) | b as usize)
+        }
+        _ => return None,
+    };
+    let (content, rest) = data.split_at_checked(len)?;
+    Some((tag, content, rest))
+}
+
+/// UTCTime (`YYMMDDHHMMSSZ`) or GeneralizedTime (`YYYYMMDDHHMMSSZ`).
+fn der_time(tag: u8, content: &[u8]) -> Option<i64> {
+    let text = std::str::from_utf8(content).ok()?.strip_suffix('Z')?;
+    let (year, rest) = match tag {
+        0x17 => {
+            let yy: i64 = text.get(..2)?.parse().ok()?;
+            (if yy >= 50 { 1900 + yy } else { 2000 + yy }, text.get(2..)?)
+        }
+        0x18 => (text.get(..4)?.parse().ok()?, text.get(4..)?),
+        _ => return None,
+    };
+    let field = |i: usize| -> Option<i64> { rest.get(i..i + 2)?.parse().ok() };
+    let days = days_from_civil(year, field(0)?, field(2)?);
+    Some(days * 86400 + field(4)? * 3600 + field(6)? * 60 + field(8)?)
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// `YYYY-MM-DD` of Unix seconds.
+fn date(secs: i64) -> String {
+    let z = secs.div_euclid(86400) + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
@@ -0,0 +1,592 @@
+use clap::Parser;
+use octa_config::{ConfigError, SecurityConfig, Validate};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use octa_warden_core::schedule::parse_interval;
+use report::{Report, Status};
+use serde::Deserialize;
+use serde_yaml::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+use std::time::{Duration, Instant};
+use tool::{field, Finished, Server};
+use tracing::{error, info, warn, Level};
+
+mod report;
+mod tool;
+
+/*
+OCTA-DRILL: Disaster-recovery drill
+=============================================
+Mission: Prove the backups can bring the service back, end to end: take a
+         backup, restore it to a scratch location, audit the restore with
+         octa-warden, start octa-server on it and put a short octa-pulse
+         load on it. One pass/fail report covers every step.
+Safety:  Production is only touched by the backup itself (a new generation
+         and retention, as octa-backup create always does). Everything
+         after runs on the scratch copy, with a config of its own: no
+         notifications, no CDN purges, a free port, and the environment
+         overrides of the live config removed.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Back up, restore, audit, serve and load-test a copy of Octa, as a DR drill"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Drill an existing generation (or `latest`) instead of taking a new backup
+    #[arg(long)]
+    generation: Option<String>,
+
+    /// Directory to restore under (overrides drill.scratch)
+    #[arg(long, value_name = "DIR")]
+    scratch: Option<PathBuf>,
+
+    /// Keep the restore and the logs after a drill that passed
+    #[arg(long)]
+    keep: bool,
+
+    /// Fail the drill on audit warnings too (overrides drill.strict)
+    #[arg(long)]
+    strict: bool,
+
+    /// Write the report as JSON to this file, e.g. for the DR record
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+}
+
+/// The parts of config.yaml octa-drill reads; the rest is passed on.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    security: SecurityConfig,
+    drill: DrillConfig,
+}
+
+/// `drill:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DrillConfig {
+    /// Where octa-backup, octa-warden and octa-pulse are, relative to
+    /// config.yaml; unset, next to octa-drill.
+    bin_dir: Option<String>,
+    /// The octa-server binary, relative to config.yaml; unset, in `bin_dir`.
+    server: Option<String>,
+    /// Directory the restore goes under; unset, the system's temp directory.
+    scratch: Option<String>,
+    startup_timeout: String,
+    pulse_requests: usize,
+    pulse_workers: usize,
+    /// Audit warnings fail the drill.
+    strict: bool,
+}
+
+impl Default for DrillConfig {
+    fn default() -> Self {
+        Self {
+            bin_dir: None,
+            server: None,
+            scratch: None,
+            startup_timeout: "30s".to_string(),
+            pulse_requests: 200,
+            pulse_workers: 10,
+            strict: false,
+        }
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        let drill = &self.drill;
+        if self.security.upload_secret.trim().is_empty() {
+            problems.push((
+                "security.upload_secret".to_string(),
+                "is required for the write test".to_string(),
+            ));
+        }
+        if let Err(e) = parse_interval(&drill.startup_timeout) {
+            problems.push(("drill.startup_timeout".to_string(), e));
+        }
+        if drill.pulse_requests == 0 {
+            problems.push((
+                "drill.pulse_requests".to_string(),
+                "must be at least 1".to_string(),
+            ));
+        }
+        if drill.pulse_workers == 0 {
+            problems.push((
+                "drill.pulse_workers".to_string(),
+                "must be at least 1".to_string(),
+            ));
+        }
+        problems
+    }
+}
+
+/// Like octa-backup: without a file, the environment alone configures the
+/// drill. Returns the file, if any, and the whole config as YAML, for the
+/// scratch copy's config.
+fn load_config(path: Option<&str>) -> Result<(Option<PathBuf>, FileConfig, Value), ConfigError> {
+    match octa_config::discover(path.map(Path::new)) {
+        Some(path) => {
+            let config = octa_config::load(&path)?;
+            let raw = octa_config::read(&path)?;
+            Ok((Some(path), config, raw))
+        }
+        None => {
+            let config: FileConfig = octa_config::from_env()?;
+            let config = octa_config::check(Path::new("<environment>"), config)?;
+            Ok((None, config, octa_config::from_env()?))
+        }
+    }
+}
+
+/// `path` as given, or relative to the directory of config.yaml.
+fn resolve(file: Option<&Path>, path: &str) -> PathBuf {
+    let dir = file.and_then(Path::parent).unwrap_or(Path::new("."));
+    dir.join(path)
+}
+
+/// Sets `value` at `path` of a YAML mapping, creating the mappings on the way.
+fn set(root: &mut Value, path: &[&str], value: Value) {
+    let mut node = root;
+    for key in path {
+        if !node.is_mapping() {
+            *node = Value::Mapping(Default::default());
+        }
+        let map = node.as_mapping_mut().expect("just made a mapping");
+        node = map
+            .entry(Value::from(*key))
+            .or_insert(Value::Mapping(Default::default()));
+    }
+    *node = value;
+}
+
+fn remove(root: &mut Value, path: &[&str]) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let mut node = root;
+    for key in parents {
+        match node.get_mut(*key) {
+            Some(next) => node = next,
+            None => return,
+        }
+    }
+    if let Some(map) = node.as_mapping_mut() {
+        map.remove(*last);
+    }
+}
+
+/// The live config pointed at the restore: its own port, history and load
+/// test, and nothing that reaches outside the drill.
+fn scratch_config(
+    live: &Value,
+    db: &Path,
+    scratch: &Path,
+    port: u16,
+    drill: &DrillConfig,
+) -> Value {
+    let base_url = format!("http://127.0.0.1:{}", port);
+    let mut config = live.clone();
+    remove(&mut config, &["cdn"]);
+    remove(&mut config, &["warden", "notify"]);
+    let settings = [
+        (
+            &["database", "path"][..],
+            Value::from(db.to_string_lossy().as_ref()),
+        ),
+        (&["server", "port"], Value::from(port)),
+        (&["base_url"], Value::from(base_url.as_str())),
+        // Pulse would mostly measure the rate limiter.
+        (&["security", "rate_limit", "enabled"], Value::from(false)),
+        (
+            &["warden", "history_path"],
+            Value::from(scratch.join("warden-history.db").to_string_lossy().as_ref()),
+        ),
+        (&["pulse", "base_url"], Value::from(base_url.as_str())),
+        (&["pulse", "total_req"], Value::from(drill.pulse_requests)),
+        (&["pulse", "worker"], Value::from(drill.pulse_workers)),
+    ];
+    for (path, value) in settings {
+        set(&mut config, path, value);
+    }
+    config
+}
+
+/// What a drill runs, and where.
+struct Drill {
+    /// The live config.yaml, passed to octa-backup.
+    live: Option<PathBuf>,
+    bin_dir: PathBuf,
+    server: PathBuf,
+    scratch: PathBuf,
+    startup_timeout: Duration,
+    strict: bool,
+}
+
+impl Drill {
+    fn tool(&self, name: &str) -> Command {
+        Command::new(self.bin_dir.join(name))
+    }
+
+    fn backup(&self) -> Command {
+        let mut command = self.tool("octa-backup");
+        if let Some(live) = &self.live {
+            command.arg("--config").arg(live);
+        }
+        command.args(["--log-format", "json"]);
+        command
+    }
+
+    fn log(&self, step: &str) -> PathBuf {
+        self.scratch.join(format!("{}.log", step))
+    }
+
+    fn run(&self, command: &mut Command, step: &str) -> Result<Finished, String> {
+        let program = command.get_program().to_string_lossy().into_owned();
+        tool::run(command, &self.log(step)).map_err(|e| format!("{}: {}", program, e))
+    }
+
+    /// Takes a new generation and returns its name.
+    fn take_backup(&self) -> Result<(String, String), String> {
+        let done = self.run(self.backup().arg("create"), "backup")?;
+        let written = done
+            .event("Generation written")
+            .filter(|_| done.status.success())
+            .ok_or_else(|| done.failure())?;
+        let generation = field(written, "generation").unwrap_or_default();
+        let detail = format!(
+            "generation {}, {} assets, {}",
+            generation,
+            field(written, "assets").unwrap_or_default(),
+            field(written, "size").unwrap_or_default()
+        );
+        Ok((generation, detail))
+    }
+
+    /// Restores `generation` and returns how many assets it holds.
+    fn restore(&self, generation: &str, db: &Path) -> Result<(u64, String), String> {
+        let done = self.run(
+            self.backup().args(["restore", generation, "--to"]).arg(db),
+            "restore",
+        )?;
+        let restored = done
+            .event("Database restored")
+            .filter(|_| done.status.success())
+            .ok_or_else(|| done.failure())?;
+        let assets = field(restored, "assets")
+            .and_then(|a| a.parse().ok())
+            .unwrap_or(0);
+        let detail = format!(
+            "generation {}, {} assets, {}",
+            field(restored, "generation").unwrap_or_default(),
+            assets,
+            field(restored, "size").unwrap_or_default()
+        );
+        Ok((assets, detail))
+    }
+
+    /// Audits the restore; warden exits 0, 1 or 2 for healthy, warning or
+    /// critical.
+    fn audit(&self, config: &Path, db: &Path) -> Result<(Status, String), String> {
+        let mut command = tool::scrubbed(self.tool("octa-warden"));
+        command
+            .arg("--config")
+            .arg(config)
+            .arg("--db")
+            .arg(db)
+            .args(["--quiet", "--log-format", "json"]);
+        let done = self.run(&mut command, "warden")?;
+        let report = done.event("Warden audit report");
+        let status = match (done.status.code(), report) {
+            (Some(0), Some(_)) => Status::Pass,
+            (Some(1), Some(_)) if !self.strict => Status::Warn,
+            (Some(1 | 2), Some(_)) => Status::Fail,
+            _ => return Err(done.failure()),
+        };
+        let report = report.expect("matched above");
+        let mut detail = format!(
+            "{} scanned, {} healthy: {}",
+            field(report, "scanned").unwrap_or_default(),
+            field(report, "healthy").unwrap_or_default(),
+            field(report, "status").unwrap_or_default()
+        );
+        if let Some(reasons) = field(report, "reasons").filter(|r| !r.is_empty()) {
+            detail.push_str(&format!(" ({})", reasons));
+        }
+        Ok((status, detail))
+    }
+
+    fn pulse(&self, config: &Path, server: &mut Server) -> Result<String, String> {
+        let mut command = tool::scrubbed(self.tool("octa-pulse"));
+        command
+            .current_dir(&self.scratch)
+            .env("OCTA_CONFIG", config)
+            .arg("--config")
+            .arg(config);
+        let done = self.run(&mut command, "pulse")?;
+        if !done.status.success() {
+            return Err(done.failure());
+        }
+        if !server.running() {
+            return Err(format!(
+                "the server exited under load, see {}",
+                self.log("server").display()
+            ));
+        }
+        let assets = tool::health(&server.base_url)
+            .map_err(|e| format!("/health failed after the load test: {}", e))?;
+        Ok(format!("load test done, still serving {} assets", assets))
+    }
+}
+
+/// The octa-drill command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa drill` the arguments it was given.
+pub fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, Level::INFO, false, false);
+
+    let (live, config, raw) = match load_config(args.config.as_deref()) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let settings = &config.drill;
+    let bin_dir = match &settings.bin_dir {
+        Some(dir) => resolve(live.as_deref(), dir),
+        None => match std::env::current_exe() {
+            Ok(exe) => exe.parent().unwrap_or(Path::new(".")).to_path_buf(),
+            Err(e) => {
+                error!(tag = "FATAL", reason = %e, "Could not find the tools (set drill.bin_dir)");
+                return Kind::Config.exit_code();
+            }
+        },
+    };
+    let server = match &settings.server {
+        Some(server) => resolve(live.as_deref(), server),
+        None => bin_dir.join("octa-server"),
+    };
+    let started_at = chrono::Local::now();
+    let parent = match (&args.scratch, &settings.scratch) {
+        (Some(dir), _) => dir.clone(),
+        (None, Some(dir)) => resolve(live.as_deref(), dir),
+        (None, None) => std::env::temp_dir(),
+    };
+    let scratch = parent.join(format!("octa-drill-{}", started_at.format("%Y%m%d-%H%M%S")));
+    if let Err(e) = fs::create_dir_all(&scratch) {
+        error!(tag = "FATAL", path = %scratch.display(), reason = %e, "Could not create the scratch directory");
+        return Kind::Io.exit_code();
+    }
+
+    let drill = Drill {
+        live,
+        bin_dir,
+        server,
+        scratch: scratch.clone(),
+        startup_timeout: parse_interval(&settings.startup_timeout)
+            .unwrap_or(Duration::from_secs(30)),
+        strict: args.strict || settings.strict,
+    };
+    let mut report = Report {
+        started_at: started_at.to_rfc3339(),
+        generation: args.generation.clone(),
+        scratch: scratch.display().to_string(),
+        passed: false,
+        seconds: 0.0,
+        steps: Vec::new(),
+    };
+    info!(tag = "→", scratch = %scratch.display(), strict = drill.strict, "Starting DR drill");
+    let started = Instant::now();
+    run(&drill, &raw, settings, &mut report);
+    report.passed = report.ok();
+    report.seconds = started.elapsed().as_secs_f64().round();
+
+    if report.passed && !args.keep {
+        if let Err(e) = fs::remove_dir_all(&scratch) {
+            warn!(tag = "WARN", path = %scratch.display(), reason = %e, "Could not remove the scratch directory");
+        }
+    } else {
+        info!(tag = "→", path = %scratch.display(), "Restore and logs kept for inspection");
+    }
+    if octa_logging::is_human() {
+        report.print();
+    }
+    if let Some(path) = &args.report {
+        let written = serde_json::to_string_pretty(&report)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(path, json + "\n").map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            error!(tag = "FATAL", path = %path.display(), reason = %e, "Could not write the report");
+            return Kind::Io.exit_code();
+        }
+    }
+
+    if report.passed {
+        info!(
+            tag = "OK",
+            seconds = report.seconds,
+            generation = report.generation.as_deref().unwrap_or("-"),
+            "DR drill passed"
+        );
+        ExitCode::SUCCESS
+    } else {
+        let failed: Vec<&str> = report
+            .steps
+            .iter()
+            .filter(|s| s.status == Status::Fail)
+            .map(|s| s.name)
+            .collect();
+        error!(tag = "FAIL", steps = %failed.join(","), "DR drill failed");
+        ExitCode::FAILURE
+    }
+}
+
+const STEPS: [&str; 5] = ["backup", "restore", "audit", "serve", "pulse"];
+
+/// Runs the steps in order; after a failure, the rest are skipped.
+fn run(drill: &Drill, raw: &Value, settings: &DrillConfig, report: &mut Report) {
+    let db = drill.scratch.join("avatar.db");
+    let config = drill.scratch.join("config.yaml");
+
+    // 1. Backup
+    let started = Instant::now();
+    match report.generation.clone() {
+        Some(generation) => {
+            let detail = format!("--generation {}: no new backup", generation);
+            report.add("backup", Ok((Status::Skipped, detail)), started.elapsed());
+        }
+        None => {
+            info!(tag = "→", step = "backup", "Taking a backup");
+            let taken = drill.take_backup();
+            if let Ok((generation, _)) = &taken {
+                report.generation = Some(generation.clone());
+            }
+            report.add(
+                "backup",
+                taken.map(|(_, d)| (Status::Pass, d)),
+                started.elapsed(),
+            );
+        }
+    }
+
+    // 2. Restore
+    let mut restored = 0;
+    if report.ok() {
+        info!(tag = "→", step = "restore", to = %db.display(), "Restoring");
+        let started = Instant::now();
+        let generation = report
+            .generation
+            .clone()
+            .unwrap_or_else(|| "latest".to_string());
+        let outcome = drill.restore(&generation, &db).map(|(assets, detail)| {
+            restored = assets;
+            (Status::Pass, detail)
+        });
+        report.add("restore", outcome, started.elapsed());
+    }
+
+    // The restore's own config, on a port of its own.
+    let port = match tool::free_port() {
+        Ok(port) => port,
+        Err(e) => {
+            report.add("serve", Err(format!("no free port: {}", e)), Duration::ZERO);
+            0
+        }
+    };
+    if report.ok() {
+        let yaml = serde_yaml::to_string(&scratch_config(raw, &db, &drill.scratch, port, settings));
+        let written = yaml
+            .map_err(|e| e.to_string())
+            .and_then(|yaml| fs::write(&config, yaml).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            report.add(
+                "audit",
+                Err(format!("{}: {}", config.display(), e)),
+                Duration::ZERO,
+            );
+        }
+    }
+
+    // 3. Audit
+    if report.ok() {
+        info!(tag = "→", step = "audit", "Auditing the restore");
+        let started = Instant::now();
+        report.add("audit", drill.audit(&config, &db), started.elapsed());
+    }
+
+    // 4. Serve, and 5. load test while it runs.
+    let mut server = None;
+    if report.ok() {
+        info!(
+            tag = "→",
+            step = "serve",
+            port,
+            "Starting the server on the restore"
+        );
+        let started = Instant::now();
+        let outcome = Server::start(
+            &drill.server,
+            &config,
+            port,
+            drill.startup_timeout,
+            &drill.log("server"),
+        )
+        .and_then(|s| {
+            let serving = tool::health(&s.base_url)?;
+            let url = s.base_url.clone();
+            server = Some(s);
+            if serving != restored {
+                return Err(format!(
+                    "serves {} assets, the restore has {}",
+                    serving, restored
+                ));
+            }
+            Ok((
+                Status::Pass,
+                format!("serving {} assets on {}", serving, url),
+            ))
+        });
+        report.add("serve", outcome, started.elapsed());
+    }
+    if let (true, Some(server)) = (report.ok(), &mut server) {
+        info!(
+            tag = "→",
+            step = "pulse",
+            requests = settings.pulse_requests,
+            workers = settings.pulse_workers,
+            "Load testing"
+        );
+        let started = Instant::now();
+        let outcome = drill.pulse(&config, server).map(|d| (Status::Pass, d));
+        report.add("pulse", outcome, started.elapsed());
+    }
+    drop(server);
+
+    for name in STEPS {
+        if !report.steps.iter().any(|s| s.name == name) {
+            report.add(
+                name,
+                Ok((Status::Skipped, "after a failed step".to_string())),
+                Duration::ZERO,
+            );
+        }
+    }
+    report
+        .steps
+        .sort_by_key(|s| STEPS.iter().position(|name| *name == s.name));
+}
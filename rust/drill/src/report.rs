@@ -0,0 +1,99 @@
+use serde::Serialize;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pass,
+    /// Passed with findings a strict drill fails on.
+    Warn,
+    Fail,
+    /// Not run: an earlier step failed, or it was not asked for.
+    Skipped,
+}
+
+impl Status {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Status::Pass => "PASS",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+            Status::Skipped => "SKIPPED",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Step {
+    pub name: &'static str,
+    pub status: Status,
+    pub seconds: f64,
+    pub detail: String,
+}
+
+/// The outcome of one drill, kept as the record of the exercise.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub started_at: String,
+    pub generation: Option<String>,
+    /// Where the restore was made; removed after a drill that passed,
+    /// unless `--keep`.
+    pub scratch: String,
+    pub passed: bool,
+    pub seconds: f64,
+    pub steps: Vec<Step>,
+}
+
+impl Report {
+    /// Records and logs a step; an error is its failure.
+    pub fn add(
+        &mut self,
+        name: &'static str,
+        outcome: Result<(Status, String), String>,
+        took: Duration,
+    ) {
+        let (status, detail) = outcome.unwrap_or_else(|e| (Status::Fail, e));
+        match status {
+            Status::Pass | Status::Skipped => {
+                info!(tag = "OK", step = name, detail = %detail, "Step done")
+            }
+            Status::Warn => {
+                warn!(tag = "WARN", step = name, detail = %detail, "Step done with warnings")
+            }
+            Status::Fail => error!(tag = "FAIL", step = name, detail = %detail, "Step failed"),
+        }
+        self.steps.push(Step {
+            name,
+            status,
+            seconds: (took.as_secs_f64() * 10.0).round() / 10.0,
+            detail,
+        });
+    }
+
+    /// Whether the steps so far allow the next one to run.
+    pub fn ok(&self) -> bool {
+        !self.steps.iter().any(|s| s.status == Status::Fail)
+    }
+
+    pub fn print(&self) {
+        println!("{:<8}  {:<7}  {:>7}  DETAIL", "STEP", "STATUS", "TIME");
+        for step in &self.steps {
+            println!(
+                "{:<8}  {:<7}  {:>6.1}s  {}",
+                step.name,
+                step.status.label(),
+                step.seconds,
+                step.detail
+            );
+        }
+        println!();
+        println!(
+            "DR drill {} in {:.0}s (generation {}, restored to {})",
+            if self.passed { "PASSED" } else { "FAILED" },
+            self.seconds,
+            self.generation.as_deref().unwrap_or("-"),
+            self.scratch
+        );
+    }
+}
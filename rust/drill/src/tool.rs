@@ -0,0 +1,186 @@
+//! The tools a drill runs, as child processes: their output goes to a log
+//! file of their own, and their JSON log events are read back for the report.
+
+use serde_json::{Map, Value};
+use std::fs::{self, File};
+use std::io;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+/// A finished run of one tool.
+pub struct Finished {
+    pub status: ExitStatus,
+    pub log: PathBuf,
+    events: Vec<Map<String, Value>>,
+}
+
+impl Finished {
+    /// The last event logged with this message.
+    pub fn event(&self, message: &str) -> Option<&Map<String, Value>> {
+        self.events
+            .iter()
+            .rev()
+            .find(|e| e.get("message").and_then(Value::as_str) == Some(message))
+    }
+
+    /// Why the tool failed, in its own words: the reason of its last FATAL
+    /// or FAIL event, else the exit status.
+    pub fn failure(&self) -> String {
+        let reason = self.events.iter().rev().find_map(|e| {
+            let tag = e.get("tag").and_then(Value::as_str)?;
+            if tag != "FATAL" && tag != "FAIL" {
+                return None;
+            }
+            let message = e.get("message").and_then(Value::as_str).unwrap_or("");
+            Some(match e.get("reason").and_then(Value::as_str) {
+                Some(reason) => format!("{}: {}", message, reason),
+                None => message.to_string(),
+            })
+        });
+        match reason {
+            Some(reason) => format!("{} ({})", reason, self.status),
+            None => format!("{}, see {}", self.status, self.log.display()),
+        }
+    }
+}
+
+/// A string field of an event, whatever its JSON type.
+pub fn field(event: &Map<String, Value>, name: &str) -> Option<String> {
+    match event.get(name)? {
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+/// Runs `command` to completion with its stdout and stderr in `log`.
+pub fn run(command: &mut Command, log: &Path) -> io::Result<Finished> {
+    let out = File::create(log)?;
+    let err = out.try_clone()?;
+    let status = command
+        .stdin(Stdio::null())
+        .stdout(out)
+        .stderr(err)
+        .status()?;
+    let text = fs::read_to_string(log).unwrap_or_default();
+    let events = text
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    Ok(Finished {
+        status,
+        log: log.to_path_buf(),
+        events,
+    })
+}
+
+/// `command` without the environment overrides of config.yaml, for tools
+/// given the drill's own config: it already has them applied, and
+/// `OCTA_DATABASE_PATH` and the like would point them back at production.
+pub fn scrubbed(mut command: Command) -> Command {
+    for (name, _) in std::env::vars_os() {
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        if name.starts_with("OCTA_") || name.starts_with("AVATAR_") || name == "APP_PORT" {
+            command.env_remove(name);
+        }
+    }
+    command
+}
+
+/// A server started on the restored database; killed when dropped.
+pub struct Server {
+    child: Child,
+    pub base_url: String,
+}
+
+impl Server {
+    /// Starts `binary` with `config` (octa-server finds it through
+    /// $OCTA_CONFIG, the Go server in its working directory) and waits
+    /// until `/health` answers.
+    pub fn start(
+        binary: &Path,
+        config: &Path,
+        port: u16,
+        timeout: Duration,
+        log: &Path,
+    ) -> Result<Self, String> {
+        let out = File::create(log).map_err(|e| e.to_string())?;
+        let err = out.try_clone().map_err(|e| e.to_string())?;
+        let dir = config.parent().unwrap_or(Path::new("."));
+        let child = scrubbed(Command::new(binary))
+            .current_dir(dir)
+            .env("OCTA_CONFIG", config)
+            .stdin(Stdio::null())
+            .stdout(out)
+            .stderr(err)
+            .spawn()
+            .map_err(|e| format!("{}: {}", binary.display(), e))?;
+        let mut server = Self {
+            child,
+            base_url: format!("http://127.0.0.1:{}", port),
+        };
+
+        let started = Instant::now();
+        loop {
+            if health(&server.base_url).is_ok() {
+                return Ok(server);
+            }
+            if let Ok(Some(status)) = server.child.try_wait() {
+                return Err(format!(
+                    "server exited with {}, see {}",
+                    status,
+                    log.display()
+                ));
+            }
+            if started.elapsed() > timeout {
+                return Err(format!(
+                    "/health did not answer within {:?}, see {}",
+                    timeout,
+                    log.display()
+                ));
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    /// Whether the server is still running.
+    pub fn running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The assets `/health` counts.
+pub fn health(base_url: &str) -> Result<u64, String> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(5)))
+        .build()
+        .into();
+    let mut response = agent
+        .get(format!("{}/health", base_url))
+        .call()
+        .map_err(|e| e.to_string())?;
+    let body = response
+        .body_mut()
+        .read_to_string()
+        .map_err(|e| e.to_string())?;
+    let health: Value = serde_json::from_str(&body).map_err(|e| e.to_string())?;
+    health
+        .get("assets")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| format!("no asset count in {}", body.trim()))
+}
+
+/// A port nothing listens on right now; the server binds it a moment later.
+pub fn free_port() -> io::Result<u16> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
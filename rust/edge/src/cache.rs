@@ -0,0 +1,344 @@
+//! The cache: responses in a memory LRU bounded in bytes, and optionally a
+//! larger one on disk behind it that survives restarts. Entries are keyed
+//! by path and query, as the edge URLs octa-cdn purges.
+
+use axum::body::Bytes;
+use lru::LruCache;
+use octa_warden_core::export::sha256_hex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Bytes counted per entry on top of its body, for the headers and the key.
+const OVERHEAD: u64 = 512;
+
+/// A cached 200 response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub key: String,
+    pub content_type: String,
+    pub cache_control: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// When it was fetched or last revalidated, in Unix seconds.
+    pub stored_at: u64,
+    /// Seconds it is fresh for.
+    pub ttl: u64,
+    #[serde(skip)]
+    pub body: Bytes,
+}
+
+impl Entry {
+    pub fn age(&self, now: u64) -> u64 {
+        now.saturating_sub(self.stored_at)
+    }
+
+    pub fn is_fresh(&self, now: u64) -> bool {
+        self.age(now) < self.ttl
+    }
+
+    fn weight(&self) -> u64 {
+        self.body.len() as u64 + OVERHEAD
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// An LRU bounded by the summed weight of its values.
+struct Tier<V> {
+    lru: LruCache<String, (V, u64)>,
+    used: u64,
+    capacity: u64,
+}
+
+impl<V> Tier<V> {
+    fn new(capacity: u64) -> Self {
+        Self {
+            lru: LruCache::unbounded(),
+            used: 0,
+            capacity,
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<&V> {
+        self.lru.get(key).map(|(value, _)| value)
+    }
+
+    /// Inserts `value` and returns what was evicted to make room, the
+    /// replaced value included.
+    fn insert(&mut self, key: String, value: V, weight: u64) -> Vec<(String, V)> {
+        let mut evicted = Vec::new();
+        if let Some((old, old_weight)) = self.lru.pop(&key) {
+            self.used -= old_weight;
+            evicted.push((key.clone(), old));
+        }
+        self.used += weight;
+        self.lru.put(key, (value, weight));
+        while self.used > self.capacity {
+            let Some((key, (value, weight))) = self.lru.pop_lru() else {
+                break;
+            };
+            self.used -= weight;
+            evicted.push((key, value));
+        }
+        evicted
+    }
+
+    /// Removes the keys `matches` picks.
+    fn remove(&mut self, matches: impl Fn(&str) -> bool) -> Vec<(String, V)> {
+        let keys: Vec<String> = self
+            .lru
+            .iter()
+            .filter(|(key, _)| matches(key))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.into_iter()
+            .filter_map(|key| {
+                let (value, weight) = self.lru.pop(&key)?;
+                self.used -= weight;
+                Some((key, value))
+            })
+            .collect()
+    }
+}
+
+/// How full the cache is.
+#[derive(Debug, Default, Serialize)]
+pub struct Usage {
+    pub memory_entries: usize,
+    pub memory_bytes: u64,
+    pub disk_entries: usize,
+    pub disk_bytes: u64,
+}
+
+pub struct Cache {
+    memory: Mutex<Tier<Arc<Entry>>>,
+    disk: Option<Disk>,
+    /// Larger responses are passed on, never stored.
+    pub max_object: u64,
+}
+
+impl Cache {
+    /// With `disk`, the entries already there are indexed first.
+    pub fn new(memory: u64, disk: Option<(PathBuf, u64)>, max_object: u64) -> io::Result<Self> {
+        let disk = disk
+            .map(|(dir, capacity)| Disk::open(dir, capacity))
+            .transpose()?;
+        Ok(Self {
+            memory: Mutex::new(Tier::new(memory)),
+            disk,
+            max_object,
+        })
+    }
+
+    /// The entry for `key`, from memory or else from disk (and then kept in
+    /// memory again).
+    pub async fn get(&self, key: &str) -> Option<Arc<Entry>> {
+        if let Some(entry) = self.memory.lock().expect("cache lock").get(key) {
+            return Some(Arc::clone(entry));
+        }
+        let disk = self.disk.as_ref()?;
+        let path = disk.path_of(key)?;
+        let read = tokio::task::spawn_blocking(move || Disk::read(&path))
+            .await
+            .ok()?;
+        match read {
+            Ok(entry) if entry.key == key => {
+                let entry = Arc::new(entry);
+                self.remember(Arc::clone(&entry));
+                Some(entry)
+            }
+            Ok(_) => None,
+            Err(e) => {
+                warn!(tag = "WARN", key, reason = %e, "Dropping unreadable disk entry");
+                disk.forget(|k| k == key);
+                None
+            }
+        }
+    }
+
+    /// Stores `entry` in memory and, when there is a disk tier, on disk.
+    pub async fn put(&self, entry: Arc<Entry>) {
+        self.remember(Arc::clone(&entry));
+        if let Some(disk) = &self.disk {
+            disk.write(entry).await;
+        }
+    }
+
+    fn remember(&self, entry: Arc<Entry>) {
+        let weight = entry.weight();
+        self.memory
+            .lock()
+            .expect("cache lock")
+            .insert(entry.key.clone(), entry, weight);
+    }
+
+    /// Drops every entry `matches` picks, from both tiers; returns how many
+    /// keys were cached.
+    pub fn purge(&self, matches: impl Fn(&str) -> bool) -> usize {
+        let mut purged: Vec<String> = self
+            .memory
+            .lock()
+            .expect("cache lock")
+            .remove(&matches)
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        if let Some(disk) = &self.disk {
+            purged.extend(disk.forget(&matches));
+        }
+        purged.sort();
+        purged.dedup();
+        purged.len()
+    }
+
+    pub fn usage(&self) -> Usage {
+        let memory = self.memory.lock().expect("cache lock");
+        let mut usage = Usage {
+            memory_entries: memory.lru.len(),
+            memory_bytes: memory.used,
+            ..Usage::default()
+        };
+        if let Some(disk) = &self.disk {
+            let index = disk.index.lock().expect("cache lock");
+            usage.disk_entries = index.lru.len();
+            usage.disk_bytes = index.used;
+        }
+        usage
+    }
+}
+
+/// Entries as files named by the SHA-256 of their key: one JSON line with
+/// the headers, then the body.
+struct Disk {
+    dir: PathBuf,
+    /// Keys on disk, by the size of their file.
+    index: Mutex<Tier<()>>,
+}
+
+impl Disk {
+    fn open(dir: PathBuf, capacity: u64) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let disk = Self {
+            dir,
+            index: Mutex::new(Tier::new(capacity)),
+        };
+        let mut found: Vec<(SystemTime, String, u64)> = Vec::new();
+        for file in fs::read_dir(&disk.dir)? {
+            let path = file?.path();
+            // Only files the edge named: a wrong `disk` keeps its contents.
+            let ours = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .is_some_and(|s| s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit()));
+            if !ours {
+                continue;
+            }
+            if path.extension().is_some_and(|e| e == "tmp") {
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            let Ok(key) = Disk::read_key(&path) else {
+                let _ = fs::remove_file(&path);
+                continue;
+            };
+            let meta = fs::metadata(&path)?;
+            found.push((meta.modified()?, key, meta.len()));
+        }
+        // Oldest first, so the least recently written go first when full.
+        found.sort();
+        for (_, key, size) in found {
+            disk.evicted(disk.index.lock().expect("cache lock").insert(key, (), size));
+        }
+        Ok(disk)
+    }
+
+    fn file(&self, key: &str) -> PathBuf {
+        self.dir.join(sha256_hex(key.as_bytes()))
+    }
+
+    /// The file of `key`, if it is on disk.
+    fn path_of(&self, key: &str) -> Option<PathBuf> {
+        let mut index = self.index.lock().expect("cache lock");
+        index.get(key)?;
+        Some(self.file(key))
+    }
+
+    /// Only the header line, for the index.
+    fn read_key(path: &Path) -> io::Result<String> {
+        #[derive(Deserialize)]
+        struct Header {
+            key: String,
+        }
+        let mut line = Vec::new();
+        io::BufReader::new(fs::File::open(path)?).read_until(b'\n', &mut line)?;
+        let header: Header = serde_json::from_slice(&line).map_err(io::Error::other)?;
+        Ok(header.key)
+    }
+
+    fn read(path: &Path) -> io::Result<Entry> {
+        Disk::parse(&fs::read(path)?)
+    }
+
+    fn parse(data: &[u8]) -> io::Result<Entry> {
+        let end = data
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| io::Error::other("no header line"))?;
+        let mut entry: Entry = serde_json::from_slice(&data[..end]).map_err(io::Error::other)?;
+        entry.body = Bytes::copy_from_slice(&data[end + 1..]);
+        Ok(entry)
+    }
+
+    async fn write(&self, entry: Arc<Entry>) {
+        let path = self.file(&entry.key);
+        let key = entry.key.clone();
+        let written = tokio::task::spawn_blocking(move || -> io::Result<u64> {
+            let mut header = serde_json::to_vec(&*entry).map_err(io::Error::other)?;
+            header.push(b'\n');
+            let tmp = path.with_extension("tmp");
+            let mut file = fs::File::create(&tmp)?;
+            file.write_all(&header)?;
+            file.write_all(&entry.body)?;
+            fs::rename(&tmp, &path)?;
+            Ok((header.len() + entry.body.len()) as u64)
+        })
+        .await
+        .map_err(io::Error::other)
+        .and_then(|written| written);
+        match written {
+            Ok(size) => {
+                let evicted = self
+                    .index
+                    .lock()
+                    .expect("cache lock")
+                    .insert(key.clone(), (), size);
+                // The replaced entry shares the new one's file.
+                self.evicted(evicted.into_iter().filter(|(k, _)| *k != key).collect());
+            }
+            Err(e) => warn!(tag = "WARN", key, reason = %e, "Could not write disk entry"),
+        }
+    }
+
+    fn forget(&self, matches: impl Fn(&str) -> bool) -> Vec<String> {
+        let removed = self.index.lock().expect("cache lock").remove(matches);
+        let keys = removed.iter().map(|(key, _)| key.clone()).collect();
+        self.evicted(removed);
+        keys
+    }
+
+    fn evicted(&self, evicted: Vec<(String, ())>) {
+        for (key, _) in evicted {
+            let _ = fs::remove_file(self.file(&key));
+        }
+    }
+}
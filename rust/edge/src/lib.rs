@@ -0,0 +1,413 @@
+use axum::extract::{DefaultBodyLimit, Request};
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::Router;
+use cache::Cache;
+use clap::Parser;
+use octa_config::{ConfigError, ServerConfig, Validate, Watch};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use octa_warden_core::growth::{format_bytes, parse_bytes};
+use octa_warden_core::schedule::parse_interval;
+use proxy::{Edge, Shared};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn, Level};
+
+mod cache;
+mod proxy;
+
+/*
+OCTA-EDGE: Caching reverse proxy for hot avatars
+=============================================
+Mission: Serve the avatars asked for most from memory (and optionally a
+         disk cache behind it), so the Octa instance only sees misses and
+         revalidations; for a single site or a region without a CDN.
+Safety:  Only what the origin marks public with a max-age is stored, for
+         no longer than edge.max_ttl; a stale entry is revalidated with its
+         ETag. Writes through the edge purge the keys they name, and
+         octa-warden and octa-ctl purge it like any CDN (cdn.provider:
+         edge). Everything else is passed on untouched.
+*/
+
+/// Largest request body passed on (uploads); the instance enforces its own,
+/// usually smaller, limit.
+const MAX_BODY: usize = 32 * 1024 * 1024;
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Cache hot avatars in front of an Octa instance"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Address to serve on (overrides edge.listen)
+    #[arg(long, env = "OCTA_EDGE_LISTEN")]
+    listen: Option<String>,
+
+    /// Octa instance to cache (overrides edge.upstream, else base_url)
+    #[arg(long, env = "OCTA_EDGE_UPSTREAM")]
+    upstream: Option<String>,
+
+    /// Directory of the disk cache (overrides edge.disk)
+    #[arg(long, env = "OCTA_EDGE_DISK")]
+    disk: Option<PathBuf>,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+
+    /// Minimum level to log; `debug` adds one line per request
+    #[arg(long, default_value_t = Level::INFO)]
+    log_level: Level,
+}
+
+/// The parts of config.yaml octa-edge reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    server: ServerConfig,
+    base_url: Option<String>,
+    edge: EdgeConfig,
+}
+
+/// `edge:`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct EdgeConfig {
+    listen: String,
+    upstream: Option<String>,
+    memory: String,
+    disk: Option<PathBuf>,
+    disk_size: String,
+    max_object: String,
+    max_ttl: String,
+    timeout: String,
+    token_env: String,
+}
+
+impl Default for EdgeConfig {
+    fn default() -> Self {
+        Self {
+            listen: "0.0.0.0:9960".to_string(),
+            upstream: None,
+            memory: "256MB".to_string(),
+            disk: None,
+            disk_size: "2GB".to_string(),
+            max_object: "2MB".to_string(),
+            max_ttl: "1h".to_string(),
+            timeout: "10s".to_string(),
+            token_env: "OCTA_CDN_TOKEN".to_string(),
+        }
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = self.server.validate();
+        let edge = &self.edge;
+        problems.extend(octa_config::check_url("base_url", self.base_url.as_deref()));
+        problems.extend(octa_config::check_url(
+            "edge.upstream",
+            edge.upstream.as_deref(),
+        ));
+        if edge.listen.parse::<SocketAddr>().is_err() {
+            problems.push((
+                "edge.listen".to_string(),
+                format!("'{}' is not an address like 0.0.0.0:9960", edge.listen),
+            ));
+        }
+        for (field, value) in [
+            ("edge.memory", &edge.memory),
+            ("edge.disk_size", &edge.disk_size),
+            ("edge.max_object", &edge.max_object),
+        ] {
+            match parse_bytes(value) {
+                Ok(0) => problems.push((field.to_string(), "must be greater than 0".to_string())),
+                Ok(_) => {}
+                Err(e) => problems.push((field.to_string(), e)),
+            }
+        }
+        if let (Ok(memory), Ok(max_object)) =
+            (parse_bytes(&edge.memory), parse_bytes(&edge.max_object))
+        {
+            if max_object > memory {
+                problems.push((
+                    "edge.max_object".to_string(),
+                    format!("must not exceed edge.memory ({})", edge.memory),
+                ));
+            }
+        }
+        for (field, value) in [
+            ("edge.max_ttl", &edge.max_ttl),
+            ("edge.timeout", &edge.timeout),
+        ] {
+            if let Err(e) = parse_interval(value) {
+                problems.push((field.to_string(), e));
+            }
+        }
+        if edge.token_env.trim().is_empty() {
+            problems.push((
+                "edge.token_env".to_string(),
+                "must name the variable holding the purge token".to_string(),
+            ));
+        }
+        problems
+    }
+}
+
+/// The arguments that override `edge:`, applied on every (re)load.
+#[derive(Debug, Clone)]
+struct Overrides {
+    listen: Option<String>,
+    upstream: Option<String>,
+    disk: Option<PathBuf>,
+}
+
+impl Overrides {
+    fn apply(&self, mut config: FileConfig) -> FileConfig {
+        let edge = &mut config.edge;
+        if let Some(listen) = &self.listen {
+            edge.listen = listen.clone();
+        }
+        if let Some(upstream) = &self.upstream {
+            edge.upstream = Some(upstream.clone());
+        }
+        if let Some(disk) = &self.disk {
+            edge.disk = Some(disk.clone());
+        }
+        config
+    }
+}
+
+/// Without a file, the environment alone can configure the edge (e.g. in a
+/// container). Arguments are applied before the config is checked. Also
+/// returns the file read, to reload it from.
+fn load_config(
+    args: &Args,
+    overrides: &Overrides,
+) -> Result<(FileConfig, Option<PathBuf>), ConfigError> {
+    let found = octa_config::discover(args.config.as_deref().map(Path::new));
+    let config: FileConfig = match &found {
+        Some(found) => octa_config::read(found)?,
+        None => octa_config::from_env()?,
+    };
+    let origin = found.clone().unwrap_or_else(|| "<environment>".into());
+    octa_config::check(&origin, overrides.apply(config)).map(|config| (config, found))
+}
+
+/// The purge token, from the variable `edge.token_env` names.
+fn token(variable: &str) -> Option<String> {
+    std::env::var(variable)
+        .ok()
+        .filter(|token| !token.trim().is_empty())
+}
+
+/// Applies every reload of the config file to the running edge: a new
+/// `edge.max_ttl` and `edge.token_env` take effect for the next request.
+/// The rest of `edge:` is fixed at start.
+fn follow(watch: Watch<FileConfig>, edge: Shared, mut running: EdgeConfig) {
+    loop {
+        let Some(reload) = watch.wait(Duration::from_secs(3600)) else {
+            continue;
+        };
+        let config = match reload {
+            Ok(config) => config.edge,
+            Err(e) => {
+                warn!(tag = "RELOAD", reason = %e, "Config change rejected, keeping the running config");
+                continue;
+            }
+        };
+        // Checked by validate().
+        let max_ttl = parse_interval(&config.max_ttl).unwrap_or_default();
+        edge.reconfigure(max_ttl.as_secs(), token(&config.token_env));
+        let fixed = EdgeConfig {
+            max_ttl: running.max_ttl.clone(),
+            token_env: running.token_env.clone(),
+            ..config.clone()
+        };
+        if fixed != running {
+            warn!(tag = "RELOAD", "Only edge.max_ttl and edge.token_env apply without a restart");
+        }
+        info!(tag = "RELOAD", max_ttl = %config.max_ttl, "Configuration reloaded");
+        running = config;
+    }
+}
+
+/// The octa-edge command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa edge` the arguments it was given.
+#[tokio::main]
+pub async fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, args.log_level, false, false);
+
+    let overrides = Overrides {
+        listen: args.listen.clone(),
+        upstream: args.upstream.clone(),
+        disk: args.disk.clone(),
+    };
+    let (config, found) = match load_config(&args, &overrides) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let edge = config.edge;
+    let upstream = octa_config::base_url(
+        edge.upstream.as_deref().or(config.base_url.as_deref()),
+        &config.server,
+    );
+    let upstream = upstream.trim_end_matches('/').to_string();
+    // Checked by validate().
+    let memory = parse_bytes(&edge.memory).unwrap_or_default();
+    let disk_size = parse_bytes(&edge.disk_size).unwrap_or_default();
+    let max_object = parse_bytes(&edge.max_object).unwrap_or_default();
+    let max_ttl = parse_interval(&edge.max_ttl).unwrap_or_default();
+    let timeout = parse_interval(&edge.timeout).unwrap_or_default();
+
+    let token = token(&edge.token_env);
+    if token.is_none() {
+        warn!(
+            tag = "WARN",
+            variable = %edge.token_env,
+            "No purge token set; /edge/purge and /edge/stats are disabled"
+        );
+    }
+
+    let cache = match Cache::new(
+        memory,
+        edge.disk.clone().map(|dir| (dir, disk_size)),
+        max_object,
+    ) {
+        Ok(cache) => cache,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Could not open the disk cache");
+            return Kind::Io.exit_code();
+        }
+    };
+    // Redirects are the client's to follow, and cached as they came.
+    let client = match reqwest::Client::builder()
+        .timeout(timeout)
+        .redirect(reqwest::redirect::Policy::none())
+        .user_agent(format!("octa-edge/{}", env!("CARGO_PKG_VERSION")))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Could not create the HTTP client");
+            return Kind::Internal.exit_code();
+        }
+    };
+    let usage = cache.usage();
+    let state = Arc::new(Edge::new(
+        client,
+        upstream.clone(),
+        cache,
+        max_ttl.as_secs(),
+        token,
+    ));
+    if let Some(path) = found {
+        let watch = octa_config::watch(&path, move |path| {
+            let config = octa_config::read(path)?;
+            octa_config::check(path, overrides.apply(config))
+        });
+        let (state, running) = (Arc::clone(&state), edge.clone());
+        std::thread::spawn(move || follow(watch, state, running));
+    }
+    let app = Router::new()
+        .fallback(proxy::handle)
+        .layer(DefaultBodyLimit::max(MAX_BODY))
+        .layer(middleware::from_fn(log_request))
+        .with_state(Arc::clone(&state));
+
+    let listener = match tokio::net::TcpListener::bind(&edge.listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(tag = "FATAL", addr = %edge.listen, reason = %e, "Could not bind");
+            return Kind::Unavailable.exit_code();
+        }
+    };
+    info!(
+        tag = "OK",
+        addr = %edge.listen,
+        upstream = %upstream,
+        memory = %format_bytes(memory as f64),
+        disk = %edge.disk.as_deref().map_or("off".into(), |d| d.display().to_string()),
+        disk_entries = usage.disk_entries,
+        max_ttl = %edge.max_ttl,
+        "Edge listening"
+    );
+
+    if let Err(e) = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown())
+        .await
+    {
+        error!(tag = "FATAL", reason = %e, "Edge stopped");
+        return Kind::Unavailable.exit_code();
+    }
+    let report = state.report();
+    info!(
+        tag = "OK",
+        hits = report.stats.hits.load(Ordering::Relaxed),
+        misses = report.stats.misses.load(Ordering::Relaxed),
+        hit_ratio = report.hit_ratio,
+        memory_entries = report.usage.memory_entries,
+        disk_entries = report.usage.disk_entries,
+        "Shut down"
+    );
+    ExitCode::SUCCESS
+}
+
+async fn log_request(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    debug!(
+        tag = "HTTP",
+        method = %method,
+        path = %path,
+        status = response.status().as_u16(),
+        cache = response
+            .headers()
+            .get("x-cache")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("-"),
+        ms = started.elapsed().as_millis() as u64,
+        "Request"
+    );
+    response
+}
+
+/// Resolves on Ctrl-C or SIGTERM.
+async fn shutdown() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
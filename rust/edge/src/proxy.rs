@@ -0,0 +1,515 @@
+use crate::cache::{self, Cache, Entry, Usage};
+use axum::body::{Body, Bytes};
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::OnceCell;
+use tracing::{info, warn};
+
+/// Headers of one hop only, never passed on (RFC 9110, 7.6.1).
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "host",
+    "content-length",
+];
+
+/// What the edge answered with, per the `X-Cache` header.
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    /// Served fresh from the cache.
+    pub hits: AtomicU64,
+    /// Fetched from the origin.
+    pub misses: AtomicU64,
+    /// Stale, and confirmed unchanged by the origin (304).
+    pub revalidated: AtomicU64,
+    /// Stale, served because the origin failed.
+    pub stale: AtomicU64,
+    /// Not cacheable: writes, private or uncacheable responses.
+    pub bypassed: AtomicU64,
+}
+
+pub struct Edge {
+    pub client: reqwest::Client,
+    pub upstream: String,
+    pub cache: Cache,
+    /// Longest freshness granted, whatever the origin allows, in seconds.
+    max_ttl: AtomicU64,
+    /// Bearer token of `/edge/purge` and `/edge/stats`; unset disables them.
+    token: RwLock<Option<String>>,
+    pub stats: Stats,
+    /// Origin fetches in flight, shared by every request for the key.
+    inflight: Mutex<HashMap<String, Fetch>>,
+}
+
+/// An origin fetch that every request waiting on it shares.
+type Fetch = Arc<OnceCell<Result<Origin, String>>>;
+
+impl Edge {
+    pub fn new(
+        client: reqwest::Client,
+        upstream: String,
+        cache: Cache,
+        max_ttl: u64,
+        token: Option<String>,
+    ) -> Self {
+        Self {
+            client,
+            upstream,
+            cache,
+            max_ttl: AtomicU64::new(max_ttl),
+            token: RwLock::new(token),
+            stats: Stats::default(),
+            inflight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a reloaded `edge.max_ttl` and purge token. Each applies to the
+    /// requests that start after it; cached entries keep their freshness.
+    pub fn reconfigure(&self, max_ttl: u64, token: Option<String>) {
+        self.max_ttl.store(max_ttl, Ordering::Relaxed);
+        *self.token.write().unwrap_or_else(|e| e.into_inner()) = token;
+    }
+
+    fn max_ttl(&self) -> u64 {
+        self.max_ttl.load(Ordering::Relaxed)
+    }
+}
+
+pub type Shared = Arc<Edge>;
+
+/// An origin response, read in full.
+#[derive(Debug, Clone)]
+struct Origin {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+/// Every request: the edge's own endpoints, cached reads of the avatar
+/// routes, and everything else passed through.
+pub async fn handle(
+    State(edge): State<Shared>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let path = uri.path();
+    match (&method, path) {
+        (&Method::POST, "/edge/purge") => purge(&edge, &headers, &body),
+        (&Method::GET, "/edge/stats") => stats(&edge, &headers),
+        (&Method::GET | &Method::HEAD, _) if cacheable_path(path) => {
+            cached(&edge, &uri, &headers).await
+        }
+        _ => pass(&edge, method, &uri, headers, body).await,
+    }
+}
+
+/// Stored and generated avatars; the upload API is never cached.
+fn cacheable_path(path: &str) -> bool {
+    path.starts_with("/u/") || path.starts_with("/avatar/")
+}
+
+fn key_of(uri: &Uri) -> String {
+    uri.path_and_query()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| uri.path().to_string())
+}
+
+async fn cached(edge: &Edge, uri: &Uri, headers: &HeaderMap) -> Response {
+    let key = key_of(uri);
+    let now = cache::now();
+    let cached = edge.cache.get(&key).await;
+    if let Some(entry) = &cached {
+        if entry.is_fresh(now) {
+            edge.stats.hits.fetch_add(1, Ordering::Relaxed);
+            return from_cache(entry, headers, now, "HIT");
+        }
+    }
+
+    let etag = cached.as_ref().and_then(|e| e.etag.clone());
+    match coalesced(edge, &key, etag).await {
+        Ok(origin) if origin.status == StatusCode::NOT_MODIFIED => {
+            let Some(stale) = cached else {
+                // Not asked for; only a revalidation sends If-None-Match.
+                return bad_gateway("unexpected 304 from the origin");
+            };
+            let mut entry = (*stale).clone();
+            entry.stored_at = now;
+            entry.ttl = ttl(&origin.headers, edge.max_ttl()).unwrap_or(entry.ttl);
+            let entry = Arc::new(entry);
+            edge.cache.put(Arc::clone(&entry)).await;
+            edge.stats.revalidated.fetch_add(1, Ordering::Relaxed);
+            from_cache(&entry, headers, now, "REVALIDATED")
+        }
+        Ok(origin) => match storable(edge, &key, &origin, now) {
+            Some(entry) => {
+                let entry = Arc::new(entry);
+                edge.cache.put(Arc::clone(&entry)).await;
+                edge.stats.misses.fetch_add(1, Ordering::Relaxed);
+                from_cache(&entry, headers, now, "MISS")
+            }
+            None => {
+                if origin.status.is_server_error() {
+                    if let Some(stale) = cached {
+                        return served_stale(edge, &stale, headers, now);
+                    }
+                }
+                // A stale copy the origin no longer lets us keep.
+                edge.cache.purge(|k| k == key);
+                edge.stats.bypassed.fetch_add(1, Ordering::Relaxed);
+                passed_on(origin, "BYPASS")
+            }
+        },
+        Err(e) => match cached {
+            Some(stale) => served_stale(edge, &stale, headers, now),
+            None => bad_gateway(&e),
+        },
+    }
+}
+
+fn served_stale(edge: &Edge, stale: &Entry, headers: &HeaderMap, now: u64) -> Response {
+    edge.stats.stale.fetch_add(1, Ordering::Relaxed);
+    from_cache(stale, headers, now, "STALE")
+}
+
+/// One origin fetch per key at a time: requests for a key that is being
+/// fetched wait for that fetch instead of sending their own.
+async fn coalesced(edge: &Edge, key: &str, etag: Option<String>) -> Result<Origin, String> {
+    let cell = Arc::clone(
+        edge.inflight
+            .lock()
+            .expect("inflight lock")
+            .entry(key.to_string())
+            .or_default(),
+    );
+    let result = cell.get_or_init(|| fetch(edge, key, etag)).await.clone();
+    let mut inflight = edge.inflight.lock().expect("inflight lock");
+    if inflight.get(key).is_some_and(|c| Arc::ptr_eq(c, &cell)) {
+        inflight.remove(key);
+    }
+    result
+}
+
+async fn fetch(edge: &Edge, key: &str, etag: Option<String>) -> Result<Origin, String> {
+    let mut request = edge.client.get(format!("{}{}", edge.upstream, key));
+    if let Some(etag) = etag {
+        request = request.header(header::IF_NONE_MATCH, etag);
+    }
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = response.bytes().await.map_err(|e| e.to_string())?;
+    Ok(Origin {
+        status,
+        headers,
+        body,
+    })
+}
+
+/// Seconds `headers` let a shared cache keep the response, capped at
+/// `max_ttl`; `None` when it must not be stored.
+fn ttl(headers: &HeaderMap, max_ttl: u64) -> Option<u64> {
+    let cache_control = headers.get(header::CACHE_CONTROL)?.to_str().ok()?;
+    let mut max_age = None;
+    let mut shared_max_age = None;
+    for directive in cache_control.split(',') {
+        let (name, value) = directive
+            .trim()
+            .split_once('=')
+            .unwrap_or((directive.trim(), ""));
+        match name.to_ascii_lowercase().as_str() {
+            "private" | "no-store" | "no-cache" => return None,
+            "max-age" => max_age = value.trim_matches('"').parse::<u64>().ok(),
+            "s-maxage" => shared_max_age = value.trim_matches('"').parse::<u64>().ok(),
+            _ => {}
+        }
+    }
+    shared_max_age
+        .or(max_age)
+        .filter(|&ttl| ttl > 0)
+        .map(|ttl| ttl.min(max_ttl))
+}
+
+/// The cache entry for a 200 the origin lets shared caches keep.
+fn storable(edge: &Edge, key: &str, origin: &Origin, now: u64) -> Option<Entry> {
+    if origin.status != StatusCode::OK || origin.body.len() as u64 > edge.cache.max_object {
+        return None;
+    }
+    let ttl = ttl(&origin.headers, edge.max_ttl())?;
+    let text = |name: HeaderName| {
+        origin
+            .headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    Some(Entry {
+        key: key.to_string(),
+        content_type: text(header::CONTENT_TYPE).unwrap_or_default(),
+        cache_control: text(header::CACHE_CONTROL).unwrap_or_default(),
+        etag: text(header::ETAG),
+        last_modified: text(header::LAST_MODIFIED),
+        stored_at: now,
+        ttl,
+        body: origin.body.clone(),
+    })
+}
+
+/// `entry` for this client: 304 when its If-None-Match has the ETag.
+fn from_cache(entry: &Entry, request: &HeaderMap, now: u64, outcome: &'static str) -> Response {
+    let fresh_copy = entry.etag.as_deref().is_some_and(|etag| {
+        request
+            .get(header::IF_NONE_MATCH)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains(etag))
+    });
+    let mut headers = HeaderMap::new();
+    let mut set = |name: HeaderName, value: &str| {
+        if let Ok(value) = HeaderValue::from_str(value) {
+            headers.insert(name, value);
+        }
+    };
+    set(header::CACHE_CONTROL, &entry.cache_control);
+    if let Some(etag) = &entry.etag {
+        set(header::ETAG, etag);
+    }
+    if let Some(last_modified) = &entry.last_modified {
+        set(header::LAST_MODIFIED, last_modified);
+    }
+    set(header::AGE, &entry.age(now).to_string());
+    set(HeaderName::from_static("x-cache"), outcome);
+    if fresh_copy {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+    set(header::CONTENT_TYPE, &entry.content_type);
+    (StatusCode::OK, headers, entry.body.clone()).into_response()
+}
+
+/// An origin response as it came, minus the hop-by-hop headers.
+fn passed_on(origin: Origin, outcome: &'static str) -> Response {
+    let mut headers = end_to_end(&origin.headers);
+    headers.insert(
+        HeaderName::from_static("x-cache"),
+        HeaderValue::from_static(outcome),
+    );
+    (origin.status, headers, Body::from(origin.body)).into_response()
+}
+
+fn end_to_end(headers: &HeaderMap) -> HeaderMap {
+    let mut kept = headers.clone();
+    for name in HOP_BY_HOP {
+        kept.remove(*name);
+    }
+    kept
+}
+
+/// Everything but cached reads goes to the origin as is. A successful
+/// upload or delete also purges the keys it names, so the edge does not
+/// serve the old image.
+async fn pass(edge: &Edge, method: Method, uri: &Uri, headers: HeaderMap, body: Bytes) -> Response {
+    edge.stats.bypassed.fetch_add(1, Ordering::Relaxed);
+    let url = format!("{}{}", edge.upstream, key_of(uri));
+    let sent = edge
+        .client
+        .request(method.clone(), url)
+        .headers(end_to_end(&headers))
+        .body(body)
+        .send()
+        .await;
+    let response = match sent {
+        Ok(response) => response,
+        Err(e) => return bad_gateway(&e.to_string()),
+    };
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = match response.bytes().await {
+        Ok(body) => body,
+        Err(e) => return bad_gateway(&e.to_string()),
+    };
+
+    if status.is_success() {
+        let keys = written_keys(&method, uri, &body);
+        if !keys.is_empty() {
+            let purged = edge
+                .cache
+                .purge(|k| keys.iter().any(|key| is_variant(k, key)));
+            if purged > 0 {
+                info!(tag = "PURGE", keys = %keys.join(","), entries = purged, "Purged written keys");
+            }
+        }
+    }
+    passed_on(
+        Origin {
+            status,
+            headers,
+            body,
+        },
+        "BYPASS",
+    )
+}
+
+/// The keys an upload (`keys` of its answer) or a delete (`?key=`) changed.
+fn written_keys(method: &Method, uri: &Uri, answer: &[u8]) -> Vec<String> {
+    match (method, uri.path()) {
+        (&Method::POST, "/upload") => serde_json::from_slice::<Value>(answer)
+            .ok()
+            .and_then(|v| v.get("keys").cloned())
+            .and_then(|keys| serde_json::from_value(keys).ok())
+            .unwrap_or_default(),
+        (&Method::DELETE, "/upload/delete") => uri
+            .query()
+            .unwrap_or("")
+            .split('&')
+            .filter_map(|pair| pair.strip_prefix("key="))
+            .map(|key| {
+                percent_encoding::percent_decode_str(&key.replace('+', " "))
+                    .decode_utf8_lossy()
+                    .into_owned()
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Whether cache key `cached` is `/u/<key>` in some variant.
+fn is_variant(cached: &str, key: &str) -> bool {
+    let Some(rest) = cached.strip_prefix("/u/") else {
+        return false;
+    };
+    rest.strip_prefix(key)
+        .is_some_and(|after| after.is_empty() || after.starts_with('?'))
+}
+
+/// The refusal for a request without the purge token, if it lacks it.
+fn denied(edge: &Edge, headers: &HeaderMap) -> Option<Response> {
+    let token = edge.token.read().unwrap_or_else(|e| e.into_inner()).clone();
+    let Some(token) = token else {
+        return Some(error(
+            StatusCode::FORBIDDEN,
+            "auth/invalid_credentials",
+            "No purge token is configured (edge.token_env).",
+        ));
+    };
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if given != Some(token.as_str()) {
+        return Some(error(
+            StatusCode::FORBIDDEN,
+            "auth/invalid_credentials",
+            "Invalid token.",
+        ));
+    }
+    None
+}
+
+/// Body of `POST /edge/purge`, as octa-cdn sends it.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Purge {
+    /// Full URLs or paths, each purged exactly.
+    urls: Vec<String>,
+    /// Path prefixes (`/u/team/`): everything under them.
+    prefixes: Vec<String>,
+}
+
+fn purge(edge: &Edge, headers: &HeaderMap, body: &[u8]) -> Response {
+    if let Some(denied) = denied(edge, headers) {
+        return denied;
+    }
+    let request: Purge = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                "request/invalid_parameters",
+                &format!("Invalid purge request: {}.", e),
+            )
+        }
+    };
+    let urls: Vec<&str> = request.urls.iter().map(|url| path_of(url)).collect();
+    let purged = edge
+        .cache
+        .purge(|k| urls.contains(&k) || request.prefixes.iter().any(|p| k.starts_with(p.as_str())));
+    info!(
+        tag = "PURGE",
+        urls = urls.len(),
+        prefixes = request.prefixes.len(),
+        entries = purged,
+        "Purged"
+    );
+    Json(json!({ "status": "success", "purged": purged })).into_response()
+}
+
+/// `https://cdn.example.com/u/alice?size=64` -> `/u/alice?size=64`.
+fn path_of(url: &str) -> &str {
+    match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("/", |at| &rest[at..]),
+        None => url,
+    }
+}
+
+/// The counters and the cache's usage, as JSON.
+#[derive(Serialize)]
+pub struct Report<'a> {
+    #[serde(flatten)]
+    pub stats: &'a Stats,
+    pub hit_ratio: f64,
+    #[serde(flatten)]
+    pub usage: Usage,
+}
+
+impl Edge {
+    pub fn report(&self) -> Report<'_> {
+        let served = |n: &AtomicU64| n.load(Ordering::Relaxed) as f64;
+        let stats = &self.stats;
+        let from_cache = served(&stats.hits) + served(&stats.revalidated) + served(&stats.stale);
+        let reads = from_cache + served(&stats.misses);
+        Report {
+            stats,
+            hit_ratio: if reads > 0.0 {
+                (from_cache / reads * 1000.0).round() / 1000.0
+            } else {
+                0.0
+            },
+            usage: self.cache.usage(),
+        }
+    }
+}
+
+fn stats(edge: &Edge, headers: &HeaderMap) -> Response {
+    if let Some(denied) = denied(edge, headers) {
+        return denied;
+    }
+    Json(edge.report()).into_response()
+}
+
+fn bad_gateway(reason: &str) -> Response {
+    warn!(tag = "HTTP", reason, "Origin unavailable");
+    error(
+        StatusCode::BAD_GATEWAY,
+        "server/internal_error",
+        "The origin is unavailable.",
+    )
+}
+
+/// An error in the servers' format: `{"code", "message", "status"}`.
+fn error(status: StatusCode, code: &str, message: &str) -> Response {
+    let body = json!({ "code": code, "message": message, "status": status.as_u16() });
+    (status, Json(body)).into_response()
+}
@@ -0,0 +1,146 @@
+//! Why an Octa tool gave up, and the exit code that tells a script so.
+//!
+//! Health-style tools (octa-warden, octa-quota) exit 0, 1 or 2
+//! for healthy, warning and critical. Failing to run at all is something
+//! else, so it exits with a code of its own, from BSD's `sysexits.h`: a
+//! cron job or CI step can tell "the database is degraded" (2) from "the
+//! config is invalid" (78) or "the server is down" (69).
+//!
+//! ```
+//! use octa_errors::{Error, Kind};
+//!
+//! let error = Error::new(Kind::NoInput, "database file not found: octa.db");
+//! assert_eq!(error.kind().code(), 66);
+//! assert_eq!(error.to_string(), "database file not found: octa.db");
+//! ```
+
+use std::fmt;
+use std::io;
+use std::process::ExitCode;
+
+/// What kind of failure ended the run; each has its own exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Invalid arguments (64, `EX_USAGE`).
+    Usage,
+    /// Input that could be read but is wrong: a malformed plan, an
+    /// unreadable bundle (65, `EX_DATAERR`).
+    Data,
+    /// A file or database to read does not exist (66, `EX_NOINPUT`).
+    NoInput,
+    /// A server or service could not be reached (69, `EX_UNAVAILABLE`).
+    Unavailable,
+    /// A bug: something that cannot happen did (70, `EX_SOFTWARE`).
+    Internal,
+    /// Reading or writing failed, the database included (74, `EX_IOERR`).
+    Io,
+    /// config.yaml is missing or invalid (78, `EX_CONFIG`).
+    Config,
+}
+
+impl Kind {
+    pub fn code(self) -> u8 {
+        match self {
+            Kind::Usage => 64,
+            Kind::Data => 65,
+            Kind::NoInput => 66,
+            Kind::Unavailable => 69,
+            Kind::Internal => 70,
+            Kind::Io => 74,
+            Kind::Config => 78,
+        }
+    }
+
+    pub fn exit_code(self) -> ExitCode {
+        ExitCode::from(self.code())
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kind::Usage => "usage",
+            Kind::Data => "data",
+            Kind::NoInput => "no input",
+            Kind::Unavailable => "unavailable",
+            Kind::Internal => "internal",
+            Kind::Io => "io",
+            Kind::Config => "config",
+        }
+    }
+}
+
+/// A failure that ends the run: its kind and what to tell the user.
+#[derive(Debug)]
+pub struct Error {
+    kind: Kind,
+    message: String,
+    source: Option<Box<dyn std::error::Error + Send + Sync>>,
+}
+
+/// A result ending in [`Error`].
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn new(kind: Kind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    /// `source` as the cause, its message as this error's.
+    pub fn from_source(kind: Kind, source: impl std::error::Error + Send + Sync + 'static) -> Self {
+        Self {
+            kind,
+            message: source.to_string(),
+            source: Some(Box::new(source)),
+        }
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    pub fn exit_code(&self) -> ExitCode {
+        self.kind.exit_code()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source
+            .as_deref()
+            .map(|e| e as &(dyn std::error::Error + 'static))
+    }
+}
+
+/// A missing file is [`Kind::NoInput`], anything else [`Kind::Io`].
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        let kind = match e.kind() {
+            io::ErrorKind::NotFound => Kind::NoInput,
+            _ => Kind::Io,
+        };
+        Error::from_source(kind, e)
+    }
+}
+
+#[cfg(feature = "config")]
+impl From<octa_config::ConfigError> for Error {
+    fn from(e: octa_config::ConfigError) -> Self {
+        Error::from_source(Kind::Config, e)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::from_source(Kind::Io, e)
+    }
+}
@@ -0,0 +1,190 @@
+use octa_warden_core::cas;
+use octa_warden_core::db::{self, OpenOptions};
+use octa_warden_core::growth;
+use octa_warden_core::metrics::bool_gauge;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// One reading of the database. Everything here comes from indexes, the
+/// record headers or the file system, so it stays cheap on large files.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub images: u64,
+    pub keys: u64,
+    /// Sum of the stored image bytes.
+    pub image_bytes: u64,
+    /// Since the newest `images.updated_at`; `None` while the table is empty.
+    pub last_write_age: Option<f64>,
+    pub db_bytes: u64,
+    /// 0 when there is no `-wal` file (rollback journal, or checkpointed away).
+    pub wal_bytes: u64,
+    /// Free pages inside the file, returned to the disk by `VACUUM`.
+    pub free_bytes: u64,
+    pub disk_free: Option<u64>,
+}
+
+/// Reads a [`Sample`] over a read-only connection.
+pub fn sample(db_path: &str, opts: &OpenOptions) -> Result<Sample, String> {
+    let conn = db::open_read_only(db_path, opts).map_err(|e| e.to_string())?;
+    let query = || -> rusqlite::Result<Sample> {
+        // COUNT(*) walks the smallest index.
+        let images: i64 = conn.query_row("SELECT COUNT(*) FROM images", [], |row| row.get(0))?;
+        let image_bytes = cas::stored_bytes(&conn)?;
+        let keys: i64 =
+            conn.query_row("SELECT COUNT(*) FROM key_mappings", [], |row| row.get(0))?;
+        // MAX() is answered from idx_images_updated_at.
+        let last_write_age: Option<f64> = conn.query_row(
+            "SELECT (julianday('now') - julianday(MAX(updated_at))) * 86400 FROM images",
+            [],
+            |row| row.get(0),
+        )?;
+        let page_size: i64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        let free_pages: i64 = conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))?;
+        Ok(Sample {
+            images: images as u64,
+            keys: keys as u64,
+            image_bytes,
+            last_write_age: last_write_age.map(|age| age.max(0.0)),
+            db_bytes: 0,
+            wal_bytes: 0,
+            free_bytes: (free_pages * page_size) as u64,
+            disk_free: None,
+        })
+    };
+    let mut sample = query().map_err(|e| e.to_string())?;
+
+    let path = Path::new(db_path);
+    sample.db_bytes = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    sample.wal_bytes = fs::metadata(format!("{}-wal", db_path))
+        .map(|m| m.len())
+        .unwrap_or(0);
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty());
+    sample.disk_free = growth::disk_free(dir.unwrap_or(Path::new(".")));
+    Ok(sample)
+}
+
+/// The exporter's view between collections: the last good sample, and how
+/// the last attempt went.
+#[derive(Debug, Default)]
+pub struct State {
+    pub sample: Option<Sample>,
+    pub success: bool,
+    pub duration: Duration,
+    /// Unix time of the last successful collection.
+    pub collected_at: u64,
+    pub collections: u64,
+    pub errors: u64,
+}
+
+impl State {
+    pub fn record(&mut self, result: Result<Sample, String>, duration: Duration) {
+        self.collections += 1;
+        self.duration = duration;
+        match result {
+            Ok(sample) => {
+                self.sample = Some(sample);
+                self.success = true;
+                self.collected_at = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default();
+            }
+            // The previous sample stays; the success gauge tells it is stale.
+            Err(_) => {
+                self.success = false;
+                self.errors += 1;
+            }
+        }
+    }
+
+    /// Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        if let Some(s) = &self.sample {
+            let gauges: [(&str, &str, Option<f64>); 8] = [
+                ("octa_images", "Rows in images.", Some(s.images as f64)),
+                ("octa_keys", "Rows in key_mappings.", Some(s.keys as f64)),
+                (
+                    "octa_image_bytes",
+                    "Sum of the stored image bytes.",
+                    Some(s.image_bytes as f64),
+                ),
+                (
+                    "octa_last_write_age_seconds",
+                    "Seconds since the newest images.updated_at (last upload or update).",
+                    s.last_write_age,
+                ),
+                (
+                    "octa_db_bytes",
+                    "Size of the database file.",
+                    Some(s.db_bytes as f64),
+                ),
+                (
+                    "octa_db_wal_bytes",
+                    "Size of the -wal file, 0 without one.",
+                    Some(s.wal_bytes as f64),
+                ),
+                (
+                    "octa_db_free_bytes",
+                    "Free pages inside the database file, returned by VACUUM.",
+                    Some(s.free_bytes as f64),
+                ),
+                (
+                    "octa_disk_free_bytes",
+                    "Free space on the filesystem holding the database.",
+                    s.disk_free.map(|b| b as f64),
+                ),
+            ];
+            for (name, help, value) in gauges {
+                if let Some(value) = value {
+                    metric(&mut out, name, help, "gauge", value);
+                }
+            }
+        }
+
+        let own: [(&str, &str, f64); 5] = [
+            (
+                "octa_exporter_collect_success",
+                "1 if the last collection read the database, 0 if it failed.",
+                bool_gauge(self.success),
+            ),
+            (
+                "octa_exporter_collect_duration_seconds",
+                "Wall time of the last collection.",
+                self.duration.as_secs_f64(),
+            ),
+            (
+                "octa_exporter_last_collect_timestamp_seconds",
+                "Unix time of the last successful collection.",
+                self.collected_at as f64,
+            ),
+            (
+                "octa_exporter_collections_total",
+                "Collections attempted since start.",
+                self.collections as f64,
+            ),
+            (
+                "octa_exporter_collect_errors_total",
+                "Collections that failed since start.",
+                self.errors as f64,
+            ),
+        ];
+        for (name, help, value) in own {
+            let kind = if name.ends_with("_total") {
+                "counter"
+            } else {
+                "gauge"
+            };
+            metric(&mut out, name, help, kind, value);
+        }
+        out
+    }
+}
+
+fn metric(out: &mut String, name: &str, help: &str, kind: &str, value: f64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
@@ -0,0 +1,275 @@
+use clap::Parser;
+use collect::State;
+use octa_config::{ConfigError, DatabaseConfig, Validate};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use octa_warden_core::db::OpenOptions;
+use octa_warden_core::metrics;
+use octa_warden_core::schedule::parse_interval;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn, Level};
+
+mod collect;
+
+/*
+OCTA-EXPORTER: Prometheus metrics for an Octa database
+=============================================
+Mission: Sit next to a deployment and publish what the server itself does
+         not: asset and key counts, stored bytes, time since the last write,
+         and the size of the database file, its WAL and its free pages.
+Safety:  Reads only, over a read-only connection, and only what indexes,
+         record headers and the file system answer cheaply.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Export Prometheus metrics about an Octa database"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// SQLite database to watch (overrides database.path; the config file becomes optional)
+    #[arg(long = "db", env = "OCTA_DB_PATH", value_name = "PATH")]
+    db_path: Option<String>,
+
+    /// Address to serve /metrics on (overrides exporter.listen)
+    #[arg(long, env = "OCTA_EXPORTER_LISTEN")]
+    listen: Option<String>,
+
+    /// Time between collections, e.g. 30s or 5m (overrides exporter.interval)
+    #[arg(long)]
+    interval: Option<String>,
+
+    /// Also write the metrics to this file after every collection, for node_exporter's textfile collector
+    #[arg(long, value_name = "PATH")]
+    textfile: Option<PathBuf>,
+
+    /// Collect once, print the metrics and exit
+    #[arg(long)]
+    once: bool,
+
+    /// Milliseconds to wait on a locked database before giving up (SQLITE_BUSY)
+    #[arg(long, default_value_t = 5000)]
+    busy_timeout: u64,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+}
+
+/// The parts of config.yaml octa-exporter reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    database: DatabaseConfig,
+    exporter: ExporterConfig,
+}
+
+/// `exporter:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ExporterConfig {
+    listen: String,
+    interval: String,
+    textfile: Option<PathBuf>,
+}
+
+impl Default for ExporterConfig {
+    fn default() -> Self {
+        Self {
+            listen: "0.0.0.0:9465".to_string(),
+            interval: "30s".to_string(),
+            textfile: None,
+        }
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        if self.database.path.trim().is_empty() {
+            problems.push(("database.path".to_string(), "is required".to_string()));
+        }
+        if self.exporter.listen.trim().is_empty() {
+            problems.push((
+                "exporter.listen".to_string(),
+                "must not be empty".to_string(),
+            ));
+        }
+        if let Err(e) = parse_interval(&self.exporter.interval) {
+            problems.push(("exporter.interval".to_string(), e));
+        }
+        problems
+    }
+}
+
+/// Like octa-warden: `--db` makes the config file optional. Also returns
+/// the file read, to reload it from.
+fn load_config(
+    path: Option<&str>,
+    db_path: Option<&str>,
+) -> Result<(FileConfig, Option<PathBuf>), ConfigError> {
+    let found = octa_config::discover(path.map(Path::new)).filter(|p| p.exists());
+    let mut config: FileConfig = match (&found, db_path) {
+        (Some(found), _) => return Ok((read_config(found, db_path)?, Some(found.clone()))),
+        (None, Some(_)) => octa_config::from_env()?,
+        (None, None) => {
+            return Err(match path {
+                Some(path) => ConfigError::Read {
+                    path: path.into(),
+                    source: std::io::ErrorKind::NotFound.into(),
+                },
+                None => ConfigError::NotFound,
+            })
+        }
+    };
+    if let Some(db_path) = db_path {
+        config.database.path = db_path.to_string();
+    }
+    octa_config::check(Path::new("<defaults>"), config).map(|config| (config, None))
+}
+
+/// The file at `path`, with `--db` applied; at start and on every reload.
+fn read_config(path: &Path, db_path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    let mut config: FileConfig = octa_config::read(path)?;
+    if let Some(db_path) = db_path {
+        config.database.path = db_path.to_string();
+    }
+    octa_config::check(path, config)
+}
+
+/// The octa-exporter command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa exporter` the arguments it was given.
+pub fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, Level::INFO, false, args.once);
+
+    let (config, found) = match load_config(args.config.as_deref(), args.db_path.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let interval = args
+        .interval
+        .as_deref()
+        .unwrap_or(&config.exporter.interval);
+    let mut interval = match parse_interval(interval) {
+        Ok(interval) => interval,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %format!("--interval: {}", e), "Invalid arguments");
+            return Kind::Usage.exit_code();
+        }
+    };
+    let db_path = config.database.path.as_str();
+    if !Path::new(db_path).exists() {
+        error!(tag = "FATAL", path = %db_path, "Database file not found");
+        return Kind::NoInput.exit_code();
+    }
+    let opts = OpenOptions {
+        busy_timeout: Duration::from_millis(args.busy_timeout),
+        immutable: false,
+    };
+
+    let state = Arc::new(Mutex::new(State::default()));
+    collect_into(&state, db_path, &opts);
+
+    if args.once {
+        let state = state.lock().unwrap_or_else(|e| e.into_inner());
+        print!("{}", state.render());
+        return if state.success {
+            ExitCode::SUCCESS
+        } else {
+            Kind::Io.exit_code()
+        };
+    }
+
+    let listen = args.listen.as_deref().unwrap_or(&config.exporter.listen);
+    let served = Arc::clone(&state);
+    let render = move || {
+        served
+            .lock()
+            .map(|state| state.render())
+            .unwrap_or_default()
+    };
+    if let Err(e) = metrics::serve(listen, render) {
+        error!(tag = "FATAL", addr = %listen, reason = %e, "Could not listen");
+        return Kind::Unavailable.exit_code();
+    }
+    let mut textfile = args.textfile.clone().or(config.exporter.textfile.clone());
+    // exporter.interval and exporter.textfile follow the file, unless given
+    // as flags; the listener and the database are fixed at start.
+    let reload = found.map(|path| {
+        let db_path = args.db_path.clone();
+        octa_config::watch(&path, move |path| read_config(path, db_path.as_deref()))
+    });
+    info!(
+        tag = "OK",
+        db = %db_path,
+        metrics = %format!("http://{}/metrics", listen),
+        interval = ?interval,
+        "Exporter started"
+    );
+
+    loop {
+        if let Some(path) = &textfile {
+            if let Ok(state) = state.lock() {
+                metrics::write_textfile(path, &state.render());
+            }
+        }
+        let Some(reload) = &reload else {
+            thread::sleep(interval);
+            collect_into(&state, db_path, &opts);
+            continue;
+        };
+
+        // Sleep until the next sample; a new interval counts from the last one.
+        let last = Instant::now();
+        while let Some(left) = (last + interval).checked_duration_since(Instant::now()) {
+            match reload.wait(left) {
+                None => {}
+                Some(Ok(config)) => {
+                    if args.interval.is_none() {
+                        // Checked by validate().
+                        interval = parse_interval(&config.exporter.interval).unwrap_or(interval);
+                    }
+                    if args.textfile.is_none() {
+                        textfile = config.exporter.textfile;
+                    }
+                    let moved = args.listen.is_none() && config.exporter.listen != listen;
+                    if moved || config.database.path != db_path {
+                        warn!(tag = "RELOAD", "exporter.listen and database.path apply after a restart");
+                    }
+                    info!(tag = "RELOAD", interval = ?interval, "Configuration reloaded");
+                }
+                Some(Err(e)) => {
+                    warn!(tag = "RELOAD", reason = %e, "Config change rejected, keeping the running config")
+                }
+            }
+        }
+        collect_into(&state, db_path, &opts);
+    }
+}
+
+/// Takes a sample and records it; a failure is logged and leaves the last
+/// good sample in place.
+fn collect_into(state: &Mutex<State>, db_path: &str, opts: &OpenOptions) {
+    let started = Instant::now();
+    let result = collect::sample(db_path, opts);
+    if let Err(e) = &result {
+        warn!(tag = "WARN", path = %db_path, reason = %e, "Collection failed");
+    }
+    if let Ok(mut state) = state.lock() {
+        state.record(result, started.elapsed());
+    }
+}
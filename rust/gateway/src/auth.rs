@@ -0,0 +1,290 @@
+//! Checks AWS Signature Version 4 `Authorization` headers, and decodes the
+//! `aws-chunked` bodies some S3 clients send with them.
+
+use crate::xml::S3Error;
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use chrono::{NaiveDateTime, Utc};
+use octa_warden_core::export::sha256_hex;
+use octa_warden_core::sigv4;
+use percent_encoding::percent_decode_str;
+
+/// Requests signed further from the gateway's clock than this are refused,
+/// as S3 does.
+const MAX_SKEW_SECS: i64 = 15 * 60;
+
+/// The one key pair the gateway accepts.
+pub struct Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub region: String,
+}
+
+/// The parts of an `AWS4-HMAC-SHA256` header.
+struct Authorization<'a> {
+    access_key_id: &'a str,
+    date: &'a str,
+    region: &'a str,
+    signed_headers: Vec<&'a str>,
+    signature: &'a str,
+}
+
+/// What [`verify`] accepted, for [`decode_chunked`] to check the chunks against.
+pub struct Signed {
+    amz_date: String,
+    signature: String,
+    payload_hash: String,
+}
+
+/// The streaming payload whose chunks carry chained signatures.
+const STREAMING_SIGNED: &str = "STREAMING-AWS4-HMAC-SHA256-PAYLOAD";
+
+/// The streaming payload with unsigned chunks, like `UNSIGNED-PAYLOAD`.
+const STREAMING_UNSIGNED: &str = "STREAMING-UNSIGNED-PAYLOAD-TRAILER";
+
+/// Checks the signature over the request, and the body against the payload
+/// hash when one was signed. Streaming bodies are checked chunk by chunk
+/// in [`decode_chunked`]; streaming kinds it cannot check are refused here.
+pub fn verify(
+    credentials: &Credentials,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<Signed, S3Error> {
+    let value = header(headers, "authorization").ok_or_else(|| {
+        if uri.query().is_some_and(|q| q.contains("X-Amz-Signature=")) {
+            S3Error::not_implemented("Presigned URLs")
+        } else {
+            S3Error::access_denied("Requests must be signed (AWS Signature Version 4).")
+        }
+    })?;
+    let auth = parse(&value)?;
+    if auth.access_key_id != credentials.access_key_id {
+        return Err(S3Error::new(
+            StatusCode::FORBIDDEN,
+            "InvalidAccessKeyId",
+            "The AWS access key Id you provided does not exist in our records.",
+        ));
+    }
+    if auth.region != credentials.region {
+        return Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "AuthorizationHeaderMalformed",
+            format!(
+                "the region '{}' is wrong; expecting '{}'",
+                auth.region, credentials.region
+            ),
+        ));
+    }
+
+    let amz_date = header(headers, "x-amz-date")
+        .ok_or_else(|| S3Error::access_denied("Missing x-amz-date header."))?;
+    let signed_at = NaiveDateTime::parse_from_str(&amz_date, "%Y%m%dT%H%M%SZ")
+        .map_err(|_| S3Error::access_denied("Invalid x-amz-date header."))?
+        .and_utc();
+    if (Utc::now() - signed_at).num_seconds().abs() > MAX_SKEW_SECS {
+        return Err(S3Error::new(
+            StatusCode::FORBIDDEN,
+            "RequestTimeTooSkewed",
+            "The difference between the request time and the current time is too large.",
+        ));
+    }
+    if !amz_date.starts_with(auth.date) {
+        return Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "AuthorizationHeaderMalformed",
+            "the credential date does not match x-amz-date",
+        ));
+    }
+
+    let payload_hash = header(headers, "x-amz-content-sha256").unwrap_or_else(|| sha256_hex(body));
+    if payload_hash.starts_with("STREAMING-")
+        && payload_hash != STREAMING_SIGNED
+        && payload_hash != STREAMING_UNSIGNED
+    {
+        return Err(S3Error::not_implemented(&format!(
+            "The '{}' payload",
+            payload_hash
+        )));
+    }
+    if is_hash(&payload_hash) && !payload_hash.eq_ignore_ascii_case(&sha256_hex(body)) {
+        return Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "XAmzContentSHA256Mismatch",
+            "The provided 'x-amz-content-sha256' header does not match what was computed.",
+        ));
+    }
+
+    let values: Vec<String> = auth
+        .signed_headers
+        .iter()
+        .map(|name| header(headers, name).unwrap_or_default())
+        .collect();
+    let signed: Vec<(&str, &str)> = auth
+        .signed_headers
+        .iter()
+        .copied()
+        .zip(values.iter().map(String::as_str))
+        .collect();
+    let path = percent_decode_str(uri.path()).decode_utf8_lossy();
+    let canonical = sigv4::canonical_request(
+        method.as_str(),
+        &sigv4::encode_path(&path),
+        &sigv4::canonical_query(&query_pairs(uri.query().unwrap_or_default())),
+        &signed,
+        &payload_hash,
+    );
+    let expected = sigv4::signature(
+        &credentials.secret_access_key,
+        auth.region,
+        &amz_date,
+        &canonical,
+    );
+    if !constant_time_eq(expected.as_bytes(), auth.signature.as_bytes()) {
+        return Err(S3Error::new(
+            StatusCode::FORBIDDEN,
+            "SignatureDoesNotMatch",
+            "The request signature we calculated does not match the signature you provided.",
+        ));
+    }
+    Ok(Signed {
+        amz_date,
+        signature: expected,
+        payload_hash,
+    })
+}
+
+fn parse(value: &str) -> Result<Authorization<'_>, S3Error> {
+    let malformed = || {
+        S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "AuthorizationHeaderMalformed",
+            "The authorization header is malformed.",
+        )
+    };
+    let rest = value
+        .strip_prefix("AWS4-HMAC-SHA256 ")
+        .ok_or_else(|| S3Error::access_denied("Only AWS4-HMAC-SHA256 signatures are supported."))?;
+    let (mut credential, mut signed_headers, mut signature) = (None, None, None);
+    for part in rest.split(',') {
+        match part.trim().split_once('=') {
+            Some(("Credential", v)) => credential = Some(v),
+            Some(("SignedHeaders", v)) => signed_headers = Some(v),
+            Some(("Signature", v)) => signature = Some(v),
+            _ => {}
+        }
+    }
+    let credential: Vec<&str> = credential.ok_or_else(malformed)?.split('/').collect();
+    let [access_key_id, date, region, "s3", "aws4_request"] = credential[..] else {
+        return Err(malformed());
+    };
+    Ok(Authorization {
+        access_key_id,
+        date,
+        region,
+        signed_headers: signed_headers.ok_or_else(malformed)?.split(';').collect(),
+        signature: signature.ok_or_else(malformed)?,
+    })
+}
+
+/// A header's values joined with `,`, inner whitespace runs collapsed, as
+/// canonical headers carry them.
+fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    let values: Vec<String> = headers
+        .get_all(name)
+        .iter()
+        .map(|v| {
+            String::from_utf8_lossy(v.as_bytes())
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+    (!values.is_empty()).then(|| values.join(","))
+}
+
+/// Decoded `name=value` pairs of a raw query string.
+pub fn query_pairs(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let decode = |s: &str| percent_decode_str(s).decode_utf8_lossy().into_owned();
+            (decode(name), decode(value))
+        })
+        .collect()
+}
+
+fn is_hash(value: &str) -> bool {
+    value.len() == 64 && value.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// The object bytes of an `aws-chunked` body: `<hex size>[;chunk-signature=...]\r\n<data>\r\n`
+/// until a zero-size chunk, then optional trailers. Under
+/// `STREAMING-AWS4-HMAC-SHA256-PAYLOAD` every chunk, the last included,
+/// must be signed over the one before it, starting from the request's
+/// signature.
+pub fn decode_chunked(
+    body: &[u8],
+    credentials: &Credentials,
+    signed: &Signed,
+) -> Result<Vec<u8>, S3Error> {
+    let invalid = || S3Error::invalid_argument("Malformed aws-chunked body.");
+    let check = signed.payload_hash == STREAMING_SIGNED;
+    let mut previous = signed.signature.clone();
+    let mut data = Vec::with_capacity(body.len());
+    let mut rest = body;
+    loop {
+        let end = rest
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(invalid)?;
+        let line = std::str::from_utf8(&rest[..end]).map_err(|_| invalid())?;
+        let (size, extensions) = line.split_once(';').unwrap_or((line, ""));
+        let size = usize::from_str_radix(size.trim(), 16).map_err(|_| invalid())?;
+        rest = &rest[end + 2..];
+        let end = size.checked_add(2).ok_or_else(invalid)?;
+        let chunk = match size {
+            0 => &[][..],
+            _ if rest.len() < end || &rest[size..end] != b"\r\n" => return Err(invalid()),
+            _ => &rest[..size],
+        };
+        if check {
+            let signature = extensions
+                .split(';')
+                .find_map(|e| e.trim().strip_prefix("chunk-signature="))
+                .ok_or_else(invalid)?;
+            let expected = sigv4::chunk_signature(
+                &credentials.secret_access_key,
+                &credentials.region,
+                &signed.amz_date,
+                &previous,
+                chunk,
+            );
+            if !constant_time_eq(expected.as_bytes(), signature.as_bytes()) {
+                return Err(S3Error::new(
+                    StatusCode::FORBIDDEN,
+                    "SignatureDoesNotMatch",
+                    "The chunk signature we calculated does not match the signature you provided.",
+                ));
+            }
+            previous = expected;
+        }
+        if size == 0 {
+            return Ok(data);
+        }
+        data.extend_from_slice(chunk);
+        rest = &rest[end..];
+    }
+}
+
+/// Whether the body is `aws-chunked`.
+pub fn is_chunked(headers: &HeaderMap) -> bool {
+    header(headers, "x-amz-content-sha256").is_some_and(|h| h.starts_with("STREAMING-"))
+        || header(headers, "content-encoding").is_some_and(|h| h.contains("aws-chunked"))
+}
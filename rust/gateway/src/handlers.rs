@@ -0,0 +1,445 @@
+use crate::auth::{self, Credentials};
+use crate::xml::{self, Listing, Object, S3Error, Version};
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use octa_client::{Client, Error, Mode, UploadOptions};
+use percent_encoding::percent_decode_str;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Query parameters of a bucket listing; anything else on a bucket is a
+/// subresource the gateway does not have.
+const LIST_PARAMS: &[&str] = &[
+    "list-type",
+    "prefix",
+    "delimiter",
+    "max-keys",
+    "continuation-token",
+    "start-after",
+    "marker",
+    "encoding-type",
+    "fetch-owner",
+];
+
+/// Longest listing page, as S3 caps `max-keys`.
+const MAX_KEYS: usize = 1000;
+
+/// Object name extensions, stripped to get the key. The first one per
+/// format is what listings show.
+const EXTENSIONS: &[(&str, &str)] = &[("png", ".png"), ("jpeg", ".jpg"), ("jpeg", ".jpeg")];
+
+pub struct Gateway {
+    pub client: Client,
+    pub credentials: Credentials,
+    pub bucket: String,
+    /// Shown as the bucket's creation date.
+    pub started: DateTime<Utc>,
+}
+
+pub type Shared = Arc<Gateway>;
+
+/// Every request: check the signature, then route on the path. Only
+/// path-style addressing (`/<bucket>/<name>`) is understood.
+pub async fn handle(
+    State(gateway): State<Shared>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    match dispatch(&gateway, &method, &uri, &headers, body).await {
+        Ok(response) => response,
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn dispatch(
+    gateway: &Gateway,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Result<Response, S3Error> {
+    let signed = auth::verify(&gateway.credentials, method, uri, headers, &body)?;
+    let query = auth::query_pairs(uri.query().unwrap_or_default());
+    let path = percent_decode_str(uri.path()).decode_utf8_lossy();
+    let path = path.trim_start_matches('/');
+
+    let (bucket, name) = path.split_once('/').unwrap_or((path, ""));
+    if bucket.is_empty() {
+        return match *method {
+            Method::GET => Ok(xml::document(xml::buckets(
+                &gateway.bucket,
+                &iso8601(gateway.started),
+            ))),
+            _ => Err(S3Error::not_implemented("This operation")),
+        };
+    }
+    if bucket != gateway.bucket {
+        return Err(S3Error::no_such_bucket());
+    }
+
+    if name.is_empty() {
+        return bucket_request(gateway, method, &query).await;
+    }
+    // Newer AWS SDKs name the operation in `x-id`; it carries nothing else.
+    if let Some((param, _)) = query.iter().find(|(k, _)| k != "x-id") {
+        return Err(S3Error::not_implemented(&format!(
+            "The '{}' subresource",
+            param
+        )));
+    }
+    match *method {
+        Method::PUT => {
+            if headers.contains_key("x-amz-copy-source") {
+                return Err(S3Error::not_implemented("CopyObject"));
+            }
+            let body = if auth::is_chunked(headers) {
+                Bytes::from(auth::decode_chunked(&body, &gateway.credentials, &signed)?)
+            } else {
+                body
+            };
+            put_object(gateway, name, headers, body).await
+        }
+        Method::GET => get_object(gateway, name, headers, true).await,
+        Method::HEAD => get_object(gateway, name, headers, false).await,
+        Method::DELETE => delete_object(gateway, name).await,
+        _ => Err(S3Error::not_implemented("This operation")),
+    }
+}
+
+async fn bucket_request(
+    gateway: &Gateway,
+    method: &Method,
+    query: &[(String, String)],
+) -> Result<Response, S3Error> {
+    match *method {
+        Method::HEAD => Ok(StatusCode::OK.into_response()),
+        Method::PUT => Err(S3Error::new(
+            StatusCode::CONFLICT,
+            "BucketAlreadyOwnedByYou",
+            "The bucket exists and is the gateway's only one.",
+        )),
+        Method::GET if param(query, "location").is_some() => {
+            Ok(xml::document(xml::location(&gateway.credentials.region)))
+        }
+        Method::GET => {
+            if let Some((param, _)) = query
+                .iter()
+                .find(|(k, _)| !LIST_PARAMS.contains(&k.as_str()))
+            {
+                return Err(S3Error::not_implemented(&format!(
+                    "The '{}' subresource",
+                    param
+                )));
+            }
+            list_objects(gateway, query).await
+        }
+        _ => Err(S3Error::not_implemented("This operation")),
+    }
+}
+
+/// PutObject: the body is stored as is (`mode=original`) under the key.
+async fn put_object(
+    gateway: &Gateway,
+    name: &str,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Result<Response, S3Error> {
+    let key = key(name)?;
+    let md5 = md5::compute(&body);
+    if let Some(expected) = headers.get("content-md5") {
+        let given = base64::engine::general_purpose::STANDARD
+            .decode(expected.as_bytes())
+            .map_err(|_| {
+                S3Error::new(
+                    StatusCode::BAD_REQUEST,
+                    "InvalidDigest",
+                    "The Content-MD5 you specified was invalid.",
+                )
+            })?;
+        if given != md5.0 {
+            return Err(S3Error::new(
+                StatusCode::BAD_REQUEST,
+                "BadDigest",
+                "The Content-MD5 you specified did not match what we received.",
+            ));
+        }
+    }
+
+    gateway
+        .client
+        .upload_avatar(
+            &key,
+            body,
+            UploadOptions::new().mode(Mode::Original).file_name(name),
+        )
+        .await
+        .map_err(|e| upstream("PutObject", &key, e))?;
+    Ok((StatusCode::OK, [(header::ETAG, format!("\"{:x}\"", md5))]).into_response())
+}
+
+/// GetObject and HeadObject. The extension of `name` is not checked against
+/// the stored format.
+async fn get_object(
+    gateway: &Gateway,
+    name: &str,
+    headers: &HeaderMap,
+    with_body: bool,
+) -> Result<Response, S3Error> {
+    let key = key(name)?;
+    // The image route answers unknown keys with a generated avatar; stat
+    // is what tells a stored key apart.
+    let asset = gateway
+        .client
+        .stat(&key)
+        .await
+        .map_err(|e| upstream("HeadObject", &key, e))?;
+    let data = gateway
+        .client
+        .get_avatar(&key)
+        .await
+        .map_err(|e| upstream("GetObject", &key, e))?;
+
+    let etag = format!("\"{:x}\"", md5::compute(&data));
+    let mut response = if headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| {
+            v.split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        }) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else if with_body {
+        data.into_response()
+    } else {
+        let mut response = StatusCode::OK.into_response();
+        response
+            .headers_mut()
+            .insert(header::CONTENT_LENGTH, HeaderValue::from(data.len()));
+        response
+    };
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(mime(&asset.format)),
+    );
+    headers.insert(header::ETAG, header_value(&etag));
+    if let Some(modified) = timestamp(&asset.updated_at) {
+        let date = modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        headers.insert(header::LAST_MODIFIED, header_value(&date));
+    }
+    Ok(response)
+}
+
+/// DeleteObject. Removes the whole asset behind the key, aliases included;
+/// S3 answers 204 whether or not the object existed.
+async fn delete_object(gateway: &Gateway, name: &str) -> Result<Response, S3Error> {
+    let key = key(name)?;
+    match gateway.client.delete(&key).await {
+        Ok(_) | Err(Error::NotFound { .. }) => Ok(StatusCode::NO_CONTENT.into_response()),
+        Err(e) => Err(upstream("DeleteObject", &key, e)),
+    }
+}
+
+/// ListObjectsV2 (`list-type=2`) and ListObjects. Names are listed in key
+/// order; `start-after` and `marker` are read as keys. Continuation tokens
+/// are the key the next page starts after.
+async fn list_objects(gateway: &Gateway, query: &[(String, String)]) -> Result<Response, S3Error> {
+    let param = |name: &str| param(query, name);
+    let v2 = param("list-type") == Some("2");
+    let prefix = param("prefix").unwrap_or_default();
+    let delimiter = param("delimiter").filter(|d| !d.is_empty());
+    let max_keys = match param("max-keys") {
+        Some(raw) => raw
+            .parse::<usize>()
+            .map_err(|_| {
+                S3Error::invalid_argument(
+                    "Provided max-keys not an integer or within integer range",
+                )
+            })?
+            .min(MAX_KEYS),
+        None => MAX_KEYS,
+    };
+    let url_encoded = match param("encoding-type") {
+        None => false,
+        Some("url") => true,
+        Some(_) => {
+            return Err(S3Error::invalid_argument(
+                "Invalid Encoding Method specified in Request",
+            ))
+        }
+    };
+    let token = param("continuation-token").filter(|_| v2);
+    let start = if v2 {
+        token.or(param("start-after"))
+    } else {
+        param("marker")
+    };
+
+    // Keys have no dots, so the part of the prefix before one is the key
+    // prefix; names are filtered on the whole of it.
+    let key_prefix = prefix.split('.').next().unwrap_or_default();
+    let mut cursor = start.map(|s| s.split('.').next().unwrap_or_default().to_string());
+    let mut objects = Vec::new();
+    let mut common_prefixes: Vec<String> = Vec::new();
+    let mut next = None;
+    'pages: loop {
+        let page = gateway
+            .client
+            .list(key_prefix, cursor.as_deref(), MAX_KEYS as u32)
+            .await
+            .map_err(|e| upstream("ListObjects", prefix, e))?;
+        for item in &page.items {
+            if objects.len() + common_prefixes.len() == max_keys {
+                next = cursor;
+                break 'pages;
+            }
+            cursor = Some(item.key.clone());
+            let name = object_name(&item.key, &item.format);
+            if !name.starts_with(prefix) {
+                continue;
+            }
+            let rolled = delimiter.and_then(|d| {
+                name[prefix.len()..]
+                    .find(d)
+                    .map(|i| name[..prefix.len() + i + d.len()].to_string())
+            });
+            if let Some(common) = rolled {
+                if common_prefixes.last() != Some(&common) {
+                    common_prefixes.push(common.clone());
+                }
+                // Every key under a dot-free common prefix sorts before
+                // it + '~' ('~' is above every key character): skip them.
+                if !common.contains('.') {
+                    cursor = Some(format!("{}~", common));
+                    continue 'pages;
+                }
+                continue;
+            }
+            objects.push(Object {
+                name,
+                last_modified: timestamp(&item.updated_at).map(iso8601).unwrap_or_default(),
+                size: item.size,
+            });
+        }
+        if page.next.is_none() {
+            break;
+        }
+    }
+
+    let listing = Listing {
+        bucket: &gateway.bucket,
+        prefix,
+        delimiter,
+        max_keys,
+        objects,
+        common_prefixes,
+        next,
+        url_encoded,
+    };
+    let version = if v2 {
+        Version::V2 {
+            continuation_token: token,
+            start_after: param("start-after"),
+        }
+    } else {
+        Version::V1 {
+            marker: param("marker").unwrap_or_default(),
+        }
+    };
+    Ok(xml::document(listing.render(version)))
+}
+
+fn param<'a>(query: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    query
+        .iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// The key behind an object name: the name without its image extension.
+/// Names are not lowercased, so that what a tool lists is what it wrote.
+fn key(name: &str) -> Result<String, S3Error> {
+    let lower = name.to_ascii_lowercase();
+    let key = EXTENSIONS
+        .iter()
+        .find(|(_, ext)| lower.ends_with(ext))
+        .map_or(name, |(_, ext)| &name[..name.len() - ext.len()]);
+    octa_key::validate(key)
+        .map_err(|e| S3Error::invalid_argument(format!("Object name '{}': {}", name, e)))?;
+    Ok(key.to_string())
+}
+
+/// The name a key is listed under.
+fn object_name(key: &str, format: &str) -> String {
+    match EXTENSIONS.iter().find(|(f, _)| *f == format) {
+        Some((_, ext)) => format!("{}{}", key, ext),
+        None => key.to_string(),
+    }
+}
+
+fn mime(format: &str) -> &'static str {
+    match format {
+        "jpeg" | "jpg" => "image/jpeg",
+        "png" => "image/png",
+        _ => "application/octet-stream",
+    }
+}
+
+/// The server's timestamps are RFC 3339; the Go server's may use a space
+/// for the `T`.
+fn timestamp(raw: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(&raw.replacen(' ', "T", 1))
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// As S3 lists times: UTC with milliseconds.
+fn iso8601(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+fn header_value(value: &str) -> HeaderValue {
+    HeaderValue::from_str(value).unwrap_or_else(|_| HeaderValue::from_static(""))
+}
+
+/// The S3 error for a failed call to the Octa instance.
+fn upstream(operation: &str, key: &str, e: Error) -> S3Error {
+    match e {
+        Error::NotFound { .. } => S3Error::no_such_key(),
+        Error::RateLimited { .. } => S3Error::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "SlowDown",
+            "Please reduce your request rate.",
+        ),
+        Error::Api { status: 415, .. } => {
+            S3Error::invalid_argument("Only PNG and JPEG images can be stored.")
+        }
+        Error::Api { status: 413, .. } => S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "EntityTooLarge",
+            "Your proposed upload exceeds the maximum allowed size.",
+        ),
+        Error::Api {
+            status: 400,
+            message,
+            ..
+        } => S3Error::invalid_argument(message),
+        e => {
+            warn!(tag = "UPSTREAM", operation, key, reason = %e, "Octa request failed");
+            match e {
+                Error::Http(_) => S3Error::new(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "ServiceUnavailable",
+                    "The Octa instance behind the gateway is unreachable.",
+                ),
+                _ => S3Error::internal("The Octa instance behind the gateway failed the request."),
+            }
+        }
+    }
+}
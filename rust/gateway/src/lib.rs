@@ -0,0 +1,312 @@
+use auth::Credentials;
+use axum::extract::{DefaultBodyLimit, Request};
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::Router;
+use clap::Parser;
+use handlers::Gateway;
+use octa_client::Client;
+use octa_config::{ConfigError, SecurityConfig, ServerConfig, Validate};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use octa_warden_core::schedule::parse_interval;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, error, info, Level};
+
+mod auth;
+mod handlers;
+mod xml;
+
+/*
+OCTA-GATEWAY: S3-compatible API in front of an Octa instance
+=============================================
+Mission: Let tools that already speak S3 (backup agents, static-site
+         builders, rclone, the AWS CLI) read, write, list and delete
+         avatars in one bucket, through Octa's own upload API.
+Safety:  Every request is checked against one SigV4 key pair; nothing is
+         anonymous. Images are stored as uploaded (mode=original), so what
+         is read back is what was written. Deleting a key deletes the asset
+         behind it, aliases included.
+*/
+
+/// Largest request body read; the Octa instance enforces its own, usually
+/// smaller, upload limit.
+const MAX_BODY: usize = 32 * 1024 * 1024;
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Serve an S3-compatible API backed by an Octa instance"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Address to serve the S3 API on (overrides gateway.listen)
+    #[arg(long, env = "OCTA_GATEWAY_LISTEN")]
+    listen: Option<String>,
+
+    /// Octa instance to store objects in (overrides gateway.upstream, else base_url)
+    #[arg(long, env = "OCTA_GATEWAY_UPSTREAM")]
+    upstream: Option<String>,
+
+    /// Access key id S3 clients sign with (overrides gateway.access_key_id)
+    #[arg(long, env = "OCTA_GATEWAY_ACCESS_KEY_ID")]
+    access_key_id: Option<String>,
+
+    /// Secret access key S3 clients sign with (overrides gateway.secret_access_key)
+    #[arg(long, env = "OCTA_GATEWAY_SECRET_ACCESS_KEY", hide_env_values = true)]
+    secret_access_key: Option<String>,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+
+    /// Minimum level to log; `debug` adds one line per request
+    #[arg(long, default_value_t = Level::INFO)]
+    log_level: Level,
+}
+
+/// The parts of config.yaml octa-gateway reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    server: ServerConfig,
+    base_url: Option<String>,
+    security: SecurityConfig,
+    gateway: GatewayConfig,
+}
+
+/// `gateway:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct GatewayConfig {
+    listen: String,
+    upstream: Option<String>,
+    bucket: String,
+    region: String,
+    access_key_id: String,
+    secret_access_key: String,
+    timeout: String,
+}
+
+impl Default for GatewayConfig {
+    fn default() -> Self {
+        Self {
+            listen: "0.0.0.0:9970".to_string(),
+            upstream: None,
+            bucket: "avatars".to_string(),
+            region: "us-east-1".to_string(),
+            access_key_id: String::new(),
+            secret_access_key: String::new(),
+            timeout: "30s".to_string(),
+        }
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = self.server.validate();
+        let gateway = &self.gateway;
+        problems.extend(octa_config::check_url("base_url", self.base_url.as_deref()));
+        problems.extend(octa_config::check_url(
+            "gateway.upstream",
+            gateway.upstream.as_deref(),
+        ));
+        if self.security.upload_secret.trim().is_empty() {
+            problems.push((
+                "security.upload_secret".to_string(),
+                "is required to write to the instance".to_string(),
+            ));
+        }
+        if gateway.listen.parse::<SocketAddr>().is_err() {
+            problems.push((
+                "gateway.listen".to_string(),
+                format!("'{}' is not an address like 0.0.0.0:9970", gateway.listen),
+            ));
+        }
+        if !valid_bucket(&gateway.bucket) {
+            problems.push((
+                "gateway.bucket".to_string(),
+                format!(
+                    "'{}' is not a bucket name (3-63 lowercase letters, digits, '-' and '.')",
+                    gateway.bucket
+                ),
+            ));
+        }
+        if gateway.region.trim().is_empty() {
+            problems.push((
+                "gateway.region".to_string(),
+                "must not be empty".to_string(),
+            ));
+        }
+        for (field, value) in [
+            ("gateway.access_key_id", &gateway.access_key_id),
+            ("gateway.secret_access_key", &gateway.secret_access_key),
+        ] {
+            if value.trim().is_empty() {
+                problems.push((field.to_string(), "is required".to_string()));
+            }
+        }
+        if let Err(e) = parse_interval(&gateway.timeout) {
+            problems.push(("gateway.timeout".to_string(), e));
+        }
+        problems
+    }
+}
+
+fn valid_bucket(name: &str) -> bool {
+    (3..=63).contains(&name.len())
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'.')
+        && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name.ends_with(|c: char| c.is_ascii_alphanumeric())
+}
+
+/// Without a file, the environment alone can configure the gateway (e.g. in
+/// a container). Arguments are applied before the config is checked.
+fn load_config(args: &Args) -> Result<FileConfig, ConfigError> {
+    let found = octa_config::discover(args.config.as_deref().map(Path::new));
+    let mut config: FileConfig = match &found {
+        Some(found) => octa_config::read(found)?,
+        None => octa_config::from_env()?,
+    };
+    let gateway = &mut config.gateway;
+    if let Some(listen) = &args.listen {
+        gateway.listen = listen.clone();
+    }
+    if let Some(upstream) = &args.upstream {
+        gateway.upstream = Some(upstream.clone());
+    }
+    if let Some(id) = &args.access_key_id {
+        gateway.access_key_id = id.clone();
+    }
+    if let Some(secret) = &args.secret_access_key {
+        gateway.secret_access_key = secret.clone();
+    }
+    let origin = found.unwrap_or_else(|| "<environment>".into());
+    octa_config::check(&origin, config)
+}
+
+/// The octa-gateway command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa gateway` the arguments it was given.
+#[tokio::main]
+pub async fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, args.log_level, false, false);
+
+    let config = match load_config(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let gateway = config.gateway;
+    let upstream = octa_config::base_url(
+        gateway.upstream.as_deref().or(config.base_url.as_deref()),
+        &config.server,
+    );
+    let timeout = parse_interval(&gateway.timeout).unwrap_or_default();
+    let client = match Client::builder(&upstream)
+        .secret(config.security.upload_secret)
+        .timeout(timeout)
+        .user_agent(format!("octa-gateway/{}", env!("CARGO_PKG_VERSION")))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Could not create the Octa client");
+            return e.kind().exit_code();
+        }
+    };
+
+    let bucket = gateway.bucket.clone();
+    let state = Arc::new(Gateway {
+        client,
+        credentials: Credentials {
+            access_key_id: gateway.access_key_id,
+            secret_access_key: gateway.secret_access_key,
+            region: gateway.region,
+        },
+        bucket: gateway.bucket,
+        started: chrono::Utc::now(),
+    });
+    let app = Router::new()
+        .fallback(handlers::handle)
+        .layer(DefaultBodyLimit::max(MAX_BODY))
+        .layer(middleware::from_fn(log_request))
+        .with_state(state);
+
+    let listener = match tokio::net::TcpListener::bind(&gateway.listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(tag = "FATAL", addr = %gateway.listen, reason = %e, "Could not bind");
+            return Kind::Unavailable.exit_code();
+        }
+    };
+    info!(
+        tag = "OK",
+        addr = %gateway.listen,
+        bucket = %bucket,
+        upstream = %upstream,
+        "Gateway listening"
+    );
+
+    if let Err(e) = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown())
+        .await
+    {
+        error!(tag = "FATAL", reason = %e, "Gateway stopped");
+        return Kind::Unavailable.exit_code();
+    }
+    info!(tag = "OK", "Shut down");
+    ExitCode::SUCCESS
+}
+
+async fn log_request(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    debug!(
+        tag = "HTTP",
+        method = %method,
+        path = %path,
+        status = response.status().as_u16(),
+        ms = started.elapsed().as_millis() as u64,
+        "Request"
+    );
+    response
+}
+
+/// Resolves on Ctrl-C or SIGTERM.
+async fn shutdown() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
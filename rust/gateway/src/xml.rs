@@ -0,0 +1,243 @@
+//! S3 response bodies and errors, written by hand: the gateway only ever
+//! produces a handful of fixed shapes.
+
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use std::fmt::Write;
+
+const DECLARATION: &str = r#"<?xml version="1.0" encoding="UTF-8"?>"#;
+const NAMESPACE: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+
+/// An S3 error response: `<Error><Code>` with the status S3 uses for it.
+#[derive(Debug)]
+pub struct S3Error {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl S3Error {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn access_denied(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "AccessDenied", message)
+    }
+
+    pub fn invalid_argument(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "InvalidArgument", message)
+    }
+
+    pub fn no_such_key() -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "NoSuchKey",
+            "The specified key does not exist.",
+        )
+    }
+
+    pub fn no_such_bucket() -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "NoSuchBucket",
+            "The specified bucket does not exist.",
+        )
+    }
+
+    pub fn not_implemented(what: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_IMPLEMENTED,
+            "NotImplemented",
+            format!("{} is not supported by the Octa gateway.", what),
+        )
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "InternalError", message)
+    }
+}
+
+impl IntoResponse for S3Error {
+    fn into_response(self) -> Response {
+        let body = format!(
+            "{}<Error><Code>{}</Code><Message>{}</Message></Error>",
+            DECLARATION,
+            self.code,
+            escape(&self.message)
+        );
+        (
+            self.status,
+            [(header::CONTENT_TYPE, "application/xml")],
+            body,
+        )
+            .into_response()
+    }
+}
+
+/// A 200 with an XML document.
+pub fn document(body: String) -> Response {
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/xml")],
+        format!("{}{}", DECLARATION, body),
+    )
+        .into_response()
+}
+
+/// `ListAllMyBucketsResult` with the one bucket.
+pub fn buckets(bucket: &str, created: &str) -> String {
+    format!(
+        r#"<ListAllMyBucketsResult xmlns="{}"><Owner><ID>octa</ID><DisplayName>octa</DisplayName></Owner><Buckets><Bucket><Name>{}</Name><CreationDate>{}</CreationDate></Bucket></Buckets></ListAllMyBucketsResult>"#,
+        NAMESPACE,
+        escape(bucket),
+        created
+    )
+}
+
+/// `LocationConstraint`; empty for us-east-1, as S3 answers.
+pub fn location(region: &str) -> String {
+    let region = if region == "us-east-1" { "" } else { region };
+    format!(
+        r#"<LocationConstraint xmlns="{}">{}</LocationConstraint>"#,
+        NAMESPACE,
+        escape(region)
+    )
+}
+
+/// One object of a listing.
+pub struct Object {
+    pub name: String,
+    /// ISO 8601 with milliseconds, as S3 lists them.
+    pub last_modified: String,
+    pub size: u64,
+}
+
+/// One page of a listing, shaped for either ListObjects version.
+pub struct Listing<'a> {
+    pub bucket: &'a str,
+    pub prefix: &'a str,
+    pub delimiter: Option<&'a str>,
+    pub max_keys: usize,
+    pub objects: Vec<Object>,
+    pub common_prefixes: Vec<String>,
+    /// Where the next page starts, when there is one.
+    pub next: Option<String>,
+    /// `encoding-type=url`: names are percent-encoded.
+    pub url_encoded: bool,
+}
+
+/// What the request named, echoed per version.
+pub enum Version<'a> {
+    V1 {
+        marker: &'a str,
+    },
+    V2 {
+        continuation_token: Option<&'a str>,
+        start_after: Option<&'a str>,
+    },
+}
+
+impl Listing<'_> {
+    pub fn render(&self, version: Version) -> String {
+        let name = |value: &str| {
+            if self.url_encoded {
+                octa_warden_core::sigv4::encode_path(value)
+            } else {
+                escape(value)
+            }
+        };
+        let mut xml = format!(
+            r#"<ListBucketResult xmlns="{}"><Name>{}</Name><Prefix>{}</Prefix>"#,
+            NAMESPACE,
+            escape(self.bucket),
+            name(self.prefix)
+        );
+        match &version {
+            Version::V1 { marker } => {
+                let _ = write!(xml, "<Marker>{}</Marker>", name(marker));
+                // NextMarker is only sent with a delimiter; without one the
+                // last key listed is the marker.
+                if let (Some(next), Some(_)) = (&self.next, self.delimiter) {
+                    let _ = write!(xml, "<NextMarker>{}</NextMarker>", name(next));
+                }
+            }
+            Version::V2 {
+                continuation_token,
+                start_after,
+            } => {
+                let _ = write!(
+                    xml,
+                    "<KeyCount>{}</KeyCount>",
+                    self.objects.len() + self.common_prefixes.len()
+                );
+                if let Some(token) = continuation_token {
+                    let _ = write!(
+                        xml,
+                        "<ContinuationToken>{}</ContinuationToken>",
+                        escape(token)
+                    );
+                }
+                if let Some(next) = &self.next {
+                    let _ = write!(
+                        xml,
+                        "<NextContinuationToken>{}</NextContinuationToken>",
+                        escape(next)
+                    );
+                }
+                if let Some(start_after) = start_after {
+                    let _ = write!(xml, "<StartAfter>{}</StartAfter>", name(start_after));
+                }
+            }
+        }
+        if let Some(delimiter) = self.delimiter {
+            let _ = write!(xml, "<Delimiter>{}</Delimiter>", name(delimiter));
+        }
+        if self.url_encoded {
+            xml.push_str("<EncodingType>url</EncodingType>");
+        }
+        let _ = write!(
+            xml,
+            "<MaxKeys>{}</MaxKeys><IsTruncated>{}</IsTruncated>",
+            self.max_keys,
+            self.next.is_some()
+        );
+        for object in &self.objects {
+            let _ = write!(
+                xml,
+                "<Contents><Key>{}</Key><LastModified>{}</LastModified><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+                name(&object.name),
+                object.last_modified,
+                object.size
+            );
+        }
+        for prefix in &self.common_prefixes {
+            let _ = write!(
+                xml,
+                "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+                name(prefix)
+            );
+        }
+        xml.push_str("</ListBucketResult>");
+        xml
+    }
+}
+
+pub fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,181 @@
+use octa_store::cas;
+use octa_warden_core::retention::{self, RetentionRule};
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, Result};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+/// Why an asset is garbage. An asset that qualifies several ways is listed
+/// under the first, in this order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Reason {
+    /// No key maps to it; nothing can serve it.
+    Orphan,
+    /// None of its keys is in any application's ownership records.
+    Unowned,
+    /// Every key is past its TTL rule.
+    Expired,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Reason::Orphan => "orphan",
+            Reason::Unowned => "unowned",
+            Reason::Expired => "expired",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Garbage {
+    pub id: String,
+    pub keys: Vec<String>,
+    pub bytes: u64,
+    pub reason: Reason,
+    /// TTL pattern, for expired assets.
+    pub rule: Option<String>,
+    /// `updated_at` as scanned. The delete is skipped when it changed, so a
+    /// re-upload between scan and delete is never lost.
+    updated_at: Value,
+}
+
+struct Asset {
+    bytes: u64,
+    updated_at: Value,
+    keys: Vec<String>,
+}
+
+/// Lists garbage, orphans first, then by id. `owned` is `None` when no
+/// ownership records are configured, which skips the unowned check.
+pub fn find(
+    conn: &Connection,
+    owned: Option<&HashSet<String>>,
+    ttl: &[RetentionRule],
+) -> Result<Vec<Garbage>, String> {
+    let mut assets: HashMap<String, Asset> = HashMap::new();
+    {
+        let mut stmt = conn
+            .prepare("SELECT id, COALESCE(size, LENGTH(data), 0), updated_at FROM images")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, Value>(2)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+        for row in rows {
+            let (id, bytes, updated_at) = row.map_err(|e| e.to_string())?;
+            assets.insert(
+                id,
+                Asset {
+                    bytes: bytes.max(0) as u64,
+                    updated_at,
+                    keys: Vec::new(),
+                },
+            );
+        }
+
+        let mut stmt = conn
+            .prepare("SELECT image_id, key FROM key_mappings ORDER BY key")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(|e| e.to_string())?;
+        for row in rows {
+            let (id, key) = row.map_err(|e| e.to_string())?;
+            if let Some(asset) = assets.get_mut(&id) {
+                asset.keys.push(key);
+            }
+        }
+    }
+
+    let expired: HashMap<String, String> = if ttl.is_empty() {
+        HashMap::new()
+    } else {
+        retention::find_expired(conn, ttl)?
+            .into_iter()
+            .map(|e| (e.id, e.rule))
+            .collect()
+    };
+
+    let mut garbage: Vec<Garbage> = assets
+        .into_iter()
+        .filter_map(|(id, asset)| {
+            let (reason, rule) = if asset.keys.is_empty() {
+                (Reason::Orphan, None)
+            } else if owned.is_some_and(|owned| !asset.keys.iter().any(|k| owned.contains(k))) {
+                (Reason::Unowned, None)
+            } else if let Some(rule) = expired.get(&id) {
+                (Reason::Expired, Some(rule.clone()))
+            } else {
+                return None;
+            };
+            Some(Garbage {
+                id,
+                keys: asset.keys,
+                bytes: asset.bytes,
+                reason,
+                rule,
+                updated_at: asset.updated_at,
+            })
+        })
+        .collect();
+    garbage.sort_by(|a, b| a.reason.cmp(&b.reason).then_with(|| a.id.cmp(&b.id)));
+    Ok(garbage)
+}
+
+/// What [`delete`] did.
+#[derive(Debug, Default)]
+pub struct Deleted {
+    pub assets: u64,
+    pub bytes: u64,
+    /// Changed since the scan (re-uploaded, or given a key) and left alone.
+    pub skipped: u64,
+}
+
+/// Deletes `garbage` in transactions of `batch` assets, sleeping `pause`
+/// between them so the server's writes keep getting through.
+pub fn delete(
+    conn: &mut Connection,
+    garbage: &[Garbage],
+    batch: usize,
+    pause: Duration,
+) -> Result<Deleted> {
+    let mut done = Deleted::default();
+    for (i, chunk) in garbage.chunks(batch.max(1)).enumerate() {
+        if i > 0 && !pause.is_zero() {
+            thread::sleep(pause);
+        }
+        let tx = conn.transaction()?;
+        for asset in chunk {
+            let blob = cas::blob_of(&tx, &asset.id)?;
+            // Orphans must still be orphans; everything else must be unchanged.
+            let removed = tx.execute(
+                "DELETE FROM images WHERE id = ?1 AND updated_at IS ?2
+                 AND (?3 = 0 OR NOT EXISTS (SELECT 1 FROM key_mappings WHERE image_id = ?1))",
+                params![asset.id, asset.updated_at, asset.reason == Reason::Orphan],
+            )?;
+            if removed == 0 {
+                done.skipped += 1;
+                continue;
+            }
+            tx.execute("DELETE FROM key_mappings WHERE image_id = ?1", [&asset.id])?;
+            // A content-addressed asset's bytes go with its last reference.
+            if let Some(hash) = blob {
+                cas::release(&tx, &hash)?;
+            }
+            done.assets += 1;
+            done.bytes += asset.bytes;
+        }
+        tx.commit()?;
+    }
+    Ok(done)
+}
@@ -0,0 +1,343 @@
+use clap::Parser;
+use octa_config::{ConfigError, DatabaseConfig, Validate};
+use octa_errors::{Error, Kind};
+use octa_logging::LogFormat;
+use octa_warden_core::db::{self, OpenOptions};
+use octa_warden_core::growth::format_bytes;
+use octa_warden_core::retention::RetentionRule;
+use octa_warden_core::schedule::parse_interval;
+use rusqlite::Connection;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+use tracing::{error, info, warn, Level};
+
+mod collect;
+mod owners;
+
+use collect::Reason;
+use owners::OwnerSource;
+
+/*
+OCTA-GC: Garbage collection for the Octa database
+=============================================
+Mission: Find assets nothing can reach or anyone still wants (orphans,
+         unowned by every application, past their TTL) and delete them.
+Safety:  Dry-run unless --execute. Deletes run in small batches with a pause
+         in between, and skip any asset that changed since the scan.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Garbage collection for unreferenced and expired Octa assets"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// SQLite database to collect (overrides database.path; the config file becomes optional)
+    #[arg(long = "db", env = "OCTA_DB_PATH", value_name = "PATH")]
+    db_path: Option<String>,
+
+    /// Milliseconds to wait on a locked database before giving up (SQLITE_BUSY)
+    #[arg(long, default_value_t = 5000)]
+    busy_timeout: u64,
+
+    /// Actually delete (default: list what would be deleted)
+    #[arg(long)]
+    execute: bool,
+
+    /// More ownership records: a file with one live key per line (repeatable)
+    #[arg(long = "owners-file", value_name = "PATH")]
+    owners_files: Vec<String>,
+
+    /// Assets per delete transaction (overrides gc.batch_size)
+    #[arg(long)]
+    batch_size: Option<usize>,
+
+    /// Milliseconds to wait between batches (overrides gc.pause_ms)
+    #[arg(long)]
+    pause_ms: Option<u64>,
+
+    /// Delete at most this many assets in this run
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// Run VACUUM afterwards so the file shrinks (locks the database while it runs)
+    #[arg(long, requires = "execute")]
+    vacuum: bool,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+}
+
+/// The parts of config.yaml octa-gc reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    database: DatabaseConfig,
+    gc: GcConfig,
+    warden: WardenRetention,
+}
+
+/// `gc:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct GcConfig {
+    /// Where applications list the keys they still use. Without any, only
+    /// orphans and expired assets are collected.
+    owners: Vec<OwnerSource>,
+    /// TTL rules, as `warden.retention` (which is used when this is unset).
+    ttl: Option<Vec<RetentionRule>>,
+    batch_size: usize,
+    pause_ms: u64,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            owners: Vec::new(),
+            ttl: None,
+            batch_size: 200,
+            pause_ms: 100,
+        }
+    }
+}
+
+/// Only `warden.retention`; the rest of the section belongs to octa-warden.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct WardenRetention {
+    retention: Vec<RetentionRule>,
+}
+
+impl FileConfig {
+    fn ttl(&self) -> &[RetentionRule] {
+        self.gc.ttl.as_deref().unwrap_or(&self.warden.retention)
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        if self.database.path.trim().is_empty() {
+            problems.push(("database.path".to_string(), "is required".to_string()));
+        }
+        if self.gc.batch_size == 0 {
+            problems.push((
+                "gc.batch_size".to_string(),
+                "must be at least 1".to_string(),
+            ));
+        }
+        let field = if self.gc.ttl.is_some() {
+            "gc.ttl"
+        } else {
+            "warden.retention"
+        };
+        for (i, rule) in self.ttl().iter().enumerate() {
+            if let Err(e) = parse_interval(&rule.max_age) {
+                problems.push((format!("{}[{}].max_age", field, i), e));
+            }
+        }
+        for (i, owner) in self.gc.owners.iter().enumerate() {
+            if let OwnerSource::Sqlite { query, .. } = owner {
+                if query.trim().is_empty() {
+                    problems.push((format!("gc.owners[{}].query", i), "is required".to_string()));
+                }
+            }
+        }
+        problems
+    }
+}
+
+/// Like octa-warden: `--db` makes the config file optional.
+fn load_config(path: Option<&str>, db_path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    let found = octa_config::discover(path.map(Path::new)).filter(|p| p.exists());
+    let mut config: FileConfig = match (&found, db_path) {
+        (Some(found), _) => octa_config::read(found)?,
+        (None, Some(_)) => octa_config::from_env()?,
+        (None, None) => {
+            return Err(match path {
+                Some(path) => ConfigError::Read {
+                    path: path.into(),
+                    source: std::io::ErrorKind::NotFound.into(),
+                },
+                None => ConfigError::NotFound,
+            })
+        }
+    };
+    if let Some(db_path) = db_path {
+        config.database.path = db_path.to_string();
+    }
+    octa_config::check(found.as_deref().unwrap_or(Path::new("<defaults>")), config)
+}
+
+/// The octa-gc command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa gc` the arguments it was given.
+pub fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, Level::INFO, false, false);
+
+    let config = match load_config(args.config.as_deref(), args.db_path.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    if !Path::new(&config.database.path).exists() {
+        error!(tag = "FATAL", path = %config.database.path, "Database file not found");
+        return Kind::NoInput.exit_code();
+    }
+    match run(&args, &config) {
+        Ok(code) => code,
+        Err(e) => {
+            error!(tag = "FATAL", path = %config.database.path, reason = %e, "Garbage collection failed");
+            e.exit_code()
+        }
+    }
+}
+
+fn run(args: &Args, config: &FileConfig) -> Result<ExitCode, Error> {
+    let db_path = config.database.path.as_str();
+    let opts = OpenOptions {
+        busy_timeout: Duration::from_millis(args.busy_timeout),
+        immutable: false,
+    };
+
+    let mut sources = config.gc.owners.clone();
+    sources.extend(
+        args.owners_files
+            .iter()
+            .map(|file| OwnerSource::File { file: file.clone() }),
+    );
+    let owned = if sources.is_empty() {
+        info!(
+            tag = "→",
+            "No ownership records configured (gc.owners); unowned assets are not collected"
+        );
+        None
+    } else {
+        Some(owners::load(&sources)?)
+    };
+    if config.ttl().is_empty() {
+        info!(
+            tag = "→",
+            "No TTL rules configured (gc.ttl or warden.retention); nothing expires"
+        );
+    }
+
+    let mut conn = if args.execute {
+        db::open_read_write(db_path, &opts)
+    } else {
+        db::open_read_only(db_path, &opts)
+    }?;
+
+    let mut garbage =
+        collect::find(&conn, owned.as_ref(), config.ttl()).map_err(|e| Error::new(Kind::Io, e))?;
+    if let Some(limit) = args.limit {
+        garbage.truncate(limit);
+    }
+
+    let verb = if args.execute {
+        "Deleting"
+    } else {
+        "Would delete"
+    };
+    let mut totals: BTreeMap<Reason, (u64, u64)> = BTreeMap::new();
+    for asset in &garbage {
+        info!(
+            tag = "GC",
+            id = %asset.id,
+            reason = %asset.reason,
+            keys = %asset.keys.join(","),
+            bytes = asset.bytes,
+            rule = asset.rule.as_deref(),
+            "{}",
+            verb
+        );
+        let total = totals.entry(asset.reason).or_default();
+        total.0 += 1;
+        total.1 += asset.bytes;
+    }
+    for (reason, (assets, bytes)) in &totals {
+        info!(tag = "SUMMARY", reason = %reason, assets, bytes, size = %format_bytes(*bytes as f64), "Garbage");
+    }
+    let bytes: u64 = garbage.iter().map(|a| a.bytes).sum();
+
+    if !args.execute {
+        info!(
+            tag = "OK",
+            assets = garbage.len(),
+            bytes,
+            size = %format_bytes(bytes as f64),
+            "Dry run: nothing deleted. Re-run with --execute to remove these assets"
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let before = file_stats(&conn)?;
+    let deleted = collect::delete(
+        &mut conn,
+        &garbage,
+        args.batch_size.unwrap_or(config.gc.batch_size),
+        Duration::from_millis(args.pause_ms.unwrap_or(config.gc.pause_ms)),
+    )?;
+    if deleted.skipped > 0 {
+        warn!(
+            tag = "SKIP",
+            assets = deleted.skipped,
+            "Changed since the scan and left alone"
+        );
+    }
+    info!(
+        tag = "OK",
+        assets = deleted.assets,
+        bytes = deleted.bytes,
+        size = %format_bytes(deleted.bytes as f64),
+        "Garbage deleted"
+    );
+
+    if args.vacuum {
+        info!(tag = "→", "Running VACUUM");
+        conn.execute_batch("VACUUM")?;
+        let after = file_stats(&conn)?;
+        info!(
+            tag = "OK",
+            reclaimed = %format_bytes(before.file.saturating_sub(after.file) as f64),
+            file = %format_bytes(after.file as f64),
+            "Database file compacted"
+        );
+    } else {
+        let after = file_stats(&conn)?;
+        info!(
+            tag = "OK",
+            free = %format_bytes(after.free as f64),
+            file = %format_bytes(after.file as f64),
+            "Freed pages are reused by new uploads; run with --vacuum to shrink the file"
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+struct FileStats {
+    /// Bytes in the main database file.
+    file: u64,
+    /// Bytes on the freelist, reusable without growing the file.
+    free: u64,
+}
+
+fn file_stats(conn: &Connection) -> rusqlite::Result<FileStats> {
+    let pragma = |name: &str| conn.pragma_query_value(None, name, |row| row.get::<_, i64>(0));
+    let page_size = pragma("page_size")?.max(0) as u64;
+    Ok(FileStats {
+        file: pragma("page_count")?.max(0) as u64 * page_size,
+        free: pragma("freelist_count")?.max(0) as u64 * page_size,
+    })
+}
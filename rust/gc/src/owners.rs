@@ -0,0 +1,77 @@
+use octa_errors::{Error, Kind};
+use rusqlite::{Connection, OpenFlags};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+
+/// One `gc.owners` entry: where an application lists the keys it still uses.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum OwnerSource {
+    /// A text file with one key per line; blank lines and `#` comments are skipped.
+    File { file: String },
+    /// A query against the application's own SQLite database; the first
+    /// column of every row is a key. NULLs are skipped.
+    Sqlite { sqlite: String, query: String },
+}
+
+impl OwnerSource {
+    pub fn describe(&self) -> String {
+        match self {
+            OwnerSource::File { file } => file.clone(),
+            OwnerSource::Sqlite { sqlite, .. } => format!("sqlite:{}", sqlite),
+        }
+    }
+
+    /// The source's keys, normalized the way the server stores them.
+    pub fn keys(&self) -> Result<Vec<String>, String> {
+        let raw = match self {
+            OwnerSource::File { file } => fs::read_to_string(file)
+                .map_err(|e| format!("{}: {}", file, e))?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string)
+                .collect::<Vec<_>>(),
+            OwnerSource::Sqlite { sqlite, query } => {
+                // Read-only: the application's database is never written.
+                let conn = Connection::open_with_flags(
+                    sqlite,
+                    OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+                )
+                .map_err(|e| format!("{}: {}", sqlite, e))?;
+                let mut stmt = conn
+                    .prepare(query)
+                    .map_err(|e| format!("{}: {}", sqlite, e))?;
+                let rows = stmt
+                    .query_map([], |row| row.get::<_, Option<String>>(0))
+                    .map_err(|e| format!("{}: {}", sqlite, e))?;
+                rows.filter_map(|row| row.transpose())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format!("{}: {}", sqlite, e))?
+            }
+        };
+        Ok(raw.iter().map(|k| octa_key::normalize(k)).collect())
+    }
+}
+
+/// Every key the sources list. A source that cannot be read fails the whole
+/// load: a partial list would mark live assets as unowned.
+pub fn load(sources: &[OwnerSource]) -> Result<HashSet<String>, Error> {
+    let mut owned = HashSet::new();
+    for source in sources {
+        let keys = source.keys().map_err(|e| Error::new(Kind::Io, e))?;
+        if keys.is_empty() {
+            return Err(Error::new(
+                Kind::Data,
+                format!(
+                    "{} lists no keys; refusing to treat every asset as unowned",
+                    source.describe()
+                ),
+            ));
+        }
+        tracing::info!(tag = "OWNERS", source = %source.describe(), keys = keys.len(), "Ownership records loaded");
+        owned.extend(keys);
+    }
+    Ok(owned)
+}
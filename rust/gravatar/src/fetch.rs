@@ -0,0 +1,149 @@
+use image::ImageFormat;
+use std::io::Cursor;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// Longest wait honoured from a `Retry-After`.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// What the service has for a hash.
+pub enum Fetched {
+    Found(Vec<u8>),
+    /// No avatar: the service answered 404 instead of its default image.
+    Missing,
+}
+
+/// A Gravatar-compatible avatar service, called at most `rate` times per
+/// second across all tasks.
+pub struct Service {
+    http: reqwest::Client,
+    url: String,
+    size: u32,
+    retries: u32,
+    limiter: Limiter,
+}
+
+impl Service {
+    pub fn new(
+        url: &str,
+        size: u32,
+        rate: f64,
+        retries: u32,
+        timeout: Duration,
+    ) -> Result<Self, String> {
+        let http = reqwest::Client::builder()
+            .timeout(timeout)
+            .user_agent(concat!("octa-gravatar/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            http,
+            url: url.trim_end_matches('/').to_string(),
+            size,
+            retries,
+            limiter: Limiter::new(rate),
+        })
+    }
+
+    /// The avatar of `hash`, retrying rate limits, 5xx and connection
+    /// failures up to `retries` times.
+    pub async fn fetch(&self, hash: &str) -> Result<Fetched, String> {
+        // d=404 asks for a 404 instead of a generated default.
+        let url = format!("{}/{}?s={}&d=404", self.url, hash, self.size);
+        let mut attempt = 0;
+        loop {
+            self.limiter.wait().await;
+            let (reason, retry_after) = match self.http.get(&url).send().await {
+                Ok(response) if response.status().is_success() => {
+                    let body = response.bytes().await.map_err(|e| e.to_string())?;
+                    return Ok(Fetched::Found(body.to_vec()));
+                }
+                Ok(response) if response.status() == reqwest::StatusCode::NOT_FOUND => {
+                    return Ok(Fetched::Missing);
+                }
+                Ok(response)
+                    if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS
+                        || response.status().is_server_error() =>
+                {
+                    let retry_after = response
+                        .headers()
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.trim().parse::<u64>().ok())
+                        .map(Duration::from_secs);
+                    (response.status().to_string(), retry_after)
+                }
+                Ok(response) => return Err(format!("service answered {}", response.status())),
+                Err(e) if e.is_timeout() || e.is_connect() => (e.to_string(), None),
+                Err(e) => return Err(e.to_string()),
+            };
+            if attempt == self.retries {
+                return Err(format!("{} (after {} retries)", reason, attempt));
+            }
+            attempt += 1;
+            let wait = retry_after
+                .unwrap_or(Duration::from_secs(1 << attempt.min(6)))
+                .min(MAX_RETRY_AFTER);
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Spaces calls evenly: each takes the next free slot.
+struct Limiter {
+    period: Duration,
+    next: Mutex<Instant>,
+}
+
+impl Limiter {
+    /// `rate` calls per second; 0 leaves them unlimited.
+    fn new(rate: f64) -> Self {
+        let period = if rate > 0.0 {
+            Duration::from_secs_f64(1.0 / rate)
+        } else {
+            Duration::ZERO
+        };
+        Self {
+            period,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    async fn wait(&self) {
+        if self.period.is_zero() {
+            return;
+        }
+        let slot = {
+            let mut next = self.next.lock().await;
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.period;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+/// Checks a fetched avatar the way the servers will, returning what to
+/// upload: JPEG and PNG as fetched, GIF and WebP (which the servers refuse)
+/// converted to PNG.
+pub fn prepare(data: Vec<u8>) -> Result<Vec<u8>, String> {
+    if data.len() as u64 > octa_image::DEFAULT_MAX_UPLOAD {
+        return Err(octa_image::Error::TooLarge(octa_image::DEFAULT_MAX_UPLOAD).to_string());
+    }
+    let format = octa_image::sniff(&data);
+    let decoded = match format {
+        Some(ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Gif | ImageFormat::WebP) => {
+            image::load_from_memory(&data).map_err(|e| format!("not a valid image: {}", e))?
+        }
+        _ => return Err(octa_image::Error::Unsupported.to_string()),
+    };
+    if octa_image::is_allowed(&data) {
+        return Ok(data);
+    }
+    let mut png = Vec::new();
+    decoded
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| format!("could not convert to PNG: {}", e))?;
+    Ok(png)
+}
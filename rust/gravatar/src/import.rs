@@ -0,0 +1,217 @@
+use crate::fetch::{self, Fetched, Service};
+use futures::stream::{self, StreamExt};
+use octa_client::{Action, Client, Error, UploadOptions};
+use octa_errors::Kind;
+use octa_image::image::ImageFormat;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::io::Read;
+use std::process::ExitCode;
+use tracing::{info, warn};
+
+/// How emails are hashed for the service. Hashes given in the list are
+/// used as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashKind {
+    /// Understood by every Gravatar-compatible service.
+    Md5,
+    Sha256,
+}
+
+/// What the import was asked to do.
+pub struct Run<'a> {
+    pub client: &'a Client,
+    pub service: &'a Service,
+    pub upload: &'a UploadOptions,
+    pub force: bool,
+    pub dry_run: bool,
+    pub concurrency: usize,
+}
+
+/// One user of the list.
+pub struct Record {
+    pub hash: String,
+    pub key: String,
+}
+
+enum Outcome {
+    Created,
+    Replaced,
+    /// Has an image in Octa already.
+    Kept,
+    /// No avatar at the service.
+    Missing,
+    /// Dry run: fetched and valid, would have been uploaded.
+    Planned,
+}
+
+/// Imports every record, at most `concurrency` at a time.
+pub async fn run(run: &Run<'_>, records: Vec<Record>) -> ExitCode {
+    let total = records.len() as u64;
+    let step = (total / 10).max(1);
+    let (mut created, mut replaced, mut kept, mut missing, mut planned, mut failed) =
+        (0u64, 0, 0, 0, 0, 0);
+    // Why the first record failed, for the exit code.
+    let mut failure = None;
+    let mut outcomes = stream::iter(records)
+        .map(|record| async move {
+            let outcome = process(run, &record).await;
+            (record, outcome)
+        })
+        .buffer_unordered(run.concurrency.max(1));
+
+    let mut done = 0u64;
+    while let Some((record, outcome)) = outcomes.next().await {
+        done += 1;
+        match outcome {
+            Ok(Outcome::Created) => created += 1,
+            Ok(Outcome::Replaced) => replaced += 1,
+            Ok(Outcome::Kept) => kept += 1,
+            Ok(Outcome::Missing) => missing += 1,
+            Ok(Outcome::Planned) => {
+                planned += 1;
+                info!(tag = "PLAN", key = %record.key, hash = %record.hash, "Would upload");
+            }
+            Err((kind, e)) => {
+                failed += 1;
+                failure.get_or_insert(kind);
+                warn!(tag = "FAIL", key = %record.key, hash = %record.hash, reason = %e, "Not imported");
+            }
+        }
+        if done.is_multiple_of(step) || done == total {
+            info!(tag = "→", done, total, failed, "Progress");
+        }
+    }
+
+    info!(
+        tag = if failed == 0 { "OK" } else { "WARN" },
+        created, replaced, planned, kept, missing, failed, "Import finished"
+    );
+    failure.map_or(ExitCode::SUCCESS, Kind::exit_code)
+}
+
+/// Imports one record, or says why not.
+async fn process(run: &Run<'_>, record: &Record) -> Result<Outcome, (Kind, String)> {
+    let octa = |e: Error| (e.kind(), e.to_string());
+    // Checked first, so users already in Octa cost no call to the service.
+    let exists = match run.client.stat(&record.key).await {
+        Ok(_) => true,
+        Err(Error::NotFound { .. }) => false,
+        Err(e) => return Err(octa(e)),
+    };
+    if exists && !run.force {
+        return Ok(Outcome::Kept);
+    }
+    let fetched = run.service.fetch(&record.hash).await;
+    let data = match fetched.map_err(|e| (Kind::Unavailable, e))? {
+        Fetched::Found(data) => data,
+        Fetched::Missing => return Ok(Outcome::Missing),
+    };
+    let data = tokio::task::spawn_blocking(move || fetch::prepare(data))
+        .await
+        .map_err(|e| (Kind::Internal, e.to_string()))?
+        .map_err(|e| (Kind::Data, e))?;
+    if run.dry_run {
+        return Ok(Outcome::Planned);
+    }
+
+    let extension = match octa_image::sniff(&data) {
+        Some(ImageFormat::Jpeg) => "jpg",
+        _ => "png",
+    };
+    let upload = run.upload.clone().file_name(format!(
+        "{}.{}",
+        octa_key::file_name(&record.key),
+        extension
+    ));
+    let uploaded = run
+        .client
+        .upload_avatar(&record.key, data, upload)
+        .await
+        .map_err(octa)?;
+    Ok(match uploaded.action {
+        Action::Created => Outcome::Created,
+        Action::Updated => Outcome::Replaced,
+    })
+}
+
+/// Reads the list, refusing it whole if a line is invalid. Each line holds
+/// an email or a hash, then optionally the user's id, separated by
+/// whitespace or a comma; blank lines and `#` comments are skipped.
+/// `template` makes the key from `{id}` (the hash when the line has none)
+/// and `{hash}`. Repeated keys are read once.
+pub fn read_records(
+    path: &str,
+    template: &str,
+    kind: HashKind,
+) -> Result<Vec<Record>, octa_errors::Error> {
+    let mut text = String::new();
+    let read = if path == "-" {
+        std::io::stdin().read_to_string(&mut text).map(|_| ())
+    } else {
+        std::fs::read_to_string(path).map(|t| text = t)
+    };
+    read.map_err(|e| {
+        let kind = match e.kind() {
+            std::io::ErrorKind::NotFound => Kind::NoInput,
+            _ => Kind::Io,
+        };
+        octa_errors::Error::new(kind, format!("could not read {}: {}", path, e))
+    })?;
+    let invalid = |message: String| octa_errors::Error::new(Kind::Data, message);
+
+    let mut records = Vec::new();
+    let mut seen = HashSet::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|f| !f.is_empty());
+        let Some(user) = fields.next() else {
+            continue;
+        };
+        let id = fields.next();
+        let hash = hash(user, kind).ok_or_else(|| {
+            invalid(format!(
+                "{}:{}: '{}' is neither an email nor a hash",
+                path,
+                n + 1,
+                user
+            ))
+        })?;
+        let raw = template
+            .replace("{id}", id.unwrap_or(&hash))
+            .replace("{hash}", &hash);
+        let key = octa_key::parse(&raw)
+            .map_err(|e| invalid(format!("{}:{}: invalid key '{}': {}", path, n + 1, raw, e)))?;
+        if seen.insert(key.clone()) {
+            records.push(Record { hash, key });
+        }
+    }
+    Ok(records)
+}
+
+/// The service's hash of `user`: a given MD5 or SHA-256 hash as is, an
+/// email trimmed, lowercased and hashed.
+fn hash(user: &str, kind: HashKind) -> Option<String> {
+    let is_hex = user.chars().all(|c| c.is_ascii_hexdigit());
+    if is_hex && (user.len() == 32 || user.len() == 64) {
+        return Some(user.to_ascii_lowercase());
+    }
+    if !user.contains('@') {
+        return None;
+    }
+    let email = user.trim().to_lowercase();
+    Some(match kind {
+        HashKind::Md5 => format!("{:x}", md5::compute(email.as_bytes())),
+        HashKind::Sha256 => Sha256::digest(email.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect(),
+    })
+}
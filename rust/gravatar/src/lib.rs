@@ -0,0 +1,295 @@
+use clap::Parser;
+use fetch::Service;
+use import::HashKind;
+use octa_client::{Client, UploadOptions};
+use octa_config::{ConfigError, SecurityConfig, ServerConfig, Validate};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use serde::Deserialize;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+use tracing::{error, info, Level};
+
+mod fetch;
+mod import;
+
+/*
+OCTA-GRAVATAR: Import avatars from Gravatar and Libravatar
+=============================================
+Mission: Move an existing user base into Octa: for every email or hash of
+         a list, fetch the avatar from a Gravatar-compatible service, check
+         it, and upload it under gravatar.key (`users/{id}`).
+Safety:  The service is called at most gravatar.rate times per second, and
+         rate limits and 5xx are retried with backoff. Users who already
+         have an image are left alone unless --force is given; users
+         without an avatar at the service get nothing. --dry-run fetches
+         and checks, but uploads nothing.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Import avatars from Gravatar-compatible services into Octa"
+)]
+struct Args {
+    /// File with one user per line: an email or an MD5/SHA-256 hash, then optionally the user's id (`-` for stdin)
+    users: String,
+
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Instance to upload to (overrides base_url)
+    #[arg(long, env = "OCTA_GRAVATAR_URL")]
+    url: Option<String>,
+
+    /// Upload secret of the instance (default: security.upload_secret)
+    #[arg(long, env = "OCTA_GRAVATAR_SECRET", hide_env_values = true)]
+    secret: Option<String>,
+
+    /// gravatar, libravatar or the avatar URL of another compatible service (overrides gravatar.service)
+    #[arg(long)]
+    service: Option<String>,
+
+    /// Key of each user, from {id} and {hash} (overrides gravatar.key)
+    #[arg(long)]
+    key: Option<String>,
+
+    /// Replace the image of users who already have one
+    #[arg(long)]
+    force: bool,
+
+    /// Fetch and check the avatars, but upload nothing
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Users imported at once (overrides gravatar.concurrency)
+    #[arg(long)]
+    concurrency: Option<usize>,
+
+    /// Seconds before a request is abandoned
+    #[arg(long, default_value_t = 30)]
+    timeout: u64,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+}
+
+/// The parts of config.yaml octa-gravatar reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    server: ServerConfig,
+    security: SecurityConfig,
+    base_url: Option<String>,
+    gravatar: GravatarConfig,
+}
+
+/// `gravatar:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct GravatarConfig {
+    /// gravatar, libravatar, or an avatar URL the hash is appended to.
+    service: String,
+    /// How emails are hashed.
+    hash: HashKind,
+    /// Edge length asked of the service and stored in Octa.
+    size: u32,
+    /// Key of each user; `{id}` is the id of the list, else the hash.
+    key: String,
+    /// Calls to the service per second; 0 for no limit.
+    rate: f64,
+    /// Retries of a rate-limited or failed call.
+    retries: u32,
+    concurrency: usize,
+}
+
+impl Default for GravatarConfig {
+    fn default() -> Self {
+        Self {
+            service: "gravatar".to_string(),
+            hash: HashKind::Md5,
+            size: 256,
+            key: "users/{id}".to_string(),
+            rate: 5.0,
+            retries: 3,
+            concurrency: 4,
+        }
+    }
+}
+
+/// The avatar URL of a service name, or the URL itself.
+fn service_url(service: &str) -> Result<String, String> {
+    match service {
+        "gravatar" => Ok("https://gravatar.com/avatar".to_string()),
+        "libravatar" => Ok("https://seccdn.libravatar.org/avatar".to_string()),
+        url if url.starts_with("http://") || url.starts_with("https://") => Ok(url.to_string()),
+        other => Err(format!(
+            "'{}' is not gravatar, libravatar or an http(s) URL",
+            other
+        )),
+    }
+}
+
+fn check_key(template: &str) -> Option<String> {
+    (!template.contains("{id}") && !template.contains("{hash}")).then(|| {
+        format!(
+            "'{}' names every user alike; use {{id}} or {{hash}}",
+            template
+        )
+    })
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = self.server.validate();
+        let gravatar = &self.gravatar;
+        problems.extend(octa_config::check_url("base_url", self.base_url.as_deref()));
+        if let Err(e) = service_url(&gravatar.service) {
+            problems.push(("gravatar.service".to_string(), e));
+        }
+        if let Some(problem) = check_key(&gravatar.key) {
+            problems.push(("gravatar.key".to_string(), problem));
+        }
+        if !octa_image::SIZES.contains(&gravatar.size) {
+            problems.push((
+                "gravatar.size".to_string(),
+                format!(
+                    "{} is not within {}-{}",
+                    gravatar.size,
+                    octa_image::SIZES.start(),
+                    octa_image::SIZES.end()
+                ),
+            ));
+        }
+        if gravatar.rate.is_nan() || gravatar.rate < 0.0 {
+            problems.push((
+                "gravatar.rate".to_string(),
+                "must not be negative".to_string(),
+            ));
+        }
+        if gravatar.concurrency == 0 {
+            problems.push((
+                "gravatar.concurrency".to_string(),
+                "must be at least 1".to_string(),
+            ));
+        }
+        problems
+    }
+}
+
+/// Without a file, the environment and arguments alone configure it.
+fn load_config(path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    match octa_config::discover(path.map(Path::new)) {
+        Some(path) => octa_config::load(&path),
+        None => {
+            let config: FileConfig = octa_config::from_env()?;
+            octa_config::check(Path::new("<environment>"), config)
+        }
+    }
+}
+
+/// The octa-gravatar command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa gravatar` the arguments it was given.
+#[tokio::main]
+pub async fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, Level::INFO, false, false);
+
+    let config = match load_config(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let gravatar = &config.gravatar;
+
+    if let Some((field, problem)) = octa_config::check_url("--url", args.url.as_deref()) {
+        error!(tag = "FATAL", reason = %format!("{}: {}", field, problem), "Invalid arguments");
+        return Kind::Usage.exit_code();
+    }
+    let service = match service_url(args.service.as_deref().unwrap_or(&gravatar.service)) {
+        Ok(service) => service,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %format!("--service: {}", e), "Invalid arguments");
+            return Kind::Usage.exit_code();
+        }
+    };
+    let template = args.key.as_deref().unwrap_or(&gravatar.key);
+    if let Some(problem) = check_key(template) {
+        error!(tag = "FATAL", reason = %format!("--key: {}", problem), "Invalid arguments");
+        return Kind::Usage.exit_code();
+    }
+    let secret = args
+        .secret
+        .as_deref()
+        .unwrap_or(&config.security.upload_secret);
+    if secret.trim().is_empty() {
+        error!(
+            tag = "FATAL",
+            "No upload secret (set security.upload_secret or pass --secret)"
+        );
+        return Kind::Config.exit_code();
+    }
+
+    let records = match import::read_records(&args.users, template, gravatar.hash) {
+        Ok(records) => records,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid user list");
+            return e.exit_code();
+        }
+    };
+    let timeout = Duration::from_secs(args.timeout);
+    let base_url = octa_config::base_url(
+        args.url.as_deref().or(config.base_url.as_deref()),
+        &config.server,
+    );
+    let client = match Client::builder(&base_url)
+        .secret(secret)
+        .timeout(timeout)
+        .user_agent(concat!("octa-gravatar/", env!("CARGO_PKG_VERSION")))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Could not create the Octa client");
+            return e.kind().exit_code();
+        }
+    };
+    let service_client = match Service::new(
+        &service,
+        gravatar.size,
+        gravatar.rate,
+        gravatar.retries,
+        timeout,
+    ) {
+        Ok(service) => service,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Could not create the service client");
+            return Kind::Internal.exit_code();
+        }
+    };
+
+    info!(
+        tag = "→",
+        service = %service,
+        url = %base_url,
+        users = records.len(),
+        dry_run = args.dry_run,
+        "Importing avatars"
+    );
+    let upload = UploadOptions::new().size(gravatar.size);
+    let run = import::Run {
+        client: &client,
+        service: &service_client,
+        upload: &upload,
+        force: args.force,
+        dry_run: args.dry_run,
+        concurrency: args.concurrency.unwrap_or(gravatar.concurrency),
+    };
+    import::run(&run, records).await
+}
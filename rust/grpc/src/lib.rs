@@ -0,0 +1,279 @@
+//! The gRPC protocol of Octa (`octa.proto`), shared by octa-server and
+//! octa-client: the messages, the length-prefixed framing, status codes
+//! and the unary response body that ends in trailers.
+//!
+//! Only unary calls without compression are used, so this is the whole of
+//! the protocol the two need, without a gRPC framework.
+//!
+//! ```
+//! use octa_grpc::{decode, encode, pb};
+//!
+//! let request = pb::Target { key: "alice".to_string(), id: String::new() };
+//! let frame = encode(&request);
+//! assert_eq!(decode::<pb::Target>(&frame).unwrap(), request);
+//! ```
+
+pub mod pb;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use http::{HeaderMap, HeaderValue};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use std::convert::Infallible;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// The service of `octa.proto`; methods are served at `/<SERVICE>/<method>`.
+pub const SERVICE: &str = "octa.v1.Avatars";
+
+pub const CONTENT_TYPE: &str = "application/grpc";
+
+/// Metadata holding the upload secret, as `X-Secret-Key` over HTTP.
+pub const SECRET_HEADER: &str = "x-secret-key";
+
+/// Trailer with the HTTP API's error code (`request/invalid_parameters`).
+pub const CODE_TRAILER: &str = "octa-code";
+
+/// Trailer with the status the HTTP API answers the same error with.
+pub const HTTP_STATUS_TRAILER: &str = "octa-status";
+
+/// `grpc-message` is percent-encoded (gRPC over HTTP/2, "Responses").
+const MESSAGE: &AsciiSet = &CONTROLS.add(b'%');
+
+/// `/octa.v1.Avatars/<method>`.
+pub fn path(method: &str) -> String {
+    format!("/{}/{}", SERVICE, method)
+}
+
+/// `message` as one uncompressed frame: a zero flag byte, the length as
+/// four big-endian bytes, then the message.
+pub fn encode<M: prost::Message>(message: &M) -> Bytes {
+    let len = message.encoded_len();
+    let mut frame = BytesMut::with_capacity(5 + len);
+    frame.put_u8(0);
+    frame.put_u32(len as u32);
+    message
+        .encode(&mut frame)
+        .expect("the buffer holds the encoded length");
+    frame.freeze()
+}
+
+/// The message of a body holding exactly one frame.
+pub fn decode<M: prost::Message + Default>(body: &[u8]) -> Result<M, Status> {
+    let invalid = |why: &str| Status::new(Code::InvalidArgument, why);
+    let (&flag, rest) = body.split_first().ok_or_else(|| invalid("empty message"))?;
+    if flag != 0 {
+        return Err(Status::new(
+            Code::Unimplemented,
+            "compressed messages are not supported",
+        ));
+    }
+    let (len, message) = rest
+        .split_at_checked(4)
+        .ok_or_else(|| invalid("truncated message"))?;
+    let len = u32::from_be_bytes(len.try_into().expect("four bytes")) as usize;
+    if message.len() != len {
+        return Err(invalid("message length does not match its frame"));
+    }
+    M::decode(message).map_err(|e| invalid(&format!("invalid message: {}", e)))
+}
+
+/// gRPC status codes (the subset Octa answers with, plus `Unknown`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    Ok = 0,
+    Unknown = 2,
+    InvalidArgument = 3,
+    DeadlineExceeded = 4,
+    NotFound = 5,
+    PermissionDenied = 7,
+    ResourceExhausted = 8,
+    Unimplemented = 12,
+    Internal = 13,
+    Unavailable = 14,
+    Unauthenticated = 16,
+}
+
+impl Code {
+    pub fn from_i32(code: i32) -> Code {
+        match code {
+            0 => Code::Ok,
+            3 => Code::InvalidArgument,
+            4 => Code::DeadlineExceeded,
+            5 => Code::NotFound,
+            7 => Code::PermissionDenied,
+            8 => Code::ResourceExhausted,
+            12 => Code::Unimplemented,
+            13 => Code::Internal,
+            14 => Code::Unavailable,
+            16 => Code::Unauthenticated,
+            _ => Code::Unknown,
+        }
+    }
+
+    /// The code of an HTTP API error with this status.
+    pub fn from_http(status: u16) -> Code {
+        match status {
+            200..=299 => Code::Ok,
+            401 => Code::Unauthenticated,
+            403 => Code::PermissionDenied,
+            404 => Code::NotFound,
+            408 | 504 => Code::DeadlineExceeded,
+            429 => Code::ResourceExhausted,
+            400..=499 => Code::InvalidArgument,
+            503 => Code::Unavailable,
+            _ => Code::Internal,
+        }
+    }
+
+    /// The closest HTTP status, for errors without `octa-status`.
+    pub fn to_http(self) -> u16 {
+        match self {
+            Code::Ok => 200,
+            Code::InvalidArgument => 400,
+            Code::Unauthenticated => 401,
+            Code::PermissionDenied => 403,
+            Code::NotFound => 404,
+            Code::DeadlineExceeded => 504,
+            Code::ResourceExhausted => 429,
+            Code::Unimplemented => 501,
+            Code::Unavailable => 503,
+            Code::Unknown | Code::Internal => 500,
+        }
+    }
+}
+
+/// The outcome of a call that did not return its message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    pub code: Code,
+    pub message: String,
+    /// The HTTP API's error code and status for the same failure.
+    pub octa_code: Option<String>,
+    pub http_status: Option<u16>,
+}
+
+impl Status {
+    pub fn new(code: Code, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            octa_code: None,
+            http_status: None,
+        }
+    }
+
+    /// An error of the HTTP API, as the same error over gRPC.
+    pub fn from_api(http_status: u16, octa_code: &str, message: impl Into<String>) -> Self {
+        Self {
+            code: Code::from_http(http_status),
+            message: message.into(),
+            octa_code: Some(octa_code.to_string()),
+            http_status: Some(http_status),
+        }
+    }
+
+    /// `grpc-status`, `grpc-message` and the `octa-*` trailers.
+    pub fn to_trailers(&self) -> HeaderMap {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from(self.code as i32));
+        let message = utf8_percent_encode(&self.message, MESSAGE).to_string();
+        if let Ok(message) = HeaderValue::from_str(&message) {
+            trailers.insert("grpc-message", message);
+        }
+        if let Some(code) = self.octa_code.as_deref() {
+            if let Ok(code) = HeaderValue::from_str(code) {
+                trailers.insert(CODE_TRAILER, code);
+            }
+        }
+        if let Some(status) = self.http_status {
+            trailers.insert(HTTP_STATUS_TRAILER, HeaderValue::from(status));
+        }
+        trailers
+    }
+
+    /// The outcome in trailers (or the headers of a trailers-only
+    /// response): `None` without `grpc-status`, `Some(Ok)` for status 0.
+    pub fn from_trailers(trailers: &HeaderMap) -> Option<Result<(), Status>> {
+        let text = |name: &str| trailers.get(name).and_then(|v| v.to_str().ok());
+        let code = Code::from_i32(text("grpc-status")?.trim().parse().unwrap_or(-1));
+        if code == Code::Ok {
+            return Some(Ok(()));
+        }
+        let message = text("grpc-message")
+            .map(|m| percent_decode_str(m).decode_utf8_lossy().into_owned())
+            .unwrap_or_default();
+        Some(Err(Status {
+            code,
+            message,
+            octa_code: text(CODE_TRAILER).map(str::to_string),
+            http_status: text(HTTP_STATUS_TRAILER).and_then(|s| s.parse().ok()),
+        }))
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for Status {}
+
+/// The body of a unary response: the message frame, if any, then the
+/// trailers.
+#[derive(Debug)]
+pub struct Unary {
+    data: Option<Bytes>,
+    trailers: Option<HeaderMap>,
+}
+
+impl http_body::Body for Unary {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<http_body::Frame<Bytes>, Infallible>>> {
+        let frame = match self.data.take() {
+            Some(data) => Some(http_body::Frame::data(data)),
+            None => self.trailers.take().map(http_body::Frame::trailers),
+        };
+        Poll::Ready(frame.map(Ok))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.data.is_none() && self.trailers.is_none()
+    }
+}
+
+/// The response to a unary call. A failure is answered trailers-only: its
+/// status in the headers and an empty body.
+pub fn response<M: prost::Message>(result: Result<M, Status>) -> http::Response<Unary> {
+    let (mut headers, body) = match result {
+        Ok(message) => {
+            let mut trailers = HeaderMap::new();
+            trailers.insert("grpc-status", HeaderValue::from(Code::Ok as i32));
+            let body = Unary {
+                data: Some(encode(&message)),
+                trailers: Some(trailers),
+            };
+            (HeaderMap::new(), body)
+        }
+        Err(status) => {
+            let body = Unary {
+                data: None,
+                trailers: None,
+            };
+            (status.to_trailers(), body)
+        }
+    };
+    headers.insert(
+        http::header::CONTENT_TYPE,
+        HeaderValue::from_static(CONTENT_TYPE),
+    );
+    let mut response = http::Response::new(body);
+    *response.headers_mut() = headers;
+    response
+}
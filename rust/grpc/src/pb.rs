@@ -0,0 +1,142 @@
+//! The messages of `octa.proto`, as prost-build would generate them; the
+//! tags are the wire format, so they change only with the file.
+
+use prost::bytes::Bytes;
+use std::collections::HashMap;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AvatarRequest {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(map = "string, string", tag = "2")]
+    pub params: HashMap<String, String>,
+    #[prost(bool, tag = "3")]
+    pub generated: bool,
+    #[prost(string, tag = "4")]
+    pub if_none_match: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Avatar {
+    #[prost(bytes = "bytes", tag = "1")]
+    pub data: Bytes,
+    #[prost(string, tag = "2")]
+    pub content_type: String,
+    #[prost(string, tag = "3")]
+    pub etag: String,
+    #[prost(string, tag = "4")]
+    pub cache_control: String,
+    #[prost(bool, tag = "5")]
+    pub not_modified: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UploadRequest {
+    #[prost(string, repeated, tag = "1")]
+    pub keys: Vec<String>,
+    #[prost(bytes = "bytes", tag = "2")]
+    pub image: Bytes,
+    #[prost(string, tag = "3")]
+    pub mode: String,
+    #[prost(uint32, tag = "4")]
+    pub size: u32,
+    #[prost(uint32, tag = "5")]
+    pub scale: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UploadReply {
+    #[prost(string, tag = "1")]
+    pub action: String,
+    #[prost(string, tag = "2")]
+    pub avatar_id: String,
+    #[prost(string, repeated, tag = "3")]
+    pub keys: Vec<String>,
+    #[prost(string, tag = "4")]
+    pub url: String,
+    #[prost(uint64, tag = "5")]
+    pub size_kb: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Target {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(string, tag = "2")]
+    pub id: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Asset {
+    #[prost(string, tag = "1")]
+    pub avatar_id: String,
+    #[prost(string, repeated, tag = "2")]
+    pub keys: Vec<String>,
+    #[prost(uint32, tag = "3")]
+    pub width: u32,
+    #[prost(uint32, tag = "4")]
+    pub height: u32,
+    #[prost(string, tag = "5")]
+    pub format: String,
+    #[prost(uint64, tag = "6")]
+    pub size: u64,
+    #[prost(string, tag = "7")]
+    pub created_at: String,
+    #[prost(string, tag = "8")]
+    pub updated_at: String,
+    #[prost(string, tag = "9")]
+    pub url: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DeleteReply {
+    #[prost(string, tag = "1")]
+    pub target: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListRequest {
+    #[prost(string, tag = "1")]
+    pub prefix: String,
+    #[prost(string, tag = "2")]
+    pub after: String,
+    #[prost(uint32, tag = "3")]
+    pub limit: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListItem {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(string, tag = "2")]
+    pub avatar_id: String,
+    #[prost(uint64, tag = "3")]
+    pub size: u64,
+    #[prost(string, tag = "4")]
+    pub format: String,
+    #[prost(string, tag = "5")]
+    pub updated_at: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListReply {
+    #[prost(message, repeated, tag = "1")]
+    pub items: Vec<ListItem>,
+    #[prost(string, tag = "2")]
+    pub next: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HealthRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HealthReply {
+    #[prost(string, tag = "1")]
+    pub status: String,
+    #[prost(uint64, tag = "2")]
+    pub assets: u64,
+    #[prost(uint64, tag = "3")]
+    pub bytes: u64,
+    #[prost(uint64, tag = "4")]
+    pub uptime_seconds: u64,
+}
@@ -0,0 +1,288 @@
+use axum::extract::{DefaultBodyLimit, Request};
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::routing::{get, post};
+use axum::Router;
+use clap::Parser;
+use octa_config::{ConfigError, Validate};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use octa_warden_core::growth::parse_bytes;
+use octa_warden_core::schedule::parse_interval;
+use serde::Deserialize;
+use sink::Sink;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Instant;
+use store::Store;
+use tracing::{debug, error, info, warn, Level};
+
+mod sink;
+mod store;
+
+/*
+OCTA-HOOKSINK: Webhook receiver for integration tests
+=============================================
+Mission: Stand in for the endpoint Octa posts its callbacks to (upload and
+         delete events, and the warden, quota and moderate notifications),
+         record every one of them to SQLite (and NDJSON), and answer the
+         test harness's assertions about what arrived, without a
+         requestbin-style external service.
+Safety:  A test fixture: listings and assertions are unauthenticated and
+         bodies are kept whole, so it belongs on a test network only. With
+         a secret, callbacks without a valid X-Octa-Signature are recorded
+         but answered 401.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Record and assert on the webhooks Octa sends"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Address to serve on (overrides hooksink.listen)
+    #[arg(long, env = "OCTA_HOOKSINK_LISTEN")]
+    listen: Option<String>,
+
+    /// SQLite file to record to (overrides hooksink.database; unset keeps them in memory)
+    #[arg(long, env = "OCTA_HOOKSINK_DATABASE")]
+    database: Option<PathBuf>,
+
+    /// NDJSON file to append every hook to (overrides hooksink.ndjson)
+    #[arg(long)]
+    ndjson: Option<PathBuf>,
+
+    /// Forget the hooks already in the database on start
+    #[arg(long)]
+    reset: bool,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+
+    /// Minimum level to log; `debug` adds one line per request
+    #[arg(long, default_value_t = Level::INFO)]
+    log_level: Level,
+}
+
+/// The parts of config.yaml octa-hooksink reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    hooksink: HooksinkConfig,
+}
+
+/// `hooksink:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct HooksinkConfig {
+    listen: String,
+    database: Option<PathBuf>,
+    ndjson: Option<PathBuf>,
+    secret_env: String,
+    max_body: String,
+    wait: String,
+    max_wait: String,
+}
+
+impl Default for HooksinkConfig {
+    fn default() -> Self {
+        Self {
+            listen: "0.0.0.0:9950".to_string(),
+            database: None,
+            ndjson: None,
+            secret_env: "OCTA_HOOK_SECRET".to_string(),
+            max_body: "1MB".to_string(),
+            wait: "5s".to_string(),
+            max_wait: "60s".to_string(),
+        }
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        let sink = &self.hooksink;
+        if sink.listen.parse::<SocketAddr>().is_err() {
+            problems.push((
+                "hooksink.listen".to_string(),
+                format!("'{}' is not an address like 0.0.0.0:9950", sink.listen),
+            ));
+        }
+        match parse_bytes(&sink.max_body) {
+            Ok(0) => problems.push((
+                "hooksink.max_body".to_string(),
+                "must be greater than 0".to_string(),
+            )),
+            Ok(_) => {}
+            Err(e) => problems.push(("hooksink.max_body".to_string(), e)),
+        }
+        for (field, value) in [
+            ("hooksink.wait", &sink.wait),
+            ("hooksink.max_wait", &sink.max_wait),
+        ] {
+            if let Err(e) = parse_interval(value) {
+                problems.push((field.to_string(), e));
+            }
+        }
+        if sink.secret_env.trim().is_empty() {
+            problems.push((
+                "hooksink.secret_env".to_string(),
+                "must name the variable holding the signing secret".to_string(),
+            ));
+        }
+        problems
+    }
+}
+
+/// Without a file, the environment alone can configure the sink (e.g. in a
+/// test container). Arguments are applied before the config is checked.
+fn load_config(args: &Args) -> Result<FileConfig, ConfigError> {
+    let found = octa_config::discover(args.config.as_deref().map(Path::new));
+    let mut config: FileConfig = match &found {
+        Some(found) => octa_config::read(found)?,
+        None => octa_config::from_env()?,
+    };
+    let sink = &mut config.hooksink;
+    if let Some(listen) = &args.listen {
+        sink.listen = listen.clone();
+    }
+    if let Some(database) = &args.database {
+        sink.database = Some(database.clone());
+    }
+    if let Some(ndjson) = &args.ndjson {
+        sink.ndjson = Some(ndjson.clone());
+    }
+    let origin = found.unwrap_or_else(|| "<environment>".into());
+    octa_config::check(&origin, config)
+}
+
+/// The octa-hooksink command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa hooksink` the arguments it was given.
+#[tokio::main]
+pub async fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, args.log_level, false, false);
+
+    let config = match load_config(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let sink = config.hooksink;
+    // Checked by validate().
+    let max_body = parse_bytes(&sink.max_body).unwrap_or_default();
+    let wait = parse_interval(&sink.wait).unwrap_or_default();
+    let max_wait = parse_interval(&sink.max_wait).unwrap_or_default();
+
+    let mut store = match Store::open(sink.database.as_deref(), sink.ndjson.as_deref()) {
+        Ok(store) => store,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Could not open the hook store");
+            return Kind::Io.exit_code();
+        }
+    };
+    if args.reset {
+        match store.clear() {
+            Ok(deleted) => info!(tag = "OK", deleted, "Reset"),
+            Err(e) => {
+                error!(tag = "FATAL", reason = %e, "Could not reset the hook store");
+                return Kind::Io.exit_code();
+            }
+        }
+    }
+    let secret = std::env::var(&sink.secret_env)
+        .ok()
+        .filter(|secret| !secret.is_empty());
+    if secret.is_none() {
+        warn!(
+            tag = "WARN",
+            variable = %sink.secret_env,
+            "No signing secret set; signatures are recorded as unchecked"
+        );
+    }
+
+    let state = Arc::new(Sink::new(store, secret, wait, max_wait));
+    let app = Router::new()
+        .route("/hooks", post(sink::receive_default))
+        .route("/hooks/events", get(sink::events).delete(sink::clear))
+        .route("/hooks/assert", get(sink::assert))
+        .route("/hooks/{*channel}", post(sink::receive))
+        .route("/health", get(|| async { "ok" }))
+        .layer(DefaultBodyLimit::max(max_body as usize))
+        .layer(middleware::from_fn(log_request))
+        .with_state(state);
+
+    let listener = match tokio::net::TcpListener::bind(&sink.listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(tag = "FATAL", addr = %sink.listen, reason = %e, "Could not bind");
+            return Kind::Unavailable.exit_code();
+        }
+    };
+    info!(
+        tag = "OK",
+        addr = %sink.listen,
+        database = %sink.database.as_deref().map_or("memory".into(), |d| d.display().to_string()),
+        ndjson = %sink.ndjson.as_deref().map_or("off".into(), |d| d.display().to_string()),
+        "Hook sink listening"
+    );
+
+    if let Err(e) = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown())
+        .await
+    {
+        error!(tag = "FATAL", reason = %e, "Hook sink stopped");
+        return Kind::Unavailable.exit_code();
+    }
+    info!(tag = "OK", "Shut down");
+    ExitCode::SUCCESS
+}
+
+async fn log_request(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    debug!(
+        tag = "HTTP",
+        method = %method,
+        path = %path,
+        status = response.status().as_u16(),
+        ms = started.elapsed().as_millis() as u64,
+        "Request"
+    );
+    response
+}
+
+/// Resolves on Ctrl-C or SIGTERM; waiting assertions are answered first.
+async fn shutdown() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
@@ -0,0 +1,266 @@
+use crate::store::{Filter, Hook, Store};
+use axum::body::Bytes;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use hmac::{Hmac, KeyInit, Mac};
+use octa_warden_core::schedule::parse_interval;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// `sha256=<hex>`: HMAC-SHA256 of the raw body with the shared secret, as
+/// GitHub signs its webhooks.
+pub const SIGNATURE_HEADER: &str = "x-octa-signature";
+/// Names the event when the payload does not.
+pub const EVENT_HEADER: &str = "x-octa-event";
+
+pub type Shared = Arc<Sink>;
+
+pub struct Sink {
+    store: Mutex<Store>,
+    /// Woken on every hook, for the assertions waiting on one.
+    received: Notify,
+    secret: Option<Vec<u8>>,
+    wait: Duration,
+    max_wait: Duration,
+}
+
+impl Sink {
+    pub fn new(store: Store, secret: Option<String>, wait: Duration, max_wait: Duration) -> Self {
+        Self {
+            store: Mutex::new(store),
+            received: Notify::new(),
+            secret: secret.map(String::into_bytes),
+            wait,
+            max_wait,
+        }
+    }
+
+    fn store(&self) -> std::sync::MutexGuard<'_, Store> {
+        self.store.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// `unchecked` without a secret, else whether the header matches.
+    fn signature(&self, headers: &HeaderMap, body: &[u8]) -> &'static str {
+        let Some(secret) = &self.secret else {
+            return "unchecked";
+        };
+        let Some(header) = headers.get(SIGNATURE_HEADER).and_then(|v| v.to_str().ok()) else {
+            return "missing";
+        };
+        let Some(sig) = header.trim().strip_prefix("sha256=").and_then(decode_hex) else {
+            return "invalid";
+        };
+        let mut mac = <Hmac<Sha256> as KeyInit>::new_from_slice(secret)
+            .expect("HMAC takes keys of any length");
+        mac.update(body);
+        match mac.verify_slice(&sig) {
+            Ok(()) => "valid",
+            Err(_) => "invalid",
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ReceiveQuery {
+    /// Answer with this status instead (still recorded), to test retries.
+    status: Option<u16>,
+}
+
+/// POST /hooks
+pub async fn receive_default(
+    state: State<Shared>,
+    query: Query<ReceiveQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    receive(state, Path("default".to_string()), query, headers, body).await
+}
+
+/// POST /hooks/{*channel}: records the callback. A bad or missing signature
+/// is recorded too, and answered 401.
+pub async fn receive(
+    State(sink): State<Shared>,
+    Path(channel): Path<String>,
+    Query(query): Query<ReceiveQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let signature = sink.signature(&headers, &body);
+    let payload = serde_json::from_slice::<Value>(&body)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned()));
+    let event = headers
+        .get(EVENT_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| event_of(&payload))
+        .unwrap_or_else(|| "unknown".to_string());
+    let status = match (
+        signature,
+        query.status.and_then(|s| StatusCode::from_u16(s).ok()),
+    ) {
+        ("missing" | "invalid", _) => StatusCode::UNAUTHORIZED,
+        (_, Some(status)) => status,
+        _ => StatusCode::OK,
+    };
+    let mut hook = Hook {
+        id: 0,
+        received_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        channel,
+        event,
+        keys: keys_of(&payload),
+        signature: signature.to_string(),
+        status: status.as_u16(),
+        body: payload,
+    };
+    if let Err(e) = sink.store().insert(&mut hook) {
+        warn!(tag = "FAIL", reason = %e, "Could not record a hook");
+        return problem(StatusCode::INTERNAL_SERVER_ERROR, &e);
+    }
+    sink.received.notify_waiters();
+    info!(
+        tag = "HOOK",
+        id = hook.id,
+        channel = %hook.channel,
+        event = %hook.event,
+        keys = %hook.keys.join(","),
+        signature = %hook.signature,
+        status = hook.status,
+        "Received"
+    );
+    if status == StatusCode::UNAUTHORIZED {
+        return problem(status, &format!("signature {}", signature));
+    }
+    (status, Json(json!({ "id": hook.id }))).into_response()
+}
+
+/// GET /hooks/events: the hooks matching the query, oldest first.
+pub async fn events(State(sink): State<Shared>, Query(filter): Query<Filter>) -> Response {
+    let found = sink.store().find(&filter);
+    match found {
+        Ok(hooks) => Json(json!({ "count": hooks.len(), "events": hooks })).into_response(),
+        Err(e) => problem(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    }
+}
+
+/// DELETE /hooks/events: forgets every hook, between tests. Answers with
+/// the last id, for `after` in later queries.
+pub async fn clear(State(sink): State<Shared>) -> Response {
+    let mut store = sink.store();
+    match store
+        .clear()
+        .and_then(|deleted| Ok((deleted, store.last_id()?)))
+    {
+        Ok((deleted, last_id)) => {
+            Json(json!({ "deleted": deleted, "last_id": last_id })).into_response()
+        }
+        Err(e) => problem(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AssertQuery {
+    /// At least this many must match (default 1); 0 asserts that none
+    /// arrive before the timeout.
+    count: Option<usize>,
+    /// How long to wait for them (default hooksink.wait).
+    timeout: Option<String>,
+}
+
+/// GET /hooks/assert: waits until enough hooks match, then answers 200 with
+/// them, or 417 with what did match when the timeout passes first.
+pub async fn assert(
+    State(sink): State<Shared>,
+    Query(filter): Query<Filter>,
+    Query(query): Query<AssertQuery>,
+) -> Response {
+    let wait = match query.timeout.as_deref().map(parse_interval) {
+        None => sink.wait,
+        Some(Ok(wait)) => wait.min(sink.max_wait),
+        Some(Err(e)) => return problem(StatusCode::BAD_REQUEST, &format!("timeout: {}", e)),
+    };
+    let expected = query.count.unwrap_or(1);
+    let deadline = Instant::now() + wait;
+    loop {
+        // Registered before the check, so a hook stored in between wakes it.
+        let received = sink.received.notified();
+        let found = sink.store().find(&filter);
+        let hooks = match found {
+            Ok(hooks) => hooks,
+            Err(e) => return problem(StatusCode::INTERNAL_SERVER_ERROR, &e),
+        };
+        let passed = if expected == 0 {
+            hooks.is_empty()
+        } else {
+            hooks.len() >= expected
+        };
+        // Nothing can change the verdict once it is failed for 0, or met.
+        let settled = if expected == 0 { !passed } else { passed };
+        if settled || Instant::now() >= deadline {
+            let status = if passed {
+                StatusCode::OK
+            } else {
+                StatusCode::EXPECTATION_FAILED
+            };
+            let body = json!({
+                "ok": passed,
+                "expected": expected,
+                "matched": hooks.len(),
+                "waited_ms": (wait - deadline.saturating_duration_since(Instant::now())).as_millis() as u64,
+                "events": hooks,
+            });
+            return (status, Json(body)).into_response();
+        }
+        tokio::select! {
+            _ = received => {}
+            _ = tokio::time::sleep_until(deadline) => {}
+        }
+    }
+}
+
+/// The event a payload names: `event` or `type`, else the `source` of the
+/// warden, quota and moderate notifications.
+fn event_of(payload: &Value) -> Option<String> {
+    ["event", "type", "source"]
+        .iter()
+        .find_map(|field| payload.get(field).and_then(Value::as_str))
+        .map(str::to_string)
+}
+
+fn keys_of(payload: &Value) -> Vec<String> {
+    let mut keys = Vec::new();
+    for field in ["key", "keys", "target"] {
+        match payload.get(field) {
+            Some(Value::String(key)) => keys.push(key.clone()),
+            Some(Value::Array(list)) => {
+                keys.extend(list.iter().filter_map(Value::as_str).map(str::to_string))
+            }
+            _ => {}
+        }
+    }
+    keys.dedup();
+    keys
+}
+
+fn problem(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
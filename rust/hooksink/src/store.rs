@@ -0,0 +1,172 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS hooks (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    received_at TEXT NOT NULL,
+    channel     TEXT NOT NULL,
+    event       TEXT NOT NULL,
+    keys        TEXT NOT NULL,
+    signature   TEXT NOT NULL,
+    status      INTEGER NOT NULL,
+    body        TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS hooks_event ON hooks (channel, event);
+";
+
+/// One callback as it was received.
+#[derive(Debug, Clone, Serialize)]
+pub struct Hook {
+    pub id: i64,
+    pub received_at: String,
+    /// The path after `/hooks/`, `default` for `/hooks` itself.
+    pub channel: String,
+    pub event: String,
+    /// Every key the payload names (`key`, `keys`, `target`).
+    pub keys: Vec<String>,
+    /// `valid`, `invalid` or `missing`; `unchecked` without a secret.
+    pub signature: String,
+    /// The status the sink answered with.
+    pub status: u16,
+    /// The payload, parsed when it is JSON, else the raw text.
+    pub body: Value,
+}
+
+/// What a listing or an assertion matches; unset fields match anything.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct Filter {
+    pub channel: Option<String>,
+    pub event: Option<String>,
+    /// Matches a hook naming this key among its keys.
+    pub key: Option<String>,
+    pub signature: Option<String>,
+    /// Only hooks received after the one with this id.
+    pub after: Option<i64>,
+}
+
+impl Filter {
+    fn matches(&self, hook: &Hook) -> bool {
+        self.channel.as_ref().is_none_or(|c| *c == hook.channel)
+            && self.event.as_ref().is_none_or(|e| *e == hook.event)
+            && self.key.as_ref().is_none_or(|k| hook.keys.contains(k))
+            && self.signature.as_ref().is_none_or(|s| *s == hook.signature)
+            && self.after.is_none_or(|after| hook.id > after)
+    }
+}
+
+/// The SQLite table of received hooks, mirrored line by line to an NDJSON
+/// file when one is configured.
+pub struct Store {
+    db: Connection,
+    ndjson: Option<File>,
+}
+
+impl Store {
+    /// `database` unset keeps the hooks in memory for the life of the sink.
+    pub fn open(database: Option<&Path>, ndjson: Option<&Path>) -> Result<Self, String> {
+        let db = match database {
+            Some(path) => Connection::open(path),
+            None => Connection::open_in_memory(),
+        }
+        .map_err(|e| e.to_string())?;
+        db.execute_batch(SCHEMA).map_err(|e| e.to_string())?;
+        let ndjson = match ndjson {
+            Some(path) => Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?,
+            ),
+            None => None,
+        };
+        Ok(Self { db, ndjson })
+    }
+
+    /// Stores `hook` under the next id, which it returns. An NDJSON write
+    /// failure is returned after the row is stored.
+    pub fn insert(&mut self, hook: &mut Hook) -> Result<i64, String> {
+        self.db
+            .execute(
+                "INSERT INTO hooks (received_at, channel, event, keys, signature, status, body)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    hook.received_at,
+                    hook.channel,
+                    hook.event,
+                    serde_json::to_string(&hook.keys).unwrap_or_default(),
+                    hook.signature,
+                    hook.status,
+                    hook.body.to_string(),
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        hook.id = self.db.last_insert_rowid();
+        if let Some(file) = &mut self.ndjson {
+            let line = serde_json::to_string(hook).unwrap_or_default();
+            writeln!(file, "{}", line).map_err(|e| format!("NDJSON: {}", e))?;
+        }
+        Ok(hook.id)
+    }
+
+    /// The hooks matching `filter`, oldest first.
+    pub fn find(&self, filter: &Filter) -> Result<Vec<Hook>, String> {
+        let mut statement = self
+            .db
+            .prepare(
+                "SELECT id, received_at, channel, event, keys, signature, status, body
+                 FROM hooks WHERE id > ?1 ORDER BY id",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map([filter.after.unwrap_or(0)], |row| {
+                let keys: String = row.get(4)?;
+                let body: String = row.get(7)?;
+                Ok(Hook {
+                    id: row.get(0)?,
+                    received_at: row.get(1)?,
+                    channel: row.get(2)?,
+                    event: row.get(3)?,
+                    keys: serde_json::from_str(&keys).unwrap_or_default(),
+                    signature: row.get(5)?,
+                    status: row.get(6)?,
+                    body: serde_json::from_str(&body).unwrap_or(Value::String(body)),
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        let mut hooks = Vec::new();
+        for hook in rows {
+            let hook = hook.map_err(|e| e.to_string())?;
+            if filter.matches(&hook) {
+                hooks.push(hook);
+            }
+        }
+        Ok(hooks)
+    }
+
+    /// The id of the last hook received, cleared or not; 0 before the first.
+    pub fn last_id(&self) -> Result<i64, String> {
+        self.db
+            .query_row(
+                "SELECT seq FROM sqlite_sequence WHERE name = 'hooks'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .map(|id| id.unwrap_or(0))
+            .map_err(|e| e.to_string())
+    }
+
+    /// Forgets every hook; ids keep counting up. The NDJSON file is kept.
+    pub fn clear(&mut self) -> Result<usize, String> {
+        self.db
+            .execute("DELETE FROM hooks", [])
+            .map_err(|e| e.to_string())
+    }
+}
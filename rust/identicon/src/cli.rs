@@ -0,0 +1,333 @@
+use crate::{Options, Style, MAX_SIZE, MIN_SIZE};
+use clap::{Parser, Subcommand, ValueEnum};
+use octa_config::{ConfigError, SecurityConfig, ServerConfig, Validate};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use serde::Deserialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+use tracing::{error, info, Level};
+
+mod batch;
+
+/*
+OCTA-IDENTICON: Default avatars for users without an upload
+=============================================
+Mission: Draw the same fallback every Octa consumer would otherwise
+         reimplement: identicons or initials, deterministic in the user's
+         identifier, in the servers' sizes and styles. `render` writes one;
+         `batch` uploads one for every user of a list.
+Safety:  Users who already have an image are left alone unless --force is
+         given. --dry-run only lists the uploads.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Generate deterministic default avatars and upload them to Octa"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long, global = true)]
+    config: Option<String>,
+
+    /// Log format
+    #[arg(
+        long,
+        env = "OCTA_LOG_FORMAT",
+        value_enum,
+        global = true,
+        default_value_t = LogFormat::Pretty
+    )]
+    log_format: LogFormat,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Parser, Debug)]
+struct Look {
+    /// identicon or initials (overrides identicon.style)
+    #[arg(long, value_enum)]
+    style: Option<StyleArg>,
+
+    /// Edge length in pixels, 16-1024 (overrides identicon.size)
+    #[arg(long)]
+    size: Option<u32>,
+
+    /// Background of initials: color, gradient or soft, optionally with a palette (gradient/pro) (overrides identicon.theme)
+    #[arg(long)]
+    theme: Option<String>,
+
+    /// More query parameters of the avatar URL, e.g. rounded=50 or bg=navy; repeatable
+    #[arg(long = "param", value_name = "KEY=VALUE", value_parser = key_value)]
+    params: Vec<(String, String)>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Draw the avatar of one identifier
+    Render {
+        /// User identifier (a key, an email, a user id)
+        id: String,
+
+        /// Name to take the initials from (default: the identifier)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// SVG instead of PNG
+        #[arg(long)]
+        svg: bool,
+
+        /// Output file, `-` for stdout
+        #[arg(short, long, default_value = "-")]
+        output: String,
+
+        #[command(flatten)]
+        look: Look,
+    },
+    /// Upload a default avatar for every user of a list that has none yet
+    Batch {
+        /// File with one user per line: the key, then optionally the name to take initials from (`-` for stdin)
+        users: String,
+
+        /// Instance to upload to (overrides base_url)
+        #[arg(long, env = "OCTA_IDENTICON_URL")]
+        url: Option<String>,
+
+        /// Upload secret of the instance (default: security.upload_secret)
+        #[arg(long, env = "OCTA_IDENTICON_SECRET", hide_env_values = true)]
+        secret: Option<String>,
+
+        /// Replace the image of users who already have one
+        #[arg(long)]
+        force: bool,
+
+        /// List the uploads instead of making them
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Users checked and uploaded at once (overrides identicon.concurrency)
+        #[arg(long)]
+        concurrency: Option<usize>,
+
+        /// Seconds before a request is abandoned
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+
+        #[command(flatten)]
+        look: Look,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum StyleArg {
+    Identicon,
+    Initials,
+}
+
+impl From<StyleArg> for Style {
+    fn from(style: StyleArg) -> Self {
+        match style {
+            StyleArg::Identicon => Style::Identicon,
+            StyleArg::Initials => Style::Initials,
+        }
+    }
+}
+
+fn key_value(raw: &str) -> Result<(String, String), String> {
+    raw.split_once('=')
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .ok_or_else(|| format!("'{}' is not KEY=VALUE", raw))
+}
+
+/// The parts of config.yaml octa-identicon reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    server: ServerConfig,
+    security: SecurityConfig,
+    base_url: Option<String>,
+    identicon: IdenticonConfig,
+}
+
+/// `identicon:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct IdenticonConfig {
+    style: StyleArg,
+    /// Edge length; uploads are stored as drawn (`mode=original`).
+    size: u32,
+    theme: String,
+    concurrency: usize,
+}
+
+impl Default for IdenticonConfig {
+    fn default() -> Self {
+        Self {
+            style: StyleArg::Identicon,
+            // What a square upload is cropped to when no size is given.
+            size: 256,
+            theme: String::new(),
+            concurrency: 4,
+        }
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = self.server.validate();
+        problems.extend(octa_config::check_url("base_url", self.base_url.as_deref()));
+        if let Some(problem) = check_size(self.identicon.size) {
+            problems.push(("identicon.size".to_string(), problem));
+        }
+        if self.identicon.concurrency == 0 {
+            problems.push((
+                "identicon.concurrency".to_string(),
+                "must be at least 1".to_string(),
+            ));
+        }
+        problems
+    }
+}
+
+fn check_size(size: u32) -> Option<String> {
+    (!(MIN_SIZE..=MAX_SIZE).contains(&size))
+        .then(|| format!("{} is not within {}-{}", size, MIN_SIZE, MAX_SIZE))
+}
+
+/// Without a file, the environment and arguments alone configure it.
+fn load_config(path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    match octa_config::discover(path.map(Path::new)) {
+        Some(path) => octa_config::load(&path),
+        None => {
+            let config: FileConfig = octa_config::from_env()?;
+            octa_config::check(Path::new("<environment>"), config)
+        }
+    }
+}
+
+impl Look {
+    /// The query the avatar URL would carry: config defaults, overridden
+    /// by arguments.
+    fn options(&self, config: &IdenticonConfig) -> Result<Options, String> {
+        let size = self.size.unwrap_or(config.size);
+        if let Some(problem) = check_size(size) {
+            return Err(format!("--size: {}", problem));
+        }
+        let mut options = Options::new()
+            .style(self.style.unwrap_or(config.style).into())
+            .size(size);
+        let theme = self.theme.as_deref().unwrap_or(&config.theme);
+        if !theme.is_empty() {
+            options = options.theme(theme);
+        }
+        for (key, value) in &self.params {
+            options = options.param(key, value);
+        }
+        Ok(options)
+    }
+}
+
+/// The octa-identicon command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa identicon` the arguments it was given.
+#[tokio::main]
+pub async fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    let stdout_taken = matches!(&args.command, Command::Render { output, .. } if output == "-");
+    octa_logging::init(args.log_format, Level::INFO, false, stdout_taken);
+
+    let config = match load_config(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+
+    match args.command {
+        Command::Render {
+            id,
+            name,
+            svg,
+            output,
+            look,
+        } => {
+            let options = match look.options(&config.identicon) {
+                Ok(options) => options.svg(svg),
+                Err(e) => {
+                    error!(tag = "FATAL", reason = %e, "Invalid arguments");
+                    return Kind::Usage.exit_code();
+                }
+            };
+            let options = match name {
+                Some(name) => options.name(name),
+                None => options,
+            };
+            render(&id, &options, &output)
+        }
+        Command::Batch {
+            users,
+            url,
+            secret,
+            force,
+            dry_run,
+            concurrency,
+            timeout,
+            look,
+        } => {
+            if let Some((field, problem)) = octa_config::check_url("--url", url.as_deref()) {
+                error!(tag = "FATAL", reason = %format!("{}: {}", field, problem), "Invalid arguments");
+                return Kind::Usage.exit_code();
+            }
+            let options = match look.options(&config.identicon) {
+                Ok(options) => options,
+                Err(e) => {
+                    error!(tag = "FATAL", reason = %e, "Invalid arguments");
+                    return Kind::Usage.exit_code();
+                }
+            };
+            let base_url = octa_config::base_url(
+                url.as_deref().or(config.base_url.as_deref()),
+                &config.server,
+            );
+            let run = batch::Run {
+                base_url: &base_url,
+                secret: secret.as_deref().unwrap_or(&config.security.upload_secret),
+                options: &options,
+                force,
+                dry_run,
+                concurrency: concurrency.unwrap_or(config.identicon.concurrency),
+                timeout: Duration::from_secs(timeout),
+            };
+            batch::run(&run, &users).await
+        }
+    }
+}
+
+fn render(id: &str, options: &Options, output: &str) -> ExitCode {
+    let avatar = match options.render(id) {
+        Ok(avatar) => avatar,
+        Err(e) => {
+            error!(tag = "FATAL", id = %id, reason = %e, "Could not render");
+            return Kind::Data.exit_code();
+        }
+    };
+    let written = if output == "-" {
+        std::io::stdout().write_all(&avatar.data)
+    } else {
+        std::fs::write(PathBuf::from(output), &avatar.data)
+    };
+    if let Err(e) = written {
+        error!(tag = "FATAL", path = %output, reason = %e, "Could not write");
+        return Kind::Io.exit_code();
+    }
+    if output != "-" {
+        info!(tag = "OK", id = %id, path = %output, bytes = avatar.data.len(), "Rendered");
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,193 @@
+use crate::Options;
+use futures::stream::{self, StreamExt};
+use octa_client::{Action, Client, Error, Mode, UploadOptions};
+use octa_errors::Kind;
+use std::collections::HashSet;
+use std::io::Read;
+use std::process::ExitCode;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// What `batch` was asked to do.
+pub struct Run<'a> {
+    pub base_url: &'a str,
+    pub secret: &'a str,
+    pub options: &'a Options,
+    pub force: bool,
+    pub dry_run: bool,
+    pub concurrency: usize,
+    pub timeout: Duration,
+}
+
+/// One line of the user list.
+struct User {
+    key: String,
+    /// What the initials are taken from; the key when absent.
+    name: Option<String>,
+}
+
+enum Outcome {
+    Created,
+    Replaced,
+    /// Has an image already.
+    Kept,
+    /// Dry run: would have been uploaded.
+    Planned,
+}
+
+/// Reads the list, refusing it whole if a key is invalid, then checks and
+/// uploads at most `concurrency` users at a time.
+pub async fn run(run: &Run<'_>, users: &str) -> ExitCode {
+    if run.secret.trim().is_empty() {
+        error!(
+            tag = "FATAL",
+            "No upload secret (set security.upload_secret or pass --secret)"
+        );
+        return Kind::Config.exit_code();
+    }
+    let users = match read_users(users) {
+        Ok(users) => users,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid user list");
+            return e.exit_code();
+        }
+    };
+    let client = match Client::builder(run.base_url)
+        .secret(run.secret)
+        .timeout(run.timeout)
+        .user_agent(concat!("octa-identicon/", env!("CARGO_PKG_VERSION")))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Could not create the Octa client");
+            return e.kind().exit_code();
+        }
+    };
+
+    info!(
+        tag = "→",
+        url = %run.base_url,
+        users = users.len(),
+        dry_run = run.dry_run,
+        "Generating defaults"
+    );
+    let total = users.len() as u64;
+    let step = (total / 10).max(1);
+    let (mut created, mut replaced, mut kept, mut planned, mut failed) = (0u64, 0, 0, 0, 0);
+    // Why the first user failed, for the exit code.
+    let mut failure = None;
+    let client = &client;
+    let mut outcomes = stream::iter(users)
+        .map(|user| async move {
+            let outcome = process(run, client, &user).await;
+            (user.key, outcome)
+        })
+        .buffer_unordered(run.concurrency.max(1));
+
+    let mut done = 0u64;
+    while let Some((key, outcome)) = outcomes.next().await {
+        done += 1;
+        match outcome {
+            Ok(Outcome::Created) => created += 1,
+            Ok(Outcome::Replaced) => replaced += 1,
+            Ok(Outcome::Kept) => kept += 1,
+            Ok(Outcome::Planned) => {
+                planned += 1;
+                info!(tag = "PLAN", key = %key, "Would upload");
+            }
+            Err((kind, e)) => {
+                failed += 1;
+                failure.get_or_insert(kind);
+                warn!(tag = "FAIL", key = %key, reason = %e, "Not uploaded");
+            }
+        }
+        if !run.dry_run && (done.is_multiple_of(step) || done == total) {
+            info!(tag = "→", done, total, failed, "Progress");
+        }
+    }
+
+    info!(
+        tag = if failed == 0 { "OK" } else { "WARN" },
+        created, replaced, planned, kept, failed, "Batch finished"
+    );
+    failure.map_or(ExitCode::SUCCESS, Kind::exit_code)
+}
+
+/// Uploads one user's default, or says why not.
+async fn process(run: &Run<'_>, client: &Client, user: &User) -> Result<Outcome, (Kind, String)> {
+    let octa = |e: Error| (e.kind(), e.to_string());
+    let exists = match client.stat(&user.key).await {
+        Ok(_) => true,
+        Err(Error::NotFound { .. }) => false,
+        Err(e) => return Err(octa(e)),
+    };
+    if exists && !run.force {
+        return Ok(Outcome::Kept);
+    }
+    if run.dry_run {
+        return Ok(Outcome::Planned);
+    }
+
+    // Stored as drawn: the servers only take PNG and JPEG, and a re-encode
+    // would blur the flat colors.
+    let mut options = run.options.clone().svg(false);
+    if let Some(name) = &user.name {
+        options = options.name(name);
+    }
+    let key = user.key.clone();
+    let avatar = tokio::task::spawn_blocking(move || options.render(&key))
+        .await
+        .map_err(|e| (Kind::Internal, e.to_string()))?
+        .map_err(|e| (Kind::Data, e))?;
+    let upload = UploadOptions::new()
+        .mode(Mode::Original)
+        .file_name(format!("{}.png", octa_key::file_name(&user.key)));
+    let uploaded = client
+        .upload_avatar(&user.key, avatar.data, upload)
+        .await
+        .map_err(octa)?;
+    Ok(match uploaded.action {
+        Action::Created => Outcome::Created,
+        Action::Updated => Outcome::Replaced,
+    })
+}
+
+/// `<key> [name...]` per line; blank lines and `#` comments are skipped,
+/// repeated keys are read once.
+fn read_users(path: &str) -> Result<Vec<User>, octa_errors::Error> {
+    let mut text = String::new();
+    let read = if path == "-" {
+        std::io::stdin().read_to_string(&mut text).map(|_| ())
+    } else {
+        std::fs::read_to_string(path).map(|t| text = t)
+    };
+    read.map_err(|e| {
+        let kind = match e.kind() {
+            std::io::ErrorKind::NotFound => Kind::NoInput,
+            _ => Kind::Io,
+        };
+        octa_errors::Error::new(kind, format!("could not read {}: {}", path, e))
+    })?;
+
+    let mut users = Vec::new();
+    let mut seen = HashSet::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (raw, name) = match line.split_once(char::is_whitespace) {
+            Some((raw, name)) => (raw, Some(name.trim().to_string())),
+            None => (line, None),
+        };
+        let key = octa_key::parse(raw).map_err(|e| {
+            let message = format!("{}:{}: invalid key '{}': {}", path, n + 1, raw, e);
+            octa_errors::Error::new(Kind::Data, message)
+        })?;
+        if seen.insert(key.clone()) {
+            users.push(User { key, name });
+        }
+    }
+    Ok(users)
+}
@@ -0,0 +1,309 @@
+//! Palettes and color math of the initials avatars, as the Go server has them
+//! (`pkg/utils/color.go`), plus the few helpers identicons share.
+
+pub(crate) type Rgb = [u8; 3];
+
+pub(crate) const WHITE: Rgb = [255, 255, 255];
+pub(crate) const BLACK: Rgb = [0, 0, 0];
+
+const CSS_COLORS: &[(&str, Rgb)] = &[
+    ("white", [255, 255, 255]),
+    ("black", [0, 0, 0]),
+    ("red", [255, 0, 0]),
+    ("green", [0, 128, 0]),
+    ("blue", [0, 0, 255]),
+    ("cyan", [0, 255, 255]),
+    ("magenta", [255, 0, 255]),
+    ("yellow", [255, 255, 0]),
+    ("orange", [255, 165, 0]),
+    ("purple", [128, 0, 128]),
+    ("pink", [255, 192, 203]),
+    ("gray", [128, 128, 128]),
+    ("grey", [128, 128, 128]),
+    ("silver", [192, 192, 192]),
+    ("gold", [255, 215, 0]),
+    ("teal", [0, 128, 128]),
+    ("lime", [0, 255, 0]),
+    ("navy", [0, 0, 128]),
+];
+
+const PRO_COLORS: &[Rgb] = &[
+    [71, 85, 105],
+    [51, 65, 85],
+    [30, 41, 59], // Slate
+    [82, 82, 91],
+    [63, 63, 70],
+    [39, 39, 42], // Zinc
+    [87, 83, 78],
+    [68, 64, 60],
+    [41, 37, 36], // Stone
+    [75, 85, 99],
+    [55, 65, 81],
+    [31, 41, 55], // Gray
+    [239, 68, 68],
+    [220, 38, 38],
+    [185, 28, 28], // Red
+    [244, 63, 94],
+    [225, 29, 72],
+    [190, 18, 60], // Rose
+    [236, 72, 153],
+    [219, 39, 119],
+    [190, 24, 93], // Pink
+    [249, 115, 22],
+    [234, 88, 12],
+    [194, 65, 12], // Orange
+    [245, 158, 11],
+    [217, 119, 6],
+    [180, 83, 9], // Amber
+    [234, 179, 8],
+    [202, 138, 4],
+    [161, 98, 7], // Yellow
+    [34, 197, 94],
+    [22, 163, 74],
+    [21, 128, 61], // Green
+    [16, 185, 129],
+    [5, 150, 105],
+    [4, 120, 87], // Emerald
+    [132, 204, 22],
+    [101, 163, 13],
+    [77, 124, 15], // Lime
+    [20, 184, 166],
+    [13, 148, 136],
+    [15, 118, 110], // Teal
+    [6, 182, 212],
+    [8, 145, 178],
+    [21, 94, 117], // Cyan
+    [14, 165, 233],
+    [2, 132, 199],
+    [3, 105, 161], // Sky
+    [59, 130, 246],
+    [37, 99, 235],
+    [29, 78, 216], // Blue
+    [99, 102, 241],
+    [79, 70, 229],
+    [67, 56, 202], // Indigo
+    [139, 92, 246],
+    [124, 58, 237],
+    [109, 40, 217], // Violet
+    [168, 85, 247],
+    [147, 51, 234],
+    [126, 34, 206], // Purple
+    [217, 70, 239],
+    [192, 38, 211],
+    [162, 28, 175], // Fuchsia
+    [88, 101, 242], // Blurple
+    [29, 161, 242], // Twitter Blue
+    [0, 0, 0],      // Pure Black
+    [25, 25, 25],   // Off Black
+];
+
+const PRO_GRADIENTS: &[(Rgb, Rgb)] = &[
+    ([59, 130, 246], [37, 99, 235]),
+    ([139, 92, 246], [124, 58, 237]),
+    ([236, 72, 153], [219, 39, 119]),
+    ([16, 185, 129], [5, 150, 105]),
+    ([249, 115, 22], [234, 88, 12]),
+    ([99, 102, 241], [168, 85, 247]),
+    ([6, 182, 212], [59, 130, 246]),
+    ([244, 63, 94], [249, 115, 22]),
+    ([34, 197, 94], [20, 184, 166]),
+    ([71, 85, 105], [30, 41, 59]),
+    ([168, 85, 247], [236, 72, 153]),
+    ([14, 165, 233], [99, 102, 241]),
+];
+
+const GOOGLE_COLORS: &[Rgb] = &[
+    [59, 130, 246],
+    [37, 99, 235],
+    [14, 165, 233],
+    [6, 182, 212],
+    [139, 92, 246],
+    [124, 58, 237],
+    [192, 38, 211],
+    [219, 39, 119],
+    [225, 29, 72],
+    [16, 185, 129],
+    [5, 150, 105],
+    [20, 184, 166],
+    [13, 148, 136],
+    [249, 115, 22],
+    [234, 88, 12],
+    [245, 158, 11],
+    [220, 38, 38],
+    [71, 85, 105],
+    [82, 82, 91],
+    [79, 70, 229],
+];
+
+/// Go's `hash = int(c) + ((hash << 5) - hash)` over the runes, on a 64-bit int.
+pub(crate) fn name_hash(name: &str) -> usize {
+    let hash = name.chars().fold(0i64, |hash, c| {
+        (c as i64).wrapping_add(hash.wrapping_shl(5).wrapping_sub(hash))
+    });
+    hash.unsigned_abs() as usize
+}
+
+pub(crate) fn palette_color(name: &str, palette: &str) -> Rgb {
+    let list = match palette.to_lowercase().as_str() {
+        "google" | "brand" => GOOGLE_COLORS,
+        _ => PRO_COLORS,
+    };
+    list[name_hash(name) % list.len()]
+}
+
+pub(crate) fn gradient(name: &str, palette: &str) -> (Rgb, Rgb) {
+    let hash = md5::compute(name.as_bytes()).0;
+    match palette.to_lowercase().as_str() {
+        "pro" | "curated" => PRO_GRADIENTS[name_hash(name) % PRO_GRADIENTS.len()],
+        "retro" | "raw" => ([hash[0], hash[1], hash[2]], [hash[3], hash[4], hash[5]]),
+        _ => {
+            let h1 = hash[0] as f64 * (360.0 / 255.0);
+            let s1 = 0.65 + (hash[1] % 35) as f64 / 100.0;
+            let l1 = 0.45 + (hash[2] % 20) as f64 / 100.0;
+            let mut h2 = h1 + 30.0 + (hash[3] % 60) as f64;
+            if h2 > 360.0 {
+                h2 -= 360.0;
+            }
+            let s2 = 0.65 + (hash[4] % 35) as f64 / 100.0;
+            let l2 = 0.45 + (hash[5] % 20) as f64 / 100.0;
+            (hsl_to_rgb(h1, s1, l1), hsl_to_rgb(h2, s2, l2))
+        }
+    }
+}
+
+/// `(background, text)`: the seed's hue, light background and dark text.
+pub(crate) fn make_soft(seed: Rgb) -> (Rgb, Rgb) {
+    let (h, s, _) = rgb_to_hsl(seed);
+    (
+        hsl_to_rgb(h, s.min(0.6), 0.95),
+        hsl_to_rgb(h, (s + 0.2).min(1.0), 0.20),
+    )
+}
+
+pub(crate) fn soft_darken(c: Rgb, factor: f64) -> Rgb {
+    let (h, s, l) = rgb_to_hsl(c);
+    hsl_to_rgb(h, s, (l - factor).max(0.0))
+}
+
+/// A CSS name, `#rrggbb` or `#rgb` (the `#` optional).
+pub(crate) fn parse_color(value: &str) -> Option<Rgb> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    let lower = value.to_lowercase();
+    if let Some((_, c)) = CSS_COLORS.iter().find(|(name, _)| *name == lower) {
+        return Some(*c);
+    }
+    let hex = value.trim_start_matches('#');
+    let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+    match hex.len() {
+        6 if hex.is_ascii() => Some([
+            channel(&hex[0..2])?,
+            channel(&hex[2..4])?,
+            channel(&hex[4..6])?,
+        ]),
+        3 if hex.is_ascii() => {
+            let double = |i: usize| channel(&hex[i..i + 1].repeat(2));
+            Some([double(0)?, double(1)?, double(2)?])
+        }
+        _ => None,
+    }
+}
+
+/// `DetermineTextColorAdvanced`: an explicit `input` wins, otherwise black
+/// or white by the background's luminance.
+pub(crate) fn text_color(c1: Rgb, c2: Rgb, style: &str, input: &str) -> Rgb {
+    if !input.is_empty() {
+        match input.to_lowercase().as_str() {
+            "white" => return WHITE,
+            "black" => return BLACK,
+            _ => {}
+        }
+        if let Some(c) = parse_color(input) {
+            return c;
+        }
+    }
+    let base = if style == "gradient" {
+        [
+            ((c1[0] as u16 + c2[0] as u16) / 2) as u8,
+            ((c1[1] as u16 + c2[1] as u16) / 2) as u8,
+            ((c1[2] as u16 + c2[2] as u16) / 2) as u8,
+        ]
+    } else {
+        c1
+    };
+    let luminance = 0.2126 * base[0] as f64 / 255.0
+        + 0.7152 * base[1] as f64 / 255.0
+        + 0.0722 * base[2] as f64 / 255.0;
+    if luminance > 0.6 {
+        BLACK
+    } else {
+        WHITE
+    }
+}
+
+pub(crate) fn rgb_to_hsl(c: Rgb) -> (f64, f64, f64) {
+    let (r, g, b) = (
+        c[0] as f64 / 255.0,
+        c[1] as f64 / 255.0,
+        c[2] as f64 / 255.0,
+    );
+    let max = r.max(g.max(b));
+    let min = r.min(g.min(b));
+    let l = (max + min) / 2.0;
+    if max == min {
+        return (0.0, 0.0, l);
+    }
+    let d = max - min;
+    let s = if l > 0.5 {
+        d / (2.0 - max - min)
+    } else {
+        d / (max + min)
+    };
+    let h = if max == r {
+        (g - b) / d + if g < b { 6.0 } else { 0.0 }
+    } else if max == g {
+        (b - r) / d + 2.0
+    } else {
+        (r - g) / d + 4.0
+    };
+    (h * 60.0, s, l)
+}
+
+pub(crate) fn hsl_to_rgb(h: f64, s: f64, l: f64) -> Rgb {
+    if s == 0.0 {
+        let v = (l * 255.0) as u8;
+        return [v, v, v];
+    }
+    let q = if l < 0.5 {
+        l * (1.0 + s)
+    } else {
+        l + s - l * s
+    };
+    let p = 2.0 * l - q;
+    let channel = |t: f64| (hue_to_rgb(p, q, t) * 255.0) as u8;
+    [
+        channel(h / 360.0 + 1.0 / 3.0),
+        channel(h / 360.0),
+        channel(h / 360.0 - 1.0 / 3.0),
+    ]
+}
+
+fn hue_to_rgb(p: f64, q: f64, mut t: f64) -> f64 {
+    if t < 0.0 {
+        t += 1.0;
+    }
+    if t > 1.0 {
+        t -= 1.0;
+    }
+    if t < 1.0 / 6.0 {
+        p + (q - p) * 6.0 * t
+    } else if t < 0.5 {
+        q
+    } else if t < 2.0 / 3.0 {
+        p + (q - p) * (2.0 / 3.0 - t) * 6.0
+    } else {
+        p
+    }
+}
@@ -0,0 +1,121 @@
+//! Geometric identicons: a 5×5 grid, mirrored around the middle column,
+//! in one color on a light background. The MD5 of the identifier picks the
+//! cells (15 bits) and the color (the last bytes), so the same identifier
+//! always gives the same image and similar ones look nothing alike.
+
+use crate::color::{hsl_to_rgb, parse_color, Rgb};
+use crate::{param, Avatar, Frame};
+use image::{Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const GRID: u32 = 5;
+
+/// GitHub's identicon background.
+const BACKGROUND: Rgb = [240, 240, 240];
+
+/// The identicon for `name`, with the query's `bg` and `color` overriding
+/// the background and cell colors.
+pub(crate) fn render(
+    name: &str,
+    query: &HashMap<String, String>,
+    frame: &Frame,
+) -> Result<Avatar, String> {
+    let hash = md5::compute(name.as_bytes()).0;
+    let cells = cells(&hash);
+    let bg = parse_color(param(query, "bg")).unwrap_or(BACKGROUND);
+    let fg = parse_color(param(query, "color")).unwrap_or_else(|| color(&hash));
+    let bounds = bounds(frame.size);
+
+    if frame.svg {
+        return Ok(frame.svg(render_svg(frame, &cells, &bounds, bg, fg)));
+    }
+    let mut img = RgbaImage::new(frame.size, frame.size);
+    for y in 0..frame.size {
+        for x in 0..frame.size {
+            if frame.clipped(x, y) {
+                continue;
+            }
+            let cell = |v: u32| bounds.windows(2).position(|w| (w[0]..w[1]).contains(&v));
+            let on = match (cell(x), cell(y)) {
+                (Some(col), Some(row)) => cells[row * GRID as usize + col],
+                _ => false,
+            };
+            let c = if on { fg } else { bg };
+            img.put_pixel(x, y, Rgba([c[0], c[1], c[2], 255]));
+        }
+    }
+    frame.png(&img)
+}
+
+/// Which of the 25 cells, row by row, are filled: the first three columns
+/// from the hash's low bits, the last two mirroring the first two.
+fn cells(hash: &[u8; 16]) -> [bool; (GRID * GRID) as usize] {
+    let mut cells = [false; (GRID * GRID) as usize];
+    for row in 0..GRID as usize {
+        for col in 0..3 {
+            let bit = row * 3 + col;
+            let on = hash[bit / 8] >> (bit % 8) & 1 == 1;
+            cells[row * GRID as usize + col] = on;
+            cells[row * GRID as usize + (GRID as usize - 1 - col)] = on;
+        }
+    }
+    cells
+}
+
+/// A saturated, mid-light color, so that every hue reads on the background.
+fn color(hash: &[u8; 16]) -> Rgb {
+    let hue = u16::from_be_bytes([hash[12], hash[13]]) as f64 % 360.0;
+    let saturation = 0.45 + (hash[14] % 20) as f64 / 100.0;
+    let lightness = 0.45 + (hash[15] % 15) as f64 / 100.0;
+    hsl_to_rgb(hue, saturation, lightness)
+}
+
+/// Where the grid lines fall: a margin of a twelfth on each side, and the
+/// rest split into five cells whose edges land on whole pixels.
+fn bounds(size: u32) -> [u32; GRID as usize + 1] {
+    let margin = size / 12;
+    let inner = size - 2 * margin;
+    let mut bounds = [0; GRID as usize + 1];
+    for (i, bound) in bounds.iter_mut().enumerate() {
+        *bound = margin + inner * i as u32 / GRID;
+    }
+    bounds
+}
+
+fn render_svg(frame: &Frame, cells: &[bool], bounds: &[u32], bg: Rgb, fg: Rgb) -> String {
+    let mut rects = String::new();
+    for (i, _) in cells.iter().enumerate().filter(|(_, on)| **on) {
+        let (row, col) = (i / GRID as usize, i % GRID as usize);
+        let _ = write!(
+            rects,
+            "\n\t\t<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" />",
+            bounds[col],
+            bounds[row],
+            bounds[col + 1] - bounds[col],
+            bounds[row + 1] - bounds[row]
+        );
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<svg width="{s}" height="{s}" viewBox="0 0 {s} {s}" xmlns="http://www.w3.org/2000/svg" shape-rendering="crispEdges">
+	<defs>
+		<clipPath id="frame">
+			<rect width="{s}" height="{s}" rx="{r}" ry="{r}" />
+		</clipPath>
+	</defs>
+	<rect width="{s}" height="{s}" rx="{r}" ry="{r}" fill="rgb({},{},{})" />
+	<g clip-path="url(#frame)" fill="rgb({},{},{})">{}
+	</g>
+</svg>"#,
+        bg[0],
+        bg[1],
+        bg[2],
+        fg[0],
+        fg[1],
+        fg[2],
+        rects,
+        s = frame.size,
+        r = frame.radius as i64
+    )
+}
@@ -0,0 +1,262 @@
+//! Initials avatars, ported from the Go server (`pkg/generator/styles`,
+//! `pkg/utils/image.go`) so both servers draw the same image for the same
+//! seed and query.
+
+use crate::color::{gradient, make_soft, palette_color, parse_color, soft_darken, text_color, Rgb};
+use crate::{param, Avatar, Frame};
+use ab_glyph::{Font, FontRef, PxScale, ScaleFont};
+use image::{Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// The font the Go server draws PNG initials with.
+const FONT: &[u8] = include_bytes!("../../../fonts/Inter_24pt-Medium.ttf");
+
+/// `GenerateImageBytes`: the avatar for `name` with the query's `theme`,
+/// `initials`, `iName`, `bg` and `color`.
+pub(crate) fn render(
+    name: &str,
+    query: &HashMap<String, String>,
+    frame: &Frame,
+) -> Result<Avatar, String> {
+    let get = |key: &str| param(query, key);
+
+    let (mut style, mut palette) = ("color", "auto");
+    if !get("theme").is_empty() {
+        let mut parts = get("theme").split('/');
+        style = parts.next().unwrap_or("color");
+        palette = parts.next().unwrap_or("auto");
+    } else if !get("aType").is_empty() {
+        style = get("aType");
+    }
+    if style != "gradient" && style != "soft" {
+        style = "color";
+    }
+
+    let mut text = get("initials").to_string();
+    if text.is_empty() || text == "auto" {
+        let target = match get("iName") {
+            "" => name,
+            other => other,
+        };
+        text = initials(target);
+    }
+
+    let (mut bg1, mut bg2, mut fg) = match style {
+        "soft" => {
+            let seed = if palette == "auto" {
+                gradient(name, "auto").0
+            } else {
+                palette_color(name, palette)
+            };
+            let (bg, text) = make_soft(seed);
+            (bg, soft_darken(bg, 0.05), text)
+        }
+        "gradient" => {
+            let (a, b) = gradient(name, palette);
+            (a, b, text_color(a, b, "gradient", ""))
+        }
+        _ => {
+            let c = palette_color(name, palette);
+            (c, c, text_color(c, c, "color", ""))
+        }
+    };
+
+    let mut custom_bg = false;
+    if let Some(c) = parse_color(get("bg")) {
+        (bg1, bg2) = (c, c);
+        custom_bg = true;
+    }
+    if !get("color").is_empty() {
+        fg = text_color(bg1, bg2, style, get("color"));
+    } else if custom_bg {
+        fg = text_color(bg1, bg2, "custom", "");
+    }
+
+    if frame.svg {
+        let rounded = frame.radius as i64;
+        return Ok(frame.svg(render_svg(frame.size, bg1, bg2, &text, rounded, fg, style)));
+    }
+    render_png(frame, bg1, bg2, &text, fg)
+}
+
+fn render_png(frame: &Frame, bg1: Rgb, bg2: Rgb, text: &str, fg: Rgb) -> Result<Avatar, String> {
+    let size = frame.size;
+    let mut img = RgbaImage::new(size, size);
+    let fsize = size as f64;
+
+    for y in 0..size {
+        for x in 0..size {
+            if frame.clipped(x, y) {
+                continue;
+            }
+            let pixel = if bg1 == bg2 {
+                bg1
+            } else {
+                let ratio = (x as f64 + y as f64) / (2.0 * fsize);
+                let mix = |a: u8, b: u8| (a as f64 * (1.0 - ratio) + b as f64 * ratio) as u8;
+                [
+                    mix(bg1[0], bg2[0]),
+                    mix(bg1[1], bg2[1]),
+                    mix(bg1[2], bg2[2]),
+                ]
+            };
+            img.put_pixel(x, y, Rgba([pixel[0], pixel[1], pixel[2], 255]));
+        }
+    }
+
+    if !text.is_empty() {
+        draw_text(&mut img, text, fg, size);
+    }
+    frame.png(&img)
+}
+
+fn font() -> &'static FontRef<'static> {
+    static FONT_REF: OnceLock<FontRef<'static>> = OnceLock::new();
+    FONT_REF.get_or_init(|| FontRef::try_from_slice(FONT).expect("embedded font parses"))
+}
+
+/// `DrawText`: centered, at half the avatar's size, blended over the background.
+fn draw_text(img: &mut RgbaImage, text: &str, color: Rgb, size: u32) {
+    let font = font().as_scaled(PxScale::from((size / 2) as f32));
+
+    let mut width = 0.0f32;
+    let mut previous = None;
+    let glyphs: Vec<_> = text
+        .chars()
+        .map(|c| {
+            let id = font.glyph_id(c);
+            if let Some(prev) = previous {
+                width += font.kern(prev, id);
+            }
+            let x = width;
+            width += font.h_advance(id);
+            previous = Some(id);
+            (id, x)
+        })
+        .collect();
+
+    let ascent = font.ascent().ceil() as i64;
+    let descent = (-font.descent()).ceil() as i64;
+    let x0 = (size as i64 - width.round() as i64) / 2;
+    let baseline = (size as i64 - (ascent + descent)) / 2 + ascent;
+
+    for (id, x) in glyphs {
+        let glyph = id.with_scale_and_position(
+            font.scale(),
+            ab_glyph::point(x0 as f32 + x, baseline as f32),
+        );
+        let Some(outlined) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outlined.px_bounds();
+        outlined.draw(|gx, gy, coverage| {
+            let px = bounds.min.x as i64 + gx as i64;
+            let py = bounds.min.y as i64 + gy as i64;
+            if px < 0 || py < 0 || px >= size as i64 || py >= size as i64 {
+                return;
+            }
+            let pixel = img.get_pixel_mut(px as u32, py as u32);
+            let a = coverage.clamp(0.0, 1.0);
+            for i in 0..3 {
+                pixel[i] = (color[i] as f32 * a + pixel[i] as f32 * (1.0 - a)).round() as u8;
+            }
+            pixel[3] = (255.0 * a + pixel[3] as f32 * (1.0 - a)).round() as u8;
+        });
+    }
+}
+
+fn render_svg(
+    size: u32,
+    bg1: Rgb,
+    bg2: Rgb,
+    text: &str,
+    rounded: i64,
+    fg: Rgb,
+    style: &str,
+) -> String {
+    let font_size = font_size(size, text);
+    let text_svg = if text.is_empty() {
+        String::new()
+    } else {
+        format!(
+            r#"
+	<text
+		x="50%"
+		y="50%"
+		text-anchor="middle"
+		dominant-baseline="central"
+		font-family="Inter, system-ui, -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif"
+		font-weight="600"
+		font-size="{}"
+		fill="rgb({},{},{})"
+		letter-spacing="-0.03em"
+	>{}</text>"#,
+            font_size, fg[0], fg[1], fg[2], text
+        )
+    };
+
+    if style == "soft" || style == "color" {
+        return format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<svg width="{s}" height="{s}" viewBox="0 0 {s} {s}" xmlns="http://www.w3.org/2000/svg">
+	<rect width="{s}" height="{s}" rx="{r}" ry="{r}" fill="rgb({},{},{})" />
+	{}
+</svg>"#,
+            bg1[0],
+            bg1[1],
+            bg1[2],
+            text_svg,
+            s = size,
+            r = rounded
+        );
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<svg width="{s}" height="{s}" viewBox="0 0 {s} {s}" xmlns="http://www.w3.org/2000/svg">
+	<defs>
+		<linearGradient id="gradient" x1="1" y1="1" x2="0" y2="0">
+			<stop offset="0%" stop-color="rgb({},{},{})" />
+			<stop offset="100%" stop-color="rgb({},{},{})" />
+		</linearGradient>
+	</defs>
+	<rect width="{s}" height="{s}" rx="{r}" ry="{r}" fill="url(#gradient)" />
+	{}
+</svg>"#,
+        bg1[0],
+        bg1[1],
+        bg1[2],
+        bg2[0],
+        bg2[1],
+        bg2[2],
+        text_svg,
+        s = size,
+        r = rounded
+    )
+}
+
+fn font_size(size: u32, text: &str) -> i64 {
+    let base = size as f64 * 0.6;
+    match text.chars().count() {
+        1 => base as i64,
+        2 => (base * 0.72) as i64,
+        _ => (base * 0.63) as i64,
+    }
+}
+
+/// First letters of the first two words, uppercased.
+pub fn initials(name: &str) -> String {
+    let mut out: String = name
+        .split_whitespace()
+        .filter_map(|word| word.chars().next())
+        .flat_map(char::to_uppercase)
+        .take(2)
+        .collect();
+    if out.is_empty() {
+        if let Some(c) = name.chars().next() {
+            out.extend(c.to_uppercase());
+        }
+    }
+    out
+}
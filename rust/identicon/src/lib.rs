@@ -0,0 +1,219 @@
+//! octa-identicon: the default avatars Octa draws for keys nobody uploaded
+//! an image for, deterministic in the identifier.
+//!
+//! Two styles, picked like the servers pick them from `/avatar/<seed>`:
+//! initials on a color, gradient or soft background (the Go server's
+//! `pkg/generator/styles`), and geometric identicons, a mirrored 5×5 grid
+//! in one color. [`generate`] takes the query string of the avatar URL, so
+//! an image made here is the one the server answers for the same seed and
+//! query; [`Options`] builds that query in code.
+//!
+//! ```
+//! use octa_identicon::{Options, Style};
+//!
+//! let avatar = Options::new().style(Style::Identicon).size(64).render("alice@example.com")?;
+//! assert_eq!(avatar.mime, "image/png");
+//! assert_eq!(avatar, Options::new().style(Style::Identicon).size(64).render("alice@example.com")?);
+//! # Ok::<(), String>(())
+//! ```
+
+use image::{ImageEncoder, RgbaImage};
+use std::collections::HashMap;
+
+#[cfg(feature = "cli")]
+mod cli;
+mod color;
+mod identicon;
+mod initials;
+
+pub use initials::initials;
+#[cfg(feature = "cli")]
+pub use cli::cli;
+
+/// Smallest and largest edge a generated avatar is drawn at; `size` is
+/// clamped to these, as both servers do.
+pub const MIN_SIZE: u32 = 16;
+pub const MAX_SIZE: u32 = 1024;
+
+/// Edge length when the query has no `size` (the servers' default
+/// `image.default_size`).
+pub const DEFAULT_SIZE: u32 = 360;
+
+/// A generated avatar and its content type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Avatar {
+    pub data: Vec<u8>,
+    pub mime: &'static str,
+}
+
+/// What an avatar looks like; the `style` query parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Style {
+    /// Up to two initials, centered (`theme` picks the background).
+    #[default]
+    Initials,
+    /// A mirrored 5×5 grid of cells in a color from the identifier.
+    Identicon,
+}
+
+impl Style {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Style::Initials => "initials",
+            Style::Identicon => "identicon",
+        }
+    }
+}
+
+/// The avatar for `name` with the query's `style`, `format`, `size`, `bg`,
+/// `color` and `rounded`, plus `theme`, `initials` and `iName` for
+/// initials. Unknown styles draw initials, as the Go server does.
+pub fn generate(
+    name: &str,
+    query: &HashMap<String, String>,
+    default_size: u32,
+) -> Result<Avatar, String> {
+    let get = |key: &str| param(query, key);
+
+    let svg = match get("format") {
+        "svg" => true,
+        "png" => false,
+        _ => get("type") == "svg",
+    };
+
+    // Like the Go server, only `size` is read; `w` has no effect.
+    let size = match get("size").parse::<i64>() {
+        Ok(s) => s.clamp(MIN_SIZE as i64, MAX_SIZE as i64) as u32,
+        Err(_) => default_size,
+    };
+
+    let radius = match get("rounded") {
+        "true" => size as f64 / 16.0,
+        "" => 0.0,
+        value => match value.parse::<i64>() {
+            Ok(v) => (size as f64 / 2.0) * (v.min(50) as f64 / 100.0) * 2.0,
+            Err(_) => 0.0,
+        },
+    };
+
+    let frame = Frame { size, svg, radius };
+    match get("style") {
+        "identicon" => identicon::render(name, query, &frame),
+        _ => initials::render(name, query, &frame),
+    }
+}
+
+/// Builds the query [`generate`] reads.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    query: HashMap<String, String>,
+    default_size: Option<u32>,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn style(self, style: Style) -> Self {
+        self.param("style", style.as_str())
+    }
+
+    /// Edge length in pixels, clamped to [`MIN_SIZE`]..=[`MAX_SIZE`].
+    pub fn size(self, size: u32) -> Self {
+        self.param("size", size.to_string())
+    }
+
+    /// SVG instead of PNG.
+    pub fn svg(self, svg: bool) -> Self {
+        self.param("format", if svg { "svg" } else { "png" })
+    }
+
+    /// `<style>/<palette>` for initials, e.g. `gradient/pro` or `soft`.
+    pub fn theme(self, theme: impl Into<String>) -> Self {
+        self.param("theme", theme)
+    }
+
+    /// The name initials are taken from, when it is not the identifier.
+    pub fn name(self, name: impl Into<String>) -> Self {
+        self.param("iName", name)
+    }
+
+    /// Any other query parameter of the avatar URL (`bg`, `color`, `rounded`, ...).
+    pub fn param(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.query.insert(key.into(), value.into());
+        self
+    }
+
+    /// Size used when [`Self::size`] is not set (default [`DEFAULT_SIZE`]).
+    pub fn default_size(mut self, size: u32) -> Self {
+        self.default_size = Some(size);
+        self
+    }
+
+    /// The query, as the avatar URL would carry it.
+    pub fn query(&self) -> &HashMap<String, String> {
+        &self.query
+    }
+
+    pub fn render(&self, id: &str) -> Result<Avatar, String> {
+        generate(id, &self.query, self.default_size.unwrap_or(DEFAULT_SIZE))
+    }
+}
+
+/// Size, format and corner radius, read the same way for every style.
+pub(crate) struct Frame {
+    pub size: u32,
+    pub svg: bool,
+    pub radius: f64,
+}
+
+impl Frame {
+    /// Whether the pixel at (`x`, `y`) lies outside the rounded corners.
+    pub fn clipped(&self, x: u32, y: u32) -> bool {
+        let (radius, size) = (self.radius, self.size as f64);
+        if radius <= 0.0 {
+            return false;
+        }
+        let edge = |v: f64| {
+            if v < radius {
+                Some(v - radius)
+            } else if v > size - radius {
+                Some(v - (size - radius))
+            } else {
+                None
+            }
+        };
+        match (edge(x as f64 + 0.5), edge(y as f64 + 0.5)) {
+            (Some(dx), Some(dy)) => dx * dx + dy * dy > radius * radius,
+            _ => false,
+        }
+    }
+
+    pub fn png(&self, img: &RgbaImage) -> Result<Avatar, String> {
+        let mut out = Vec::new();
+        image::codecs::png::PngEncoder::new(&mut out)
+            .write_image(
+                img.as_raw(),
+                self.size,
+                self.size,
+                image::ExtendedColorType::Rgba8,
+            )
+            .map_err(|e| format!("encode error: {}", e))?;
+        Ok(Avatar {
+            data: out,
+            mime: "image/png",
+        })
+    }
+
+    pub fn svg(&self, document: String) -> Avatar {
+        Avatar {
+            data: document.into_bytes(),
+            mime: "image/svg+xml",
+        }
+    }
+}
+
+pub(crate) fn param<'a>(query: &'a HashMap<String, String>, key: &str) -> &'a str {
+    query.get(key).map(String::as_str).unwrap_or("")
+}
@@ -0,0 +1,80 @@
+//! How Octa processes images, in one place for octa-server, octa-warden and
+//! octa-pulse: the upload modes and their resize profiles, format sniffing,
+//! the limits uploads are validated against, what a processed image looks
+//! like when it is checked later, and where the time of processing goes
+//! ([`stages`]).
+//!
+//! The semantics are the Go server's (`processUploadImage` and
+//! `utils.ProcessImage`); `circle` is the one addition.
+//!
+//! ```no_run
+//! use octa_image::Profile;
+//!
+//! let data = std::fs::read("avatar.png").unwrap();
+//! octa_image::validate(&data, octa_image::DEFAULT_MAX_UPLOAD).unwrap();
+//! let profile = Profile::from_fields(Some("square"), Some("512"), None);
+//! let processed = octa_image::process(data, &profile).unwrap();
+//! assert_eq!((processed.width, processed.height), (512, 512));
+//! ```
+
+/// The `image` version every Octa crate builds against.
+pub use image;
+
+mod process;
+mod profile;
+mod sniff;
+pub mod stages;
+
+pub use process::{
+    circle, cover, encode_jpeg, process, process_with, resize, verify, Processed,
+};
+pub use profile::{Mode, Profile};
+pub use sniff::{format_name, is_allowed, sniff, validate};
+
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// JPEG quality of every processed upload.
+pub const QUALITY: u8 = 85;
+
+/// Edge length for `square`, `circle` and `fit` when the upload names none.
+pub const DEFAULT_SIZE: u32 = 256;
+/// Edge lengths an upload may ask for; others are clamped into range.
+pub const SIZES: RangeInclusive<u32> = 16..=2048;
+
+/// Percentage for `scale` when the upload names none.
+pub const DEFAULT_SCALE: u32 = 75;
+/// Percentages an upload may ask for; others are clamped into range.
+pub const SCALES: RangeInclusive<u32> = 1..=100;
+
+/// `image.max_upload_size` when unset (5 MB), as in the Go server.
+pub const DEFAULT_MAX_UPLOAD: u64 = 5 << 20;
+
+/// Why an upload was refused or could not be processed. The messages are
+/// the Go server's, so both servers answer alike.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Larger than the upload limit (bytes).
+    TooLarge(u64),
+    /// Not JPEG or PNG, judged by content.
+    Unsupported,
+    /// `original` upload whose header cannot be read.
+    Unreadable,
+    /// Processed upload that does not decode.
+    Corrupt,
+    Encode(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::TooLarge(limit) => write!(f, "file exceeds size limit ({} bytes)", limit),
+            Error::Unsupported => f.write_str("unsupported file type"),
+            Error::Unreadable => f.write_str("file is not a valid image"),
+            Error::Corrupt => f.write_str("corrupt image data"),
+            Error::Encode(e) => write!(f, "could not encode image: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
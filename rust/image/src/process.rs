@@ -0,0 +1,163 @@
+use crate::sniff::format_name;
+use crate::stages::{Sample, Stage};
+use crate::{Error, Mode, Profile, QUALITY, SIZES};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageFormat, ImageReader};
+use std::io::Cursor;
+
+/// The bytes to store for an upload, with what the `images` row records.
+#[derive(Debug, Clone)]
+pub struct Processed {
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    /// As Go names it (`jpeg`, `png`).
+    pub format: String,
+}
+
+/// Applies `profile` to an upload. `original` only reads the header; every
+/// other mode decodes, resizes and re-encodes.
+pub fn process(data: Vec<u8>, profile: &Profile) -> Result<Processed, Error> {
+    process_with(data, profile, &mut Sample::default())
+}
+
+/// [`process`], timing its sniff, decode, transform and encode stages into
+/// `sample`.
+pub fn process_with(
+    data: Vec<u8>,
+    profile: &Profile,
+    sample: &mut Sample,
+) -> Result<Processed, Error> {
+    let reader = sample
+        .time(Stage::Sniff, || {
+            ImageReader::new(Cursor::new(&data)).with_guessed_format()
+        })
+        .map_err(|_| Error::Unreadable)?;
+    sample.format = reader.format();
+
+    if profile.mode == Mode::Original {
+        let format = reader.format();
+        let (width, height) = sample
+            .time(Stage::Decode, || reader.into_dimensions())
+            .map_err(|_| Error::Unreadable)?;
+        return Ok(Processed {
+            data,
+            width,
+            height,
+            format: format.map(format_name).unwrap_or_default(),
+        });
+    }
+
+    let img = sample
+        .time(Stage::Decode, || reader.decode())
+        .map_err(|_| Error::Corrupt)?;
+    let out = sample.time(Stage::Transform, || resize(&img, profile));
+    let (width, height) = out.dimensions();
+    let (data, format) = sample.time(Stage::Encode, || -> Result<_, Error> {
+        if profile.mode == Mode::Circle {
+            let mut data = Vec::new();
+            out.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+                .map_err(|e| Error::Encode(e.to_string()))?;
+            Ok((data, ImageFormat::Png))
+        } else {
+            Ok((encode_jpeg(&out, QUALITY)?, ImageFormat::Jpeg))
+        }
+    })?;
+    Ok(Processed {
+        data,
+        width,
+        height,
+        format: format_name(format),
+    })
+}
+
+/// The pixels `profile` produces from `img`, before encoding.
+pub fn resize(img: &DynamicImage, profile: &Profile) -> DynamicImage {
+    let size = profile.size;
+    match profile.mode {
+        Mode::Square => cover(img, size, size),
+        Mode::Circle => circle(img, size),
+        Mode::Fit if img.width() > size || img.height() > size => {
+            img.resize(size, size, FilterType::Lanczos3)
+        }
+        Mode::Scale if profile.scale < 100 => {
+            let width = (img.width() * profile.scale / 100).max(1);
+            let height = (img.height() * profile.scale / 100).max(1);
+            img.resize_exact(width, height, FilterType::Lanczos3)
+        }
+        Mode::Fit | Mode::Scale | Mode::Original => img.clone(),
+    }
+}
+
+/// Scales `img` to cover `width`x`height` and crops the center, like Go's
+/// `imaging.Fill(..., imaging.Center, imaging.Lanczos)`.
+pub fn cover(img: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+    img.resize_to_fill(width, height, FilterType::Lanczos3)
+}
+
+/// [`cover`] to `size`x`size`, then the corners outside the inscribed
+/// circle made transparent, with a one-pixel anti-aliased edge.
+pub fn circle(img: &DynamicImage, size: u32) -> DynamicImage {
+    let mut out = cover(img, size, size).to_rgba8();
+    let radius = size as f32 / 2.0;
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        let dx = x as f32 + 0.5 - radius;
+        let dy = y as f32 + 0.5 - radius;
+        let coverage = (radius - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0.0, 1.0);
+        pixel[3] = (pixel[3] as f32 * coverage).round() as u8;
+    }
+    DynamicImage::ImageRgba8(out)
+}
+
+/// JPEG at `quality`. JPEG has no alpha; it is dropped the way Go's
+/// encoder drops it.
+pub fn encode_jpeg(img: &DynamicImage, quality: u8) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    DynamicImage::ImageRgb8(img.to_rgb8())
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut data, quality))
+        .map_err(|e| Error::Encode(e.to_string()))?;
+    Ok(data)
+}
+
+/// Whether a stored image could have come out of `mode`: the shape and
+/// format it produces. `None` when it could; otherwise what is wrong.
+pub fn verify(mode: Mode, width: u32, height: u32, format: Option<ImageFormat>) -> Option<String> {
+    let expected = match mode {
+        Mode::Square | Mode::Fit | Mode::Scale => ImageFormat::Jpeg,
+        Mode::Circle => ImageFormat::Png,
+        Mode::Original => {
+            return (!matches!(format, Some(ImageFormat::Jpeg | ImageFormat::Png)))
+                .then(|| format!("mode={} but image is not a JPEG or PNG", mode));
+        }
+    };
+    if matches!(mode, Mode::Square | Mode::Circle) {
+        if width != height {
+            return Some(format!("mode={} but image is {}x{}", mode, width, height));
+        }
+        if !SIZES.contains(&width) {
+            return Some(format!(
+                "mode={} but size {} is outside {}-{}",
+                mode,
+                width,
+                SIZES.start(),
+                SIZES.end()
+            ));
+        }
+    }
+    if mode == Mode::Fit && width.max(height) > *SIZES.end() {
+        return Some(format!(
+            "mode=fit but image is {}x{}, larger than {}",
+            width,
+            height,
+            SIZES.end()
+        ));
+    }
+    (format != Some(expected)).then(|| {
+        format!(
+            "mode={} but image is not a {}",
+            mode,
+            format_name(expected).to_uppercase()
+        )
+    })
+}
@@ -0,0 +1,102 @@
+use crate::{DEFAULT_SCALE, DEFAULT_SIZE, SCALES, SIZES};
+use std::fmt;
+
+/// The `mode` field of an upload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// Center crop to `size`x`size`, JPEG.
+    Square,
+    /// As `Square`, with the corners outside the inscribed circle made
+    /// transparent; PNG, since JPEG has no alpha.
+    Circle,
+    /// Shrink to fit within `size`x`size`, keeping the aspect ratio; never
+    /// enlarges. JPEG.
+    Fit,
+    /// Both edges to `scale` percent (at least 1px). JPEG.
+    Scale,
+    /// Stored as uploaded.
+    Original,
+}
+
+impl Mode {
+    pub fn parse(value: &str) -> Option<Mode> {
+        match value {
+            "square" => Some(Mode::Square),
+            "circle" => Some(Mode::Circle),
+            "fit" => Some(Mode::Fit),
+            "scale" => Some(Mode::Scale),
+            "original" => Some(Mode::Original),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Mode::Square => "square",
+            Mode::Circle => "circle",
+            Mode::Fit => "fit",
+            Mode::Scale => "scale",
+            Mode::Original => "original",
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A mode with its parameters: what one upload turns into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Profile {
+    pub mode: Mode,
+    /// Edge length for `square`, `circle` and `fit`, within [`SIZES`].
+    pub size: u32,
+    /// Percentage for `scale`, within [`SCALES`].
+    pub scale: u32,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Square,
+            size: DEFAULT_SIZE,
+            scale: DEFAULT_SCALE,
+        }
+    }
+}
+
+impl Profile {
+    /// The profile for an upload's `mode`, `size` and `scale` form fields.
+    /// Missing or unparsable numbers take the default and out-of-range ones
+    /// are clamped. A missing mode is `square`; an unknown one is `square`
+    /// at the default size whatever `size` says, as in the Go server.
+    pub fn from_fields(mode: Option<&str>, size: Option<&str>, scale: Option<&str>) -> Self {
+        let size = clamp(size, DEFAULT_SIZE, SIZES.start(), SIZES.end());
+        let scale = clamp(scale, DEFAULT_SCALE, SCALES.start(), SCALES.end());
+        match mode {
+            None | Some("") => Self {
+                mode: Mode::Square,
+                size,
+                scale,
+            },
+            Some(mode) => match Mode::parse(mode) {
+                Some(mode) => Self { mode, size, scale },
+                None => Self {
+                    scale,
+                    ..Self::default()
+                },
+            },
+        }
+    }
+}
+
+/// The Go server's `ParseInt`: the default when absent or not a number,
+/// else clamped.
+fn clamp(value: Option<&str>, default: u32, min: &u32, max: &u32) -> u32 {
+    match value.and_then(|v| v.trim().parse::<i64>().ok()) {
+        Some(v) => v.clamp(*min as i64, *max as i64) as u32,
+        None => default,
+    }
+}
@@ -0,0 +1,33 @@
+use crate::Error;
+use image::ImageFormat;
+
+/// The format `data` is in, judged by its leading bytes.
+pub fn sniff(data: &[u8]) -> Option<ImageFormat> {
+    image::guess_format(data).ok()
+}
+
+/// Only JPEG and PNG are accepted for upload, judged by content, not by name.
+pub fn is_allowed(data: &[u8]) -> bool {
+    matches!(sniff(data), Some(ImageFormat::Jpeg | ImageFormat::Png))
+}
+
+/// Checks an upload against the limits before it is decoded, returning its
+/// format.
+pub fn validate(data: &[u8], max_upload: u64) -> Result<ImageFormat, Error> {
+    if data.len() as u64 > max_upload {
+        return Err(Error::TooLarge(max_upload));
+    }
+    match sniff(data) {
+        Some(format @ (ImageFormat::Jpeg | ImageFormat::Png)) => Ok(format),
+        _ => Err(Error::Unsupported),
+    }
+}
+
+/// Format names as Go's image package reports them, which is what the
+/// `images.format` column holds.
+pub fn format_name(format: ImageFormat) -> String {
+    match format {
+        ImageFormat::Jpeg => "jpeg".to_string(),
+        other => format!("{:?}", other).to_lowercase(),
+    }
+}
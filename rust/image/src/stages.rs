@@ -0,0 +1,231 @@
+//! Where the time of an image pipeline goes, stage by stage: [`Sample`]
+//! holds the stage times of one asset, [`Timings`] adds up many of them
+//! into per-stage statistics and folded stacks for a flamegraph.
+//!
+//! ```no_run
+//! use octa_image::stages::{Sample, Timings};
+//! use octa_image::Profile;
+//!
+//! let mut timings = Timings::default();
+//! let mut sample = Sample::default();
+//! let data = std::fs::read("avatar.png").unwrap();
+//! octa_image::process_with(data, &Profile::default(), &mut sample).unwrap();
+//! timings.record(&sample);
+//! timings.write_folded("upload", &mut std::io::stdout()).unwrap();
+//! ```
+
+use crate::sniff::format_name;
+use image::ImageFormat;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    /// Getting the bytes: a row, a file, an object or an upload, decrypted
+    /// where they are stored encrypted.
+    Read,
+    /// Telling the format from the leading bytes.
+    Sniff,
+    /// Decoding to pixels, or only the header where that is enough.
+    Decode,
+    /// Resizing, cropping, color conversion.
+    Transform,
+    Encode,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 5] = [
+        Stage::Read,
+        Stage::Sniff,
+        Stage::Decode,
+        Stage::Transform,
+        Stage::Encode,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Stage::Read => "read",
+            Stage::Sniff => "sniff",
+            Stage::Decode => "decode",
+            Stage::Transform => "transform",
+            Stage::Encode => "encode",
+        }
+    }
+}
+
+/// The stage times of one asset. A stage that runs more than once (the
+/// extra BLOB columns of a row) adds up.
+#[derive(Debug, Clone, Default)]
+pub struct Sample {
+    /// The sniffed format, which the folded stacks are grouped by.
+    pub format: Option<ImageFormat>,
+    times: [Option<Duration>; 5],
+}
+
+impl Sample {
+    /// Runs `f` as `stage`.
+    pub fn time<T>(&mut self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let out = f();
+        self.add(stage, started.elapsed());
+        out
+    }
+
+    pub fn add(&mut self, stage: Stage, took: Duration) {
+        let time = &mut self.times[stage as usize];
+        *time = Some(time.unwrap_or_default() + took);
+    }
+
+    /// `None` when the stage did not run.
+    pub fn get(&self, stage: Stage) -> Option<Duration> {
+        self.times[stage as usize]
+    }
+}
+
+/// Histogram buckets per power of two: percentiles are exact to 1/8.
+const SUB_BUCKETS: u64 = 8;
+/// Covers up to 2^40 µs, about 12 days.
+const BUCKETS: usize = 38 * SUB_BUCKETS as usize;
+
+/// One stage over many assets.
+#[derive(Debug, Clone)]
+pub struct StageStats {
+    /// Assets the stage ran for.
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+    /// Counts by microseconds, log-linear.
+    buckets: Vec<u64>,
+}
+
+impl Default for StageStats {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+            buckets: vec![0; BUCKETS],
+        }
+    }
+}
+
+impl StageStats {
+    fn add(&mut self, took: Duration) {
+        self.count += 1;
+        self.total += took;
+        self.max = self.max.max(took);
+        self.buckets[bucket(took.as_micros() as u64)] += 1;
+    }
+
+    fn merge(&mut self, other: &StageStats) {
+        self.count += other.count;
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+        for (mine, theirs) in self.buckets.iter_mut().zip(&other.buckets) {
+            *mine += theirs;
+        }
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        self.total.div_f64(self.count as f64)
+    }
+
+    /// The time `q` (0-1) of the assets took at most, e.g. `0.95` for the
+    /// 95th percentile; rounded up to its histogram bucket, never above
+    /// [`StageStats::max`].
+    pub fn percentile(&self, q: f64) -> Duration {
+        let rank = ((self.count as f64 * q).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_micros(upper_bound(index)).min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+/// Eight linear buckets per power of two, below 8 µs one per microsecond.
+fn bucket(micros: u64) -> usize {
+    if micros < SUB_BUCKETS {
+        return micros as usize;
+    }
+    let octave = 63 - micros.leading_zeros() as u64;
+    let sub = (micros >> (octave - 3)) & (SUB_BUCKETS - 1);
+    (((octave - 2) * SUB_BUCKETS + sub) as usize).min(BUCKETS - 1)
+}
+
+fn upper_bound(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return index;
+    }
+    let octave = index / SUB_BUCKETS + 2;
+    let sub = index % SUB_BUCKETS;
+    ((SUB_BUCKETS + sub + 1) << (octave - 3)) - 1
+}
+
+/// The stage statistics of a run, and its time by format and stage for
+/// folded stacks. Threads keep their own and [`Timings::merge`] them.
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    /// Assets recorded.
+    pub assets: u64,
+    stages: [StageStats; 5],
+    /// Microseconds by (format, stage).
+    folded: BTreeMap<(String, Stage), u64>,
+}
+
+impl Timings {
+    pub fn record(&mut self, sample: &Sample) {
+        self.assets += 1;
+        let format = sample
+            .format
+            .map(format_name)
+            .unwrap_or_else(|| "unknown".to_string());
+        for stage in Stage::ALL {
+            let Some(took) = sample.get(stage) else {
+                continue;
+            };
+            self.stages[stage as usize].add(took);
+            *self.folded.entry((format.clone(), stage)).or_default() += took.as_micros() as u64;
+        }
+    }
+
+    pub fn merge(&mut self, other: &Timings) {
+        self.assets += other.assets;
+        for (mine, theirs) in self.stages.iter_mut().zip(&other.stages) {
+            mine.merge(theirs);
+        }
+        for (key, micros) in &other.folded {
+            *self.folded.entry(key.clone()).or_default() += micros;
+        }
+    }
+
+    pub fn stage(&self, stage: Stage) -> &StageStats {
+        &self.stages[stage as usize]
+    }
+
+    /// The stages that ran at least once, in pipeline order.
+    pub fn ran(&self) -> impl Iterator<Item = (Stage, &StageStats)> {
+        Stage::ALL
+            .into_iter()
+            .map(|stage| (stage, self.stage(stage)))
+            .filter(|(_, stats)| stats.count > 0)
+    }
+
+    /// One `root;format;stage microseconds` line per format and stage, the
+    /// folded-stack input of `flamegraph.pl`, `inferno-flamegraph` and
+    /// speedscope.
+    pub fn write_folded(&self, root: &str, out: &mut impl Write) -> io::Result<()> {
+        for ((format, stage), micros) in &self.folded {
+            writeln!(out, "{};{};{} {}", root, format, stage.as_str(), micros)?;
+        }
+        Ok(())
+    }
+}
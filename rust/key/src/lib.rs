@@ -0,0 +1,193 @@
+//! What a legal Octa key is, in one place for octa-server, octa-ctl,
+//! octa-pulse, octa-warden and the tools that read or generate keys.
+//!
+//! A key is stored normalized: trimmed, lowercased, without leading,
+//! trailing or doubled slashes. It is then valid when it
+//!
+//! * uses only `a-z 0-9 - _ / @`, so no `.`: no `..` segment can walk out
+//!   of a directory, and no key looks like a file name with an extension,
+//! * is at most [`MAX_LEN`] bytes and [`MAX_DEPTH`] segments deep,
+//! * does not start with a [`RESERVED`] prefix.
+//!
+//! The character set is the Go server's (`utils.IsValidKeyFormat`); the
+//! length, depth and prefix limits are tighter than what it accepts.
+//!
+//! ```
+//! assert_eq!(octa_key::parse(" /Team//Alice/ ").unwrap(), "team/alice");
+//! assert!(octa_key::parse("../etc/passwd").is_err());
+//! assert!(octa_key::parse("-rf").is_err());
+//!
+//! // The `keys` field of an upload: valid ones, unique, in the order given
+//! assert_eq!(octa_key::parse_list("alice, ALICE,bad key,bob"), ["alice", "bob"]);
+//! ```
+
+use std::fmt;
+
+/// Longest key, in bytes (keys are ASCII).
+pub const MAX_LEN: usize = 255;
+
+/// Most `/`-separated segments in a key.
+pub const MAX_DEPTH: usize = 8;
+
+/// Prefixes no key may start with. A leading `-` reads as an option to
+/// every command line that takes a key (`octa-ctl get -x`).
+pub const RESERVED: &[&str] = &["-"];
+
+/// Why a key is not legal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Invalid {
+    /// Nothing left after normalizing.
+    Empty,
+    /// Longer than [`MAX_LEN`] (bytes).
+    TooLong(usize),
+    /// More than [`MAX_DEPTH`] segments.
+    TooDeep(usize),
+    /// A character outside `a-z 0-9 - _ / @`.
+    Character(char),
+    /// A leading, trailing or doubled `/`, which [`normalize`] removes.
+    Slashes,
+    /// Starts with this [`RESERVED`] prefix.
+    Reserved(&'static str),
+}
+
+impl fmt::Display for Invalid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Invalid::Empty => f.write_str("key is empty"),
+            Invalid::TooLong(len) => {
+                write!(f, "key is {} bytes long, at most {} allowed", len, MAX_LEN)
+            }
+            Invalid::TooDeep(depth) => write!(
+                f,
+                "key has {} segments, at most {} allowed",
+                depth, MAX_DEPTH
+            ),
+            Invalid::Character(c) => write!(
+                f,
+                "key contains '{}'; allowed: a-z, 0-9, -, _, /, @",
+                c.escape_default()
+            ),
+            Invalid::Slashes => f.write_str("key has a leading, trailing or doubled '/'"),
+            Invalid::Reserved(prefix) => write!(f, "keys may not start with '{}'", prefix),
+        }
+    }
+}
+
+impl std::error::Error for Invalid {}
+
+/// Trimmed, lowercased, without leading/trailing or doubled slashes; what
+/// the servers store and look up.
+///
+/// ```
+/// assert_eq!(octa_key::normalize("  //Team///Alice/ "), "team/alice");
+/// assert_eq!(octa_key::normalize(" / Alice"), "alice");
+/// ```
+pub fn normalize(raw: &str) -> String {
+    let mut key = raw
+        .trim_matches(|c: char| c.is_whitespace() || c == '/')
+        .to_lowercase();
+    while key.contains("//") {
+        key = key.replace("//", "/");
+    }
+    key
+}
+
+/// Checks a key as stored; run [`normalize`] on user input first, or use
+/// [`parse`].
+pub fn validate(key: &str) -> Result<(), Invalid> {
+    if key.is_empty() {
+        return Err(Invalid::Empty);
+    }
+    if let Some(c) = key.chars().find(|c| !allowed(*c)) {
+        return Err(Invalid::Character(c));
+    }
+    if key.split('/').any(str::is_empty) {
+        return Err(Invalid::Slashes);
+    }
+    if let Some(prefix) = RESERVED.iter().find(|p| key.starts_with(*p)) {
+        return Err(Invalid::Reserved(prefix));
+    }
+    if key.len() > MAX_LEN {
+        return Err(Invalid::TooLong(key.len()));
+    }
+    let depth = key.split('/').count();
+    if depth > MAX_DEPTH {
+        return Err(Invalid::TooDeep(depth));
+    }
+    Ok(())
+}
+
+/// Normalizes and checks user input, returning the key to store.
+pub fn parse(raw: &str) -> Result<String, Invalid> {
+    let key = normalize(raw);
+    validate(&key)?;
+    Ok(key)
+}
+
+/// The comma-separated `keys` field of an upload: the valid keys, unique,
+/// in the order given. Invalid ones are dropped, as both servers do.
+pub fn parse_list(raw: &str) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    for key in raw.split(',').filter_map(|k| parse(k).ok()) {
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    keys
+}
+
+/// Checks a prefix that keys are generated under or listed by (`seed/`,
+/// `team/a`): empty, or the start of a valid key. A trailing `/` is kept.
+///
+/// ```
+/// assert!(octa_key::validate_prefix("").is_ok());
+/// assert!(octa_key::validate_prefix("seed/").is_ok());
+/// assert!(octa_key::validate_prefix("Seed/").is_err());
+/// ```
+pub fn validate_prefix(prefix: &str) -> Result<(), Invalid> {
+    if prefix.is_empty() {
+        return Ok(());
+    }
+    validate(prefix.strip_suffix('/').unwrap_or(prefix))
+}
+
+/// A file name for a key's image, without extension: `/` becomes `%2F`
+/// (and `%` itself `%25`), and everything else is already safe in a path.
+/// Unlike `_`, which is a key character too, the escape is reversible, so
+/// two keys never share a file; [`from_file_name`] undoes it.
+///
+/// ```
+/// assert_eq!(octa_key::file_name("team/alice"), "team%2Falice");
+/// assert_ne!(octa_key::file_name("team/alice"), octa_key::file_name("team_alice"));
+/// ```
+pub fn file_name(key: &str) -> String {
+    key.replace('%', "%25").replace('/', "%2F")
+}
+
+/// The key a [`file_name`] was made from, or `None` when the name holds a
+/// `%` that is not one of its escapes.
+///
+/// ```
+/// assert_eq!(octa_key::from_file_name("team%2Falice").as_deref(), Some("team/alice"));
+/// assert_eq!(octa_key::from_file_name("100%"), None);
+/// ```
+pub fn from_file_name(name: &str) -> Option<String> {
+    let mut key = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(at) = rest.find('%') {
+        key.push_str(&rest[..at]);
+        let escape = rest.get(at..at + 3)?;
+        key.push(match escape {
+            "%2F" | "%2f" => '/',
+            "%25" => '%',
+            _ => return None,
+        });
+        rest = &rest[at + 3..];
+    }
+    key.push_str(rest);
+    Some(key)
+}
+
+fn allowed(c: char) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_' | '/' | '@')
+}
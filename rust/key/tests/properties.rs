@@ -0,0 +1,61 @@
+//! Properties of key normalization and validation over generated input.
+
+use proptest::prelude::*;
+
+/// Raw input close to keys: key characters, uppercase, whitespace, dots,
+/// slashes and control characters, so every rule gets exercised.
+fn raw() -> impl Strategy<Value = String> {
+    "[a-zA-Z0-9_@./ \t\n\u{0}\u{7f}-]{0,40}"
+}
+
+/// A valid key, written the way users do: any case, surrounded by
+/// whitespace and slashes, with doubled slashes between segments.
+fn sloppy_key() -> impl Strategy<Value = String> {
+    (
+        prop::collection::vec("[a-zA-Z0-9_@][a-zA-Z0-9_@-]{0,10}", 1..=octa_key::MAX_DEPTH),
+        prop::collection::vec("/{1,3}", octa_key::MAX_DEPTH),
+        "[ \t/]{0,3}",
+        "[ \t/]{0,3}",
+    )
+        .prop_map(|(segments, slashes, before, after)| {
+            let mut key = before;
+            for (i, segment) in segments.iter().enumerate() {
+                if i > 0 {
+                    key.push_str(&slashes[i]);
+                }
+                key.push_str(segment);
+            }
+            key + &after
+        })
+}
+
+proptest! {
+    #[test]
+    fn normalize_is_idempotent(raw in prop_oneof![raw(), any::<String>()]) {
+        let once = octa_key::normalize(&raw);
+        prop_assert_eq!(octa_key::normalize(&once), once);
+    }
+
+    #[test]
+    fn normalized_keys_validate(raw in sloppy_key()) {
+        let key = octa_key::normalize(&raw);
+        prop_assert_eq!(octa_key::validate(&key), Ok(()), "{:?} -> {:?}", raw, key);
+    }
+
+    #[test]
+    fn accepted_keys_are_safe(raw in prop_oneof![raw(), any::<String>()]) {
+        if let Ok(key) = octa_key::parse(&raw) {
+            prop_assert!(!key.contains(".."), "{:?}", key);
+            prop_assert!(!key.starts_with('/'), "{:?}", key);
+            prop_assert!(!key.chars().any(char::is_control), "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn file_names_are_reversible(raw in sloppy_key()) {
+        let key = octa_key::normalize(&raw);
+        let name = octa_key::file_name(&key);
+        prop_assert!(!name.contains('/'));
+        prop_assert_eq!(octa_key::from_file_name(&name), Some(key));
+    }
+}
@@ -0,0 +1,331 @@
+use crate::{ApiKey, Status};
+use clap::{Parser, Subcommand};
+use octa_config::{ConfigError, DatabaseConfig, Validate};
+use octa_errors::{Error, Kind};
+use octa_logging::LogFormat;
+use octa_warden_core::db::{self, OpenOptions};
+use octa_warden_core::schedule::{parse_interval, parse_jitter};
+use rusqlite::Connection;
+use serde::Deserialize;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+use tracing::{error, info, warn, Level};
+
+/*
+OCTA-KEYS: Upload secrets in the database
+=============================================
+Mission: Issue, list, revoke and rotate the secrets clients upload with,
+         in the instance's database instead of the config.yaml of every
+         host. `rotate --grace 24h` issues a new secret and keeps the old
+         one working for a day of rollout.
+Safety:  Secrets are printed once, when issued, and stored only as SHA-256.
+         Revoking takes effect on the next request. security.upload_secret
+         is left alone and keeps working.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Issue, list, revoke and rotate Octa upload secrets"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long, global = true)]
+    config: Option<String>,
+
+    /// SQLite database holding the keys (overrides database.path; the config file becomes optional)
+    #[arg(long = "db", env = "OCTA_DB_PATH", value_name = "PATH", global = true)]
+    db_path: Option<String>,
+
+    /// Milliseconds to wait on a locked database before giving up (SQLITE_BUSY)
+    #[arg(long, default_value_t = 5000, global = true)]
+    busy_timeout: u64,
+
+    /// Log format
+    #[arg(
+        long,
+        env = "OCTA_LOG_FORMAT",
+        value_enum,
+        global = true,
+        default_value_t = LogFormat::Pretty
+    )]
+    log_format: LogFormat,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Issue a key and print its secret (shown only this once)
+    Generate {
+        /// Who holds the key, e.g. ci or mobile-app
+        name: String,
+
+        /// Let the key stop working after this long, e.g. 90d (default: never)
+        #[arg(long)]
+        expires_in: Option<String>,
+    },
+    /// List the keys that work (all of them with --all)
+    List {
+        /// Include expired and revoked keys
+        #[arg(long)]
+        all: bool,
+
+        /// Print JSON instead of columns
+        #[arg(long)]
+        json: bool,
+    },
+    /// Stop a key from working
+    Revoke {
+        /// Key id, or the name of exactly one working key
+        target: String,
+    },
+    /// Issue a successor of a key and print its secret; the old one works for the grace period
+    Rotate {
+        /// Key id, or the name of exactly one working key
+        target: String,
+
+        /// How long the old key keeps working, e.g. 24h; 0 revokes it now (overrides keys.grace)
+        #[arg(long)]
+        grace: Option<String>,
+    },
+}
+
+/// The parts of config.yaml octa-keys reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    database: DatabaseConfig,
+    keys: KeysConfig,
+}
+
+/// `keys:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct KeysConfig {
+    /// How long a rotated key keeps working without --grace.
+    grace: String,
+}
+
+impl Default for KeysConfig {
+    fn default() -> Self {
+        Self {
+            grace: "24h".to_string(),
+        }
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        if self.database.path.trim().is_empty() {
+            problems.push(("database.path".to_string(), "is required".to_string()));
+        }
+        if let Err(e) = parse_jitter(&self.keys.grace) {
+            problems.push(("keys.grace".to_string(), e));
+        }
+        problems
+    }
+}
+
+/// Like octa-gc: `--db` makes the config file optional.
+fn load_config(path: Option<&str>, db_path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    let found = octa_config::discover(path.map(Path::new)).filter(|p| p.exists());
+    let mut config: FileConfig = match (&found, db_path) {
+        (Some(found), _) => octa_config::read(found)?,
+        (None, Some(_)) => octa_config::from_env()?,
+        (None, None) => {
+            return Err(match path {
+                Some(path) => ConfigError::Read {
+                    path: path.into(),
+                    source: std::io::ErrorKind::NotFound.into(),
+                },
+                None => ConfigError::NotFound,
+            })
+        }
+    };
+    if let Some(db_path) = db_path {
+        config.database.path = db_path.to_string();
+    }
+    octa_config::check(found.as_deref().unwrap_or(Path::new("<defaults>")), config)
+}
+
+/// The octa-keys command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa keys` the arguments it was given.
+pub fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    // Secrets and the list go to stdout, everything else to stderr.
+    octa_logging::init(args.log_format, Level::INFO, false, true);
+
+    let config = match load_config(args.config.as_deref(), args.db_path.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let db_path = config.database.path.as_str();
+    if !Path::new(db_path).exists() {
+        error!(tag = "FATAL", path = %db_path, "Database file not found");
+        return Kind::NoInput.exit_code();
+    }
+    let opts = OpenOptions {
+        busy_timeout: Duration::from_millis(args.busy_timeout),
+        immutable: false,
+    };
+    let conn = match db::open_read_write(db_path, &opts) {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!(tag = "FATAL", path = %db_path, reason = %e, "Could not open database");
+            return Kind::Io.exit_code();
+        }
+    };
+
+    match run(&conn, args.command, &config.keys) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!(tag = "FATAL", path = %db_path, reason = %e, "Key management failed");
+            e.exit_code()
+        }
+    }
+}
+
+fn run(conn: &Connection, command: Command, config: &KeysConfig) -> Result<(), Error> {
+    crate::ensure_table(conn)?;
+    match command {
+        Command::Generate { name, expires_in } => {
+            if name.trim().is_empty() {
+                return Err(Error::new(Kind::Usage, "the name must not be empty"));
+            }
+            let lifetime = expires_in
+                .as_deref()
+                .map(parse_interval)
+                .transpose()
+                .map_err(|e| Error::new(Kind::Usage, format!("--expires-in: {}", e)))?;
+            let (key, secret) = crate::generate(conn, &name, lifetime)?;
+            println!("{}", secret);
+            info!(
+                tag = "OK",
+                id = %key.id,
+                name = %key.name,
+                expires = key.expires_at.as_deref().unwrap_or("never"),
+                "Key issued; the secret above is not shown again"
+            );
+        }
+        Command::List { all, json } => {
+            let now = crate::now();
+            let keys: Vec<ApiKey> = crate::list(conn)?
+                .into_iter()
+                .filter(|key| all || working(key, &now))
+                .collect();
+            if json {
+                let rows: Vec<_> = keys
+                    .iter()
+                    .map(|key| ListedKey {
+                        key,
+                        status: key.status(&now).as_str(),
+                    })
+                    .collect();
+                let text = serde_json::to_string_pretty(&rows)
+                    .map_err(|e| Error::new(Kind::Internal, e.to_string()))?;
+                println!("{}", text);
+                return Ok(());
+            }
+            let width = keys.iter().map(|k| k.name.len()).max().unwrap_or(0);
+            for key in &keys {
+                let status = key.status(&now);
+                let until = match status {
+                    Status::Revoked => key.revoked_at.as_deref(),
+                    _ => key.expires_at.as_deref(),
+                };
+                println!(
+                    "{}  {:<width$}  {}…  {:<8}  {}  {}",
+                    key.id,
+                    key.name,
+                    key.hint,
+                    status.as_str(),
+                    key.created_at,
+                    until.unwrap_or("-"),
+                );
+            }
+            eprintln!("{} key(s)", keys.len());
+        }
+        Command::Revoke { target } => {
+            let key = resolve(conn, &target)?;
+            if crate::revoke(conn, &key.id)? {
+                info!(tag = "OK", id = %key.id, name = %key.name, "Key revoked");
+            } else {
+                warn!(tag = "WARN", id = %key.id, name = %key.name, "Key was already revoked");
+            }
+        }
+        Command::Rotate { target, grace } => {
+            let key = resolve(conn, &target)?;
+            if !working(&key, &crate::now()) {
+                return Err(Error::new(
+                    Kind::Usage,
+                    format!(
+                        "{} is {}; issue a new key with generate",
+                        key.id,
+                        key.status(&crate::now()).as_str()
+                    ),
+                ));
+            }
+            // A zero grace is allowed here, unlike an interval: it revokes.
+            let grace = grace.as_deref().unwrap_or(&config.grace);
+            let grace = parse_jitter(grace)
+                .map_err(|e| Error::new(Kind::Usage, format!("--grace: {}", e)))?;
+            let (new, secret) = crate::rotate(conn, &key.id, grace)?;
+            println!("{}", secret);
+            info!(
+                tag = "OK",
+                id = %new.id,
+                name = %new.name,
+                replaces = %key.id,
+                grace_secs = grace.as_secs(),
+                "Key rotated; the secret above is not shown again"
+            );
+        }
+    }
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+struct ListedKey<'a> {
+    #[serde(flatten)]
+    key: &'a ApiKey,
+    status: &'static str,
+}
+
+fn working(key: &ApiKey, now: &str) -> bool {
+    matches!(key.status(now), Status::Active | Status::Expiring)
+}
+
+/// The one key `target` names, by id or by the name of a working key.
+fn resolve(conn: &Connection, target: &str) -> Result<ApiKey, Error> {
+    let mut keys = crate::find(conn, target)?;
+    match keys.len() {
+        0 => Err(Error::new(
+            Kind::NoInput,
+            format!(
+                "no key with the id or working key with the name '{}'",
+                target
+            ),
+        )),
+        1 => Ok(keys.remove(0)),
+        _ => Err(Error::new(
+            Kind::Usage,
+            format!(
+                "'{}' names {} working keys ({}); pass an id",
+                target,
+                keys.len(),
+                keys.iter()
+                    .map(|k| k.id.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        )),
+    }
+}
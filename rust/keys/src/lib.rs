@@ -0,0 +1,272 @@
+//! octa-keys: upload secrets kept in the database next to the images, so a
+//! secret can be issued, rotated or revoked once instead of in the
+//! `config.yaml` of every host.
+//!
+//! Each key has a public id (`key_…`), a name saying who holds it, and a
+//! secret that is only shown when the key is made: the table stores its
+//! SHA-256. A key works until it is revoked or its `expires_at` passes;
+//! rotating a key issues a new one under the same name and lets the old
+//! one expire after a grace period, so clients can switch over at their
+//! own pace. The Rust server accepts any working key as `X-Secret-Key`, in
+//! addition to `security.upload_secret`.
+//!
+//! ```
+//! use rusqlite::Connection;
+//!
+//! let conn = Connection::open_in_memory()?;
+//! octa_keys::ensure_table(&conn)?;
+//! let (ci, secret) = octa_keys::generate(&conn, "ci", None)?;
+//! assert_eq!(octa_keys::verify(&conn, &secret)?.map(|k| k.id), Some(ci.id.clone()));
+//!
+//! let (_, rotated) = octa_keys::rotate(&conn, &ci.id, std::time::Duration::ZERO)?;
+//! assert!(octa_keys::verify(&conn, &secret)?.is_none());
+//! assert!(octa_keys::verify(&conn, &rotated)?.is_some());
+//! # Ok::<(), rusqlite::Error>(())
+//! ```
+
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+#[cfg(feature = "cli")]
+mod cli;
+#[cfg(feature = "cli")]
+pub use cli::cli;
+
+/// The table the keys live in.
+pub const TABLE: &str = "api_keys";
+
+const CREATE_TABLE: &str = "CREATE TABLE IF NOT EXISTS api_keys (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    secret_hash TEXT NOT NULL UNIQUE,
+    hint TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    expires_at TEXT,
+    revoked_at TEXT
+)";
+
+/// Secrets start with this, so they are recognizable in a leaked file.
+pub const SECRET_PREFIX: &str = "octa_";
+
+/// One row of the key table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(serde::Serialize))]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    /// The first characters of the secret, to tell keys apart.
+    pub hint: String,
+    /// RFC 3339, UTC, like every timestamp of the table.
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+/// Where a key stands at a given moment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Active,
+    /// Works, but has an expiry (e.g. the old key of a rotation).
+    Expiring,
+    Expired,
+    Revoked,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Active => "active",
+            Status::Expiring => "expiring",
+            Status::Expired => "expired",
+            Status::Revoked => "revoked",
+        }
+    }
+}
+
+impl ApiKey {
+    pub fn status(&self, now: &str) -> Status {
+        match (&self.revoked_at, &self.expires_at) {
+            (Some(_), _) => Status::Revoked,
+            (None, Some(expires)) if expires.as_str() <= now => Status::Expired,
+            (None, Some(_)) => Status::Expiring,
+            (None, None) => Status::Active,
+        }
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            hint: row.get(2)?,
+            created_at: row.get(3)?,
+            expires_at: row.get(4)?,
+            revoked_at: row.get(5)?,
+        })
+    }
+}
+
+const COLUMNS: &str = "id, name, hint, created_at, expires_at, revoked_at";
+
+/// The current time as the table stores it. Fixed width, so timestamps
+/// compare as text.
+pub fn now() -> String {
+    timestamp(chrono::Utc::now())
+}
+
+fn timestamp(at: chrono::DateTime<chrono::Utc>) -> String {
+    at.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+fn later(by: Duration) -> String {
+    let by = chrono::Duration::from_std(by).unwrap_or(chrono::Duration::MAX);
+    let at = chrono::Utc::now()
+        .checked_add_signed(by)
+        .unwrap_or(chrono::DateTime::<chrono::Utc>::MAX_UTC);
+    timestamp(at)
+}
+
+/// Creates the table unless it exists.
+pub fn ensure_table(conn: &Connection) -> Result<()> {
+    conn.execute(CREATE_TABLE, []).map(|_| ())
+}
+
+/// Whether the database has a key table at all; the server does not
+/// create it.
+pub fn has_table(conn: &Connection) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+        [TABLE],
+        |row| row.get(0),
+    )
+}
+
+/// Issues a key named `name`, working for `lifetime` (or until revoked).
+/// The secret is returned here and nowhere else.
+pub fn generate(
+    conn: &Connection,
+    name: &str,
+    lifetime: Option<Duration>,
+) -> Result<(ApiKey, String)> {
+    let secret = format!("{}{}", SECRET_PREFIX, random_hex(32));
+    let key = ApiKey {
+        id: format!("key_{}", random_hex(6)),
+        name: name.to_string(),
+        hint: secret[..SECRET_PREFIX.len() + 6].to_string(),
+        created_at: now(),
+        expires_at: lifetime.map(later),
+        revoked_at: None,
+    };
+    conn.execute(
+        "INSERT INTO api_keys (id, name, secret_hash, hint, created_at, expires_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            key.id,
+            key.name,
+            hash(&secret),
+            key.hint,
+            key.created_at,
+            key.expires_at
+        ],
+    )?;
+    Ok((key, secret))
+}
+
+/// Every key, oldest first.
+pub fn list(conn: &Connection) -> Result<Vec<ApiKey>> {
+    conn.prepare(&format!(
+        "SELECT {} FROM api_keys ORDER BY created_at, rowid",
+        COLUMNS
+    ))?
+    .query_map([], ApiKey::from_row)?
+    .collect()
+}
+
+/// The keys `target` names: the key with that id, else the working keys
+/// with that name.
+pub fn find(conn: &Connection, target: &str) -> Result<Vec<ApiKey>> {
+    let by_id = conn
+        .query_row(
+            &format!("SELECT {} FROM api_keys WHERE id = ?1", COLUMNS),
+            [target],
+            ApiKey::from_row,
+        )
+        .optional()?;
+    if let Some(key) = by_id {
+        return Ok(vec![key]);
+    }
+    conn.prepare(&format!(
+        "SELECT {} FROM api_keys
+         WHERE name = ?1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > ?2)
+         ORDER BY created_at, rowid",
+        COLUMNS
+    ))?
+    .query_map(params![target, now()], ApiKey::from_row)?
+    .collect()
+}
+
+/// Stops the key with `id` from working. False when it already did not.
+pub fn revoke(conn: &Connection, id: &str) -> Result<bool> {
+    let changed = conn.execute(
+        "UPDATE api_keys SET revoked_at = ?2 WHERE id = ?1 AND revoked_at IS NULL",
+        params![id, now()],
+    )?;
+    Ok(changed > 0)
+}
+
+/// Issues a successor of the key with `id` under the same name, and lets
+/// the old key work for `grace` more (revoking it when `grace` is zero).
+/// An earlier expiry of the old key is kept. Both happen in one
+/// transaction.
+pub fn rotate(conn: &Connection, id: &str, grace: Duration) -> Result<(ApiKey, String)> {
+    let tx = conn.unchecked_transaction()?;
+    let old = tx.query_row(
+        &format!("SELECT {} FROM api_keys WHERE id = ?1", COLUMNS),
+        [id],
+        ApiKey::from_row,
+    )?;
+    if grace.is_zero() {
+        revoke(&tx, id)?;
+    } else {
+        tx.execute(
+            "UPDATE api_keys SET expires_at = ?2
+             WHERE id = ?1 AND (expires_at IS NULL OR expires_at > ?2)",
+            params![id, later(grace)],
+        )?;
+    }
+    let new = generate(&tx, &old.name, None)?;
+    tx.commit()?;
+    Ok(new)
+}
+
+/// The working key whose secret is `secret`, if any. Secrets are looked up
+/// by their hash, which leaks nothing about them through timing.
+pub fn verify(conn: &Connection, secret: &str) -> Result<Option<ApiKey>> {
+    if !secret.starts_with(SECRET_PREFIX) || !has_table(conn)? {
+        return Ok(None);
+    }
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM api_keys
+             WHERE secret_hash = ?1 AND revoked_at IS NULL
+               AND (expires_at IS NULL OR expires_at > ?2)",
+            COLUMNS
+        ),
+        params![hash(secret), now()],
+        ApiKey::from_row,
+    )
+    .optional()
+}
+
+fn hash(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    getrandom::fill(&mut buf).expect("the OS random number generator is available");
+    buf.iter().map(|b| format!("{:02x}", b)).collect()
+}
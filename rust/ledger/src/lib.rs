@@ -0,0 +1,392 @@
+//! Tamper-evident record of administrative actions: the `ledger` section of
+//! `config.yaml` and an append-only NDJSON file that octa-ctl, octa-warden
+//! and octa-rekey add every upload, delete, purge, repair and rename to, so
+//! "who deleted what, when" has an answer after the fact.
+//!
+//! Each line carries the SHA-256 of its own content and of the line before
+//! it, so editing, removing, inserting or reordering lines breaks the chain
+//! at that point, which [`verify`] reports.
+//!
+//! ```no_run
+//! use octa_ledger::{Ledger, LedgerConfig};
+//!
+//! # fn example(cfg: &LedgerConfig) -> Result<(), octa_ledger::Error> {
+//! if let Some(ledger) = Ledger::open(cfg, "octa-ctl")? {
+//!     ledger.record("delete", &["alice".to_string()], &[("asset", "a1b2".to_string())])?;
+//! }
+//! let verification = octa_ledger::verify(cfg.path.as_deref().unwrap(), None)?;
+//! assert!(verification.is_intact());
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// `prev` of the first entry.
+pub const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// `ledger:` of config.yaml.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LedgerConfig {
+    /// The ledger file; unset records nothing.
+    pub path: Option<PathBuf>,
+    /// Who the actions are recorded for (default: `$USER@<host>`).
+    pub actor: Option<String>,
+}
+
+impl LedgerConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// `(field, problem)` pairs, as `octa_config::Validate` reports them.
+    pub fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        if self.path.as_ref().is_some_and(|p| p.as_os_str().is_empty()) {
+            problems.push((
+                "ledger.path".to_string(),
+                "must not be empty (leave it out to record nothing)".to_string(),
+            ));
+        }
+        if self.actor.as_ref().is_some_and(|a| a.trim().is_empty()) {
+            problems.push(("ledger.actor".to_string(), "must not be empty".to_string()));
+        }
+        problems
+    }
+}
+
+/// Why the ledger could not be read or appended to.
+#[derive(Debug)]
+pub enum Error {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// The last line is not an entry, so the next one could not be chained
+    /// to it; `verify` says more.
+    Corrupt {
+        path: PathBuf,
+        reason: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Corrupt { path, reason } => write!(
+                f,
+                "{}: {} (run `octa-ctl ledger verify`)",
+                path.display(),
+                reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// One line of the ledger.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// 1 for the first entry, one more for every next one.
+    pub seq: u64,
+    /// RFC 3339, UTC.
+    pub at: String,
+    pub actor: String,
+    /// The binary that acted, e.g. `octa-ctl`.
+    pub tool: String,
+    /// What it did, e.g. `delete` or `fix.srgb`.
+    pub action: String,
+    /// Keys or asset ids acted on.
+    pub targets: Vec<String>,
+    /// Anything else worth keeping (asset id, bytes, provider).
+    pub detail: BTreeMap<String, String>,
+    /// `hash` of the entry before, [`GENESIS`] for the first.
+    pub prev: String,
+    /// SHA-256 of `prev` and every field above.
+    pub hash: String,
+}
+
+/// The hashed part of an entry, in a fixed field order.
+#[derive(Serialize)]
+struct Chained<'a> {
+    seq: u64,
+    at: &'a str,
+    actor: &'a str,
+    tool: &'a str,
+    action: &'a str,
+    targets: &'a [String],
+    detail: &'a BTreeMap<String, String>,
+    prev: &'a str,
+}
+
+impl Entry {
+    /// What `hash` must be for the entry's content.
+    pub fn digest(&self) -> String {
+        let chained = Chained {
+            seq: self.seq,
+            at: &self.at,
+            actor: &self.actor,
+            tool: &self.tool,
+            action: &self.action,
+            targets: &self.targets,
+            detail: &self.detail,
+            prev: &self.prev,
+        };
+        let content = serde_json::to_string(&chained).unwrap_or_default();
+        Sha256::digest(content.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+/// Appends to the ledger of one tool.
+pub struct Ledger {
+    path: PathBuf,
+    actor: String,
+    tool: String,
+}
+
+impl Ledger {
+    /// `None` when `ledger.path` is unset. The file is created and its last
+    /// entry read here, so a ledger that cannot be written or chained to
+    /// fails before anything changes.
+    pub fn open(cfg: &LedgerConfig, tool: &str) -> Result<Option<Self>, Error> {
+        let Some(path) = &cfg.path else {
+            return Ok(None);
+        };
+        let ledger = Self {
+            path: path.clone(),
+            actor: cfg.actor.clone().unwrap_or_else(default_actor),
+            tool: tool.to_string(),
+        };
+        ledger.head(&mut append_mode(path)?)?;
+        Ok(Some(ledger))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The last entry, `None` in an empty ledger.
+    fn head(&self, file: &mut File) -> Result<Option<Entry>, Error> {
+        let last = last_line(file).map_err(|source| Error::Io {
+            path: self.path.clone(),
+            source,
+        })?;
+        last.map(|line| {
+            serde_json::from_str(&line).map_err(|e| Error::Corrupt {
+                path: self.path.clone(),
+                reason: format!("last line is not a ledger entry: {}", e),
+            })
+        })
+        .transpose()
+    }
+
+    /// Appends one action, chained to the last entry. The file is locked
+    /// while it is read and written, so tools running at once take turns.
+    pub fn record(
+        &self,
+        action: &str,
+        targets: &[String],
+        detail: &[(&str, String)],
+    ) -> Result<Entry, Error> {
+        let io = |source| Error::Io {
+            path: self.path.clone(),
+            source,
+        };
+        let mut file = append_mode(&self.path)?;
+        file.lock().map_err(io)?;
+        let (seq, prev) = match self.head(&mut file)? {
+            None => (1, GENESIS.to_string()),
+            Some(last) => (last.seq + 1, last.hash),
+        };
+        let mut entry = Entry {
+            seq,
+            at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            actor: self.actor.clone(),
+            tool: self.tool.clone(),
+            action: action.to_string(),
+            targets: targets.to_vec(),
+            detail: detail
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+            prev,
+            hash: String::new(),
+        };
+        entry.hash = entry.digest();
+        let line = serde_json::to_string(&entry).unwrap_or_default();
+        writeln!(file, "{}", line).map_err(io)?;
+        file.sync_data().map_err(io)?;
+        Ok(entry)
+    }
+}
+
+fn append_mode(path: &Path) -> Result<File, Error> {
+    OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)
+        .map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+/// The last non-empty line, read backwards from the end in growing chunks.
+fn last_line(file: &mut File) -> io::Result<Option<String>> {
+    let len = file.seek(SeekFrom::End(0))?;
+    let mut chunk = 4096u64;
+    loop {
+        let start = len.saturating_sub(chunk);
+        file.seek(SeekFrom::Start(start))?;
+        let mut tail = Vec::new();
+        file.take(len - start).read_to_end(&mut tail)?;
+        let text = String::from_utf8_lossy(&tail);
+        let trimmed = text.trim_end();
+        match trimmed.rfind('\n') {
+            Some(at) => return Ok(Some(trimmed[at + 1..].to_string())),
+            None if start == 0 => return Ok((!trimmed.is_empty()).then(|| trimmed.to_string())),
+            None => chunk *= 4,
+        }
+    }
+}
+
+fn default_actor() -> String {
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+    let host = std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "unknown-host".to_string());
+    format!("{}@{}", user, host)
+}
+
+/// A place where the chain does not hold.
+#[derive(Debug, Clone, Serialize)]
+pub struct Break {
+    /// 1-based line of the file; 0 for the end of it.
+    pub line: usize,
+    pub seq: Option<u64>,
+    pub reason: String,
+}
+
+/// What [`verify`] found.
+#[derive(Debug, Default, Serialize)]
+pub struct Verification {
+    /// Lines that are entries, intact or not.
+    pub entries: u64,
+    /// The last entry, the head to keep elsewhere and pass to the next
+    /// `verify`, so a cut-off end is caught as well.
+    pub head: Option<Entry>,
+    pub breaks: Vec<Break>,
+}
+
+impl Verification {
+    pub fn is_intact(&self) -> bool {
+        self.breaks.is_empty()
+    }
+}
+
+/// Walks the whole ledger and reports every break in the chain: lines that
+/// are not entries, entries whose content no longer matches their hash,
+/// and gaps or reordering (a `seq` or `prev` that does not follow the line
+/// before). After a break, checking resumes from the next entry. With
+/// `head`, a hash an earlier verify reported, the ledger must still
+/// contain that entry, or its end was cut off.
+pub fn verify(path: &Path, head: Option<&str>) -> Result<Verification, Error> {
+    let io = |source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let file = File::open(path).map_err(io)?;
+    let mut verification = Verification::default();
+    // What the next entry must carry; unknown after an unreadable line.
+    let mut expected: Option<(u64, String)> = Some((1, GENESIS.to_string()));
+    let mut head_found = head.is_none();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(io)?;
+        let mut problem = |seq, reason: String| {
+            verification.breaks.push(Break {
+                line: i + 1,
+                seq,
+                reason,
+            })
+        };
+        let entry: Entry = match serde_json::from_str(&line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                problem(None, format!("not a ledger entry: {}", e));
+                expected = None;
+                continue;
+            }
+        };
+        let seq = Some(entry.seq);
+        if entry.hash != entry.digest() {
+            problem(seq, "content does not match its hash (edited)".to_string());
+        }
+        if let Some((next, prev)) = &expected {
+            if entry.seq != *next {
+                problem(
+                    seq,
+                    format!(
+                        "seq {} where {} was due (entries removed or reordered)",
+                        entry.seq, next
+                    ),
+                );
+            } else if entry.prev != *prev {
+                problem(
+                    seq,
+                    "prev is not the hash of the entry before (edited, inserted or removed)"
+                        .to_string(),
+                );
+            }
+        }
+        head_found |= head == Some(entry.hash.as_str());
+        expected = Some((entry.seq + 1, entry.hash.clone()));
+        verification.entries += 1;
+        verification.head = Some(entry);
+    }
+    if !head_found {
+        verification.breaks.push(Break {
+            line: 0,
+            seq: None,
+            reason: format!(
+                "head {} is no longer in the ledger (its end was cut off or rewritten)",
+                head.unwrap_or_default()
+            ),
+        });
+    }
+    Ok(verification)
+}
+
+/// Every readable entry, oldest first; lines that are not entries are
+/// skipped (`verify` reports them).
+pub fn read(path: &Path) -> Result<Vec<Entry>, Error> {
+    let io = |source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let file = File::open(path).map_err(io)?;
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        if let Ok(entry) = serde_json::from_str(&line.map_err(io)?) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
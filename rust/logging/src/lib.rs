@@ -0,0 +1,206 @@
+//! Log output of the Octa tools: the console style every tool shares
+//! (`[TAG] message | Field: value`) or one JSON object per line, chosen with
+//! `--log-format`. Events carry a `tag` field (`OK`, `WARN`, `FATAL`, `→`)
+//! that the console colors by meaning and JSON keeps as a field.
+//!
+//! ```no_run
+//! use octa_logging::LogFormat;
+//! use tracing::{info, Level};
+//!
+//! octa_logging::init(LogFormat::Pretty, Level::INFO, false, false);
+//! info!(tag = "OK", keys = 3, "Done");
+//! // [OK] Done | Keys: 3
+//! ```
+//!
+//! With the `otel` feature, spans are exported to an OpenTelemetry collector
+//! once [`otel::start`] is called.
+
+#[cfg(feature = "otel")]
+pub mod otel;
+
+use clap::ValueEnum;
+use console::style;
+use std::fmt;
+use std::sync::OnceLock;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::{filter_fn, LevelFilter};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Colored console output for humans
+    Pretty,
+    /// One JSON object per line, for log pipelines
+    Json,
+}
+
+/// Target of the per-row finding events of a scan; `--quiet` drops
+/// everything logged here.
+pub const FINDING_TARGET: &str = "warden::finding";
+
+/// Target of the final report of a scan (the warden core's `report`
+/// module), which `--quiet` keeps.
+pub const REPORT_TARGET: &str = "octa_warden_core::report";
+
+static FORMAT: OnceLock<LogFormat> = OnceLock::new();
+static QUIET: OnceLock<bool> = OnceLock::new();
+static STDOUT_TAKEN: OnceLock<bool> = OnceLock::new();
+
+/// True when logs go to a machine pipeline as JSON lines.
+pub fn is_json() -> bool {
+    FORMAT.get() == Some(&LogFormat::Json)
+}
+
+/// True when human-only output (banner, report table) is printed: pretty
+/// logs, and stdout not taken by `--findings-stream -`. Otherwise the same
+/// information goes out as log events.
+pub fn is_human() -> bool {
+    !is_json() && STDOUT_TAKEN.get() != Some(&true)
+}
+
+/// True in summary-only mode (`--quiet`).
+pub fn is_quiet() -> bool {
+    QUIET.get() == Some(&true)
+}
+
+/// Installs the global subscriber. With `stdout_taken`, stdout carries
+/// nothing but the tool's output (a findings stream, a report, a secret)
+/// and logs go to stderr.
+pub fn init(format: LogFormat, level: Level, quiet: bool, stdout_taken: bool) {
+    let _ = FORMAT.set(format);
+    let _ = QUIET.set(quiet);
+    let _ = STDOUT_TAKEN.set(stdout_taken);
+
+    // Quiet mode keeps problems (warn/error) and the final report, and drops
+    // per-row findings plus routine progress lines.
+    let filter = filter_fn(move |meta| {
+        if !quiet {
+            return true;
+        }
+        if meta.target() == FINDING_TARGET {
+            return false;
+        }
+        *meta.level() <= Level::WARN || meta.target() == REPORT_TARGET
+    });
+
+    let writer = move || -> Box<dyn std::io::Write> {
+        if stdout_taken {
+            Box::new(std::io::stderr())
+        } else {
+            Box::new(std::io::stdout())
+        }
+    };
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+    let registry = tracing_subscriber::registry();
+    #[cfg(feature = "otel")]
+    let registry = registry.with(otel::slot());
+    let registry = registry.with(LevelFilter::from_level(level));
+
+    match format {
+        LogFormat::Pretty => registry
+            .with(layer.event_format(PrettyFormat).with_filter(filter))
+            .init(),
+        LogFormat::Json => registry
+            .with(
+                layer
+                    .json()
+                    .flatten_event(true)
+                    // Spans are for traces (see `otel`), not the log lines.
+                    .with_current_span(false)
+                    .with_span_list(false)
+                    .with_filter(filter),
+            )
+            .init(),
+    }
+}
+
+/// Renders events in the console style of every tool (Warden's classic one):
+/// `[TAG] message | Field: value`, with the tag colored by its meaning.
+struct PrettyFormat;
+
+impl<S, N> FormatEvent<S, N> for PrettyFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        _ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut fields = FieldCollector::default();
+        event.record(&mut fields);
+
+        if let Some(tag) = &fields.tag {
+            write!(writer, "{} ", styled_tag(tag, event.metadata().level()))?;
+        }
+        write!(writer, "{}", fields.message)?;
+
+        for (name, value) in &fields.rest {
+            write!(writer, " | {}: {}", label(name), style(value).dim())?;
+        }
+        writeln!(writer)
+    }
+}
+
+fn styled_tag(tag: &str, level: &Level) -> String {
+    let text = if tag == "→" {
+        tag.to_string()
+    } else {
+        format!("[{}]", tag)
+    };
+
+    match tag {
+        "CORRUPT" => format!("{} {}", style(text).red(), style("!").on_red()),
+        "DB-ERR" => format!("{} {}", style(text).magenta(), style("X").on_magenta()),
+        "CYCLE" => style(text).cyan().bold().to_string(),
+        "→" | "NOTIFY" => style(text).cyan().to_string(),
+        _ => match *level {
+            Level::ERROR => style(text).red().bold().to_string(),
+            Level::WARN => style(text).yellow().to_string(),
+            Level::INFO => style(text).green().to_string(),
+            _ => style(text).dim().to_string(),
+        },
+    }
+}
+
+/// "id" -> "ID", "reason" -> "Reason", "state_file" -> "State file".
+fn label(name: &str) -> String {
+    if name == "id" {
+        return "ID".to_string();
+    }
+    let spaced = name.replace('_', " ");
+    let mut chars = spaced.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[derive(Default)]
+struct FieldCollector {
+    tag: Option<String>,
+    message: String,
+    rest: Vec<(String, String)>,
+}
+
+impl Visit for FieldCollector {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "tag" => self.tag = Some(value.to_string()),
+            "message" => self.message = value.to_string(),
+            name => self.rest.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record_str(field, &format!("{:?}", value));
+    }
+}
@@ -0,0 +1,101 @@
+//! OpenTelemetry export of the tools' spans (feature `otel`). [`init`]
+//! leaves an empty slot in the subscriber; [`start`] fills it once the
+//! config is read, so a tool logs its config errors before it knows where
+//! traces go. Without [`start`], spans are only seen by the log output, which
+//! ignores them.
+//!
+//! ```no_run
+//! # use octa_logging::LogFormat;
+//! # use tracing::Level;
+//! octa_logging::init(LogFormat::Pretty, Level::INFO, false, false);
+//! let _telemetry = octa_logging::otel::start("octa-pulse", "http://localhost:4318", 1.0).unwrap();
+//! let span = tracing::info_span!("request");
+//! let headers = span.in_scope(octa_logging::otel::context_headers);
+//! // `traceparent`, for the server to continue the trace
+//! # let _ = headers;
+//! ```
+//!
+//! [`init`]: crate::init
+
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{reload, Layer, Registry};
+
+/// Path of the OTLP/HTTP traces endpoint under the collector's URL.
+const TRACES_PATH: &str = "/v1/traces";
+
+type Slot = Option<Box<dyn Layer<Registry> + Send + Sync>>;
+
+static HANDLE: OnceLock<reload::Handle<Slot, Registry>> = OnceLock::new();
+
+/// The layer [`start`] fills, installed by [`init`](crate::init).
+pub(crate) fn slot() -> reload::Layer<Slot, Registry> {
+    let (layer, handle) = reload::Layer::new(None);
+    let _ = HANDLE.set(handle);
+    layer
+}
+
+/// Exports spans until dropped; dropping it sends what is still buffered.
+/// Keep it alive until the tool exits.
+pub struct Telemetry {
+    provider: SdkTracerProvider,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Err(e) = self.provider.shutdown() {
+            tracing::warn!(tag = "WARN", reason = %e, "Could not flush traces");
+        }
+    }
+}
+
+/// Sends every span from now on to the OTLP/HTTP collector at `endpoint`,
+/// as `service`, keeping `sample_ratio` of the traces that start here.
+/// Outgoing requests then carry [`context_headers`].
+pub fn start(service: &str, endpoint: &str, sample_ratio: f64) -> Result<Telemetry, String> {
+    let handle = HANDLE
+        .get()
+        .ok_or("octa_logging::init was not called")?;
+    let endpoint = endpoint.trim_end_matches('/');
+    let endpoint = match endpoint.ends_with(TRACES_PATH) {
+        true => endpoint.to_string(),
+        false => format!("{}{}", endpoint, TRACES_PATH),
+    };
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            sample_ratio,
+        ))))
+        .with_resource(Resource::builder().with_service_name(service.to_string()).build())
+        .build();
+    let tracer = provider.tracer(service.to_string());
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let layer = tracing_opentelemetry::layer().with_tracer(tracer);
+    handle
+        .reload(Some(layer.boxed()))
+        .map_err(|e| e.to_string())?;
+    Ok(Telemetry { provider })
+}
+
+/// W3C trace-context headers (`traceparent`, `tracestate`) of the current
+/// span, for the server to record its work under it. Empty until [`start`].
+pub fn context_headers() -> HashMap<String, String> {
+    let context = tracing::Span::current().context();
+    let mut headers = HashMap::new();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut headers)
+    });
+    headers
+}
@@ -0,0 +1,254 @@
+use clap::Parser;
+use octa_config::{ConfigError, Validate};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use output::Output;
+use parse::Format;
+use replay::Replay;
+use report::{Abuse, Analyzer};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use tracing::{error, info, Level};
+
+mod output;
+mod parse;
+mod replay;
+mod report;
+
+/*
+OCTA-LOGS: Access log analysis for Octa
+=============================================
+Mission: Read the request logs of an Octa server or the reverse proxy in
+         front of it and report the hottest keys, cache hit ratios, status
+         codes, latency percentiles per route and clients that hammer or
+         probe the instance, as text, JSON or CSV. --replay keeps the reads
+         as a timed request list for load tests.
+Safety:  Reads the logs, writes only the report and the replay file.
+*/
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Analyze Octa and reverse-proxy access logs")]
+struct Args {
+    /// Log files to read, `-` for stdin (default: stdin)
+    files: Vec<PathBuf>,
+
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Format of the log lines (overrides logs.format)
+    #[arg(short, long, value_enum)]
+    format: Option<Format>,
+
+    /// What the report is printed as
+    #[arg(short, long, value_enum, default_value_t = Output::Text)]
+    output: Output,
+
+    /// Keys and clients to list (overrides logs.top)
+    #[arg(long)]
+    top: Option<usize>,
+
+    /// Also write the GET requests as JSON lines ({"at","method","uri"}) to replay them
+    #[arg(long, value_name = "PATH")]
+    replay: Option<PathBuf>,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+}
+
+/// The parts of config.yaml octa-logs reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    logs: LogsConfig,
+}
+
+/// `logs:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct LogsConfig {
+    format: Format,
+    top: usize,
+    abuse: Abuse,
+}
+
+impl Default for LogsConfig {
+    fn default() -> Self {
+        Self {
+            format: Format::Auto,
+            top: 20,
+            abuse: Abuse::default(),
+        }
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let abuse = &self.logs.abuse;
+        let mut problems = Vec::new();
+        if self.logs.top == 0 {
+            problems.push(("logs.top".to_string(), "must be at least 1".to_string()));
+        }
+        if abuse.max_per_minute == 0 {
+            problems.push((
+                "logs.abuse.max_per_minute".to_string(),
+                "must be at least 1".to_string(),
+            ));
+        }
+        if !(0.0..=1.0).contains(&abuse.max_error_ratio) {
+            problems.push((
+                "logs.abuse.max_error_ratio".to_string(),
+                "must be between 0 and 1".to_string(),
+            ));
+        }
+        problems
+    }
+}
+
+/// Without a file, the environment and arguments alone configure the report.
+fn load_config(path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    match octa_config::discover(path.map(Path::new)) {
+        Some(path) => octa_config::load(&path),
+        None => {
+            let config: FileConfig = octa_config::from_env()?;
+            octa_config::check(Path::new("<environment>"), config)
+        }
+    }
+}
+
+/// The octa-logs command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa logs` the arguments it was given.
+pub fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    // The report owns stdout; logs go to stderr.
+    octa_logging::init(args.log_format, Level::INFO, false, true);
+
+    let config = match load_config(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let format = args.format.unwrap_or(config.logs.format);
+    let top = args.top.unwrap_or(config.logs.top);
+
+    let mut replay = match args.replay.as_deref().map(Replay::create).transpose() {
+        Ok(replay) => replay,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Could not create the replay file");
+            return Kind::Io.exit_code();
+        }
+    };
+
+    let stdin = [PathBuf::from("-")];
+    let files = if args.files.is_empty() {
+        &stdin[..]
+    } else {
+        &args.files[..]
+    };
+    let mut analyzer = Analyzer::default();
+    let mut skipped = 0;
+    for path in files {
+        let reader: Box<dyn BufRead> = if path.as_os_str() == "-" {
+            Box::new(io::stdin().lock())
+        } else {
+            match File::open(path) {
+                Ok(file) => Box::new(BufReader::new(file)),
+                Err(e) => {
+                    error!(tag = "FATAL", path = %path.display(), reason = %e, "Could not open log");
+                    return octa_errors::Error::from(e).exit_code();
+                }
+            }
+        };
+        let read = read_log(reader, format, &mut analyzer, replay.as_mut());
+        match read {
+            Ok(lines) => skipped += lines,
+            Err(e) => {
+                error!(tag = "FATAL", path = %path.display(), reason = %e, "Could not read log");
+                return Kind::Io.exit_code();
+            }
+        }
+    }
+
+    if analyzer.requests() == 0 {
+        error!(
+            tag = "FATAL",
+            skipped, "No requests recognized (check --format)"
+        );
+        return Kind::Data.exit_code();
+    }
+    info!(
+        tag = "OK",
+        requests = analyzer.requests(),
+        skipped,
+        "Logs read"
+    );
+    if let Some(replay) = replay {
+        match replay.finish() {
+            Ok(written) => info!(
+                tag = "OK",
+                path = %args.replay.as_deref().unwrap_or(Path::new("")).display(),
+                requests = written,
+                "Replay written"
+            ),
+            Err(e) => {
+                error!(tag = "FATAL", reason = %e, "Could not write the replay file");
+                return Kind::Io.exit_code();
+            }
+        }
+    }
+
+    let report = analyzer.finish(top, &config.logs.abuse, skipped);
+    let rendered = match args.output {
+        Output::Text => output::text(&report),
+        Output::Csv => output::csv(&report),
+        Output::Json => match serde_json::to_string_pretty(&report) {
+            Ok(json) => json + "\n",
+            Err(e) => {
+                error!(tag = "FATAL", reason = %e, "Could not encode the report");
+                return Kind::Internal.exit_code();
+            }
+        },
+    };
+    // A closed pipe (`| head`) is not an error worth reporting.
+    let _ = io::stdout().lock().write_all(rendered.as_bytes());
+    ExitCode::SUCCESS
+}
+
+/// Feeds every request line to the analyzer (and the replay file) and
+/// returns how many non-empty lines were not requests.
+fn read_log(
+    mut reader: Box<dyn BufRead>,
+    format: Format,
+    analyzer: &mut Analyzer,
+    mut replay: Option<&mut Replay>,
+) -> io::Result<u64> {
+    let mut skipped = 0;
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            return Ok(skipped);
+        }
+        // Request paths are not always valid UTF-8.
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        match parse::parse_line(line, format) {
+            Some(entry) => {
+                analyzer.add(&entry);
+                if let Some(replay) = replay.as_deref_mut() {
+                    replay.add(&entry)?;
+                }
+            }
+            None => skipped += 1,
+        }
+    }
+}
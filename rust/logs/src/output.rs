@@ -0,0 +1,225 @@
+use crate::report::Report;
+use clap::ValueEnum;
+use console::style;
+use octa_warden_core::growth::format_bytes;
+use std::fmt::Write as _;
+
+/// What the report is printed as.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Output {
+    /// Tables for a terminal
+    #[default]
+    Text,
+    /// One JSON document
+    Json,
+    /// `section,item,metric,value` rows, one value per row
+    Csv,
+}
+
+pub fn text(report: &Report) -> String {
+    let mut out = String::new();
+    let s = &report.summary;
+    let _ = writeln!(out, "{}", style("Summary").bold());
+    let _ = writeln!(out, "  Requests      : {}", s.requests);
+    if let (Some(first), Some(last)) = (&s.first, &s.last) {
+        let _ = writeln!(out, "  Period        : {} .. {}", first, last);
+    }
+    if let Some(per_second) = s.per_second {
+        let _ = writeln!(out, "  Rate          : {:.2}/s", per_second);
+    }
+    if s.bytes > 0 {
+        let _ = writeln!(out, "  Sent          : {}", format_bytes(s.bytes as f64));
+    }
+    let _ = writeln!(out, "  Cache hits    : {}", percent(s.cache_hit_ratio));
+    let _ = writeln!(out, "  304 of images : {}", percent(s.not_modified_ratio));
+    if s.skipped_lines > 0 {
+        let _ = writeln!(out, "  Skipped lines : {}", s.skipped_lines);
+    }
+
+    let _ = writeln!(out, "\n{}", style("Status codes").bold());
+    for row in &report.statuses {
+        let _ = writeln!(
+            out,
+            "  {}  {:>9}  {:>6}",
+            row.status,
+            row.requests,
+            percent(Some(row.share))
+        );
+    }
+
+    let _ = writeln!(out, "\n{}", style("Routes").bold());
+    let width = report
+        .routes
+        .iter()
+        .map(|r| r.route.len())
+        .max()
+        .unwrap_or(0)
+        .max(5);
+    let _ = writeln!(
+        out,
+        "  {}",
+        style(format!(
+            "{:<width$}  {:>9}  {:>6}  {:>6}  {:>6}  {:>6}  {:>9}  {:>9}  {:>9}",
+            "route", "requests", "share", "4xx", "5xx", "hits", "p50", "p90", "p99"
+        ))
+        .dim()
+    );
+    for row in &report.routes {
+        let _ = writeln!(
+            out,
+            "  {:<width$}  {:>9}  {:>6}  {:>6}  {:>6}  {:>6}  {:>9}  {:>9}  {:>9}",
+            row.route,
+            row.requests,
+            percent(Some(row.share)),
+            row.client_errors,
+            row.server_errors,
+            percent(row.cache_hit_ratio),
+            millis(row.p50_ms),
+            millis(row.p90_ms),
+            millis(row.p99_ms),
+        );
+    }
+
+    let _ = writeln!(out, "\n{}", style("Top keys").bold());
+    let width = report.keys.iter().map(|k| k.key.len()).max().unwrap_or(0);
+    for row in &report.keys {
+        let _ = writeln!(
+            out,
+            "  {:<width$}  {:>9}  {:>6}  {}",
+            row.key,
+            row.requests,
+            percent(Some(row.share)),
+            if row.not_found > 0 {
+                style(format!("{} not found", row.not_found))
+                    .yellow()
+                    .to_string()
+            } else {
+                String::new()
+            }
+        );
+    }
+    if report.keys.is_empty() {
+        let _ = writeln!(out, "  {}", style("no /u/ requests").dim());
+    }
+
+    let _ = writeln!(out, "\n{}", style("Abusive clients").bold());
+    if s.clients == 0 {
+        let _ = writeln!(
+            out,
+            "  {}",
+            style("the log records no client addresses").dim()
+        );
+    } else if report.clients.is_empty() {
+        let _ = writeln!(out, "  {}", style("none over the thresholds").dim());
+    }
+    let width = report.clients.iter().map(|c| c.ip.len()).max().unwrap_or(0);
+    for row in &report.clients {
+        let _ = writeln!(
+            out,
+            "  {:<width$}  {:>9}  {}",
+            row.ip,
+            row.requests,
+            style(row.reasons.join("; ")).red()
+        );
+    }
+    out
+}
+
+pub fn csv(report: &Report) -> String {
+    let mut out = String::from("section,item,metric,value\r\n");
+    let mut row = |section: &str, item: &str, metric: &str, value: String| {
+        let fields = [section, item, metric, value.as_str()];
+        let fields: Vec<String> = fields.iter().map(|v| csv_field(v)).collect();
+        out.push_str(&fields.join(","));
+        out.push_str("\r\n");
+    };
+    let opt = |value: Option<f64>| value.map(|v| v.to_string()).unwrap_or_default();
+
+    let s = &report.summary;
+    row("summary", "", "requests", s.requests.to_string());
+    row("summary", "", "skipped_lines", s.skipped_lines.to_string());
+    row("summary", "", "first", s.first.clone().unwrap_or_default());
+    row("summary", "", "last", s.last.clone().unwrap_or_default());
+    row("summary", "", "per_second", opt(s.per_second));
+    row("summary", "", "bytes", s.bytes.to_string());
+    row("summary", "", "cache_hit_ratio", opt(s.cache_hit_ratio));
+    row(
+        "summary",
+        "",
+        "not_modified_ratio",
+        opt(s.not_modified_ratio),
+    );
+    row("summary", "", "clients", s.clients.to_string());
+    for r in &report.statuses {
+        let status = r.status.to_string();
+        row("status", &status, "requests", r.requests.to_string());
+        row("status", &status, "share", r.share.to_string());
+    }
+    for r in &report.routes {
+        row("route", &r.route, "requests", r.requests.to_string());
+        row("route", &r.route, "share", r.share.to_string());
+        row(
+            "route",
+            &r.route,
+            "client_errors",
+            r.client_errors.to_string(),
+        );
+        row(
+            "route",
+            &r.route,
+            "server_errors",
+            r.server_errors.to_string(),
+        );
+        row("route", &r.route, "cache_hit_ratio", opt(r.cache_hit_ratio));
+        row("route", &r.route, "p50_ms", opt(r.p50_ms));
+        row("route", &r.route, "p90_ms", opt(r.p90_ms));
+        row("route", &r.route, "p99_ms", opt(r.p99_ms));
+        row("route", &r.route, "max_ms", opt(r.max_ms));
+    }
+    for r in &report.keys {
+        row("key", &r.key, "requests", r.requests.to_string());
+        row("key", &r.key, "share", r.share.to_string());
+        row("key", &r.key, "not_found", r.not_found.to_string());
+        row("key", &r.key, "bytes", r.bytes.to_string());
+    }
+    for r in &report.clients {
+        row("client", &r.ip, "requests", r.requests.to_string());
+        row(
+            "client",
+            &r.ip,
+            "client_errors",
+            r.client_errors.to_string(),
+        );
+        row("client", &r.ip, "throttled", r.throttled.to_string());
+        row(
+            "client",
+            &r.ip,
+            "peak_per_minute",
+            r.peak_per_minute.to_string(),
+        );
+        row("client", &r.ip, "keys", r.keys.to_string());
+        row("client", &r.ip, "reasons", r.reasons.join("; "));
+    }
+    out
+}
+
+/// RFC 4180: fields with commas, quotes or line breaks are quoted.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn percent(value: Option<f64>) -> String {
+    value.map_or("-".to_string(), |v| format!("{:.1}%", v * 100.0))
+}
+
+fn millis(value: Option<f64>) -> String {
+    match value {
+        None => "-".to_string(),
+        Some(ms) if ms >= 1000.0 => format!("{:.2}s", ms / 1000.0),
+        Some(ms) => format!("{:.1}ms", ms),
+    }
+}
@@ -0,0 +1,265 @@
+use chrono::{DateTime, NaiveDateTime};
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// How the access log lines are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// Detect each line's format
+    #[default]
+    Auto,
+    /// The Octa server's own request log (colored or not)
+    Octa,
+    /// nginx/Apache combined (or common) log, optionally followed by
+    /// $request_time and $upstream_cache_status
+    Combined,
+    /// Caddy's JSON access log
+    Caddy,
+}
+
+/// Whether the edge answered from its cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cache {
+    Hit,
+    Miss,
+    /// Bypassed, not cacheable, or a status only the proxy understands.
+    Other,
+}
+
+/// One request, as much of it as the log line tells.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// Unix seconds. Octa's own log has no time zone and is read as UTC,
+    /// which only shifts every entry by the same amount.
+    pub time: Option<i64>,
+    pub client: Option<String>,
+    pub method: String,
+    /// Path and query, as requested.
+    pub uri: String,
+    pub status: u16,
+    pub bytes: Option<u64>,
+    /// Seconds from request to response.
+    pub latency: Option<f64>,
+    pub cache: Option<Cache>,
+}
+
+/// `None` for lines that are not requests in `format` (startup messages,
+/// truncated lines).
+pub fn parse_line(line: &str, format: Format) -> Option<Entry> {
+    match format {
+        Format::Octa => octa(line),
+        Format::Combined => combined(line),
+        Format::Caddy => caddy(line),
+        Format::Auto if line.trim_start().starts_with('{') => caddy(line),
+        Format::Auto => octa(line).or_else(|| combined(line)),
+    }
+}
+
+/// `2006-01-02 15:04:05 [GET]   /u/alice?size=64 200 | 1.234ms`, with the
+/// ANSI colors of a terminal stripped.
+fn octa(line: &str) -> Option<Entry> {
+    let line = strip_ansi(line);
+    let mut parts = line.split_whitespace();
+    let (date, time) = (parts.next()?, parts.next()?);
+    let method = parts.next()?.strip_prefix('[')?.strip_suffix(']')?;
+    let uri = parts.next()?;
+    let status = parts.next()?.parse().ok()?;
+    if parts.next()? != "|" {
+        return None;
+    }
+    let latency = go_duration(parts.next()?)?;
+    let time = NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M:%S")
+        .ok()?
+        .and_utc()
+        .timestamp();
+    Some(Entry {
+        time: Some(time),
+        client: None,
+        method: method.to_string(),
+        uri: uri.to_string(),
+        status,
+        bytes: None,
+        latency: Some(latency),
+        cache: None,
+    })
+}
+
+/// `1.2.3.4 - - [10/Oct/2000:13:55:36 -0700] "GET /u/alice HTTP/1.1" 200 2326
+/// "referer" "agent"`; fields after the agent may carry the request time in
+/// seconds and the upstream cache status, bare or as `name=value`.
+fn combined(line: &str) -> Option<Entry> {
+    let fields = split_fields(line);
+    if fields.len() < 7 {
+        return None;
+    }
+    let time = DateTime::parse_from_str(fields[3].strip_prefix('[')?, "%d/%b/%Y:%H:%M:%S %z")
+        .ok()?
+        .timestamp();
+    let mut request = fields[4].split_whitespace();
+    let (method, uri) = (request.next()?, request.next()?);
+    let status = fields[5].parse().ok()?;
+
+    let mut latency = None;
+    let mut cache = None;
+    for extra in fields.iter().skip(9) {
+        let value = extra.rsplit_once('=').map_or(extra.as_str(), |(_, v)| v);
+        if latency.is_none() {
+            if let Ok(seconds) = value.parse::<f64>() {
+                latency = Some(seconds);
+                continue;
+            }
+        }
+        if cache.is_none() {
+            cache = cache_status(value);
+        }
+    }
+    Some(Entry {
+        time: Some(time),
+        client: Some(fields[0].clone()).filter(|ip| ip != "-"),
+        method: method.to_string(),
+        uri: uri.to_string(),
+        status,
+        bytes: fields[6].parse().ok(),
+        latency,
+        cache,
+    })
+}
+
+/// One JSON object per line, as Caddy's `log` directive writes it.
+fn caddy(line: &str) -> Option<Entry> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    let request = value.get("request")?;
+    let client = ["client_ip", "remote_ip"]
+        .iter()
+        .find_map(|field| request.get(field)?.as_str())
+        .map(str::to_string);
+    // Whichever header the proxy behind Caddy sets.
+    let cache = value.get("resp_headers").and_then(|headers| {
+        [
+            "Cache-Status",
+            "X-Cache",
+            "X-Cache-Status",
+            "Cf-Cache-Status",
+        ]
+        .iter()
+        .find_map(|name| headers.get(name)?.get(0)?.as_str())
+        .and_then(cache_status)
+    });
+    Some(Entry {
+        time: value.get("ts").and_then(Value::as_f64).map(|ts| ts as i64),
+        client,
+        method: request.get("method")?.as_str()?.to_string(),
+        uri: request.get("uri")?.as_str()?.to_string(),
+        status: value.get("status")?.as_u64()? as u16,
+        bytes: value.get("size").and_then(Value::as_u64),
+        latency: value.get("duration").and_then(Value::as_f64),
+        cache,
+    })
+}
+
+/// nginx `$upstream_cache_status` and the `X-Cache` values of common CDNs.
+fn cache_status(value: &str) -> Option<Cache> {
+    let value = value.to_ascii_uppercase();
+    let first = value.split([',', ';', ' ']).next().unwrap_or_default();
+    match first {
+        "HIT" | "STALE" | "UPDATING" | "REVALIDATED" | "TCP_HIT" | "TCP_MEM_HIT" => {
+            Some(Cache::Hit)
+        }
+        "MISS" | "EXPIRED" | "TCP_MISS" => Some(Cache::Miss),
+        "BYPASS" | "DYNAMIC" | "PASS" | "NONE" | "UNKNOWN" => Some(Cache::Other),
+        _ => None,
+    }
+}
+
+/// Splits a combined log line into bare words, `"quoted strings"` (with
+/// `\"` escapes) and `[bracketed]` timestamps. Quotes are removed; brackets
+/// are kept on the opening side so the timestamp is recognisable.
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut field = String::new();
+        match c {
+            '"' => {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => field.extend(chars.next()),
+                        '"' => break,
+                        c => field.push(c),
+                    }
+                }
+            }
+            '[' => {
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    field.push(c);
+                }
+            }
+            _ => {
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    field.push(c);
+                    chars.next();
+                }
+            }
+        }
+        fields.push(field);
+    }
+    fields
+}
+
+/// Go's `time.Duration.String()`: `850µs`, `1.234ms`, `2m3.5s`, `0s`.
+fn go_duration(value: &str) -> Option<f64> {
+    let mut seconds = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let split = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+        let (number, tail) = rest.split_at(split);
+        let number: f64 = number.parse().ok()?;
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        seconds += number
+            * match unit {
+                "h" => 3600.0,
+                "m" => 60.0,
+                "s" => 1.0,
+                "ms" => 1e-3,
+                "µs" | "μs" | "us" => 1e-6,
+                "ns" => 1e-9,
+                _ => return None,
+            };
+        rest = tail;
+    }
+    Some(seconds)
+}
+
+/// Removes `ESC [ ... <letter>` color sequences.
+fn strip_ansi(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
@@ -0,0 +1,57 @@
+use crate::parse::Entry;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// One request to send again, `at` seconds after the first one.
+#[derive(Serialize)]
+struct Request<'a> {
+    at: i64,
+    method: &'a str,
+    uri: &'a str,
+}
+
+/// Writes the reads of a log as JSON lines, in log order, for load tests
+/// that want production's key mix and pacing. Uploads and deletes are left
+/// out: the log has neither their bodies nor their secret.
+pub struct Replay {
+    out: BufWriter<File>,
+    start: Option<i64>,
+    written: u64,
+}
+
+impl Replay {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            out: BufWriter::new(File::create(path)?),
+            start: None,
+            written: 0,
+        })
+    }
+
+    pub fn add(&mut self, entry: &Entry) -> io::Result<()> {
+        if !matches!(entry.method.as_str(), "GET" | "HEAD") {
+            return Ok(());
+        }
+        let Some(time) = entry.time else {
+            return Ok(());
+        };
+        let start = *self.start.get_or_insert(time);
+        let request = Request {
+            // Lines a proxy writes slightly out of order go out at once.
+            at: (time - start).max(0),
+            method: &entry.method,
+            uri: &entry.uri,
+        };
+        serde_json::to_writer(&mut self.out, &request)?;
+        self.out.write_all(b"\n")?;
+        self.written += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<u64> {
+        self.out.flush()?;
+        Ok(self.written)
+    }
+}
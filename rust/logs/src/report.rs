@@ -0,0 +1,406 @@
+use crate::parse::{Cache, Entry};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// `logs.abuse`: when a client address is reported.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Abuse {
+    /// Requests in any one minute.
+    pub max_per_minute: u64,
+    /// Share of 4xx answers, for clients with at least `min_requests`.
+    pub max_error_ratio: f64,
+    pub min_requests: u64,
+}
+
+impl Default for Abuse {
+    fn default() -> Self {
+        Self {
+            max_per_minute: 600,
+            max_error_ratio: 0.5,
+            min_requests: 50,
+        }
+    }
+}
+
+#[derive(Default)]
+struct RouteTally {
+    requests: u64,
+    client_errors: u64,
+    server_errors: u64,
+    hits: u64,
+    misses: u64,
+    latencies: Vec<f64>,
+}
+
+#[derive(Default)]
+struct KeyTally {
+    requests: u64,
+    not_found: u64,
+    bytes: u64,
+}
+
+#[derive(Default)]
+struct ClientTally {
+    requests: u64,
+    client_errors: u64,
+    throttled: u64,
+    per_minute: HashMap<i64, u64>,
+    keys: HashSet<String>,
+}
+
+/// Counts entries as they are read; [`Analyzer::finish`] turns the counts
+/// into a [`Report`].
+#[derive(Default)]
+pub struct Analyzer {
+    requests: u64,
+    bytes: u64,
+    first: Option<i64>,
+    last: Option<i64>,
+    statuses: BTreeMap<u16, u64>,
+    routes: HashMap<String, RouteTally>,
+    keys: HashMap<String, KeyTally>,
+    clients: HashMap<String, ClientTally>,
+    /// GETs of images, and how many of them were answered 304.
+    image_gets: u64,
+    not_modified: u64,
+}
+
+impl Analyzer {
+    pub fn add(&mut self, entry: &Entry) {
+        self.requests += 1;
+        self.bytes += entry.bytes.unwrap_or(0);
+        if let Some(time) = entry.time {
+            self.first = Some(self.first.map_or(time, |t| t.min(time)));
+            self.last = Some(self.last.map_or(time, |t| t.max(time)));
+        }
+        *self.statuses.entry(entry.status).or_default() += 1;
+
+        let (route, key) = route(&entry.method, &entry.uri);
+        let tally = self.routes.entry(route).or_default();
+        tally.requests += 1;
+        match entry.status {
+            400..=499 => tally.client_errors += 1,
+            500..=599 => tally.server_errors += 1,
+            _ => {}
+        }
+        match entry.cache {
+            Some(Cache::Hit) => tally.hits += 1,
+            Some(Cache::Miss) => tally.misses += 1,
+            Some(Cache::Other) | None => {}
+        }
+        tally.latencies.extend(entry.latency);
+
+        if entry.method == "GET" && (key.is_some() || entry.uri.starts_with("/avatar/")) {
+            self.image_gets += 1;
+            if entry.status == 304 {
+                self.not_modified += 1;
+            }
+        }
+        if let Some(key) = &key {
+            let tally = self.keys.entry(key.clone()).or_default();
+            tally.requests += 1;
+            tally.bytes += entry.bytes.unwrap_or(0);
+            if entry.status == 404 {
+                tally.not_found += 1;
+            }
+        }
+        if let Some(client) = &entry.client {
+            let tally = self.clients.entry(client.clone()).or_default();
+            tally.requests += 1;
+            match entry.status {
+                429 => tally.throttled += 1,
+                400..=499 => tally.client_errors += 1,
+                _ => {}
+            }
+            if let Some(time) = entry.time {
+                *tally.per_minute.entry(time.div_euclid(60)).or_default() += 1;
+            }
+            if let Some(key) = key {
+                tally.keys.insert(key);
+            }
+        }
+    }
+
+    pub fn requests(&self) -> u64 {
+        self.requests
+    }
+
+    pub fn finish(self, top: usize, abuse: &Abuse, skipped: u64) -> Report {
+        let requests = self.requests;
+        let share = |n: u64| ratio(n, requests).unwrap_or(0.0);
+        let (hits, misses) = self
+            .routes
+            .values()
+            .fold((0, 0), |(h, m), r| (h + r.hits, m + r.misses));
+        let span = match (self.first, self.last) {
+            (Some(first), Some(last)) => Some((last - first) as u64),
+            _ => None,
+        };
+
+        let summary = Summary {
+            requests,
+            skipped_lines: skipped,
+            first: self.first.and_then(timestamp),
+            last: self.last.and_then(timestamp),
+            span_seconds: span,
+            per_second: span.filter(|s| *s > 0).map(|s| requests as f64 / s as f64),
+            bytes: self.bytes,
+            cache_hit_ratio: ratio(hits, hits + misses),
+            not_modified_ratio: ratio(self.not_modified, self.image_gets),
+            clients: self.clients.len() as u64,
+        };
+
+        let statuses = self
+            .statuses
+            .iter()
+            .map(|(&status, &n)| StatusRow {
+                status,
+                requests: n,
+                share: share(n),
+            })
+            .collect();
+
+        let mut routes: Vec<RouteRow> = self
+            .routes
+            .into_iter()
+            .map(|(route, mut tally)| {
+                tally.latencies.sort_by(f64::total_cmp);
+                let at = |q: f64| percentile(&tally.latencies, q).map(|s| s * 1000.0);
+                RouteRow {
+                    route,
+                    requests: tally.requests,
+                    share: share(tally.requests),
+                    client_errors: tally.client_errors,
+                    server_errors: tally.server_errors,
+                    cache_hit_ratio: ratio(tally.hits, tally.hits + tally.misses),
+                    p50_ms: at(0.50),
+                    p90_ms: at(0.90),
+                    p99_ms: at(0.99),
+                    max_ms: at(1.0),
+                }
+            })
+            .collect();
+        routes.sort_by(|a, b| b.requests.cmp(&a.requests).then(a.route.cmp(&b.route)));
+
+        let key_requests: u64 = self.keys.values().map(|k| k.requests).sum();
+        let mut keys: Vec<KeyRow> = self
+            .keys
+            .into_iter()
+            .map(|(key, tally)| KeyRow {
+                key,
+                requests: tally.requests,
+                share: ratio(tally.requests, key_requests).unwrap_or(0.0),
+                not_found: tally.not_found,
+                bytes: tally.bytes,
+            })
+            .collect();
+        keys.sort_by(|a, b| b.requests.cmp(&a.requests).then(a.key.cmp(&b.key)));
+        keys.truncate(top);
+
+        let mut clients: Vec<ClientRow> = self
+            .clients
+            .into_iter()
+            .filter_map(|(ip, tally)| {
+                let peak = tally.per_minute.values().max().copied().unwrap_or(0);
+                let mut reasons = Vec::new();
+                if peak > abuse.max_per_minute {
+                    reasons.push(format!(
+                        "{} requests in one minute (max {})",
+                        peak, abuse.max_per_minute
+                    ));
+                }
+                if tally.requests >= abuse.min_requests {
+                    let errors = ratio(tally.client_errors, tally.requests).unwrap_or(0.0);
+                    if errors > abuse.max_error_ratio {
+                        reasons.push(format!(
+                            "{:.0}% 4xx (max {:.0}%)",
+                            errors * 100.0,
+                            abuse.max_error_ratio * 100.0
+                        ));
+                    }
+                    if tally.throttled > 0 {
+                        reasons.push(format!("{} rate limited (429)", tally.throttled));
+                    }
+                }
+                (!reasons.is_empty()).then_some(ClientRow {
+                    ip,
+                    requests: tally.requests,
+                    client_errors: tally.client_errors,
+                    throttled: tally.throttled,
+                    peak_per_minute: peak,
+                    keys: tally.keys.len() as u64,
+                    reasons,
+                })
+            })
+            .collect();
+        clients.sort_by(|a, b| b.requests.cmp(&a.requests).then(a.ip.cmp(&b.ip)));
+        clients.truncate(top);
+
+        Report {
+            summary,
+            statuses,
+            routes,
+            keys,
+            clients,
+        }
+    }
+}
+
+/// The server's routes, so `/u/alice` and `/u/bob` count as one; anything
+/// else is `other`, which keeps scanners from adding a route per probe. The
+/// key is returned for `/u/` requests.
+fn route(method: &str, uri: &str) -> (String, Option<String>) {
+    let path = uri.split(['?', '#']).next().unwrap_or_default();
+    let (pattern, key) = if let Some(key) = path.strip_prefix("/u/") {
+        (
+            "/u/:key",
+            Some(percent_decode(key)).filter(|k| !k.is_empty()),
+        )
+    } else if path.starts_with("/avatar/github/") {
+        ("/avatar/github/:username", None)
+    } else if path.starts_with("/avatar/") {
+        ("/avatar/:seed", None)
+    } else if path.starts_with("/console/static/") {
+        ("/console/static/*", None)
+    } else if path.starts_with("/console/api/assets/") {
+        ("/console/api/assets/:id", None)
+    } else {
+        let known = [
+            "/",
+            "/upload",
+            "/upload/delete",
+            "/upload/stat",
+            "/upload/list",
+            "/health",
+            "/console",
+            "/console/login",
+            "/console/api/login",
+            "/console/api/logout",
+            "/console/api/stats",
+            "/console/api/assets",
+            "/console/api/backup",
+        ];
+        (
+            known.into_iter().find(|p| *p == path).unwrap_or("other"),
+            None,
+        )
+    };
+    (format!("{} {}", method, pattern), key)
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Nearest rank on sorted values.
+fn percentile(sorted: &[f64], q: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((sorted.len() as f64 * q).ceil() as usize).clamp(1, sorted.len());
+    Some(sorted[rank - 1])
+}
+
+fn ratio(part: u64, whole: u64) -> Option<f64> {
+    (whole > 0).then(|| part as f64 / whole as f64)
+}
+
+fn timestamp(secs: i64) -> Option<String> {
+    chrono::DateTime::from_timestamp(secs, 0).map(|t| t.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub summary: Summary,
+    pub statuses: Vec<StatusRow>,
+    /// Busiest first.
+    pub routes: Vec<RouteRow>,
+    /// The most requested `/u/` keys.
+    pub keys: Vec<KeyRow>,
+    /// Client addresses over a `logs.abuse` threshold, busiest first.
+    pub clients: Vec<ClientRow>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub requests: u64,
+    /// Lines that were not requests in the chosen format.
+    pub skipped_lines: u64,
+    pub first: Option<String>,
+    pub last: Option<String>,
+    pub span_seconds: Option<u64>,
+    pub per_second: Option<f64>,
+    /// Response bytes, where the log records them.
+    pub bytes: u64,
+    /// Edge hits over hits and misses, where the log records cache status.
+    pub cache_hit_ratio: Option<f64>,
+    /// Image GETs answered 304 from the client's own cache.
+    pub not_modified_ratio: Option<f64>,
+    /// Distinct client addresses; 0 when the log has none.
+    pub clients: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusRow {
+    pub status: u16,
+    pub requests: u64,
+    pub share: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RouteRow {
+    /// Method and route pattern, `GET /u/:key`.
+    pub route: String,
+    pub requests: u64,
+    pub share: f64,
+    pub client_errors: u64,
+    pub server_errors: u64,
+    pub cache_hit_ratio: Option<f64>,
+    pub p50_ms: Option<f64>,
+    pub p90_ms: Option<f64>,
+    pub p99_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyRow {
+    pub key: String,
+    pub requests: u64,
+    /// Of all `/u/` requests.
+    pub share: f64,
+    pub not_found: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClientRow {
+    pub ip: String,
+    pub requests: u64,
+    /// 4xx other than 429.
+    pub client_errors: u64,
+    pub throttled: u64,
+    pub peak_per_minute: u64,
+    /// Distinct `/u/` keys requested; many with many 404s is enumeration.
+    pub keys: u64,
+    pub reasons: Vec<String>,
+}
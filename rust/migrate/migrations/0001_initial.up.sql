@@ -0,0 +1,25 @@
+-- The tables as the Go server's GORM AutoMigrate creates them
+-- (internal/database/model.go). IF NOT EXISTS adopts databases the server
+-- created before migrations were versioned.
+CREATE TABLE IF NOT EXISTS `images` (
+    `id` text,
+    `data` blob,
+    `width` integer,
+    `height` integer,
+    `format` text,
+    `size` integer,
+    `updated_at` datetime,
+    `created_at` datetime,
+    PRIMARY KEY (`id`)
+);
+
+CREATE TABLE IF NOT EXISTS `key_mappings` (
+    `key` text,
+    `image_id` text,
+    `created_at` datetime,
+    PRIMARY KEY (`key`),
+    CONSTRAINT `fk_images_mappings` FOREIGN KEY (`image_id`) REFERENCES `images`(`id`)
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS `idx_key_mappings_image_id` ON `key_mappings`(`image_id`);
@@ -0,0 +1,3 @@
+-- Serves the cleaner's oldest-first eviction scan (internal/database/cleaner.go).
+-- The Go server also creates it on startup (internal/database/db.go).
+CREATE INDEX IF NOT EXISTS idx_images_updated_at ON images(updated_at DESC);
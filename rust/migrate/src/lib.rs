@@ -0,0 +1,406 @@
+use clap::{Parser, Subcommand};
+use octa_config::{ConfigError, DatabaseConfig, Validate};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use octa_warden_core::db::{self, OpenOptions};
+use octa_warden_core::schema;
+use rusqlite::Connection;
+use serde::Deserialize;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+use tracing::{error, info, warn, Level};
+
+mod migrations;
+
+use migrations::{Migration, MIGRATIONS};
+
+/*
+OCTA-MIGRATE: Versioned schema migrations for the Octa database
+=============================================
+Mission: Apply the schema changes embedded in this binary in order, record
+         each in `schema_version`, and revert the reversible ones.
+Safety:  Every `up`/`down` that changes something writes a backup next to the
+         database first; each migration runs in its own transaction.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Versioned schema migrations for the Octa database"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long, global = true)]
+    config: Option<String>,
+
+    /// SQLite database to migrate (overrides database.path; the config file becomes optional)
+    #[arg(long = "db", global = true, env = "OCTA_DB_PATH", value_name = "PATH")]
+    db_path: Option<String>,
+
+    /// Milliseconds to wait on a locked database before giving up (SQLITE_BUSY)
+    #[arg(long, global = true, default_value_t = 5000)]
+    busy_timeout: u64,
+
+    /// Log format
+    #[arg(
+        long,
+        env = "OCTA_LOG_FORMAT",
+        global = true,
+        value_enum,
+        default_value_t = LogFormat::Pretty
+    )]
+    log_format: LogFormat,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show the database's version and which migrations are applied or pending
+    Status,
+    /// Apply pending migrations (creates the database if it does not exist)
+    Up {
+        /// Stop at this version instead of the latest
+        #[arg(long)]
+        to: Option<u32>,
+        /// List what would be applied without changing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Skip the pre-migration backup
+        #[arg(long)]
+        no_backup: bool,
+    },
+    /// Revert applied migrations (default: the last one)
+    Down {
+        /// Revert down to this version (it stays applied)
+        #[arg(long)]
+        to: Option<u32>,
+        /// List what would be reverted without changing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Skip the pre-migration backup
+        #[arg(long)]
+        no_backup: bool,
+    },
+}
+
+/// The part of config.yaml octa-migrate reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    database: DatabaseConfig,
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        if self.database.path.trim().is_empty() {
+            return vec![("database.path".to_string(), "is required".to_string())];
+        }
+        Vec::new()
+    }
+}
+
+/// Like octa-warden: `--db` makes the config file optional.
+fn load_config(path: Option<&str>, db_path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    let found = octa_config::discover(path.map(Path::new)).filter(|p| p.exists());
+    let mut config: FileConfig = match (&found, db_path) {
+        (Some(found), _) => octa_config::read(found)?,
+        (None, Some(_)) => octa_config::from_env()?,
+        (None, None) => {
+            return Err(match path {
+                Some(path) => ConfigError::Read {
+                    path: path.into(),
+                    source: std::io::ErrorKind::NotFound.into(),
+                },
+                None => ConfigError::NotFound,
+            })
+        }
+    };
+    if let Some(db_path) = db_path {
+        config.database.path = db_path.to_string();
+    }
+    octa_config::check(found.as_deref().unwrap_or(Path::new("<defaults>")), config)
+}
+
+/// The octa-migrate command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa migrate` the arguments it was given.
+pub fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, Level::INFO, false, false);
+
+    let config = match load_config(args.config.as_deref(), args.db_path.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let db_path = config.database.path.as_str();
+    let opts = OpenOptions {
+        busy_timeout: Duration::from_millis(args.busy_timeout),
+        immutable: false,
+    };
+
+    let result = match args.command {
+        Command::Status => status(db_path, &opts),
+        Command::Up {
+            to,
+            dry_run,
+            no_backup,
+        } => up(db_path, &opts, to, dry_run, !no_backup),
+        Command::Down {
+            to,
+            dry_run,
+            no_backup,
+        } => down(db_path, &opts, to, dry_run, !no_backup),
+    };
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            error!(tag = "FATAL", path = db_path, reason = %e, "Migration failed");
+            Kind::Io.exit_code()
+        }
+    }
+}
+
+fn status(db_path: &str, opts: &OpenOptions) -> Result<ExitCode, String> {
+    if !Path::new(db_path).exists() {
+        error!(tag = "FATAL", path = db_path, "Database file not found");
+        return Ok(Kind::NoInput.exit_code());
+    }
+    let conn = db::open_read_only(db_path, opts).map_err(|e| e.to_string())?;
+    let applied = migrations::applied(&conn).map_err(|e| e.to_string())?;
+    let current = applied.last().map_or(0, |a| a.version);
+
+    println!("Database : {}", db_path);
+    println!("Version  : {} (latest {})", current, migrations::latest());
+    for migration in MIGRATIONS {
+        let state = match applied.iter().find(|a| a.version == migration.version) {
+            Some(a) if a.checksum != migration.checksum() => {
+                format!("applied {} (file changed since)", a.applied_at)
+            }
+            Some(a) => format!("applied {}", a.applied_at),
+            None => "pending".to_string(),
+        };
+        println!(
+            "  {:04}  {:<28} {}",
+            migration.version, migration.name, state
+        );
+    }
+    for unknown in applied.iter().filter(|a| a.version > migrations::latest()) {
+        println!(
+            "  {:04}  {:<28} applied {} (unknown to this octa-migrate)",
+            unknown.version, unknown.name, unknown.applied_at
+        );
+    }
+
+    check_schema(&conn);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn up(
+    db_path: &str,
+    opts: &OpenOptions,
+    to: Option<u32>,
+    dry_run: bool,
+    backup: bool,
+) -> Result<ExitCode, String> {
+    let target = to.unwrap_or_else(migrations::latest);
+    if target > migrations::latest() {
+        error!(
+            tag = "FATAL",
+            to = target,
+            latest = migrations::latest(),
+            "No such migration"
+        );
+        return Ok(Kind::Usage.exit_code());
+    }
+
+    let existed = Path::new(db_path).exists();
+    let applied = if existed {
+        let conn = db::open_read_only(db_path, opts).map_err(|e| e.to_string())?;
+        migrations::applied(&conn).map_err(|e| e.to_string())?
+    } else {
+        Vec::new()
+    };
+    if let Some(missing) = migrations::gap(&applied) {
+        error!(
+            tag = "FATAL",
+            version = missing.version,
+            name = missing.name,
+            "An earlier migration is not recorded as applied; fix schema_version before migrating"
+        );
+        return Ok(Kind::Data.exit_code());
+    }
+    let current = applied.last().map_or(0, |a| a.version);
+    if current > migrations::latest() {
+        error!(
+            tag = "FATAL",
+            version = current,
+            latest = migrations::latest(),
+            "The database is newer than this octa-migrate"
+        );
+        return Ok(Kind::Data.exit_code());
+    }
+
+    let steps = migrations::pending(current, target);
+    if steps.is_empty() {
+        info!(tag = "OK", version = current, "Nothing to apply");
+        return Ok(ExitCode::SUCCESS);
+    }
+    if dry_run {
+        for migration in steps {
+            info!(
+                tag = "PLAN",
+                version = migration.version,
+                name = migration.name,
+                "Would apply"
+            );
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if existed && backup {
+        write_backup(db_path, opts)?;
+    }
+    let mut conn = open(db_path, opts, existed)?;
+    let code = run(&mut conn, &steps, migrations::apply_up);
+    check_schema(&conn);
+    Ok(code)
+}
+
+fn down(
+    db_path: &str,
+    opts: &OpenOptions,
+    to: Option<u32>,
+    dry_run: bool,
+    backup: bool,
+) -> Result<ExitCode, String> {
+    if !Path::new(db_path).exists() {
+        error!(tag = "FATAL", path = db_path, "Database file not found");
+        return Ok(Kind::NoInput.exit_code());
+    }
+    let current = {
+        let conn = db::open_read_only(db_path, opts).map_err(|e| e.to_string())?;
+        migrations::current(&conn).map_err(|e| e.to_string())?
+    };
+    if current > migrations::latest() {
+        error!(
+            tag = "FATAL",
+            version = current,
+            latest = migrations::latest(),
+            "The database is newer than this octa-migrate"
+        );
+        return Ok(Kind::Data.exit_code());
+    }
+    let target = to.unwrap_or(current.saturating_sub(1));
+    if target >= current {
+        info!(tag = "OK", version = current, "Nothing to revert");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let steps = migrations::reverting(current, target);
+    if let Some(fixed) = steps.iter().find(|m| m.down.is_none()) {
+        error!(
+            tag = "FATAL",
+            version = fixed.version,
+            name = fixed.name,
+            "Migration cannot be reverted; restore a backup instead"
+        );
+        return Ok(Kind::Usage.exit_code());
+    }
+    if dry_run {
+        for migration in steps {
+            info!(
+                tag = "PLAN",
+                version = migration.version,
+                name = migration.name,
+                "Would revert"
+            );
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if backup {
+        write_backup(db_path, opts)?;
+    }
+    let mut conn = open(db_path, opts, true)?;
+    Ok(run(&mut conn, &steps, migrations::apply_down))
+}
+
+/// Applies `steps` in order, stopping at the first failure; the failed
+/// migration is rolled back and the ones before it stay applied.
+fn run(
+    conn: &mut Connection,
+    steps: &[&Migration],
+    apply: fn(&mut Connection, &Migration) -> rusqlite::Result<()>,
+) -> ExitCode {
+    for migration in steps {
+        if let Err(e) = apply(conn, migration) {
+            error!(
+                tag = "ERROR",
+                version = migration.version,
+                name = migration.name,
+                reason = %e,
+                "Migration rolled back"
+            );
+            let version = migrations::current(conn).unwrap_or_default();
+            info!(tag = "→", version, "Database left at this version");
+            return Kind::Io.exit_code();
+        }
+    }
+    let version = migrations::current(conn).unwrap_or_default();
+    info!(tag = "OK", version, "Database is at this version");
+    ExitCode::SUCCESS
+}
+
+fn open(db_path: &str, opts: &OpenOptions, existed: bool) -> Result<Connection, String> {
+    if existed {
+        return db::open_read_write(db_path, opts).map_err(|e| e.to_string());
+    }
+    if let Some(dir) = Path::new(db_path)
+        .parent()
+        .filter(|d| !d.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("could not create {}: {}", dir.display(), e))?;
+    }
+    info!(tag = "→", path = db_path, "Creating database");
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    // The Go server runs in WAL mode; a fresh file starts out the same way.
+    conn.pragma_update(None, "journal_mode", "WAL")
+        .map_err(|e| e.to_string())?;
+    conn.busy_timeout(opts.busy_timeout)
+        .map_err(|e| e.to_string())?;
+    Ok(conn)
+}
+
+fn write_backup(db_path: &str, opts: &OpenOptions) -> Result<(), String> {
+    let backup = schema::backup_path(db_path);
+    db::backup(db_path, opts, &backup).map_err(|e| format!("backup failed: {}", e))?;
+    info!(tag = "OK", path = %backup.display(), "Pre-migration backup written");
+    Ok(())
+}
+
+/// Migrations create what is missing but do not rewrite what was changed by
+/// hand. Drift against the canonical schema is left to octa-warden.
+fn check_schema(conn: &Connection) {
+    if migrations::current(conn).unwrap_or_default() == 0 {
+        return;
+    }
+    match schema::diagnose(conn, &["data".to_string()]) {
+        Ok(steps) => {
+            for step in steps {
+                warn!(
+                    tag = "SCHEMA",
+                    step = %step,
+                    "Schema drift the migrations do not cover; run octa-warden --migrate-schema"
+                );
+            }
+        }
+        Err(e) => warn!(tag = "SCHEMA", reason = %e, "Could not compare with the canonical schema"),
+    }
+}
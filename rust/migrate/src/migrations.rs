@@ -0,0 +1,156 @@
+use octa_warden_core::export::sha256_hex;
+use rusqlite::{params, Connection, Result};
+use tracing::info;
+
+/// One versioned change. Migrations are applied in version order, each in
+/// its own transaction together with its `schema_version` row.
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub up: &'static str,
+    /// `None` for migrations that cannot be reverted.
+    pub down: Option<&'static str>,
+}
+
+impl Migration {
+    /// Recorded when applied, so an edited migration shows up in `status`.
+    pub fn checksum(&self) -> String {
+        sha256_hex(self.up.as_bytes())
+    }
+}
+
+/// Every migration, oldest first. New ones go at the end as
+/// `migrations/NNNN_name.up.sql` (plus `.down.sql` when reversible);
+/// applied files are never edited.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        up: include_str!("../migrations/0001_initial.up.sql"),
+        down: None,
+    },
+    Migration {
+        version: 2,
+        name: "images_updated_at_index",
+        up: include_str!("../migrations/0002_images_updated_at_index.up.sql"),
+        down: Some(include_str!(
+            "../migrations/0002_images_updated_at_index.down.sql"
+        )),
+    },
+];
+
+/// The version the embedded migrations lead to.
+pub fn latest() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+const CREATE_VERSION_TABLE: &str = "CREATE TABLE IF NOT EXISTS schema_version (
+    version INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    checksum TEXT NOT NULL,
+    applied_at TEXT NOT NULL
+)";
+
+/// A row of `schema_version`.
+pub struct Applied {
+    pub version: u32,
+    pub name: String,
+    pub checksum: String,
+    pub applied_at: String,
+}
+
+/// The applied migrations, oldest first. Empty when the database has never
+/// been migrated (no `schema_version` table).
+pub fn applied(conn: &Connection) -> Result<Vec<Applied>> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Ok(Vec::new());
+    }
+    conn.prepare("SELECT version, name, checksum, applied_at FROM schema_version ORDER BY version")?
+        .query_map([], |row| {
+            Ok(Applied {
+                version: row.get(0)?,
+                name: row.get(1)?,
+                checksum: row.get(2)?,
+                applied_at: row.get(3)?,
+            })
+        })?
+        .collect()
+}
+
+/// The highest applied version; 0 for a database never migrated.
+pub fn current(conn: &Connection) -> Result<u32> {
+    Ok(applied(conn)?.last().map_or(0, |a| a.version))
+}
+
+/// The migrations `up` would apply to reach `target`.
+pub fn pending(current: u32, target: u32) -> Vec<&'static Migration> {
+    MIGRATIONS
+        .iter()
+        .filter(|m| m.version > current && m.version <= target)
+        .collect()
+}
+
+/// The migrations `down` would revert to get back to `target`, newest first.
+pub fn reverting(current: u32, target: u32) -> Vec<&'static Migration> {
+    MIGRATIONS
+        .iter()
+        .rev()
+        .filter(|m| m.version <= current && m.version > target)
+        .collect()
+}
+
+pub fn apply_up(conn: &mut Connection, migration: &Migration) -> Result<()> {
+    let tx = conn.transaction()?;
+    tx.execute_batch(CREATE_VERSION_TABLE)?;
+    tx.execute_batch(migration.up)?;
+    tx.execute(
+        "INSERT INTO schema_version (version, name, checksum, applied_at) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            migration.version,
+            migration.name,
+            migration.checksum(),
+            chrono::Utc::now().to_rfc3339()
+        ],
+    )?;
+    tx.commit()?;
+    info!(
+        tag = "UP",
+        version = migration.version,
+        name = migration.name,
+        "Migration applied"
+    );
+    Ok(())
+}
+
+/// Reverts `migration`. The caller has checked that it has a down script.
+pub fn apply_down(conn: &mut Connection, migration: &Migration) -> Result<()> {
+    let tx = conn.transaction()?;
+    tx.execute_batch(migration.down.unwrap_or_default())?;
+    tx.execute(
+        "DELETE FROM schema_version WHERE version = ?1",
+        [migration.version],
+    )?;
+    tx.commit()?;
+    info!(
+        tag = "DOWN",
+        version = migration.version,
+        name = migration.name,
+        "Migration reverted"
+    );
+    Ok(())
+}
+
+/// The first embedded migration below the current version that has no
+/// `schema_version` row, e.g. after rows were deleted by hand.
+pub fn gap(applied: &[Applied]) -> Option<&'static Migration> {
+    let current = applied.last()?.version;
+    MIGRATIONS
+        .iter()
+        .filter(|m| m.version < current)
+        .find(|m| !applied.iter().any(|a| a.version == m.version))
+}
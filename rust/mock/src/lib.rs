@@ -0,0 +1,275 @@
+use axum::extract::Request;
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use clap::Parser;
+use octa_config::{ConfigError, SecurityConfig, ServerConfig, Validate};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use octa_testkit::mock::{self, Faults};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, Level};
+
+/*
+OCTA-MOCK: In-memory Octa server for offline development
+=============================================
+Mission: Answer the upload, read, stat, list and delete API with the routes,
+         JSON and error codes of octa-server, from memory, so octa-pulse,
+         the client SDKs and their tests run in CI and on a laptop without
+         a deployment. Latency and failures can be injected, and the same
+         seed repeats them.
+Safety:  A test tool: it listens on 127.0.0.1 by default, keeps nothing
+         once stopped and serves a grey placeholder instead of generated
+         avatars. It only speaks HTTP; octa-pulse's gRPC tests need a
+         real server.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Serve the Octa API from memory, with injected latency and failures"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Address to serve on (overrides mock.listen)
+    #[arg(long, env = "OCTA_MOCK_LISTEN")]
+    listen: Option<String>,
+
+    /// Milliseconds added to every response (overrides mock.latency_ms)
+    #[arg(long)]
+    latency_ms: Option<u64>,
+
+    /// Milliseconds of random variation of the latency (overrides mock.jitter_ms)
+    #[arg(long)]
+    jitter_ms: Option<u64>,
+
+    /// Share of requests answered with an error, 0-1 (overrides mock.failure_rate)
+    #[arg(long)]
+    failure_rate: Option<f64>,
+
+    /// Status of those errors, 400-599 (overrides mock.failure_status)
+    #[arg(long)]
+    failure_status: Option<u16>,
+
+    /// Seed of the latency and failures; the same seed repeats them (overrides mock.seed)
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+
+    /// Minimum level to log; `debug` adds one line per request
+    #[arg(long, default_value_t = Level::INFO)]
+    log_level: Level,
+}
+
+/// The parts of config.yaml octa-mock reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    server: ServerConfig,
+    security: SecurityConfig,
+    mock: MockConfig,
+}
+
+/// `mock:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct MockConfig {
+    /// `127.0.0.1:<server.port>` when unset, where clients look for the server.
+    listen: Option<String>,
+    latency_ms: u64,
+    jitter_ms: u64,
+    failure_rate: f64,
+    failure_status: u16,
+    /// Unset draws one per run, which is logged.
+    seed: Option<u64>,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            listen: None,
+            latency_ms: 0,
+            jitter_ms: 0,
+            failure_rate: 0.0,
+            failure_status: 500,
+            seed: None,
+        }
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = self.server.validate();
+        let mock = &self.mock;
+        if self.security.upload_secret.trim().is_empty() {
+            problems.push((
+                "security.upload_secret".to_string(),
+                "is required; clients send it as X-Secret-Key".to_string(),
+            ));
+        }
+        if let Some(listen) = &mock.listen {
+            if listen.parse::<SocketAddr>().is_err() {
+                problems.push((
+                    "mock.listen".to_string(),
+                    format!("'{}' is not an address like 127.0.0.1:9980", listen),
+                ));
+            }
+        }
+        if !(0.0..=1.0).contains(&mock.failure_rate) {
+            problems.push((
+                "mock.failure_rate".to_string(),
+                format!("{} is not within 0-1", mock.failure_rate),
+            ));
+        }
+        if !(400..=599).contains(&mock.failure_status) {
+            problems.push((
+                "mock.failure_status".to_string(),
+                format!("{} is not an error status (400-599)", mock.failure_status),
+            ));
+        }
+        problems
+    }
+}
+
+/// Without a file, the environment and arguments alone configure it.
+/// Arguments are applied before the config is checked.
+fn load_config(args: &Args) -> Result<FileConfig, ConfigError> {
+    let found = octa_config::discover(args.config.as_deref().map(Path::new));
+    let mut config: FileConfig = match &found {
+        Some(found) => octa_config::read(found)?,
+        None => octa_config::from_env()?,
+    };
+    let mock = &mut config.mock;
+    if let Some(listen) = &args.listen {
+        mock.listen = Some(listen.clone());
+    }
+    if let Some(latency_ms) = args.latency_ms {
+        mock.latency_ms = latency_ms;
+    }
+    if let Some(jitter_ms) = args.jitter_ms {
+        mock.jitter_ms = jitter_ms;
+    }
+    if let Some(failure_rate) = args.failure_rate {
+        mock.failure_rate = failure_rate;
+    }
+    if let Some(failure_status) = args.failure_status {
+        mock.failure_status = failure_status;
+    }
+    if let Some(seed) = args.seed {
+        mock.seed = Some(seed);
+    }
+    let origin = found.unwrap_or_else(|| "<environment>".into());
+    octa_config::check(&origin, config)
+}
+
+/// The octa-mock command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa mock` the arguments it was given.
+#[tokio::main]
+pub async fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, args.log_level, false, false);
+
+    let config = match load_config(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let mock = config.mock;
+    let listen = mock
+        .listen
+        .unwrap_or_else(|| format!("127.0.0.1:{}", config.server.port));
+    let faults = Faults {
+        latency: Duration::from_millis(mock.latency_ms),
+        jitter: Duration::from_millis(mock.jitter_ms),
+        failure_rate: mock.failure_rate,
+        // Checked by validate().
+        status: StatusCode::from_u16(mock.failure_status).unwrap_or_default(),
+        seed: mock.seed.unwrap_or_else(rand::random),
+    };
+    let seed = faults.seed;
+
+    let base_url = format!("http://{}", listen);
+    let app = mock::router(&config.security.upload_secret, &base_url, faults)
+        .layer(middleware::from_fn(log_request));
+
+    let listener = match tokio::net::TcpListener::bind(&listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(tag = "FATAL", addr = %listen, reason = %e, "Could not bind");
+            return Kind::Unavailable.exit_code();
+        }
+    };
+    info!(
+        tag = "OK",
+        url = %base_url,
+        latency_ms = mock.latency_ms,
+        jitter_ms = mock.jitter_ms,
+        failure_rate = mock.failure_rate,
+        failure_status = mock.failure_status,
+        seed,
+        "Mock server listening"
+    );
+
+    if let Err(e) = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown())
+        .await
+    {
+        error!(tag = "FATAL", reason = %e, "Mock server stopped");
+        return Kind::Unavailable.exit_code();
+    }
+    info!(tag = "OK", seed, "Shut down");
+    ExitCode::SUCCESS
+}
+
+async fn log_request(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    debug!(
+        tag = "HTTP",
+        method = %method,
+        path = %path,
+        status = response.status().as_u16(),
+        injected = response.headers().contains_key("X-Mock-Fault"),
+        ms = started.elapsed().as_millis() as u64,
+        "Request"
+    );
+    response
+}
+
+/// Resolves on Ctrl-C or SIGTERM.
+async fn shutdown() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
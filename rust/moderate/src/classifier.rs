@@ -0,0 +1,125 @@
+use image::imageops::FilterType;
+use serde::Deserialize;
+use std::path::Path;
+use tract_onnx::prelude::*;
+
+/// Where the channels go in the model's input tensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// `[1, 3, size, size]`, as models exported from PyTorch expect.
+    Nchw,
+    /// `[1, size, size, 3]`, as models exported from TensorFlow expect.
+    Nhwc,
+}
+
+/// How images are turned into the model's input.
+#[derive(Debug, Clone)]
+pub struct Input {
+    pub size: usize,
+    pub layout: Layout,
+    /// Per RGB channel, applied to pixels scaled to 0–1: `(x - mean) / std`.
+    pub mean: [f32; 3],
+    pub std: [f32; 3],
+}
+
+/// An image classifier loaded from an ONNX file: one image in, one score
+/// per label out.
+pub struct Classifier {
+    model: Arc<TypedRunnableModel>,
+    input: Input,
+    outputs: usize,
+    softmax: bool,
+}
+
+impl Classifier {
+    /// Loads and optimizes the model for `input`; `outputs` is the number of
+    /// scores it must produce.
+    pub fn load(path: &Path, input: Input, outputs: usize, softmax: bool) -> Result<Self, String> {
+        let shape = match input.layout {
+            Layout::Nchw => [1, 3, input.size, input.size],
+            Layout::Nhwc => [1, input.size, input.size, 3],
+        };
+        let model = tract_onnx::onnx()
+            .model_for_path(path)
+            .and_then(|model| model.with_input_fact(0, f32::fact(shape).into()))
+            .and_then(|model| model.into_optimized())
+            .and_then(|model| model.into_runnable())
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Self {
+            model,
+            input,
+            outputs,
+            softmax,
+        })
+    }
+
+    /// Scores of the image in `bytes`, in label order.
+    pub fn scores(&self, bytes: &[u8]) -> Result<Vec<f32>, String> {
+        let image = image::load_from_memory(bytes).map_err(|e| format!("decode: {}", e))?;
+        let size = self.input.size as u32;
+        let pixels = image
+            .resize_exact(size, size, FilterType::Triangle)
+            .to_rgb8();
+        let Input {
+            size,
+            layout,
+            mean,
+            std,
+        } = self.input;
+        let value = |x: usize, y: usize, c: usize| {
+            let raw = pixels.get_pixel(x as u32, y as u32)[c] as f32 / 255.0;
+            (raw - mean[c]) / std[c]
+        };
+        let tensor: Tensor = match layout {
+            Layout::Nchw => {
+                tract_ndarray::Array4::from_shape_fn((1, 3, size, size), |(_, c, y, x)| {
+                    value(x, y, c)
+                })
+            }
+            Layout::Nhwc => {
+                tract_ndarray::Array4::from_shape_fn((1, size, size, 3), |(_, y, x, c)| {
+                    value(x, y, c)
+                })
+            }
+        }
+        .into();
+
+        let outputs = self
+            .model
+            .run(tvec!(tensor.into()))
+            .map_err(|e| format!("inference: {}", e))?;
+        let output = outputs
+            .first()
+            .ok_or_else(|| "inference: the model has no output".to_string())?;
+        let mut scores: Vec<f32> = output
+            .to_plain_array_view::<f32>()
+            .map_err(|e| format!("inference: {}", e))?
+            .iter()
+            .copied()
+            .collect();
+        if scores.len() != self.outputs {
+            return Err(format!(
+                "the model produces {} scores, moderate.labels names {}",
+                scores.len(),
+                self.outputs
+            ));
+        }
+        if self.softmax {
+            softmax(&mut scores);
+        }
+        Ok(scores)
+    }
+}
+
+fn softmax(scores: &mut [f32]) {
+    let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let mut sum = 0.0;
+    for score in scores.iter_mut() {
+        *score = (*score - max).exp();
+        sum += *score;
+    }
+    for score in scores.iter_mut() {
+        *score /= sum;
+    }
+}
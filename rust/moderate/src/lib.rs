@@ -0,0 +1,443 @@
+use clap::Parser;
+use classifier::{Classifier, Input, Layout};
+use octa_config::{ConfigError, DatabaseConfig, Validate};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use octa_warden_core::db::{self, OpenOptions};
+use octa_warden_core::notify;
+use octa_warden_core::plan::{self, Action, Plan, PlanEntry};
+use octa_warden_core::schedule::parse_interval;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+use tracing::{error, info, warn, Level};
+
+mod classifier;
+mod store;
+
+/*
+OCTA-MODERATE: Content moderation of uploads
+=============================================
+Mission: Run an ONNX image classifier (NSFW, violence, ...) over every asset
+         not scanned yet, record its scores in the moderation table, and
+         flag the assets scoring over a threshold of moderate.thresholds.
+         Octa has no upload events, so new assets are found by polling.
+Safety:  Opt-in: flagged assets are only recorded (and posted to
+         moderate.webhook_url) unless moderate.quarantine is set; then they
+         move into the quarantine table as octa-warden triage would, and
+         can be restored from there.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Classify new Octa uploads and quarantine flagged ones"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// SQLite database to moderate (overrides database.path)
+    #[arg(long = "db", env = "OCTA_DB_PATH", value_name = "PATH")]
+    db_path: Option<String>,
+
+    /// Milliseconds to wait on a locked database before giving up (SQLITE_BUSY)
+    #[arg(long, default_value_t = 5000)]
+    busy_timeout: u64,
+
+    /// Scan what is pending and exit, instead of polling every moderate.interval
+    #[arg(long)]
+    once: bool,
+
+    /// Only record scores, even with moderate.quarantine set
+    #[arg(long)]
+    no_quarantine: bool,
+
+    /// Do not post to moderate.webhook_url
+    #[arg(long)]
+    no_notify: bool,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+}
+
+/// The parts of config.yaml octa-moderate reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    database: DatabaseConfig,
+    moderate: ModerateConfig,
+}
+
+/// `moderate:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ModerateConfig {
+    /// Path of the ONNX model.
+    model: String,
+    /// Names of the model's outputs, in order.
+    labels: Vec<String>,
+    /// Labels that flag an asset, each from this score on (0–1).
+    thresholds: BTreeMap<String, f32>,
+    /// Turn the outputs into probabilities (for models that emit logits).
+    softmax: bool,
+    /// Width and height the model expects.
+    input_size: usize,
+    layout: Layout,
+    mean: [f32; 3],
+    std: [f32; 3],
+    /// Move flagged assets into the quarantine table.
+    quarantine: bool,
+    /// Pause between polls.
+    interval: String,
+    /// Assets read from the database at a time.
+    batch_size: usize,
+    webhook_url: Option<String>,
+}
+
+impl Default for ModerateConfig {
+    fn default() -> Self {
+        Self {
+            model: String::new(),
+            labels: Vec::new(),
+            thresholds: BTreeMap::new(),
+            softmax: false,
+            input_size: 224,
+            layout: Layout::Nchw,
+            // ImageNet statistics, which most image classifiers are trained with.
+            mean: [0.485, 0.456, 0.406],
+            std: [0.229, 0.224, 0.225],
+            quarantine: false,
+            interval: "30s".to_string(),
+            batch_size: 16,
+            webhook_url: None,
+        }
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        if self.database.path.trim().is_empty() {
+            problems.push(("database.path".to_string(), "is required".to_string()));
+        }
+        let moderate = &self.moderate;
+        let mut problem = |field: &str, reason: String| {
+            problems.push((format!("moderate.{}", field), reason));
+        };
+        if moderate.model.trim().is_empty() {
+            problem("model", "is required".to_string());
+        }
+        if moderate.labels.is_empty() {
+            problem("labels", "must name the model's outputs".to_string());
+        }
+        for (label, threshold) in &moderate.thresholds {
+            if !moderate.labels.contains(label) {
+                problem(
+                    &format!("thresholds.{}", label),
+                    "is not one of moderate.labels".to_string(),
+                );
+            }
+            if !(0.0..=1.0).contains(threshold) {
+                problem(
+                    &format!("thresholds.{}", label),
+                    "must be between 0 and 1".to_string(),
+                );
+            }
+        }
+        if moderate.input_size == 0 {
+            problem("input_size", "must not be 0".to_string());
+        }
+        if moderate.std.contains(&0.0) {
+            problem("std", "must not contain 0".to_string());
+        }
+        if moderate.batch_size == 0 {
+            problem("batch_size", "must not be 0".to_string());
+        }
+        if let Err(e) = parse_interval(&moderate.interval) {
+            problem("interval", e);
+        }
+        problems.extend(octa_config::check_url(
+            "moderate.webhook_url",
+            moderate.webhook_url.as_deref(),
+        ));
+        problems
+    }
+}
+
+/// `--db` replaces database.path; the model still comes from the file.
+fn load_config(path: Option<&str>, db_path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    let path = octa_config::discover(path.map(Path::new)).ok_or(ConfigError::NotFound)?;
+    let mut config: FileConfig = octa_config::read(&path)?;
+    if let Some(db_path) = db_path {
+        config.database.path = db_path.to_string();
+    }
+    octa_config::check(&path, config)
+}
+
+/// A flagged asset, as reported to the webhook.
+#[derive(Debug, Serialize)]
+struct Flagged {
+    id: String,
+    keys: Vec<String>,
+    label: String,
+    score: f32,
+    quarantined: bool,
+}
+
+#[derive(Debug, Default)]
+struct Round {
+    scanned: u64,
+    failed: u64,
+    flagged: Vec<Flagged>,
+}
+
+/// The octa-moderate command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa moderate` the arguments it was given.
+pub fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, Level::INFO, false, false);
+
+    let config = match load_config(args.config.as_deref(), args.db_path.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let moderate = &config.moderate;
+    let db_path = config.database.path.as_str();
+    if !Path::new(db_path).exists() {
+        error!(tag = "FATAL", path = %db_path, "Database file not found");
+        return Kind::NoInput.exit_code();
+    }
+    let input = Input {
+        size: moderate.input_size,
+        layout: moderate.layout,
+        mean: moderate.mean,
+        std: moderate.std,
+    };
+    let model_path = Path::new(&moderate.model);
+    let classifier =
+        match Classifier::load(model_path, input, moderate.labels.len(), moderate.softmax) {
+            Ok(classifier) => classifier,
+            Err(e) => {
+                error!(tag = "FATAL", reason = %e, "Could not load the model");
+                return match model_path.exists() {
+                    true => Kind::Data.exit_code(),
+                    false => Kind::NoInput.exit_code(),
+                };
+            }
+        };
+    let opts = OpenOptions {
+        busy_timeout: Duration::from_millis(args.busy_timeout),
+        immutable: false,
+    };
+    let conn = match db::open_read_write(db_path, &opts)
+        .and_then(|conn| store::ensure_table(&conn).map(|_| conn))
+    {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!(tag = "FATAL", path = %db_path, reason = %e, "Could not open database");
+            return Kind::Io.exit_code();
+        }
+    };
+
+    let scanner = Scanner {
+        conn: &conn,
+        db_path,
+        opts: &opts,
+        classifier: &classifier,
+        config: moderate,
+        model: model_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| moderate.model.clone()),
+        quarantine: moderate.quarantine && !args.no_quarantine,
+    };
+    let interval = parse_interval(&moderate.interval).unwrap_or(Duration::from_secs(30));
+    info!(
+        tag = "→",
+        database = %db_path,
+        model = %scanner.model,
+        quarantine = scanner.quarantine,
+        "Moderating uploads"
+    );
+
+    loop {
+        let failed = match scanner.round() {
+            Ok(round) => {
+                if round.scanned > 0 || args.once {
+                    let quarantined = round.flagged.iter().filter(|f| f.quarantined).count();
+                    info!(
+                        tag = if round.failed == 0 { "OK" } else { "WARN" },
+                        scanned = round.scanned,
+                        flagged = round.flagged.len(),
+                        quarantined,
+                        failed = round.failed,
+                        "Moderation round finished"
+                    );
+                }
+                if let (Some(url), false, false) = (
+                    &moderate.webhook_url,
+                    args.no_notify,
+                    round.flagged.is_empty(),
+                ) {
+                    let payload = json!({
+                        "source": "octa-moderate",
+                        "host": notify::hostname(),
+                        "database": db_path,
+                        "model": scanner.model,
+                        "flagged": round.flagged,
+                    });
+                    notify::post(url, &payload, "webhook");
+                }
+                false
+            }
+            Err(e) => {
+                error!(tag = "ERROR", reason = %e, "Moderation round failed");
+                true
+            }
+        };
+        if args.once {
+            return if failed {
+                Kind::Io.exit_code()
+            } else {
+                ExitCode::SUCCESS
+            };
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+struct Scanner<'a> {
+    conn: &'a Connection,
+    db_path: &'a str,
+    opts: &'a OpenOptions,
+    classifier: &'a Classifier,
+    config: &'a ModerateConfig,
+    /// Recorded with the scores, to tell scans by different models apart.
+    model: String,
+    quarantine: bool,
+}
+
+impl Scanner<'_> {
+    /// Scans batches until nothing is pending.
+    fn round(&self) -> rusqlite::Result<Round> {
+        let mut round = Round::default();
+        loop {
+            let batch = store::pending(self.conn, self.config.batch_size)?;
+            if batch.is_empty() {
+                return Ok(round);
+            }
+            for asset in &batch {
+                self.scan(asset, &mut round)?;
+            }
+        }
+    }
+
+    fn scan(&self, asset: &store::Pending, round: &mut Round) -> rusqlite::Result<()> {
+        round.scanned += 1;
+        let scores = match self.classifier.scores(&asset.data) {
+            Ok(scores) => scores,
+            Err(e) => {
+                round.failed += 1;
+                warn!(tag = "FAIL", id = %asset.id, reason = %e, "Could not score asset");
+                // Recorded, so it is not retried until it changes.
+                return store::record(
+                    self.conn,
+                    asset,
+                    &store::Verdict {
+                        model: &self.model,
+                        scores: None,
+                        flagged: None,
+                        error: Some(e),
+                    },
+                );
+            }
+        };
+
+        let labels = &self.config.labels;
+        let flagged = labels
+            .iter()
+            .zip(&scores)
+            .filter(|(label, score)| {
+                self.config
+                    .thresholds
+                    .get(label.as_str())
+                    .is_some_and(|threshold| **score >= *threshold)
+            })
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(label, score)| (label.as_str(), *score));
+        let scores_json: serde_json::Map<String, serde_json::Value> = labels
+            .iter()
+            .zip(&scores)
+            .map(|(label, score)| (label.clone(), json!(score)))
+            .collect();
+        store::record(
+            self.conn,
+            asset,
+            &store::Verdict {
+                model: &self.model,
+                scores: Some(serde_json::Value::Object(scores_json).to_string()),
+                flagged,
+                error: None,
+            },
+        )?;
+
+        let Some((label, score)) = flagged else {
+            return Ok(());
+        };
+        warn!(
+            tag = "FLAG",
+            id = %asset.id,
+            keys = %asset.keys.join(","),
+            label,
+            score = format!("{:.3}", score),
+            "Asset flagged"
+        );
+        let quarantined = self.quarantine && self.quarantine_asset(&asset.id, label, score);
+        round.flagged.push(Flagged {
+            id: asset.id.clone(),
+            keys: asset.keys.clone(),
+            label: label.to_string(),
+            score,
+            quarantined,
+        });
+        Ok(())
+    }
+
+    /// Moves the asset into the quarantine table, through the same path as
+    /// `octa-warden triage --apply`.
+    fn quarantine_asset(&self, id: &str, label: &str, score: f32) -> bool {
+        let plan = Plan::new(
+            self.db_path,
+            vec![PlanEntry {
+                id: id.to_string(),
+                action: Action::Quarantine,
+                kind: "moderation".to_string(),
+                reason: format!("moderation: {} {:.3} ({})", label, score, self.model),
+            }],
+        );
+        match plan::apply(self.db_path, self.opts, &plan) {
+            Ok(summary) if summary.applied > 0 => {
+                if let Err(e) = store::mark_quarantined(self.conn, id) {
+                    warn!(tag = "WARN", id = %id, reason = %e, "Quarantined, but not marked so in the moderation table");
+                }
+                true
+            }
+            Ok(_) => false,
+            Err(e) => {
+                error!(tag = "ERROR", id = %id, reason = %e, "Could not quarantine asset");
+                false
+            }
+        }
+    }
+}
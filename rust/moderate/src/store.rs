@@ -0,0 +1,88 @@
+use rusqlite::{params, Connection, Result};
+
+/// One row per asset scanned, kept after the asset is quarantined.
+/// `image_updated_at` is the version that was scanned: an asset uploaded
+/// again under the same id is scanned again.
+const CREATE_TABLE: &str = "CREATE TABLE IF NOT EXISTS moderation (
+    image_id         TEXT PRIMARY KEY,
+    image_updated_at DATETIME,
+    model            TEXT NOT NULL,
+    scores           TEXT,
+    label            TEXT,
+    score            REAL,
+    error            TEXT,
+    quarantined      INTEGER NOT NULL DEFAULT 0,
+    scanned_at       DATETIME NOT NULL
+)";
+
+pub fn ensure_table(conn: &Connection) -> Result<()> {
+    conn.execute(CREATE_TABLE, []).map(|_| ())
+}
+
+/// An asset not scanned yet, or changed since.
+pub struct Pending {
+    pub id: String,
+    pub data: Vec<u8>,
+    pub updated_at: Option<String>,
+    pub keys: Vec<String>,
+}
+
+/// Up to `limit` assets to scan, oldest first.
+pub fn pending(conn: &Connection, limit: usize) -> Result<Vec<Pending>> {
+    conn.prepare(
+        "SELECT i.id, CAST(i.data AS BLOB), CAST(i.updated_at AS TEXT),
+                (SELECT group_concat(k.key, ',') FROM key_mappings k WHERE k.image_id = i.id)
+         FROM images i LEFT JOIN moderation m ON m.image_id = i.id
+         WHERE m.image_id IS NULL OR m.image_updated_at IS NOT CAST(i.updated_at AS TEXT)
+         ORDER BY i.created_at, i.id
+         LIMIT ?1",
+    )?
+    .query_map([limit as i64], |row| {
+        let keys: Option<String> = row.get(3)?;
+        Ok(Pending {
+            id: row.get(0)?,
+            data: row.get::<_, Option<Vec<u8>>>(1)?.unwrap_or_default(),
+            updated_at: row.get(2)?,
+            keys: keys
+                .map(|k| k.split(',').map(str::to_string).collect())
+                .unwrap_or_default(),
+        })
+    })?
+    .collect()
+}
+
+/// What scanning one asset found.
+pub struct Verdict<'a> {
+    pub model: &'a str,
+    /// JSON object of label to score; `None` when the asset could not be scored.
+    pub scores: Option<String>,
+    /// The flagged label with the highest score over its threshold.
+    pub flagged: Option<(&'a str, f32)>,
+    pub error: Option<String>,
+}
+
+pub fn record(conn: &Connection, asset: &Pending, verdict: &Verdict) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO moderation
+            (image_id, image_updated_at, model, scores, label, score, error, quarantined, scanned_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, datetime('now'))",
+        params![
+            asset.id,
+            asset.updated_at,
+            verdict.model,
+            verdict.scores,
+            verdict.flagged.map(|(label, _)| label),
+            verdict.flagged.map(|(_, score)| score as f64),
+            verdict.error,
+        ],
+    )
+    .map(|_| ())
+}
+
+pub fn mark_quarantined(conn: &Connection, id: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE moderation SET quarantined = 1 WHERE image_id = ?1",
+        [id],
+    )
+    .map(|_| ())
+}
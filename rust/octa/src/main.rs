@@ -0,0 +1,172 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::ExitCode;
+use tracing::{error, Level};
+
+/*
+OCTA: One entry point for the Octa tools
+=============================================
+Mission: Run every tool as `octa <tool> [ARGS]` (`octa warden`, `octa ctl
+         list`, `octa bench`), with the config file, database and log format
+         given once, before the tool name, for all of them.
+Safety:  Adds nothing to what a tool does: it resolves the shared options
+         into the environment the tools already read (OCTA_CONFIG,
+         OCTA_DB_PATH, OCTA_LOG_FORMAT) and then runs the tool, linked into
+         this binary, with the remaining arguments.
+*/
+
+/// A tool's command line (`cli` of its crate), called with the program name
+/// first.
+type Cli = fn(Vec<OsString>) -> ExitCode;
+
+/// `(command, command line, what it does)`, in the order `octa tools` lists them.
+const TOOLS: &[(&str, Cli, &str)] = &[
+    ("server", |args| octa_server::cli(args), "Run the Rust server implementation"),
+    ("ctl", |args| octa_ctl::cli(args), "Admin CLI: upload, get, delete, list, stat, purge, check-tls"),
+    ("warden", |args| octa_warden::cli(args), "Audit the database for corrupt, duplicate and drifting assets"),
+    ("bench", |args| octa_pulse::cli(args), "Load-test a running instance"),
+    ("migrate", |args| octa_migrate::cli(args), "Versioned schema migrations (status, up, down)"),
+    ("backup", |args| octa_backup::cli(args), "Back up, restore, verify and prune generations"),
+    ("drill", |args| octa_drill::cli(args), "Disaster-recovery drill: back up, restore, audit and load-test"),
+    ("gc", |args| octa_gc::cli(args), "Collect orphaned, unowned and expired assets"),
+    ("sync", |args| octa_sync::cli(args), "Replicate assets between two instances or databases"),
+    ("rekey", |args| octa_rekey::cli(args), "Rename keys in bulk by rule, with a mapping file"),
+    ("seed", |args| octa_seed::cli(args), "Generate a synthetic dataset into an instance or database"),
+    ("warm", |args| octa_warm::cli(args), "Pre-warm caches by requesting every key"),
+    ("tail", |args| octa_tail::cli(args), "Stream new and replaced uploads as NDJSON"),
+    ("quota", |args| octa_quota::cli(args), "Report assets and bytes per tenant against quotas"),
+    ("keys", |args| octa_keys::cli(args), "Issue, list, revoke and rotate upload secrets"),
+    ("sign", |args| octa_sign::cli(args), "Create or check time-limited links to private keys"),
+    ("identicon", |args| octa_identicon::cli(args), "Render default avatars, or upload them for a list of users"),
+    ("gravatar", |args| octa_gravatar::cli(args), "Import avatars from Gravatar or Libravatar"),
+    ("transcode", |args| octa_transcode::cli(args), "Store WebP and AVIF derivatives of every asset"),
+    ("moderate", |args| octa_moderate::cli(args), "Score new uploads with an ONNX classifier"),
+    ("exporter", |args| octa_exporter::cli(args), "Serve Prometheus metrics about the database"),
+    ("logs", |args| octa_logs::cli(args), "Report top keys, hit ratios, latency and abusive clients from access logs"),
+    ("gateway", |args| octa_gateway::cli(args), "Serve an S3-compatible API for a running instance"),
+    ("edge", |args| octa_edge::cli(args), "Cache hot avatars of a running instance, with purge hooks"),
+    ("chaos", |args| octa_chaos::cli(args), "Proxy that injects latency, bandwidth caps and cut responses"),
+    ("hooksink", |args| octa_hooksink::cli(args), "Record the webhooks Octa sends, for integration tests"),
+    ("ratecheck", |args| octa_ratecheck::cli(args), "Compare the server's rate limit with security.rate_limit"),
+    ("mock", |args| octa_mock::cli(args), "Serve the API from memory, with injected latency and failures"),
+];
+
+/// Other names some tools go by.
+const ALIASES: &[(&str, &str)] = &[("pulse", "bench"), ("server-rust", "server")];
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Run the Octa tools: octa <tool> [ARGS]",
+    after_help = "Run `octa tools` for the list, `octa <tool> --help` for a tool's options."
+)]
+struct Args {
+    /// Path to the configuration file, for every tool (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// SQLite database, for the tools that open one (overrides database.path)
+    #[arg(long = "db", env = "OCTA_DB_PATH", value_name = "PATH")]
+    db_path: Option<String>,
+
+    /// Log format, for every tool
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List the tools
+    Tools,
+    /// Print the config file every tool would read
+    Config,
+    /// A tool and its arguments, e.g. `warden --quiet`
+    #[command(external_subcommand)]
+    Tool(Vec<OsString>),
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    // Resolved here, so a tool started from another directory, or one that
+    // falls back to the defaults without a file, reads the same one.
+    let config = match octa_config::discover(args.config.as_deref().map(Path::new)) {
+        Some(path) if !path.is_file() => {
+            init_logging(args.log_format);
+            error!(tag = "FATAL", path = %path.display(), "Config file not found");
+            return Kind::Config.exit_code();
+        }
+        Some(path) => Some(path.canonicalize().unwrap_or(path)),
+        None => None,
+    };
+
+    match args.command {
+        Command::Tools => {
+            let width = TOOLS.iter().map(|(name, ..)| name.len()).max().unwrap_or(0);
+            for (name, _, about) in TOOLS {
+                println!("{:<width$}  {}", name, about);
+            }
+            ExitCode::SUCCESS
+        }
+        Command::Config => match config {
+            Some(path) => {
+                println!("{}", path.display());
+                ExitCode::SUCCESS
+            }
+            None => {
+                init_logging(args.log_format);
+                error!(tag = "FATAL", "No config file found");
+                Kind::Config.exit_code()
+            }
+        },
+        Command::Tool(argv) => {
+            let Some((name, rest)) = argv.split_first() else {
+                return Kind::Usage.exit_code();
+            };
+            let name = name.to_string_lossy();
+            let Some((name, cli)) = tool(&name) else {
+                init_logging(args.log_format);
+                error!(tag = "FATAL", tool = %name, "Unknown tool; `octa tools` lists them");
+                return Kind::Usage.exit_code();
+            };
+            // Nothing else runs yet, so the environment is still ours to set.
+            if let Some(path) = &config {
+                std::env::set_var(octa_config::PATH_ENV, path);
+            }
+            if let Some(db_path) = &args.db_path {
+                std::env::set_var("OCTA_DB_PATH", db_path);
+            }
+            if let Some(format) = args.log_format.to_possible_value() {
+                std::env::set_var("OCTA_LOG_FORMAT", format.get_name());
+            }
+            // `octa warden` in the tool's usage lines and errors.
+            let program = OsString::from(format!("octa {}", name));
+            cli([program].into_iter().chain(rest.iter().cloned()).collect())
+        }
+    }
+}
+
+/// Logging for the launcher's own failures only: the tool sets it up
+/// itself, and it can be set up once per process.
+fn init_logging(format: LogFormat) {
+    octa_logging::init(format, Level::INFO, false, false);
+}
+
+/// The command and command line behind a tool name or alias.
+fn tool(name: &str) -> Option<(&'static str, Cli)> {
+    let name = ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, tool)| tool);
+    TOOLS
+        .iter()
+        .find(|(tool, ..)| *tool == name)
+        .map(|(tool, cli, _)| (*tool, *cli))
+}
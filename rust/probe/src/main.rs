@@ -0,0 +1,232 @@
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+/*
+OCTA-PROBE: Health check for an Octa server
+=============================================
+Mission: Ask the health endpoint once and answer with the exit code, for
+         Docker HEALTHCHECK and Kubernetes exec probes, without curl or the
+         benchmark tool in the runtime image.
+Safety:  One GET with a deadline; plain HTTP only, as probes talk to the
+         server inside its own container.
+*/
+
+const USAGE: &str = "\
+Check that an Octa server answers its health endpoint
+
+Usage: octa-probe [OPTIONS] [URL]
+
+Arguments:
+  [URL]  Endpoint to check [env: OCTA_PROBE_URL] [default: http://127.0.0.1:9980/health]
+
+Options:
+  -t, --timeout <SECS>  Seconds for the whole check, connect included [default: 3]
+  -q, --quiet           Print nothing; the exit code alone tells
+  -h, --help            Print help
+  -V, --version         Print version
+
+Exits 0 when the server answers 2xx in time, else 1.";
+
+const DEFAULT_URL: &str = "http://127.0.0.1:9980/health";
+
+struct Args {
+    url: String,
+    timeout: Duration,
+    quiet: bool,
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => return ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("octa-probe: {}\n\n{}", e, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let started = Instant::now();
+    match probe(&args.url, args.timeout) {
+        Ok(status) => {
+            if !args.quiet {
+                println!(
+                    "ok: {} answered {} in {}ms",
+                    args.url,
+                    status,
+                    started.elapsed().as_millis()
+                );
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            if !args.quiet {
+                println!("unhealthy: {}: {}", args.url, e);
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// `None` when only help or the version was asked for.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut url = None;
+    let mut timeout = Duration::from_secs(3);
+    let mut quiet = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(None);
+            }
+            "-V" | "--version" => {
+                println!("octa-probe {}", env!("CARGO_PKG_VERSION"));
+                return Ok(None);
+            }
+            "-q" | "--quiet" => quiet = true,
+            "-t" | "--timeout" => {
+                let value = args.next().ok_or("--timeout needs a value")?;
+                timeout = seconds(&value)?;
+            }
+            _ if arg.starts_with("--timeout=") => timeout = seconds(&arg["--timeout=".len()..])?,
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ if url.is_none() => url = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    let url = url
+        .or_else(|| {
+            std::env::var("OCTA_PROBE_URL")
+                .ok()
+                .filter(|u| !u.is_empty())
+        })
+        .unwrap_or_else(|| DEFAULT_URL.to_string());
+    Ok(Some(Args {
+        url,
+        timeout,
+        quiet,
+    }))
+}
+
+/// Positive seconds, no longer than a deadline can lie ahead.
+fn seconds(value: &str) -> Result<Duration, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|s| *s > 0.0)
+        .and_then(|s| Duration::try_from_secs_f64(s).ok())
+        .filter(|timeout| Instant::now().checked_add(*timeout).is_some())
+        .ok_or_else(|| format!("invalid timeout '{}' (seconds, e.g. 3 or 0.5)", value))
+}
+
+/// The status code of a 2xx answer; anything else is the reason it failed.
+fn probe(url: &str, timeout: Duration) -> Result<u16, String> {
+    let deadline = Instant::now() + timeout;
+    let (host, port, path) = split_url(url)?;
+
+    let addrs = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("cannot resolve {}: {}", host, e))?;
+    let mut last = format!("{} resolves to no address", host);
+    let mut stream = None;
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, remaining(deadline)?) {
+            Ok(s) => {
+                stream = Some(s);
+                break;
+            }
+            Err(e) => last = format!("cannot connect to {}: {}", addr, e),
+        }
+    }
+    let mut stream = stream.ok_or(last)?;
+
+    stream
+        .set_write_timeout(Some(remaining(deadline)?))
+        .map_err(|e| e.to_string())?;
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: octa-probe/{}\r\nConnection: close\r\n\r\n",
+        path,
+        host,
+        env!("CARGO_PKG_VERSION")
+    );
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|e| format!("sending request: {}", e))?;
+
+    // The status line is all that is needed; the body is not read.
+    let mut head = Vec::new();
+    let mut buf = [0u8; 512];
+    while !head.windows(2).any(|w| w == b"\r\n") {
+        stream
+            .set_read_timeout(Some(remaining(deadline)?))
+            .map_err(|e| e.to_string())?;
+        let n = stream.read(&mut buf).map_err(|e| match e.kind() {
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => {
+                format!("no answer within {:?}", timeout)
+            }
+            _ => format!("reading answer: {}", e),
+        })?;
+        if n == 0 {
+            return Err("connection closed before an answer".to_string());
+        }
+        head.extend_from_slice(&buf[..n]);
+        if head.len() > 8192 {
+            return Err("answer is not HTTP".to_string());
+        }
+    }
+
+    let line = String::from_utf8_lossy(&head);
+    let line = line.lines().next().unwrap_or_default();
+    let status = line
+        .strip_prefix("HTTP/")
+        .and_then(|rest| rest.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| format!("answer is not HTTP: {:?}", line))?;
+    if (200..300).contains(&status) {
+        Ok(status)
+    } else {
+        Err(format!(
+            "answered {}",
+            line.split_once(' ').map_or(line, |(_, s)| s)
+        ))
+    }
+}
+
+/// Host, port and path of a plain `http://` URL.
+fn split_url(url: &str) -> Result<(&str, u16, &str), String> {
+    let rest = match url.split_once("://") {
+        Some(("http", rest)) => rest,
+        Some((scheme, _)) => {
+            return Err(format!(
+                "{} is not supported; probe the server's plain HTTP port",
+                scheme
+            ))
+        }
+        None => url,
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => (
+            host,
+            port.parse()
+                .map_err(|_| format!("invalid port '{}'", port))?,
+        ),
+        _ => (authority, 80),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(format!("no host in '{}'", url));
+    }
+    Ok((host, port, path))
+}
+
+fn remaining(deadline: Instant) -> Result<Duration, String> {
+    deadline
+        .checked_duration_since(Instant::now())
+        .filter(|d| !d.is_zero())
+        .ok_or_else(|| "timed out".to_string())
+}
@@ -0,0 +1,403 @@
+use clap::Parser;
+use octa_config::{ConfigError, DatabaseConfig, Validate};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use octa_warden_core::db::{self, OpenOptions};
+use octa_warden_core::growth::{format_bytes, parse_bytes};
+use octa_warden_core::health::Verdict;
+use octa_warden_core::notify;
+use octa_warden_core::tenants::{self, Attribution, Usage};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashSet;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+use tracing::{error, info, warn, Level};
+
+/*
+OCTA-QUOTA: Storage per tenant against its quota
+=============================================
+Mission: Count the assets, keys and bytes of every tenant (a key prefix),
+         compare them with quota.tenants and quota.default, and report who
+         is near or over. Exit 0 when every tenant is within its quota,
+         1 when one is near it, 2 when one is over.
+Safety:  Read-only; nothing is deleted or refused. Over-limit tenants are
+         reported, and posted to quota.webhook_url when it is set.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Report Octa storage per tenant against configured quotas"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// SQLite database to measure (overrides database.path; the config file becomes optional)
+    #[arg(long = "db", env = "OCTA_DB_PATH", value_name = "PATH")]
+    db_path: Option<String>,
+
+    /// Milliseconds to wait on a locked database before giving up (SQLITE_BUSY)
+    #[arg(long, default_value_t = 5000)]
+    busy_timeout: u64,
+
+    /// Path segments that name a tenant when no prefix matches (overrides quota.depth)
+    #[arg(long)]
+    depth: Option<usize>,
+
+    /// Print JSON instead of columns
+    #[arg(long)]
+    json: bool,
+
+    /// Do not post to quota.webhook_url
+    #[arg(long)]
+    no_notify: bool,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+}
+
+/// The parts of config.yaml octa-quota reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    database: DatabaseConfig,
+    quota: QuotaConfig,
+}
+
+/// `quota:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct QuotaConfig {
+    depth: usize,
+    tenants: Vec<TenantQuota>,
+    /// Limits of tenants without an entry of their own.
+    default: Limits,
+    /// Share of a limit from which a tenant is reported as near it.
+    warn_percent: f64,
+    webhook_url: Option<String>,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self {
+            depth: 1,
+            tenants: Vec::new(),
+            default: Limits::default(),
+            warn_percent: 80.0,
+            webhook_url: None,
+        }
+    }
+}
+
+/// One `quota.tenants` entry.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TenantQuota {
+    name: String,
+    /// Keys of the tenant; without any, the ones `depth` attributes to `name`.
+    #[serde(default)]
+    prefixes: Vec<String>,
+    #[serde(default)]
+    max_assets: Option<u64>,
+    #[serde(default)]
+    max_bytes: Option<String>,
+}
+
+impl TenantQuota {
+    fn limits(&self) -> Limits {
+        Limits {
+            max_assets: self.max_assets,
+            max_bytes: self.max_bytes.clone(),
+        }
+    }
+}
+
+/// `quota.default`
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Limits {
+    #[serde(default)]
+    max_assets: Option<u64>,
+    /// e.g. `5GB`, binary units.
+    #[serde(default)]
+    max_bytes: Option<String>,
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        let quota = &self.quota;
+        if self.database.path.trim().is_empty() {
+            problems.push(("database.path".to_string(), "is required".to_string()));
+        }
+        if !(0.0..=100.0).contains(&quota.warn_percent) {
+            problems.push((
+                "quota.warn_percent".to_string(),
+                "must be between 0 and 100".to_string(),
+            ));
+        }
+        problems.extend(octa_config::check_url(
+            "quota.webhook_url",
+            quota.webhook_url.as_deref(),
+        ));
+        if let Some(Err(e)) = quota.default.max_bytes.as_deref().map(parse_bytes) {
+            problems.push(("quota.default.max_bytes".to_string(), e));
+        }
+        let mut names = HashSet::new();
+        for (i, tenant) in quota.tenants.iter().enumerate() {
+            if tenant.name.trim().is_empty() {
+                problems.push((
+                    format!("quota.tenants[{}].name", i),
+                    "is required".to_string(),
+                ));
+            } else if !names.insert(tenant.name.as_str()) {
+                problems.push((
+                    format!("quota.tenants[{}].name", i),
+                    format!("'{}' is listed twice", tenant.name),
+                ));
+            }
+            if let Some(Err(e)) = tenant.max_bytes.as_deref().map(parse_bytes) {
+                problems.push((format!("quota.tenants[{}].max_bytes", i), e));
+            }
+        }
+        problems
+    }
+}
+
+/// Like octa-gc: `--db` makes the config file optional.
+fn load_config(path: Option<&str>, db_path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    let found = octa_config::discover(path.map(Path::new)).filter(|p| p.exists());
+    let mut config: FileConfig = match (&found, db_path) {
+        (Some(found), _) => octa_config::read(found)?,
+        (None, Some(_)) => octa_config::from_env()?,
+        (None, None) => {
+            return Err(match path {
+                Some(path) => ConfigError::Read {
+                    path: path.into(),
+                    source: std::io::ErrorKind::NotFound.into(),
+                },
+                None => ConfigError::NotFound,
+            })
+        }
+    };
+    if let Some(db_path) = db_path {
+        config.database.path = db_path.to_string();
+    }
+    octa_config::check(found.as_deref().unwrap_or(Path::new("<defaults>")), config)
+}
+
+/// One line of the report.
+#[derive(Debug, Serialize)]
+struct Row {
+    /// `None` for keys of no tenant.
+    tenant: Option<String>,
+    assets: u64,
+    keys: u64,
+    bytes: u64,
+    max_assets: Option<u64>,
+    max_bytes: Option<u64>,
+    /// The larger share of the two limits, in percent.
+    used_percent: Option<f64>,
+    status: &'static str,
+}
+
+/// The octa-quota command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa quota` the arguments it was given.
+pub fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, Level::INFO, false, true);
+
+    let config = match load_config(args.config.as_deref(), args.db_path.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let db_path = config.database.path.as_str();
+    if !Path::new(db_path).exists() {
+        error!(tag = "FATAL", path = %db_path, "Database file not found");
+        return Kind::NoInput.exit_code();
+    }
+    let opts = OpenOptions {
+        busy_timeout: Duration::from_millis(args.busy_timeout),
+        immutable: false,
+    };
+    let quota = &config.quota;
+    let attribution = Attribution::new(
+        quota.tenants.iter().flat_map(|t| {
+            t.prefixes
+                .iter()
+                .map(move |prefix| (prefix.clone(), t.name.clone()))
+        }),
+        args.depth.unwrap_or(quota.depth),
+    );
+    let usage = match db::open_read_only(db_path, &opts)
+        .and_then(|conn| tenants::usage(&conn, &attribution))
+    {
+        Ok(usage) => usage,
+        Err(e) => {
+            error!(tag = "FATAL", path = %db_path, reason = %e, "Could not measure tenants");
+            return Kind::Io.exit_code();
+        }
+    };
+
+    let mut rows: Vec<Row> = usage
+        .iter()
+        .map(|(tenant, usage)| row(quota, tenant.as_deref(), usage))
+        .collect();
+    // Configured tenants that store nothing yet are reported too.
+    for tenant in &quota.tenants {
+        if !usage.contains_key(&Some(tenant.name.clone())) {
+            rows.push(row(quota, Some(&tenant.name), &Usage::default()));
+        }
+    }
+    rows.sort_by(|a, b| {
+        b.used_percent
+            .unwrap_or(-1.0)
+            .total_cmp(&a.used_percent.unwrap_or(-1.0))
+            .then(b.bytes.cmp(&a.bytes))
+            .then(a.tenant.cmp(&b.tenant))
+    });
+
+    if args.json {
+        match serde_json::to_string_pretty(&rows) {
+            Ok(text) => println!("{}", text),
+            Err(e) => {
+                error!(tag = "FATAL", reason = %e, "Could not write the report");
+                return Kind::Internal.exit_code();
+            }
+        }
+    } else {
+        print_table(&rows);
+    }
+
+    let over: Vec<&Row> = rows.iter().filter(|r| r.status == "over").collect();
+    let near: Vec<&Row> = rows.iter().filter(|r| r.status == "near").collect();
+    for row in over.iter().chain(&near) {
+        warn!(
+            tag = "WARN",
+            tenant = row.tenant.as_deref().unwrap_or("-"),
+            used_percent = format!("{:.1}", row.used_percent.unwrap_or(0.0)),
+            assets = row.assets,
+            bytes = %format_bytes(row.bytes as f64),
+            "{} quota",
+            if row.status == "over" { "Over" } else { "Near" }
+        );
+    }
+    let verdict = if !over.is_empty() {
+        Verdict::Critical
+    } else if !near.is_empty() {
+        Verdict::Warning
+    } else {
+        Verdict::Healthy
+    };
+    info!(
+        tag = if verdict == Verdict::Healthy {
+            "OK"
+        } else {
+            "WARN"
+        },
+        tenants = rows.len(),
+        over = over.len(),
+        near = near.len(),
+        "Quota report finished"
+    );
+
+    if let (Some(url), false) = (&quota.webhook_url, args.no_notify) {
+        if verdict != Verdict::Healthy {
+            let payload = json!({
+                "source": "octa-quota",
+                "host": notify::hostname(),
+                "database": db_path,
+                "status": verdict.label(),
+                "over": over,
+                "near": near,
+            });
+            notify::post(url, &payload, "webhook");
+        }
+    }
+    verdict.exit_code()
+}
+
+fn row(quota: &QuotaConfig, tenant: Option<&str>, usage: &Usage) -> Row {
+    // Keys of no tenant have no quota to be held to.
+    let limits = match tenant {
+        None => Limits::default(),
+        Some(name) => quota
+            .tenants
+            .iter()
+            .find(|t| t.name == name)
+            .map(TenantQuota::limits)
+            .unwrap_or_else(|| quota.default.clone()),
+    };
+    let max_bytes = limits
+        .max_bytes
+        .as_deref()
+        .and_then(|b| parse_bytes(b).ok());
+    let share = |used: u64, max: Option<u64>| {
+        max.map(|max| {
+            if max == 0 {
+                if used == 0 {
+                    0.0
+                } else {
+                    f64::INFINITY
+                }
+            } else {
+                used as f64 * 100.0 / max as f64
+            }
+        })
+    };
+    let used_percent = match (
+        share(usage.assets, limits.max_assets),
+        share(usage.bytes, max_bytes),
+    ) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    };
+    let status = match used_percent {
+        None => "-",
+        Some(p) if p > 100.0 => "over",
+        Some(p) if p >= quota.warn_percent => "near",
+        Some(_) => "ok",
+    };
+    Row {
+        tenant: tenant.map(str::to_string),
+        assets: usage.assets,
+        keys: usage.keys,
+        bytes: usage.bytes,
+        max_assets: limits.max_assets,
+        max_bytes,
+        used_percent,
+        status,
+    }
+}
+
+fn print_table(rows: &[Row]) {
+    let name = |row: &Row| row.tenant.clone().unwrap_or_else(|| "(none)".to_string());
+    let width = rows.iter().map(|r| name(r).len()).max().unwrap_or(0).max(6);
+    println!(
+        "{:<width$}  {:>8}  {:>8}  {:>10}  {:>8}  {:>10}  {:>7}  STATUS",
+        "TENANT", "ASSETS", "KEYS", "BYTES", "MAX", "MAX BYTES", "USED"
+    );
+    for row in rows {
+        let limit = |v: Option<String>| v.unwrap_or_else(|| "-".to_string());
+        println!(
+            "{:<width$}  {:>8}  {:>8}  {:>10}  {:>8}  {:>10}  {:>7}  {}",
+            name(row),
+            row.assets,
+            row.keys,
+            format_bytes(row.bytes as f64),
+            limit(row.max_assets.map(|m| m.to_string())),
+            limit(row.max_bytes.map(|m| format_bytes(m as f64))),
+            limit(row.used_percent.map(|p| format!("{:.1}%", p))),
+            row.status,
+        );
+    }
+}
@@ -0,0 +1,354 @@
+use clap::Parser;
+use octa_config::{ConfigError, RateLimitConfig, SecurityConfig, ServerConfig, Validate};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use probe::{Measured, Probe, Scope};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+use tracing::{error, info, warn, Level};
+
+mod probe;
+
+/*
+OCTA-RATECHECK: Rate-limit configuration verifier
+=============================================
+Mission: Measure the rate limit a running server actually enforces (burst,
+         refill rate, and whether it is kept per IP) by pushing requests
+         until 429s appear, and compare it with security.rate_limit.
+         Exit 0 when they agree, 1 on a mismatch.
+Safety:  Only GETs of one generated avatar, but hundreds to thousands of
+         them: point it at staging, or at production off-peak. The probe
+         claims its own client IPs, so real clients keep their buckets.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Measure the server's rate limit and compare it with security.rate_limit"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Server to probe (overrides ratecheck.base_url and base_url)
+    #[arg(long, env = "OCTA_RATECHECK_URL")]
+    url: Option<String>,
+
+    /// Largest relative difference still counted as a match, in percent (overrides ratecheck.tolerance)
+    #[arg(long)]
+    tolerance: Option<f64>,
+
+    /// Print JSON instead of columns
+    #[arg(long)]
+    json: bool,
+
+    /// Seconds before a request is abandoned
+    #[arg(long, default_value_t = 10)]
+    timeout: u64,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+}
+
+/// The parts of config.yaml octa-ratecheck reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    server: ServerConfig,
+    security: SecurityConfig,
+    base_url: Option<String>,
+    ratecheck: RatecheckConfig,
+}
+
+/// `ratecheck:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RatecheckConfig {
+    /// Defaults to the server's base_url.
+    base_url: Option<String>,
+    /// Requested over and over; a generated avatar needs no stored asset.
+    path: String,
+    /// Percent a measured rate or burst may differ from the declared one.
+    tolerance: f64,
+    /// Requests in flight while draining the bucket.
+    concurrency: usize,
+    /// Requests from a full bucket before concluding there is no limit.
+    max_requests: usize,
+    /// Highest requests per second offered while ramping.
+    max_rate: f64,
+}
+
+impl Default for RatecheckConfig {
+    fn default() -> Self {
+        Self {
+            base_url: None,
+            path: "/avatar/octa-ratecheck".to_string(),
+            tolerance: 20.0,
+            concurrency: 50,
+            max_requests: 2000,
+            max_rate: 1000.0,
+        }
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = self.server.validate();
+        let ratecheck = &self.ratecheck;
+        problems.extend(octa_config::check_url("base_url", self.base_url.as_deref()));
+        problems.extend(octa_config::check_url(
+            "ratecheck.base_url",
+            ratecheck.base_url.as_deref(),
+        ));
+        if let Err(e) = self.security.rate_limit.per_second() {
+            problems.push(("security.rate_limit.window".to_string(), e));
+        }
+        if !ratecheck.path.starts_with('/') {
+            problems.push((
+                "ratecheck.path".to_string(),
+                "must start with /".to_string(),
+            ));
+        }
+        if ratecheck.tolerance.is_nan() || ratecheck.tolerance < 0.0 {
+            problems.push((
+                "ratecheck.tolerance".to_string(),
+                "must not be negative".to_string(),
+            ));
+        }
+        if ratecheck.concurrency == 0 {
+            problems.push((
+                "ratecheck.concurrency".to_string(),
+                "must be at least 1".to_string(),
+            ));
+        }
+        if ratecheck.max_requests == 0 {
+            problems.push((
+                "ratecheck.max_requests".to_string(),
+                "must be at least 1".to_string(),
+            ));
+        }
+        if ratecheck.max_rate.is_nan() || ratecheck.max_rate < 1.0 {
+            problems.push((
+                "ratecheck.max_rate".to_string(),
+                "must be at least 1".to_string(),
+            ));
+        }
+        problems
+    }
+}
+
+/// Without a file, the environment and arguments alone configure it.
+fn load_config(path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    match octa_config::discover(path.map(Path::new)) {
+        Some(path) => octa_config::load(&path),
+        None => {
+            let config: FileConfig = octa_config::from_env()?;
+            octa_config::check(Path::new("<environment>"), config)
+        }
+    }
+}
+
+/// One line of the comparison.
+#[derive(Debug, Serialize)]
+struct Check {
+    setting: &'static str,
+    declared: String,
+    measured: String,
+    ok: bool,
+}
+
+/// The octa-ratecheck command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa ratecheck` the arguments it was given.
+#[tokio::main]
+pub async fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, Level::INFO, false, false);
+
+    let config = match load_config(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return octa_errors::Error::from(e).exit_code();
+        }
+    };
+    let ratecheck = &config.ratecheck;
+    let base_url = match &args.url {
+        Some(url) => url.trim_end_matches('/').to_string(),
+        None => octa_config::base_url(
+            ratecheck.base_url.as_deref().or(config.base_url.as_deref()),
+            &config.server,
+        ),
+    };
+    let client = match Client::builder()
+        .timeout(Duration::from_secs(args.timeout))
+        .pool_max_idle_per_host(ratecheck.concurrency)
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Could not create the HTTP client");
+            return Kind::Internal.exit_code();
+        }
+    };
+
+    let declared = &config.security.rate_limit;
+    info!(
+        tag = "→",
+        url = %base_url,
+        enabled = declared.enabled,
+        requests = declared.requests,
+        window = %declared.window,
+        burst = declared.burst(),
+        "Probing the rate limit"
+    );
+    let probe = Probe {
+        client,
+        base_url: base_url.clone(),
+        path: ratecheck.path.clone(),
+        concurrency: ratecheck.concurrency,
+        max_requests: ratecheck.max_requests,
+        max_rate: ratecheck.max_rate,
+        step: Duration::from_secs(2),
+        window: Duration::from_secs(5),
+    };
+    let measured = match probe.run().await {
+        Ok(measured) => measured,
+        Err(e) => {
+            error!(tag = "FATAL", url = %base_url, reason = %e, "Server unreachable");
+            return Kind::Unavailable.exit_code();
+        }
+    };
+
+    let tolerance = args.tolerance.unwrap_or(ratecheck.tolerance);
+    let checks = compare(declared, &measured, tolerance);
+    if args.json {
+        match serde_json::to_string_pretty(&checks) {
+            Ok(text) => println!("{}", text),
+            Err(e) => {
+                error!(tag = "FATAL", reason = %e, "Could not write the report");
+                return Kind::Internal.exit_code();
+            }
+        }
+    } else {
+        print_table(&checks);
+    }
+
+    match measured.scope {
+        Some(Scope::Ip) => warn!(
+            tag = "WARN",
+            "The client IP is taken from X-Forwarded-For as the client sends it: without a proxy that overwrites the header, a client can pick a fresh bucket for every request"
+        ),
+        Some(Scope::Shared) => warn!(
+            tag = "WARN",
+            "Another client IP was refused too: either one bucket is shared by everyone, or a proxy in front replaces X-Forwarded-For and the per-IP limit cannot be told apart from here"
+        ),
+        _ => {}
+    }
+    let mismatches = checks.iter().filter(|c| !c.ok).count();
+    for check in checks.iter().filter(|c| !c.ok) {
+        warn!(
+            tag = "MISMATCH",
+            setting = check.setting,
+            declared = %check.declared,
+            measured = %check.measured,
+            "Rate limit differs from the config"
+        );
+    }
+    info!(
+        tag = if mismatches == 0 { "OK" } else { "WARN" },
+        sent = measured.sent,
+        mismatches,
+        "Rate limit check finished"
+    );
+    if mismatches == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Declared against measured. Rate and burst match within `tolerance`
+/// percent, and the burst within two requests whatever its size: a token
+/// refilled mid-drain is not a misconfiguration.
+fn compare(declared: &RateLimitConfig, measured: &Measured, tolerance: f64) -> Vec<Check> {
+    let limited = measured.burst.is_some();
+    let mut checks = vec![Check {
+        setting: "enabled",
+        declared: declared.enabled.to_string(),
+        measured: match limited {
+            true => "true".to_string(),
+            false => format!("false (no 429 in {} requests)", measured.sent),
+        },
+        ok: declared.enabled == limited,
+    }];
+    if !declared.enabled || !limited {
+        return checks;
+    }
+
+    let close = |declared: f64, measured: f64, slack: f64| {
+        (measured - declared).abs() <= (declared * tolerance / 100.0).max(slack)
+    };
+    let per_second = declared.per_second().unwrap_or(0.0);
+    checks.push(match measured.per_second {
+        Some(measured) => Check {
+            setting: "rate",
+            declared: format!("{:.2}/s", per_second),
+            measured: format!("{:.2}/s", measured),
+            ok: close(per_second, measured, 0.0),
+        },
+        None => Check {
+            setting: "rate",
+            declared: format!("{:.2}/s", per_second),
+            measured: "not refused when ramping".to_string(),
+            ok: false,
+        },
+    });
+    let burst = f64::from(declared.burst());
+    let measured_burst = measured.burst.unwrap_or(0.0);
+    checks.push(Check {
+        setting: "burst",
+        declared: format!("{}", declared.burst()),
+        measured: format!("{:.0}", measured_burst),
+        ok: close(burst, measured_burst, 2.0),
+    });
+    checks.push(Check {
+        setting: "scope",
+        declared: "ip".to_string(),
+        measured: measured
+            .scope
+            .map_or("unknown", Scope::as_str)
+            .to_string(),
+        // Behind a proxy, per IP looks shared; only a per-key limit is
+        // surely not what the Go server does.
+        ok: measured.scope != Some(Scope::Key),
+    });
+    checks
+}
+
+fn print_table(checks: &[Check]) {
+    let width = checks
+        .iter()
+        .map(|c| c.measured.len())
+        .max()
+        .unwrap_or(0)
+        .max(8);
+    println!(
+        "{:<8}  {:>10}  {:>width$}  STATUS",
+        "SETTING", "DECLARED", "MEASURED"
+    );
+    for check in checks {
+        println!(
+            "{:<8}  {:>10}  {:>width$}  {}",
+            check.setting,
+            check.declared,
+            check.measured,
+            if check.ok { "ok" } else { "MISMATCH" },
+        );
+    }
+}
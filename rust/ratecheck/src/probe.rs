@@ -0,0 +1,243 @@
+//! Measuring a token bucket from the outside: drain it at once to learn its
+//! size, then offer ever more requests per second until 429s appear and
+//! count what an empty bucket still lets through, which is its refill rate.
+//!
+//! Every request claims a client IP in `X-Forwarded-For` (and
+//! `X-Real-IP`), from the TEST-NET-2 range. The Go server takes the client
+//! IP from these, so each run starts on fresh buckets, and a second IP
+//! shows whether the limit is kept per IP.
+
+use futures::future::join_all;
+use reqwest::Client;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::time::MissedTickBehavior;
+use tracing::info;
+
+/// What the limit applies to, judged by who is still served once one
+/// client has emptied its bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Another IP is served, another key from the same IP is not.
+    Ip,
+    /// Another key from the same IP is served.
+    Key,
+    /// Neither is: one bucket for everyone, or a proxy in front that
+    /// replaces X-Forwarded-For, so every request comes from one IP.
+    Shared,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Ip => "ip",
+            Scope::Key => "key",
+            Scope::Shared => "shared",
+        }
+    }
+}
+
+/// The limiter as observed.
+#[derive(Debug, Default)]
+pub struct Measured {
+    /// Requests accepted from a full bucket; `None` when none was refused.
+    pub burst: Option<f64>,
+    /// Requests accepted per second from an empty bucket.
+    pub per_second: Option<f64>,
+    pub scope: Option<Scope>,
+    /// Requests sent in all.
+    pub sent: usize,
+}
+
+pub struct Probe {
+    pub client: Client,
+    pub base_url: String,
+    /// Path requested; `<path>-other` is the second key of the scope check.
+    pub path: String,
+    /// Requests in flight while draining the bucket.
+    pub concurrency: usize,
+    /// Requests sent from a full bucket before concluding there is no limit.
+    pub max_requests: usize,
+    /// Highest rate offered while ramping.
+    pub max_rate: f64,
+    /// Length of each ramp step.
+    pub step: Duration,
+    /// Length of the measurement of the refill rate.
+    pub window: Duration,
+}
+
+/// Outcome of a run of requests: `(accepted, refused with 429)`.
+type Tally = (usize, usize);
+
+impl Probe {
+    pub async fn run(&self) -> Result<Measured, reqwest::Error> {
+        // A different pair of IPs on every run, so a bucket left half empty
+        // by the last one does not skew this one.
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        let ip = format!("198.51.100.{}", seed % 250 + 1);
+        let other_ip = format!("198.51.100.{}", (seed + 1) % 250 + 1);
+        let mut measured = Measured::default();
+
+        let started = Instant::now();
+        let (accepted, refused, sent) = self.drain(&ip, self.max_requests).await?;
+        let elapsed = started.elapsed();
+        measured.sent += sent;
+        if refused == 0 {
+            info!(tag = "PROBE", sent, "No request refused from a full bucket");
+            return Ok(measured);
+        }
+        info!(
+            tag = "PROBE",
+            accepted,
+            ms = elapsed.as_millis() as u64,
+            "Bucket drained"
+        );
+
+        let (per_second, sent) = self.refill_rate(&ip).await?;
+        measured.sent += sent;
+        measured.per_second = per_second;
+        // What was refilled while draining was not part of the burst.
+        let refilled = per_second.unwrap_or(0.0) * elapsed.as_secs_f64();
+        measured.burst = Some((accepted as f64 - refilled).max(0.0));
+
+        let (scope, sent) = self.scope(&ip, &other_ip).await?;
+        measured.sent += sent;
+        measured.scope = scope;
+        Ok(measured)
+    }
+
+    async fn status(&self, ip: &str, path: &str) -> Result<u16, reqwest::Error> {
+        let response = self
+            .client
+            .get(format!("{}{}", self.base_url, path))
+            .header("X-Forwarded-For", ip)
+            .header("X-Real-IP", ip)
+            .send()
+            .await?;
+        Ok(response.status().as_u16())
+    }
+
+    /// Waves of concurrent requests until one is refused, or `limit` are
+    /// sent: `(accepted, refused, sent)`.
+    async fn drain(&self, ip: &str, limit: usize) -> Result<(usize, usize, usize), reqwest::Error> {
+        let (mut accepted, mut sent) = (0, 0);
+        while sent < limit {
+            let wave = self.concurrency.min(limit - sent);
+            let statuses = join_all((0..wave).map(|_| self.status(ip, &self.path))).await;
+            sent += wave;
+            let mut refused = 0;
+            for status in statuses {
+                match status? {
+                    429 => refused += 1,
+                    _ => accepted += 1,
+                }
+            }
+            if refused > 0 {
+                return Ok((accepted, refused, sent));
+            }
+        }
+        Ok((accepted, 0, sent))
+    }
+
+    /// Requests paced at `rate` per second for `length`.
+    async fn offer(&self, ip: &str, rate: f64, length: Duration) -> Result<Tally, reqwest::Error> {
+        let mut ticks = tokio::time::interval(Duration::from_secs_f64(1.0 / rate));
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Burst);
+        let end = Instant::now() + length;
+        let mut requests = Vec::new();
+        while Instant::now() < end {
+            ticks.tick().await;
+            let (client, url, ip) = (
+                self.client.clone(),
+                format!("{}{}", self.base_url, self.path),
+                ip.to_string(),
+            );
+            requests.push(tokio::spawn(async move {
+                let response = client
+                    .get(url)
+                    .header("X-Forwarded-For", &ip)
+                    .header("X-Real-IP", &ip)
+                    .send()
+                    .await?;
+                Ok::<u16, reqwest::Error>(response.status().as_u16())
+            }));
+        }
+        let mut tally = (0, 0);
+        for request in requests {
+            // A panicked task sent nothing worth counting.
+            match request.await.ok().transpose()? {
+                Some(429) => tally.1 += 1,
+                Some(_) => tally.0 += 1,
+                None => {}
+            }
+        }
+        Ok(tally)
+    }
+
+    /// Doubles the offered rate from 1/s until requests are refused, then
+    /// offers twice that to the emptied bucket and counts what it accepts.
+    /// `None` when nothing is refused up to `max_rate`.
+    async fn refill_rate(&self, ip: &str) -> Result<(Option<f64>, usize), reqwest::Error> {
+        let mut offered: f64 = 1.0_f64.min(self.max_rate);
+        let mut sent = 0;
+        loop {
+            let (accepted, refused) = self.offer(ip, offered, self.step).await?;
+            sent += accepted + refused;
+            info!(tag = "PROBE", offered, accepted, refused, "Ramp step");
+            if refused > 0 {
+                break;
+            }
+            if offered >= self.max_rate {
+                return Ok((None, sent));
+            }
+            offered = (offered * 2.0).min(self.max_rate);
+        }
+        let offered = (offered * 2.0).min(self.max_rate);
+        let (accepted, refused) = self.offer(ip, offered, self.window).await?;
+        sent += accepted + refused;
+        let per_second = accepted as f64 / self.window.as_secs_f64();
+        info!(
+            tag = "PROBE",
+            offered,
+            accepted,
+            refused,
+            per_second = format!("{:.2}", per_second),
+            "Refill rate measured"
+        );
+        Ok((Some(per_second), sent))
+    }
+
+    /// Empties the bucket of `ip` and at once asks for another key from
+    /// `ip`, the same key from `other_ip`, and the same key from `ip` as
+    /// the control. Trials where the control slipped through on a token
+    /// refilled meanwhile do not count; `None` when too few did.
+    async fn scope(&self, ip: &str, other_ip: &str) -> Result<(Option<Scope>, usize), reqwest::Error> {
+        const TRIALS: usize = 3;
+        let other_path = format!("{}-other", self.path);
+        let (mut valid, mut key_served, mut ip_served, mut sent) = (0, 0, 0, 0);
+        for _ in 0..TRIALS {
+            let (_, _, drained) = self.drain(ip, self.max_requests).await?;
+            let (control, other_key, other_ip) = tokio::join!(
+                self.status(ip, &self.path),
+                self.status(ip, &other_path),
+                self.status(other_ip, &self.path),
+            );
+            sent += drained + 3;
+            if control? != 429 {
+                continue;
+            }
+            valid += 1;
+            key_served += usize::from(other_key? != 429);
+            ip_served += usize::from(other_ip? != 429);
+        }
+        let scope = match valid {
+            0 | 1 => None,
+            _ if key_served == valid => Some(Scope::Key),
+            _ if ip_served == valid => Some(Scope::Ip),
+            _ => Some(Scope::Shared),
+        };
+        Ok((scope, sent))
+    }
+}
@@ -0,0 +1,181 @@
+use crate::rules::Rule;
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, Result};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// One key getting a new name.
+#[derive(Debug)]
+pub struct Move {
+    pub old: String,
+    pub new: String,
+    pub image_id: String,
+    /// Index into the rules, for the per-rule summary.
+    pub rule: usize,
+    /// `created_at` of the old row, carried over to the new one.
+    created_at: Value,
+    /// The new key already points at the same asset (an earlier run with
+    /// old keys kept), so only the old row is left to remove.
+    pub exists: bool,
+}
+
+/// A key that matched a rule but cannot be moved.
+#[derive(Debug)]
+pub struct Blocked {
+    pub old: String,
+    pub new: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub moves: Vec<Move>,
+    pub blocked: Vec<Blocked>,
+    /// Keys no rule matches; they keep their name.
+    pub untouched: usize,
+}
+
+/// Matches every key against the rules, first match wins, and checks the
+/// result: a new key may not be taken by another asset unless that key is
+/// itself moving away, and two old keys may not get the same new one.
+pub fn plan(conn: &Connection, rules: &[Rule], keep_old: bool) -> Result<Plan> {
+    let mut existing: HashMap<String, (String, Value)> = HashMap::new();
+    let mut keys = Vec::new();
+    {
+        let mut stmt =
+            conn.prepare("SELECT key, image_id, created_at FROM key_mappings ORDER BY key")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                row.get::<_, Value>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (key, image_id, created_at) = row?;
+            keys.push(key.clone());
+            existing.insert(key, (image_id, created_at));
+        }
+    }
+
+    let mut plan = Plan::default();
+    let mut candidates = Vec::new();
+    for old in keys {
+        let matched = rules
+            .iter()
+            .enumerate()
+            .find_map(|(i, rule)| rule.apply(&old).map(|new| (i, new)));
+        match matched {
+            None => plan.untouched += 1,
+            Some((_, Ok(new))) if new == old => plan.untouched += 1,
+            Some((rule, Ok(new))) => candidates.push((old, new, rule)),
+            Some((_, Err(reason))) => plan.blocked.push(Blocked {
+                old,
+                new: None,
+                reason: reason.to_string(),
+            }),
+        }
+    }
+
+    let mut targets: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (old, new, _) in &candidates {
+        targets.entry(new.as_str()).or_default().push(old.as_str());
+    }
+
+    // A blocked key stays where it is, which can block the key that was to
+    // take its name; check again until nothing more is blocked.
+    let moving: HashSet<&str> = candidates.iter().map(|(old, _, _)| old.as_str()).collect();
+    let mut stuck: HashSet<String> = HashSet::new();
+    let mut blocked = Vec::new();
+    let mut moves = Vec::new();
+    loop {
+        blocked.clear();
+        moves.clear();
+        for (old, new, rule) in &candidates {
+            let (image_id, created_at) = existing[old].clone();
+            let sources = &targets[new.as_str()];
+            let mut exists = false;
+            let reason = if sources.len() > 1 {
+                Some(format!(
+                    "{} keys get this name: {}",
+                    sources.len(),
+                    sources.join(", ")
+                ))
+            } else {
+                match existing.get(new) {
+                    Some((taken_by, _)) if *taken_by == image_id => {
+                        exists = true;
+                        None
+                    }
+                    Some(_)
+                        if !keep_old && moving.contains(new.as_str()) && !stuck.contains(new) =>
+                    {
+                        None
+                    }
+                    Some((taken_by, _)) => Some(format!("already the key of asset {}", taken_by)),
+                    None => None,
+                }
+            };
+            match reason {
+                Some(reason) => blocked.push(Blocked {
+                    old: old.clone(),
+                    new: Some(new.clone()),
+                    reason,
+                }),
+                None => moves.push(Move {
+                    old: old.clone(),
+                    new: new.clone(),
+                    image_id,
+                    rule: *rule,
+                    created_at,
+                    exists,
+                }),
+            }
+        }
+        let before = stuck.len();
+        stuck.extend(blocked.iter().map(|b| b.old.clone()));
+        if stuck.len() == before {
+            break;
+        }
+    }
+    plan.blocked.extend(blocked);
+    plan.blocked.sort_by(|a, b| a.old.cmp(&b.old));
+    plan.moves = moves;
+    // Nothing left to do for a key already moved and kept.
+    if keep_old {
+        plan.untouched += plan.moves.iter().filter(|m| m.exists).count();
+        plan.moves.retain(|m| !m.exists);
+    }
+    Ok(plan)
+}
+
+/// Runs the moves: every old row goes first (unless kept), then the new
+/// rows are added, so a key can take a name another key is leaving. The
+/// caller owns the transaction, which makes it all or nothing.
+pub fn execute(conn: &Connection, moves: &[Move], keep_old: bool) -> Result<()> {
+    if !keep_old {
+        let mut delete = conn.prepare("DELETE FROM key_mappings WHERE key = ?1")?;
+        for m in moves {
+            delete.execute([&m.old])?;
+        }
+    }
+    let mut insert =
+        conn.prepare("INSERT INTO key_mappings (key, image_id, created_at) VALUES (?1, ?2, ?3)")?;
+    for m in moves.iter().filter(|m| !m.exists) {
+        insert.execute(params![m.new, m.image_id, m.created_at])?;
+    }
+    Ok(())
+}
+
+/// Writes `old_key,new_key,asset_id`, one line per move, for clients to
+/// rewrite the keys they hold. Keys never contain commas.
+pub fn write_mapping(path: &Path, moves: &[Move]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "old_key,new_key,asset_id")?;
+    for m in moves {
+        writeln!(out, "{},{},{}", m.old, m.new, m.image_id)?;
+    }
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()
+}
@@ -0,0 +1,352 @@
+use clap::Parser;
+use octa_cdn::{CdnConfig, Purger};
+use octa_config::{ConfigError, DatabaseConfig, Validate};
+use octa_errors::{Error, Kind};
+use octa_ledger::{Ledger, LedgerConfig};
+use octa_logging::LogFormat;
+use octa_warden_core::db::{self, OpenOptions};
+use rusqlite::TransactionBehavior;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+use tracing::{error, info, warn, Level};
+
+mod apply;
+mod rules;
+
+use rules::{Rule, RuleConfig};
+
+/*
+OCTA-REKEY: Bulk key renames for the Octa database
+=============================================
+Mission: Move keys to a new scheme by rule (users/{id} ->
+         tenants/{org}/users/{id}) and hand clients a mapping file of
+         every old and new key.
+Safety:  Dry-run unless --execute. Every rename happens in one
+         transaction, and nothing changes while any matched key has no
+         legal, free new name.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Rename keys in bulk by rule, with a mapping file for clients"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// SQLite database to rename keys in (overrides database.path; the config file becomes optional)
+    #[arg(long = "db", env = "OCTA_DB_PATH", value_name = "PATH")]
+    db_path: Option<String>,
+
+    /// Milliseconds to wait on a locked database before giving up (SQLITE_BUSY)
+    #[arg(long, default_value_t = 5000)]
+    busy_timeout: u64,
+
+    /// Actually rename (default: list what would be renamed)
+    #[arg(long)]
+    execute: bool,
+
+    /// One rule instead of rekey.rules: keys to rename, like users/{id}
+    #[arg(long, requires = "to", value_name = "TEMPLATE")]
+    from: Option<String>,
+
+    /// Their new name, like tenants/{org}/users/{id}
+    #[arg(long, requires = "from", value_name = "TEMPLATE")]
+    to: Option<String>,
+
+    /// CSV of values --to needs that --from does not capture (header: id,org)
+    #[arg(long, requires = "from", value_name = "PATH")]
+    lookup: Option<String>,
+
+    /// Keep the old keys as aliases of the same asset, so old URLs keep working (overrides rekey.keep_old)
+    #[arg(long)]
+    keep_old: bool,
+
+    /// Write old_key,new_key,asset_id for every rename here (overrides rekey.mapping)
+    #[arg(long, value_name = "PATH")]
+    mapping: Option<String>,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+}
+
+/// The parts of config.yaml octa-rekey reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    database: DatabaseConfig,
+    rekey: RekeyConfig,
+    cdn: CdnConfig,
+    ledger: LedgerConfig,
+}
+
+/// `rekey:`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RekeyConfig {
+    /// Tried in order; a key is renamed by the first that matches.
+    rules: Vec<RuleConfig>,
+    /// Leave the old keys in place as aliases.
+    keep_old: bool,
+    /// Where to write the mapping file; none is written when unset.
+    mapping: Option<String>,
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        if self.database.path.trim().is_empty() {
+            problems.push(("database.path".to_string(), "is required".to_string()));
+        }
+        for (i, rule) in self.rekey.rules.iter().enumerate() {
+            if rule.from.trim().is_empty() {
+                problems.push((
+                    format!("rekey.rules[{}].from", i),
+                    "is required".to_string(),
+                ));
+            }
+            if rule.to.trim().is_empty() {
+                problems.push((format!("rekey.rules[{}].to", i), "is required".to_string()));
+            }
+        }
+        problems.extend(self.cdn.validate());
+        problems.extend(self.ledger.validate());
+        problems
+    }
+}
+
+/// Like octa-gc: `--db` makes the config file optional.
+fn load_config(path: Option<&str>, db_path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    let found = octa_config::discover(path.map(Path::new)).filter(|p| p.exists());
+    let mut config: FileConfig = match (&found, db_path) {
+        (Some(found), _) => octa_config::read(found)?,
+        (None, Some(_)) => octa_config::from_env()?,
+        (None, None) => {
+            return Err(match path {
+                Some(path) => ConfigError::Read {
+                    path: path.into(),
+                    source: std::io::ErrorKind::NotFound.into(),
+                },
+                None => ConfigError::NotFound,
+            })
+        }
+    };
+    if let Some(db_path) = db_path {
+        config.database.path = db_path.to_string();
+    }
+    octa_config::check(found.as_deref().unwrap_or(Path::new("<defaults>")), config)
+}
+
+/// The octa-rekey command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa rekey` the arguments it was given.
+pub fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, Level::INFO, false, false);
+
+    let config = match load_config(args.config.as_deref(), args.db_path.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    if !Path::new(&config.database.path).exists() {
+        error!(tag = "FATAL", path = %config.database.path, "Database file not found");
+        return Kind::NoInput.exit_code();
+    }
+    match run(&args, &config) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!(tag = "FATAL", path = %config.database.path, reason = %e, "Rename failed");
+            e.exit_code()
+        }
+    }
+}
+
+/// `--from`/`--to` when given, else `rekey.rules`.
+fn load_rules(args: &Args, config: &RekeyConfig) -> Result<Vec<Rule>, Error> {
+    let from_args = args.from.is_some() && args.to.is_some();
+    let configs = match (&args.from, &args.to) {
+        (Some(from), Some(to)) => vec![RuleConfig {
+            from: from.clone(),
+            to: to.clone(),
+            lookup: args.lookup.clone(),
+        }],
+        _ => config.rules.clone(),
+    };
+    if configs.is_empty() {
+        return Err(Error::new(
+            Kind::Config,
+            "no rules: set rekey.rules or pass --from and --to",
+        ));
+    }
+    configs
+        .iter()
+        .enumerate()
+        .map(|(i, cfg)| {
+            Rule::load(cfg).map_err(|e| {
+                let field = match from_args {
+                    true => "--from/--to".to_string(),
+                    false => format!("rekey.rules[{}]", i),
+                };
+                Error::new(Kind::Config, format!("{}: {}", field, e))
+            })
+        })
+        .collect()
+}
+
+fn run(args: &Args, config: &FileConfig) -> Result<(), Error> {
+    let rules = load_rules(args, &config.rekey)?;
+    let keep_old = args.keep_old || config.rekey.keep_old;
+    let mapping = args.mapping.as_deref().or(config.rekey.mapping.as_deref());
+    for rule in &rules {
+        info!(tag = "→", rule = %rule.describe(), "Rule");
+    }
+
+    // Opened before the database, so an unwritable ledger stops the run
+    // before anything is renamed.
+    let ledger = match args.execute {
+        true => Ledger::open(&config.ledger, "octa-rekey")
+            .map_err(|e| Error::new(Kind::Io, format!("ledger: {}", e)))?,
+        false => None,
+    };
+
+    let opts = OpenOptions {
+        busy_timeout: Duration::from_millis(args.busy_timeout),
+        immutable: false,
+    };
+    let db_path = config.database.path.as_str();
+    let mut conn = if args.execute {
+        db::open_read_write(db_path, &opts)
+    } else {
+        db::open_read_only(db_path, &opts)
+    }?;
+    // Planned inside the write transaction, so no upload can take a new
+    // name between the check and the rename.
+    let tx = conn.transaction_with_behavior(if args.execute {
+        TransactionBehavior::Immediate
+    } else {
+        TransactionBehavior::Deferred
+    })?;
+    let plan = apply::plan(&tx, &rules, keep_old)?;
+
+    let verb = if args.execute {
+        "Renaming"
+    } else {
+        "Would rename"
+    };
+    let mut per_rule: BTreeMap<usize, usize> = BTreeMap::new();
+    for m in &plan.moves {
+        info!(tag = "REKEY", old = %m.old, new = %m.new, id = %m.image_id, "{}", verb);
+        *per_rule.entry(m.rule).or_default() += 1;
+    }
+    for b in &plan.blocked {
+        warn!(tag = "BLOCKED", old = %b.old, new = b.new.as_deref(), reason = %b.reason, "Cannot rename");
+    }
+    for (i, rule) in rules.iter().enumerate() {
+        let keys = per_rule.get(&i).copied().unwrap_or(0);
+        info!(tag = "SUMMARY", rule = %rule.describe(), keys, "Renames");
+    }
+    if !plan.blocked.is_empty() {
+        return Err(Error::new(
+            Kind::Data,
+            format!(
+                "{} key(s) cannot be renamed; fix the rules or lookups (nothing was renamed)",
+                plan.blocked.len()
+            ),
+        ));
+    }
+
+    if plan.moves.is_empty() {
+        info!(tag = "OK", untouched = plan.untouched, "Nothing to rename");
+        return Ok(());
+    }
+
+    // Written before the commit: the database never changes without one.
+    if let Some(mapping) = mapping {
+        apply::write_mapping(Path::new(mapping), &plan.moves)
+            .map_err(|e| Error::new(Kind::Io, format!("{}: {}", mapping, e)))?;
+        info!(
+            tag = "OK",
+            path = mapping,
+            keys = plan.moves.len(),
+            "Mapping file written"
+        );
+    }
+
+    if !args.execute {
+        info!(
+            tag = "OK",
+            keys = plan.moves.len(),
+            untouched = plan.untouched,
+            "Dry run: nothing renamed. Re-run with --execute to rename these keys"
+        );
+        return Ok(());
+    }
+
+    apply::execute(&tx, &plan.moves, keep_old)?;
+    tx.commit()?;
+    info!(
+        tag = "OK",
+        keys = plan.moves.len(),
+        untouched = plan.untouched,
+        kept = keep_old,
+        "Keys renamed"
+    );
+    let old: Vec<String> = plan.moves.iter().map(|m| m.old.clone()).collect();
+    if let Some(ledger) = &ledger {
+        let rules = rules.iter().map(Rule::describe).collect::<Vec<_>>();
+        ledger
+            .record(
+                "rekey",
+                &old,
+                &[
+                    ("rules", rules.join("; ")),
+                    ("keys", plan.moves.len().to_string()),
+                    ("keep_old", keep_old.to_string()),
+                    ("mapping", mapping.unwrap_or_default().to_string()),
+                ],
+            )
+            .map_err(|e| {
+                Error::new(
+                    Kind::Io,
+                    format!("rekey done, but not recorded in the ledger: {}", e),
+                )
+            })?;
+    }
+    if !keep_old {
+        purge(&config.cdn, &old);
+    }
+    Ok(())
+}
+
+/// Old keys now answer 404, but the edge keeps serving what it cached.
+fn purge(cdn: &CdnConfig, keys: &[String]) {
+    let purger = match Purger::new(cdn, Duration::from_secs(30)) {
+        Ok(Some(purger)) => purger,
+        Ok(None) => return,
+        Err(e) => {
+            warn!(tag = "WARN", reason = %e, keys = keys.len(), "Edge cache not purged; run octa-ctl purge for the old keys");
+            return;
+        }
+    };
+    match purger.purge_keys(keys) {
+        Ok(purged) => info!(
+            tag = "PURGE",
+            provider = purger.provider().as_str(),
+            keys = keys.len(),
+            urls = purged.urls,
+            "Edge cache purged"
+        ),
+        Err(e) => {
+            warn!(tag = "WARN", reason = %e, keys = keys.len(), "Edge cache not purged; run octa-ctl purge for the old keys")
+        }
+    }
+}
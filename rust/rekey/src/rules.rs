@@ -0,0 +1,278 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+
+/// One `rekey.rules` entry, as written in config.yaml.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    /// Keys to rename: `users/{id}`. `{name}` matches one segment,
+    /// `{*name}` (last only) the rest of the key.
+    pub from: String,
+    /// Their new name: `tenants/{org}/users/{id}`.
+    pub to: String,
+    /// CSV of values `to` needs that `from` does not capture. The header
+    /// names the columns; the first is a `from` capture to look up by
+    /// (`id,org`).
+    #[serde(default)]
+    pub lookup: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    One(String),
+    Rest(String),
+}
+
+#[derive(Debug)]
+struct Template(Vec<Segment>);
+
+impl Template {
+    fn parse(raw: &str) -> Result<Self, String> {
+        let raw = raw.trim().trim_matches('/');
+        if raw.is_empty() {
+            return Err("is empty".to_string());
+        }
+        let parts: Vec<&str> = raw.split('/').collect();
+        let mut segments = Vec::with_capacity(parts.len());
+        for (i, part) in parts.iter().enumerate() {
+            let segment = match part.strip_prefix('{').and_then(|p| p.strip_suffix('}')) {
+                Some(name) => match name.strip_prefix('*') {
+                    Some(name) if i + 1 < parts.len() => {
+                        return Err(format!("{{*{}}} must be the last segment", name))
+                    }
+                    Some(name) => Segment::Rest(variable(name)?),
+                    None => Segment::One(variable(name)?),
+                },
+                None if part.contains(['{', '}']) => {
+                    return Err(format!(
+                        "'{}': a variable must be a whole segment, like {{id}}",
+                        part
+                    ))
+                }
+                // Keys are stored lowercase.
+                None => Segment::Literal(part.to_lowercase()),
+            };
+            segments.push(segment);
+        }
+        Ok(Template(segments))
+    }
+
+    fn variables(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().filter_map(|s| match s {
+            Segment::Literal(_) => None,
+            Segment::One(name) | Segment::Rest(name) => Some(name.as_str()),
+        })
+    }
+
+    /// The captures when `key` matches, by variable name.
+    fn captures<'k>(&self, key: &'k str) -> Option<HashMap<&str, &'k str>> {
+        let mut captures = HashMap::new();
+        let mut rest = key;
+        for (i, segment) in self.0.iter().enumerate() {
+            let last = i + 1 == self.0.len();
+            if let Segment::Rest(name) = segment {
+                if rest.is_empty() {
+                    return None;
+                }
+                captures.insert(name.as_str(), rest);
+                return Some(captures);
+            }
+            let (part, tail) = match rest.split_once('/') {
+                Some((part, tail)) if !last => (part, tail),
+                None if last => (rest, ""),
+                _ => return None,
+            };
+            match segment {
+                Segment::Literal(literal) if literal != part => return None,
+                Segment::One(name) => {
+                    captures.insert(name.as_str(), part);
+                }
+                _ => {}
+            }
+            rest = tail;
+        }
+        Some(captures)
+    }
+
+    fn render(&self, values: &HashMap<&str, &str>) -> String {
+        self.0
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(literal) => literal.as_str(),
+                Segment::One(name) | Segment::Rest(name) => values[name.as_str()],
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("/")?;
+            }
+            match segment {
+                Segment::Literal(literal) => f.write_str(literal)?,
+                Segment::One(name) => write!(f, "{{{}}}", name)?,
+                Segment::Rest(name) => write!(f, "{{*{}}}", name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn variable(name: &str) -> Result<String, String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!(
+            "'{{{}}}' is not a variable name (a-z, 0-9, _)",
+            name
+        ));
+    }
+    Ok(name.to_string())
+}
+
+/// Rows of a lookup CSV, by the value of its first column.
+#[derive(Debug)]
+struct Lookup {
+    file: String,
+    /// Column names; the first is the `from` capture looked up.
+    columns: Vec<String>,
+    rows: HashMap<String, Vec<String>>,
+}
+
+impl Lookup {
+    fn load(file: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?;
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+        let columns: Vec<String> = match lines.next() {
+            Some((_, header)) => header.split(',').map(|c| c.trim().to_string()).collect(),
+            None => return Err(format!("{}: no header line", file)),
+        };
+        if columns.len() < 2 {
+            return Err(format!(
+                "{}: the header needs a key column and at least one value column",
+                file
+            ));
+        }
+        let mut rows = HashMap::new();
+        for (n, line) in lines {
+            let fields: Vec<String> = line.split(',').map(|f| f.trim().to_string()).collect();
+            if fields.len() != columns.len() {
+                return Err(format!(
+                    "{}:{}: {} fields, the header has {}",
+                    file,
+                    n + 1,
+                    fields.len(),
+                    columns.len()
+                ));
+            }
+            let key = octa_key::normalize(&fields[0]);
+            if rows.contains_key(&key) {
+                return Err(format!("{}:{}: '{}' is listed twice", file, n + 1, key));
+            }
+            rows.insert(key, fields);
+        }
+        Ok(Lookup {
+            file: file.to_string(),
+            columns,
+            rows,
+        })
+    }
+}
+
+/// A parsed rule, lookup loaded.
+#[derive(Debug)]
+pub struct Rule {
+    from: Template,
+    to: Template,
+    lookup: Option<Lookup>,
+}
+
+/// Why a key matched a rule but has no new name.
+#[derive(Debug)]
+pub enum Unresolved {
+    /// The lookup has no row for this capture.
+    NotInLookup { file: String, value: String },
+    /// The new name is not a legal key.
+    Invalid(String),
+}
+
+impl fmt::Display for Unresolved {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unresolved::NotInLookup { file, value } => write!(f, "'{}' is not in {}", value, file),
+            Unresolved::Invalid(reason) => write!(f, "new key is invalid: {}", reason),
+        }
+    }
+}
+
+impl Rule {
+    /// Parses the templates and loads the lookup. Every variable of `to`
+    /// must come from `from` or a lookup column.
+    pub fn load(cfg: &RuleConfig) -> Result<Self, String> {
+        let from = Template::parse(&cfg.from).map_err(|e| format!("from: {}", e))?;
+        let to = Template::parse(&cfg.to).map_err(|e| format!("to: {}", e))?;
+        let mut seen = Vec::new();
+        for name in from.variables() {
+            if seen.contains(&name) {
+                return Err(format!("from: {{{}}} is used twice", name));
+            }
+            seen.push(name);
+        }
+        let lookup = cfg.lookup.as_deref().map(Lookup::load).transpose()?;
+        if let Some(lookup) = &lookup {
+            if !seen.contains(&lookup.columns[0].as_str()) {
+                return Err(format!(
+                    "lookup: the first column of {} ('{}') must be a variable of from",
+                    lookup.file, lookup.columns[0]
+                ));
+            }
+        }
+        let known = |name: &str| {
+            seen.contains(&name)
+                || lookup
+                    .as_ref()
+                    .is_some_and(|l| l.columns[1..].iter().any(|c| c == name))
+        };
+        if let Some(name) = to.variables().find(|name| !known(name)) {
+            return Err(format!(
+                "to: {{{}}} is neither captured by from nor a lookup column",
+                name
+            ));
+        }
+        Ok(Rule { from, to, lookup })
+    }
+
+    /// `from -> to`, for logs and the ledger.
+    pub fn describe(&self) -> String {
+        format!("{} -> {}", self.from, self.to)
+    }
+
+    /// `None` when the rule does not match `key`, else its new name.
+    pub fn apply(&self, key: &str) -> Option<Result<String, Unresolved>> {
+        let mut values = self.from.captures(key)?;
+        if let Some(lookup) = &self.lookup {
+            let by = values[lookup.columns[0].as_str()];
+            let Some(row) = lookup.rows.get(by) else {
+                return Some(Err(Unresolved::NotInLookup {
+                    file: lookup.file.clone(),
+                    value: by.to_string(),
+                }));
+            };
+            for (column, value) in lookup.columns.iter().zip(row).skip(1) {
+                values.insert(column.as_str(), value.as_str());
+            }
+        }
+        Some(
+            octa_key::parse(&self.to.render(&values))
+                .map_err(|e| Unresolved::Invalid(e.to_string())),
+        )
+    }
+}
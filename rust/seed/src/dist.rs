@@ -0,0 +1,110 @@
+use rand::Rng;
+use serde::Deserialize;
+
+/// A distribution of numbers, as written in `seed:` (`{dist: lognormal,
+/// median: 256, sigma: 0.5}`). Samples are clamped by the caller.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "dist", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Dist {
+    Fixed {
+        value: f64,
+    },
+    Uniform {
+        min: f64,
+        max: f64,
+    },
+    Normal {
+        mean: f64,
+        stddev: f64,
+    },
+    /// Most values near `median`, a long tail above it: how avatar
+    /// dimensions and file sizes usually spread.
+    Lognormal {
+        median: f64,
+        sigma: f64,
+    },
+}
+
+impl Dist {
+    pub fn sample(&self, rng: &mut impl Rng) -> f64 {
+        match *self {
+            Dist::Fixed { value } => value,
+            Dist::Uniform { min, max } if max > min => rng.random_range(min..max),
+            Dist::Uniform { min, .. } => min,
+            Dist::Normal { mean, stddev } => mean + stddev * standard_normal(rng),
+            Dist::Lognormal { median, sigma } => median * (sigma * standard_normal(rng)).exp(),
+        }
+    }
+
+    /// `(field, problem)` pairs, fields below `field`.
+    pub fn validate(&self, field: &str) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        match *self {
+            Dist::Uniform { min, max } if max < min => problems.push((
+                format!("{}.max", field),
+                "must not be below min".to_string(),
+            )),
+            Dist::Normal { stddev, .. } if stddev < 0.0 => problems.push((
+                format!("{}.stddev", field),
+                "must not be negative".to_string(),
+            )),
+            Dist::Lognormal { median, .. } if median <= 0.0 => {
+                problems.push((format!("{}.median", field), "must be positive".to_string()))
+            }
+            Dist::Lognormal { sigma, .. } if sigma < 0.0 => problems.push((
+                format!("{}.sigma", field),
+                "must not be negative".to_string(),
+            )),
+            _ => {}
+        }
+        problems
+    }
+}
+
+/// Box-Muller.
+fn standard_normal(rng: &mut impl Rng) -> f64 {
+    let u1: f64 = 1.0 - rng.random::<f64>();
+    let u2: f64 = rng.random();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+/// Ranks `0..n`, rank `k` drawn with weight `1 / (k + 1)^exponent`. An
+/// exponent of 0 is uniform; around 1 a few ranks dominate, as a few
+/// tenants own most assets.
+pub struct Zipf {
+    cumulative: Vec<f64>,
+}
+
+impl Zipf {
+    pub fn new(n: usize, exponent: f64) -> Self {
+        let mut total = 0.0;
+        let cumulative = (1..=n.max(1))
+            .map(|rank| {
+                total += (rank as f64).powf(-exponent);
+                total
+            })
+            .collect();
+        Self { cumulative }
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng) -> usize {
+        let total = self.cumulative.last().copied().unwrap_or(0.0);
+        let target = rng.random::<f64>() * total;
+        self.cumulative
+            .partition_point(|&c| c <= target)
+            .min(self.cumulative.len() - 1)
+    }
+}
+
+/// Picks a key of `weights` in proportion to its weight.
+pub fn weighted<'a, T>(weights: &'a [(T, f64)], rng: &mut impl Rng) -> &'a T {
+    let total: f64 = weights.iter().map(|(_, w)| w).sum();
+    let mut target = rng.random::<f64>() * total;
+    for (value, weight) in weights {
+        if target < *weight {
+            return value;
+        }
+        target -= weight;
+    }
+    &weights[weights.len() - 1].0
+}
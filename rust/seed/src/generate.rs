@@ -0,0 +1,155 @@
+use crate::dist::{self, Dist, Zipf};
+use octa_image::image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::io::Cursor;
+
+/// Largest edge generated; the server processes up to this comfortably.
+const MAX_EDGE: u32 = 4096;
+
+/// Encodes tried per asset to land near a byte-size target.
+const SIZE_ATTEMPTS: u32 = 7;
+
+/// What to generate, as read from `seed:`.
+pub struct Shape<'a> {
+    pub prefix: &'a str,
+    pub formats: Vec<(ImageFormat, f64)>,
+    pub width: &'a Dist,
+    /// Height over width.
+    pub aspect: &'a Dist,
+    /// Noise amplitude, 0-255.
+    pub detail: &'a Dist,
+    /// Encoded size to aim for; replaces `detail`.
+    pub bytes: Option<&'a Dist>,
+    pub tenants: usize,
+    /// Zipf exponent of the tenant spread; 0 is even.
+    pub tenant_skew: f64,
+    /// Days before now the asset was created (databases only).
+    pub age_days: &'a Dist,
+}
+
+/// One asset to generate. Everything random is drawn here, from the run's
+/// seed, so the same seed always produces the same dataset.
+#[derive(Debug, Clone)]
+pub struct Spec {
+    pub key: String,
+    pub tenant: usize,
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+    pub detail: u8,
+    pub target_bytes: Option<u64>,
+    pub age_days: f64,
+    /// Seeds the pixels.
+    pub seed: u64,
+}
+
+/// The key of the `i`th asset, owned by `tenant`.
+pub fn key(prefix: &str, tenant: usize, i: u64) -> String {
+    format!("{}tenant-{:04}/asset-{:07}", prefix, tenant, i)
+}
+
+pub fn plan(shape: &Shape, count: u64, seed: u64) -> Vec<Spec> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let tenants = Zipf::new(shape.tenants, shape.tenant_skew);
+    (0..count)
+        .map(|i| {
+            let tenant = tenants.sample(&mut rng);
+            let format = *dist::weighted(&shape.formats, &mut rng);
+            let width = edge(shape.width.sample(&mut rng));
+            let height = edge(width as f64 * shape.aspect.sample(&mut rng));
+            Spec {
+                key: key(shape.prefix, tenant, i),
+                tenant,
+                format,
+                width,
+                height,
+                detail: shape.detail.sample(&mut rng).clamp(0.0, 255.0) as u8,
+                target_bytes: shape
+                    .bytes
+                    .map(|bytes| bytes.sample(&mut rng).max(1.0) as u64),
+                age_days: shape.age_days.sample(&mut rng).max(0.0),
+                seed: rng.random(),
+            }
+        })
+        .collect()
+}
+
+fn edge(value: f64) -> u32 {
+    (value.round() as u32).clamp(1, MAX_EDGE)
+}
+
+/// The encoded image of `spec`. With a byte target, the noise amplitude is
+/// bisected and the closest encode kept; small images may not reach a large
+/// target (and the reverse).
+pub fn render(spec: &Spec) -> Result<Vec<u8>, String> {
+    let Some(target) = spec.target_bytes else {
+        return encode(&draw(spec, spec.detail), spec.format);
+    };
+    let (mut low, mut high) = (0u8, 255u8);
+    let mut best: Option<Vec<u8>> = None;
+    for _ in 0..SIZE_ATTEMPTS {
+        let detail = low + (high - low) / 2;
+        let data = encode(&draw(spec, detail), spec.format)?;
+        let closer = best.as_ref().is_none_or(|b| {
+            b.len().abs_diff(target as usize) > data.len().abs_diff(target as usize)
+        });
+        let below = (data.len() as u64) < target;
+        if closer {
+            best = Some(data);
+        }
+        if below {
+            low = detail.saturating_add(1);
+        } else {
+            high = detail.saturating_sub(1);
+        }
+        if low > high {
+            break;
+        }
+    }
+    best.ok_or_else(|| "nothing encoded".to_string())
+}
+
+/// A gradient backdrop with a disc, like a placeholder avatar, plus noise
+/// of amplitude `detail`, which is what makes files bigger.
+fn draw(spec: &Spec, detail: u8) -> DynamicImage {
+    let mut rng = StdRng::seed_from_u64(spec.seed);
+    let from: [u8; 3] = rng.random();
+    let to: [u8; 3] = rng.random();
+    let disc: [u8; 3] = rng.random();
+    let (w, h) = (spec.width, spec.height);
+    let (cx, cy) = (w as f64 / 2.0, h as f64 / 2.0);
+    let radius = w.min(h) as f64 * rng.random_range(0.2..0.45);
+
+    let mut noise = StdRng::seed_from_u64(spec.seed ^ 0x5eed);
+    let amplitude = detail as i16;
+    let img = RgbImage::from_fn(w, h, |x, y| {
+        let inside = (x as f64 - cx).powi(2) + (y as f64 - cy).powi(2) < radius * radius;
+        let t = (x + y) as f64 / (w + h) as f64;
+        let base: [u8; 3] = if inside {
+            disc
+        } else {
+            std::array::from_fn(|c| (from[c] as f64 * (1.0 - t) + to[c] as f64 * t) as u8)
+        };
+        Rgb(base.map(|v| {
+            if amplitude == 0 {
+                return v;
+            }
+            (v as i16 + noise.random_range(-amplitude..=amplitude)).clamp(0, 255) as u8
+        }))
+    });
+    DynamicImage::ImageRgb8(img)
+}
+
+fn encode(img: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, String> {
+    match format {
+        ImageFormat::Jpeg => {
+            octa_image::encode_jpeg(img, octa_image::QUALITY).map_err(|e| e.to_string())
+        }
+        format => {
+            let mut out = Cursor::new(Vec::new());
+            img.write_to(&mut out, format).map_err(|e| e.to_string())?;
+            Ok(out.into_inner())
+        }
+    }
+}
@@ -0,0 +1,389 @@
+use clap::Parser;
+use dist::Dist;
+use generate::Shape;
+use octa_config::{ConfigError, SecurityConfig, Validate};
+use octa_errors::Kind;
+use octa_image::image::ImageFormat;
+use octa_logging::LogFormat;
+use octa_warden_core::growth::format_bytes;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use target::Target;
+use tracing::{error, info, warn, Level};
+
+mod dist;
+mod generate;
+mod populate;
+mod target;
+
+/*
+OCTA-SEED: Synthetic datasets for Octa
+=============================================
+Mission: Fill an instance (through its API), its SQLite file, or a
+         directory or bucket (as `<key>.<ext>` files) with N generated
+         assets whose formats, dimensions, file sizes, ages and tenant
+         spread follow configurable distributions, reproducibly from a
+         seed, for benchmarks and warden testing.
+Safety:  Only adds: every key starts with seed.prefix, and keys a database
+         already has are left alone; in a directory or bucket only files
+         of the same name are replaced. --dry-run only lists the plan.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Populate an Octa instance or database with a synthetic dataset"
+)]
+struct Args {
+    /// Instance URL, SQLite path, fs:<dir> or s3:<bucket>[/<prefix>] to fill (overrides seed.target)
+    target: Option<String>,
+
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Upload secret of the instance (default: security.upload_secret)
+    #[arg(long, env = "OCTA_SEED_SECRET", hide_env_values = true)]
+    secret: Option<String>,
+
+    /// Assets to generate (overrides seed.count)
+    #[arg(short = 'n', long)]
+    count: Option<u64>,
+
+    /// Random seed; the same seed gives the same dataset (overrides seed.seed)
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Prefix of every key (overrides seed.prefix)
+    #[arg(long)]
+    prefix: Option<String>,
+
+    /// Images rendered and uploaded at once (overrides seed.concurrency)
+    #[arg(long)]
+    concurrency: Option<usize>,
+
+    /// List the planned assets instead of generating them
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Seconds before an upload is abandoned
+    #[arg(long, default_value_t = 60)]
+    timeout: u64,
+
+    /// Milliseconds to wait on a locked database before giving up (SQLITE_BUSY)
+    #[arg(long, default_value_t = 5000)]
+    busy_timeout: u64,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+}
+
+/// The parts of config.yaml octa-seed reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    security: SecurityConfig,
+    seed: SeedConfig,
+}
+
+/// `seed:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SeedConfig {
+    target: String,
+    count: u64,
+    seed: u64,
+    prefix: String,
+    concurrency: usize,
+    /// Relative weights; the server accepts `jpeg` and `png`.
+    formats: BTreeMap<String, f64>,
+    /// Width in pixels.
+    width: Dist,
+    /// Height over width.
+    aspect: Dist,
+    /// Noise amplitude (0-255): more detail, bigger files.
+    detail: Dist,
+    /// Encoded size in bytes to aim for, instead of `detail`.
+    bytes: Option<Dist>,
+    tenants: usize,
+    /// Zipf exponent of how assets spread over tenants; 0 is even.
+    tenant_skew: f64,
+    /// Days since upload; only a database target can backdate.
+    age_days: Dist,
+}
+
+impl Default for SeedConfig {
+    fn default() -> Self {
+        Self {
+            target: String::new(),
+            count: 1000,
+            seed: 1,
+            prefix: "seed/".to_string(),
+            concurrency: 8,
+            formats: BTreeMap::from([("jpeg".to_string(), 80.0), ("png".to_string(), 20.0)]),
+            width: Dist::Lognormal {
+                median: 256.0,
+                sigma: 0.5,
+            },
+            aspect: Dist::Fixed { value: 1.0 },
+            detail: Dist::Uniform {
+                min: 0.0,
+                max: 24.0,
+            },
+            bytes: None,
+            tenants: 20,
+            tenant_skew: 1.0,
+            age_days: Dist::Uniform {
+                min: 0.0,
+                max: 365.0,
+            },
+        }
+    }
+}
+
+impl SeedConfig {
+    fn formats(&self) -> Vec<(ImageFormat, f64)> {
+        self.formats
+            .iter()
+            .filter_map(|(name, weight)| Some((format(name)?, *weight)))
+            .collect()
+    }
+}
+
+fn format(name: &str) -> Option<ImageFormat> {
+    match name {
+        "jpeg" | "jpg" => Some(ImageFormat::Jpeg),
+        "png" => Some(ImageFormat::Png),
+        _ => None,
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let seed = &self.seed;
+        let mut problems = Vec::new();
+        for (name, weight) in &seed.formats {
+            if format(name).is_none() {
+                problems.push((
+                    format!("seed.formats.{}", name),
+                    "is not one of jpeg, png".to_string(),
+                ));
+            } else if *weight < 0.0 {
+                problems.push((
+                    format!("seed.formats.{}", name),
+                    "must not be negative".to_string(),
+                ));
+            }
+        }
+        if seed.formats.values().sum::<f64>() <= 0.0 {
+            problems.push((
+                "seed.formats".to_string(),
+                "needs a format with a positive weight".to_string(),
+            ));
+        }
+        if seed.concurrency == 0 {
+            problems.push((
+                "seed.concurrency".to_string(),
+                "must be at least 1".to_string(),
+            ));
+        }
+        if seed.tenants == 0 {
+            problems.push(("seed.tenants".to_string(), "must be at least 1".to_string()));
+        }
+        if seed.tenant_skew < 0.0 {
+            problems.push((
+                "seed.tenant_skew".to_string(),
+                "must not be negative".to_string(),
+            ));
+        }
+        problems.extend(seed.width.validate("seed.width"));
+        problems.extend(seed.aspect.validate("seed.aspect"));
+        problems.extend(seed.detail.validate("seed.detail"));
+        problems.extend(seed.age_days.validate("seed.age_days"));
+        if let Some(bytes) = &seed.bytes {
+            problems.extend(bytes.validate("seed.bytes"));
+        }
+        problems
+    }
+}
+
+/// Without a file, the environment and arguments alone configure seeding.
+fn load_config(path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    match octa_config::discover(path.map(Path::new)) {
+        Some(path) => octa_config::load(&path),
+        None => {
+            let config: FileConfig = octa_config::from_env()?;
+            octa_config::check(Path::new("<environment>"), config)
+        }
+    }
+}
+
+/// The octa-seed command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa seed` the arguments it was given.
+#[tokio::main]
+pub async fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, Level::INFO, false, false);
+
+    let config = match load_config(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let seed = &config.seed;
+    let spec = args.target.as_deref().unwrap_or(&seed.target);
+    if spec.trim().is_empty() {
+        error!(
+            tag = "FATAL",
+            "No target (pass it as an argument or set seed.target)"
+        );
+        return Kind::Config.exit_code();
+    }
+
+    let shape = Shape {
+        prefix: args.prefix.as_deref().unwrap_or(&seed.prefix),
+        formats: seed.formats(),
+        width: &seed.width,
+        aspect: &seed.aspect,
+        detail: &seed.detail,
+        bytes: seed.bytes.as_ref(),
+        tenants: seed.tenants,
+        tenant_skew: seed.tenant_skew,
+        age_days: &seed.age_days,
+    };
+    let count = args.count.unwrap_or(seed.count);
+    // The longest key planned; the rest are legal when it is.
+    let longest = generate::key(shape.prefix, seed.tenants, count.saturating_sub(1));
+    if let Err(e) = octa_key::validate(&longest) {
+        error!(tag = "FATAL", key = %longest, reason = %e, "Prefix gives invalid keys");
+        return Kind::Config.exit_code();
+    }
+    let specs = generate::plan(&shape, count, args.seed.unwrap_or(seed.seed));
+    describe_plan(&specs, seed.tenants);
+
+    if args.dry_run {
+        for spec in &specs {
+            info!(
+                tag = "SEED",
+                key = %spec.key,
+                format = %octa_image::format_name(spec.format),
+                width = spec.width,
+                height = spec.height,
+                bytes = spec.target_bytes,
+                age_days = spec.age_days.round() as u64,
+                "Would create"
+            );
+        }
+        info!(
+            tag = "OK",
+            assets = specs.len(),
+            "Dry run: nothing generated"
+        );
+        return ExitCode::SUCCESS;
+    }
+
+    let secret = args
+        .secret
+        .as_deref()
+        .unwrap_or(&config.security.upload_secret);
+    let busy_timeout = Duration::from_millis(args.busy_timeout);
+    let target = match Target::new(spec, secret, Duration::from_secs(args.timeout), busy_timeout) {
+        Ok(target) => target,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid target");
+            return e.exit_code();
+        }
+    };
+    match target {
+        Target::Api(_) => info!(
+            tag = "→",
+            "Uploads are stamped by the instance; seed.age_days only applies to database targets"
+        ),
+        Target::Store(..) => info!(
+            tag = "→",
+            "Files and objects carry no upload time; seed.age_days only applies to database targets"
+        ),
+        Target::Db(_) => {}
+    }
+
+    info!(
+        tag = "→",
+        target = %target.describe(),
+        assets = count,
+        "Seeding"
+    );
+    let started = Instant::now();
+    let stats = populate::run(&target, specs, args.concurrency.unwrap_or(seed.concurrency)).await;
+    let elapsed = started.elapsed();
+
+    if stats.skipped > 0 {
+        warn!(
+            tag = "SKIP",
+            assets = stats.skipped,
+            "Keys already in the database, left alone"
+        );
+    }
+    for (format, assets) in &stats.formats {
+        info!(tag = "SUMMARY", format = %format, assets, "Written");
+    }
+    info!(
+        tag = if stats.failed == 0 { "OK" } else { "WARN" },
+        written = stats.written,
+        failed = stats.failed,
+        size = %format_bytes(stats.bytes as f64),
+        p50 = %format_bytes(stats.size_percentile(50) as f64),
+        p90 = %format_bytes(stats.size_percentile(90) as f64),
+        p99 = %format_bytes(stats.size_percentile(99) as f64),
+        per_sec = format!("{:.1}", stats.written as f64 / elapsed.as_secs_f64().max(0.001)),
+        "Seeding finished"
+    );
+    if stats.failed > 0 {
+        target.failure().exit_code()
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// How the plan spreads: formats, dimensions and the busiest tenants.
+fn describe_plan(specs: &[generate::Spec], tenants: usize) {
+    if specs.is_empty() {
+        return;
+    }
+    let mut formats: BTreeMap<String, u64> = BTreeMap::new();
+    let mut per_tenant = vec![0u64; tenants];
+    let mut widths: Vec<u32> = Vec::with_capacity(specs.len());
+    for spec in specs {
+        *formats
+            .entry(octa_image::format_name(spec.format))
+            .or_default() += 1;
+        per_tenant[spec.tenant] += 1;
+        widths.push(spec.width);
+    }
+    widths.sort_unstable();
+    let width = |q: usize| widths[(widths.len() * q / 100).min(widths.len() - 1)];
+    let busiest = per_tenant.iter().max().copied().unwrap_or(0);
+    let used = per_tenant.iter().filter(|n| **n > 0).count();
+
+    let formats: Vec<String> = formats
+        .iter()
+        .map(|(format, n)| format!("{} {}", format, n))
+        .collect();
+    info!(
+        tag = "PLAN",
+        assets = specs.len(),
+        formats = %formats.join(", "),
+        width_p50 = width(50),
+        width_p90 = width(90),
+        width_max = width(100),
+        tenants = used,
+        busiest_tenant_share = format!("{:.1}%", busiest as f64 * 100.0 / specs.len() as f64),
+        "Dataset planned"
+    );
+}
@@ -0,0 +1,124 @@
+use crate::generate::{self, Spec};
+use crate::target::{Asset, Target};
+use futures::stream::{self, StreamExt};
+use std::collections::BTreeMap;
+use tracing::{info, warn};
+
+/// Assets per batch on a database or store target; a database inserts
+/// each batch in one transaction.
+const BATCH_SIZE: usize = 200;
+
+/// How a run went.
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub written: u64,
+    /// Keys a database already had.
+    pub skipped: u64,
+    pub failed: u64,
+    pub bytes: u64,
+    /// Encoded size of each written asset.
+    pub sizes: Vec<u64>,
+    /// Assets written per format.
+    pub formats: BTreeMap<String, u64>,
+}
+
+impl Stats {
+    /// The `q`th percentile (0-100) of the encoded sizes.
+    pub fn size_percentile(&self, q: usize) -> u64 {
+        let mut sorted = self.sizes.clone();
+        sorted.sort_unstable();
+        match sorted.len() {
+            0 => 0,
+            n => sorted[(n * q / 100).min(n - 1)],
+        }
+    }
+
+    fn record(&mut self, asset: &Asset) {
+        self.written += 1;
+        self.bytes += asset.data.len() as u64;
+        self.sizes.push(asset.data.len() as u64);
+        *self
+            .formats
+            .entry(octa_image::format_name(asset.spec.format))
+            .or_default() += 1;
+    }
+}
+
+/// Renders every spec, at most `concurrency` at a time, and stores it:
+/// uploads go out as soon as an image is ready, rows and files are written
+/// in batches.
+pub async fn run(target: &Target, specs: Vec<Spec>, concurrency: usize) -> Stats {
+    let total = specs.len() as u64;
+    let step = (total / 10).max(1);
+    let mut stats = Stats::default();
+    let mut batch: Vec<Asset> = Vec::new();
+
+    let mut outcomes = stream::iter(specs)
+        .map(|spec| async move {
+            let key = spec.key.clone();
+            let rendered = tokio::task::spawn_blocking(move || {
+                generate::render(&spec).map(|data| Asset { spec, data })
+            })
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|rendered| rendered);
+            let asset = match rendered {
+                Ok(asset) => asset,
+                Err(e) => return (key, Err(format!("could not render: {}", e))),
+            };
+            if let Target::Api(api) = target {
+                if let Err(e) = api.upload(&asset).await {
+                    return (key, Err(e));
+                }
+            }
+            (key, Ok(asset))
+        })
+        .buffer_unordered(concurrency.max(1));
+
+    let mut done = 0u64;
+    while let Some((key, outcome)) = outcomes.next().await {
+        done += 1;
+        match (outcome, target) {
+            (Ok(asset), Target::Api(_)) => stats.record(&asset),
+            (Ok(asset), _) => batch.push(asset),
+            (Err(e), _) => {
+                stats.failed += 1;
+                warn!(tag = "FAIL", key = %key, reason = %e, "Not stored");
+            }
+        }
+        if !matches!(target, Target::Api(_))
+            && (batch.len() >= BATCH_SIZE || (done == total && !batch.is_empty()))
+        {
+            let assets = std::mem::take(&mut batch);
+            match tokio::task::block_in_place(|| target.write(&assets)) {
+                Ok(written) => {
+                    for (asset, written) in assets.iter().zip(written) {
+                        match written {
+                            Ok(true) => stats.record(asset),
+                            Ok(false) => stats.skipped += 1,
+                            Err(e) => {
+                                stats.failed += 1;
+                                warn!(tag = "FAIL", key = %asset.spec.key, reason = %e, "Not stored");
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    stats.failed += assets.len() as u64;
+                    warn!(tag = "FAIL", assets = assets.len(), reason = %e, "Batch not stored");
+                }
+            }
+        }
+        if done.is_multiple_of(step) || done == total {
+            info!(
+                tag = "→",
+                done,
+                total,
+                written = stats.written,
+                failed = stats.failed,
+                "Progress"
+            );
+        }
+    }
+    stats
+}
@@ -0,0 +1,164 @@
+use crate::generate::Spec;
+use chrono::{Duration as Age, Utc};
+use octa_client::{Client, Mode, UploadOptions};
+use octa_errors::{Error, Kind};
+use octa_storage::{Added, Backend, Location, NewAsset, Sqlite};
+use std::time::Duration;
+
+/// Where the assets go: a running instance, through its API, the SQLite
+/// file behind one, or a file or object store (`fs:<dir>`,
+/// `s3:<bucket>[/<prefix>]`) to import or audit later.
+pub enum Target {
+    Api(Api),
+    Db(Db),
+    Store(Box<dyn Backend>, Location),
+}
+
+pub struct Api {
+    url: String,
+    client: Client,
+}
+
+pub struct Db {
+    sqlite: Sqlite,
+}
+
+/// A generated asset, ready to store.
+pub struct Asset {
+    pub spec: Spec,
+    pub data: Vec<u8>,
+}
+
+impl Target {
+    /// `http(s)://...` is an instance, `fs:` and `s3:` a store; anything
+    /// else a database path, which must already have the server's schema.
+    pub fn new(
+        spec: &str,
+        secret: &str,
+        timeout: Duration,
+        busy_timeout: Duration,
+    ) -> Result<Self, Error> {
+        if spec.starts_with("http://") || spec.starts_with("https://") {
+            let client = Client::builder(spec)
+                .secret(secret)
+                .timeout(timeout)
+                .user_agent(concat!("octa-seed/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .map_err(|e| Error::new(Kind::Config, e.to_string()))?;
+            return Ok(Target::Api(Api {
+                url: spec.trim_end_matches('/').to_string(),
+                client,
+            }));
+        }
+        if spec.starts_with("fs:") || spec.starts_with("s3:") {
+            let location = Location::parse(spec).map_err(|e| Error::new(Kind::Config, e))?;
+            let store = location
+                .open(None)
+                .map_err(|e| Error::new(e.kind(), e.to_string()))?;
+            return Ok(Target::Store(store, location));
+        }
+        if !std::path::Path::new(spec).exists() {
+            return Err(Error::new(
+                Kind::NoInput,
+                format!(
+                    "{}: database file not found (start the server once, or run `make migrate ARGS=up`)",
+                    spec
+                ),
+            ));
+        }
+        let sqlite =
+            Sqlite::open(spec, busy_timeout).map_err(|e| Error::new(e.kind(), e.to_string()))?;
+        Ok(Target::Db(Db { sqlite }))
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Target::Api(api) => api.url.clone(),
+            Target::Db(db) => db.sqlite.describe(),
+            Target::Store(store, _) => store.describe(),
+        }
+    }
+
+    /// What a failed write means: the instance or bucket could not be
+    /// reached, or the database or directory could not be written.
+    pub fn failure(&self) -> Kind {
+        match self {
+            Target::Api(_) | Target::Store(_, Location::S3 { .. }) => Kind::Unavailable,
+            Target::Db(_) | Target::Store(_, Location::Fs(_)) => Kind::Io,
+        }
+    }
+
+    /// Stores a batch on a database or store: for each asset, whether it
+    /// was written (`false`: the database had its key already), or why
+    /// not. An instance takes one [`Api::upload`] at a time instead.
+    pub fn write(&self, assets: &[Asset]) -> Result<Vec<Result<bool, String>>, String> {
+        match self {
+            Target::Api(_) => Err("an instance takes uploads, not batches".to_string()),
+            Target::Db(db) => db.insert(assets),
+            Target::Store(store, _) => {
+                let items: Vec<(String, Vec<u8>)> = assets
+                    .iter()
+                    .map(|asset| (file_name(&asset.spec), asset.data.clone()))
+                    .collect();
+                let written = store.write_batch(&items).map_err(|e| e.to_string())?;
+                Ok(written
+                    .into_iter()
+                    .map(|written| written.map(|()| true).map_err(|e| e.to_string()))
+                    .collect())
+            }
+        }
+    }
+}
+
+impl Api {
+    /// Uploads one asset as is (`mode=original`), so the generated format
+    /// and dimensions are what the instance stores. An existing key is
+    /// updated in place.
+    pub async fn upload(&self, asset: &Asset) -> Result<(), String> {
+        self.client
+            .upload_avatar(
+                &asset.spec.key,
+                asset.data.clone(),
+                UploadOptions::new().mode(Mode::Original),
+            )
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl Db {
+    /// Inserts a batch in one transaction, created and updated `age_days`
+    /// ago. Keys already in the database are left alone.
+    fn insert(&self, assets: &[Asset]) -> Result<Vec<Result<bool, String>>, String> {
+        let keys: Vec<[String; 1]> = assets.iter().map(|a| [a.spec.key.clone()]).collect();
+        let batch: Vec<NewAsset> = assets
+            .iter()
+            .zip(&keys)
+            .map(|(asset, keys)| NewAsset {
+                data: &asset.data,
+                keys,
+                at: Some(
+                    (Utc::now() - Age::seconds((asset.spec.age_days * 86_400.0) as i64))
+                        .format("%Y-%m-%d %H:%M:%S%.f+00:00")
+                        .to_string(),
+                ),
+            })
+            .collect();
+        let added = self.sqlite.add(&batch).map_err(|e| e.to_string())?;
+        Ok(added
+            .into_iter()
+            .map(|added| match added {
+                Added::Inserted(_) => Ok(true),
+                Added::Taken { .. } => Ok(false),
+                Added::Invalid(reason) => Err(reason),
+            })
+            .collect())
+    }
+}
+
+/// `<key>.<ext>`: the name `octa-warden import` turns back into the key.
+fn file_name(spec: &Spec) -> String {
+    let ext = spec.format.extensions_str().first().copied().unwrap_or("bin");
+    format!("{}.{}", spec.key, ext)
+}
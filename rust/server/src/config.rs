@@ -0,0 +1,124 @@
+use octa_config::{ConfigError, DatabaseConfig, SecurityConfig, ServerConfig, Validate};
+use serde::Deserialize;
+use std::path::Path;
+
+/// The sections of config.yaml the server reads, with the Go server's
+/// defaults. `cache`, `consoleui`, CORS and rate limiting are not
+/// implemented and are ignored.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub security: SecurityConfig,
+    pub image: ImageConfig,
+    pub base_url: Option<String>,
+}
+
+/// `image:`
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ImageConfig {
+    /// Edge length of generated avatars without `?size=`.
+    pub default_size: u32,
+    /// Upload body limit, e.g. `5MB` (binary units, as the Go server reads it).
+    pub max_upload_size: String,
+    /// Keys per upload.
+    pub max_key_limit: usize,
+}
+
+impl Default for ImageConfig {
+    fn default() -> Self {
+        Self {
+            default_size: 360,
+            max_upload_size: "5MB".to_string(),
+            max_key_limit: 7,
+        }
+    }
+}
+
+impl ImageConfig {
+    pub fn max_upload_bytes(&self) -> usize {
+        parse_size(&self.max_upload_size).unwrap_or(5 << 20)
+    }
+}
+
+impl Validate for Config {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = self.server.validate();
+        if self.database.path.trim().is_empty() {
+            problems.push(("database.path".to_string(), "is required".to_string()));
+        }
+        let secret = self.security.upload_secret.as_str();
+        if self.server.env == "production" && (secret.is_empty() || secret == "secret") {
+            problems.push((
+                "security.upload_secret".to_string(),
+                "cannot be default or empty in production".to_string(),
+            ));
+        }
+        if !self.security.private_prefixes.is_empty()
+            && self.security.signing_secret.trim().is_empty()
+        {
+            problems.push((
+                "security.signing_secret".to_string(),
+                "is required when security.private_prefixes is set".to_string(),
+            ));
+        }
+        if parse_size(&self.image.max_upload_size).is_none() {
+            problems.push((
+                "image.max_upload_size".to_string(),
+                format!("'{}' is not a size like 5MB", self.image.max_upload_size),
+            ));
+        }
+        if !(16..=1024).contains(&self.image.default_size) {
+            problems.push((
+                "image.default_size".to_string(),
+                "must be between 16 and 1024".to_string(),
+            ));
+        }
+        if self.image.max_key_limit == 0 {
+            problems.push((
+                "image.max_key_limit".to_string(),
+                "must be at least 1".to_string(),
+            ));
+        }
+        problems.extend(octa_config::check_url("base_url", self.base_url.as_deref()));
+        problems
+    }
+}
+
+impl Config {
+    pub fn base_url(&self) -> String {
+        octa_config::base_url(self.base_url.as_deref(), &self.server)
+    }
+}
+
+/// Same lookup as the other tools; without a file the environment alone
+/// configures the server, like the Go server's Viper setup.
+pub fn load(path: Option<&str>) -> Result<Config, ConfigError> {
+    match octa_config::discover(path.map(Path::new)) {
+        Some(path) => octa_config::load(&path),
+        None => {
+            let config: Config = octa_config::from_env()?;
+            octa_config::check(Path::new("<environment>"), config)
+        }
+    }
+}
+
+/// `5MB`, `512 KB`, `1024`: binary units, case-insensitive.
+fn parse_size(value: &str) -> Option<usize> {
+    let value = value.trim().to_uppercase();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: usize = number.parse().ok().filter(|&n| n > 0)?;
+    let shift = match unit.trim() {
+        "" | "B" => 0,
+        "KB" => 10,
+        "MB" => 20,
+        "GB" => 30,
+        _ => return None,
+    };
+    number.checked_mul(1 << shift)
+}
@@ -0,0 +1,61 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+use tracing::warn;
+
+// Error codes, as in the Go server's pkg/utils/response.go.
+pub const REQUEST_INVALID: &str = "request/invalid_parameters";
+pub const REQUEST_MISSING_KEY: &str = "request/missing_key";
+pub const REQUEST_BODY_TOO_LARGE: &str = "request/body_too_large";
+pub const REQUEST_UNSUPPORTED_MEDIA: &str = "request/invalid_media";
+pub const AUTH_INVALID: &str = "auth/invalid_credentials";
+pub const SERVER_INTERNAL: &str = "server/internal_error";
+pub const RESOURCE_NOT_FOUND: &str = "resource/not_found";
+pub const IMAGE_GENERATION_FAILED: &str = "image/generation_failed";
+pub const IMAGE_PROCESSING_FAILED: &str = "image/processing_failed";
+
+/// An error response: `{"code", "message", "status"}` with that status.
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    pub code: &'static str,
+    pub message: String,
+    #[serde(rename = "status")]
+    pub status_code: u16,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            status_code: status.as_u16(),
+        }
+    }
+
+    pub fn bad_request(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, RESOURCE_NOT_FOUND, message)
+    }
+
+    pub fn forbidden() -> Self {
+        Self::new(StatusCode::FORBIDDEN, AUTH_INVALID, "Invalid secret key.")
+    }
+
+    /// A 500 whose detail goes to the log rather than to the client.
+    pub fn internal(message: &str, detail: impl std::fmt::Display) -> Self {
+        warn!(tag = "HTTP", error = %detail, "{}", message);
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, SERVER_INTERNAL, message)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status =
+            StatusCode::from_u16(self.status_code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        (status, Json(self)).into_response()
+    }
+}
@@ -0,0 +1,172 @@
+use crate::error::{self, ApiError};
+use crate::handlers::{self, Shared, Target};
+use axum::body::{Body, Bytes};
+use axum::extract::rejection::BytesRejection;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::Response;
+use octa_grpc::{decode, pb, Code, Status};
+use octa_image::Profile;
+use octa_warden_core::export::sha256_hex;
+
+/// The same failure as the HTTP API reports it, with its code and status
+/// in the `octa-*` trailers.
+impl From<ApiError> for Status {
+    fn from(e: ApiError) -> Self {
+        Status::from_api(e.status_code, e.code, e.message)
+    }
+}
+
+/// POST /octa.v1.Avatars/{method}: the unary calls of `octa.proto`, each
+/// the same work as its HTTP route.
+pub async fn handle(
+    State(state): State<Shared>,
+    Path(method): Path<String>,
+    headers: HeaderMap,
+    body: Result<Bytes, BytesRejection>,
+) -> Response {
+    let body = match body {
+        Ok(body) => body,
+        Err(e) if e.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+            let too_large =
+                ApiError::bad_request(error::REQUEST_BODY_TOO_LARGE, "File exceeds size limit.");
+            return reply::<pb::UploadReply>(Err(too_large.into()));
+        }
+        Err(e) => {
+            let unreadable = Status::new(Code::InvalidArgument, e.body_text());
+            return reply::<pb::UploadReply>(Err(unreadable));
+        }
+    };
+    match method.as_str() {
+        "GetAvatar" => reply(get_avatar(&state, &body).await),
+        "Upload" => reply(upload(&state, &headers, &body).await),
+        "Stat" => reply(stat(&state, &headers, &body).await),
+        "Delete" => reply(delete(&state, &headers, &body).await),
+        "List" => reply(list(&state, &headers, &body).await),
+        "Health" => reply(health(&state).await),
+        _ => reply::<pb::HealthReply>(Err(Status::new(
+            Code::Unimplemented,
+            format!("unknown method {}", method),
+        ))),
+    }
+}
+
+fn reply<M: prost::Message>(result: Result<M, Status>) -> Response {
+    octa_grpc::response(result).map(Body::new)
+}
+
+async fn get_avatar(state: &Shared, body: &[u8]) -> Result<pb::Avatar, Status> {
+    let request: pb::AvatarRequest = decode(body)?;
+    if request.key.is_empty() {
+        return Err(ApiError::bad_request(
+            error::REQUEST_MISSING_KEY,
+            "Avatar seed key is missing.",
+        )
+        .into());
+    }
+    let image = if request.generated {
+        let cache = handlers::PUBLIC_CACHE.to_string();
+        handlers::generated(state, request.key, request.params, cache).await?
+    } else {
+        handlers::avatar(state, request.key, request.params).await?
+    };
+    let etag = sha256_hex(&image.data);
+    let not_modified = request.if_none_match.contains(&etag);
+    Ok(pb::Avatar {
+        data: if not_modified {
+            Bytes::new()
+        } else {
+            image.data.into()
+        },
+        content_type: image.mime.to_string(),
+        etag: format!("\"{}\"", etag),
+        cache_control: image.cache,
+        not_modified,
+    })
+}
+
+async fn upload(
+    state: &Shared,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<pb::UploadReply, Status> {
+    handlers::authorize(state, headers).await?;
+    let request: pb::UploadRequest = decode(body)?;
+    let number = |n: u32| (n > 0).then(|| n.to_string());
+    let (size, scale) = (number(request.size), number(request.scale));
+    let mode = (!request.mode.is_empty()).then_some(request.mode.as_str());
+    let profile = Profile::from_fields(mode, size.as_deref(), scale.as_deref());
+    let image = (!request.image.is_empty()).then(|| request.image.to_vec());
+    let uploaded = handlers::save(state, &request.keys.join(","), image, profile).await?;
+    Ok(pb::UploadReply {
+        action: uploaded.saved.action.to_string(),
+        avatar_id: uploaded.saved.id,
+        keys: uploaded.saved.keys,
+        url: uploaded.url,
+        size_kb: (uploaded.size / 1024) as u64,
+    })
+}
+
+fn target(body: &[u8]) -> Result<Target, Status> {
+    let target: pb::Target = decode(body)?;
+    Ok(Target {
+        key: target.key,
+        id: target.id,
+    })
+}
+
+async fn stat(state: &Shared, headers: &HeaderMap, body: &[u8]) -> Result<pb::Asset, Status> {
+    handlers::authorize(state, headers).await?;
+    let (asset, url) = handlers::asset(state, target(body)?).await?;
+    Ok(pb::Asset {
+        avatar_id: asset.avatar_id,
+        keys: asset.keys,
+        width: asset.width as u32,
+        height: asset.height as u32,
+        format: asset.format,
+        size: asset.size as u64,
+        created_at: asset.created_at.unwrap_or_default(),
+        updated_at: asset.updated_at.unwrap_or_default(),
+        url,
+    })
+}
+
+async fn delete(
+    state: &Shared,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<pb::DeleteReply, Status> {
+    handlers::authorize(state, headers).await?;
+    let target = handlers::remove(state, target(body)?).await?;
+    Ok(pb::DeleteReply { target })
+}
+
+async fn list(state: &Shared, headers: &HeaderMap, body: &[u8]) -> Result<pb::ListReply, Status> {
+    handlers::authorize(state, headers).await?;
+    let request: pb::ListRequest = decode(body)?;
+    let limit = (request.limit > 0).then_some(request.limit as usize);
+    let (items, next) = handlers::page(state, request.prefix, request.after, limit).await?;
+    Ok(pb::ListReply {
+        items: items
+            .into_iter()
+            .map(|item| pb::ListItem {
+                key: item.key,
+                avatar_id: item.avatar_id,
+                size: item.size as u64,
+                format: item.format,
+                updated_at: item.updated_at.unwrap_or_default(),
+            })
+            .collect(),
+        next,
+    })
+}
+
+async fn health(state: &Shared) -> Result<pb::HealthReply, Status> {
+    let (assets, bytes) = handlers::totals(state).await?;
+    Ok(pb::HealthReply {
+        status: "ok".to_string(),
+        assets: assets as u64,
+        bytes: bytes as u64,
+        uptime_seconds: state.started.elapsed().as_secs(),
+    })
+}
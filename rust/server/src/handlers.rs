@@ -0,0 +1,453 @@
+use crate::config::Config;
+use crate::error::{self, ApiError};
+use axum::extract::multipart::MultipartError;
+use axum::extract::{Multipart, Path, Query, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use octa_image::Profile;
+use octa_store::{Asset, ListItem, Saved, Store};
+use octa_warden_core::export::sha256_hex;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+pub struct AppState {
+    pub config: Config,
+    /// Checks links to keys under `security.private_prefixes`.
+    pub signer: octa_sign::Signer,
+    pub store: Store,
+    pub started: Instant,
+}
+
+pub type Shared = Arc<AppState>;
+
+/// `?key=` or `?id=`, for stat and delete.
+#[derive(Deserialize)]
+pub struct Target {
+    #[serde(default)]
+    pub key: String,
+    #[serde(default)]
+    pub id: String,
+}
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+    #[serde(default)]
+    prefix: String,
+    #[serde(default)]
+    after: String,
+    limit: Option<String>,
+}
+
+/// Runs blocking work (SQLite, image codecs) off the async workers.
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, ApiError> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| ApiError::internal("Worker failed.", e))
+}
+
+/// `X-Secret-Key` against `security.upload_secret`, in constant time, then
+/// against the working keys octa-keys issued.
+pub async fn authorize(state: &Shared, headers: &HeaderMap) -> Result<(), ApiError> {
+    let given = headers
+        .get("X-Secret-Key")
+        .map(HeaderValue::as_bytes)
+        .unwrap_or_default();
+    let expected = state.config.security.upload_secret.as_bytes();
+    let diff = given.len() != expected.len();
+    let mismatch = given
+        .iter()
+        .zip(expected)
+        .fold(0u8, |acc, (a, b)| acc | (a ^ b));
+    if !diff && mismatch == 0 {
+        return Ok(());
+    }
+
+    let Ok(secret) = std::str::from_utf8(given).map(str::to_string) else {
+        return Err(ApiError::forbidden());
+    };
+    let store = state.clone();
+    match blocking(move || store.store.with_conn(|conn| octa_keys::verify(conn, &secret)))
+        .await?
+        .map_err(|e| ApiError::internal("Key lookup failed.", e))?
+    {
+        Some(_) => Ok(()),
+        None => Err(ApiError::forbidden()),
+    }
+}
+
+/// A multipart error as the Go server reports it: `REQUEST_BODY_TOO_LARGE`
+/// for a body over the upload limit, `REQUEST_INVALID` for a malformed form.
+fn too_large(e: MultipartError) -> ApiError {
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        ApiError::bad_request(error::REQUEST_BODY_TOO_LARGE, "File exceeds size limit.")
+    } else {
+        ApiError::bad_request(error::REQUEST_INVALID, e.body_text())
+    }
+}
+
+/// POST /upload
+pub async fn upload(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Json<Value>, ApiError> {
+    authorize(&state, &headers).await?;
+
+    let mut fields: HashMap<String, String> = HashMap::new();
+    let mut avatar = None;
+    while let Some(field) = multipart.next_field().await.map_err(too_large)? {
+        let name = field.name().unwrap_or_default().to_string();
+        if name == "avatar" {
+            avatar = Some(field.bytes().await.map_err(too_large)?.to_vec());
+        } else {
+            fields.insert(name, field.text().await.map_err(too_large)?);
+        }
+    }
+
+    let field = |name: &str| fields.get(name).map(String::as_str);
+    let profile = Profile::from_fields(field("mode"), field("size"), field("scale"));
+    let uploaded = save(&state, field("keys").unwrap_or_default(), avatar, profile).await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "action": uploaded.saved.action,
+        "avatar_id": uploaded.saved.id,
+        "url": uploaded.url,
+        "keys": uploaded.saved.keys,
+        "size_kb": uploaded.size / 1024,
+    })))
+}
+
+/// What an upload stored.
+pub struct Uploaded {
+    pub saved: Saved,
+    pub url: String,
+    /// Bytes stored, after processing.
+    pub size: usize,
+}
+
+/// An upload once its fields are read, over HTTP or gRPC: `keys` is the
+/// comma-separated list of the form field.
+pub async fn save(
+    state: &Shared,
+    keys: &str,
+    avatar: Option<Vec<u8>>,
+    profile: Profile,
+) -> Result<Uploaded, ApiError> {
+    let keys = octa_key::parse_list(keys);
+    if keys.is_empty() {
+        return Err(ApiError::bad_request(
+            error::REQUEST_INVALID,
+            "At least one valid key is required.",
+        ));
+    }
+    if keys.len() > state.config.image.max_key_limit {
+        return Err(ApiError::bad_request(
+            error::REQUEST_INVALID,
+            "Too many keys provided.",
+        ));
+    }
+    let Some(data) = avatar else {
+        return Err(ApiError::bad_request(
+            error::REQUEST_INVALID,
+            "Missing 'avatar' file field.",
+        ));
+    };
+    if !octa_image::is_allowed(&data) {
+        return Err(ApiError::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            error::REQUEST_UNSUPPORTED_MEDIA,
+            "Unsupported file type.",
+        ));
+    }
+
+    let image = blocking(move || octa_image::process(data, &profile))
+        .await?
+        .map_err(|e| ApiError::bad_request(error::IMAGE_PROCESSING_FAILED, e.to_string()))?;
+    let size = image.data.len();
+
+    let store = state.clone();
+    let saved = blocking(move || store.store.save(&keys, image))
+        .await?
+        .map_err(|e| ApiError::internal("Failed to save image.", e))?;
+
+    Ok(Uploaded {
+        url: format!("{}/u/{}", state.config.base_url(), saved.keys[0]),
+        saved,
+        size,
+    })
+}
+
+/// The asset id of `?id=`, or of the asset behind `?key=`.
+async fn resolve(state: &Shared, target: Target) -> Result<String, ApiError> {
+    if !target.id.is_empty() {
+        return Ok(target.id);
+    }
+    if target.key.is_empty() {
+        return Err(ApiError::bad_request(
+            error::REQUEST_INVALID,
+            "Parameter 'key' or 'id' is required.",
+        ));
+    }
+    let store = state.clone();
+    blocking(move || store.store.asset_id(&target.key))
+        .await?
+        .map_err(|e| ApiError::internal("Lookup failed.", e))?
+        .ok_or_else(|| ApiError::not_found("Key not found."))
+}
+
+/// DELETE /upload/delete?key=|id=
+pub async fn delete(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Query(target): Query<Target>,
+) -> Result<Json<Value>, ApiError> {
+    authorize(&state, &headers).await?;
+    let id = remove(&state, target).await?;
+    Ok(Json(json!({
+        "status": "success",
+        "action": "deleted",
+        "target": id,
+    })))
+}
+
+/// Deletes the asset of `target`; returns its id.
+pub async fn remove(state: &Shared, target: Target) -> Result<String, ApiError> {
+    let id = resolve(state, target).await?;
+
+    // Like the Go server, an unknown id still reports success.
+    let store = state.clone();
+    let target = id.clone();
+    blocking(move || store.store.delete(&target))
+        .await?
+        .map_err(|e| ApiError::internal("Deletion failed.", e))?;
+    Ok(id)
+}
+
+/// GET /upload/stat?key=|id=
+pub async fn stat(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Query(target): Query<Target>,
+) -> Result<Json<Value>, ApiError> {
+    authorize(&state, &headers).await?;
+    let (asset, url) = asset(&state, target).await?;
+    let mut body =
+        serde_json::to_value(&asset).map_err(|e| ApiError::internal("Lookup failed.", e))?;
+    body["status"] = json!("success");
+    body["url"] = json!(url);
+    Ok(Json(body))
+}
+
+/// The asset of `target`, and its URL.
+pub async fn asset(state: &Shared, target: Target) -> Result<(Asset, String), ApiError> {
+    let id = resolve(state, target).await?;
+
+    let store = state.clone();
+    let asset = blocking(move || store.store.stat(&id))
+        .await?
+        .map_err(|e| ApiError::internal("Lookup failed.", e))?
+        .ok_or_else(|| ApiError::not_found("Asset not found."))?;
+
+    let url_key = asset.keys.first().unwrap_or(&asset.avatar_id);
+    let url = format!("{}/u/{}", state.config.base_url(), url_key);
+    Ok((asset, url))
+}
+
+/// GET /upload/list?prefix=&after=&limit=
+pub async fn list(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<Value>, ApiError> {
+    authorize(&state, &headers).await?;
+    let limit = query.limit.and_then(|l| l.parse::<usize>().ok());
+    let (items, next) = page(&state, query.prefix, query.after, limit).await?;
+    Ok(Json(json!({
+        "status": "success",
+        "items": items,
+        "next": next,
+    })))
+}
+
+/// Up to `limit` (1-1000, else 100) keys after `after`, and the `next` to
+/// pass for the page after them (empty on the last page).
+pub async fn page(
+    state: &Shared,
+    prefix: String,
+    after: String,
+    limit: Option<usize>,
+) -> Result<(Vec<ListItem>, String), ApiError> {
+    let limit = limit.filter(|l| (1..=1000).contains(l)).unwrap_or(100);
+
+    let store = state.clone();
+    let items = blocking(move || store.store.list(&prefix, &after, limit))
+        .await?
+        .map_err(|e| ApiError::internal("Listing failed.", e))?;
+
+    let next = match items.last() {
+        Some(last) if items.len() == limit => last.key.clone(),
+        _ => String::new(),
+    };
+    Ok((items, next))
+}
+
+/// An image to serve, with its `Cache-Control`.
+pub struct Image {
+    pub data: Vec<u8>,
+    pub mime: &'static str,
+    pub cache: String,
+}
+
+/// GET /u/{key}: the stored image, or a generated one for unknown keys.
+pub async fn user_avatar(
+    State(state): State<Shared>,
+    Path(key): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    Ok(serve(&headers, avatar(&state, key, query).await?))
+}
+
+/// The image of `key`: stored, else generated.
+pub async fn avatar(
+    state: &Shared,
+    key: String,
+    query: HashMap<String, String>,
+) -> Result<Image, ApiError> {
+    // Private keys answer only signed links, generated fallback included,
+    // so that a refusal says nothing about whether the key exists.
+    let cache = if state.config.security.is_private(&key) {
+        let expires = state
+            .signer
+            .verify(&key, &query, octa_sign::now())
+            .map_err(|e| {
+                ApiError::new(
+                    StatusCode::FORBIDDEN,
+                    error::AUTH_INVALID,
+                    format!("Invalid link: {}.", e),
+                )
+            })?;
+        // Shared caches must not outlive the link.
+        let left = (expires - octa_sign::now()).clamp(0, 86400);
+        format!("private, max-age={}", left)
+    } else {
+        PUBLIC_CACHE.to_string()
+    };
+
+    let store = state.clone();
+    let lookup = key.clone();
+    let stored = blocking(move || store.store.image(&lookup))
+        .await?
+        .map_err(|e| ApiError::internal("Lookup failed.", e))?;
+
+    match stored {
+        Some((data, format)) => Ok(Image {
+            data,
+            mime: mime(&format),
+            cache,
+        }),
+        // The Go server seeds this fallback with its cache key
+        // (`gen:<key>?...`); the key itself is what it means to use.
+        None => generated(state, key, query, cache).await,
+    }
+}
+
+/// GET /avatar/{seed}
+pub async fn direct_avatar(
+    State(state): State<Shared>,
+    Path(seed): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    if seed.is_empty() {
+        return Err(ApiError::bad_request(
+            error::REQUEST_MISSING_KEY,
+            "Avatar seed key is missing.",
+        ));
+    }
+    let image = generated(&state, seed, query, PUBLIC_CACHE.to_string()).await?;
+    Ok(serve(&headers, image))
+}
+
+pub async fn generated(
+    state: &Shared,
+    seed: String,
+    query: HashMap<String, String>,
+    cache: String,
+) -> Result<Image, ApiError> {
+    let default_size = state.config.image.default_size;
+    let avatar = blocking(move || octa_identicon::generate(&seed, &query, default_size))
+        .await?
+        .map_err(|e| {
+            tracing::warn!(tag = "HTTP", error = %e, "Avatar generation failed");
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                error::IMAGE_GENERATION_FAILED,
+                "Failed to generate avatar image.",
+            )
+        })?;
+    Ok(Image {
+        data: avatar.data,
+        mime: avatar.mime,
+        cache,
+    })
+}
+
+/// GET /health: liveness plus what is stored.
+pub async fn health(State(state): State<Shared>) -> Result<Json<Value>, ApiError> {
+    let (assets, bytes) = totals(&state).await?;
+    Ok(Json(json!({
+        "status": "ok",
+        "assets": assets,
+        "bytes": bytes,
+        "uptime_seconds": state.started.elapsed().as_secs(),
+    })))
+}
+
+/// Assets stored, and their bytes.
+pub async fn totals(state: &Shared) -> Result<(i64, i64), ApiError> {
+    let store = state.clone();
+    blocking(move || store.store.totals())
+        .await?
+        .map_err(|e| ApiError::internal("Database unavailable.", e))
+}
+
+/// `Cache-Control` of everything but signed links: a day, anywhere.
+pub const PUBLIC_CACHE: &str = "public, max-age=86400";
+
+/// `serveWithETag`: the image's `Cache-Control` (a day of public caching,
+/// unless the link is signed), and 304 when the client has the bytes.
+fn serve(headers: &HeaderMap, image: Image) -> Response {
+    let etag = sha256_hex(&image.data);
+    let fresh = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains(&etag));
+    let cache = [
+        (header::CONTENT_TYPE, image.mime.to_string()),
+        (header::CACHE_CONTROL, image.cache),
+        (header::ETAG, format!("\"{}\"", etag)),
+    ];
+    if fresh {
+        return (StatusCode::NOT_MODIFIED, cache).into_response();
+    }
+    (cache, image.data).into_response()
+}
+
+/// Content type for a stored `format`. The Go server labels every stored
+/// image `image/png`.
+fn mime(format: &str) -> &'static str {
+    match format {
+        "jpeg" | "jpg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        _ => "image/png",
+    }
+}
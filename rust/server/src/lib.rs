@@ -0,0 +1,162 @@
+use axum::extract::{DefaultBodyLimit, Request};
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::routing::{delete, get, post};
+use axum::Router;
+use clap::Parser;
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, error, info, Level};
+
+mod config;
+mod error;
+mod grpc;
+mod handlers;
+
+use handlers::AppState;
+
+/*
+OCTA-SERVER: Rust reference implementation of the Octa HTTP API
+=============================================
+Mission: Serve the same routes, JSON and database as the Go server, so the
+         two can be benchmarked against each other on one schema.
+Scope:   Upload, read, stat, list, delete, generated avatars and /health,
+         plus signed links to private keys, upload secrets issued by
+         octa-keys and the same calls over gRPC (rust/grpc/octa.proto, on
+         the same port), which the Go server lacks.
+         No in-memory cache, rate limiting, CORS, console UI or GitHub
+         avatars.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Rust implementation of the Octa avatar server"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+
+    /// Minimum level to log; `debug` adds one line per request
+    #[arg(long, default_value_t = Level::INFO)]
+    log_level: Level,
+}
+
+/// The octa-server command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa server` the arguments it was given.
+#[tokio::main]
+pub async fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, args.log_level, false, false);
+
+    let config = match config::load(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let store = match octa_store::Store::open(&config.database.path) {
+        Ok(store) => store,
+        Err(e) => {
+            error!(tag = "FATAL", path = %config.database.path, reason = %e, "Could not open database");
+            return Kind::Io.exit_code();
+        }
+    };
+
+    let port = config.server.port;
+    let body_limit = config.image.max_upload_bytes();
+    let base_url = config.base_url();
+    let signer = octa_sign::Signer::new(&config.security.signing_secret);
+    let state = Arc::new(AppState {
+        signer,
+        config,
+        store,
+        started: Instant::now(),
+    });
+
+    let app = Router::new()
+        .route("/avatar/{seed}", get(handlers::direct_avatar))
+        .route("/u/{*key}", get(handlers::user_avatar))
+        .route(
+            "/upload",
+            post(handlers::upload).layer(DefaultBodyLimit::max(body_limit)),
+        )
+        .route("/upload/delete", delete(handlers::delete))
+        .route("/upload/stat", get(handlers::stat))
+        .route("/upload/list", get(handlers::list))
+        .route("/health", get(handlers::health))
+        .route(
+            &octa_grpc::path("{method}"),
+            post(grpc::handle).layer(DefaultBodyLimit::max(body_limit)),
+        )
+        .layer(middleware::from_fn(log_request))
+        .with_state(state);
+
+    let listener = match tokio::net::TcpListener::bind(("0.0.0.0", port)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(tag = "FATAL", port, reason = %e, "Could not bind");
+            return Kind::Unavailable.exit_code();
+        }
+    };
+    info!(tag = "SERVER", port, url = %base_url, "Octa server listening");
+
+    if let Err(e) = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown())
+        .await
+    {
+        error!(tag = "FATAL", reason = %e, "Server stopped");
+        return Kind::Unavailable.exit_code();
+    }
+    info!(tag = "SERVER", "Shut down");
+    ExitCode::SUCCESS
+}
+
+async fn log_request(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    debug!(
+        tag = "HTTP",
+        method = %method,
+        path = %path,
+        status = response.status().as_u16(),
+        ms = started.elapsed().as_millis() as u64,
+        "Request"
+    );
+    response
+}
+
+/// Resolves on Ctrl-C or SIGTERM; in-flight requests finish first.
+async fn shutdown() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
@@ -0,0 +1,348 @@
+use crate::Signer;
+use clap::{Parser, Subcommand};
+use octa_config::{ConfigError, SecurityConfig, ServerConfig, Validate};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use octa_warden_core::schedule::parse_interval;
+use serde::Deserialize;
+use std::path::Path;
+use std::process::ExitCode;
+use tracing::{error, info, warn, Level};
+
+mod batch;
+
+/*
+OCTA-SIGN: Time-limited links to private avatars
+=============================================
+Mission: Hand out links to keys under security.private_prefixes without a
+         developer: `sign` prints one per key, `batch` one per line of a key
+         list (text, CSV or JSON), `verify` says whether a link works and
+         until when.
+Safety:  Links never outlive signing.max_ttl. The signing secret is read
+         from the config or the environment and never printed; rotating it
+         revokes every link handed out.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Create and check time-limited signed links to Octa avatars"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long, global = true)]
+    config: Option<String>,
+
+    /// Secret links are signed with (default: security.signing_secret)
+    #[arg(long, global = true, env = "OCTA_SIGN_SECRET", hide_env_values = true)]
+    secret: Option<String>,
+
+    /// Log format
+    #[arg(
+        long,
+        env = "OCTA_LOG_FORMAT",
+        value_enum,
+        global = true,
+        default_value_t = LogFormat::Pretty
+    )]
+    log_format: LogFormat,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Parser, Debug)]
+struct Link {
+    /// Public root URL the links start with (overrides base_url)
+    #[arg(long, env = "OCTA_SIGN_URL")]
+    url: Option<String>,
+
+    /// How long the links work, e.g. 30m, 12h or 7d (overrides signing.ttl)
+    #[arg(long, conflicts_with = "expires")]
+    ttl: Option<String>,
+
+    /// When the links stop working: RFC 3339 or Unix seconds
+    #[arg(long)]
+    expires: Option<String>,
+
+    /// Image parameter to add to the links, e.g. size=128 (not signed); repeatable
+    #[arg(long = "param", value_name = "KEY=VALUE", value_parser = key_value)]
+    params: Vec<(String, String)>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print a signed link for each key
+    Sign {
+        /// Keys to link to
+        #[arg(required = true)]
+        keys: Vec<String>,
+
+        #[command(flatten)]
+        link: Link,
+    },
+    /// Print a signed link for every key of a list
+    Batch {
+        /// File with one key per line (`-` for stdin)
+        keys: String,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = batch::Format::Text)]
+        format: batch::Format,
+
+        #[command(flatten)]
+        link: Link,
+    },
+    /// Check signed links: exit 0 when all of them work, 1 otherwise
+    Verify {
+        /// Links to check
+        #[arg(required = true)]
+        urls: Vec<String>,
+    },
+}
+
+fn key_value(raw: &str) -> Result<(String, String), String> {
+    raw.split_once('=')
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .ok_or_else(|| format!("'{}' is not KEY=VALUE", raw))
+}
+
+/// The parts of config.yaml octa-sign reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    server: ServerConfig,
+    security: SecurityConfig,
+    base_url: Option<String>,
+    signing: SigningConfig,
+}
+
+/// `signing:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SigningConfig {
+    /// Lifetime of a link without --ttl or --expires.
+    ttl: String,
+    /// Longest lifetime a link may be given.
+    max_ttl: String,
+}
+
+impl Default for SigningConfig {
+    fn default() -> Self {
+        Self {
+            ttl: "1h".to_string(),
+            max_ttl: "7d".to_string(),
+        }
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = self.server.validate();
+        problems.extend(octa_config::check_url("base_url", self.base_url.as_deref()));
+        let ttl = parse_interval(&self.signing.ttl);
+        let max_ttl = parse_interval(&self.signing.max_ttl);
+        if let Err(e) = &ttl {
+            problems.push(("signing.ttl".to_string(), e.clone()));
+        }
+        if let Err(e) = &max_ttl {
+            problems.push(("signing.max_ttl".to_string(), e.clone()));
+        }
+        if let (Ok(ttl), Ok(max_ttl)) = (ttl, max_ttl) {
+            if ttl > max_ttl {
+                problems.push((
+                    "signing.ttl".to_string(),
+                    format!("is longer than signing.max_ttl ({})", self.signing.max_ttl),
+                ));
+            }
+        }
+        problems
+    }
+}
+
+/// Without a file, the environment and arguments alone configure it.
+fn load_config(path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    match octa_config::discover(path.map(Path::new)) {
+        Some(path) => octa_config::load(&path),
+        None => {
+            let config: FileConfig = octa_config::from_env()?;
+            octa_config::check(Path::new("<environment>"), config)
+        }
+    }
+}
+
+impl Link {
+    /// The Unix second the links expire at: --expires, else now plus
+    /// --ttl or signing.ttl, and never later than signing.max_ttl from now.
+    fn expires(&self, config: &SigningConfig, now: i64) -> Result<i64, String> {
+        let expires = match &self.expires {
+            Some(raw) => parse_time(raw)?,
+            None => {
+                let ttl = self.ttl.as_deref().unwrap_or(&config.ttl);
+                let ttl = parse_interval(ttl).map_err(|e| format!("--ttl: {}", e))?;
+                now + ttl.as_secs() as i64
+            }
+        };
+        if expires <= now {
+            return Err(format!("--expires: {} is in the past", timestamp(expires)));
+        }
+        let max_ttl = parse_interval(&config.max_ttl).unwrap_or_default();
+        if expires - now > max_ttl.as_secs() as i64 {
+            return Err(format!(
+                "links may work for at most {} (signing.max_ttl)",
+                config.max_ttl
+            ));
+        }
+        Ok(expires)
+    }
+}
+
+fn parse_time(raw: &str) -> Result<i64, String> {
+    if let Ok(secs) = raw.parse::<i64>() {
+        return Ok(secs);
+    }
+    chrono::DateTime::parse_from_rfc3339(raw)
+        .map(|t| t.timestamp())
+        .map_err(|_| format!("--expires: '{}' is neither RFC 3339 nor Unix seconds", raw))
+}
+
+/// `2026-10-15T12:00:00Z`
+pub(crate) fn timestamp(secs: i64) -> String {
+    chrono::DateTime::from_timestamp(secs, 0)
+        .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        .unwrap_or_else(|| secs.to_string())
+}
+
+/// The octa-sign command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa sign` the arguments it was given.
+pub fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    let stdout_taken = !matches!(args.command, Command::Verify { .. });
+    octa_logging::init(args.log_format, Level::INFO, false, stdout_taken);
+
+    let config = match load_config(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let secret = args
+        .secret
+        .as_deref()
+        .unwrap_or(&config.security.signing_secret);
+    if secret.trim().is_empty() {
+        error!(
+            tag = "FATAL",
+            "No signing secret (set security.signing_secret or pass --secret)"
+        );
+        return Kind::Config.exit_code();
+    }
+    let signer = Signer::new(secret);
+
+    let (keys, format, link) = match args.command {
+        Command::Verify { urls } => return verify(&signer, &urls),
+        Command::Sign { keys, link } => {
+            let mut parsed = Vec::new();
+            for raw in &keys {
+                match octa_key::parse(raw) {
+                    Ok(key) => parsed.push(key),
+                    Err(e) => {
+                        error!(tag = "FATAL", key = %raw, reason = %e, "Invalid key");
+                        return Kind::Usage.exit_code();
+                    }
+                }
+            }
+            (parsed, batch::Format::Text, link)
+        }
+        Command::Batch { keys, format, link } => match batch::read_keys(&keys) {
+            Ok(keys) => (keys, format, link),
+            Err(e) => {
+                error!(tag = "FATAL", reason = %e, "Invalid key list");
+                return e.exit_code();
+            }
+        },
+    };
+
+    if let Some((field, problem)) = octa_config::check_url("--url", link.url.as_deref()) {
+        error!(tag = "FATAL", reason = %format!("{}: {}", field, problem), "Invalid arguments");
+        return Kind::Usage.exit_code();
+    }
+    let expires = match link.expires(&config.signing, crate::now()) {
+        Ok(expires) => expires,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid arguments");
+            return Kind::Usage.exit_code();
+        }
+    };
+    let base_url = octa_config::base_url(
+        link.url.as_deref().or(config.base_url.as_deref()),
+        &config.server,
+    );
+
+    let public = if config.security.private_prefixes.is_empty() {
+        0
+    } else {
+        keys.iter()
+            .filter(|key| !config.security.is_private(key))
+            .count()
+    };
+    if public > 0 {
+        warn!(
+            tag = "WARN",
+            keys = public,
+            "Not under security.private_prefixes; the server serves these unsigned"
+        );
+    }
+
+    let links: Vec<batch::Signed> = keys
+        .into_iter()
+        .map(|key| batch::Signed {
+            url: signer.url(&base_url, &key, expires, &link.params),
+            key,
+            expires: timestamp(expires),
+        })
+        .collect();
+    if let Err(e) = batch::write(&links, format) {
+        error!(tag = "FATAL", reason = %e, "Could not write the links");
+        return Kind::Io.exit_code();
+    }
+    info!(
+        tag = "OK",
+        links = links.len(),
+        expires = %timestamp(expires),
+        "Signed"
+    );
+    ExitCode::SUCCESS
+}
+
+fn verify(signer: &Signer, urls: &[String]) -> ExitCode {
+    let now = crate::now();
+    let mut failed = 0;
+    for url in urls {
+        let Some(key) = crate::key(url) else {
+            failed += 1;
+            warn!(tag = "FAIL", url = %url, reason = "no /u/<key> in the path", "Invalid");
+            continue;
+        };
+        match signer.verify(&key, &crate::query(url), now) {
+            Ok(expires) => info!(
+                tag = "OK",
+                key = %key,
+                expires = %timestamp(expires),
+                left_secs = expires - now,
+                "Valid"
+            ),
+            Err(e) => {
+                failed += 1;
+                warn!(tag = "FAIL", key = %key, reason = %e, "Invalid");
+            }
+        }
+    }
+    if failed > 0 {
+        Kind::Data.exit_code()
+    } else {
+        ExitCode::SUCCESS
+    }
+}
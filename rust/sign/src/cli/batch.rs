@@ -0,0 +1,83 @@
+use clap::ValueEnum;
+use octa_errors::{Error, Kind};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io::{Read, Write};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Format {
+    /// One link per line
+    Text,
+    /// `key,url,expires` with a header
+    Csv,
+    /// An array of `{"key", "url", "expires"}`
+    Json,
+}
+
+/// One link handed out.
+#[derive(Serialize)]
+pub struct Signed {
+    pub key: String,
+    pub url: String,
+    /// RFC 3339.
+    pub expires: String,
+}
+
+/// One key per line; blank lines and `#` comments are skipped, repeated
+/// keys are read once, and an invalid key refuses the whole list.
+pub fn read_keys(path: &str) -> Result<Vec<String>, Error> {
+    let mut text = String::new();
+    let read = if path == "-" {
+        std::io::stdin().read_to_string(&mut text).map(|_| ())
+    } else {
+        std::fs::read_to_string(path).map(|t| text = t)
+    };
+    read.map_err(|e| {
+        let kind = match e.kind() {
+            std::io::ErrorKind::NotFound => Kind::NoInput,
+            _ => Kind::Io,
+        };
+        Error::new(kind, format!("could not read {}: {}", path, e))
+    })?;
+
+    let mut keys = Vec::new();
+    let mut seen = HashSet::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let key = octa_key::parse(line).map_err(|e| {
+            let message = format!("{}:{}: invalid key '{}': {}", path, n + 1, line, e);
+            Error::new(Kind::Data, message)
+        })?;
+        if seen.insert(key.clone()) {
+            keys.push(key);
+        }
+    }
+    Ok(keys)
+}
+
+/// Writes `links` to stdout, in the order of the list.
+pub fn write(links: &[Signed], format: Format) -> std::io::Result<()> {
+    let mut out = std::io::stdout().lock();
+    match format {
+        Format::Text => {
+            for link in links {
+                writeln!(out, "{}", link.url)?;
+            }
+        }
+        // Keys and encoded URLs hold no commas or quotes.
+        Format::Csv => {
+            writeln!(out, "key,url,expires")?;
+            for link in links {
+                writeln!(out, "{},{},{}", link.key, link.url, link.expires)?;
+            }
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut out, links)?;
+            writeln!(out)?;
+        }
+    }
+    out.flush()
+}
@@ -0,0 +1,244 @@
+//! octa-sign: time-limited links to avatars under a private prefix.
+//!
+//! A signed link is the ordinary `/u/<key>` URL plus two query parameters:
+//! `expires`, the Unix second after which it stops working, and `sig`, the
+//! hex HMAC-SHA256 of `/u/<key>\n<expires>` under `security.signing_secret`.
+//! Only the key and the expiry are signed, so `size`, `format` and the other
+//! image parameters can still be changed on a link that was handed out, and
+//! the link works behind any base URL (a CDN, a path prefix).
+//!
+//! ```
+//! use octa_sign::{Invalid, Signer};
+//!
+//! let signer = Signer::new("s3cret");
+//! let url = signer.url("https://avatars.example.com", "team/alice", 1_900_000_000, &[]);
+//! assert!(url.starts_with("https://avatars.example.com/u/team/alice?expires=1900000000&sig="));
+//!
+//! let query = octa_sign::query(&url);
+//! assert_eq!(signer.verify("team/alice", &query, 1_800_000_000), Ok(1_900_000_000));
+//! assert_eq!(signer.verify("team/bob", &query, 1_800_000_000), Err(Invalid::Mismatch));
+//! assert_eq!(
+//!     signer.verify("team/alice", &query, 1_900_000_001),
+//!     Err(Invalid::Expired { at: 1_900_000_000 })
+//! );
+//! ```
+
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "cli")]
+mod cli;
+#[cfg(feature = "cli")]
+pub use cli::cli;
+
+/// Query parameter with the expiry, in Unix seconds.
+pub const EXPIRES: &str = "expires";
+/// Query parameter with the signature, lowercase hex.
+pub const SIGNATURE: &str = "sig";
+
+/// Why a link is refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Invalid {
+    /// No `expires` or no `sig`.
+    Missing,
+    /// `expires` is not a number or `sig` is not hex.
+    Malformed,
+    /// Signed for another key, or with another secret.
+    Mismatch,
+    /// Correctly signed, but past its expiry.
+    Expired { at: i64 },
+}
+
+impl fmt::Display for Invalid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Invalid::Missing => write!(f, "not signed (no {} or {})", EXPIRES, SIGNATURE),
+            Invalid::Malformed => write!(f, "malformed {} or {}", EXPIRES, SIGNATURE),
+            Invalid::Mismatch => write!(f, "signature does not match"),
+            Invalid::Expired { at } => write!(f, "expired at {}", at),
+        }
+    }
+}
+
+impl std::error::Error for Invalid {}
+
+/// Signs and checks links with one secret.
+#[derive(Clone)]
+pub struct Signer {
+    secret: Vec<u8>,
+}
+
+impl fmt::Debug for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Signer").finish_non_exhaustive()
+    }
+}
+
+impl Signer {
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            secret: secret.as_ref().to_vec(),
+        }
+    }
+
+    /// The `sig` of `key` until `expires`.
+    pub fn signature(&self, key: &str, expires: i64) -> String {
+        self.mac(key, expires)
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// `<base_url>/u/<key>?<params>&expires=...&sig=...`; `params` are
+    /// appended as given, unsigned.
+    pub fn url(
+        &self,
+        base_url: &str,
+        key: &str,
+        expires: i64,
+        params: &[(String, String)],
+    ) -> String {
+        let mut url = format!("{}{}?", base_url.trim_end_matches('/'), path(key));
+        for (name, value) in params {
+            url.push_str(&format!("{}={}&", encode(name), encode(value)));
+        }
+        url.push_str(&format!(
+            "{}={}&{}={}",
+            EXPIRES,
+            expires,
+            SIGNATURE,
+            self.signature(key, expires)
+        ));
+        url
+    }
+
+    /// The expiry of a valid link to `key` with `query`, at Unix second
+    /// `now`. The signature is compared in constant time.
+    pub fn verify(
+        &self,
+        key: &str,
+        query: &HashMap<String, String>,
+        now: i64,
+    ) -> Result<i64, Invalid> {
+        let (expires, sig) = match (query.get(EXPIRES), query.get(SIGNATURE)) {
+            (Some(expires), Some(sig)) => (expires, sig),
+            _ => return Err(Invalid::Missing),
+        };
+        let expires: i64 = expires.parse().map_err(|_| Invalid::Malformed)?;
+        let sig = decode_hex(sig).ok_or(Invalid::Malformed)?;
+        self.mac(key, expires)
+            .verify_slice(&sig)
+            .map_err(|_| Invalid::Mismatch)?;
+        if now > expires {
+            return Err(Invalid::Expired { at: expires });
+        }
+        Ok(expires)
+    }
+
+    fn mac(&self, key: &str, expires: i64) -> Hmac<Sha256> {
+        let mut mac = <Hmac<Sha256> as KeyInit>::new_from_slice(&self.secret)
+            .expect("HMAC takes keys of any length");
+        mac.update(format!("{}\n{}", path(key), expires).as_bytes());
+        mac
+    }
+}
+
+/// The path a link to `key` points at, and the first line of what is signed.
+pub fn path(key: &str) -> String {
+    format!("/u/{}", key)
+}
+
+/// The query parameters of `url`, percent-decoded; the last of repeated
+/// names wins.
+pub fn query(url: &str) -> HashMap<String, String> {
+    let query = url.split_once('?').map(|(_, q)| q).unwrap_or("");
+    let query = query.split('#').next().unwrap_or("");
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(name), decode(value))
+        })
+        .collect()
+}
+
+/// The key a link points at: what follows the first `/u/` of its path,
+/// percent-decoded.
+pub fn key(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next().unwrap_or("");
+    let path = match path.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|i| &rest[i..]).unwrap_or(""),
+        None => path,
+    };
+    let key = &path[path.find("/u/")? + 3..];
+    (!key.is_empty()).then(|| decode(key))
+}
+
+/// The current Unix second.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+fn encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~' | b'/' | b'@') {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len()
+                && bytes[i + 1].is_ascii_hexdigit()
+                && bytes[i + 2].is_ascii_hexdigit() =>
+            {
+                out.push(hex_value(bytes[i + 1]) << 4 | hex_value(bytes[i + 2]));
+                i += 3;
+                continue;
+            }
+            b'+' => out.push(b' '),
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    let bytes = value.as_bytes();
+    if !bytes.len().is_multiple_of(2) || !bytes.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    Some(
+        bytes
+            .chunks(2)
+            .map(|pair| hex_value(pair[0]) << 4 | hex_value(pair[1]))
+            .collect(),
+    )
+}
+
+fn hex_value(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => digit - b'A' + 10,
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{Backend, Error, Stat};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use tracing::warn;
+
+/// Image files in a directory tree. The asset ID is the path relative to
+/// the root, with `/` as the separator. Hidden entries (dotfiles) and
+/// symlinks are not assets.
+pub struct Fs {
+    root: PathBuf,
+}
+
+impl Fs {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The file of `id`; IDs that would leave the root are refused.
+    pub fn path(&self, id: &str) -> Result<PathBuf, Error> {
+        let relative = Path::new(id);
+        if id.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(Error::Invalid(format!("'{}' is not a path inside the root", id)));
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+impl Backend for Fs {
+    fn describe(&self) -> String {
+        self.root.display().to_string()
+    }
+
+    fn list(&self) -> Result<Vec<String>, Error> {
+        Ok(walk(&self.root).into_iter().map(|(id, _)| id).collect())
+    }
+
+    fn read(&self, id: &str) -> Result<Option<Vec<u8>>, Error> {
+        match fs::read(self.path(id)?) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Written to a hidden file next to it, fsynced and renamed into place,
+    /// so a crash never leaves a truncated asset where a listing finds it.
+    fn write(&self, id: &str, data: &[u8]) -> Result<(), Error> {
+        let path = self.path(id)?;
+        let dir = path.parent().unwrap_or(&self.root);
+        fs::create_dir_all(dir)?;
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let partial = dir.join(format!(".{}.partial", name));
+        {
+            let mut file = File::create(&partial)?;
+            file.write_all(data)?;
+            file.sync_all()?;
+        }
+        fs::rename(&partial, &path)?;
+        Ok(())
+    }
+
+    fn delete(&self, id: &str) -> Result<(), Error> {
+        match fs::remove_file(self.path(id)?) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn stat(&self, id: &str) -> Result<Option<Stat>, Error> {
+        match fs::metadata(self.path(id)?) {
+            Ok(meta) if meta.is_file() => Ok(Some(Stat { size: meta.len() })),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Lists every regular file under `root` as `(asset id, path)`, sorted by ID
+/// so that runs are comparable. IDs always use `/` as the separator.
+pub fn walk(root: &Path) -> Vec<(String, PathBuf)> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!(tag = "WARN", path = %dir.display(), reason = %e, "Could not read directory");
+                continue;
+            }
+        };
+
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => pending.push(path),
+                Ok(t) if t.is_file() => {
+                    if let Some(id) = asset_id(root, &path) {
+                        files.push((id, path));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    files
+}
+
+fn asset_id(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let parts: Vec<String> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    Some(parts.join("/"))
+}
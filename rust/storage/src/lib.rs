@@ -0,0 +1,218 @@
+//! Where Octa's assets can live, behind one [`Backend`] trait: the SQLite
+//! database ([`Sqlite`]), a directory tree ([`Fs`]) and an S3-compatible
+//! bucket ([`S3`]). Each lists, reads, writes, deletes and stats assets by
+//! ID, and [`Backend::stream`] reads many of them, in parallel where the
+//! backend allows it. Tools written against the trait (octa-warden's file
+//! and bucket audits, `import` and `migrate`, octa-seed) take a new backend
+//! without changes of their own.
+//!
+//! ```no_run
+//! use octa_storage::{Backend, Location};
+//! use std::ops::ControlFlow;
+//!
+//! let store = Location::parse("s3:octa-assets/avatars/").unwrap().open(None).unwrap();
+//! let ids = store.list().unwrap();
+//! store.stream(&ids, &mut |fetched| {
+//!     if let Ok(Some(data)) = fetched.data {
+//!         println!("{} {} bytes", fetched.id, data.len());
+//!     }
+//!     ControlFlow::Continue(())
+//! });
+//! ```
+//!
+//! IDs are the backend's own: the `images.id` of a row, the path of a file
+//! relative to the root, the key of an object without the prefix. Names
+//! with a segment starting with `.` are bookkeeping (`.warden/keys.tsv`)
+//! and never listed by the file and object backends.
+
+pub mod fs;
+mod location;
+pub mod s3;
+pub mod sigv4;
+pub mod sqlite;
+
+pub use fs::Fs;
+pub use location::Location;
+pub use s3::S3;
+pub use sqlite::{Added, NewAsset, Sqlite};
+
+use octa_errors::Kind;
+use std::fmt;
+use std::io;
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Why a [`Backend`] call failed.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Sqlite(rusqlite::Error),
+    /// A request to the object store failed, after its retries.
+    Remote(String),
+    /// The backend cannot be set up: a malformed endpoint, missing
+    /// credentials, a database without Octa's tables.
+    Config(String),
+    /// The data or ID is not something the backend can store.
+    Invalid(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => e.fmt(f),
+            Error::Sqlite(e) => e.fmt(f),
+            Error::Remote(e) | Error::Config(e) | Error::Invalid(e) => f.write_str(e),
+        }
+    }
+}
+
+impl Error {
+    /// What a tool failing on it exits with: an unreachable bucket is
+    /// [`Kind::Unavailable`], a backend that cannot be set up [`Kind::Config`].
+    pub fn kind(&self) -> Kind {
+        match self {
+            Error::Io(e) if e.kind() == io::ErrorKind::NotFound => Kind::NoInput,
+            Error::Io(_) | Error::Sqlite(_) => Kind::Io,
+            Error::Remote(_) => Kind::Unavailable,
+            Error::Config(_) => Kind::Config,
+            Error::Invalid(_) => Kind::Data,
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Sqlite(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Sqlite(e)
+    }
+}
+
+/// What [`Backend::stat`] knows without reading the data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stat {
+    /// Stored bytes.
+    pub size: u64,
+}
+
+/// One asset of [`Backend::stream`].
+pub struct Fetched<'a> {
+    pub id: &'a str,
+    /// `None` when it was deleted after it was listed.
+    pub data: Result<Option<Vec<u8>>, Error>,
+    /// How long the caller waited for it: the read itself, or what was
+    /// left of it when reads run ahead on other threads.
+    pub waited: Duration,
+}
+
+/// A place assets are stored. Implementations are shared across threads;
+/// every method takes `&self`.
+pub trait Backend: Send + Sync {
+    /// Where the assets are, for logs and reports: a path, `s3://bucket/prefix`.
+    fn describe(&self) -> String;
+
+    /// Every asset ID, in a stable order so that runs are comparable.
+    fn list(&self) -> Result<Vec<String>, Error>;
+
+    /// The stored bytes; `None` when there is no such asset.
+    fn read(&self, id: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Stores `data` as `id`, replacing what was there.
+    fn write(&self, id: &str, data: &[u8]) -> Result<(), Error>;
+
+    /// Deleting a missing asset succeeds.
+    fn delete(&self, id: &str) -> Result<(), Error>;
+
+    /// `None` when there is no such asset.
+    fn stat(&self, id: &str) -> Result<Option<Stat>, Error>;
+
+    /// Reads [`stream`](Backend::stream) runs at once. One reads in order,
+    /// on the calling thread.
+    fn concurrency(&self) -> usize {
+        1
+    }
+
+    /// Writes every item, with one result per item. SQLite commits them in
+    /// one transaction, and fails as a whole when that does; the others
+    /// write one after another.
+    fn write_batch(&self, items: &[(String, Vec<u8>)]) -> Result<Vec<Result<(), Error>>, Error> {
+        Ok(items.iter().map(|(id, data)| self.write(id, data)).collect())
+    }
+
+    /// Reads `ids` and hands each to `each`, until it breaks. With a
+    /// [`concurrency`](Backend::concurrency) above one, reads run ahead on
+    /// worker threads and arrive in the order they finish.
+    fn stream(&self, ids: &[String], each: &mut dyn FnMut(Fetched<'_>) -> ControlFlow<()>) {
+        let workers = self.concurrency().max(1);
+        if workers == 1 {
+            for id in ids {
+                let started = Instant::now();
+                let data = self.read(id);
+                let waited = started.elapsed();
+                if each(Fetched { id, data, waited }).is_break() {
+                    return;
+                }
+            }
+            return;
+        }
+
+        let next = AtomicUsize::new(0);
+        let stop = AtomicBool::new(false);
+        // Bounded, so a slow consumer holds back reads instead of buffering the store.
+        let (tx, rx) = mpsc::sync_channel(workers * 2);
+
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                let tx = tx.clone();
+                let (next, stop) = (&next, &stop);
+                scope.spawn(move || {
+                    while !stop.load(Ordering::Relaxed) {
+                        let Some(id) = ids.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                            break;
+                        };
+                        if tx.send((id, self.read(id))).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(tx);
+
+            loop {
+                let started = Instant::now();
+                let Ok((id, data)) = rx.recv() else {
+                    break;
+                };
+                let waited = started.elapsed();
+                if each(Fetched { id, data, waited }).is_break() {
+                    stop.store(true, Ordering::Relaxed);
+                    break;
+                }
+            }
+            // Unblocks workers waiting to send, so the scope can join them.
+            drop(rx);
+        });
+    }
+}
+
+/// Whether a listed name is bookkeeping rather than an asset.
+fn hidden(id: &str) -> bool {
+    id.split('/').any(|segment| segment.starts_with('.'))
+}
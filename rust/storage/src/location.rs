@@ -0,0 +1,54 @@
+use crate::s3::S3Config;
+use crate::{Backend, Error, Fs, S3};
+use std::path::PathBuf;
+
+/// A file or object store named on the command line: `fs:<dir>` or
+/// `s3:<bucket>[/<prefix>]`. SQLite databases are opened as [`Sqlite`](crate::Sqlite).
+#[derive(Debug, Clone)]
+pub enum Location {
+    /// Every file below the directory.
+    Fs(PathBuf),
+    /// Every object below the prefix.
+    S3 { bucket: String, prefix: String },
+}
+
+impl Location {
+    /// Parses `fs:/srv/dump` or `s3:octa-assets/avatars/`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.split_once(':') {
+            Some(("fs", dir)) if !dir.is_empty() => Ok(Location::Fs(PathBuf::from(dir))),
+            Some(("s3", rest)) if !rest.is_empty() => {
+                let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+                Ok(Location::S3 {
+                    bucket: bucket.to_string(),
+                    prefix: prefix.to_string(),
+                })
+            }
+            _ => Err(format!(
+                "unsupported location '{}' (expected fs:<dir> or s3:<bucket>[/<prefix>])",
+                value
+            )),
+        }
+    }
+
+    /// The directory, or `s3://bucket/prefix`.
+    pub fn describe(&self) -> String {
+        match self {
+            Location::Fs(root) => root.display().to_string(),
+            Location::S3 { bucket, prefix } => format!("s3://{}/{}", bucket, prefix),
+        }
+    }
+
+    /// A bucket takes its endpoint and credentials from `base`, the S3
+    /// storage a tool is configured with, or else from the environment
+    /// (see [`S3Config::for_bucket`]).
+    pub fn open(&self, base: Option<&S3Config>) -> Result<Box<dyn Backend>, Error> {
+        match self {
+            Location::Fs(root) => Ok(Box::new(Fs::new(root))),
+            Location::S3 { bucket, prefix } => {
+                let cfg = S3Config::for_bucket(base, bucket, prefix).map_err(Error::Config)?;
+                Ok(Box::new(S3::new(&cfg)?))
+            }
+        }
+    }
+}
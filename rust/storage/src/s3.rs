@@ -0,0 +1,471 @@
+use crate::sigv4::{self, encode_path, hex};
+use crate::{hidden, Backend, Error, Stat};
+use chrono::Utc;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+use ureq::http::Response;
+use ureq::Body;
+
+/// S3-compatible object storage (AWS, MinIO, R2, ...).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct S3Config {
+    /// e.g. "https://s3.eu-central-1.amazonaws.com" or "http://minio:9000".
+    pub endpoint: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    pub bucket: String,
+    /// Only objects under this prefix are assets; it is stripped from the asset ID.
+    #[serde(default)]
+    pub prefix: String,
+    /// Falls back to AWS_ACCESS_KEY_ID / AWS_SECRET_ACCESS_KEY (and AWS_SESSION_TOKEN).
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    /// `endpoint/bucket/key` URLs instead of `bucket.endpoint/key` (MinIO and most self-hosted stores).
+    #[serde(default = "default_path_style")]
+    pub path_style: bool,
+    /// Objects downloaded in parallel.
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    /// Extra attempts for throttled (429), 5xx and network failures.
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_path_style() -> bool {
+    true
+}
+
+fn default_concurrency() -> usize {
+    8
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+impl S3Config {
+    /// Settings for an ad-hoc bucket (e.g. `import --from s3:...`): connection
+    /// details come from `base` (the configured storage) or, without one, from
+    /// AWS_ENDPOINT_URL and AWS_REGION.
+    pub fn for_bucket(base: Option<&S3Config>, bucket: &str, prefix: &str) -> Result<Self, String> {
+        let mut cfg = match base {
+            Some(base) => base.clone(),
+            None => S3Config {
+                endpoint: std::env::var("AWS_ENDPOINT_URL")
+                    .map_err(|_| "no S3 storage configured and AWS_ENDPOINT_URL is not set")?,
+                region: std::env::var("AWS_REGION").unwrap_or_else(|_| default_region()),
+                bucket: String::new(),
+                prefix: String::new(),
+                access_key_id: None,
+                secret_access_key: None,
+                path_style: default_path_style(),
+                concurrency: default_concurrency(),
+                retries: default_retries(),
+            },
+        };
+        cfg.bucket = bucket.to_string();
+        cfg.prefix = prefix.to_string();
+        Ok(cfg)
+    }
+
+    /// `s3://bucket/prefix`, for logs and manifests.
+    pub fn describe(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.prefix)
+    }
+}
+
+/// Objects in an S3-compatible bucket. The asset ID is the key without the
+/// configured prefix; reads are streamed `concurrency` at a time.
+pub struct S3 {
+    client: Client,
+    prefix: String,
+    concurrency: usize,
+    url: String,
+}
+
+impl S3 {
+    pub fn new(cfg: &S3Config) -> Result<Self, Error> {
+        Ok(Self {
+            client: Client::new(cfg).map_err(Error::Config)?,
+            prefix: cfg.prefix.clone(),
+            concurrency: cfg.concurrency,
+            url: cfg.describe(),
+        })
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    fn key(&self, id: &str) -> String {
+        format!("{}{}", self.prefix, id)
+    }
+}
+
+impl Backend for S3 {
+    fn describe(&self) -> String {
+        self.url.clone()
+    }
+
+    /// In key order, as S3 lists them.
+    fn list(&self) -> Result<Vec<String>, Error> {
+        Ok(self
+            .client
+            .list()
+            .map_err(Error::Remote)?
+            .into_iter()
+            .map(|key| key.strip_prefix(&self.prefix).unwrap_or(&key).to_string())
+            .filter(|id| !hidden(id))
+            .collect())
+    }
+
+    fn read(&self, id: &str) -> Result<Option<Vec<u8>>, Error> {
+        self.client.get(&self.key(id)).map_err(Error::Remote)
+    }
+
+    fn write(&self, id: &str, data: &[u8]) -> Result<(), Error> {
+        self.client.put(&self.key(id), data).map_err(Error::Remote)
+    }
+
+    fn delete(&self, id: &str) -> Result<(), Error> {
+        self.client.delete(&self.key(id)).map_err(Error::Remote)
+    }
+
+    fn stat(&self, id: &str) -> Result<Option<Stat>, Error> {
+        let size = self.client.head(&self.key(id)).map_err(Error::Remote)?;
+        Ok(size.map(|size| Stat { size }))
+    }
+
+    fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+}
+
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+pub struct Client {
+    agent: ureq::Agent,
+    cfg: S3Config,
+    scheme: String,
+    host: String,
+    credentials: Credentials,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListBucketResult {
+    #[serde(default)]
+    is_truncated: bool,
+    #[serde(default)]
+    contents: Vec<ListedObject>,
+    next_continuation_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListedObject {
+    key: String,
+}
+
+impl Client {
+    pub fn new(cfg: &S3Config) -> Result<Self, String> {
+        let (scheme, host) = cfg
+            .endpoint
+            .trim_end_matches('/')
+            .split_once("://")
+            .ok_or_else(|| format!("invalid S3 endpoint '{}'", cfg.endpoint))?;
+
+        let env = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let access_key_id = cfg
+            .access_key_id
+            .clone()
+            .or_else(|| env("AWS_ACCESS_KEY_ID"))
+            .ok_or("missing S3 access key (access_key_id or AWS_ACCESS_KEY_ID)")?;
+        let secret_access_key = cfg
+            .secret_access_key
+            .clone()
+            .or_else(|| env("AWS_SECRET_ACCESS_KEY"))
+            .ok_or("missing S3 secret key (secret_access_key or AWS_SECRET_ACCESS_KEY)")?;
+
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(60)))
+            .build()
+            .into();
+
+        Ok(Self {
+            agent,
+            cfg: cfg.clone(),
+            scheme: scheme.to_string(),
+            host: if cfg.path_style {
+                host.to_string()
+            } else {
+                format!("{}.{}", cfg.bucket, host)
+            },
+            credentials: Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: env("AWS_SESSION_TOKEN"),
+            },
+        })
+    }
+
+    /// Every object key under the configured prefix (ListObjectsV2, all pages).
+    pub fn list(&self) -> Result<Vec<String>, String> {
+        self.list_under(&self.cfg.prefix)
+    }
+
+    /// Every object key under `prefix`, whatever the configured one.
+    pub fn list_under(&self, prefix: &str) -> Result<Vec<String>, String> {
+        let mut keys = Vec::new();
+        let mut token: Option<String> = None;
+
+        loop {
+            let mut query = vec![
+                ("list-type", "2".to_string()),
+                ("prefix", prefix.to_string()),
+            ];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token.clone()));
+            }
+
+            let mut response = self
+                .send(Method::Get, "", &query, &[])
+                .map_err(|e| format!("listing failed: {}", e))?;
+            let xml = response
+                .body_mut()
+                .read_to_string()
+                .map_err(|e| format!("listing failed: {}", e))?;
+            let page: ListBucketResult = quick_xml::de::from_str(&xml)
+                .map_err(|e| format!("unexpected listing response: {}", e))?;
+
+            // Zero-byte "folder" markers are not assets.
+            keys.extend(
+                page.contents
+                    .into_iter()
+                    .map(|o| o.key)
+                    .filter(|k| !k.ends_with('/')),
+            );
+
+            match page.next_continuation_token {
+                Some(next) if page.is_truncated => token = Some(next),
+                _ => break,
+            }
+        }
+
+        Ok(keys)
+    }
+
+    /// Downloads one object. `None` when it no longer exists.
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        match self.send(Method::Get, key, &[], &[]) {
+            Ok(mut response) => response
+                .body_mut()
+                .with_config()
+                .limit(u64::MAX)
+                .read_to_vec()
+                .map(Some)
+                .map_err(|e| e.to_string()),
+            Err(ureq::Error::StatusCode(404)) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Size of one object, from its headers. `None` when it does not exist.
+    pub fn head(&self, key: &str) -> Result<Option<u64>, String> {
+        match self.send(Method::Head, key, &[], &[]) {
+            Ok(response) => Ok(Some(
+                response
+                    .headers()
+                    .get("content-length")
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0),
+            )),
+            Err(ureq::Error::StatusCode(404)) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Uploads one object, replacing any under the same key.
+    pub fn put(&self, key: &str, body: &[u8]) -> Result<(), String> {
+        self.send(Method::Put, key, &[], body)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Deletes one object; deleting a missing one succeeds.
+    pub fn delete(&self, key: &str) -> Result<(), String> {
+        match self.send(Method::Delete, key, &[], &[]) {
+            Ok(_) | Err(ureq::Error::StatusCode(404)) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Signed request with retries and exponential backoff for transient failures.
+    fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, String)],
+        body: &[u8],
+    ) -> Result<Response<Body>, ureq::Error> {
+        let mut attempt = 0;
+        loop {
+            match self.send_once(method, key, query, body) {
+                Err(e) if attempt < self.cfg.retries && is_transient(&e) => {
+                    attempt += 1;
+                    let backoff = Duration::from_millis(200 * 2u64.pow(attempt));
+                    warn!(tag = "RETRY", key, attempt, error = %e, "S3 request failed, retrying");
+                    thread::sleep(backoff);
+                }
+                other => return other,
+            }
+        }
+    }
+
+    fn send_once(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, String)],
+        body: &[u8],
+    ) -> Result<Response<Body>, ureq::Error> {
+        let path = if self.cfg.path_style {
+            format!("/{}/{}", self.cfg.bucket, key)
+        } else {
+            format!("/{}", key)
+        };
+        let uri = encode_path(&path);
+
+        let query = sigv4::canonical_query(query);
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let headers = self.signed_headers(
+            method,
+            &uri,
+            &query,
+            &hex(&Sha256::digest(body)),
+            &amz_date,
+            &now.format("%Y%m%d").to_string(),
+        );
+
+        let url = if query.is_empty() {
+            format!("{}://{}{}", self.scheme, self.host, uri)
+        } else {
+            format!("{}://{}{}?{}", self.scheme, self.host, uri, query)
+        };
+
+        match method {
+            Method::Get => headers
+                .into_iter()
+                .fold(self.agent.get(&url), |r, (name, value)| {
+                    r.header(name, value)
+                })
+                .call(),
+            Method::Head => headers
+                .into_iter()
+                .fold(self.agent.head(&url), |r, (name, value)| {
+                    r.header(name, value)
+                })
+                .call(),
+            Method::Delete => headers
+                .into_iter()
+                .fold(self.agent.delete(&url), |r, (name, value)| {
+                    r.header(name, value)
+                })
+                .call(),
+            Method::Put => headers
+                .into_iter()
+                .fold(self.agent.put(&url), |r, (name, value)| {
+                    r.header(name, value)
+                })
+                .send(body),
+        }
+    }
+
+    /// AWS Signature Version 4 headers.
+    fn signed_headers(
+        &self,
+        method: Method,
+        uri: &str,
+        query: &str,
+        payload_hash: &str,
+        amz_date: &str,
+        date: &str,
+    ) -> Vec<(&'static str, String)> {
+        let mut headers = vec![
+            ("host", self.host.clone()),
+            ("x-amz-content-sha256", payload_hash.to_string()),
+            ("x-amz-date", amz_date.to_string()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+
+        let pairs: Vec<(&str, &str)> = headers.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        let canonical_request =
+            sigv4::canonical_request(method.as_str(), uri, query, &pairs, payload_hash);
+        let signed = sigv4::signed_headers(&pairs);
+        let scope = sigv4::scope(date, &self.cfg.region);
+        let signature = sigv4::signature(
+            &self.credentials.secret_access_key,
+            &self.cfg.region,
+            amz_date,
+            &canonical_request,
+        );
+
+        headers.push((
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.credentials.access_key_id, scope, signed, signature
+            ),
+        ));
+        // ureq derives Host from the URL itself.
+        headers.retain(|(k, _)| *k != "host");
+        headers
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Method {
+    Get,
+    Head,
+    Put,
+    Delete,
+}
+
+impl Method {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+        }
+    }
+}
+
+fn is_transient(e: &ureq::Error) -> bool {
+    match e {
+        ureq::Error::StatusCode(code) => *code == 429 || *code >= 500,
+        ureq::Error::Io(_)
+        | ureq::Error::Timeout(_)
+        | ureq::Error::ConnectionFailed
+        | ureq::Error::HostNotFound => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,139 @@
+//! AWS Signature Version 4 for S3, shared by the [`s3`](crate::s3) client
+//! (which signs) and octa-gateway (which checks what S3 tools signed).
+
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::{Digest, Sha256};
+
+/// `<date>/<region>/s3/aws4_request`
+pub fn scope(date: &str, region: &str) -> String {
+    format!("{}/{}/s3/aws4_request", date, region)
+}
+
+/// The canonical request: method, encoded path, canonical query, the
+/// signed headers as `name:value` lines (names lowercase, in order), their
+/// names, and the payload hash.
+pub fn canonical_request(
+    method: &str,
+    uri: &str,
+    query: &str,
+    headers: &[(&str, &str)],
+    payload_hash: &str,
+) -> String {
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(k, v)| format!("{}:{}\n", k, v.trim()))
+        .collect();
+    format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method,
+        uri,
+        query,
+        canonical_headers,
+        signed_headers(headers),
+        payload_hash
+    )
+}
+
+/// The `SignedHeaders` list of `headers`.
+pub fn signed_headers(headers: &[(&str, &str)]) -> String {
+    headers
+        .iter()
+        .map(|(k, _)| *k)
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Hex signature of `canonical_request`, made at `amz_date`
+/// (`YYYYMMDDTHHMMSSZ`) in `region`.
+pub fn signature(
+    secret_access_key: &str,
+    region: &str,
+    amz_date: &str,
+    canonical_request: &str,
+) -> String {
+    let date = &amz_date[..amz_date.len().min(8)];
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope(date, region),
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+    hex(&hmac(
+        &signing_key(secret_access_key, date, region),
+        string_to_sign.as_bytes(),
+    ))
+}
+
+/// Hex signature of one `aws-chunked` chunk, chained to the `previous`
+/// chunk's (the request's signature for the first).
+pub fn chunk_signature(
+    secret_access_key: &str,
+    region: &str,
+    amz_date: &str,
+    previous: &str,
+    chunk: &[u8],
+) -> String {
+    let date = &amz_date[..amz_date.len().min(8)];
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256-PAYLOAD\n{}\n{}\n{}\n{}\n{}",
+        amz_date,
+        scope(date, region),
+        previous,
+        hex(&Sha256::digest(b"")),
+        hex(&Sha256::digest(chunk))
+    );
+    hex(&hmac(
+        &signing_key(secret_access_key, date, region),
+        string_to_sign.as_bytes(),
+    ))
+}
+
+fn signing_key(secret_access_key: &str, date: &str, region: &str) -> Vec<u8> {
+    let secret = format!("AWS4{}", secret_access_key);
+    let key = hmac(secret.as_bytes(), date.as_bytes());
+    let key = hmac(&key, region.as_bytes());
+    let key = hmac(&key, b"s3");
+    hmac(&key, b"aws4_request")
+}
+
+/// `name=value` pairs, encoded, sorted and joined with `&`.
+pub fn canonical_query<K: AsRef<str>, V: AsRef<str>>(pairs: &[(K, V)]) -> String {
+    let mut query: Vec<(String, String)> = pairs
+        .iter()
+        .map(|(k, v)| (encode(k.as_ref()), encode(v.as_ref())))
+        .collect();
+    query.sort();
+    query
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// RFC 3986 percent-encoding as SigV4 expects it (only unreserved characters kept).
+pub fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// [`encode`] for each segment of a path.
+pub fn encode_path(path: &str) -> String {
+    path.split('/').map(encode).collect::<Vec<_>>().join("/")
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
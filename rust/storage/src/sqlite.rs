@@ -0,0 +1,187 @@
+use crate::{Backend, Error, Stat};
+use octa_image::{Processed, Profile};
+use octa_store::cas;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+/// The Octa SQLite database. The asset ID is `images.id`. Writes through
+/// [`Backend`] store an image under that ID without touching its keys;
+/// [`Sqlite::add`] creates assets with keys, as an upload would.
+pub struct Sqlite {
+    path: String,
+    conn: Mutex<Connection>,
+}
+
+/// An asset for [`Sqlite::add`]: its bytes, stored as they are (like a
+/// `mode=original` upload), and its keys.
+pub struct NewAsset<'a> {
+    pub data: &'a [u8],
+    pub keys: &'a [String],
+    /// `created_at` and `updated_at`, as [`octa_store::now`] writes them;
+    /// `None` is now.
+    pub at: Option<String>,
+}
+
+/// What [`Sqlite::add`] did with one asset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Added {
+    /// Stored under this new ID.
+    Inserted(String),
+    /// `key` already maps to asset `id`; nothing was written.
+    Taken { key: String, id: String },
+    /// Not written, and why: not an image, or the insert failed.
+    Invalid(String),
+}
+
+impl Sqlite {
+    /// Opens an existing database for writing.
+    pub fn open(path: &str, busy_timeout: Duration) -> Result<Self, Error> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        Self::open_with(path, flags, busy_timeout)
+    }
+
+    /// Opens an existing database that is only read.
+    pub fn open_read_only(path: &str, busy_timeout: Duration) -> Result<Self, Error> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        Self::open_with(path, flags, busy_timeout)
+    }
+
+    /// Refuses a database without the server's tables.
+    fn open_with(path: &str, flags: OpenFlags, busy_timeout: Duration) -> Result<Self, Error> {
+        let conn = Connection::open_with_flags(path, flags)?;
+        conn.busy_timeout(busy_timeout)?;
+        conn.prepare("SELECT id, data, width, height, format, size, created_at, updated_at FROM images LIMIT 0")
+            .and_then(|_| conn.prepare("SELECT key, image_id, created_at FROM key_mappings LIMIT 0"))
+            .map_err(|e| Error::Config(format!("{}: not an Octa database ({})", path, e)))?;
+        Ok(Self {
+            path: path.to_string(),
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Inserts every asset as a new one under its keys, in one transaction.
+    /// An asset with a key that is already mapped is left out, never
+    /// overwritten, so an interrupted run can simply be repeated. Fails
+    /// only when the transaction does.
+    pub fn add(&self, assets: &[NewAsset<'_>]) -> Result<Vec<Added>, Error> {
+        let mut conn = self.conn();
+        let mut tx = conn.transaction()?;
+        let now = octa_store::now();
+        let mut added = Vec::with_capacity(assets.len());
+        for asset in assets {
+            // One savepoint per asset: a failing key insert must not leave
+            // an orphaned image row in the batch.
+            let sp = tx.savepoint()?;
+            let outcome = insert(&sp, asset, &now)
+                .unwrap_or_else(|e| Added::Invalid(format!("database error: {}", e)));
+            if let Added::Inserted(_) = outcome {
+                sp.commit()?;
+            }
+            added.push(outcome);
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+}
+
+impl Backend for Sqlite {
+    fn describe(&self) -> String {
+        self.path.clone()
+    }
+
+    fn list(&self) -> Result<Vec<String>, Error> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT id FROM images ORDER BY id")?;
+        let ids = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(ids)
+    }
+
+    fn read(&self, id: &str) -> Result<Option<Vec<u8>>, Error> {
+        let conn = self.conn();
+        let sql = format!("SELECT {} FROM images WHERE id = ?1", cas::data(&conn)?);
+        let data = conn.query_row(&sql, [id], |row| row.get(0)).optional()?;
+        Ok(data)
+    }
+
+    fn write(&self, id: &str, data: &[u8]) -> Result<(), Error> {
+        let image = header(data).map_err(Error::Invalid)?;
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        octa_store::put(&tx, id, &image, &octa_store::now())?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The asset and its keys.
+    fn delete(&self, id: &str) -> Result<(), Error> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        octa_store::delete(&tx, id)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn stat(&self, id: &str) -> Result<Option<Stat>, Error> {
+        let conn = self.conn();
+        // length() reads the BLOB size from the record header, not the data itself.
+        let sql = format!("SELECT length({}) FROM images WHERE id = ?1", cas::data(&conn)?);
+        let size: Option<Option<i64>> = conn.query_row(&sql, [id], |row| row.get(0)).optional()?;
+        Ok(size.map(|size| Stat {
+            size: size.unwrap_or(0) as u64,
+        }))
+    }
+
+    fn write_batch(&self, items: &[(String, Vec<u8>)]) -> Result<Vec<Result<(), Error>>, Error> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let now = octa_store::now();
+        let written = items
+            .iter()
+            .map(|(id, data)| {
+                let image = header(data).map_err(Error::Invalid)?;
+                octa_store::put(&tx, id, &image, &now).map_err(Error::from)
+            })
+            .collect();
+        tx.commit()?;
+        Ok(written)
+    }
+}
+
+/// Format and dimensions from the header; the bytes are kept as they are.
+fn header(data: &[u8]) -> Result<Processed, String> {
+    let original = Profile::from_fields(Some("original"), None, None);
+    octa_image::process(data.to_vec(), &original).map_err(|e| e.to_string())
+}
+
+fn insert(conn: &Connection, asset: &NewAsset<'_>, now: &str) -> rusqlite::Result<Added> {
+    let image = match header(asset.data) {
+        Ok(image) => image,
+        Err(reason) => return Ok(Added::Invalid(reason)),
+    };
+    for key in asset.keys {
+        if let Some(id) = octa_store::owner(conn, key)? {
+            return Ok(Added::Taken {
+                key: key.clone(),
+                id,
+            });
+        }
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let at = asset.at.as_deref().unwrap_or(now);
+    octa_store::put(conn, &id, &image, at)?;
+    for key in asset.keys {
+        conn.execute(
+            "INSERT INTO key_mappings (key, image_id, created_at) VALUES (?1, ?2, ?3)",
+            params![key, id, at],
+        )?;
+    }
+    Ok(Added::Inserted(id))
+}
@@ -0,0 +1,252 @@
+//! The queries behind [`Store`](crate::Store), on a connection the caller
+//! holds: tools that open the database themselves (octa-sync, migrations)
+//! write rows exactly as the servers do by calling these inside their own
+//! transactions.
+
+use crate::cas;
+use octa_image::Processed;
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use serde::Serialize;
+
+/// Timestamps as GORM writes them, so rows from either server sort and parse alike.
+pub fn now() -> String {
+    chrono::Utc::now()
+        .format("%Y-%m-%d %H:%M:%S%.f+00:00")
+        .to_string()
+}
+
+/// A timestamp column as RFC 3339, which is how the Go server's JSON shows
+/// it. GORM writes text, but imported or hand-edited rows may hold unix
+/// seconds instead.
+fn timestamp(row: &Row, idx: usize) -> Result<Option<String>> {
+    Ok(match row.get_ref(idx)? {
+        ValueRef::Text(text) => {
+            let text = String::from_utf8_lossy(text);
+            Some(match text.split_once(' ') {
+                Some((date, time)) => format!("{}T{}", date, time),
+                None => text.into_owned(),
+            })
+        }
+        ValueRef::Integer(secs) => {
+            chrono::DateTime::from_timestamp(secs, 0).map(|t| t.to_rfc3339())
+        }
+        _ => None,
+    })
+}
+
+/// What an upload stored.
+#[derive(Debug, Clone)]
+pub struct Saved {
+    /// `created` or `updated`, as the Go server reports it.
+    pub action: &'static str,
+    pub id: String,
+    pub keys: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Asset {
+    pub avatar_id: String,
+    pub keys: Vec<String>,
+    pub width: i64,
+    pub height: i64,
+    pub format: String,
+    pub size: i64,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ListItem {
+    pub key: String,
+    pub avatar_id: String,
+    pub size: i64,
+    pub format: String,
+    pub updated_at: Option<String>,
+}
+
+/// The asset `key` maps to.
+pub fn owner(conn: &Connection, key: &str) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT image_id FROM key_mappings WHERE key = ?1",
+        [key],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// The upload upsert: the first key decides between replacing its asset's
+/// image and creating a new asset. Further keys are added when free and
+/// skipped when another asset has them. Run it in a transaction; `now` is
+/// the [`now`] of the upload. In a content-addressed database (see
+/// [`cas`](crate::cas)) the bytes go into a blob, shared with any identical
+/// upload.
+pub fn save(conn: &Connection, keys: &[String], image: &Processed, now: &str) -> Result<Saved> {
+    let primary = &keys[0];
+    let (action, id) = match owner(conn, primary)? {
+        Some(id) => ("updated", id),
+        None => ("created", uuid::Uuid::new_v4().to_string()),
+    };
+    put(conn, &id, image, now)?;
+    if action == "created" {
+        conn.execute(
+            "INSERT INTO key_mappings (key, image_id, created_at) VALUES (?1, ?2, ?3)",
+            params![primary, id, now],
+        )?;
+    }
+
+    let mut assigned = vec![primary.clone()];
+    for key in &keys[1..] {
+        match owner(conn, key)? {
+            Some(owner) if owner == id => assigned.push(key.clone()),
+            Some(_) => {}
+            None => {
+                conn.execute(
+                    "INSERT INTO key_mappings (key, image_id, created_at) VALUES (?1, ?2, ?3)",
+                    params![key, id, now],
+                )?;
+                assigned.push(key.clone());
+            }
+        }
+    }
+    Ok(Saved {
+        action,
+        id,
+        keys: assigned,
+    })
+}
+
+/// Replaces the image of asset `id`, or creates the asset without keys;
+/// `at` is its `updated_at`, and its `created_at` when new. In a
+/// content-addressed database the bytes go into a blob and the one they
+/// replace is released. Run it in a transaction.
+pub fn put(conn: &Connection, id: &str, image: &Processed, at: &str) -> Result<()> {
+    let size = image.data.len() as i64;
+    let blob = match cas::enabled(conn)? {
+        true => Some(cas::put(conn, &image.data)?),
+        false => None,
+    };
+    let data = blob.is_none().then_some(image.data.as_slice());
+    let replaced = cas::blob_of(conn, id)?;
+
+    let updated = conn.execute(
+        "UPDATE images SET data = ?2, width = ?3, height = ?4, format = ?5, size = ?6, updated_at = ?7
+         WHERE id = ?1",
+        params![id, data, image.width, image.height, image.format, size, at],
+    )?;
+    if updated == 0 {
+        conn.execute(
+            "INSERT INTO images (id, data, width, height, format, size, updated_at, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?7)",
+            params![id, data, image.width, image.height, image.format, size, at],
+        )?;
+    }
+    if let Some(hash) = &blob {
+        conn.execute(
+            "UPDATE images SET blob_hash = ?2 WHERE id = ?1",
+            params![id, hash],
+        )?;
+        if let Some(replaced) = replaced.filter(|replaced| replaced != hash) {
+            cas::release(conn, &replaced)?;
+        }
+    }
+    Ok(())
+}
+
+/// `(data, format)` of the asset behind `key`, as stored.
+pub fn image(conn: &Connection, key: &str) -> Result<Option<(Vec<u8>, String)>> {
+    let sql = match cas::enabled(conn)? {
+        true => {
+            "SELECT COALESCE(i.data, b.data), IFNULL(i.format, '') FROM key_mappings k
+             JOIN images i ON i.id = k.image_id LEFT JOIN blobs b ON b.hash = i.blob_hash
+             WHERE k.key = ?1"
+        }
+        false => {
+            "SELECT i.data, IFNULL(i.format, '') FROM key_mappings k
+             JOIN images i ON i.id = k.image_id WHERE k.key = ?1"
+        }
+    };
+    conn.query_row(sql, [key], |row| Ok((row.get(0)?, row.get(1)?)))
+        .optional()
+}
+
+pub fn stat(conn: &Connection, id: &str) -> Result<Option<Asset>> {
+    let asset = conn
+        .query_row(
+            "SELECT id, IFNULL(width, 0), IFNULL(height, 0), IFNULL(format, ''), IFNULL(size, 0),
+                    created_at, updated_at
+             FROM images WHERE id = ?1",
+            [id],
+            |row| {
+                Ok(Asset {
+                    avatar_id: row.get(0)?,
+                    keys: Vec::new(),
+                    width: row.get(1)?,
+                    height: row.get(2)?,
+                    format: row.get(3)?,
+                    size: row.get(4)?,
+                    created_at: timestamp(row, 5)?,
+                    updated_at: timestamp(row, 6)?,
+                })
+            },
+        )
+        .optional()?;
+    let Some(mut asset) = asset else {
+        return Ok(None);
+    };
+    asset.keys = conn
+        .prepare("SELECT key FROM key_mappings WHERE image_id = ?1 ORDER BY created_at")?
+        .query_map([id], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+    Ok(Some(asset))
+}
+
+/// Keys under `prefix` after `after`, in key order.
+pub fn list(conn: &Connection, prefix: &str, after: &str, limit: usize) -> Result<Vec<ListItem>> {
+    // '%' and '_' in the prefix are literal.
+    let escaped = prefix
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    conn.prepare(
+        "SELECT k.key, k.image_id, IFNULL(i.size, 0), IFNULL(i.format, ''), i.updated_at
+         FROM key_mappings k JOIN images i ON i.id = k.image_id
+         WHERE k.key LIKE ?1 ESCAPE '\\' AND k.key > ?2
+         ORDER BY k.key LIMIT ?3",
+    )?
+    .query_map(
+        params![format!("{}%", escaped), after, limit as i64],
+        |row| {
+            Ok(ListItem {
+                key: row.get(0)?,
+                avatar_id: row.get(1)?,
+                size: row.get(2)?,
+                format: row.get(3)?,
+                updated_at: timestamp(row, 4)?,
+            })
+        },
+    )?
+    .collect()
+}
+
+/// Removes the asset and its keys, children first (as the Go server's
+/// CoreDeleteAsset does), and its blob when no other asset shares it.
+/// False when there was no such asset. Run it in a transaction.
+pub fn delete(conn: &Connection, id: &str) -> Result<bool> {
+    let blob = cas::blob_of(conn, id)?;
+    conn.execute("DELETE FROM key_mappings WHERE image_id = ?1", [id])?;
+    let removed = conn.execute("DELETE FROM images WHERE id = ?1", [id])? > 0;
+    if let Some(hash) = blob {
+        cas::release(conn, &hash)?;
+    }
+    Ok(removed)
+}
+
+/// `(assets, total bytes)`.
+pub fn totals(conn: &Connection) -> Result<(i64, i64)> {
+    conn.query_row(
+        "SELECT COUNT(*), IFNULL(SUM(size), 0) FROM images",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+}
@@ -0,0 +1,336 @@
+//! The content-addressed layout, an opt-in alternative to a BLOB per asset:
+//! image bytes live once in `blobs`, named by their SHA-256, and
+//! `images.blob_hash` refers to them (`images.data` is then NULL). Identical
+//! uploads share one blob, a blob is checked by hashing it again, and two
+//! databases differ exactly in the hashes one has and the other lacks.
+//!
+//! The queries of this crate handle both layouts, and a database half
+//! converted, so [`convert`] can run in batches against a live server. The
+//! Go server reads `images.data` only: serve a content-addressed database
+//! with the Rust server, or [`convert`] it back to [`Direction::Inline`]
+//! first.
+
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection, OptionalExtension, Result, TransactionBehavior};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+/// Rows moved per transaction by [`convert`].
+const BATCH_SIZE: usize = 500;
+
+/// The image bytes of an `images` row in either layout, for queries that
+/// select from `images` unaliased.
+pub const DATA: &str =
+    "COALESCE(images.data, (SELECT blobs.data FROM blobs WHERE blobs.hash = images.blob_hash))";
+
+/// [`DATA`] once the database is content-addressed, else plain
+/// `images.data`: [`DATA`] needs the `blobs` table to exist.
+pub fn data(conn: &Connection) -> Result<&'static str> {
+    Ok(match enabled(conn)? {
+        true => DATA,
+        false => "images.data",
+    })
+}
+
+/// SHA-256 of `data` in lowercase hex, the name of its blob.
+pub fn hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Whether the database has the content-addressed tables; new uploads are
+/// then stored as blobs.
+pub fn enabled(conn: &Connection) -> Result<bool> {
+    conn.query_row(
+        "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'blobs'",
+        [],
+        |_| Ok(()),
+    )
+    .optional()
+    .map(|found| found.is_some())
+}
+
+/// Adds `blobs` and `images.blob_hash`. Existing rows stay inline until
+/// [`convert`]ed.
+pub fn enable(conn: &Connection) -> Result<()> {
+    let has_ref = conn
+        .prepare("SELECT 1 FROM pragma_table_info('images') WHERE name = 'blob_hash'")?
+        .exists([])?;
+    if !has_ref {
+        conn.execute("ALTER TABLE images ADD COLUMN blob_hash text", [])?;
+    }
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS blobs (
+             hash text PRIMARY KEY,
+             data blob NOT NULL,
+             size integer NOT NULL
+         );
+         CREATE INDEX IF NOT EXISTS idx_images_blob_hash ON images(blob_hash);",
+    )
+}
+
+/// Bytes of image data stored: inline BLOBs, plus every blob once however
+/// many assets share it. `length()` reads the size from the record header,
+/// not the data itself.
+pub fn stored_bytes(conn: &Connection) -> Result<u64> {
+    let sql = match enabled(conn)? {
+        true => {
+            "SELECT (SELECT IFNULL(SUM(length(data)), 0) FROM images)
+                  + (SELECT IFNULL(SUM(size), 0) FROM blobs)"
+        }
+        false => "SELECT IFNULL(SUM(length(data)), 0) FROM images",
+    };
+    conn.query_row(sql, [], |row| row.get::<_, i64>(0).map(|n| n as u64))
+}
+
+/// Stores `data` unless a blob of the same content exists; its hash.
+pub fn put(conn: &Connection, data: &[u8]) -> Result<String> {
+    let hash = hash(data);
+    conn.execute(
+        "INSERT OR IGNORE INTO blobs (hash, data, size) VALUES (?1, ?2, ?3)",
+        params![hash, data, data.len() as i64],
+    )?;
+    Ok(hash)
+}
+
+/// Removes the blob `hash` once no asset refers to it. True when it went.
+pub fn release(conn: &Connection, hash: &str) -> Result<bool> {
+    conn.execute(
+        "DELETE FROM blobs WHERE hash = ?1
+         AND NOT EXISTS (SELECT 1 FROM images WHERE blob_hash = ?1)",
+        [hash],
+    )
+    .map(|removed| removed > 0)
+}
+
+/// The blob an asset refers to, if it is stored content-addressed.
+pub fn blob_of(conn: &Connection, id: &str) -> Result<Option<String>> {
+    if !enabled(conn)? {
+        return Ok(None);
+    }
+    conn.query_row("SELECT blob_hash FROM images WHERE id = ?1", [id], |row| {
+        row.get(0)
+    })
+    .optional()
+    .map(Option::flatten)
+}
+
+/// Replaces the image bytes of asset `id` in the layout it is stored in:
+/// a content-addressed asset refers to the blob of `data` and releases its
+/// old one, an inline asset has `images.data` overwritten. Run it in a
+/// transaction.
+pub fn rewrite(conn: &Connection, id: &str, data: &[u8]) -> Result<()> {
+    match blob_of(conn, id)? {
+        Some(old) => {
+            let hash = put(conn, data)?;
+            conn.execute(
+                "UPDATE images SET blob_hash = ?1 WHERE id = ?2",
+                params![hash, id],
+            )?;
+            release(conn, &old)?;
+        }
+        None => {
+            conn.execute(
+                "UPDATE images SET data = ?1 WHERE id = ?2",
+                params![data, id],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Which way [`convert`] moves image bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From `images.data` into `blobs`.
+    ContentAddressed,
+    /// Back into `images.data`, where the Go server reads them.
+    Inline,
+}
+
+#[derive(Debug, Default)]
+pub struct ConversionSummary {
+    /// Assets moved.
+    pub converted: u64,
+    /// Assets already in the target layout.
+    pub done: u64,
+    /// Bytes of the moved assets, one copy each.
+    pub bytes: u64,
+    /// Bytes written: into new blobs, or back into rows.
+    pub written: u64,
+}
+
+/// Moves every asset into `direction`'s layout, committing every
+/// [`BATCH_SIZE`] rows. Assets already there are left alone, so an
+/// interrupted run continues when started again. Without `execute` nothing
+/// is written and the summary says what would change. The space freed by
+/// shared blobs is returned to the file system by `VACUUM`, not by this.
+pub fn convert(
+    conn: &mut Connection,
+    direction: Direction,
+    execute: bool,
+) -> Result<ConversionSummary> {
+    let mut summary = ConversionSummary::default();
+    if direction == Direction::ContentAddressed && execute {
+        enable(conn)?;
+    }
+    let enabled = enabled(conn)?;
+    if direction == Direction::Inline && !enabled {
+        summary.done = conn.query_row("SELECT COUNT(*) FROM images", [], |row| {
+            row.get::<_, i64>(0).map(|n| n as u64)
+        })?;
+        return Ok(summary);
+    }
+    // Hashes a dry run would have stored by now, so identical uploads in
+    // one run count as shared.
+    let mut planned = std::collections::HashSet::new();
+
+    let select = match enabled {
+        true => "SELECT rowid, data, blob_hash FROM images WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+        // A dry run before the first conversion: every row is inline.
+        false => "SELECT rowid, data, NULL FROM images WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+    };
+    let mut last = i64::MIN;
+    loop {
+        // Immediate: take the write lock up front instead of failing to upgrade a read.
+        let behavior = if execute {
+            TransactionBehavior::Immediate
+        } else {
+            TransactionBehavior::Deferred
+        };
+        let tx = conn.transaction_with_behavior(behavior)?;
+        let rows = {
+            let mut stmt = tx.prepare(select)?;
+            let rows = stmt.query_map(params![last, BATCH_SIZE as i64], |row| {
+                let data = match row.get_ref(1)? {
+                    ValueRef::Blob(data) => Some(data.to_vec()),
+                    _ => None,
+                };
+                Ok((row.get::<_, i64>(0)?, data, row.get::<_, Option<String>>(2)?))
+            })?;
+            rows.collect::<Result<Vec<_>>>()?
+        };
+        let Some((end, _, _)) = rows.last() else {
+            break;
+        };
+        last = *end;
+
+        let mut changed = 0;
+        for (rowid, data, blob_hash) in rows {
+            match (direction, data, blob_hash) {
+                (Direction::ContentAddressed, Some(data), None) => {
+                    let hash = hash(&data);
+                    let shared = planned.contains(&hash)
+                        || (enabled
+                            && tx
+                                .prepare_cached("SELECT 1 FROM blobs WHERE hash = ?1")?
+                                .exists([&hash])?);
+                    summary.converted += 1;
+                    summary.bytes += data.len() as u64;
+                    if !shared {
+                        summary.written += data.len() as u64;
+                    }
+                    if execute {
+                        put(&tx, &data)?;
+                        tx.execute(
+                            "UPDATE images SET data = NULL, blob_hash = ?1 WHERE rowid = ?2",
+                            params![hash, rowid],
+                        )?;
+                        changed += 1;
+                    } else {
+                        planned.insert(hash);
+                    }
+                }
+                (Direction::Inline, None, Some(hash)) => {
+                    let data: Option<Vec<u8>> = tx
+                        .query_row("SELECT data FROM blobs WHERE hash = ?1", [&hash], |row| {
+                            row.get(0)
+                        })
+                        .optional()?;
+                    let Some(data) = data else {
+                        warn!(tag = "SKIP", rowid, hash = %hash, "Blob missing, left unchanged");
+                        continue;
+                    };
+                    summary.converted += 1;
+                    summary.bytes += data.len() as u64;
+                    summary.written += data.len() as u64;
+                    if execute {
+                        tx.execute(
+                            "UPDATE images SET data = ?1, blob_hash = NULL WHERE rowid = ?2",
+                            params![data, rowid],
+                        )?;
+                        release(&tx, &hash)?;
+                        changed += 1;
+                    }
+                }
+                // NULL and non-BLOB values are not assets to convert.
+                (Direction::ContentAddressed, None, None) => {}
+                _ => summary.done += 1,
+            }
+        }
+        tx.commit()?;
+        if changed > 0 {
+            info!(tag = "→", rows = changed, total = summary.converted, "Batch committed");
+        }
+    }
+    Ok(summary)
+}
+
+/// Outcome of [`verify`].
+#[derive(Debug, Default)]
+pub struct Verification {
+    /// Blobs read.
+    pub blobs: u64,
+    /// Blobs whose content no longer hashes to their name.
+    pub corrupt: Vec<String>,
+    /// `(asset id, hash)` of references to blobs that do not exist.
+    pub dangling: Vec<(String, String)>,
+    /// Blobs no asset refers to.
+    pub orphaned: Vec<String>,
+}
+
+impl Verification {
+    pub fn is_clean(&self) -> bool {
+        self.corrupt.is_empty() && self.dangling.is_empty()
+    }
+}
+
+/// Hashes every blob again and checks the references both ways. Nothing is
+/// decoded: a blob that matches its hash is the upload that was stored.
+pub fn verify(conn: &Connection) -> Result<Verification> {
+    let mut verification = Verification::default();
+    if !enabled(conn)? {
+        return Ok(verification);
+    }
+    let mut stmt = conn.prepare("SELECT hash, data FROM blobs")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(0)?;
+        let actual = match row.get_ref(1)? {
+            ValueRef::Blob(data) => hash(data),
+            _ => String::new(),
+        };
+        verification.blobs += 1;
+        if actual != name {
+            verification.corrupt.push(name);
+        }
+    }
+    verification.dangling = conn
+        .prepare(
+            "SELECT i.id, i.blob_hash FROM images i
+             WHERE i.blob_hash IS NOT NULL
+             AND NOT EXISTS (SELECT 1 FROM blobs b WHERE b.hash = i.blob_hash)",
+        )?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_>>()?;
+    verification.orphaned = conn
+        .prepare(
+            "SELECT b.hash FROM blobs b
+             WHERE NOT EXISTS (SELECT 1 FROM images i WHERE i.blob_hash = b.hash)",
+        )?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+    Ok(verification)
+}
@@ -0,0 +1,106 @@
+//! Octa's asset storage, the SQLite layout both servers share: the canonical
+//! schema, the upload upsert, lookups by key and id, listing and deletion.
+//! octa-server runs on it; applications embed it to keep avatars in-process
+//! without running a server. Tools that write the database themselves call
+//! the same queries on their own connection (octa-sync, octa-storage's
+//! `Sqlite` backend), and check and migrate against the same [`schema`]
+//! (octa-warden, octa-migrate).
+//!
+//! ```no_run
+//! use octa_image::Profile;
+//! use octa_store::Store;
+//!
+//! let store = Store::open("data/octa.db").unwrap();
+//! let upload = std::fs::read("avatar.png").unwrap();
+//! let image = octa_image::process(upload, &Profile::from_fields(Some("square"), None, None)).unwrap();
+//!
+//! let saved = store.save(&["alice".to_string()], image).unwrap();
+//! println!("{} {}", saved.action, saved.id);
+//! let (data, format) = store.image("alice").unwrap().expect("just stored");
+//! assert_eq!(format, "jpeg");
+//! # let _ = data;
+//! ```
+//!
+//! [`cas`] stores image bytes content-addressed instead, one blob per
+//! distinct upload; every query here reads both layouts.
+//!
+//! [`Hooks`] run around every write and read of image data, for what a
+//! server does with the bytes at rest (encrypt them, watermark them) and
+//! what it tells others afterwards (purge a CDN, send a webhook).
+
+mod assets;
+pub mod cas;
+pub mod schema;
+mod store;
+
+pub use assets::{delete, image, list, now, owner, put, save, stat, totals, Asset, ListItem, Saved};
+pub use store::Store;
+
+use octa_image::Processed;
+use std::fmt;
+
+/// Why a [`Store`] call failed.
+#[derive(Debug)]
+pub enum Error {
+    /// The database could not be opened or brought to the schema.
+    Open(String),
+    Sqlite(rusqlite::Error),
+    /// A [`Hooks`] method refused the image.
+    Hook(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Open(e) => f.write_str(e),
+            Error::Sqlite(e) => e.fmt(f),
+            Error::Hook(e) => write!(f, "refused by hook: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Sqlite(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Sqlite(e)
+    }
+}
+
+/// Points where an embedding application changes or follows what a
+/// [`Store`] keeps. Every method defaults to doing nothing; `()` is the
+/// store without hooks.
+pub trait Hooks: Send + Sync {
+    /// Before an upload is written: the image to store instead, or why it
+    /// must not be stored. `keys` are the upload's, before any is found taken.
+    fn before_save(&self, keys: &[String], image: Processed) -> Result<Processed, String> {
+        let _ = keys;
+        Ok(image)
+    }
+
+    /// After an upload was committed.
+    fn after_save(&self, saved: &Saved) {
+        let _ = saved;
+    }
+
+    /// The stored bytes of `key` as they are to be served; undoes what
+    /// [`before_save`](Hooks::before_save) did to them, if anything.
+    fn after_load(&self, key: &str, data: Vec<u8>) -> Result<Vec<u8>, String> {
+        let _ = key;
+        Ok(data)
+    }
+
+    /// After the asset `id` and its keys were deleted.
+    fn after_delete(&self, id: &str) {
+        let _ = id;
+    }
+}
+
+impl Hooks for () {}
@@ -0,0 +1,387 @@
+//! The canonical schema, the one octa-warden checks against and the servers
+//! bring a database to on startup.
+
+use chrono::Local;
+use rusqlite::{Connection, Result};
+use std::path::PathBuf;
+use tracing::info;
+
+/// Columns the Go server's models map to (`internal/database/model.go`), as GORM creates them.
+const IMAGES: &[(&str, &str)] = &[
+    ("id", "text"),
+    ("data", "blob"),
+    ("width", "integer"),
+    ("height", "integer"),
+    ("format", "text"),
+    ("size", "integer"),
+    ("updated_at", "datetime"),
+    ("created_at", "datetime"),
+];
+
+const KEY_MAPPINGS: &[(&str, &str)] = &[
+    ("key", "text"),
+    ("image_id", "text"),
+    ("created_at", "datetime"),
+];
+
+/// Indexes the server creates on startup (`internal/database/db.go`).
+const INDEXES: &[(&str, &str)] = &[
+    (
+        "idx_images_updated_at",
+        "CREATE INDEX IF NOT EXISTS idx_images_updated_at ON images(updated_at DESC)",
+    ),
+    (
+        "idx_key_mappings_image_id",
+        "CREATE INDEX IF NOT EXISTS idx_key_mappings_image_id ON key_mappings(image_id)",
+    ),
+];
+
+/// Table clauses `rebuild_images` cannot reproduce from `PRAGMA table_info`.
+const UNSUPPORTED: &[&str] = &[
+    "CHECK",
+    "UNIQUE",
+    "REFERENCES",
+    "COLLATE",
+    "GENERATED",
+    "WITHOUT ROWID",
+];
+
+/// One change that brings the database closer to the canonical schema.
+#[derive(Debug)]
+pub enum Step {
+    CreateTable {
+        table: &'static str,
+    },
+    AddColumn {
+        table: &'static str,
+        column: &'static str,
+        decl: &'static str,
+    },
+    /// Rebuild `images` so that `columns` are declared BLOB.
+    RetypeColumns {
+        columns: Vec<String>,
+    },
+    /// Rewrite values stored as TEXT in a BLOB column.
+    CastValues {
+        column: String,
+        rows: u64,
+    },
+    CreateIndex {
+        name: &'static str,
+    },
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::CreateTable { table } => write!(f, "create table {}", table),
+            Step::AddColumn {
+                table,
+                column,
+                decl,
+            } => {
+                write!(f, "add column {}.{} ({})", table, column, decl)
+            }
+            Step::RetypeColumns { columns } => {
+                write!(
+                    f,
+                    "declare images.{} as BLOB (table rebuild)",
+                    columns.join(", images.")
+                )
+            }
+            Step::CastValues { column, rows } => {
+                write!(
+                    f,
+                    "convert {} TEXT value(s) in images.{} to BLOB",
+                    rows, column
+                )
+            }
+            Step::CreateIndex { name } => write!(f, "create index {}", name),
+        }
+    }
+}
+
+struct Column {
+    name: String,
+    decl: String,
+    not_null: bool,
+    default: Option<String>,
+    pk: i64,
+}
+
+fn columns(conn: &Connection, table: &str) -> Result<Vec<Column>> {
+    conn.prepare(&format!("PRAGMA table_info({})", quote(table)))?
+        .query_map([], |row| {
+            Ok(Column {
+                name: row.get(1)?,
+                decl: row.get(2)?,
+                not_null: row.get(3)?,
+                default: row.get(4)?,
+                pk: row.get(5)?,
+            })
+        })?
+        .collect()
+}
+
+fn exists(conn: &Connection, kind: &str, name: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = ?1 AND name = ?2)",
+        [kind, name],
+        |row| row.get(0),
+    )
+}
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// `<db>.pre-migrate-YYYYMMDD-HHMMSS.bak`, next to the database.
+pub fn backup_path(db_path: &str) -> PathBuf {
+    PathBuf::from(format!(
+        "{}.pre-migrate-{}.bak",
+        db_path,
+        Local::now().format("%Y%m%d-%H%M%S")
+    ))
+}
+
+/// Compares the database with the canonical schema. `image_columns` are the
+/// columns that must hold BLOBs: `data` plus `warden.extra_blob_columns`.
+pub fn diagnose(conn: &Connection, image_columns: &[String]) -> Result<Vec<Step>> {
+    let mut steps = Vec::new();
+
+    for (table, canonical) in [("images", IMAGES), ("key_mappings", KEY_MAPPINGS)] {
+        if !exists(conn, "table", table)? {
+            steps.push(Step::CreateTable { table });
+            continue;
+        }
+        let present = columns(conn, table)?;
+        for (column, decl) in canonical {
+            if !present.iter().any(|c| c.name.eq_ignore_ascii_case(column)) {
+                steps.push(Step::AddColumn {
+                    table,
+                    column,
+                    decl,
+                });
+            }
+        }
+    }
+
+    if exists(conn, "table", "images")? {
+        let present = columns(conn, "images")?;
+        let retype: Vec<String> = present
+            .iter()
+            .filter(|c| {
+                image_columns
+                    .iter()
+                    .any(|i| i.eq_ignore_ascii_case(&c.name))
+            })
+            .filter(|c| !c.decl.eq_ignore_ascii_case("blob"))
+            .map(|c| c.name.clone())
+            .collect();
+        if !retype.is_empty() {
+            steps.push(Step::RetypeColumns { columns: retype });
+        }
+
+        for column in image_columns {
+            if !present.iter().any(|c| c.name.eq_ignore_ascii_case(column)) {
+                continue;
+            }
+            let rows: i64 = conn.query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM images WHERE typeof({}) = 'text'",
+                    quote(column)
+                ),
+                [],
+                |row| row.get(0),
+            )?;
+            if rows > 0 {
+                steps.push(Step::CastValues {
+                    column: column.clone(),
+                    rows: rows as u64,
+                });
+            }
+        }
+    }
+
+    for (name, _) in INDEXES {
+        if !exists(conn, "index", name)? {
+            steps.push(Step::CreateIndex { name });
+        }
+    }
+    Ok(steps)
+}
+
+/// Applies `steps` in one transaction; nothing is changed if any step fails.
+pub fn migrate(conn: &mut Connection, steps: &[Step]) -> Result<(), String> {
+    let foreign_keys: bool = conn
+        .pragma_query_value(None, "foreign_keys", |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    // Dropping the old `images` during a rebuild must not cascade into key_mappings.
+    conn.pragma_update(None, "foreign_keys", false)
+        .map_err(|e| e.to_string())?;
+    let applied = apply(conn, steps);
+    conn.pragma_update(None, "foreign_keys", foreign_keys)
+        .map_err(|e| e.to_string())?;
+    applied
+}
+
+fn apply(conn: &mut Connection, steps: &[Step]) -> Result<(), String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    for step in steps {
+        apply_step(&tx, step)?;
+        info!(tag = "APPLY", step = %step, "Schema step applied");
+    }
+
+    let violations: i64 = tx
+        .query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| {
+            row.get(0)
+        })
+        .map_err(|e| e.to_string())?;
+    if violations > 0 {
+        // Rolled back on drop.
+        return Err(format!(
+            "migration would leave {} foreign key violation(s)",
+            violations
+        ));
+    }
+    tx.commit().map_err(|e| e.to_string())
+}
+
+fn apply_step(conn: &Connection, step: &Step) -> Result<(), String> {
+    match step {
+        Step::CreateTable { table } => {
+            let canonical = if *table == "images" {
+                IMAGES
+            } else {
+                KEY_MAPPINGS
+            };
+            let columns: Vec<String> = canonical
+                .iter()
+                .enumerate()
+                .map(|(i, (name, decl))| {
+                    let pk = if i == 0 { " PRIMARY KEY" } else { "" };
+                    format!("{} {}{}", quote(name), decl, pk)
+                })
+                .collect();
+            conn.execute_batch(&format!(
+                "CREATE TABLE {} ({})",
+                quote(table),
+                columns.join(", ")
+            ))
+            .map_err(|e| e.to_string())
+        }
+        Step::AddColumn {
+            table,
+            column,
+            decl,
+        } => {
+            let mut sql = format!(
+                "ALTER TABLE {} ADD COLUMN {} {};",
+                quote(table),
+                quote(column),
+                decl
+            );
+            // The server sums `size` for its stats on startup.
+            if (*table, *column) == ("images", "size") {
+                sql.push_str("UPDATE images SET size = length(data);");
+            }
+            conn.execute_batch(&sql).map_err(|e| e.to_string())
+        }
+        Step::RetypeColumns { columns } => rebuild_images(conn, columns),
+        Step::CastValues { column, .. } => conn
+            .execute(
+                &format!(
+                    "UPDATE images SET {0} = CAST({0} AS BLOB) WHERE typeof({0}) = 'text'",
+                    quote(column)
+                ),
+                [],
+            )
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        Step::CreateIndex { name } => {
+            let sql = INDEXES.iter().find(|(n, _)| n == name).map(|(_, sql)| *sql);
+            match sql {
+                Some(sql) => conn.execute_batch(sql).map_err(|e| e.to_string()),
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+/// SQLite cannot change a column's type in place: copy `images` into a new
+/// table with the columns declared BLOB, keeping rowids, the other column
+/// definitions, indexes and triggers.
+fn rebuild_images(conn: &Connection, retype: &[String]) -> Result<(), String> {
+    let sql: String = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'images'",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    let upper = sql.to_ascii_uppercase();
+    if let Some(clause) = UNSUPPORTED.iter().find(|c| upper.contains(*c)) {
+        return Err(format!(
+            "images uses {} which a rebuild would not carry over; convert it manually",
+            clause
+        ));
+    }
+    rebuild(conn, retype).map_err(|e| e.to_string())
+}
+
+fn rebuild(conn: &Connection, retype: &[String]) -> Result<()> {
+    let columns = columns(conn, "images")?;
+    let pk: Vec<&Column> = columns.iter().filter(|c| c.pk > 0).collect();
+
+    let mut defs: Vec<String> = columns
+        .iter()
+        .map(|c| {
+            let decl = if retype.contains(&c.name) {
+                "blob"
+            } else {
+                c.decl.as_str()
+            };
+            let mut def = format!("{} {}", quote(&c.name), decl);
+            if pk.len() == 1 && c.pk > 0 {
+                def.push_str(" PRIMARY KEY");
+            }
+            if c.not_null {
+                def.push_str(" NOT NULL");
+            }
+            if let Some(default) = &c.default {
+                def.push_str(&format!(" DEFAULT {}", default));
+            }
+            def
+        })
+        .collect();
+    if pk.len() > 1 {
+        let mut pk = pk;
+        pk.sort_by_key(|c| c.pk);
+        let names: Vec<String> = pk.iter().map(|c| quote(&c.name)).collect();
+        defs.push(format!("PRIMARY KEY ({})", names.join(", ")));
+    }
+
+    // Indexes and triggers are dropped with the table; recreate them afterwards.
+    let dependents: Vec<String> = conn
+        .prepare(
+            "SELECT sql FROM sqlite_master
+             WHERE tbl_name = 'images' AND type IN ('index', 'trigger') AND sql IS NOT NULL",
+        )?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+
+    let names: Vec<String> = columns.iter().map(|c| quote(&c.name)).collect();
+    let names = names.join(", ");
+    conn.execute_batch(&format!(
+        "CREATE TABLE warden_images_new ({defs});
+         INSERT INTO warden_images_new (rowid, {names}) SELECT rowid, {names} FROM images;
+         DROP TABLE images;
+         ALTER TABLE warden_images_new RENAME TO images;",
+        defs = defs.join(", "),
+        names = names
+    ))?;
+    for sql in dependents {
+        conn.execute_batch(&sql)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use crate::assets::{self, Asset, ListItem, Saved};
+use crate::schema::{self, Step};
+use crate::{Error, Hooks};
+use octa_image::Processed;
+use rusqlite::Connection;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use tracing::{info, warn};
+
+/// The database behind one connection. SQLite has a single writer, and the
+/// Go server also keeps exactly one connection open.
+pub struct Store {
+    conn: Mutex<Connection>,
+    hooks: Box<dyn Hooks>,
+}
+
+impl Store {
+    /// Opens (or creates) the database with the Go server's pragmas and
+    /// brings it to the canonical schema octa-warden checks against.
+    pub fn open(path: &str) -> Result<Self, Error> {
+        if let Some(dir) = Path::new(path)
+            .parent()
+            .filter(|d| !d.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| Error::Open(format!("could not create {}: {}", dir.display(), e)))?;
+        }
+        let mut conn = Connection::open(path)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA busy_timeout = 5000;
+             PRAGMA synchronous = NORMAL;
+             PRAGMA cache_size = -20000;",
+        )?;
+
+        // Additive steps only, as GORM's AutoMigrate would take them. Rewriting
+        // stored data is left to `octa-warden --migrate-schema`, with its backup.
+        let (additive, rewrites): (Vec<_>, Vec<_>) = schema::diagnose(&conn, &["data".to_string()])?
+            .into_iter()
+            .partition(|step| {
+                matches!(
+                    step,
+                    Step::CreateTable { .. } | Step::AddColumn { .. } | Step::CreateIndex { .. }
+                )
+            });
+        if !additive.is_empty() {
+            info!(
+                tag = "SCHEMA",
+                steps = additive.len(),
+                "Bringing the database to the current schema"
+            );
+            schema::migrate(&mut conn, &additive).map_err(Error::Open)?;
+        }
+        for step in rewrites {
+            warn!(tag = "SCHEMA", step = %step, "Schema drift left as is; run octa-warden --migrate-schema");
+        }
+        Ok(Self {
+            conn: Mutex::new(conn),
+            hooks: Box::new(()),
+        })
+    }
+
+    /// Runs `hooks` around every write and read of image data from now on.
+    pub fn with_hooks(mut self, hooks: impl Hooks + 'static) -> Self {
+        self.hooks = Box::new(hooks);
+        self
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The connection, for queries the store has no method for (octa-keys'
+    /// table, say). Writes made here bypass the hooks.
+    pub fn with_conn<T>(&self, f: impl FnOnce(&Connection) -> T) -> T {
+        f(&self.conn())
+    }
+
+    /// `(assets, total bytes)`.
+    pub fn totals(&self) -> rusqlite::Result<(i64, i64)> {
+        assets::totals(&self.conn())
+    }
+
+    /// Stores an upload under `keys` (see [`assets::save`]), after
+    /// [`Hooks::before_save`] had its say.
+    pub fn save(&self, keys: &[String], image: Processed) -> Result<Saved, Error> {
+        let image = self.hooks.before_save(keys, image).map_err(Error::Hook)?;
+        let saved = {
+            let mut conn = self.conn();
+            let tx = conn.transaction()?;
+            let saved = assets::save(&tx, keys, &image, &assets::now())?;
+            tx.commit()?;
+            saved
+        };
+        self.hooks.after_save(&saved);
+        Ok(saved)
+    }
+
+    pub fn asset_id(&self, key: &str) -> rusqlite::Result<Option<String>> {
+        assets::owner(&self.conn(), key)
+    }
+
+    /// `(data, format)` of the asset behind `key`, through
+    /// [`Hooks::after_load`].
+    pub fn image(&self, key: &str) -> Result<Option<(Vec<u8>, String)>, Error> {
+        let Some((data, format)) = assets::image(&self.conn(), key)? else {
+            return Ok(None);
+        };
+        let data = self.hooks.after_load(key, data).map_err(Error::Hook)?;
+        Ok(Some((data, format)))
+    }
+
+    pub fn stat(&self, id: &str) -> rusqlite::Result<Option<Asset>> {
+        assets::stat(&self.conn(), id)
+    }
+
+    /// Keys under `prefix` after `after`, in key order.
+    pub fn list(&self, prefix: &str, after: &str, limit: usize) -> rusqlite::Result<Vec<ListItem>> {
+        assets::list(&self.conn(), prefix, after, limit)
+    }
+
+    /// Removes the asset and its keys. False when there was no such asset.
+    pub fn delete(&self, id: &str) -> rusqlite::Result<bool> {
+        let removed = {
+            let mut conn = self.conn();
+            let tx = conn.transaction()?;
+            let removed = assets::delete(&tx, id)?;
+            tx.commit()?;
+            removed
+        };
+        if removed {
+            self.hooks.after_delete(id);
+        }
+        Ok(removed)
+    }
+}
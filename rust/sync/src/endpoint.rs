@@ -0,0 +1,221 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use octa_client::{Client, Mode, UploadOptions};
+use octa_errors::{Error, Kind};
+use octa_image::Profile;
+use octa_warden_core::db::{self, OpenOptions};
+use rusqlite::types::ValueRef;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Keys per `/upload/list` page.
+const PAGE: u32 = 1000;
+
+/// One side of a sync: a running instance, through its API, or the SQLite
+/// file behind one.
+pub enum Endpoint {
+    Api { url: String, client: Client },
+    Db { path: String, opts: OpenOptions },
+}
+
+/// What an endpoint stores for one key.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub id: String,
+    pub size: u64,
+    pub format: String,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Every key of an endpoint, in key order.
+pub type Inventory = BTreeMap<String, Entry>;
+
+impl Endpoint {
+    /// `http(s)://...` is an instance; anything else a database path.
+    pub fn new(
+        spec: &str,
+        secret: &str,
+        timeout: Duration,
+        opts: &OpenOptions,
+    ) -> Result<Self, Error> {
+        if spec.starts_with("http://") || spec.starts_with("https://") {
+            let client = Client::builder(spec)
+                .secret(secret)
+                .timeout(timeout)
+                .user_agent(concat!("octa-sync/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .map_err(|e| Error::new(Kind::Config, e.to_string()))?;
+            return Ok(Endpoint::Api {
+                url: spec.trim_end_matches('/').to_string(),
+                client,
+            });
+        }
+        if !std::path::Path::new(spec).exists() {
+            return Err(Error::new(
+                Kind::NoInput,
+                format!("{}: database file not found", spec),
+            ));
+        }
+        Ok(Endpoint::Db {
+            path: spec.to_string(),
+            opts: opts.clone(),
+        })
+    }
+
+    pub fn describe(&self) -> &str {
+        match self {
+            Endpoint::Api { url, .. } => url,
+            Endpoint::Db { path, .. } => path,
+        }
+    }
+
+    /// What a failed request to it means: the instance could not be
+    /// reached, or the database could not be used.
+    pub fn failure(&self) -> Kind {
+        match self {
+            Endpoint::Api { .. } => Kind::Unavailable,
+            Endpoint::Db { .. } => Kind::Io,
+        }
+    }
+
+    /// Every key starting with `prefix`.
+    pub async fn inventory(&self, prefix: &str) -> Result<Inventory, String> {
+        match self {
+            Endpoint::Api { client, .. } => {
+                let mut inventory = Inventory::new();
+                let mut after: Option<String> = None;
+                loop {
+                    let page = client
+                        .list(prefix, after.as_deref(), PAGE)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    for item in page.items {
+                        let updated_at = parse_time(&item.updated_at);
+                        inventory.insert(
+                            item.key,
+                            Entry {
+                                id: item.id,
+                                size: item.size,
+                                format: item.format,
+                                updated_at,
+                            },
+                        );
+                    }
+                    match page.next {
+                        Some(next) => after = Some(next),
+                        None => return Ok(inventory),
+                    }
+                }
+            }
+            Endpoint::Db { path, opts } => {
+                let (path, opts, prefix) = (path.clone(), opts.clone(), prefix.to_string());
+                blocking(move || {
+                    let conn = db::open_read_only(&path, &opts)?;
+                    let mut stmt = conn.prepare(
+                        "SELECT k.key, k.image_id, COALESCE(i.size, LENGTH(i.data), 0), IFNULL(i.format, ''), i.updated_at
+                         FROM key_mappings k JOIN images i ON i.id = k.image_id
+                         WHERE substr(k.key, 1, length(?1)) = ?1",
+                    )?;
+                    let rows = stmt.query_map([&prefix], |row| {
+                        let updated_at = match row.get_ref(4)? {
+                            ValueRef::Text(text) => parse_time(&String::from_utf8_lossy(text)),
+                            ValueRef::Integer(secs) => DateTime::from_timestamp(secs, 0),
+                            _ => None,
+                        };
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            Entry {
+                                id: row.get(1)?,
+                                size: row.get::<_, i64>(2)?.max(0) as u64,
+                                format: row.get(3)?,
+                                updated_at,
+                            },
+                        ))
+                    })?;
+                    rows.collect()
+                })
+                .await
+            }
+        }
+    }
+
+    /// The stored bytes behind `key`.
+    pub async fn fetch(&self, key: &str) -> Result<Vec<u8>, String> {
+        match self {
+            // The inventory only lists stored keys, so this is never a
+            // generated fallback.
+            Endpoint::Api { client, .. } => client
+                .get_avatar(key)
+                .await
+                .map(|bytes| bytes.to_vec())
+                .map_err(|e| e.to_string()),
+            Endpoint::Db { path, opts } => {
+                let (path, opts, key) = (path.clone(), opts.clone(), key.to_string());
+                blocking(move || {
+                    octa_store::image(&db::open_read_only(&path, &opts)?, &key)?
+                        .map(|(data, _)| data)
+                        .ok_or(rusqlite::Error::QueryReturnedNoRows)
+                })
+                .await
+            }
+        }
+    }
+
+    /// Stores `data` as is under `keys`, the way an `original` upload does:
+    /// the first key's asset is replaced (or created), the other keys join
+    /// it unless another asset holds them. Returns the keys it now has.
+    pub async fn put(&self, keys: &[String], data: Vec<u8>) -> Result<Vec<String>, String> {
+        match self {
+            Endpoint::Api { client, .. } => {
+                let options = keys[1..]
+                    .iter()
+                    .fold(UploadOptions::new().mode(Mode::Original), |o, key| {
+                        o.alias(key.clone())
+                    });
+                client
+                    .upload_avatar(&keys[0], data, options)
+                    .await
+                    .map(|upload| upload.keys)
+                    .map_err(|e| e.to_string())
+            }
+            Endpoint::Db { path, opts } => {
+                let original = Profile {
+                    mode: octa_image::Mode::Original,
+                    ..Profile::default()
+                };
+                let image = octa_image::process(data, &original).map_err(|e| e.to_string())?;
+                let (path, opts, keys) = (path.clone(), opts.clone(), keys.to_vec());
+                blocking(move || {
+                    let mut conn = db::open_read_write(&path, &opts)?;
+                    let tx = conn.transaction()?;
+                    let saved = octa_store::save(&tx, &keys, &image, &octa_store::now())?;
+                    tx.commit()?;
+                    Ok(saved.keys)
+                })
+                .await
+            }
+        }
+    }
+}
+
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> rusqlite::Result<T> + Send + 'static,
+) -> Result<T, String> {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// RFC 3339 (the API) or GORM's `2006-01-02 15:04:05.999-07:00` (the
+/// database); `None` for anything else.
+fn parse_time(text: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(text)
+        .or_else(|_| DateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f%:z"))
+        .map(|t| t.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f")
+                .ok()
+                .map(|t| t.and_utc())
+        })
+}
@@ -0,0 +1,261 @@
+use clap::Parser;
+use endpoint::Endpoint;
+use octa_config::{ConfigError, SecurityConfig, Validate};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use octa_warden_core::db::OpenOptions;
+use octa_warden_core::growth::format_bytes;
+use octa_warden_core::schedule::parse_interval;
+use plan::Direction;
+use serde::Deserialize;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+use tracing::{error, info, warn, Level};
+
+mod endpoint;
+mod plan;
+mod transfer;
+
+/*
+OCTA-SYNC: Replication between two Octa instances
+=============================================
+Mission: Keep a standby in step with its primary. Each side is an instance
+         (through its API) or its SQLite file; missing and changed assets
+         are copied, in one direction or both, and re-read to check hashes.
+Safety:  Never deletes. Databases are read read-only and written in one
+         transaction per asset. --dry-run only lists the copies.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Replicate assets between two Octa instances or databases"
+)]
+struct Args {
+    /// Instance URL or SQLite path to copy from (overrides sync.source)
+    source: Option<String>,
+
+    /// Instance URL or SQLite path to copy to (overrides sync.target)
+    target: Option<String>,
+
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Upload secret of the source instance (default: sync.source_secret, else security.upload_secret)
+    #[arg(long, env = "OCTA_SYNC_SOURCE_SECRET", hide_env_values = true)]
+    source_secret: Option<String>,
+
+    /// Upload secret of the target instance (default: sync.target_secret, else security.upload_secret)
+    #[arg(long, env = "OCTA_SYNC_TARGET_SECRET", hide_env_values = true)]
+    target_secret: Option<String>,
+
+    /// Which way assets may travel (overrides sync.direction)
+    #[arg(long, value_enum)]
+    direction: Option<Direction>,
+
+    /// Only keys starting with this
+    #[arg(long, default_value = "")]
+    prefix: String,
+
+    /// Also compare the content of keys whose size and format match (reads every asset on both sides)
+    #[arg(long)]
+    checksum: bool,
+
+    /// Skip re-reading each copy to compare hashes
+    #[arg(long)]
+    no_verify: bool,
+
+    /// List what would be copied
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Keep syncing every sync.interval until interrupted
+    #[arg(long)]
+    watch: bool,
+
+    /// Seconds before a request is abandoned
+    #[arg(long, default_value_t = 60)]
+    timeout: u64,
+
+    /// Milliseconds to wait on a locked database before giving up (SQLITE_BUSY)
+    #[arg(long, default_value_t = 5000)]
+    busy_timeout: u64,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+}
+
+/// The parts of config.yaml octa-sync reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    security: SecurityConfig,
+    sync: SyncConfig,
+}
+
+/// `sync:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SyncConfig {
+    source: String,
+    target: String,
+    source_secret: String,
+    target_secret: String,
+    direction: Direction,
+    /// Pause between rounds with `--watch`.
+    interval: String,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            source: String::new(),
+            target: String::new(),
+            source_secret: String::new(),
+            target_secret: String::new(),
+            direction: Direction::Push,
+            interval: "1m".to_string(),
+        }
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        if let Err(e) = parse_interval(&self.sync.interval) {
+            problems.push(("sync.interval".to_string(), e));
+        }
+        problems
+    }
+}
+
+/// Without a file, the environment and arguments alone configure sync.
+fn load_config(path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    match octa_config::discover(path.map(Path::new)) {
+        Some(path) => octa_config::load(&path),
+        None => {
+            let config: FileConfig = octa_config::from_env()?;
+            octa_config::check(Path::new("<environment>"), config)
+        }
+    }
+}
+
+/// The first non-empty of `values`.
+fn first<'a>(values: &[Option<&'a str>]) -> &'a str {
+    values
+        .iter()
+        .flatten()
+        .find(|v| !v.trim().is_empty())
+        .copied()
+        .unwrap_or_default()
+}
+
+/// The octa-sync command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa sync` the arguments it was given.
+#[tokio::main]
+pub async fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, Level::INFO, false, false);
+
+    let config = match load_config(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let timeout = Duration::from_secs(args.timeout);
+    let opts = OpenOptions {
+        busy_timeout: Duration::from_millis(args.busy_timeout),
+        immutable: false,
+    };
+    let shared_secret = Some(config.security.upload_secret.as_str());
+
+    let mut sides = Vec::new();
+    for (name, spec, secret) in [
+        (
+            "source",
+            first(&[args.source.as_deref(), Some(&config.sync.source)]),
+            first(&[
+                args.source_secret.as_deref(),
+                Some(&config.sync.source_secret),
+                shared_secret,
+            ]),
+        ),
+        (
+            "target",
+            first(&[args.target.as_deref(), Some(&config.sync.target)]),
+            first(&[
+                args.target_secret.as_deref(),
+                Some(&config.sync.target_secret),
+                shared_secret,
+            ]),
+        ),
+    ] {
+        if spec.is_empty() {
+            error!(
+                tag = "FATAL",
+                "No {} (pass it as an argument or set sync.{})", name, name
+            );
+            return Kind::Config.exit_code();
+        }
+        match Endpoint::new(spec, secret, timeout, &opts) {
+            Ok(endpoint) => sides.push(endpoint),
+            Err(e) => {
+                error!(tag = "FATAL", side = name, reason = %e, "Invalid endpoint");
+                return e.exit_code();
+            }
+        }
+    }
+    let (source, target) = (&sides[0], &sides[1]);
+
+    let direction = args.direction.unwrap_or(config.sync.direction);
+    let interval = parse_interval(&config.sync.interval).unwrap_or(Duration::from_secs(60));
+    let opts = transfer::Options {
+        direction,
+        prefix: &args.prefix,
+        checksum: args.checksum,
+        verify: !args.no_verify,
+        dry_run: args.dry_run,
+    };
+    info!(
+        tag = "→",
+        source = source.describe(),
+        target = target.describe(),
+        direction = ?direction,
+        "Syncing"
+    );
+
+    loop {
+        let failure = match transfer::round(source, target, &opts).await {
+            Ok(summary) => {
+                info!(
+                    tag = if summary.failed == 0 { "OK" } else { "WARN" },
+                    copied = summary.copied,
+                    failed = summary.failed,
+                    size = %format_bytes(summary.bytes as f64),
+                    "Sync round finished"
+                );
+                summary.failure
+            }
+            Err(e) => {
+                error!(tag = "ERROR", reason = %e, "Sync round failed");
+                Some(e.kind())
+            }
+        };
+        if !args.watch {
+            return failure.map_or(ExitCode::SUCCESS, Kind::exit_code);
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = tokio::signal::ctrl_c() => {
+                warn!(tag = "STOP", "Interrupted, stopping");
+                return ExitCode::SUCCESS;
+            }
+        }
+    }
+}
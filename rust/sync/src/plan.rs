@@ -0,0 +1,130 @@
+use crate::endpoint::{Entry, Inventory};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+/// Which way assets may travel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Source to target only.
+    Push,
+    /// Target to source only.
+    Pull,
+    /// Both ways; when a key differs, the side updated last wins.
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Side {
+    Source,
+    Target,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Side::Source => "source",
+            Side::Target => "target",
+        })
+    }
+}
+
+/// One asset to copy.
+#[derive(Debug)]
+pub struct Transfer {
+    pub from: Side,
+    /// The asset's id on the sending side.
+    pub id: String,
+    /// All of the asset's keys on the sending side, so the receiving side
+    /// groups them the same way.
+    pub keys: Vec<String>,
+    pub bytes: u64,
+    /// `missing` when a key is absent on the receiving side, else `changed`.
+    pub reason: &'static str,
+}
+
+/// What to copy so the receiving side(s) match. Keys are compared by size
+/// and format; `differ` adds keys whose content differs despite equal
+/// metadata (`--checksum`). Nothing is ever deleted.
+pub fn plan(
+    source: &Inventory,
+    target: &Inventory,
+    direction: Direction,
+    differ: &HashSet<String>,
+) -> Vec<Transfer> {
+    let mut wanted: BTreeMap<(Side, &str), &'static str> = BTreeMap::new();
+    for (key, s) in source {
+        match target.get(key) {
+            None if direction != Direction::Pull => want(&mut wanted, Side::Source, s, "missing"),
+            None => {}
+            Some(t) if s.size != t.size || s.format != t.format || differ.contains(key) => {
+                match winner(s, t, direction) {
+                    Side::Source => want(&mut wanted, Side::Source, s, "changed"),
+                    Side::Target => want(&mut wanted, Side::Target, t, "changed"),
+                }
+            }
+            Some(_) => {}
+        }
+    }
+    if direction != Direction::Push {
+        for (key, t) in target {
+            if !source.contains_key(key) {
+                want(&mut wanted, Side::Target, t, "missing");
+            }
+        }
+    }
+
+    let source_keys = keys_by_id(source);
+    let target_keys = keys_by_id(target);
+    wanted
+        .into_iter()
+        .map(|((from, id), reason)| {
+            let (inventory, keys) = match from {
+                Side::Source => (source, &source_keys),
+                Side::Target => (target, &target_keys),
+            };
+            let keys: Vec<String> = keys[id].iter().map(|k| k.to_string()).collect();
+            Transfer {
+                from,
+                id: id.to_string(),
+                bytes: inventory[&keys[0]].size,
+                keys,
+                reason,
+            }
+        })
+        .collect()
+}
+
+/// Marks an asset for copying; `missing` outranks `changed`.
+fn want<'a>(
+    wanted: &mut BTreeMap<(Side, &'a str), &'static str>,
+    from: Side,
+    entry: &'a Entry,
+    reason: &'static str,
+) {
+    let slot = wanted.entry((from, &entry.id)).or_insert(reason);
+    if reason == "missing" {
+        *slot = reason;
+    }
+}
+
+fn keys_by_id(inventory: &Inventory) -> HashMap<&str, Vec<&str>> {
+    let mut keys: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (key, entry) in inventory {
+        keys.entry(&entry.id).or_default().push(key);
+    }
+    keys
+}
+
+/// The side whose copy of a differing key is kept.
+fn winner(source: &Entry, target: &Entry, direction: Direction) -> Side {
+    match direction {
+        Direction::Push => Side::Source,
+        Direction::Pull => Side::Target,
+        // Unknown times lose; a tie keeps the source.
+        Direction::Both if target.updated_at > source.updated_at => Side::Target,
+        Direction::Both => Side::Source,
+    }
+}
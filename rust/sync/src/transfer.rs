@@ -0,0 +1,183 @@
+use crate::endpoint::{Endpoint, Inventory};
+use crate::plan::{self, Direction, Side};
+use octa_errors::{Error, Kind};
+use octa_warden_core::export::sha256_hex;
+use octa_warden_core::growth::format_bytes;
+use std::collections::HashSet;
+use tracing::{error, info, warn};
+
+pub struct Options<'a> {
+    pub direction: Direction,
+    pub prefix: &'a str,
+    /// Compare the content of keys whose size and format match.
+    pub checksum: bool,
+    /// Re-read each copy from the receiving side and compare hashes.
+    pub verify: bool,
+    pub dry_run: bool,
+}
+
+/// How one round went.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub copied: u64,
+    pub bytes: u64,
+    pub failed: u64,
+    /// Why the first failed copy failed, for the exit code.
+    pub failure: Option<Kind>,
+}
+
+/// Compares both sides once and copies what differs.
+pub async fn round(
+    source: &Endpoint,
+    target: &Endpoint,
+    opts: &Options<'_>,
+) -> Result<Summary, Error> {
+    let (source_inv, target_inv) =
+        tokio::join!(source.inventory(opts.prefix), target.inventory(opts.prefix));
+    let source_inv = source_inv
+        .map_err(|e| Error::new(source.failure(), format!("{}: {}", source.describe(), e)))?;
+    let target_inv = target_inv
+        .map_err(|e| Error::new(target.failure(), format!("{}: {}", target.describe(), e)))?;
+
+    let differ = if opts.checksum {
+        differing(source, target, &source_inv, &target_inv).await?
+    } else {
+        HashSet::new()
+    };
+    let transfers = plan::plan(&source_inv, &target_inv, opts.direction, &differ);
+    info!(
+        tag = "→",
+        source = source_inv.len(),
+        target = target_inv.len(),
+        transfers = transfers.len(),
+        "Compared"
+    );
+
+    let mut summary = Summary::default();
+    for transfer in &transfers {
+        let (from, to) = match transfer.from {
+            Side::Source => (source, target),
+            Side::Target => (target, source),
+        };
+        let direction = format!("{} -> {}", transfer.from, other(transfer.from));
+        if opts.dry_run {
+            info!(
+                tag = "SYNC",
+                id = %transfer.id,
+                keys = %transfer.keys.join(","),
+                reason = transfer.reason,
+                direction = %direction,
+                size = %format_bytes(transfer.bytes as f64),
+                "Would copy"
+            );
+            continue;
+        }
+
+        match copy(from, to, &transfer.keys, opts.verify).await {
+            Ok(bytes) => {
+                summary.copied += 1;
+                summary.bytes += bytes;
+                info!(
+                    tag = "SYNC",
+                    id = %transfer.id,
+                    keys = %transfer.keys.join(","),
+                    reason = transfer.reason,
+                    direction = %direction,
+                    size = %format_bytes(bytes as f64),
+                    "Copied"
+                );
+            }
+            Err(e) => {
+                summary.failed += 1;
+                summary.failure.get_or_insert(e.kind());
+                error!(
+                    tag = "FAIL",
+                    id = %transfer.id,
+                    keys = %transfer.keys.join(","),
+                    direction = %direction,
+                    reason = %e,
+                    "Copy failed"
+                );
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// Copies one asset and returns its size.
+async fn copy(from: &Endpoint, to: &Endpoint, keys: &[String], verify: bool) -> Result<u64, Error> {
+    let data = from
+        .fetch(&keys[0])
+        .await
+        .map_err(|e| Error::new(from.failure(), e))?;
+    let size = data.len() as u64;
+    let hash = sha256_hex(&data);
+
+    let assigned = to
+        .put(keys, data)
+        .await
+        .map_err(|e| Error::new(to.failure(), e))?;
+    let skipped: Vec<&str> = keys
+        .iter()
+        .filter(|k| !assigned.contains(k))
+        .map(String::as_str)
+        .collect();
+    if !skipped.is_empty() {
+        warn!(
+            tag = "WARN",
+            keys = %skipped.join(","),
+            "Keys belong to another asset on the receiving side and were left alone"
+        );
+    }
+
+    if verify {
+        let copied = to
+            .fetch(&keys[0])
+            .await
+            .map_err(|e| Error::new(to.failure(), e))?;
+        if sha256_hex(&copied) != hash {
+            return Err(Error::new(
+                Kind::Data,
+                format!(
+                    "hash mismatch after copy ({} bytes sent, {} read back)",
+                    size,
+                    copied.len()
+                ),
+            ));
+        }
+    }
+    Ok(size)
+}
+
+/// Keys present on both sides with equal size and format but different
+/// content. Fetches every such key from both sides.
+async fn differing(
+    source: &Endpoint,
+    target: &Endpoint,
+    source_inv: &Inventory,
+    target_inv: &Inventory,
+) -> Result<HashSet<String>, Error> {
+    let mut differ = HashSet::new();
+    for (key, s) in source_inv {
+        let Some(t) = target_inv.get(key) else {
+            continue;
+        };
+        if s.size != t.size || s.format != t.format {
+            continue;
+        }
+        let (a, b) = tokio::join!(source.fetch(key), target.fetch(key));
+        let a = a.map_err(|e| Error::new(source.failure(), e))?;
+        let b = b.map_err(|e| Error::new(target.failure(), e))?;
+        if sha256_hex(&a) != sha256_hex(&b) {
+            differ.insert(key.clone());
+        }
+    }
+    Ok(differ)
+}
+
+fn other(side: Side) -> Side {
+    match side {
+        Side::Source => Side::Target,
+        Side::Target => Side::Source,
+    }
+}
@@ -0,0 +1,157 @@
+//! Finding what changed since the last poll. Octa has no change feed, so
+//! assets are followed by `updated_at` (indexed, and set on every upload),
+//! with a rowid watermark for rows that never got a timestamp.
+
+use rusqlite::{params, Connection, Result, Row};
+use std::collections::HashSet;
+
+/// One created or replaced asset.
+#[derive(Debug)]
+pub struct Change {
+    pub id: String,
+    /// `true` for a new asset, `false` for a new image under existing keys.
+    pub created: bool,
+    pub keys: Vec<String>,
+    pub size: Option<i64>,
+    pub format: Option<String>,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub updated_at: Option<String>,
+}
+
+/// Where to start following.
+#[derive(Debug, Clone)]
+pub enum Start {
+    /// Only what changes from now on.
+    Now,
+    /// Everything updated at or after this timestamp, then what follows.
+    Since(String),
+    /// Every asset in the database, then what follows.
+    Beginning,
+}
+
+/// What has been reported so far.
+#[derive(Debug)]
+pub struct Follower {
+    /// `updated_at` of the newest change reported.
+    watermark: String,
+    /// Assets reported at exactly `watermark`, so the rows sharing it are
+    /// not reported again on the next poll.
+    seen: HashSet<String>,
+    /// Highest rowid reported among assets without `updated_at`.
+    rowid: i64,
+}
+
+const COLUMNS: &str = "i.id, CAST(i.created_at AS TEXT), CAST(i.updated_at AS TEXT),
+    COALESCE(i.size, LENGTH(i.data)), i.format, i.width, i.height";
+
+impl Follower {
+    pub fn new(conn: &Connection, start: &Start) -> Result<Self> {
+        let (latest, rowid): (Option<String>, i64) = conn.query_row(
+            "SELECT (SELECT MAX(CAST(updated_at AS TEXT)) FROM images),
+                    (SELECT IFNULL(MAX(rowid), 0) FROM images)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let mut follower = Self {
+            watermark: String::new(),
+            seen: HashSet::new(),
+            rowid: 0,
+        };
+        match start {
+            Start::Now => {
+                follower.watermark = latest.unwrap_or_default();
+                follower.rowid = rowid;
+                // Everything at the current watermark is old news too.
+                let mut stmt =
+                    conn.prepare("SELECT id FROM images WHERE CAST(updated_at AS TEXT) = ?1")?;
+                follower.seen = stmt
+                    .query_map([&follower.watermark], |row| row.get(0))?
+                    .collect::<Result<_>>()?;
+            }
+            // Rows without a timestamp cannot be placed in time: only new
+            // ones are reported.
+            Start::Since(since) => {
+                follower.watermark = since.clone();
+                follower.rowid = rowid;
+            }
+            Start::Beginning => {}
+        }
+        Ok(follower)
+    }
+
+    /// Hands every asset changed since the last poll to `emit`, oldest
+    /// first. A write committed with an `updated_at` older than one already
+    /// reported is missed; the servers write one upload at a time, so that
+    /// takes two writers with skewed clocks.
+    pub fn poll<E>(
+        &mut self,
+        conn: &Connection,
+        mut emit: impl FnMut(&Change) -> std::result::Result<(), E>,
+    ) -> std::result::Result<usize, E>
+    where
+        E: From<rusqlite::Error>,
+    {
+        let mut keys = conn.prepare_cached(
+            "SELECT key FROM key_mappings WHERE image_id = ?1 ORDER BY created_at, key",
+        )?;
+        let mut changed = 0;
+
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT {COLUMNS} FROM images i
+             WHERE i.updated_at >= ?1 ORDER BY i.updated_at, i.id"
+        ))?;
+        let mut rows = stmt.query([&self.watermark])?;
+        while let Some(row) = rows.next()? {
+            let mut change = change(row)?;
+            let updated_at = change.updated_at.clone().unwrap_or_default();
+            if updated_at == self.watermark && self.seen.contains(&change.id) {
+                continue;
+            }
+            if updated_at > self.watermark {
+                self.watermark = updated_at;
+                self.seen.clear();
+            }
+            self.seen.insert(change.id.clone());
+            change.keys = keys
+                .query_map([&change.id], |row| row.get(0))?
+                .collect::<Result<_>>()?;
+            emit(&change)?;
+            changed += 1;
+        }
+
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT {COLUMNS}, i.rowid FROM images i
+             WHERE i.updated_at IS NULL AND i.rowid > ?1 ORDER BY i.rowid"
+        ))?;
+        let mut rows = stmt.query(params![self.rowid])?;
+        while let Some(row) = rows.next()? {
+            let mut change = change(row)?;
+            change.created = true;
+            self.rowid = row.get(7)?;
+            change.keys = keys
+                .query_map([&change.id], |row| row.get(0))?
+                .collect::<Result<_>>()?;
+            emit(&change)?;
+            changed += 1;
+        }
+        Ok(changed)
+    }
+}
+
+fn change(row: &Row) -> Result<Change> {
+    let created_at: Option<String> = row.get(1)?;
+    let updated_at: Option<String> = row.get(2)?;
+    Ok(Change {
+        id: row.get(0)?,
+        // The servers set both to the same instant on upload, and only
+        // updated_at when an image is replaced.
+        created: created_at.is_none() || created_at == updated_at,
+        keys: Vec::new(),
+        size: row.get(3)?,
+        format: row.get(4)?,
+        width: row.get(5)?,
+        height: row.get(6)?,
+        updated_at,
+    })
+}
@@ -0,0 +1,283 @@
+use clap::Parser;
+use follow::{Change, Follower, Start};
+use octa_config::{ConfigError, DatabaseConfig, Validate};
+use octa_errors::{Error, Kind};
+use octa_logging::LogFormat;
+use octa_warden_core::db::{self, OpenOptions};
+use octa_warden_core::schedule::parse_interval;
+use octa_warden_core::tenants::Attribution;
+use serde::Deserialize;
+use serde_json::json;
+use std::fs::OpenOptions as FileOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+use tracing::{error, info, Level};
+
+mod follow;
+
+/*
+OCTA-TAIL: Live stream of new uploads
+=============================================
+Mission: Follow the database and print every created or replaced asset as
+         one NDJSON line per key (key, size, format, tenant), for piping
+         into moderation or cache warming, or for watching uploads during
+         an incident.
+Safety:  Read-only. Polls the indexed updated_at column, so following a
+         busy database costs one index range scan per interval.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Stream new and replaced Octa uploads as NDJSON"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// SQLite database to follow (overrides database.path; the config file becomes optional)
+    #[arg(long = "db", env = "OCTA_DB_PATH", value_name = "PATH")]
+    db_path: Option<String>,
+
+    /// Milliseconds to wait on a locked database before giving up (SQLITE_BUSY)
+    #[arg(long, default_value_t = 5000)]
+    busy_timeout: u64,
+
+    /// Also report assets updated at or after this UTC time ("2026-03-15 08:00:00")
+    #[arg(long, conflicts_with = "from_start", value_name = "TIME")]
+    since: Option<String>,
+
+    /// Report every asset in the database first
+    #[arg(long)]
+    from_start: bool,
+
+    /// Report what is new and exit, instead of following
+    #[arg(long)]
+    once: bool,
+
+    /// Only report keys starting with this prefix
+    #[arg(long)]
+    prefix: Option<String>,
+
+    /// Append the events to this file instead of printing them
+    #[arg(long, value_name = "PATH")]
+    out: Option<String>,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+}
+
+/// The parts of config.yaml octa-tail reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    database: DatabaseConfig,
+    tail: TailConfig,
+    quota: Tenants,
+}
+
+/// `tail:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TailConfig {
+    /// Pause between polls.
+    interval: String,
+}
+
+impl Default for TailConfig {
+    fn default() -> Self {
+        Self {
+            interval: "1s".to_string(),
+        }
+    }
+}
+
+/// The tenants of `quota:`, so events name the tenant octa-quota would;
+/// the limits are octa-quota's business.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct Tenants {
+    depth: usize,
+    tenants: Vec<Tenant>,
+}
+
+impl Default for Tenants {
+    fn default() -> Self {
+        Self {
+            depth: 1,
+            tenants: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Tenant {
+    name: String,
+    #[serde(default)]
+    prefixes: Vec<String>,
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        if self.database.path.trim().is_empty() {
+            problems.push(("database.path".to_string(), "is required".to_string()));
+        }
+        if let Err(e) = parse_interval(&self.tail.interval) {
+            problems.push(("tail.interval".to_string(), e));
+        }
+        problems
+    }
+}
+
+/// Like octa-gc: `--db` makes the config file optional.
+fn load_config(path: Option<&str>, db_path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    let found = octa_config::discover(path.map(Path::new)).filter(|p| p.exists());
+    let mut config: FileConfig = match (&found, db_path) {
+        (Some(found), _) => octa_config::read(found)?,
+        (None, Some(_)) => octa_config::from_env()?,
+        (None, None) => {
+            return Err(match path {
+                Some(path) => ConfigError::Read {
+                    path: path.into(),
+                    source: std::io::ErrorKind::NotFound.into(),
+                },
+                None => ConfigError::NotFound,
+            })
+        }
+    };
+    if let Some(db_path) = db_path {
+        config.database.path = db_path.to_string();
+    }
+    octa_config::check(found.as_deref().unwrap_or(Path::new("<defaults>")), config)
+}
+
+/// The octa-tail command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa tail` the arguments it was given.
+pub fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    // Printed events own stdout; the logs go to stderr.
+    octa_logging::init(args.log_format, Level::INFO, false, args.out.is_none());
+
+    let config = match load_config(args.config.as_deref(), args.db_path.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    if !Path::new(&config.database.path).exists() {
+        error!(tag = "FATAL", path = %config.database.path, "Database file not found");
+        return Kind::NoInput.exit_code();
+    }
+    match run(&args, &config) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!(tag = "FATAL", path = %config.database.path, reason = %e, "Tail failed");
+            e.exit_code()
+        }
+    }
+}
+
+fn run(args: &Args, config: &FileConfig) -> Result<(), Error> {
+    let attribution = Attribution::new(
+        config.quota.tenants.iter().flat_map(|t| {
+            t.prefixes
+                .iter()
+                .map(move |prefix| (prefix.clone(), t.name.clone()))
+        }),
+        config.quota.depth,
+    );
+    let interval = parse_interval(&config.tail.interval).unwrap_or(Duration::from_secs(1));
+    let start = match (&args.since, args.from_start) {
+        // Stored as "2026-03-15 08:00:00.123+00:00"; text order is time order.
+        (Some(since), _) => Start::Since(since.trim().replacen('T', " ", 1)),
+        (None, true) => Start::Beginning,
+        (None, false) => Start::Now,
+    };
+    let mut out: Box<dyn Write> = match &args.out {
+        Some(path) => Box::new(
+            FileOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| Error::new(Kind::Io, format!("{}: {}", path, e)))?,
+        ),
+        None => Box::new(io::stdout()),
+    };
+
+    let opts = OpenOptions {
+        busy_timeout: Duration::from_millis(args.busy_timeout),
+        immutable: false,
+    };
+    let conn = db::open_read_only(&config.database.path, &opts)?;
+    let mut follower = Follower::new(&conn, &start)?;
+    info!(
+        tag = "→",
+        database = %config.database.path,
+        start = ?start,
+        interval = %config.tail.interval,
+        "Following uploads"
+    );
+
+    loop {
+        let mut closed = false;
+        let polled = follower.poll(&conn, |change| {
+            emit(&mut out, change, &attribution, args.prefix.as_deref()).map_err(|e| {
+                closed = e.kind() == io::ErrorKind::BrokenPipe;
+                Error::from(e)
+            })
+        });
+        let changed = match polled {
+            Ok(changed) => changed,
+            // The reader went away (`octa-tail | head`): a normal end.
+            Err(_) if closed => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if args.once {
+            info!(tag = "OK", assets = changed, "Caught up");
+            return Ok(());
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// One line per key of the asset (one with a null key for an asset
+/// without keys), flushed at once for readers downstream.
+fn emit(
+    out: &mut dyn Write,
+    change: &Change,
+    attribution: &Attribution,
+    prefix: Option<&str>,
+) -> io::Result<()> {
+    let keys: Vec<Option<&str>> = match change.keys.is_empty() {
+        true => vec![None],
+        false => change.keys.iter().map(|key| Some(key.as_str())).collect(),
+    };
+    let event = if change.created { "created" } else { "updated" };
+    for key in keys {
+        if let Some(prefix) = prefix {
+            if !key.is_some_and(|key| key.starts_with(prefix)) {
+                continue;
+            }
+        }
+        let line = json!({
+            "at": change.updated_at,
+            "event": event,
+            "key": key,
+            "id": change.id,
+            "size": change.size,
+            "format": change.format,
+            "width": change.width,
+            "height": change.height,
+            "tenant": key.and_then(|key| attribution.tenant(key)),
+        });
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()
+}
@@ -0,0 +1,95 @@
+use octa_image::image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+use std::io::Cursor;
+
+/// An asset to upload: its keys and an image drawn from the key, so two
+/// fixtures never share bytes and a rerun draws the same ones.
+///
+/// ```
+/// use octa_testkit::Fixture;
+///
+/// let alice = Fixture::new("alice").size(320, 200).png().alias("alice-old");
+/// assert_eq!(alice.keys(), "alice,alice-old");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    pub key: String,
+    pub aliases: Vec<String>,
+    pub width: u32,
+    pub height: u32,
+    /// JPEG or PNG, the formats the servers accept.
+    pub format: ImageFormat,
+}
+
+impl Fixture {
+    /// A 64×64 JPEG.
+    pub fn new(key: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            aliases: Vec::new(),
+            width: 64,
+            height: 64,
+            format: ImageFormat::Jpeg,
+        }
+    }
+
+    /// `count` fixtures keyed `<prefix>0`, `<prefix>1`, ...
+    pub fn batch(prefix: &str, count: usize) -> Vec<Self> {
+        (0..count)
+            .map(|i| Self::new(format!("{}{}", prefix, i)))
+            .collect()
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn png(mut self) -> Self {
+        self.format = ImageFormat::Png;
+        self
+    }
+
+    pub fn jpeg(mut self) -> Self {
+        self.format = ImageFormat::Jpeg;
+        self
+    }
+
+    pub fn alias(mut self, key: impl Into<String>) -> Self {
+        self.aliases.push(key.into());
+        self
+    }
+
+    /// The `keys` form field: the key, then the aliases.
+    pub fn keys(&self) -> String {
+        std::iter::once(self.key.as_str())
+            .chain(self.aliases.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// The encoded image: a gradient whose colors come from the key.
+    pub fn image(&self) -> Vec<u8> {
+        // FNV-1a; stable across runs, unlike the std hasher.
+        let hash = self.key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+            (h ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+        let [r, g, b, ..] = hash.to_le_bytes();
+        let (width, height) = (self.width.max(1), self.height.max(1));
+        let pixels = RgbImage::from_fn(width, height, |x, y| {
+            let dx = (x * 255 / width) as u8;
+            let dy = (y * 255 / height) as u8;
+            Rgb([r ^ dx, g ^ dy, b.wrapping_add(dx / 2 + dy / 2)])
+        });
+        let img = DynamicImage::ImageRgb8(pixels);
+        // Both encoders only fail on I/O, and this writes to memory.
+        if self.format == ImageFormat::Png {
+            let mut data = Vec::new();
+            img.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+                .expect("PNG encoding to memory");
+            data
+        } else {
+            octa_image::encode_jpeg(&img, octa_image::QUALITY).expect("JPEG encoding to memory")
+        }
+    }
+}
@@ -0,0 +1,393 @@
+//! Integration tests against a real Octa server: [`TestServer`] starts a
+//! server binary (octa-server or the Go server) on a free port with a fresh
+//! database, or an embedded mock of the HTTP API when no binary is
+//! configured, then seeds [`Fixture`]s and drives uploads, reads and deletes
+//! with assertions. Tools that work on the database (octa-warden, octa-gc)
+//! get its path from [`TestServer::db_path`].
+//!
+//! ```no_run
+//! use octa_testkit::{Fixture, TestServer};
+//!
+//! # async fn example() -> Result<(), octa_testkit::Error> {
+//! let octa = TestServer::start().await?;
+//! octa.seed(&Fixture::batch("team/", 3)).await;
+//! octa.assert_listed("team/", &["team/0", "team/1", "team/2"]).await;
+//!
+//! octa.delete("team/1").await;
+//! octa.assert_missing("team/1").await;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The backend comes from the `testkit` section of config.yaml, unless the
+//! builder names one:
+//!
+//! ```yaml
+//! testkit:
+//!   server: "rust/target/release/octa-server"  # relative to config.yaml; unset runs the mock
+//!   startup_timeout: "10s"
+//! ```
+//!
+//! The mock is also served on its own by octa-mock, with latency and
+//! failures injected: see [`mock::router`].
+
+mod fixture;
+pub mod mock;
+mod process;
+
+pub use fixture::Fixture;
+pub use octa_client::{Asset, Client, ListItem, Upload};
+
+use octa_client::{Mode, UploadOptions};
+use octa_config::{ConfigError, Validate};
+use octa_warden_core::schedule::parse_interval;
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Why a server could not be started.
+#[derive(Debug)]
+pub enum Error {
+    Config(ConfigError),
+    /// The binary or its directory could not be set up.
+    Spawn(String),
+    /// The server exited or never listened; carries the end of its output.
+    Startup(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(e) => write!(f, "{}", e),
+            Error::Spawn(e) => write!(f, "could not start the server: {}", e),
+            Error::Startup(e) => write!(f, "server did not come up: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// What serves the API under test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Backend {
+    /// A server binary, started with its own config.yaml and database.
+    Binary(PathBuf),
+    /// The in-process mock: same routes and JSON, no database, and a plain
+    /// placeholder instead of generated avatars.
+    Mock,
+}
+
+/// The parts of config.yaml the testkit reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    testkit: TestkitConfig,
+}
+
+/// `testkit:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TestkitConfig {
+    server: Option<PathBuf>,
+    startup_timeout: String,
+}
+
+impl Default for TestkitConfig {
+    fn default() -> Self {
+        Self {
+            server: None,
+            startup_timeout: "10s".to_string(),
+        }
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        if let Err(e) = parse_interval(&self.testkit.startup_timeout) {
+            problems.push(("testkit.startup_timeout".to_string(), e));
+        }
+        problems
+    }
+}
+
+/// Builds a [`TestServer`]; see [`TestServer::builder`].
+#[derive(Debug, Default)]
+pub struct Builder {
+    backend: Option<Backend>,
+    secret: Option<String>,
+    startup_timeout: Option<Duration>,
+    config: Option<PathBuf>,
+}
+
+impl Builder {
+    /// Runs this binary, ignoring `testkit.server`.
+    pub fn binary(mut self, path: impl Into<PathBuf>) -> Self {
+        self.backend = Some(Backend::Binary(path.into()));
+        self
+    }
+
+    /// Runs the mock, ignoring `testkit.server`.
+    pub fn mock(mut self) -> Self {
+        self.backend = Some(Backend::Mock);
+        self
+    }
+
+    /// Upload secret of the instance (default: a random one).
+    pub fn secret(mut self, secret: impl Into<String>) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+
+    /// How long a binary gets to start listening (default:
+    /// `testkit.startup_timeout`, 10s).
+    pub fn startup_timeout(mut self, timeout: Duration) -> Self {
+        self.startup_timeout = Some(timeout);
+        self
+    }
+
+    /// The config.yaml to read `testkit` from, instead of the usual lookup.
+    pub fn config(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config = Some(path.into());
+        self
+    }
+
+    pub async fn start(self) -> Result<TestServer, Error> {
+        let (configured, timeout) = load_config(self.config.as_deref())?;
+        let backend = self.backend.or(configured).unwrap_or(Backend::Mock);
+        let timeout = self.startup_timeout.unwrap_or(timeout);
+        let secret = self
+            .secret
+            .unwrap_or_else(|| format!("testkit-{}", uuid::Uuid::new_v4()));
+
+        let (process, mock, base_url) = match &backend {
+            Backend::Binary(path) => {
+                let process = process::Process::start(path, &secret, timeout).await?;
+                let base_url = format!("http://127.0.0.1:{}", process.port);
+                (Some(process), None, base_url)
+            }
+            Backend::Mock => {
+                let mock = mock::Mock::start(&secret).map_err(|e| Error::Spawn(e.to_string()))?;
+                let base_url = format!("http://{}", mock.addr);
+                (None, Some(mock), base_url)
+            }
+        };
+        let client = Client::builder(&base_url)
+            .secret(&secret)
+            .build()
+            .map_err(|e| Error::Spawn(e.to_string()))?;
+        Ok(TestServer {
+            process,
+            _mock: mock,
+            backend,
+            base_url,
+            secret,
+            client,
+        })
+    }
+}
+
+/// `testkit.server` (relative to the file) and `testkit.startup_timeout`.
+/// Without a file the mock runs.
+fn load_config(path: Option<&Path>) -> Result<(Option<Backend>, Duration), Error> {
+    let (config, dir): (FileConfig, Option<PathBuf>) = match octa_config::discover(path) {
+        Some(path) => {
+            let config = octa_config::load(&path).map_err(Error::Config)?;
+            (config, path.parent().map(Path::to_path_buf))
+        }
+        None => {
+            let config: FileConfig = octa_config::from_env().map_err(Error::Config)?;
+            let config =
+                octa_config::check(Path::new("<environment>"), config).map_err(Error::Config)?;
+            (config, None)
+        }
+    };
+    let timeout =
+        parse_interval(&config.testkit.startup_timeout).unwrap_or(Duration::from_secs(10));
+    let backend = config.testkit.server.map(|server| {
+        let server = match &dir {
+            Some(dir) if server.is_relative() => dir.join(server),
+            _ => server,
+        };
+        Backend::Binary(server)
+    });
+    Ok((backend, timeout))
+}
+
+/// A server for one test, stopped (and its directory removed) on drop.
+///
+/// The drive and assert helpers panic with the server's answer, like
+/// `assert!`; use [`TestServer::client`] to check errors yourself.
+pub struct TestServer {
+    process: Option<process::Process>,
+    /// Kept for its drop, which stops it.
+    _mock: Option<mock::Mock>,
+    backend: Backend,
+    base_url: String,
+    secret: String,
+    client: Client,
+}
+
+impl TestServer {
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
+    /// The backend `testkit.server` names, else the mock.
+    pub async fn start() -> Result<Self, Error> {
+        Self::builder().start().await
+    }
+
+    pub fn backend(&self) -> &Backend {
+        &self.backend
+    }
+
+    /// `http://127.0.0.1:<port>`
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+
+    /// A client with the secret set.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// The server's SQLite file; `None` for the mock. Octa's tools can run
+    /// against it while the server is up, as they would in production.
+    pub fn db_path(&self) -> Option<PathBuf> {
+        self.dir().map(|dir| dir.join("avatar.db"))
+    }
+
+    /// The server's config.yaml, to hand to a tool's `--config`; `None` for
+    /// the mock.
+    pub fn config_path(&self) -> Option<PathBuf> {
+        self.dir().map(|dir| dir.join("config.yaml"))
+    }
+
+    /// What the server printed; `None` for the mock.
+    pub fn log_path(&self) -> Option<PathBuf> {
+        self.dir().map(|dir| dir.join("server.log"))
+    }
+
+    fn dir(&self) -> Option<&Path> {
+        self.process.as_ref().map(|process| process.dir.as_path())
+    }
+
+    /// Uploads every fixture, in order.
+    pub async fn seed(&self, fixtures: &[Fixture]) -> Vec<Upload> {
+        let mut uploads = Vec::with_capacity(fixtures.len());
+        for fixture in fixtures {
+            uploads.push(self.upload(fixture).await);
+        }
+        uploads
+    }
+
+    /// Uploads a fixture as is (`mode=original`), so the stored bytes are
+    /// [`Fixture::image`].
+    pub async fn upload(&self, fixture: &Fixture) -> Upload {
+        let options = fixture.aliases.iter().fold(
+            UploadOptions::new().mode(Mode::Original),
+            |options, alias| options.alias(alias),
+        );
+        self.client
+            .upload_avatar(&fixture.key, fixture.image(), options)
+            .await
+            .unwrap_or_else(|e| panic!("upload of '{}' failed: {}", fixture.key, e))
+    }
+
+    /// `GET /u/<key>`; unknown keys answer with a generated image.
+    pub async fn read(&self, key: &str) -> Vec<u8> {
+        self.client
+            .get_avatar(key)
+            .await
+            .unwrap_or_else(|e| panic!("read of '{}' failed: {}", key, e))
+            .to_vec()
+    }
+
+    /// Deletes the asset behind `key`; returns its id.
+    pub async fn delete(&self, key: &str) -> String {
+        self.client
+            .delete(key)
+            .await
+            .unwrap_or_else(|e| panic!("delete of '{}' failed: {}", key, e))
+    }
+
+    /// Every key under `prefix`, following pages.
+    pub async fn keys(&self, prefix: &str) -> Vec<String> {
+        let mut keys = Vec::new();
+        let mut after = None;
+        loop {
+            let page = self
+                .client
+                .list(prefix, after.as_deref(), 1000)
+                .await
+                .unwrap_or_else(|e| panic!("listing '{}' failed: {}", prefix, e));
+            keys.extend(page.items.into_iter().map(|item| item.key));
+            match page.next {
+                Some(next) => after = Some(next),
+                None => return keys,
+            }
+        }
+    }
+
+    /// Asserts that `key` is stored, returning its metadata.
+    pub async fn assert_stored(&self, key: &str) -> Asset {
+        match self.client.stat(key).await {
+            Ok(asset) => asset,
+            Err(e) => panic!("expected '{}' to be stored: {}", key, e),
+        }
+    }
+
+    /// Asserts that no asset has `key`.
+    pub async fn assert_missing(&self, key: &str) {
+        match self.client.stat(key).await {
+            Err(octa_client::Error::NotFound { .. }) => {}
+            Ok(asset) => panic!("expected '{}' to be missing, found asset {}", key, asset.id),
+            Err(e) => panic!("expected '{}' to be missing: {}", key, e),
+        }
+    }
+
+    /// Asserts that `GET /u/<key>` returns exactly `expected`.
+    pub async fn assert_serves(&self, key: &str, expected: &[u8]) {
+        let served = self.read(key).await;
+        assert!(
+            served == expected,
+            "'{}' served {} bytes that differ from the {} expected",
+            key,
+            served.len(),
+            expected.len()
+        );
+    }
+
+    /// Asserts that `key` is stored with these dimensions.
+    pub async fn assert_dimensions(&self, key: &str, width: u32, height: u32) {
+        let asset = self.assert_stored(key).await;
+        assert!(
+            (asset.width, asset.height) == (width, height),
+            "'{}' is {}x{}, expected {}x{}",
+            key,
+            asset.width,
+            asset.height,
+            width,
+            height
+        );
+    }
+
+    /// Asserts that the keys under `prefix` are exactly `expected`, in key
+    /// order.
+    pub async fn assert_listed(&self, prefix: &str, expected: &[&str]) {
+        let keys = self.keys(prefix).await;
+        assert!(
+            keys == expected,
+            "keys under '{}' are {:?}, expected {:?}",
+            prefix,
+            keys,
+            expected
+        );
+    }
+}
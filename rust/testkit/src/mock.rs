@@ -0,0 +1,585 @@
+//! An in-process stand-in for the Octa HTTP API: the routes, JSON and error
+//! codes of octa-server, over a map instead of SQLite. Unknown keys get a
+//! plain grey PNG rather than a generated avatar.
+//!
+//! [`router`] is the same API with [`Faults`] injected, as octa-mock serves
+//! it.
+
+use axum::extract::{Multipart, Path, Query, Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use octa_image::image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+use octa_image::Profile;
+use octa_warden_core::export::sha256_hex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Cursor};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// Keys per upload, as `image.max_key_limit` defaults.
+const MAX_KEYS: usize = 7;
+
+struct Stored {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    format: String,
+    created_at: String,
+    updated_at: String,
+    /// In the order they were assigned.
+    keys: Vec<String>,
+}
+
+#[derive(Default)]
+struct Store {
+    assets: HashMap<String, Stored>,
+    /// Key to asset id, in key order for listings.
+    keys: BTreeMap<String, String>,
+}
+
+struct MockState {
+    secret: String,
+    base_url: String,
+    store: Mutex<Store>,
+}
+
+impl MockState {
+    fn store(&self) -> MutexGuard<'_, Store> {
+        self.store.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+type Shared = Arc<MockState>;
+
+/// Artificial latency and failures added to every request but `/health`,
+/// so a readiness check sees the mock as up. The default adds none.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Faults {
+    /// Added before every response.
+    pub latency: Duration,
+    /// Up to this much more or less than `latency`, drawn per request.
+    pub jitter: Duration,
+    /// Share of requests (0-1) answered with `status` instead of being
+    /// served; nothing is stored or deleted for them.
+    pub failure_rate: f64,
+    /// Status of a failed request, a 4xx or 5xx; 500 by default.
+    pub status: StatusCode,
+    /// The draws of a mock with the same seed repeat, request by request.
+    pub seed: u64,
+}
+
+impl Default for Faults {
+    fn default() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            failure_rate: 0.0,
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            seed: 0,
+        }
+    }
+}
+
+impl Faults {
+    fn is_none(&self) -> bool {
+        self.latency.is_zero() && self.jitter.is_zero() && self.failure_rate <= 0.0
+    }
+}
+
+struct Injector {
+    faults: Faults,
+    rng: Mutex<StdRng>,
+}
+
+impl Injector {
+    /// The delay of a request, and whether it fails.
+    fn draw(&self) -> (Duration, bool) {
+        let mut rng = self.rng.lock().unwrap_or_else(|e| e.into_inner());
+        let jitter = self.faults.jitter.as_millis() as i64;
+        let offset = if jitter > 0 {
+            rng.random_range(-jitter..=jitter)
+        } else {
+            0
+        };
+        let delay = (self.faults.latency.as_millis() as i64 + offset).max(0) as u64;
+        let failed = rng.random::<f64>() < self.faults.failure_rate;
+        (Duration::from_millis(delay), failed)
+    }
+}
+
+/// The mock API, with `faults` injected. Upload responses link to
+/// `base_url`; writes and listings need `secret` in `X-Secret-Key`.
+pub fn router(secret: &str, base_url: &str, faults: Faults) -> Router {
+    let state = Arc::new(MockState {
+        secret: secret.to_string(),
+        base_url: base_url.to_string(),
+        store: Mutex::default(),
+    });
+    let app = Router::new()
+        .route("/avatar/{seed}", get(placeholder))
+        .route("/u/{*key}", get(user_avatar))
+        .route("/upload", post(upload))
+        .route("/upload/delete", delete(remove))
+        .route("/upload/stat", get(stat))
+        .route("/upload/list", get(list));
+    let app = if faults.is_none() {
+        app
+    } else {
+        let injector = Arc::new(Injector {
+            rng: Mutex::new(StdRng::seed_from_u64(faults.seed)),
+            faults,
+        });
+        app.layer(middleware::from_fn_with_state(injector, inject))
+    };
+    app.route("/health", get(health)).with_state(state)
+}
+
+/// Waits out the drawn delay, then serves the request or answers for it
+/// with the failure status. Failed responses carry `X-Mock-Fault`.
+async fn inject(State(injector): State<Arc<Injector>>, request: Request, next: Next) -> Response {
+    let (delay, failed) = injector.draw();
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+    if !failed {
+        return next.run(request).await;
+    }
+    let status = injector.faults.status;
+    let code = if status.is_server_error() {
+        "server/internal_error"
+    } else {
+        "request/invalid_parameters"
+    };
+    let mut response = error(status, code, "Injected failure.").into_response();
+    response
+        .headers_mut()
+        .insert("X-Mock-Fault", HeaderValue::from_static("failure"));
+    response
+}
+
+/// A running mock; stopped on drop.
+pub(crate) struct Mock {
+    pub addr: SocketAddr,
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Mock {
+    /// Serves on a free port of 127.0.0.1 from a thread of its own, so it
+    /// outlives whichever runtime the test uses.
+    pub fn start(secret: &str) -> io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let listener = {
+            let _guard = runtime.enter();
+            tokio::net::TcpListener::from_std(listener)?
+        };
+
+        let app = router(secret, &format!("http://{}", addr), Faults::default());
+
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        let thread = thread::spawn(move || {
+            runtime.block_on(async move {
+                let _ = axum::serve(listener, app)
+                    .with_graceful_shutdown(async {
+                        let _ = stopped.await;
+                    })
+                    .await;
+            });
+        });
+        Ok(Self {
+            addr,
+            shutdown: Some(shutdown),
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for Mock {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// `{"code", "message", "status"}`, as the servers answer errors.
+struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = json!({
+            "code": self.code,
+            "message": self.message,
+            "status": self.status.as_u16(),
+        });
+        (self.status, Json(body)).into_response()
+    }
+}
+
+fn error(status: StatusCode, code: &'static str, message: &str) -> ApiError {
+    ApiError {
+        status,
+        code,
+        message: message.to_string(),
+    }
+}
+
+fn not_found(message: &str) -> ApiError {
+    error(StatusCode::NOT_FOUND, "resource/not_found", message)
+}
+
+fn invalid(message: &str) -> ApiError {
+    error(
+        StatusCode::BAD_REQUEST,
+        "request/invalid_parameters",
+        message,
+    )
+}
+
+fn authorize(state: &MockState, headers: &HeaderMap) -> Result<(), ApiError> {
+    let given = headers
+        .get("X-Secret-Key")
+        .map(HeaderValue::as_bytes)
+        .unwrap_or_default();
+    if given != state.secret.as_bytes() {
+        return Err(error(
+            StatusCode::FORBIDDEN,
+            "auth/invalid_credentials",
+            "Invalid secret key.",
+        ));
+    }
+    Ok(())
+}
+
+fn now() -> String {
+    chrono::Utc::now().to_rfc3339()
+}
+
+/// POST /upload
+async fn upload(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Json<Value>, ApiError> {
+    authorize(&state, &headers)?;
+    let mut fields: HashMap<String, String> = HashMap::new();
+    let mut avatar = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| invalid(&e.body_text()))?
+    {
+        let name = field.name().unwrap_or_default().to_string();
+        if name == "avatar" {
+            let data = field.bytes().await.map_err(|e| invalid(&e.body_text()))?;
+            avatar = Some(data.to_vec());
+        } else {
+            let text = field.text().await.map_err(|e| invalid(&e.body_text()))?;
+            fields.insert(name, text);
+        }
+    }
+
+    let keys = octa_key::parse_list(fields.get("keys").map(String::as_str).unwrap_or_default());
+    if keys.is_empty() {
+        return Err(invalid("At least one valid key is required."));
+    }
+    if keys.len() > MAX_KEYS {
+        return Err(invalid("Too many keys provided."));
+    }
+    let Some(data) = avatar else {
+        return Err(invalid("Missing 'avatar' file field."));
+    };
+    if !octa_image::is_allowed(&data) {
+        return Err(error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "request/invalid_media",
+            "Unsupported file type.",
+        ));
+    }
+    let field = |name: &str| fields.get(name).map(String::as_str);
+    let profile = Profile::from_fields(field("mode"), field("size"), field("scale"));
+    let image = octa_image::process(data, &profile).map_err(|e| {
+        error(
+            StatusCode::BAD_REQUEST,
+            "image/processing_failed",
+            &e.to_string(),
+        )
+    })?;
+    let size = image.data.len();
+
+    // The first key decides between replacing its asset's image and a new
+    // asset; further keys are added when free.
+    let mut store = state.store();
+    let now = now();
+    let (action, id) = match store.keys.get(&keys[0]).cloned() {
+        Some(id) => {
+            if let Some(asset) = store.assets.get_mut(&id) {
+                asset.data = image.data;
+                asset.width = image.width;
+                asset.height = image.height;
+                asset.format = image.format;
+                asset.updated_at = now;
+            }
+            ("updated", id)
+        }
+        None => {
+            let id = uuid::Uuid::new_v4().to_string();
+            store.assets.insert(
+                id.clone(),
+                Stored {
+                    data: image.data,
+                    width: image.width,
+                    height: image.height,
+                    format: image.format,
+                    created_at: now.clone(),
+                    updated_at: now,
+                    keys: Vec::new(),
+                },
+            );
+            ("created", id)
+        }
+    };
+    let mut assigned = Vec::new();
+    for key in &keys {
+        match store.keys.get(key) {
+            Some(owner) if *owner != id => continue,
+            Some(_) => {}
+            None => {
+                store.keys.insert(key.clone(), id.clone());
+                if let Some(asset) = store.assets.get_mut(&id) {
+                    asset.keys.push(key.clone());
+                }
+            }
+        }
+        assigned.push(key.clone());
+    }
+
+    Ok(Json(json!({
+        "status": "success",
+        "action": action,
+        "avatar_id": id,
+        "url": format!("{}/u/{}", state.base_url, assigned[0]),
+        "keys": assigned,
+        "size_kb": size / 1024,
+    })))
+}
+
+#[derive(Deserialize)]
+struct Target {
+    #[serde(default)]
+    key: String,
+    #[serde(default)]
+    id: String,
+}
+
+/// The asset id of `?id=`, or of the asset behind `?key=`.
+fn resolve(store: &Store, target: Target) -> Result<String, ApiError> {
+    if !target.id.is_empty() {
+        return Ok(target.id);
+    }
+    if target.key.is_empty() {
+        return Err(invalid("Parameter 'key' or 'id' is required."));
+    }
+    store
+        .keys
+        .get(&target.key)
+        .cloned()
+        .ok_or_else(|| not_found("Key not found."))
+}
+
+/// DELETE /upload/delete?key=|id=
+async fn remove(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Query(target): Query<Target>,
+) -> Result<Json<Value>, ApiError> {
+    authorize(&state, &headers)?;
+    let mut store = state.store();
+    let id = resolve(&store, target)?;
+    // Like the servers, an unknown id still reports success.
+    if let Some(asset) = store.assets.remove(&id) {
+        for key in asset.keys {
+            store.keys.remove(&key);
+        }
+    }
+    Ok(Json(json!({
+        "status": "success",
+        "action": "deleted",
+        "target": id,
+    })))
+}
+
+/// GET /upload/stat?key=|id=
+async fn stat(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Query(target): Query<Target>,
+) -> Result<Json<Value>, ApiError> {
+    authorize(&state, &headers)?;
+    let store = state.store();
+    let id = resolve(&store, target)?;
+    let asset = store
+        .assets
+        .get(&id)
+        .ok_or_else(|| not_found("Asset not found."))?;
+    let url_key = asset.keys.first().unwrap_or(&id);
+    Ok(Json(json!({
+        "status": "success",
+        "avatar_id": id,
+        "keys": asset.keys,
+        "width": asset.width,
+        "height": asset.height,
+        "format": asset.format,
+        "size": asset.data.len(),
+        "created_at": asset.created_at,
+        "updated_at": asset.updated_at,
+        "url": format!("{}/u/{}", state.base_url, url_key),
+    })))
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    #[serde(default)]
+    prefix: String,
+    #[serde(default)]
+    after: String,
+    limit: Option<String>,
+}
+
+/// GET /upload/list?prefix=&after=&limit=
+async fn list(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<Value>, ApiError> {
+    authorize(&state, &headers)?;
+    let limit = query
+        .limit
+        .and_then(|l| l.parse::<usize>().ok())
+        .filter(|l| (1..=1000).contains(l))
+        .unwrap_or(100);
+    let store = state.store();
+    let items: Vec<Value> = store
+        .keys
+        .iter()
+        .filter(|(key, _)| key.starts_with(&query.prefix) && **key > query.after)
+        .take(limit)
+        .filter_map(|(key, id)| {
+            let asset = store.assets.get(id)?;
+            Some(json!({
+                "key": key,
+                "avatar_id": id,
+                "size": asset.data.len(),
+                "format": asset.format,
+                "updated_at": asset.updated_at,
+            }))
+        })
+        .collect();
+    let next = match items.last() {
+        Some(last) if items.len() == limit => last["key"].clone(),
+        _ => json!(""),
+    };
+    Ok(Json(json!({
+        "status": "success",
+        "items": items,
+        "next": next,
+    })))
+}
+
+/// GET /u/{key}
+async fn user_avatar(
+    State(state): State<Shared>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let stored = {
+        let store = state.store();
+        store
+            .keys
+            .get(&key)
+            .and_then(|id| store.assets.get(id))
+            .map(|asset| (asset.data.clone(), asset.format.clone()))
+    };
+    match stored {
+        Some((data, format)) => {
+            let mime = if format == "jpeg" {
+                "image/jpeg"
+            } else {
+                "image/png"
+            };
+            serve(&headers, data, mime)
+        }
+        None => placeholder_png(&headers),
+    }
+}
+
+/// GET /avatar/{seed}
+async fn placeholder(headers: HeaderMap) -> Response {
+    placeholder_png(&headers)
+}
+
+fn placeholder_png(headers: &HeaderMap) -> Response {
+    let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 64, Rgb([128, 128, 128])));
+    let mut data = Vec::new();
+    match img.write_to(&mut Cursor::new(&mut data), ImageFormat::Png) {
+        Ok(()) => serve(headers, data, "image/png"),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "image/generation_failed",
+            &e.to_string(),
+        )
+        .into_response(),
+    }
+}
+
+/// GET /health
+async fn health(State(state): State<Shared>) -> Json<Value> {
+    let store = state.store();
+    let bytes: usize = store.assets.values().map(|a| a.data.len()).sum();
+    Json(json!({
+        "status": "ok",
+        "assets": store.assets.len(),
+        "bytes": bytes,
+        "uptime_seconds": 0,
+    }))
+}
+
+/// A day of caching, and 304 when the client has the bytes.
+fn serve(headers: &HeaderMap, data: Vec<u8>, mime: &'static str) -> Response {
+    let etag = sha256_hex(&data);
+    let fresh = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains(&etag));
+    let cache = [
+        (header::CONTENT_TYPE, mime.to_string()),
+        (header::CACHE_CONTROL, "public, max-age=86400".to_string()),
+        (header::ETAG, format!("\"{}\"", etag)),
+    ];
+    if fresh {
+        return (StatusCode::NOT_MODIFIED, cache).into_response();
+    }
+    (cache, data).into_response()
+}
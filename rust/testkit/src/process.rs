@@ -0,0 +1,119 @@
+//! A server binary (octa-server or the Go server) started on a free port,
+//! with a config.yaml and database of its own in a temporary directory.
+
+use crate::Error;
+use std::fs::{self, File};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+pub(crate) struct Process {
+    child: Child,
+    pub dir: PathBuf,
+    pub port: u16,
+}
+
+impl Process {
+    pub async fn start(binary: &Path, secret: &str, timeout: Duration) -> Result<Self, Error> {
+        let dir = std::env::temp_dir().join(format!("octa-testkit-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).map_err(|e| Error::Spawn(format!("{}: {}", dir.display(), e)))?;
+        match Self::spawn(binary, secret, timeout, &dir).await {
+            Ok(process) => Ok(process),
+            Err(e) => {
+                let _ = fs::remove_dir_all(&dir);
+                Err(e)
+            }
+        }
+    }
+
+    async fn spawn(
+        binary: &Path,
+        secret: &str,
+        timeout: Duration,
+        dir: &Path,
+    ) -> Result<Self, Error> {
+        let port = free_port().map_err(|e| Error::Spawn(format!("no free port: {}", e)))?;
+        let config = dir.join("config.yaml");
+        // Rate limiting and the Go server's cache off, so tests see every
+        // write at once and can hammer the instance.
+        let yaml = format!(
+            "server:\n  port: {port}\n  env: \"development\"\n\
+             database:\n  path: \"{db}\"\n\
+             security:\n  upload_secret: \"{secret}\"\n  rate_limit:\n    enabled: false\n\
+             cache:\n  enabled: false\n\
+             base_url: \"http://127.0.0.1:{port}\"\n",
+            port = port,
+            db = dir.join("avatar.db").display(),
+            secret = secret,
+        );
+        fs::write(&config, yaml).map_err(|e| Error::Spawn(e.to_string()))?;
+
+        let log = dir.join("server.log");
+        let out = File::create(&log).map_err(|e| Error::Spawn(e.to_string()))?;
+        let err = out.try_clone().map_err(|e| Error::Spawn(e.to_string()))?;
+        // The Go server reads config.yaml from its working directory,
+        // octa-server finds it through $OCTA_CONFIG.
+        let child = Command::new(binary)
+            .current_dir(dir)
+            .env("OCTA_CONFIG", &config)
+            .stdin(Stdio::null())
+            .stdout(out)
+            .stderr(err)
+            .spawn()
+            .map_err(|e| Error::Spawn(format!("{}: {}", binary.display(), e)))?;
+        let mut process = Self {
+            child,
+            dir: dir.to_path_buf(),
+            port,
+        };
+
+        let started = Instant::now();
+        loop {
+            if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                return Ok(process);
+            }
+            if let Ok(Some(status)) = process.child.try_wait() {
+                return Err(Error::Startup(format!(
+                    "server exited with {}: {}",
+                    status,
+                    tail(&log)
+                )));
+            }
+            if started.elapsed() > timeout {
+                return Err(Error::Startup(format!(
+                    "not listening on port {} after {:?}: {}",
+                    port,
+                    timeout,
+                    tail(&log)
+                )));
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// A port nothing listens on right now; the server binds it a moment later.
+fn free_port() -> std::io::Result<u16> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
+
+/// The last lines of the server's output, for startup errors.
+fn tail(log: &Path) -> String {
+    let text = fs::read_to_string(log).unwrap_or_default();
+    let lines: Vec<&str> = text.lines().collect();
+    let tail = lines[lines.len().saturating_sub(10)..].join("\n");
+    if tail.trim().is_empty() {
+        "no output".to_string()
+    } else {
+        tail
+    }
+}
@@ -0,0 +1,79 @@
+//! The drive and assert helpers against both backends: the mock, and
+//! octa-server started from this workspace's target directory.
+
+use octa_testkit::{Backend, Fixture, TestServer};
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Upload, read, list and delete, as every backend must answer them.
+async fn round_trip(octa: &TestServer) {
+    let fixtures = Fixture::batch("team/", 3);
+    let uploads = octa.seed(&fixtures).await;
+    assert_eq!(uploads.len(), 3);
+    octa.assert_listed("team/", &["team/0", "team/1", "team/2"])
+        .await;
+
+    let alice = Fixture::new("alice")
+        .size(320, 200)
+        .png()
+        .alias("alice-old");
+    let upload = octa.upload(&alice).await;
+    assert_eq!(upload.keys, ["alice", "alice-old"]);
+    octa.assert_dimensions("alice", 320, 200).await;
+    octa.assert_serves("alice", &alice.image()).await;
+    octa.assert_serves("alice-old", &alice.image()).await;
+    let asset = octa.assert_stored("alice-old").await;
+    assert_eq!(asset.id, upload.id);
+
+    // A second upload under the key replaces the image of the same asset.
+    let replaced = Fixture::new("alice").size(48, 48);
+    assert_eq!(octa.upload(&replaced).await.id, upload.id);
+    octa.assert_serves("alice", &replaced.image()).await;
+
+    assert_eq!(octa.delete("team/1").await, uploads[1].id);
+    octa.assert_missing("team/1").await;
+    octa.assert_listed("team/", &["team/0", "team/2"]).await;
+    octa.delete("alice").await;
+    octa.assert_missing("alice-old").await;
+}
+
+#[tokio::test]
+async fn mock_round_trip() {
+    let octa = TestServer::builder().mock().start().await.unwrap();
+    assert_eq!(octa.backend(), &Backend::Mock);
+    assert!(octa.db_path().is_none());
+    round_trip(&octa).await;
+}
+
+#[tokio::test]
+async fn server_round_trip() {
+    let octa = TestServer::builder()
+        .binary(server_binary())
+        .start()
+        .await
+        .unwrap();
+    round_trip(&octa).await;
+    assert!(octa.db_path().is_some_and(|db| db.is_file()));
+}
+
+/// octa-server next to this test's own binary, built first when the
+/// workspace was only tested, not built.
+fn server_binary() -> PathBuf {
+    let exe = std::env::current_exe().expect("test binary path");
+    // target/<profile>/deps/<test> -> target/<profile>
+    let dir = exe
+        .parent()
+        .and_then(|deps| deps.parent())
+        .expect("target directory");
+    let binary = dir.join(format!("octa-server{}", std::env::consts::EXE_SUFFIX));
+    if !binary.is_file() {
+        let mut cargo = Command::new(env!("CARGO"));
+        cargo.args(["build", "-p", "octa-server"]);
+        if dir.ends_with("release") {
+            cargo.arg("--release");
+        }
+        let status = cargo.status().expect("cargo runs");
+        assert!(status.success(), "building octa-server failed");
+    }
+    binary
+}
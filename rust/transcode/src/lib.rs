@@ -0,0 +1,474 @@
+use clap::Parser;
+use octa_config::{ConfigError, DatabaseConfig, Validate};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use octa_warden_core::compression::Target;
+use octa_warden_core::db::{self, OpenOptions};
+use octa_warden_core::growth::{format_bytes, parse_bytes};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use store::Pending;
+use tracing::{error, info, warn, Level};
+use transcode::{Outcome, Settings, Tally, Transcoded};
+
+mod store;
+mod transcode;
+
+/*
+OCTA-TRANSCODE: Batch WebP and AVIF derivatives
+=============================================
+Mission: Re-encode the stored assets into WebP and AVIF, at a configured
+         quality or size target, and keep each derivative that is enough
+         smaller than its original in the derivatives table, next to the
+         images it came from. Reports the bytes saved per format.
+Safety:  Originals are only read, never changed. Each batch is committed on
+         its own, so an interrupted run resumes where it stopped; an asset
+         uploaded again is transcoded again. --dry-run encodes and reports,
+         but writes nothing.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Re-encode Octa assets into WebP and AVIF derivatives"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// SQLite database to transcode (overrides database.path)
+    #[arg(long = "db", env = "OCTA_DB_PATH", value_name = "PATH")]
+    db_path: Option<String>,
+
+    /// Milliseconds to wait on a locked database before giving up (SQLITE_BUSY)
+    #[arg(long, default_value_t = 5000)]
+    busy_timeout: u64,
+
+    /// Formats to produce, comma-separated (overrides transcode.formats)
+    #[arg(long, value_delimiter = ',')]
+    formats: Option<Vec<String>>,
+
+    /// Encoding threads (overrides transcode.workers)
+    #[arg(long)]
+    workers: Option<usize>,
+
+    /// Stop after this many assets; the next run picks up the rest
+    #[arg(long)]
+    limit: Option<u64>,
+
+    /// Encode every asset again, e.g. after changing the quality
+    #[arg(long)]
+    force: bool,
+
+    /// Encode and report, but store nothing
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+}
+
+/// The parts of config.yaml octa-transcode reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    database: DatabaseConfig,
+    transcode: TranscodeConfig,
+}
+
+/// `transcode:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TranscodeConfig {
+    /// webp, avif or both.
+    formats: Vec<String>,
+    webp_quality: u8,
+    avif_quality: u8,
+    /// Size target (`50KB`): larger derivatives are encoded again at lower
+    /// quality, down to `min_quality`.
+    max_bytes: Option<String>,
+    min_quality: u8,
+    /// Derivatives over this share of the original's size are not kept.
+    max_ratio: f64,
+    /// PNG and GIF originals become lossless WebP, and no AVIF.
+    lossless: bool,
+    /// 0 for one per CPU.
+    workers: usize,
+    batch_size: usize,
+}
+
+impl Default for TranscodeConfig {
+    fn default() -> Self {
+        Self {
+            formats: vec!["webp".to_string(), "avif".to_string()],
+            webp_quality: 80,
+            avif_quality: 60,
+            max_bytes: None,
+            min_quality: 40,
+            max_ratio: 0.9,
+            lossless: false,
+            workers: 0,
+            batch_size: 32,
+        }
+    }
+}
+
+fn check_formats(formats: &[String]) -> Option<String> {
+    if formats.is_empty() {
+        return Some("must name at least one format".to_string());
+    }
+    formats
+        .iter()
+        .find(|f| Target::parse(f).is_none())
+        .map(|f| format!("'{}' is not webp or avif", f))
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        let transcode = &self.transcode;
+        if self.database.path.trim().is_empty() {
+            problems.push(("database.path".to_string(), "must not be empty".to_string()));
+        }
+        if let Some(problem) = check_formats(&transcode.formats) {
+            problems.push(("transcode.formats".to_string(), problem));
+        }
+        for (field, quality) in [
+            ("transcode.webp_quality", transcode.webp_quality),
+            ("transcode.avif_quality", transcode.avif_quality),
+            ("transcode.min_quality", transcode.min_quality),
+        ] {
+            if !(1..=100).contains(&quality) {
+                problems.push((
+                    field.to_string(),
+                    format!("{} is not within 1-100", quality),
+                ));
+            }
+        }
+        if let Some(Err(e)) = transcode.max_bytes.as_deref().map(parse_bytes) {
+            problems.push(("transcode.max_bytes".to_string(), e));
+        }
+        if !(transcode.max_ratio > 0.0 && transcode.max_ratio <= 1.0) {
+            problems.push((
+                "transcode.max_ratio".to_string(),
+                format!("{} is not within 0-1", transcode.max_ratio),
+            ));
+        }
+        if transcode.batch_size == 0 {
+            problems.push((
+                "transcode.batch_size".to_string(),
+                "must be at least 1".to_string(),
+            ));
+        }
+        problems
+    }
+}
+
+/// Like octa-gc: `--db` makes the config file optional.
+fn load_config(path: Option<&str>, db_path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    let found = octa_config::discover(path.map(Path::new)).filter(|p| p.exists());
+    let mut config: FileConfig = match (&found, db_path) {
+        (Some(found), _) => octa_config::read(found)?,
+        (None, Some(_)) => octa_config::from_env()?,
+        (None, None) => {
+            return Err(match path {
+                Some(path) => ConfigError::Read {
+                    path: path.into(),
+                    source: std::io::ErrorKind::NotFound.into(),
+                },
+                None => ConfigError::NotFound,
+            })
+        }
+    };
+    if let Some(db_path) = db_path {
+        config.database.path = db_path.to_string();
+    }
+    octa_config::check(found.as_deref().unwrap_or(Path::new("<defaults>")), config)
+}
+
+/// One line of the report.
+#[derive(Debug, Serialize)]
+struct Row {
+    format: &'static str,
+    #[serde(flatten)]
+    tally: Tally,
+    saved_bytes: i64,
+    saved_percent: f64,
+}
+
+/// The octa-transcode command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa transcode` the arguments it was given.
+pub fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, Level::INFO, false, true);
+
+    let config = match load_config(args.config.as_deref(), args.db_path.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    let transcode = &config.transcode;
+    let formats = args.formats.as_ref().unwrap_or(&transcode.formats);
+    if let Some(problem) = check_formats(formats) {
+        error!(tag = "FATAL", reason = %format!("--formats: {}", problem), "Invalid arguments");
+        return Kind::Usage.exit_code();
+    }
+    let mut targets: Vec<Target> = formats.iter().filter_map(|f| Target::parse(f)).collect();
+    targets.sort();
+    targets.dedup();
+
+    let db_path = config.database.path.as_str();
+    if !Path::new(db_path).exists() {
+        error!(tag = "FATAL", path = %db_path, "Database file not found");
+        return Kind::NoInput.exit_code();
+    }
+    let opts = OpenOptions {
+        busy_timeout: Duration::from_millis(args.busy_timeout),
+        immutable: false,
+    };
+    let opened = if args.dry_run {
+        db::open_read_only(db_path, &opts)
+    } else {
+        db::open_read_write(db_path, &opts).and_then(|conn| {
+            store::ensure_table(&conn)?;
+            let pruned = store::prune(&conn)?;
+            if pruned > 0 {
+                info!(
+                    tag = "OK",
+                    derivatives = pruned,
+                    "Removed derivatives of deleted assets"
+                );
+            }
+            Ok(conn)
+        })
+    };
+    let conn = match opened {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!(tag = "FATAL", path = %db_path, reason = %e, "Could not open database");
+            return Kind::Io.exit_code();
+        }
+    };
+
+    let settings = Settings {
+        webp_quality: transcode.webp_quality,
+        avif_quality: transcode.avif_quality,
+        max_bytes: transcode
+            .max_bytes
+            .as_deref()
+            .and_then(|b| parse_bytes(b).ok()),
+        min_quality: transcode.min_quality,
+        max_ratio: transcode.max_ratio,
+        lossless: transcode.lossless,
+    };
+    let workers = match args.workers.unwrap_or(transcode.workers) {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    let run = Run {
+        conn: &conn,
+        targets: &targets,
+        settings: &settings,
+        workers,
+        batch_size: transcode.batch_size,
+        limit: args.limit,
+        force: args.force,
+        dry_run: args.dry_run,
+    };
+    let tallies = match run.run() {
+        Ok(tallies) => tallies,
+        Err(e) => {
+            error!(tag = "FATAL", path = %db_path, reason = %e, "Transcoding stopped");
+            return Kind::Io.exit_code();
+        }
+    };
+
+    // A real run reports everything stored so far, resumed runs included.
+    let report = if args.dry_run {
+        Ok(tallies.clone())
+    } else {
+        store::totals(&conn, &targets)
+    };
+    let rows: Vec<Row> = match report {
+        Ok(report) => report.into_iter().map(|(t, tally)| row(t, tally)).collect(),
+        Err(e) => {
+            error!(tag = "FATAL", path = %db_path, reason = %e, "Could not read the derivatives");
+            return Kind::Io.exit_code();
+        }
+    };
+    if args.json {
+        match serde_json::to_string_pretty(&rows) {
+            Ok(text) => println!("{}", text),
+            Err(e) => {
+                error!(tag = "FATAL", reason = %e, "Could not write the report");
+                return Kind::Internal.exit_code();
+            }
+        }
+    } else {
+        print_table(&rows);
+    }
+
+    let failed: u64 = tallies.values().map(|t| t.failed).sum();
+    info!(
+        tag = if failed == 0 { "OK" } else { "WARN" },
+        stored = tallies.values().map(|t| t.stored).sum::<u64>(),
+        larger = tallies.values().map(|t| t.larger).sum::<u64>(),
+        skipped = tallies.values().map(|t| t.skipped).sum::<u64>(),
+        failed,
+        dry_run = args.dry_run,
+        "Transcoding finished"
+    );
+    if failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn row(target: Target, tally: Tally) -> Row {
+    Row {
+        format: target.as_str(),
+        saved_bytes: tally.original_bytes as i64 - tally.derived_bytes as i64,
+        saved_percent: (tally.saved_percent() * 10.0).round() / 10.0,
+        tally,
+    }
+}
+
+fn print_table(rows: &[Row]) {
+    println!(
+        "{:<6}  {:>8}  {:>8}  {:>8}  {:>8}  {:>10}  {:>10}  {:>7}",
+        "FORMAT", "STORED", "LARGER", "SKIPPED", "FAILED", "BEFORE", "AFTER", "SAVED"
+    );
+    for row in rows {
+        let tally = &row.tally;
+        println!(
+            "{:<6}  {:>8}  {:>8}  {:>8}  {:>8}  {:>10}  {:>10}  {:>6.1}%",
+            row.format,
+            tally.stored,
+            tally.larger,
+            tally.skipped,
+            tally.failed,
+            format_bytes(tally.original_bytes as f64),
+            format_bytes(tally.derived_bytes as f64),
+            row.saved_percent,
+        );
+    }
+}
+
+/// What the run was asked to do.
+struct Run<'a> {
+    conn: &'a rusqlite::Connection,
+    targets: &'a [Target],
+    settings: &'a Settings,
+    workers: usize,
+    batch_size: usize,
+    limit: Option<u64>,
+    force: bool,
+    dry_run: bool,
+}
+
+impl Run<'_> {
+    /// Transcodes batch after batch, in id order, and tallies this run.
+    fn run(&self) -> rusqlite::Result<BTreeMap<Target, Tally>> {
+        let existing = !self.force && store::table_exists(self.conn)?;
+        let mut total = store::count_pending(self.conn, self.targets, existing)?;
+        if let Some(limit) = self.limit {
+            total = total.min(limit);
+        }
+        info!(
+            tag = "→",
+            assets = total,
+            formats = %self.targets.iter().map(|t| t.as_str()).collect::<Vec<_>>().join(","),
+            workers = self.workers,
+            dry_run = self.dry_run,
+            "Transcoding"
+        );
+
+        let mut tallies: BTreeMap<Target, Tally> = self
+            .targets
+            .iter()
+            .map(|t| (*t, Tally::default()))
+            .collect();
+        let step = (total / 10).max(1);
+        let mut done = 0u64;
+        let mut after = String::new();
+        while done < total {
+            let size = (self.batch_size as u64).min(total - done) as usize;
+            let batch = store::pending(self.conn, self.targets, existing, &after, size)?;
+            let Some(last) = batch.last() else {
+                break;
+            };
+            after = last.id.clone();
+            let results = self.encode(&batch);
+
+            let tx = self.conn.unchecked_transaction()?;
+            for (asset, (dimensions, outcomes)) in batch.iter().zip(&results) {
+                for (target, outcome) in outcomes {
+                    tallies
+                        .entry(*target)
+                        .or_default()
+                        .add(asset.data.len(), outcome);
+                    if !self.dry_run {
+                        store::record(&tx, asset, *target, *dimensions, outcome)?;
+                    }
+                    if let Outcome::Failed(reason) = outcome {
+                        warn!(tag = "FAIL", id = %asset.id, format = target.as_str(), reason = %reason, "Not transcoded");
+                    }
+                }
+            }
+            tx.commit()?;
+
+            let before = done;
+            done += batch.len() as u64;
+            if before / step != done / step || done == total {
+                info!(tag = "→", done, total, "Progress");
+            }
+        }
+        Ok(tallies)
+    }
+
+    /// Encodes the missing derivatives of each asset on the worker threads,
+    /// returned in the batch's order.
+    fn encode(&self, batch: &[Pending]) -> Vec<Transcoded> {
+        let next = AtomicUsize::new(0);
+        let mut results: Vec<(usize, Transcoded)> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.workers.min(batch.len()))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut done = Vec::new();
+                        loop {
+                            let n = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(asset) = batch.get(n) else {
+                                return done;
+                            };
+                            let missing = &asset.missing;
+                            done.push((
+                                n,
+                                transcode::transcode(&asset.data, missing, self.settings),
+                            ));
+                        }
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|w| w.join().expect("transcode worker panicked"))
+                .collect()
+        });
+        results.sort_by_key(|(n, _)| *n);
+        results.into_iter().map(|(_, r)| r).collect()
+    }
+}
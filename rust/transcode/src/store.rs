@@ -0,0 +1,200 @@
+use crate::transcode::{Outcome, Tally};
+use octa_warden_core::compression::Target;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use std::collections::BTreeMap;
+
+/// One row per asset and format. `image_updated_at` is the version that
+/// was transcoded: an asset uploaded again is transcoded again. Only
+/// `stored` rows hold data; the others record why there is none, so a
+/// resumed run does not try again.
+const CREATE_TABLE: &str = "CREATE TABLE IF NOT EXISTS derivatives (
+    image_id         TEXT NOT NULL,
+    format           TEXT NOT NULL,
+    image_updated_at DATETIME,
+    status           TEXT NOT NULL,
+    quality          INTEGER,
+    data             BLOB,
+    size             INTEGER,
+    original_size    INTEGER NOT NULL,
+    width            INTEGER,
+    height           INTEGER,
+    error            TEXT,
+    created_at       DATETIME NOT NULL,
+    PRIMARY KEY (image_id, format)
+)";
+
+pub fn ensure_table(conn: &Connection) -> Result<()> {
+    conn.execute(CREATE_TABLE, []).map(|_| ())
+}
+
+pub fn table_exists(conn: &Connection) -> Result<bool> {
+    conn.query_row(
+        "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'derivatives'",
+        [],
+        |_| Ok(()),
+    )
+    .optional()
+    .map(|found| found.is_some())
+}
+
+/// Removes the derivatives of assets deleted since.
+pub fn prune(conn: &Connection) -> Result<usize> {
+    conn.execute(
+        "DELETE FROM derivatives WHERE image_id NOT IN (SELECT id FROM images)",
+        [],
+    )
+}
+
+/// An asset missing a derivative of the current version.
+pub struct Pending {
+    pub id: String,
+    pub data: Vec<u8>,
+    pub updated_at: Option<String>,
+    pub missing: Vec<Target>,
+}
+
+/// Which formats an asset has a current row for, as SQL: one `0`/`1`
+/// column per target. Without the table, or to redo everything, none.
+fn done_columns(targets: &[Target], existing: bool) -> String {
+    targets
+        .iter()
+        .enumerate()
+        .map(|(n, target)| {
+            if !existing {
+                return format!("0 AS done{}", n);
+            }
+            format!(
+                "EXISTS (SELECT 1 FROM derivatives d WHERE d.image_id = i.id AND d.format = '{}'
+                         AND d.image_updated_at IS CAST(i.updated_at AS TEXT)) AS done{}",
+                target.as_str(),
+                n
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn all_done(targets: &[Target]) -> String {
+    (0..targets.len())
+        .map(|n| format!("done{}", n))
+        .collect::<Vec<_>>()
+        .join(" AND ")
+}
+
+/// Up to `limit` assets after `after`, by id, missing a derivative among
+/// `targets`. `existing` is false to treat every derivative as missing.
+pub fn pending(
+    conn: &Connection,
+    targets: &[Target],
+    existing: bool,
+    after: &str,
+    limit: usize,
+) -> Result<Vec<Pending>> {
+    let sql = format!(
+        "SELECT * FROM (
+             SELECT i.id, CAST(i.data AS BLOB), CAST(i.updated_at AS TEXT), {}
+             FROM images i WHERE i.id > ?1
+         ) WHERE NOT ({}) ORDER BY 1 LIMIT ?2",
+        done_columns(targets, existing),
+        all_done(targets)
+    );
+    conn.prepare(&sql)?
+        .query_map(params![after, limit as i64], |row| {
+            let mut missing = Vec::new();
+            for (n, target) in targets.iter().enumerate() {
+                if !row.get::<_, bool>(3 + n)? {
+                    missing.push(*target);
+                }
+            }
+            Ok(Pending {
+                id: row.get(0)?,
+                data: row.get::<_, Option<Vec<u8>>>(1)?.unwrap_or_default(),
+                updated_at: row.get(2)?,
+                missing,
+            })
+        })?
+        .collect()
+}
+
+/// How many assets [`pending`] will go through.
+pub fn count_pending(conn: &Connection, targets: &[Target], existing: bool) -> Result<u64> {
+    let sql = format!(
+        "SELECT count(*) FROM (SELECT {} FROM images i) WHERE NOT ({})",
+        done_columns(targets, existing),
+        all_done(targets)
+    );
+    conn.query_row(&sql, [], |row| row.get::<_, i64>(0))
+        .map(|n| n as u64)
+}
+
+pub fn record(
+    conn: &Connection,
+    asset: &Pending,
+    target: Target,
+    dimensions: Option<(u32, u32)>,
+    outcome: &Outcome,
+) -> Result<()> {
+    let (data, size, quality, error) = match outcome {
+        Outcome::Stored { data, quality } => (Some(data), Some(data.len()), *quality, None),
+        Outcome::Larger { size, quality } => (None, Some(*size), *quality, None),
+        Outcome::Skipped(reason) | Outcome::Failed(reason) => (None, None, None, Some(reason)),
+    };
+    conn.execute(
+        "INSERT OR REPLACE INTO derivatives
+            (image_id, format, image_updated_at, status, quality, data, size, original_size,
+             width, height, error, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, datetime('now'))",
+        params![
+            asset.id,
+            target.as_str(),
+            asset.updated_at,
+            outcome.status(),
+            quality,
+            data,
+            size.map(|s| s as i64),
+            asset.data.len() as i64,
+            dimensions.map(|(w, _)| w),
+            dimensions.map(|(_, h)| h),
+            error,
+        ],
+    )
+    .map(|_| ())
+}
+
+/// What the table holds for the current version of every asset, per format.
+pub fn totals(conn: &Connection, targets: &[Target]) -> Result<BTreeMap<Target, Tally>> {
+    let mut totals: BTreeMap<Target, Tally> =
+        targets.iter().map(|t| (*t, Tally::default())).collect();
+    let mut stmt = conn.prepare(
+        "SELECT d.format, d.status, count(*), sum(d.original_size), sum(d.size)
+         FROM derivatives d JOIN images i ON i.id = d.image_id
+         WHERE d.image_updated_at IS CAST(i.updated_at AS TEXT)
+         GROUP BY d.format, d.status",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, i64>(2)? as u64,
+            row.get::<_, Option<i64>>(3)?.unwrap_or(0) as u64,
+            row.get::<_, Option<i64>>(4)?.unwrap_or(0) as u64,
+        ))
+    })?;
+    for row in rows {
+        let (format, status, count, original, size) = row?;
+        let Some(tally) = Target::parse(&format).and_then(|t| totals.get_mut(&t)) else {
+            continue;
+        };
+        match status.as_str() {
+            "stored" => {
+                tally.stored += count;
+                tally.original_bytes += original;
+                tally.derived_bytes += size;
+            }
+            "larger" => tally.larger += count,
+            "skipped" => tally.skipped += count,
+            _ => tally.failed += count,
+        }
+    }
+    Ok(totals)
+}
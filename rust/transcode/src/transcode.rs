@@ -0,0 +1,166 @@
+use image::ImageFormat;
+use octa_warden_core::compression::{self, Target};
+use serde::Serialize;
+
+/// How derivatives are encoded and which are kept.
+pub struct Settings {
+    pub webp_quality: u8,
+    pub avif_quality: u8,
+    /// Size target: a larger derivative is encoded again at lower quality,
+    /// in steps of 10 down to `min_quality`.
+    pub max_bytes: Option<u64>,
+    pub min_quality: u8,
+    /// Largest derivative kept, as a share of the original's size.
+    pub max_ratio: f64,
+    /// PNG and GIF sources become lossless WebP (and no AVIF).
+    pub lossless: bool,
+}
+
+impl Settings {
+    fn quality(&self, target: Target) -> u8 {
+        match target {
+            Target::WebP => self.webp_quality,
+            Target::Avif => self.avif_quality,
+        }
+    }
+}
+
+/// What became of one derivative.
+pub enum Outcome {
+    Stored {
+        data: Vec<u8>,
+        quality: Option<u8>,
+    },
+    /// Encoded, but not enough smaller than the original to be worth it.
+    Larger {
+        size: usize,
+        quality: Option<u8>,
+    },
+    /// Not encoded, and never will be (e.g. the original is in that format).
+    Skipped(String),
+    Failed(String),
+}
+
+impl Outcome {
+    pub fn status(&self) -> &'static str {
+        match self {
+            Outcome::Stored { .. } => "stored",
+            Outcome::Larger { .. } => "larger",
+            Outcome::Skipped(_) => "skipped",
+            Outcome::Failed(_) => "failed",
+        }
+    }
+}
+
+/// Derivatives of one format, and the bytes they save.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Tally {
+    pub stored: u64,
+    pub larger: u64,
+    pub skipped: u64,
+    pub failed: u64,
+    /// The originals of the stored derivatives, and the derivatives.
+    pub original_bytes: u64,
+    pub derived_bytes: u64,
+}
+
+impl Tally {
+    pub fn add(&mut self, original: usize, outcome: &Outcome) {
+        match outcome {
+            Outcome::Stored { data, .. } => {
+                self.stored += 1;
+                self.original_bytes += original as u64;
+                self.derived_bytes += data.len() as u64;
+            }
+            Outcome::Larger { .. } => self.larger += 1,
+            Outcome::Skipped(_) => self.skipped += 1,
+            Outcome::Failed(_) => self.failed += 1,
+        }
+    }
+
+    /// Share of the originals' bytes saved, in percent.
+    pub fn saved_percent(&self) -> f64 {
+        if self.original_bytes == 0 {
+            return 0.0;
+        }
+        (self.original_bytes as f64 - self.derived_bytes as f64) * 100.0
+            / self.original_bytes as f64
+    }
+}
+
+/// The dimensions of an asset, when it decodes, and its derivatives.
+pub type Transcoded = (Option<(u32, u32)>, Vec<(Target, Outcome)>);
+
+/// Encodes `data` into each of `targets`; the image is decoded once.
+pub fn transcode(data: &[u8], targets: &[Target], settings: &Settings) -> Transcoded {
+    let decoded = image::guess_format(data)
+        .map_err(|e| e.to_string())
+        .and_then(|format| {
+            image::load_from_memory(data)
+                .map(|img| (format, img))
+                .map_err(|e| format!("decode: {}", e))
+        });
+    let (source, img) = match decoded {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            let outcomes = targets
+                .iter()
+                .map(|t| (*t, Outcome::Failed(e.clone())))
+                .collect();
+            return (None, outcomes);
+        }
+    };
+    let lossless_source = !matches!(
+        source,
+        ImageFormat::Jpeg | ImageFormat::WebP | ImageFormat::Avif
+    );
+
+    let outcomes = targets
+        .iter()
+        .map(|&target| {
+            if target.format() == source {
+                let reason = format!("the original is {}", target.as_str());
+                return (target, Outcome::Skipped(reason));
+            }
+            let quality = if settings.lossless && lossless_source {
+                None
+            } else {
+                Some(settings.quality(target))
+            };
+            (target, encode(&img, target, quality, data.len(), settings))
+        })
+        .collect();
+    (Some((img.width(), img.height())), outcomes)
+}
+
+fn encode(
+    img: &image::DynamicImage,
+    target: Target,
+    mut quality: Option<u8>,
+    original: usize,
+    settings: &Settings,
+) -> Outcome {
+    if target == Target::Avif && quality.is_none() {
+        return Outcome::Skipped("no lossless AVIF encoder".to_string());
+    }
+    let Some(mut data) = compression::encode(img, target, quality) else {
+        return Outcome::Failed(format!("could not encode as {}", target.as_str()));
+    };
+    if let (Some(max_bytes), Some(mut q)) = (settings.max_bytes, quality) {
+        while data.len() as u64 > max_bytes && q > settings.min_quality {
+            q = q.saturating_sub(10).max(settings.min_quality);
+            match compression::encode(img, target, Some(q)) {
+                Some(smaller) => data = smaller,
+                None => return Outcome::Failed(format!("could not encode as {}", target.as_str())),
+            }
+        }
+        quality = Some(q);
+    }
+    if data.len() as f64 > original as f64 * settings.max_ratio {
+        return Outcome::Larger {
+            size: data.len(),
+            quality,
+        };
+    }
+    Outcome::Stored { data, quality }
+}
@@ -0,0 +1,188 @@
+//! `octa-warden export`: asset metadata and recorded audit findings as
+//! Parquet files, for DuckDB or Spark. Assets come from one read of the
+//! database (no BLOB is decoded), findings from the history database, so
+//! the analysis itself never touches production.
+
+use crate::audit::has_column;
+use crate::parquet::{Cell, Column, ColumnType, Writer};
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, Row};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const ASSETS: &[Column] = &[
+    Column::new("key", ColumnType::Text),
+    Column::new("asset_id", ColumnType::Text),
+    Column::new("bytes", ColumnType::Int),
+    Column::new("format", ColumnType::Text),
+    Column::new("width", ColumnType::Int),
+    Column::new("height", ColumnType::Int),
+    Column::new("created_at", ColumnType::Timestamp),
+    Column::new("updated_at", ColumnType::Timestamp),
+    Column::new("key_created_at", ColumnType::Timestamp),
+];
+
+const RUNS: &[Column] = &[
+    Column::new("run_id", ColumnType::Int),
+    Column::new("finished_at", ColumnType::Timestamp),
+    Column::new("full_scan", ColumnType::Int),
+    Column::new("duration_ms", ColumnType::Int),
+    Column::new("scanned", ColumnType::Int),
+    Column::new("healthy", ColumnType::Int),
+    Column::new("corrupted", ColumnType::Int),
+    Column::new("schema_errors", ColumnType::Int),
+    Column::new("rows_total", ColumnType::Int),
+    Column::new("bytes_total", ColumnType::Int),
+];
+
+const FINDINGS: &[Column] = &[
+    Column::new("run_id", ColumnType::Int),
+    Column::new("finished_at", ColumnType::Timestamp),
+    Column::new("asset_id", ColumnType::Text),
+    Column::new("kind", ColumnType::Text),
+    Column::new("severity", ColumnType::Text),
+    Column::new("reason", ColumnType::Text),
+];
+
+#[derive(Debug)]
+pub enum Error {
+    Db(rusqlite::Error),
+    Io { path: PathBuf, source: io::Error },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Db(e) => write!(f, "{}", e),
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Db(e)
+    }
+}
+
+/// One row per key, plus one per asset without keys (`key` null) and per
+/// key whose asset is gone (only `key` and `asset_id`). `bytes` is what
+/// the asset takes in the database; timestamps are UTC.
+pub fn write_assets(conn: &Connection, path: &Path) -> Result<usize, Error> {
+    let column = |name: &str, expr: &str| -> rusqlite::Result<String> {
+        Ok(match has_column(conn, "images", name)? {
+            true => expr.to_string(),
+            false => "NULL".to_string(),
+        })
+    };
+    let bytes = match has_column(conn, "images", "size")? {
+        true => "COALESCE(i.size, LENGTH(i.data))",
+        false => "LENGTH(i.data)",
+    };
+    let sql = format!(
+        "SELECT k.key, i.id, {bytes}, {format}, {width}, {height}, {created}, {updated}, {key_created}
+         FROM images i LEFT JOIN key_mappings k ON k.image_id = i.id
+         UNION ALL
+         SELECT k.key, k.image_id, NULL, NULL, NULL, NULL, NULL, NULL, {key_created}
+         FROM key_mappings k WHERE NOT EXISTS (SELECT 1 FROM images i WHERE i.id = k.image_id)",
+        format = column("format", "i.format")?,
+        width = column("width", "i.width")?,
+        height = column("height", "i.height")?,
+        created = column("created_at", &micros("i.created_at"))?,
+        updated = column("updated_at", &micros("i.updated_at"))?,
+        key_created = micros("k.created_at"),
+    );
+    write(conn, &sql, path, ASSETS)
+}
+
+/// Every recorded run: the denominators for the findings.
+pub fn write_runs(history: &Connection, path: &Path) -> Result<usize, Error> {
+    let sql = "SELECT id, finished_at * 1000000, full_scan, duration_ms, scanned, healthy,
+                      corrupted, schema_errors, rows_total, bytes_total
+               FROM runs ORDER BY id";
+    write(history, sql, path, RUNS)
+}
+
+/// Every finding of every recorded run, with when the run finished.
+pub fn write_findings(history: &Connection, path: &Path) -> Result<usize, Error> {
+    let sql = "SELECT r.id, r.finished_at * 1000000, f.asset_id, f.kind, f.severity, f.reason
+               FROM findings f JOIN runs r ON r.id = f.run_id ORDER BY r.id";
+    write(history, sql, path, FINDINGS)
+}
+
+/// Microseconds since the epoch of a timestamp column, as SQLite reads it;
+/// NULL when it does not parse.
+fn micros(column: &str) -> String {
+    format!(
+        "CAST(ROUND((julianday({}) - 2440587.5) * 86400000000) AS INTEGER)",
+        column
+    )
+}
+
+/// Streams the query into `path`, written under a temporary name first so
+/// a reader never sees half a file.
+fn write(conn: &Connection, sql: &str, path: &Path, columns: &[Column]) -> Result<usize, Error> {
+    let io = |source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+
+    let mut writer = Writer::create(&partial, columns).map_err(io)?;
+    let mut stmt = conn.prepare(sql)?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let texts: Vec<Option<String>> = columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| match column.kind {
+                ColumnType::Text => text(row, i),
+                _ => Ok(None),
+            })
+            .collect::<rusqlite::Result<_>>()?;
+        let cells: Vec<Cell> = columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| cell(row, i, column.kind, texts[i].as_deref()))
+            .collect::<rusqlite::Result<_>>()?;
+        writer.push(&cells).map_err(io)?;
+    }
+    let written = writer.finish().map_err(io)?;
+    fs::rename(&partial, path).map_err(io)?;
+    Ok(written)
+}
+
+/// The value of a text column; SQLite may hand back a BLOB or a number.
+fn text(row: &Row, i: usize) -> rusqlite::Result<Option<String>> {
+    Ok(match row.get_ref(i)? {
+        ValueRef::Null => None,
+        ValueRef::Text(t) | ValueRef::Blob(t) => Some(String::from_utf8_lossy(t).into_owned()),
+        ValueRef::Integer(n) => Some(n.to_string()),
+        ValueRef::Real(x) => Some(x.to_string()),
+    })
+}
+
+fn cell<'a>(
+    row: &Row,
+    i: usize,
+    kind: ColumnType,
+    text: Option<&'a str>,
+) -> rusqlite::Result<Cell<'a>> {
+    let value = row.get_ref(i)?;
+    Ok(match (kind, value) {
+        (_, ValueRef::Null) => Cell::Null,
+        (ColumnType::Text, _) => text.map_or(Cell::Null, Cell::Text),
+        (ColumnType::Int | ColumnType::Timestamp, ValueRef::Integer(n)) => Cell::Int(n),
+        (ColumnType::Int | ColumnType::Timestamp, ValueRef::Real(x)) => Cell::Int(x as i64),
+        (ColumnType::Double, ValueRef::Real(x)) => Cell::Double(x),
+        (ColumnType::Double, ValueRef::Integer(n)) => Cell::Double(n as f64),
+        // Text where a number belongs (a legacy schema): left out.
+        _ => Cell::Null,
+    })
+}
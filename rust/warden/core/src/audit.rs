@@ -0,0 +1,1153 @@
+use crate::base64_blob;
+use crate::cause::{self, Cause};
+use crate::color;
+use crate::db::{self, OpenOptions};
+use crate::encryption::{self, Key};
+use crate::partition;
+use crate::stream::FindingStream;
+use clap::ValueEnum;
+use image::{DynamicImage, GenericImageView};
+use octa_image::stages::{Sample, Stage, Timings};
+use octa_logging::FINDING_TARGET;
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{Connection, OptionalExtension, ParamsFromIter, Result, Row};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::field::Empty;
+use tracing::{error, info_span, warn, Span};
+
+/// Rows per `warden.batch` span: the unit a trace shows a scan's progress in.
+const SPAN_ROWS: u64 = 1000;
+
+#[derive(Debug, Default, Clone)]
+pub struct AuditStats {
+    pub total_scanned: u64,
+    pub healthy: u64,
+    pub corrupted_blob: u64,    // Image data is corrupted
+    pub db_schema_error: u64,   // Column type is incorrect (Text vs Blob)
+    pub derived_issues: u64,    // Missing or broken pre-generated sizes (warden.derived)
+    pub processing_errors: u64, // Decodes fine, but does not match its upload mode
+    pub base64_blobs: u64,      // Base64 text of a valid image instead of raw bytes
+    pub decrypt_failures: u64,  // Does not decrypt with the configured key
+    pub color_issues: u64,      // Broken or non-sRGB ICC profile
+    pub timestamp_issues: u64,  // NULL, zero, future or inconsistent created_at/updated_at
+    /// Corrupted blobs by likely cause.
+    pub corrupt_causes: BTreeMap<Cause, u64>,
+}
+
+impl AuditStats {
+    pub fn is_healthy(&self) -> bool {
+        self.corrupted_blob == 0 && self.db_schema_error == 0 && self.decrypt_failures == 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FindingKind {
+    /// [CORRUPT] The BLOB could not be decoded as an image.
+    CorruptBlob,
+    /// [DB-ERR] A column holds the wrong type (e.g. TEXT instead of BLOB).
+    SchemaMismatch,
+    /// The row (or file) itself could not be read.
+    RowFailure,
+    /// [PROCESS] The image decodes but is not what its upload mode produces
+    /// (e.g. a non-square `mode=square` avatar, see [`octa_image::verify`]).
+    ProcessingMismatch,
+    /// [DERIVED] An expected pre-generated size does not exist.
+    MissingDerivative,
+    /// [DERIVED] A pre-generated size is undecodable or has the wrong dimensions.
+    InvalidDerivative,
+    /// [BASE64] `data` holds base64 text of a valid image (old importer bug).
+    Base64Blob,
+    /// [DECRYPT] The BLOB does not decrypt with the key from `warden.encryption`.
+    DecryptFailed,
+    /// [COLOR] The embedded ICC profile is broken, not RGB, or not sRGB.
+    ColorProfile,
+    /// [TIME] `created_at` or `updated_at` is NULL, zero, in the future or inconsistent.
+    BadTimestamp,
+}
+
+impl FindingKind {
+    pub const ALL: [FindingKind; 10] = [
+        FindingKind::CorruptBlob,
+        FindingKind::SchemaMismatch,
+        FindingKind::RowFailure,
+        FindingKind::ProcessingMismatch,
+        FindingKind::MissingDerivative,
+        FindingKind::InvalidDerivative,
+        FindingKind::Base64Blob,
+        FindingKind::DecryptFailed,
+        FindingKind::ColorProfile,
+        FindingKind::BadTimestamp,
+    ];
+
+    /// Built-in classification; `warden.health.severity` can override it per kind.
+    pub fn default_severity(&self) -> Severity {
+        match self {
+            FindingKind::CorruptBlob | FindingKind::RowFailure | FindingKind::DecryptFailed => {
+                Severity::Critical
+            }
+            FindingKind::SchemaMismatch
+            | FindingKind::ProcessingMismatch
+            | FindingKind::MissingDerivative
+            | FindingKind::InvalidDerivative
+            | FindingKind::Base64Blob
+            | FindingKind::ColorProfile
+            | FindingKind::BadTimestamp => Severity::Warning,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FindingKind::CorruptBlob => "corrupt_blob",
+            FindingKind::SchemaMismatch => "schema_mismatch",
+            FindingKind::RowFailure => "row_failure",
+            FindingKind::ProcessingMismatch => "processing_mismatch",
+            FindingKind::MissingDerivative => "missing_derivative",
+            FindingKind::InvalidDerivative => "invalid_derivative",
+            FindingKind::Base64Blob => "base64_blob",
+            FindingKind::DecryptFailed => "decrypt_failed",
+            FindingKind::ColorProfile => "color_profile",
+            FindingKind::BadTimestamp => "bad_timestamp",
+        }
+    }
+}
+
+/// A single problem found during the scan.
+#[derive(Debug, Clone)]
+pub struct Finding {
+    /// Asset ID, when the row was readable far enough to know it.
+    pub id: Option<String>,
+    pub kind: FindingKind,
+    pub severity: Severity,
+    pub reason: String,
+    /// Extra BLOB column the finding is about; `None` for the asset itself (`data`).
+    pub column: Option<String>,
+}
+
+impl Finding {
+    pub fn new(id: Option<String>, kind: FindingKind, reason: String) -> Self {
+        Self {
+            id,
+            kind,
+            severity: kind.default_severity(),
+            reason,
+            column: None,
+        }
+    }
+}
+
+pub struct AuditResult {
+    pub stats: AuditStats,
+    pub findings: Vec<Finding>,
+    /// Set when the scan stopped early because `--max-findings` was crossed
+    /// (or the database file was replaced).
+    pub aborted: Option<String>,
+    /// The scan stopped because its database file was replaced.
+    pub source_replaced: bool,
+    /// Per-worker counters of a partitioned scan (`--readers`); empty otherwise.
+    pub workers: Vec<WorkerStats>,
+    pub performance: Performance,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkerRole {
+    /// Reads one rowid range from its own connection.
+    Reader,
+    /// Decodes the rows of one reader.
+    Decoder,
+}
+
+impl WorkerRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WorkerRole::Reader => "reader",
+            WorkerRole::Decoder => "decoder",
+        }
+    }
+}
+
+/// What one worker of a partitioned scan did.
+#[derive(Debug, Clone)]
+pub struct WorkerStats {
+    pub role: WorkerRole,
+    /// Index of the reader (range) the worker belongs to.
+    pub reader: usize,
+    /// Decoder number within its reader; `0` for the reader itself.
+    pub index: usize,
+    pub rows: u64,
+    /// BLOB bytes read or decoded.
+    pub bytes: u64,
+    /// Time spent reading rows (readers) or decoding them (decoders).
+    pub busy: Duration,
+    /// Time blocked on a full queue (readers) or an empty one (decoders).
+    pub waiting: Duration,
+}
+
+/// Where the time of a scan went. With several threads (`--readers`, S3
+/// downloads) the durations are summed over them and can exceed the elapsed time.
+#[derive(Debug, Default, Clone)]
+pub struct Performance {
+    /// BLOB bytes read from storage.
+    pub bytes_read: u64,
+    /// Decrypting and decoding.
+    pub decoding: Duration,
+    /// Reading from storage: SQLite rows, files, or waiting on object downloads.
+    pub io_wait: Duration,
+    /// Peak resident memory of the process in bytes, where the OS reports it.
+    pub peak_memory: Option<u64>,
+    /// Read, sniff and decode times of every asset (`--profile-stages`).
+    pub stages: Option<Timings>,
+}
+
+impl Performance {
+    pub fn rows_per_sec(&self, rows: u64, elapsed: Duration) -> f64 {
+        rate(rows as f64, elapsed)
+    }
+
+    /// Megabytes (10^6 bytes) of BLOB data per second.
+    pub fn mb_per_sec(&self, elapsed: Duration) -> f64 {
+        rate(self.bytes_read as f64 / 1e6, elapsed)
+    }
+}
+
+fn rate(amount: f64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 {
+        amount / secs
+    } else {
+        0.0
+    }
+}
+
+/// Peak resident set size (`VmHWM`) of this process. It never goes down, so
+/// in watch mode it is the peak since the daemon started.
+fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kib = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+impl AuditResult {
+    /// Highest severity among the findings, `None` for a clean run.
+    pub fn max_severity(&self) -> Option<Severity> {
+        self.findings.iter().map(|f| f.severity).max()
+    }
+}
+
+/// Which rows an audit pass covers.
+pub enum Scope {
+    /// Every row in the table.
+    Full,
+    /// Only rows written after the given `updated_at` watermark (incremental scans).
+    UpdatedAfter(String),
+}
+
+/// What `--fix` does with the problems it finds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FixMode {
+    /// Rebuild missing or broken derivatives from the original (needs `warden.derived`).
+    Regenerate,
+    /// Rewrite base64 text in `data` as the decoded image BLOB.
+    DecodeBase64,
+    /// Merge assets with identical images into the oldest one (dry run without `--execute`).
+    Dedup,
+    /// Convert images with a broken or non-sRGB ICC profile to plain sRGB.
+    Srgb,
+}
+
+/// Row order of a SQLite scan (`--order`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ScanOrder {
+    /// Whatever order SQLite stores the rows in.
+    #[default]
+    Table,
+    /// Most recently written first, so a current incident shows up early.
+    NewestFirst,
+}
+
+/// What "newest" means for [`ScanOrder::NewestFirst`] (`--order-by`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OrderKey {
+    /// Insertion order; free, rows stream from the first page read.
+    #[default]
+    Rowid,
+    /// `images.created_at`; SQLite sorts the rows in scope before the first one is decoded.
+    CreatedAt,
+}
+
+/// Upper bound on findings before the scan gives up and reports widespread corruption.
+#[derive(Debug, Clone, Copy)]
+pub enum FindingLimit {
+    Count(u64),
+    /// Percentage of the rows in scope.
+    Percent(f64),
+}
+
+/// Parses `--max-findings` values: "500" or "2%".
+pub fn parse_finding_limit(value: &str) -> Result<FindingLimit, String> {
+    let value = value.trim();
+    if let Some(pct) = value.strip_suffix('%') {
+        let pct: f64 = pct
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid percentage '{}'", value))?;
+        if !(pct > 0.0 && pct <= 100.0) {
+            return Err("percentage must be in (0, 100]".to_string());
+        }
+        return Ok(FindingLimit::Percent(pct));
+    }
+    match value.parse::<u64>() {
+        Ok(0) => Err("limit must be greater than zero".to_string()),
+        Ok(n) => Ok(FindingLimit::Count(n)),
+        Err(_) => Err(format!(
+            "invalid limit '{}' (use a count or a percentage like 2%)",
+            value
+        )),
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct RunOptions {
+    pub max_findings: Option<FindingLimit>,
+    /// BLOB columns of `images` validated next to `data` (`warden.extra_blob_columns`).
+    pub extra_blob_columns: Vec<String>,
+    pub order: ScanOrder,
+    pub order_by: OrderKey,
+    /// Rowid-range readers for SQLite scans (`--readers`). `None` reads on the audit connection.
+    pub parallel: Option<Parallel>,
+    /// Decrypts every BLOB before it is validated (`warden.encryption`).
+    pub decryption: Option<Arc<Key>>,
+    /// Stops the scan when this file is replaced under it (watch mode).
+    pub source: Option<db::Source>,
+    /// Receives every finding as it is found (`--findings-stream`).
+    pub findings_stream: Option<FindingStream>,
+    /// Collects [`Performance::stages`] (`--profile-stages`).
+    pub profile_stages: bool,
+}
+
+/// Settings for [`partition::scan`](crate::partition::scan).
+#[derive(Debug, Clone)]
+pub struct Parallel {
+    pub readers: usize,
+    /// Decode workers shared out between the readers (`--jobs`); `None` uses every core.
+    pub jobs: Option<usize>,
+    /// How every reader opens its own connection.
+    pub open: OpenOptions,
+}
+
+/// Returns the newest `updated_at` value in the table, used as the next incremental watermark.
+/// Compared as times, not text: rows may carry another UTC offset or precision.
+pub fn latest_update(conn: &Connection) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT updated_at FROM images WHERE julianday(updated_at) IS NOT NULL
+         ORDER BY julianday(updated_at) DESC LIMIT 1",
+        [],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Scans the selected rows and decodes every BLOB in memory.
+pub fn run(conn: &Connection, scope: &Scope, opts: &RunOptions) -> Result<AuditResult> {
+    scan(conn, scope, opts, &mut |_, _| {})
+}
+
+/// Like [`run`], but hands every asset that passes the audit to `on_healthy`,
+/// so consumers (e.g. `--export-healthy`) reuse the read instead of scanning twice.
+pub fn scan(
+    conn: &Connection,
+    scope: &Scope,
+    opts: &RunOptions,
+    on_healthy: &mut dyn FnMut(&str, &[u8]),
+) -> Result<AuditResult> {
+    let span = info_span!("warden.scan", rows = Empty, findings = Empty);
+    let _entered = span.enter();
+    let query = RowQuery::new(conn, scope, opts)?;
+
+    let limit = finding_limit(opts, || {
+        // Counting is cheap next to decoding every BLOB.
+        conn.query_row(&query.count_sql(), query.params(None), |row| {
+            row.get::<_, i64>(0)
+        })
+        .map(|n| n as u64 * (1 + query.extra_columns.len() as u64))
+    })?;
+    let mut tally = Tally::new(limit, opts.decryption.clone(), on_healthy);
+    tally.watch_source(opts.source.clone());
+    tally.stream_to(opts.findings_stream.clone());
+    tally.profile_stages(opts.profile_stages);
+
+    // Readers reopen the same file (or snapshot copy); in-memory databases have no path.
+    let workers = match (&opts.parallel, conn.path().filter(|p| !p.is_empty())) {
+        (Some(parallel), Some(path)) if parallel.readers > 1 => {
+            partition::scan(path, parallel, &query, &mut tally)?
+        }
+        _ => {
+            let mut stmt = conn.prepare(&query.sql(None))?;
+
+            // Fail-Safe Iterator: We will catch erroneous lines during iteration.
+            let mut rows = stmt.query_map(query.params(None), |row| Ok(query.read(row)))?;
+            let mut batches = Batches::new(&span, 0);
+            loop {
+                let started = Instant::now();
+                let Some(item) = rows.next() else {
+                    break;
+                };
+                batches.row();
+                match item {
+                    // Iteration successful (SQLite row could be read)
+                    Ok(mut raw) => {
+                        let took = started.elapsed();
+                        raw.record_read(took);
+                        tally.read(raw.bytes(), took);
+                        let started = Instant::now();
+                        let inspected = raw.inspect();
+                        tally.decoded(started.elapsed());
+                        tally.apply(inspected)
+                    }
+                    // The iteration itself failed (Very rare, disk error, etc.)
+                    Err(e) => tally.row_failure(None, e),
+                }
+
+                if tally.should_stop() {
+                    break;
+                }
+            }
+            Vec::new()
+        }
+    };
+
+    let mut result = tally.finish();
+    // Partitioned scans read and decode on the workers, not through the tally.
+    if !workers.is_empty() {
+        let sum = |role: WorkerRole| {
+            workers
+                .iter()
+                .filter(|w| w.role == role)
+                .fold((0, Duration::ZERO), |(bytes, busy), w| {
+                    (bytes + w.bytes, busy + w.busy)
+                })
+        };
+        (result.performance.bytes_read, result.performance.io_wait) = sum(WorkerRole::Reader);
+        result.performance.decoding = sum(WorkerRole::Decoder).1;
+    }
+    result.workers = workers;
+    span.record("rows", result.stats.total_scanned);
+    span.record("findings", result.findings.len());
+    Ok(result)
+}
+
+/// The `warden.batch` spans of one reader, each covering [`SPAN_ROWS`] rows
+/// read and, on the single-reader path, decoded. Exported with `telemetry`
+/// (see `octa_logging::otel`), they show where a scan spent its time.
+pub(crate) struct Batches {
+    parent: Span,
+    reader: usize,
+    rows: u64,
+    current: Option<Span>,
+}
+
+impl Batches {
+    pub(crate) fn new(parent: &Span, reader: usize) -> Self {
+        Self {
+            parent: parent.clone(),
+            reader,
+            rows: 0,
+            current: None,
+        }
+    }
+
+    /// Counts a row, starting the next batch every [`SPAN_ROWS`].
+    pub(crate) fn row(&mut self) {
+        if self.rows.is_multiple_of(SPAN_ROWS) {
+            self.close();
+            self.current = Some(info_span!(
+                parent: &self.parent,
+                "warden.batch",
+                reader = self.reader,
+                batch = self.rows / SPAN_ROWS,
+                rows = Empty
+            ));
+        }
+        self.rows += 1;
+    }
+
+    fn close(&mut self) {
+        if let Some(span) = self.current.take() {
+            span.record("rows", (self.rows - 1) % SPAN_ROWS + 1);
+        }
+    }
+}
+
+impl Drop for Batches {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+pub(crate) fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let columns: Vec<String> = conn
+        .prepare(&format!("PRAGMA table_info({})", table))?
+        .query_map([], |row| row.get(1))?
+        .collect::<Result<_>>()?;
+    Ok(columns.iter().any(|c| c == column))
+}
+
+/// The row query of one scan, shared by every reader.
+pub struct RowQuery {
+    /// `mode` when the table records the upload mode, otherwise `NULL`.
+    mode: &'static str,
+    /// `data`, or the blob behind it in a content-addressed database.
+    data: &'static str,
+    extra_columns: Vec<String>,
+    watermark: Option<String>,
+    /// `ORDER BY` clause, empty for table order.
+    order: String,
+    key: Option<Arc<Key>>,
+}
+
+impl RowQuery {
+    fn new(conn: &Connection, scope: &Scope, opts: &RunOptions) -> Result<Self> {
+        let mut extra_columns = Vec::new();
+        for column in &opts.extra_blob_columns {
+            if has_column(conn, "images", column)? {
+                extra_columns.push(column.clone());
+            } else {
+                warn!(tag = "WARN", column = %column, "Configured blob column not found, skipping");
+            }
+        }
+
+        // Octa does not store the upload mode; schemas that do get their
+        // rows checked against what the server would have produced.
+        let mode = if has_column(conn, "images", "mode")? {
+            "mode"
+        } else {
+            "NULL"
+        };
+
+        let data = if octa_store::cas::enabled(conn)? {
+            octa_store::cas::DATA
+        } else {
+            "data"
+        };
+
+        let order = match (opts.order, opts.order_by) {
+            (ScanOrder::Table, _) => "",
+            (ScanOrder::NewestFirst, OrderKey::CreatedAt)
+                if has_column(conn, "images", "created_at")? =>
+            {
+                " ORDER BY created_at DESC, rowid DESC"
+            }
+            (ScanOrder::NewestFirst, OrderKey::CreatedAt) => {
+                warn!(
+                    tag = "WARN",
+                    "images has no created_at column, ordering by rowid"
+                );
+                " ORDER BY rowid DESC"
+            }
+            (ScanOrder::NewestFirst, OrderKey::Rowid) => " ORDER BY rowid DESC",
+        };
+
+        Ok(Self {
+            mode,
+            data,
+            extra_columns,
+            order: order.to_string(),
+            key: opts.decryption.clone(),
+            watermark: match scope {
+                Scope::Full => None,
+                Scope::UpdatedAfter(mark) => Some(mark.clone()),
+            },
+        })
+    }
+
+    /// `WHERE` clause for the scope, optionally limited to a rowid range.
+    fn filter(&self, range: Option<(i64, i64)>) -> String {
+        let mut conditions = Vec::new();
+        if self.watermark.is_some() {
+            conditions.push("julianday(updated_at) > julianday(?1)");
+        }
+        if range.is_some() {
+            conditions.push(if self.watermark.is_some() {
+                "rowid BETWEEN ?2 AND ?3"
+            } else {
+                "rowid BETWEEN ?1 AND ?2"
+            });
+        }
+        if conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", conditions.join(" AND "))
+        }
+    }
+
+    pub fn params(&self, range: Option<(i64, i64)>) -> ParamsFromIter<Vec<Value>> {
+        let mut params = Vec::new();
+        if let Some(mark) = &self.watermark {
+            params.push(Value::Text(mark.clone()));
+        }
+        if let Some((from, to)) = range {
+            params.push(Value::Integer(from));
+            params.push(Value::Integer(to));
+        }
+        rusqlite::params_from_iter(params)
+    }
+
+    pub fn newest_first(&self) -> bool {
+        !self.order.is_empty()
+    }
+
+    fn count_sql(&self) -> String {
+        format!("SELECT COUNT(*) FROM images{}", self.filter(None))
+    }
+
+    pub fn sql(&self, range: Option<(i64, i64)>) -> String {
+        let extra_select: String = self
+            .extra_columns
+            .iter()
+            .map(|c| format!(", \"{}\"", c.replace('"', "\"\"")))
+            .collect();
+        format!(
+            "SELECT id, {}, {}, width, height{} FROM images{}{}",
+            self.data,
+            self.mode,
+            extra_select,
+            self.filter(range),
+            self.order
+        )
+    }
+
+    pub fn read(&self, row: &Row) -> RawRow {
+        RawRow {
+            id: row.get(0),
+            blob: match row.get_ref(1) {
+                // Kept for the base64 check: an old importer wrote uploads as text.
+                Ok(ValueRef::Text(text)) => Ok(Stored::Text(text.to_vec())),
+                _ => row.get(1).map(Stored::Blob),
+            },
+            processed: Processed {
+                mode: row.get(2).ok().flatten(),
+                width: row.get(3).ok().flatten(),
+                height: row.get(4).ok().flatten(),
+            },
+            extras: self
+                .extra_columns
+                .iter()
+                .enumerate()
+                .map(|(i, column)| (column.clone(), row.get(5 + i)))
+                .collect(),
+            key: self.key.clone(),
+            sample: Sample::default(),
+        }
+    }
+}
+
+/// The `data` column as stored.
+enum Stored {
+    Blob(Vec<u8>),
+    Text(Vec<u8>),
+}
+
+/// One row as read from `images`, before anything is decoded.
+pub struct RawRow {
+    id: Result<String>,
+    blob: Result<Stored>,
+    processed: Processed,
+    extras: Vec<(String, Result<Option<Vec<u8>>>)>,
+    key: Option<Arc<Key>>,
+    sample: Sample,
+}
+
+impl RawRow {
+    /// Counts `took`, the time the row took to read, as its read stage.
+    pub fn record_read(&mut self, took: Duration) {
+        self.sample.add(Stage::Read, took);
+    }
+
+    /// Size of the BLOBs read for this row.
+    pub fn bytes(&self) -> u64 {
+        let data = match &self.blob {
+            Ok(Stored::Blob(data) | Stored::Text(data)) => data.len(),
+            Err(_) => 0,
+        };
+        let extras: usize = self
+            .extras
+            .iter()
+            .filter_map(|(_, blob)| blob.as_ref().ok()?.as_ref().map(Vec::len))
+            .sum();
+        (data + extras) as u64
+    }
+
+    /// Decodes every BLOB of the row, timing each stage. Pure, so it can
+    /// run on any thread.
+    pub fn inspect(self) -> Inspected {
+        let id = match self.id {
+            Ok(id) => id,
+            Err(e) => return Inspected::NoId(e),
+        };
+        let mut sample = self.sample;
+
+        let (data, primary) = match self.blob {
+            // Deep Image Analysis (Deep Inspection)
+            Ok(Stored::Blob(data)) => {
+                let plain = sample.time(Stage::Read, || {
+                    encryption::plaintext(self.key.as_deref(), &data)
+                });
+                let decoded = match plain {
+                    Err(reason) => Decoded::Undecryptable(reason),
+                    Ok(plain) => primary(&plain, &self.processed, &mut sample),
+                };
+                (data, decoded)
+            }
+            Ok(Stored::Text(text)) => {
+                let decoded = sample.time(Stage::Decode, || base64(&text, "TEXT"));
+                let decoded = decoded.unwrap_or_else(|| {
+                    Decoded::Unreadable(rusqlite::Error::InvalidColumnType(
+                        1,
+                        "data".to_string(),
+                        rusqlite::types::Type::Text,
+                    ))
+                });
+                (Vec::new(), decoded)
+            }
+            // Column types are incorrect (e.g., TEXT instead of BLOB)
+            Err(e) => (Vec::new(), Decoded::Unreadable(e)),
+        };
+
+        // Each extra column is validated on its own; NULL means "not generated".
+        let extras = self
+            .extras
+            .into_iter()
+            .filter_map(|(column, blob)| match blob {
+                Ok(Some(blob)) => Some((column, open(self.key.as_deref(), &blob, &mut sample))),
+                Ok(None) => None,
+                Err(e) => Some((column, Decoded::Unreadable(e))),
+            })
+            .collect();
+
+        Inspected::Row {
+            id,
+            data,
+            primary,
+            extras,
+            sample,
+        }
+    }
+}
+
+/// A decoded row, ready for [`Tally::apply`].
+pub enum Inspected {
+    /// Not even the ID could be read.
+    NoId(rusqlite::Error),
+    Row {
+        id: String,
+        data: Vec<u8>,
+        primary: Decoded,
+        extras: Vec<(String, Decoded)>,
+        sample: Sample,
+    },
+}
+
+/// Outcome of decoding one BLOB.
+pub enum Decoded {
+    /// Decodes; `processing` carries a problem found by the caller's check,
+    /// `color` one with the embedded ICC profile.
+    Valid {
+        processing: Option<String>,
+        color: Option<String>,
+    },
+    /// Does not decode; the reason leads with the likely cause.
+    Corrupt(Cause, String),
+    /// The column holds something other than a BLOB.
+    Unreadable(rusqlite::Error),
+    /// Base64 text of a valid image; `--fix decode-base64` can rewrite it.
+    Base64(String),
+    /// Encryption is configured and the BLOB does not decrypt.
+    Undecryptable(String),
+}
+
+/// How the scan judges a decrypted `data` BLOB recorded with upload `mode`,
+/// without a database row. The fuzz targets drive the scan through it.
+pub fn inspect_blob(plain: &[u8], mode: Option<&str>) -> Decoded {
+    let processed = Processed {
+        mode: mode.map(str::to_string),
+        width: None,
+        height: None,
+    };
+    primary(plain, &processed, &mut Sample::default())
+}
+
+/// Decodes and checks against the recorded processing; what does not decode
+/// may still be base64 text of an image.
+fn primary(plain: &[u8], processed: &Processed, sample: &mut Sample) -> Decoded {
+    match decode(plain, |img| processed.problem(img, plain), sample) {
+        Decoded::Corrupt(cause, reason) => sample
+            .time(Stage::Decode, || base64(plain, "BLOB"))
+            .unwrap_or(Decoded::Corrupt(cause, reason)),
+        decoded => decoded,
+    }
+}
+
+/// `Some` when `raw` is base64 of a valid image, see [`base64_blob::detect`].
+fn base64(raw: &[u8], stored_as: &str) -> Option<Decoded> {
+    let (bytes, format) = base64_blob::detect(raw)?;
+    Some(Decoded::Base64(format!(
+        "{} holds base64 of a valid {:?} image ({} bytes decoded)",
+        stored_as,
+        format,
+        bytes.len()
+    )))
+}
+
+/// Decrypts (when a key is configured) and decodes a BLOB without further checks.
+fn open(key: Option<&Key>, blob: &[u8], sample: &mut Sample) -> Decoded {
+    match sample.time(Stage::Read, || encryption::plaintext(key, blob)) {
+        Ok(plain) => decode(&plain, |_| None, sample),
+        Err(reason) => Decoded::Undecryptable(reason),
+    }
+}
+
+fn decode(
+    blob: &[u8],
+    verify: impl FnOnce(&DynamicImage) -> Option<String>,
+    sample: &mut Sample,
+) -> Decoded {
+    match color::decode(blob, sample) {
+        Ok((img, icc)) => Decoded::Valid {
+            processing: verify(&img),
+            color: icc.as_deref().and_then(color::problem),
+        },
+        Err(e) => {
+            let (cause, detail) = cause::classify(blob);
+            Decoded::Corrupt(cause, format!("{}: {} ({})", cause.as_str(), detail, e))
+        }
+    }
+}
+
+/// Upload processing recorded on the row.
+struct Processed {
+    mode: Option<String>,
+    width: Option<i64>,
+    height: Option<i64>,
+}
+
+impl Processed {
+    /// Whether the image is what the server produces for the recorded mode
+    /// (see [`octa_image::verify`]) and has the recorded dimensions. Unknown
+    /// modes are not checked.
+    fn problem(&self, img: &DynamicImage, blob: &[u8]) -> Option<String> {
+        let mode = octa_image::Mode::parse(self.mode.as_deref()?)?;
+        let (w, h) = img.dimensions();
+        if let Some(problem) = octa_image::verify(mode, w, h, octa_image::sniff(blob)) {
+            return Some(problem);
+        }
+        if let (Some(rw), Some(rh)) = (self.width, self.height) {
+            if (rw, rh) != (w as i64, h as i64) {
+                return Some(format!(
+                    "image is {}x{} but the row records {}x{}",
+                    w, h, rw, rh
+                ));
+            }
+        }
+        None
+    }
+}
+
+/// Resolves `--max-findings` to an absolute count. `rows_in_scope` is only
+/// called for percentage limits.
+pub fn finding_limit<E>(
+    opts: &RunOptions,
+    rows_in_scope: impl FnOnce() -> std::result::Result<u64, E>,
+) -> std::result::Result<u64, E> {
+    Ok(match opts.max_findings {
+        None => u64::MAX,
+        Some(FindingLimit::Count(n)) => n,
+        Some(FindingLimit::Percent(pct)) => {
+            let rows = rows_in_scope()?;
+            ((rows as f64 * pct / 100.0).ceil() as u64).max(1)
+        }
+    })
+}
+
+/// How often a watched database file is checked for replacement.
+const SOURCE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Stats and findings of one pass. Every storage backend feeds its assets
+/// through here, so they all share the same decode pipeline and reporting.
+pub struct Tally<'a> {
+    stats: AuditStats,
+    findings: Vec<Finding>,
+    limit: u64,
+    aborted: Option<String>,
+    on_healthy: &'a mut dyn FnMut(&str, &[u8]),
+    key: Option<Arc<Key>>,
+    /// Extra BLOB column being checked. Only the asset itself reaches `on_healthy`.
+    column: Option<String>,
+    source: Option<db::Source>,
+    stream: Option<FindingStream>,
+    performance: Performance,
+    /// Read time of the asset [`Tally::check`] decodes next.
+    last_read: Duration,
+    source_checked: Instant,
+    source_replaced: bool,
+}
+
+impl<'a> Tally<'a> {
+    pub fn new(
+        limit: u64,
+        key: Option<Arc<Key>>,
+        on_healthy: &'a mut dyn FnMut(&str, &[u8]),
+    ) -> Self {
+        Self {
+            stats: AuditStats::default(),
+            findings: Vec::new(),
+            limit,
+            aborted: None,
+            on_healthy,
+            key,
+            column: None,
+            source: None,
+            stream: None,
+            performance: Performance::default(),
+            last_read: Duration::ZERO,
+            source_checked: Instant::now(),
+            source_replaced: false,
+        }
+    }
+
+    /// Stops the scan once `source` is replaced, see [`Tally::should_stop`].
+    pub fn watch_source(&mut self, source: Option<db::Source>) {
+        self.source = source;
+    }
+
+    /// Emits every finding to `stream` as well as collecting it.
+    pub fn stream_to(&mut self, stream: Option<FindingStream>) {
+        self.stream = stream;
+    }
+
+    /// Collects the stage times of every asset into [`Performance::stages`].
+    pub fn profile_stages(&mut self, on: bool) {
+        self.performance.stages = on.then(Timings::default);
+    }
+
+    fn profile(&mut self, sample: &Sample) {
+        if let Some(stages) = &mut self.performance.stages {
+            stages.record(sample);
+        }
+    }
+
+    /// Attributes the following checks to an extra BLOB column (`None`: the asset's `data`).
+    pub fn set_column(&mut self, column: Option<&str>) {
+        self.column = column.map(str::to_string);
+    }
+
+    fn push(&mut self, id: Option<String>, kind: FindingKind, reason: String) {
+        let mut finding = Finding::new(id, kind, reason);
+        finding.column = self.column.clone();
+        if let Some(stream) = &self.stream {
+            stream.emit(&finding);
+        }
+        self.findings.push(finding);
+    }
+
+    /// Counts `bytes` read from storage in `took`.
+    pub fn read(&mut self, bytes: u64, took: Duration) {
+        self.performance.bytes_read += bytes;
+        self.performance.io_wait += took;
+        self.last_read = took;
+    }
+
+    /// Counts time spent decoding outside the tally ([`RawRow::inspect`]).
+    pub fn decoded(&mut self, took: Duration) {
+        self.performance.decoding += took;
+    }
+
+    /// Decodes one asset in memory, read in the time last passed to
+    /// [`Tally::read`].
+    pub fn check(&mut self, id: String, blob: &[u8]) {
+        let mut sample = Sample::default();
+        sample.add(Stage::Read, std::mem::take(&mut self.last_read));
+        let started = Instant::now();
+        let decoded = open(self.key.as_deref(), blob, &mut sample);
+        self.decoded(started.elapsed());
+        self.profile(&sample);
+        self.record(id, blob, decoded)
+    }
+
+    /// Records a row decoded by [`RawRow::inspect`].
+    pub fn apply(&mut self, inspected: Inspected) {
+        match inspected {
+            Inspected::NoId(e) => self.schema_error(None, e),
+            Inspected::Row {
+                id,
+                data,
+                primary,
+                extras,
+                sample,
+            } => {
+                self.profile(&sample);
+                self.record(id.clone(), &data, primary);
+                for (column, decoded) in extras {
+                    self.set_column(Some(&column));
+                    self.record(id.clone(), &[], decoded);
+                }
+                self.set_column(None);
+            }
+        }
+    }
+
+    /// A processing problem on a decodable image still counts it as healthy
+    /// (and still exports it): the asset itself is intact.
+    fn record(&mut self, id: String, blob: &[u8], decoded: Decoded) {
+        match decoded {
+            Decoded::Corrupt(cause, reason) => {
+                self.stats.total_scanned += 1;
+                error!(target: FINDING_TARGET, tag = "CORRUPT", id = %id, column = self.column.as_deref(), cause = cause.as_str(), reason = %reason, "Corrupted blob");
+                self.stats.corrupted_blob += 1;
+                *self.stats.corrupt_causes.entry(cause).or_default() += 1;
+                self.push(Some(id), FindingKind::CorruptBlob, reason);
+            }
+            Decoded::Unreadable(e) => self.schema_error(Some(id), e),
+            Decoded::Undecryptable(reason) => {
+                self.stats.total_scanned += 1;
+                error!(target: FINDING_TARGET, tag = "DECRYPT", id = %id, column = self.column.as_deref(), reason = %reason, "Blob does not decrypt");
+                self.stats.decrypt_failures += 1;
+                self.push(Some(id), FindingKind::DecryptFailed, reason);
+            }
+            Decoded::Base64(reason) => {
+                self.stats.total_scanned += 1;
+                warn!(target: FINDING_TARGET, tag = "BASE64", id = %id, column = self.column.as_deref(), reason = %reason, "Base64 text instead of image bytes");
+                self.stats.base64_blobs += 1;
+                self.push(Some(id), FindingKind::Base64Blob, reason);
+            }
+            Decoded::Valid { processing, color } => {
+                self.stats.total_scanned += 1;
+                if let Some(reason) = color {
+                    warn!(target: FINDING_TARGET, tag = "COLOR", id = %id, column = self.column.as_deref(), reason = %reason, "Color profile problem");
+                    self.stats.color_issues += 1;
+                    self.push(Some(id.clone()), FindingKind::ColorProfile, reason);
+                }
+                if let Some(reason) = processing {
+                    warn!(target: FINDING_TARGET, tag = "PROCESS", id = %id, column = self.column.as_deref(), reason = %reason, "Processing mismatch");
+                    self.stats.processing_errors += 1;
+                    self.push(Some(id.clone()), FindingKind::ProcessingMismatch, reason);
+                }
+                self.stats.healthy += 1;
+                if self.column.is_none() {
+                    (self.on_healthy)(&id, blob);
+                }
+            }
+        }
+    }
+
+    pub fn schema_error(&mut self, id: Option<String>, e: impl std::fmt::Display) {
+        self.stats.total_scanned += 1;
+        match &id {
+            Some(id) => {
+                warn!(target: FINDING_TARGET, tag = "DB-ERR", id = %id, column = self.column.as_deref(), reason = %e, "Schema mismatch")
+            }
+            None => {
+                warn!(target: FINDING_TARGET, tag = "DB-ERR", column = self.column.as_deref(), reason = %e, "Schema mismatch")
+            }
+        }
+        self.stats.db_schema_error += 1;
+        self.push(id, FindingKind::SchemaMismatch, e.to_string());
+    }
+
+    /// The asset could not be read at all.
+    pub fn row_failure(&mut self, id: Option<String>, e: impl std::fmt::Display) {
+        self.stats.total_scanned += 1;
+        match &id {
+            Some(id) => {
+                error!(target: FINDING_TARGET, tag = "FATAL", id = %id, column = self.column.as_deref(), reason = %e, "Critical row failure")
+            }
+            None => {
+                error!(target: FINDING_TARGET, tag = "FATAL", column = self.column.as_deref(), reason = %e, "Critical row failure")
+            }
+        }
+        self.push(id, FindingKind::RowFailure, e.to_string());
+    }
+
+    /// True once the scan has to stop: the findings reached the limit, or the
+    /// watched database file was replaced. The caller stops scanning.
+    pub fn should_stop(&mut self) -> bool {
+        if self.source_replaced() {
+            return true;
+        }
+        if (self.findings.len() as u64) < self.limit {
+            return false;
+        }
+        let reason = format!(
+            "{} findings after {} rows (limit {})",
+            self.findings.len(),
+            self.stats.total_scanned,
+            self.limit
+        );
+        error!(tag = "ABORT", reason = %reason, "Widespread corruption detected, stopping scan");
+        self.aborted = Some(reason);
+        true
+    }
+
+    /// Looks at the file at most every [`SOURCE_CHECK_INTERVAL`].
+    fn source_replaced(&mut self) -> bool {
+        let Some(source) = &self.source else {
+            return false;
+        };
+        if self.source_checked.elapsed() < SOURCE_CHECK_INTERVAL {
+            return false;
+        }
+        self.source_checked = Instant::now();
+        if !source.replaced() {
+            return false;
+        }
+        let reason = format!(
+            "database file {} was replaced after {} rows",
+            source.path.display(),
+            self.stats.total_scanned
+        );
+        warn!(tag = "ROTATE", reason = %reason, "Database file replaced, stopping scan");
+        self.aborted = Some(reason);
+        self.source_replaced = true;
+        true
+    }
+
+    pub fn finish(self) -> AuditResult {
+        AuditResult {
+            stats: self.stats,
+            findings: self.findings,
+            aborted: self.aborted,
+            source_replaced: self.source_replaced,
+            workers: Vec::new(),
+            performance: Performance {
+                peak_memory: peak_memory(),
+                ..self.performance
+            },
+        }
+    }
+}
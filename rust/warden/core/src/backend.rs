@@ -0,0 +1,43 @@
+use crate::audit::{self, AuditResult, RunOptions, Tally};
+use octa_storage::Backend;
+use std::convert::Infallible;
+use std::ops::ControlFlow;
+use tracing::{debug, info};
+
+/// Audits a file or object store with the same decode pipeline as the
+/// SQLite backend. Only a failed listing fails the run; assets that cannot
+/// be read become findings.
+pub fn run(
+    backend: &dyn Backend,
+    opts: &RunOptions,
+    on_healthy: &mut dyn FnMut(&str, &[u8]),
+) -> Result<AuditResult, String> {
+    let ids = backend
+        .list()
+        .map_err(|e| format!("listing {} failed: {}", backend.describe(), e))?;
+    info!(tag = "→", assets = ids.len(), store = %backend.describe(), "Assets listed");
+
+    let Ok(limit) = audit::finding_limit(opts, || Ok::<_, Infallible>(ids.len() as u64));
+    let mut tally = Tally::new(limit, opts.decryption.clone(), on_healthy);
+    tally.profile_stages(opts.profile_stages);
+
+    // Reads may run ahead on the backend's workers; I/O wait is the time spent waiting on them.
+    backend.stream(&ids, &mut |fetched| {
+        let id = fetched.id.to_string();
+        match fetched.data {
+            Ok(Some(blob)) => {
+                tally.read(blob.len() as u64, fetched.waited);
+                tally.check(id, &blob)
+            }
+            Ok(None) => debug!(id = %id, "Asset deleted since listing, skipped"),
+            Err(e) => tally.row_failure(Some(id), e),
+        }
+
+        if tally.should_stop() {
+            return ControlFlow::Break(());
+        }
+        ControlFlow::Continue(())
+    });
+
+    Ok(tally.finish())
+}
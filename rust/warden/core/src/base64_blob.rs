@@ -0,0 +1,70 @@
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD};
+use base64::Engine;
+use image::{load_from_memory, ImageFormat};
+use octa_logging::FINDING_TARGET;
+use octa_store::cas;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OptionalExtension, Result};
+use tracing::{info, warn};
+
+/// Shorter payloads cannot hold an image worth recovering.
+const MIN_LEN: usize = 16;
+
+/// An old importer stored some uploads as base64 text instead of raw bytes.
+/// Returns the decoded image and its format when `raw` is exactly that:
+/// base64 (optionally a `data:image/...;base64,` URI) of bytes that decode as an image.
+pub fn detect(raw: &[u8]) -> Option<(Vec<u8>, ImageFormat)> {
+    let text = std::str::from_utf8(raw).ok()?.trim();
+    let text = match text.strip_prefix("data:") {
+        Some(uri) => uri.split_once(";base64,")?.1,
+        None => text,
+    };
+    let compact: String = text.split_ascii_whitespace().collect();
+    if compact.len() < MIN_LEN
+        || !compact
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'/' | b'-' | b'_' | b'='))
+    {
+        return None;
+    }
+
+    let bytes = [STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD]
+        .iter()
+        .find_map(|engine| engine.decode(&compact).ok())?;
+    let format = image::guess_format(&bytes).ok()?;
+    load_from_memory(&bytes).ok()?;
+    Some((bytes, format))
+}
+
+/// Rewrites each row's base64 `data` as the decoded BLOB (and its `size`),
+/// re-validating the payload first. Returns how many rows were rewritten.
+pub fn repair(conn: &mut Connection, ids: &[String]) -> Result<u64> {
+    let mut written = 0;
+    let tx = conn.transaction()?;
+    let select = format!("SELECT {} FROM images WHERE id = ?1", cas::data(&tx)?);
+    for id in ids {
+        let raw: Option<Vec<u8>> = tx
+            .query_row(&select, [id], |row| {
+                Ok(match row.get_ref(0)? {
+                    ValueRef::Text(b) | ValueRef::Blob(b) => Some(b.to_vec()),
+                    _ => None,
+                })
+            })
+            .optional()?
+            .flatten();
+        let Some((bytes, format)) = raw.as_deref().and_then(detect) else {
+            warn!(tag = "SKIP", id = %id, "Row is no longer base64 image data, left untouched");
+            continue;
+        };
+
+        cas::rewrite(&tx, id, &bytes)?;
+        tx.execute(
+            "UPDATE images SET size = ?1 WHERE id = ?2",
+            rusqlite::params![bytes.len() as i64, id],
+        )?;
+        info!(target: FINDING_TARGET, tag = "APPLY", id = %id, format = ?format, bytes = bytes.len(), "Base64 data decoded to BLOB");
+        written += 1;
+    }
+    tx.commit()?;
+    Ok(written)
+}
@@ -0,0 +1,281 @@
+use crate::audit::{AuditResult, Finding};
+use crate::cause;
+use crate::growth;
+use crate::health::{Assessment, Verdict};
+use crate::report;
+use chrono::Local;
+use serde_json::json;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Writes the report of one run in every format into a new directory
+/// `root/warden-YYYYMMDD-HHMMSS`: `report.txt` (the console report),
+/// `report.json`, `findings.csv` and a self-contained `report.html`.
+pub fn write(root: &Path, result: &AuditResult, duration: Duration, assessment: &Assessment) {
+    match write_all(root, result, duration, assessment) {
+        Ok(dir) => info!(
+            tag = "OK",
+            path = %dir.display(),
+            findings = result.findings.len(),
+            "Report bundle written"
+        ),
+        Err(e) => {
+            error!(tag = "ERROR", path = %root.display(), reason = %e, "Could not write report bundle")
+        }
+    }
+}
+
+fn write_all(
+    root: &Path,
+    result: &AuditResult,
+    duration: Duration,
+    assessment: &Assessment,
+) -> io::Result<PathBuf> {
+    let dir = create_dir(root)?;
+    let text = report::text_report(result, duration, assessment);
+    fs::write(
+        dir.join("report.txt"),
+        console::strip_ansi_codes(&text).as_ref(),
+    )?;
+    fs::write(
+        dir.join("report.json"),
+        serde_json::to_vec_pretty(&json_report(result, duration, assessment))?,
+    )?;
+    fs::write(dir.join("findings.csv"), csv(&result.findings))?;
+    fs::write(dir.join("report.html"), html(result, duration, assessment))?;
+    Ok(dir)
+}
+
+/// A fresh directory per run; runs within the same second get a suffix.
+fn create_dir(root: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(root)?;
+    let name = format!("warden-{}", Local::now().format("%Y%m%d-%H%M%S"));
+    let mut dir = root.join(&name);
+    let mut n = 1;
+    loop {
+        match fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                n += 1;
+                dir = root.join(format!("{}-{}", name, n));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn json_report(
+    result: &AuditResult,
+    duration: Duration,
+    assessment: &Assessment,
+) -> serde_json::Value {
+    let stats = &result.stats;
+    let perf = &result.performance;
+    json!({
+        "tool": format!("octa-warden {}", env!("CARGO_PKG_VERSION")),
+        "generated_at": Local::now().to_rfc3339(),
+        "elapsed_ms": duration.as_millis() as u64,
+        "status": assessment.verdict.label(),
+        "reasons": assessment.reasons,
+        "aborted": result.aborted,
+        "stats": {
+            "scanned": stats.total_scanned,
+            "healthy": stats.healthy,
+            "corrupted_blobs": stats.corrupted_blob,
+            "schema_errors": stats.db_schema_error,
+            "derived_issues": stats.derived_issues,
+            "processing_errors": stats.processing_errors,
+            "base64_blobs": stats.base64_blobs,
+            "decrypt_failures": stats.decrypt_failures,
+            "color_issues": stats.color_issues,
+            "timestamp_issues": stats.timestamp_issues,
+            "corrupt_causes": stats.corrupt_causes.iter()
+                .map(|(cause, count)| (cause.as_str().to_string(), json!(count)))
+                .collect::<serde_json::Map<_, _>>(),
+            "likely_origin": cause::likely_origin(&stats.corrupt_causes),
+        },
+        "performance": {
+            "rows_per_sec": perf.rows_per_sec(stats.total_scanned, duration),
+            "mb_per_sec": perf.mb_per_sec(duration),
+            "bytes_read": perf.bytes_read,
+            "decode_ms": perf.decoding.as_millis() as u64,
+            "io_wait_ms": perf.io_wait.as_millis() as u64,
+            "peak_memory_bytes": perf.peak_memory,
+            "stages": perf.stages.as_ref().map(|stages| stages.ran().map(|(stage, s)| json!({
+                "stage": stage.as_str(),
+                "assets": s.count,
+                "total_ms": s.total.as_millis() as u64,
+                "mean_us": s.mean().as_micros() as u64,
+                "p50_us": s.percentile(0.5).as_micros() as u64,
+                "p95_us": s.percentile(0.95).as_micros() as u64,
+                "max_us": s.max.as_micros() as u64,
+            })).collect::<Vec<_>>()),
+        },
+        "workers": result.workers.iter().map(|w| json!({
+            "role": w.role.as_str(),
+            "reader": w.reader,
+            "index": w.index,
+            "rows": w.rows,
+            "bytes": w.bytes,
+            "busy_ms": w.busy.as_millis() as u64,
+            "waiting_ms": w.waiting.as_millis() as u64,
+        })).collect::<Vec<_>>(),
+        "findings": result.findings.iter().map(|f| json!({
+            "kind": f.kind.as_str(),
+            "severity": f.severity.as_str(),
+            "id": f.id,
+            "reason": f.reason,
+            "column": f.column,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// RFC 4180: fields with commas, quotes or line breaks are quoted.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv(findings: &[Finding]) -> String {
+    let mut out = String::from("kind,severity,id,reason,column\r\n");
+    for f in findings {
+        let fields = [
+            f.kind.as_str(),
+            f.severity.as_str(),
+            f.id.as_deref().unwrap_or(""),
+            f.reason.trim(),
+            f.column.as_deref().unwrap_or(""),
+        ];
+        let row: Vec<String> = fields.iter().map(|v| csv_field(v)).collect();
+        out.push_str(&row.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html(result: &AuditResult, duration: Duration, assessment: &Assessment) -> String {
+    let stats = &result.stats;
+    let perf = &result.performance;
+    let color = match assessment.verdict {
+        Verdict::Healthy => "#1a7f37",
+        Verdict::Warning => "#9a6700",
+        Verdict::Critical => "#cf222e",
+    };
+
+    let mut summary = vec![
+        ("Time Elapsed", format!("{:?}", duration)),
+        ("Assets Scanned", stats.total_scanned.to_string()),
+        ("Healthy Assets", stats.healthy.to_string()),
+        ("Corrupted Blobs", stats.corrupted_blob.to_string()),
+        ("Schema Errors", stats.db_schema_error.to_string()),
+        ("Processing Bugs", stats.processing_errors.to_string()),
+        ("Base64 Blobs", stats.base64_blobs.to_string()),
+        ("Decrypt Failures", stats.decrypt_failures.to_string()),
+        ("Color Profiles", stats.color_issues.to_string()),
+        ("Timestamps", stats.timestamp_issues.to_string()),
+        ("Derived Issues", stats.derived_issues.to_string()),
+        (
+            "Throughput",
+            format!(
+                "{:.1} rows/s, {:.1} MB/s",
+                perf.rows_per_sec(stats.total_scanned, duration),
+                perf.mb_per_sec(duration)
+            ),
+        ),
+        ("Bytes Read", growth::format_bytes(perf.bytes_read as f64)),
+        (
+            "Decode Time",
+            format!("{:.2?} (I/O wait {:.2?})", perf.decoding, perf.io_wait),
+        ),
+    ];
+    if let Some(peak) = perf.peak_memory {
+        summary.push(("Peak Memory", growth::format_bytes(peak as f64)));
+    }
+    if !stats.corrupt_causes.is_empty() {
+        let causes = stats
+            .corrupt_causes
+            .iter()
+            .map(|(cause, count)| format!("{} {}", cause.as_str(), count))
+            .collect::<Vec<_>>()
+            .join(", ");
+        summary.push(("Corruption Causes", causes));
+    }
+    if let Some(origin) = cause::likely_origin(&stats.corrupt_causes) {
+        summary.push(("Likely Origin", origin.to_string()));
+    }
+    if let Some(reason) = &result.aborted {
+        summary.push(("Scan Aborted", reason.clone()));
+    }
+    let summary: String = summary
+        .iter()
+        .map(|(k, v)| format!("<tr><th>{}</th><td>{}</td></tr>\n", k, escape(v)))
+        .collect();
+    let reasons: String = assessment
+        .reasons
+        .iter()
+        .map(|r| format!("<li>{}</li>\n", escape(r)))
+        .collect();
+    let findings: String = result
+        .findings
+        .iter()
+        .map(|f| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td><code>{}</code></td><td>{}</td><td>{}</td></tr>\n",
+                f.kind.as_str(),
+                f.severity.as_str(),
+                escape(f.id.as_deref().unwrap_or("-")),
+                escape(f.reason.trim()),
+                escape(f.column.as_deref().unwrap_or("-"))
+            )
+        })
+        .collect();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Warden Audit Report</title>
+<style>
+body {{ font-family: system-ui, sans-serif; margin: 2rem; color: #1f2328; }}
+table {{ border-collapse: collapse; margin-bottom: 1.5rem; }}
+th, td {{ text-align: left; padding: .3rem .8rem; border-bottom: 1px solid #d0d7de; vertical-align: top; }}
+.status {{ color: {color}; font-weight: bold; }}
+</style>
+</head>
+<body>
+<h1>Warden Audit Report</h1>
+<p class="status">{status}</p>
+<ul>
+{reasons}</ul>
+<table>
+{summary}</table>
+<h2>Findings ({count})</h2>
+<table>
+<tr><th>Kind</th><th>Severity</th><th>ID</th><th>Reason</th><th>Column</th></tr>
+{findings}</table>
+</body>
+</html>
+"#,
+        color = color,
+        status = assessment.verdict.label(),
+        reasons = reasons,
+        summary = summary,
+        count = result.findings.len(),
+        findings = findings,
+    )
+}
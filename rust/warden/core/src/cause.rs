@@ -0,0 +1,214 @@
+use image::ImageFormat;
+use std::collections::BTreeMap;
+
+/// Zero runs at least this long (one disk sector) are not produced by image encoders.
+const SECTOR: usize = 512;
+
+/// How far into the BLOB a displaced image signature is searched for.
+const SIGNATURE_WINDOW: usize = 64;
+
+/// Likely root cause of a BLOB that does not decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Cause {
+    /// Ends early: empty, or the format's end marker is missing.
+    Truncated,
+    /// The signature or header is damaged while the rest looks like an image.
+    HeaderDamage,
+    /// Sector-sized runs of zero bytes, typical of lost or unwritten pages.
+    ZeroedPages,
+    /// Not image data at all (HTML, JSON, PDF, archives, text).
+    WrongFormat,
+    /// A valid header and trailer; the damage is somewhere in between.
+    Unknown,
+}
+
+impl Cause {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Cause::Truncated => "truncated",
+            Cause::HeaderDamage => "header_damage",
+            Cause::ZeroedPages => "zeroed_pages",
+            Cause::WrongFormat => "wrong_format",
+            Cause::Unknown => "unknown",
+        }
+    }
+
+    /// Where this kind of damage usually comes from.
+    pub fn origin(&self) -> &'static str {
+        match self {
+            Cause::ZeroedPages | Cause::HeaderDamage => "disk",
+            Cause::Truncated | Cause::WrongFormat => "application",
+            Cause::Unknown => "unknown",
+        }
+    }
+}
+
+/// The origin shared by most classified blobs, if one accounts for a
+/// majority of them. `Unknown` causes are not counted.
+pub fn likely_origin(causes: &BTreeMap<Cause, u64>) -> Option<&'static str> {
+    let mut origins: BTreeMap<&'static str, u64> = BTreeMap::new();
+    for (cause, count) in causes {
+        if *cause != Cause::Unknown {
+            *origins.entry(cause.origin()).or_default() += count;
+        }
+    }
+    let total: u64 = causes.values().sum();
+    origins
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .filter(|(_, count)| count * 2 > total)
+        .map(|(origin, _)| origin)
+}
+
+/// Classifies an undecodable BLOB by looking at its bytes. Returns the cause
+/// and a short explanation.
+pub fn classify(blob: &[u8]) -> (Cause, String) {
+    if blob.is_empty() {
+        return (Cause::Truncated, "empty".to_string());
+    }
+    if let Some(offset) = zero_run(blob) {
+        return (
+            Cause::ZeroedPages,
+            format!("{}+ zero bytes at offset {}", SECTOR, offset),
+        );
+    }
+
+    match image::guess_format(blob) {
+        Ok(format) => match missing_trailer(format, blob) {
+            Some(detail) => (Cause::Truncated, detail),
+            None => (
+                Cause::Unknown,
+                format!("{} header and trailer intact", name(format)),
+            ),
+        },
+        Err(_) => {
+            if let Some((format, offset)) = displaced_signature(blob) {
+                return (
+                    Cause::HeaderDamage,
+                    format!(
+                        "{} signature at offset {} instead of 0",
+                        name(format),
+                        offset
+                    ),
+                );
+            }
+            if let Some(detail) = damaged_signature(blob) {
+                return (Cause::HeaderDamage, detail);
+            }
+            if let Some(kind) = foreign_content(blob) {
+                return (Cause::WrongFormat, format!("looks like {}", kind));
+            }
+            (Cause::Unknown, "no image signature".to_string())
+        }
+    }
+}
+
+fn zero_run(blob: &[u8]) -> Option<usize> {
+    if blob.len() < SECTOR {
+        return blob.iter().all(|b| *b == 0).then_some(0);
+    }
+    blob.windows(SECTOR)
+        .step_by(SECTOR / 2)
+        .position(|w| w.iter().all(|b| *b == 0))
+        .map(|i| i * SECTOR / 2)
+}
+
+/// Checks the end marker of formats that have one.
+fn missing_trailer(format: ImageFormat, blob: &[u8]) -> Option<String> {
+    // Some writers pad files; ignore trailing zeros.
+    let end = blob.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    let trimmed = &blob[..end];
+    match format {
+        ImageFormat::Jpeg if !trimmed.ends_with(&[0xFF, 0xD9]) => {
+            Some("missing JPEG EOI marker".to_string())
+        }
+        ImageFormat::Png if !contains(&blob[blob.len().saturating_sub(64)..], b"IEND") => {
+            Some("missing PNG IEND chunk".to_string())
+        }
+        ImageFormat::Gif if !trimmed.ends_with(&[0x3B]) => Some("missing GIF trailer".to_string()),
+        ImageFormat::WebP if blob.len() >= 8 => {
+            let declared = u32::from_le_bytes([blob[4], blob[5], blob[6], blob[7]]) as usize + 8;
+            (declared > blob.len()).then(|| {
+                format!(
+                    "RIFF declares {} bytes, only {} present",
+                    declared,
+                    blob.len()
+                )
+            })
+        }
+        _ => None,
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// A valid signature shortly after the start: bytes were prepended or shifted.
+fn displaced_signature(blob: &[u8]) -> Option<(ImageFormat, usize)> {
+    let window = &blob[..blob.len().min(SIGNATURE_WINDOW)];
+    (1..window.len()).find_map(|offset| {
+        let format = image::guess_format(&blob[offset..]).ok()?;
+        // Short signatures (e.g. BMP's "BM") match random bytes too easily.
+        matches!(
+            format,
+            ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Gif | ImageFormat::WebP
+        )
+        .then_some((format, offset))
+    })
+}
+
+/// The signature is wrong, but the structure behind it is recognizable.
+fn damaged_signature(blob: &[u8]) -> Option<String> {
+    let at = |offset: usize, tag: &[u8]| blob.get(offset..offset + tag.len()) == Some(tag);
+    if at(6, b"JFIF") || at(6, b"Exif") {
+        return Some("JPEG SOI marker damaged".to_string());
+    }
+    if at(12, b"IHDR") {
+        return Some("PNG signature damaged".to_string());
+    }
+    if at(8, b"WEBP") {
+        return Some("RIFF header damaged".to_string());
+    }
+    if blob.len() > 4 && blob.ends_with(&[0xFF, 0xD9]) {
+        return Some("JPEG trailer intact, header unreadable".to_string());
+    }
+    None
+}
+
+fn foreign_content(blob: &[u8]) -> Option<&'static str> {
+    let start = blob
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .map_or(blob, |i| &blob[i..]);
+    let lower: Vec<u8> = start
+        .iter()
+        .take(16)
+        .map(|b| b.to_ascii_lowercase())
+        .collect();
+
+    if lower.starts_with(b"<!doctype html") || lower.starts_with(b"<html") {
+        Some("HTML")
+    } else if lower.starts_with(b"<?xml") || lower.starts_with(b"<svg") {
+        Some("XML/SVG")
+    } else if lower.starts_with(b"{") || lower.starts_with(b"[") {
+        Some("JSON")
+    } else if start.starts_with(b"%PDF") {
+        Some("PDF")
+    } else if start.starts_with(b"PK\x03\x04") {
+        Some("a ZIP archive")
+    } else if start.starts_with(&[0x1F, 0x8B]) {
+        Some("gzip data")
+    } else {
+        let sample = &blob[..blob.len().min(512)];
+        let printable = sample
+            .iter()
+            .filter(|b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+            .count();
+        (printable * 100 >= sample.len() * 95).then_some("text")
+    }
+}
+
+fn name(format: ImageFormat) -> String {
+    format!("{:?}", format).to_uppercase()
+}
@@ -0,0 +1,226 @@
+use crate::compression;
+use image::codecs::jpeg::JpegEncoder;
+use image::{
+    ColorType, DynamicImage, ImageBuffer, ImageDecoder, ImageFormat, ImageReader, ImageResult,
+    Limits,
+};
+use moxcms::{ColorProfile, DataColorSpace, Layout, ProfileText, TransformOptions, Xyzd};
+use octa_image::stages::{Sample, Stage};
+use octa_logging::FINDING_TARGET;
+use octa_store::cas;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OptionalExtension, Result};
+use std::io::Cursor;
+use tracing::{info, warn};
+
+/// Colorant distance still taken as sRGB: profiles in the wild differ in
+/// s15Fixed16 rounding and in how they adapt to D50.
+const SRGB_TOLERANCE: f64 = 0.01;
+
+/// Decodes an image together with its embedded ICC profile, timing the
+/// sniff and the decode into `sample`.
+pub fn decode(blob: &[u8], sample: &mut Sample) -> ImageResult<(DynamicImage, Option<Vec<u8>>)> {
+    let reader = sample.time(Stage::Sniff, || {
+        ImageReader::new(Cursor::new(blob)).with_guessed_format()
+    })?;
+    sample.format = reader.format();
+    sample.time(Stage::Decode, || -> ImageResult<_> {
+        let mut decoder = reader.into_decoder()?;
+        // `from_decoder` skips the allocation limit `ImageReader::decode`
+        // applies, and a forged header can claim gigabytes of pixels.
+        let mut limits = Limits::default();
+        limits.reserve(decoder.total_bytes())?;
+        decoder.set_limits(limits)?;
+        let icc = decoder.icc_profile().ok().flatten();
+        Ok((DynamicImage::from_decoder(decoder)?, icc))
+    })
+}
+
+/// `Some(reason)` when an embedded profile will not render as intended in
+/// browsers: it does not parse, is not RGB or gray, or describes an RGB
+/// space other than sRGB (wide-gamut images look washed out where the
+/// profile is ignored, and Octa's processed uploads drop it).
+pub fn problem(icc: &[u8]) -> Option<String> {
+    let profile = match ColorProfile::new_from_slice(icc) {
+        Ok(profile) => profile,
+        Err(e) => return Some(format!("broken ICC profile ({})", e)),
+    };
+    match profile.color_space {
+        DataColorSpace::Gray => None,
+        DataColorSpace::Rgb if is_srgb(&profile) => None,
+        DataColorSpace::Rgb => Some(format!("non-sRGB ICC profile '{}'", name(&profile))),
+        other => Some(format!("{:?} ICC profile '{}'", other, name(&profile))),
+    }
+}
+
+fn is_srgb(profile: &ColorProfile) -> bool {
+    let srgb = ColorProfile::new_srgb();
+    let near = |a: &Xyzd, b: &Xyzd| {
+        (a.x - b.x).abs() <= SRGB_TOLERANCE
+            && (a.y - b.y).abs() <= SRGB_TOLERANCE
+            && (a.z - b.z).abs() <= SRGB_TOLERANCE
+    };
+    // LUT-based profiles have no colorants; only their name says what they are.
+    if profile.red_colorant == Xyzd::default() {
+        return name(profile).contains("sRGB");
+    }
+    near(&profile.red_colorant, &srgb.red_colorant)
+        && near(&profile.green_colorant, &srgb.green_colorant)
+        && near(&profile.blue_colorant, &srgb.blue_colorant)
+}
+
+fn name(profile: &ColorProfile) -> String {
+    match &profile.description {
+        Some(ProfileText::PlainString(text)) => text.clone(),
+        Some(ProfileText::Localizable(texts)) => texts
+            .first()
+            .map(|text| text.value.clone())
+            .unwrap_or_default(),
+        Some(ProfileText::Description(text)) => text.ascii_string.clone(),
+        None => "unnamed".to_string(),
+    }
+    .trim_end_matches('\0')
+    .to_string()
+}
+
+/// Re-encodes an image as sRGB without a profile: JPEGs stay JPEG at their
+/// estimated quality, everything else becomes PNG. Returns the bytes and the
+/// format name Octa stores in `images.format`.
+pub fn to_srgb(blob: &[u8]) -> std::result::Result<(Vec<u8>, &'static str), String> {
+    let source = image::guess_format(blob).map_err(|e| e.to_string())?;
+    let (img, icc) = decode(blob, &mut Sample::default()).map_err(|e| e.to_string())?;
+    let icc = icc.ok_or("the image has no ICC profile")?;
+    let img = match ColorProfile::new_from_slice(&icc) {
+        // Browsers ignore a broken profile, so dropping it keeps the look.
+        Err(_) => img,
+        Ok(profile) if profile.color_space == DataColorSpace::Rgb => transform(&img, &profile)?,
+        Ok(profile) => {
+            return Err(format!(
+                "{:?} profiles cannot be converted once decoded",
+                profile.color_space
+            ))
+        }
+    };
+
+    let mut out = Vec::new();
+    if source == ImageFormat::Jpeg {
+        let quality = compression::jpeg_quality(blob).unwrap_or(compression::DEFAULT_QUALITY);
+        DynamicImage::ImageRgb8(img.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut out, quality))
+            .map_err(|e| e.to_string())?;
+        Ok((out, "jpeg"))
+    } else {
+        img.write_to(&mut Cursor::new(&mut out), ImageFormat::Png)
+            .map_err(|e| e.to_string())?;
+        Ok((out, "png"))
+    }
+}
+
+/// Converts the pixels from `profile` to sRGB, keeping alpha and 16-bit depth.
+fn transform(
+    img: &DynamicImage,
+    profile: &ColorProfile,
+) -> std::result::Result<DynamicImage, String> {
+    let srgb = ColorProfile::new_srgb();
+    let options = TransformOptions::default();
+    let (width, height) = (img.width(), img.height());
+    let wide = matches!(
+        img.color(),
+        ColorType::L16
+            | ColorType::La16
+            | ColorType::Rgb16
+            | ColorType::Rgba16
+            | ColorType::Rgb32F
+            | ColorType::Rgba32F
+    );
+    let alpha = img.color().has_alpha();
+    let layout = if alpha { Layout::Rgba } else { Layout::Rgb };
+    let buffer_error = || "converted pixels do not fit the image".to_string();
+
+    if wide {
+        let src = if alpha {
+            img.to_rgba16().into_raw()
+        } else {
+            img.to_rgb16().into_raw()
+        };
+        let mut dst = vec![0u16; src.len()];
+        profile
+            .create_transform_16bit(layout, &srgb, layout, options)
+            .and_then(|t| t.transform(&src, &mut dst))
+            .map_err(|e| e.to_string())?;
+        Ok(if alpha {
+            DynamicImage::ImageRgba16(
+                ImageBuffer::from_raw(width, height, dst).ok_or_else(buffer_error)?,
+            )
+        } else {
+            DynamicImage::ImageRgb16(
+                ImageBuffer::from_raw(width, height, dst).ok_or_else(buffer_error)?,
+            )
+        })
+    } else {
+        let src = if alpha {
+            img.to_rgba8().into_raw()
+        } else {
+            img.to_rgb8().into_raw()
+        };
+        let mut dst = vec![0u8; src.len()];
+        profile
+            .create_transform_8bit(layout, &srgb, layout, options)
+            .and_then(|t| t.transform(&src, &mut dst))
+            .map_err(|e| e.to_string())?;
+        Ok(if alpha {
+            DynamicImage::ImageRgba8(
+                ImageBuffer::from_raw(width, height, dst).ok_or_else(buffer_error)?,
+            )
+        } else {
+            DynamicImage::ImageRgb8(
+                ImageBuffer::from_raw(width, height, dst).ok_or_else(buffer_error)?,
+            )
+        })
+    }
+}
+
+/// Rewrites each row's image as sRGB (with its `size` and `format`),
+/// re-checking the profile first. Returns how many rows were rewritten.
+pub fn repair(conn: &mut Connection, ids: &[String]) -> Result<u64> {
+    let mut written = 0;
+    let tx = conn.transaction()?;
+    let select = format!("SELECT {} FROM images WHERE id = ?1", cas::data(&tx)?);
+    for id in ids {
+        let blob: Option<Vec<u8>> = tx
+            .query_row(&select, [id], |row| {
+                Ok(match row.get_ref(0)? {
+                    ValueRef::Blob(b) => Some(b.to_vec()),
+                    _ => None,
+                })
+            })
+            .optional()?
+            .flatten();
+        let flagged = blob
+            .as_deref()
+            .and_then(|blob| decode(blob, &mut Sample::default()).ok())
+            .and_then(|(_, icc)| icc)
+            .is_some_and(|icc| problem(&icc).is_some());
+        let Some(blob) = blob.filter(|_| flagged) else {
+            warn!(tag = "SKIP", id = %id, "Row no longer has a problematic ICC profile, left untouched");
+            continue;
+        };
+        let (bytes, format) = match to_srgb(&blob) {
+            Ok(converted) => converted,
+            Err(reason) => {
+                warn!(tag = "SKIP", id = %id, reason = %reason, "Could not convert to sRGB, left untouched");
+                continue;
+            }
+        };
+
+        cas::rewrite(&tx, id, &bytes)?;
+        tx.execute(
+            "UPDATE images SET size = ?1, format = ?2 WHERE id = ?3",
+            rusqlite::params![bytes.len() as i64, format, id],
+        )?;
+        info!(target: FINDING_TARGET, tag = "APPLY", id = %id, format = format, bytes = bytes.len(), "Converted to sRGB");
+        written += 1;
+    }
+    tx.commit()?;
+    Ok(written)
+}
@@ -0,0 +1,329 @@
+use crate::encryption::{self, Key};
+use image::codecs::avif::AvifEncoder;
+use image::{DynamicImage, ImageFormat};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// AVIF encoder speed (0 slowest .. 10 fastest). Sizes barely change above 6.
+const AVIF_SPEED: u8 = 6;
+
+/// Quality used for lossy sources whose quality cannot be read from the file.
+pub(crate) const DEFAULT_QUALITY: u8 = 85;
+
+/// libjpeg's baseline luminance quantization table (quality 50), in zigzag order.
+const STD_LUMINANCE: [u16; 64] = [
+    16, 11, 12, 14, 12, 10, 16, 14, 13, 14, 18, 17, 16, 19, 24, 40, 26, 24, 22, 22, 24, 49, 35, 37,
+    29, 40, 58, 51, 61, 60, 57, 51, 56, 55, 64, 72, 92, 78, 64, 68, 87, 69, 55, 56, 80, 109, 81,
+    87, 95, 98, 103, 104, 103, 62, 77, 113, 121, 112, 100, 120, 92, 101, 103, 99,
+];
+
+/// Target encodings of the survey and of octa-transcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Target {
+    WebP,
+    Avif,
+}
+
+impl Target {
+    pub const ALL: [Target; 2] = [Target::WebP, Target::Avif];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Target::WebP => "webp",
+            Target::Avif => "avif",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Target> {
+        Target::ALL.into_iter().find(|t| t.as_str() == name)
+    }
+
+    pub fn format(&self) -> ImageFormat {
+        match self {
+            Target::WebP => ImageFormat::WebP,
+            Target::Avif => ImageFormat::Avif,
+        }
+    }
+}
+
+/// Projected effect of re-encoding everything into one target format.
+#[derive(Debug, Clone, Default)]
+pub struct Projection {
+    /// Sampled assets that were encoded.
+    pub sampled: u64,
+    pub sample_bytes: u64,
+    /// The sample after re-encoding, keeping the original where it is smaller.
+    pub encoded_bytes: u64,
+    /// Bytes of the whole population the sample stands for.
+    pub population_bytes: u64,
+    /// Estimated bytes saved across the population.
+    pub projected_savings: u64,
+}
+
+impl Projection {
+    /// Share of the sample saved, in percent.
+    pub fn saved_percent(&self) -> f64 {
+        if self.sample_bytes == 0 {
+            return 0.0;
+        }
+        (self.sample_bytes - self.encoded_bytes) as f64 * 100.0 / self.sample_bytes as f64
+    }
+}
+
+/// Result of a compression survey.
+#[derive(Debug, Clone, Default)]
+pub struct Survey {
+    pub percent: f64,
+    /// Healthy assets seen, and their stored size.
+    pub assets: u64,
+    pub bytes: u64,
+    /// Sampled assets that could not be decoded or encoded.
+    pub failed: u64,
+    pub projections: BTreeMap<Target, Projection>,
+}
+
+/// Sizes of one sampled asset.
+struct Measurement {
+    source: ImageFormat,
+    bytes: u64,
+    encoded: BTreeMap<Target, u64>,
+}
+
+/// Sizes per source format: what the sample measured, and what it stands for.
+#[derive(Default)]
+struct Group {
+    population_bytes: u64,
+    sampled: BTreeMap<Target, (u64, u64, u64)>,
+}
+
+/// Measures how much a deterministic sample of the healthy assets would
+/// shrink as WebP and AVIF. Offered assets are encoded on worker threads
+/// while the scan goes on; [`Sampler::finish`] waits for them.
+pub struct Sampler {
+    percent: f64,
+    key: Option<Arc<Key>>,
+    tx: Option<SyncSender<Vec<u8>>>,
+    workers: Vec<JoinHandle<Vec<Option<Measurement>>>>,
+    assets: u64,
+    bytes: u64,
+    population: HashMap<ImageFormat, u64>,
+}
+
+impl Sampler {
+    /// `percent` of the assets (by ID, so reruns pick the same ones) are
+    /// encoded on `jobs` threads. `key` decrypts encrypted BLOBs first.
+    pub fn start(percent: f64, jobs: usize, key: Option<Arc<Key>>) -> Self {
+        let jobs = jobs.max(1);
+        let (tx, rx) = mpsc::sync_channel::<Vec<u8>>(jobs * 2);
+        let rx = Arc::new(Mutex::new(rx));
+        let workers = (0..jobs)
+            .map(|_| {
+                let rx = Arc::clone(&rx);
+                thread::spawn(move || work(&rx))
+            })
+            .collect();
+        Self {
+            percent,
+            key,
+            tx: Some(tx),
+            workers,
+            assets: 0,
+            bytes: 0,
+            population: HashMap::new(),
+        }
+    }
+
+    /// Counts a healthy asset and queues it for encoding if it is in the sample.
+    pub fn offer(&mut self, id: &str, blob: &[u8]) {
+        let Ok(plain) = encryption::plaintext(self.key.as_deref(), blob) else {
+            return;
+        };
+        let Ok(format) = image::guess_format(&plain) else {
+            return;
+        };
+        self.assets += 1;
+        self.bytes += plain.len() as u64;
+        *self.population.entry(format).or_default() += plain.len() as u64;
+
+        if sampled(id, self.percent) {
+            if let Some(tx) = &self.tx {
+                let _ = tx.send(plain.into_owned());
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Survey {
+        drop(self.tx.take());
+        let mut survey = Survey {
+            percent: self.percent,
+            assets: self.assets,
+            bytes: self.bytes,
+            ..Survey::default()
+        };
+
+        let mut groups: HashMap<ImageFormat, Group> = HashMap::new();
+        for (format, bytes) in &self.population {
+            groups.entry(*format).or_default().population_bytes = *bytes;
+        }
+        for worker in self.workers.drain(..) {
+            for measurement in worker.join().expect("compression worker panicked") {
+                let Some(m) = measurement else {
+                    survey.failed += 1;
+                    continue;
+                };
+                let group = groups.entry(m.source).or_default();
+                for (target, encoded) in m.encoded {
+                    let entry = group.sampled.entry(target).or_default();
+                    entry.0 += 1;
+                    entry.1 += m.bytes;
+                    entry.2 += encoded.min(m.bytes);
+                }
+            }
+        }
+
+        // Projected per source format: a PNG sample says nothing about JPEGs.
+        for group in groups.values() {
+            for (target, (count, sample, encoded)) in &group.sampled {
+                let p = survey.projections.entry(*target).or_default();
+                p.sampled += count;
+                p.sample_bytes += sample;
+                p.encoded_bytes += encoded;
+                p.population_bytes += group.population_bytes;
+                p.projected_savings += (group.population_bytes as f64 * (sample - encoded) as f64
+                    / *sample as f64) as u64;
+            }
+        }
+        survey
+    }
+}
+
+/// Stable membership by ID hash (FNV-1a), in steps of 0.0001%.
+fn sampled(id: &str, percent: f64) -> bool {
+    let hash = id.bytes().fold(0xcbf29ce484222325u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    ((hash % 1_000_000) as f64) < percent * 10_000.0
+}
+
+fn work(rx: &Mutex<Receiver<Vec<u8>>>) -> Vec<Option<Measurement>> {
+    let mut measurements = Vec::new();
+    loop {
+        let next = rx.lock().expect("compression worker panicked").recv();
+        let Ok(blob) = next else {
+            break;
+        };
+        measurements.push(measure(&blob));
+    }
+    measurements
+}
+
+fn measure(blob: &[u8]) -> Option<Measurement> {
+    let source = image::guess_format(blob).ok()?;
+    let img = image::load_from_memory(blob).ok()?;
+    // Lossless sources stay lossless, so the comparison is at equal quality.
+    let quality = match source {
+        ImageFormat::Jpeg => Some(jpeg_quality(blob).unwrap_or(DEFAULT_QUALITY)),
+        ImageFormat::WebP | ImageFormat::Avif => Some(DEFAULT_QUALITY),
+        _ => None,
+    };
+
+    let mut encoded = BTreeMap::new();
+    for target in Target::ALL {
+        if target.format() == source {
+            continue;
+        }
+        // There is no lossless AVIF encoder to compare with.
+        if target == Target::Avif && quality.is_none() {
+            continue;
+        }
+        encoded.insert(target, encode(&img, target, quality)?.len() as u64);
+    }
+    Some(Measurement {
+        source,
+        bytes: blob.len() as u64,
+        encoded,
+    })
+}
+
+/// `img` encoded as `target`, lossy at `quality` (1-100) or lossless when
+/// `None`. `None` when encoding fails, and for lossless AVIF, which has no
+/// encoder here.
+pub fn encode(img: &DynamicImage, target: Target, quality: Option<u8>) -> Option<Vec<u8>> {
+    match (target, quality) {
+        (Target::WebP, quality) => webp(img, quality),
+        (Target::Avif, Some(quality)) => avif(img, quality),
+        (Target::Avif, None) => None,
+    }
+}
+
+fn webp(img: &DynamicImage, quality: Option<u8>) -> Option<Vec<u8>> {
+    let img = if img.color().has_alpha() {
+        DynamicImage::ImageRgba8(img.to_rgba8())
+    } else {
+        DynamicImage::ImageRgb8(img.to_rgb8())
+    };
+    let encoder = webp::Encoder::from_image(&img).ok()?;
+    let data = match quality {
+        Some(quality) => encoder.encode(quality as f32),
+        None => encoder.encode_lossless(),
+    };
+    Some(data.to_vec())
+}
+
+fn avif(img: &DynamicImage, quality: u8) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    img.write_with_encoder(AvifEncoder::new_with_speed_quality(
+        &mut data, AVIF_SPEED, quality,
+    ))
+    .ok()?;
+    Some(data)
+}
+
+/// Estimates the quality a JPEG was saved with from its luminance
+/// quantization table, the way libjpeg scales it.
+pub(crate) fn jpeg_quality(blob: &[u8]) -> Option<u8> {
+    let mut i = 2;
+    while i + 4 <= blob.len() {
+        if blob[i] != 0xFF {
+            return None;
+        }
+        let marker = blob[i + 1];
+        let len = u16::from_be_bytes([blob[i + 2], blob[i + 3]]) as usize;
+        // DQT; the first table in it is luminance (id 0) in practice.
+        if marker == 0xDB {
+            let info = *blob.get(i + 4)?;
+            let wide = info >> 4 == 1;
+            let table: Vec<u16> = (0..64)
+                .map(|k| {
+                    if wide {
+                        let at = i + 5 + k * 2;
+                        blob.get(at..at + 2)
+                            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                    } else {
+                        blob.get(i + 5 + k).map(|b| *b as u16)
+                    }
+                })
+                .collect::<Option<_>>()?;
+            let scale: f64 = table
+                .iter()
+                .zip(STD_LUMINANCE)
+                .map(|(q, std)| *q as f64 * 100.0 / std as f64)
+                .sum::<f64>()
+                / 64.0;
+            let quality = if scale <= 100.0 {
+                (200.0 - scale) / 2.0
+            } else {
+                5000.0 / scale
+            };
+            return Some(quality.round().clamp(1.0, 100.0) as u8);
+        }
+        // Start of scan: no table before the image data.
+        if marker == 0xDA {
+            return None;
+        }
+        i += 2 + len;
+    }
+    None
+}
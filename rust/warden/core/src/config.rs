@@ -0,0 +1,336 @@
+use crate::audit::FindingKind;
+use crate::derived::DerivedConfig;
+use crate::encryption::EncryptionConfig;
+use crate::health::HealthConfig;
+use crate::notify::NotifyConfig;
+use crate::retention::RetentionRule;
+use crate::schedule::{parse_cron, parse_interval, parse_jitter};
+use crate::storage::StorageConfig;
+use crate::watch::WatchConfig;
+use octa_cdn::CdnConfig;
+use octa_config::{ConfigError, Validate};
+use octa_ledger::LedgerConfig;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use tracing::{error, info};
+
+pub use octa_config::{DatabaseConfig, TelemetryConfig};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    /// Optional when `warden.storage` points at another backend.
+    #[serde(default)]
+    pub database: DatabaseConfig,
+    /// Warden-only settings; the Go server ignores this section.
+    #[serde(default)]
+    pub warden: WardenConfig,
+    /// Edge cache purged for the keys of repaired and deleted assets.
+    #[serde(default)]
+    pub cdn: CdnConfig,
+    /// Where fixes, repairs and deletions are recorded, as octa-ctl records its changes.
+    #[serde(default)]
+    pub ledger: LedgerConfig,
+    /// Collector the scan spans are exported to, as octa-pulse exports its requests.
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WardenConfig {
+    pub notify: NotifyConfig,
+    pub health: HealthConfig,
+    pub storage: StorageConfig,
+    /// Key patterns and how long their assets live (used by `--enforce-retention`).
+    pub retention: Vec<RetentionRule>,
+    /// HMAC key for `verify-deleted` attestations (falls back to WARDEN_ATTESTATION_KEY).
+    pub attestation_key: Option<String>,
+    /// Pre-generated sizes to verify for every original (schemas that store them).
+    pub derived: Option<DerivedConfig>,
+    /// More BLOB columns of `images` to validate next to `data` (e.g. `thumb`).
+    pub extra_blob_columns: Vec<String>,
+    /// SQLite file where every run is recorded (enables `octa-warden history`).
+    pub history_path: Option<String>,
+    /// Key for BLOBs the application encrypts before storing them.
+    pub encryption: Option<EncryptionConfig>,
+    /// When `watch` audits; its flags override these.
+    pub watch: WatchConfig,
+}
+
+/// Printed under a config error, so the fix is one copy-paste away.
+const EXAMPLE: &str = r#"database:
+  path: "./data/octa.db"
+warden:
+  history_path: "./data/warden-history.db"
+  health:
+    critical:
+      corrupted_percent: 1.0
+  retention:
+    - pattern: "tmp/*"
+      max_age: "30d""#;
+
+/// The config file a run reads: `--config`, or what
+/// [`octa_config::discover`] finds. `None` when there is no such file.
+pub fn path(explicit: Option<&str>) -> Option<PathBuf> {
+    octa_config::discover(explicit.map(Path::new)).filter(|p| p.exists())
+}
+
+/// Finds, reads and parses the shared config.yaml (see [`path`]), with
+/// `OCTA_*` environment overrides applied and `database.path` replaced by
+/// `db_path` (`--db` / `OCTA_DB_PATH`) when given.
+/// With a `db_path`, a missing config file is not an error: the defaults are used.
+/// Problems are reported to the console; `None` means the run cannot continue.
+pub fn load(path: Option<&str>, db_path: Option<&str>) -> Option<Config> {
+    let found = self::path(path);
+    let loaded = match (&found, db_path) {
+        (Some(found), _) => {
+            info!(tag = "→", path = %found.display(), "Loading configuration");
+            read(found, db_path)
+        }
+        (None, Some(db_path)) => {
+            info!(tag = "→", db = db_path, "No config file, using defaults");
+            octa_config::from_env::<Config>().and_then(|mut config| {
+                config.database.path = db_path.to_string();
+                octa_config::check(Path::new("<defaults>"), config)
+            })
+        }
+        (None, None) => Err(match path {
+            Some(path) => ConfigError::Read {
+                path: path.into(),
+                source: std::io::ErrorKind::NotFound.into(),
+            },
+            None => ConfigError::NotFound,
+        }),
+    };
+
+    match loaded {
+        Ok(config) => Some(config),
+        Err(e) => {
+            report(&e);
+            None
+        }
+    }
+}
+
+/// Reads the file at `path` as [`load`] does, without logging: for
+/// reloading it in watch mode (see [`octa_config::watch`]).
+pub fn read(path: &Path, db_path: Option<&str>) -> Result<Config, ConfigError> {
+    let mut config = octa_config::read::<Config>(path)?;
+    if let Some(db_path) = db_path {
+        config.database.path = db_path.to_string();
+    }
+    octa_config::check(path, config)
+}
+
+/// Reloads the file at `path` with [`read`] whenever it changes, for watch
+/// mode. `db_path` is the `--db` the run started with.
+pub fn watch(path: &Path, db_path: Option<String>) -> octa_config::Watch<Config> {
+    octa_config::watch(path, move |path| read(path, db_path.as_deref()))
+}
+
+fn report(e: &ConfigError) {
+    match e {
+        ConfigError::NotFound => error!(tag = "FATAL", reason = %e, "No config file"),
+        ConfigError::Read { path, source } => {
+            error!(tag = "FATAL", path = %path.display(), reason = %source, "Could not read config file")
+        }
+        ConfigError::Parse(p) => {
+            error!(
+                tag = "FATAL",
+                path = %p.path.display(),
+                field = p.field,
+                line = p.line,
+                column = p.column,
+                env = p.env,
+                reason = %p.reason,
+                "Invalid config"
+            );
+            print_example();
+        }
+        ConfigError::Invalid { path, problems } => {
+            for (field, reason) in problems {
+                error!(tag = "FATAL", path = %path.display(), field = %field, reason = %reason, "Invalid config value");
+            }
+            print_example();
+        }
+    }
+}
+
+fn print_example() {
+    if octa_logging::is_human() {
+        println!("\nExample of a valid config:\n\n{}\n", EXAMPLE);
+    }
+}
+
+impl Validate for Config {
+    /// Checks values serde accepts but the run cannot work with. Whether the
+    /// database or storage root exists is checked where it is used.
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        let mut problem = |field: &str, reason: String| problems.push((field.to_string(), reason));
+        let warden = &self.warden;
+
+        match &warden.storage {
+            StorageConfig::Sqlite if self.database.path.trim().is_empty() => problem(
+                "database.path",
+                "is required for the sqlite backend (or pass --db)".to_string(),
+            ),
+            StorageConfig::Fs { root } if root.as_os_str().is_empty() => {
+                problem("warden.storage.root", "must not be empty".to_string())
+            }
+            StorageConfig::S3(s3) => {
+                if !s3.endpoint.starts_with("http://") && !s3.endpoint.starts_with("https://") {
+                    problem(
+                        "warden.storage.endpoint",
+                        format!("'{}' is not an http(s) URL", s3.endpoint),
+                    );
+                }
+                if s3.bucket.trim().is_empty() {
+                    problem("warden.storage.bucket", "must not be empty".to_string());
+                }
+                if s3.concurrency == 0 {
+                    problem(
+                        "warden.storage.concurrency",
+                        "must be at least 1".to_string(),
+                    );
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(history) = &warden.history_path {
+            let dir = Path::new(history)
+                .parent()
+                .filter(|p| !p.as_os_str().is_empty());
+            if let Some(dir) = dir.filter(|d| !d.is_dir()) {
+                problem(
+                    "warden.history_path",
+                    format!("directory '{}' does not exist", dir.display()),
+                );
+            }
+        }
+
+        for (name, url) in [
+            ("warden.notify.webhook_url", &warden.notify.webhook_url),
+            (
+                "warden.notify.slack_webhook_url",
+                &warden.notify.slack_webhook_url,
+            ),
+        ] {
+            if let Some(url) = url {
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    problem(name, format!("'{}' is not an http(s) URL", url));
+                }
+            }
+        }
+        if warden.notify.max_ids == 0 {
+            problem("warden.notify.max_ids", "must be at least 1".to_string());
+        }
+
+        for kind in warden.health.severity.keys() {
+            if !FindingKind::ALL.iter().any(|k| k.as_str() == kind) {
+                let known: Vec<&str> = FindingKind::ALL.iter().map(|k| k.as_str()).collect();
+                problem(
+                    "warden.health.severity",
+                    format!(
+                        "unknown finding kind '{}' (expected one of {})",
+                        kind,
+                        known.join(", ")
+                    ),
+                );
+            }
+        }
+        for (name, rule) in [
+            ("warden.health.warning", &warden.health.warning),
+            ("warden.health.critical", &warden.health.critical),
+        ] {
+            if let Some(pct) = rule.corrupted_percent {
+                if !(0.0..=100.0).contains(&pct) {
+                    problem(
+                        &format!("{}.corrupted_percent", name),
+                        format!("{} is not a percentage between 0 and 100", pct),
+                    );
+                }
+            }
+        }
+
+        for (i, rule) in warden.retention.iter().enumerate() {
+            let field = format!("warden.retention[{}]", i);
+            if rule.pattern.trim().is_empty() {
+                problem(
+                    &format!("{}.pattern", field),
+                    "must not be empty".to_string(),
+                );
+            }
+            match parse_interval(&rule.max_age) {
+                Ok(age) if age.is_zero() => problem(
+                    &format!("{}.max_age", field),
+                    "must be positive".to_string(),
+                ),
+                Ok(_) => {}
+                Err(e) => problem(&format!("{}.max_age", field), e),
+            }
+        }
+
+        if let Err(e) = parse_interval(&warden.watch.interval) {
+            problem("warden.watch.interval", e);
+        }
+        if let Some(Err(e)) = warden.watch.schedule.as_deref().map(parse_cron) {
+            problem("warden.watch.schedule", e);
+        }
+        if let Err(e) = parse_jitter(&warden.watch.jitter) {
+            problem("warden.watch.jitter", e);
+        }
+
+        if let Some(encryption) = &warden.encryption {
+            match &encryption.key_command {
+                Some(argv) if argv.first().is_none_or(|p| p.trim().is_empty()) => problem(
+                    "warden.encryption.key_command",
+                    "must name a program (e.g. [\"aws\", \"kms\", \"decrypt\", ...])".to_string(),
+                ),
+                None if encryption.key_env.trim().is_empty() => {
+                    problem("warden.encryption.key_env", "must not be empty".to_string())
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(derived) = &warden.derived {
+            if derived.sizes.is_empty() {
+                problem(
+                    "warden.derived.sizes",
+                    "lists no sizes (e.g. sm: 64x64)".to_string(),
+                );
+            }
+            for (name, value) in [
+                ("table", &derived.table),
+                ("original_column", &derived.original_column),
+                ("size_column", &derived.size_column),
+                ("data_column", &derived.data_column),
+            ] {
+                if value.trim().is_empty() {
+                    problem(
+                        &format!("warden.derived.{}", name),
+                        "must not be empty".to_string(),
+                    );
+                }
+            }
+        }
+
+        for (i, column) in warden.extra_blob_columns.iter().enumerate() {
+            let field = format!("warden.extra_blob_columns[{}]", i);
+            if column.trim().is_empty() {
+                problem(&field, "must not be empty".to_string());
+            } else if column == "data" {
+                problem(&field, "'data' is always validated".to_string());
+            } else if warden.extra_blob_columns[..i].contains(column) {
+                problem(&field, format!("'{}' is listed twice", column));
+            }
+        }
+
+        problems.extend(self.cdn.validate());
+        problems.extend(self.ledger.validate());
+        problems.extend(self.telemetry.validate());
+        problems
+    }
+}
@@ -0,0 +1,196 @@
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::{Connection, OpenFlags, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How Warden attaches to the live database.
+#[derive(Debug, Clone)]
+pub struct OpenOptions {
+    /// How long SQLite waits on a locked file before returning SQLITE_BUSY.
+    pub busy_timeout: Duration,
+    /// Opens with `immutable=1`: no locks are taken and the WAL is ignored.
+    /// Only safe when nothing is writing to the file (e.g. a copied backup).
+    pub immutable: bool,
+}
+
+/// Opens the database in READ_ONLY mode with the configured busy timeout.
+pub fn open_read_only(path: &str, opts: &OpenOptions) -> Result<Connection> {
+    let mut flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+
+    let conn = if opts.immutable {
+        flags |= OpenFlags::SQLITE_OPEN_URI;
+        Connection::open_with_flags(format!("file:{}?immutable=1", uri_path(path)), flags)?
+    } else {
+        Connection::open_with_flags(path, flags)?
+    };
+
+    conn.busy_timeout(opts.busy_timeout)?;
+    Ok(conn)
+}
+
+/// Escapes the characters that end or escape a URI path, so `?`, `#` and
+/// `%` in a file name open that file rather than something else.
+fn uri_path(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            '%' => out.push_str("%25"),
+            '?' => out.push_str("%3f"),
+            '#' => out.push_str("%23"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Opens the database for writing. Only used by commands that change the
+/// database on explicit request (`triage --apply`, `import`).
+pub fn open_read_write(path: &str, opts: &OpenOptions) -> Result<Connection> {
+    let conn = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    conn.busy_timeout(opts.busy_timeout)?;
+    Ok(conn)
+}
+
+/// Identifies the file behind a path. Moving another file into place (e.g. a
+/// restore) changes it, even when the name and size stay the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileId {
+    pub dev: u64,
+    pub ino: u64,
+}
+
+/// `None` when the file does not exist, or the platform has no inode numbers.
+pub fn file_id(path: &Path) -> Option<FileId> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let meta = fs::metadata(path).ok()?;
+        Some(FileId {
+            dev: meta.dev(),
+            ino: meta.ino(),
+        })
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// The database file a scan reads, as it was when the scan opened it.
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub path: PathBuf,
+    pub id: FileId,
+}
+
+impl Source {
+    pub fn new(path: &str) -> Option<Self> {
+        let path = PathBuf::from(path);
+        let id = file_id(&path)?;
+        Some(Self { path, id })
+    }
+
+    /// True once the path names another file, or none. The open connection
+    /// keeps reading the old one, which no longer is the database.
+    pub fn replaced(&self) -> bool {
+        file_id(&self.path) != Some(self.id)
+    }
+}
+
+/// An open audit connection, optionally backed by a temporary snapshot.
+/// Fields drop in order, so the connection closes before the snapshot file is removed.
+pub struct Target {
+    pub conn: Connection,
+    _snapshot: Option<Snapshot>,
+}
+
+/// Opens the database for auditing, taking a snapshot first when requested.
+pub fn attach(path: &str, opts: &OpenOptions, snapshot: bool) -> Result<Target> {
+    if !snapshot {
+        return Ok(Target {
+            conn: open_read_only(path, opts)?,
+            _snapshot: None,
+        });
+    }
+
+    let snap = take_snapshot(path, opts)?;
+    let conn = open_read_only(&snap.path().to_string_lossy(), opts)?;
+    Ok(Target {
+        conn,
+        _snapshot: Some(snap),
+    })
+}
+
+/// A point-in-time copy of the database in the OS temp directory.
+/// The file is removed when the snapshot is dropped.
+pub struct Snapshot {
+    path: PathBuf,
+}
+
+impl Snapshot {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+        for suffix in ["-wal", "-shm"] {
+            let mut side = self.path.clone().into_os_string();
+            side.push(suffix);
+            let _ = fs::remove_file(side);
+        }
+    }
+}
+
+/// Copies the live database into a temp file using SQLite's Online Backup API.
+///
+/// All pages are copied in a single step, so the copy is taken inside one read
+/// transaction and reflects exactly one committed state, even while the Go
+/// server keeps writing to the WAL.
+pub fn take_snapshot(path: &str, opts: &OpenOptions) -> Result<Snapshot> {
+    let source = open_read_only(path, opts)?;
+
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let snapshot = Snapshot {
+        path: std::env::temp_dir().join(format!("octa_warden_snapshot_{}.db", stamp)),
+    };
+
+    copy(&source, snapshot.path())?;
+    Ok(snapshot)
+}
+
+/// Writes a consistent copy of the database to `dest` (e.g. before a schema migration).
+pub fn backup(path: &str, opts: &OpenOptions, dest: &Path) -> Result<()> {
+    copy(&open_read_only(path, opts)?, dest)
+}
+
+fn copy(source: &Connection, dest: &Path) -> Result<()> {
+    let mut target = Connection::open(dest)?;
+    {
+        let backup = Backup::new(source, &mut target)?;
+
+        // A negative page count copies the whole database in one step.
+        loop {
+            match backup.step(-1)? {
+                StepResult::Done => break,
+                _ => thread::sleep(Duration::from_millis(100)),
+            }
+        }
+    }
+
+    // The copy inherits WAL mode from the source; a read-only connection
+    // would then leave -wal/-shm files behind, so fold it back to a single file.
+    target.pragma_update(None, "journal_mode", "DELETE")
+}
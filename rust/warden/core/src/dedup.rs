@@ -0,0 +1,181 @@
+use crate::audit::has_column;
+use crate::derived::DerivedConfig;
+use crate::encryption::{self, Key};
+use crate::export::sha256_hex;
+use octa_logging::FINDING_TARGET;
+use octa_store::cas;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OptionalExtension, Result, TransactionBehavior};
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+/// Assets whose images are byte-for-byte identical.
+#[derive(Debug, Clone)]
+pub struct Group {
+    /// SHA-256 of the image (decrypted, when encryption is configured).
+    pub hash: String,
+    /// The asset that is kept: the oldest one.
+    pub canonical: String,
+    pub duplicates: Vec<String>,
+    /// Stored size of one copy.
+    pub bytes: u64,
+}
+
+impl Group {
+    /// Bytes freed by removing the duplicates.
+    pub fn savings(&self) -> u64 {
+        self.bytes * self.duplicates.len() as u64
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MergeSummary {
+    pub groups: u64,
+    pub removed: u64,
+    pub bytes: u64,
+    /// Groups left alone because a row changed since it was read.
+    pub skipped: u64,
+}
+
+/// Finds assets with identical images. Only BLOBs whose length occurs more
+/// than once are read and hashed.
+pub fn find(conn: &Connection, key: Option<&Key>) -> Result<Vec<Group>> {
+    if !table_exists(conn, "key_mappings")? {
+        warn!(
+            tag = "WARN",
+            "key_mappings table not found, duplicates cannot be merged"
+        );
+        return Ok(Vec::new());
+    }
+    let order = if has_column(conn, "images", "created_at")? {
+        "created_at, rowid"
+    } else {
+        "rowid"
+    };
+    let data = cas::data(conn)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, {data} FROM images
+         WHERE typeof({data}) = 'blob' AND length({data}) IN (
+             SELECT length({data}) FROM images WHERE typeof({data}) = 'blob'
+             GROUP BY length({data}) HAVING COUNT(*) > 1)
+         ORDER BY {order}",
+    ))?;
+    let rows = stmt.query_map([], |row| {
+        let id: String = row.get(0)?;
+        let data = match row.get_ref(1)? {
+            ValueRef::Blob(data) => data,
+            _ => &[],
+        };
+        Ok((id, hash(key, data), data.len() as u64))
+    })?;
+
+    // Oldest first, so the first asset of each hash becomes the canonical one.
+    let mut groups: Vec<Group> = Vec::new();
+    let mut by_hash: HashMap<String, usize> = HashMap::new();
+    for row in rows {
+        let (id, Some(hash), bytes) = row? else {
+            continue;
+        };
+        match by_hash.get(&hash) {
+            Some(&i) => groups[i].duplicates.push(id),
+            None => {
+                by_hash.insert(hash.clone(), groups.len());
+                groups.push(Group {
+                    hash,
+                    canonical: id,
+                    duplicates: Vec::new(),
+                    bytes,
+                });
+            }
+        }
+    }
+    groups.retain(|g| !g.duplicates.is_empty());
+    Ok(groups)
+}
+
+/// `None` for BLOBs that do not decrypt: they are not compared.
+fn hash(key: Option<&Key>, data: &[u8]) -> Option<String> {
+    encryption::plaintext(key, data)
+        .ok()
+        .map(|plain| sha256_hex(&plain))
+}
+
+/// Points the keys of every duplicate at its canonical asset and removes the
+/// duplicate rows (and their `warden.derived` sizes). Each group is one
+/// transaction that first checks every row still holds the hashed image.
+pub fn merge(
+    conn: &mut Connection,
+    groups: &[Group],
+    key: Option<&Key>,
+    derived: Option<&DerivedConfig>,
+) -> Result<MergeSummary> {
+    let derived = match derived {
+        Some(cfg) if table_exists(conn, &cfg.table)? => Some(cfg),
+        _ => None,
+    };
+    let mut summary = MergeSummary::default();
+    for group in groups {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let select = format!("SELECT {} FROM images WHERE id = ?1", cas::data(&tx)?);
+
+        let mut unchanged = true;
+        for id in std::iter::once(&group.canonical).chain(&group.duplicates) {
+            let current = tx
+                .query_row(&select, [id], |row| {
+                    Ok(match row.get_ref(0)? {
+                        ValueRef::Blob(data) => hash(key, data),
+                        _ => None,
+                    })
+                })
+                .optional()?
+                .flatten();
+            if current.as_deref() != Some(group.hash.as_str()) {
+                unchanged = false;
+                break;
+            }
+        }
+        if !unchanged {
+            warn!(tag = "SKIP", canonical = %group.canonical, "An asset of the group changed since it was read, left untouched");
+            summary.skipped += 1;
+            continue;
+        }
+
+        for id in &group.duplicates {
+            tx.execute(
+                "UPDATE key_mappings SET image_id = ?1 WHERE image_id = ?2",
+                [&group.canonical, id],
+            )?;
+            // Releases the duplicate's blob too, unless the canonical shares it.
+            octa_store::delete(&tx, id)?;
+            if let Some(cfg) = derived {
+                tx.execute(
+                    &format!(
+                        "DELETE FROM {} WHERE {} = ?1",
+                        quote(&cfg.table),
+                        quote(&cfg.original_column)
+                    ),
+                    [id],
+                )?;
+            }
+        }
+        tx.commit()?;
+
+        info!(target: FINDING_TARGET, tag = "APPLY", canonical = %group.canonical, merged = %group.duplicates.join(","), bytes = group.savings(), "Duplicates merged");
+        summary.groups += 1;
+        summary.removed += group.duplicates.len() as u64;
+        summary.bytes += group.savings();
+    }
+    Ok(summary)
+}
+
+fn table_exists(conn: &Connection, table: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+        [table],
+        |row| row.get(0),
+    )
+}
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
@@ -0,0 +1,228 @@
+use crate::audit::{AuditResult, Finding, FindingKind};
+use crate::encryption::{self, Key};
+use crate::stream::FindingStream;
+use image::{load_from_memory, GenericImageView};
+use octa_logging::FINDING_TARGET;
+use octa_store::cas;
+use rusqlite::{Connection, OptionalExtension, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tracing::{error, info, warn};
+
+/// `warden.derived`: a table of pre-generated sizes for every original.
+/// Octa itself does not store derivatives; this is for schemas that do.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DerivedConfig {
+    pub table: String,
+    /// Column holding the original's `images.id`.
+    pub original_column: String,
+    /// Column naming the variant (e.g. `sm`).
+    pub size_column: String,
+    pub data_column: String,
+    /// Variant name -> expected `WIDTHxHEIGHT`.
+    pub sizes: BTreeMap<String, Dimensions>,
+}
+
+impl Default for DerivedConfig {
+    fn default() -> Self {
+        Self {
+            table: "thumbnails".to_string(),
+            original_column: "image_id".to_string(),
+            size_column: "size".to_string(),
+            data_column: "data".to_string(),
+            sizes: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Dimensions {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TryFrom<String> for Dimensions {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, String> {
+        let parsed = value
+            .split_once('x')
+            .and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)));
+        match parsed {
+            Some((width, height)) if width > 0 && height > 0 => Ok(Self { width, height }),
+            _ => Err(format!("invalid size '{}' (expected WIDTHxHEIGHT)", value)),
+        }
+    }
+}
+
+impl std::fmt::Display for Dimensions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// A derivative that needs regenerating.
+pub struct Defect {
+    pub original: String,
+    pub size: String,
+}
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Checks every original in `images` for its configured derivatives and adds
+/// `missing_derivative` / `invalid_derivative` findings to `result`. Originals
+/// that failed the audit are skipped: they cannot be regenerated from.
+/// Derivatives are decrypted with `key` first, like the originals, and
+/// findings go to `stream` as they are found.
+/// Returns the defects, so `--fix regenerate` can repair them.
+pub fn check(
+    conn: &Connection,
+    cfg: &DerivedConfig,
+    key: Option<&Key>,
+    stream: Option<&FindingStream>,
+    result: &mut AuditResult,
+) -> Result<Vec<Defect>> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+        [&cfg.table],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        warn!(tag = "WARN", table = %cfg.table, "Derived table not found, skipping derivative check");
+        return Ok(Vec::new());
+    }
+
+    let mut variants = conn.prepare(&format!(
+        "SELECT {}, {} FROM {} WHERE {} = ?1",
+        quote(&cfg.size_column),
+        quote(&cfg.data_column),
+        quote(&cfg.table),
+        quote(&cfg.original_column)
+    ))?;
+    let mut ids = conn.prepare("SELECT id FROM images ORDER BY id")?;
+    let ids = ids
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>>>()?;
+
+    let broken: HashSet<String> = result
+        .findings
+        .iter()
+        .filter(|f| f.kind != FindingKind::ProcessingMismatch)
+        .filter_map(|f| f.id.clone())
+        .collect();
+
+    let mut defects = Vec::new();
+    for id in ids.into_iter().filter(|id| !broken.contains(id)) {
+        let mut present: HashMap<String, Option<Vec<u8>>> = HashMap::new();
+        let rows = variants.query_map([&id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1).ok()))
+        })?;
+        for row in rows {
+            let (size, data) = row?;
+            present.insert(size, data);
+        }
+
+        for (size, expected) in &cfg.sizes {
+            let problem = match present.get(size) {
+                None => Some((FindingKind::MissingDerivative, "missing".to_string())),
+                Some(None) => Some((
+                    FindingKind::InvalidDerivative,
+                    "data is not a BLOB".to_string(),
+                )),
+                Some(Some(data)) => match encryption::plaintext(key, data)
+                    .map_err(|reason| format!("does not decrypt: {}", reason))
+                    .and_then(|plain| load_from_memory(&plain).map_err(|e| e.to_string()))
+                {
+                    Err(reason) => Some((FindingKind::InvalidDerivative, reason)),
+                    Ok(img) if img.dimensions() != (expected.width, expected.height) => {
+                        let (w, h) = img.dimensions();
+                        Some((
+                            FindingKind::InvalidDerivative,
+                            format!("is {}x{}, expected {}", w, h, expected),
+                        ))
+                    }
+                    Ok(_) => None,
+                },
+            };
+
+            if let Some((kind, reason)) = problem {
+                let reason = format!("size '{}': {}", size, reason);
+                warn!(target: FINDING_TARGET, tag = "DERIVED", id = %id, reason = %reason, "Derivative problem");
+                result.stats.derived_issues += 1;
+                let finding = Finding::new(Some(id.clone()), kind, reason);
+                if let Some(stream) = stream {
+                    stream.emit(&finding);
+                }
+                result.findings.push(finding);
+                defects.push(Defect {
+                    original: id.clone(),
+                    size: size.clone(),
+                });
+            }
+        }
+    }
+    Ok(defects)
+}
+
+/// Rebuilds each defect from its original (center crop to the expected size,
+/// JPEG like the server's processed uploads) and replaces the stored variant.
+/// Returns how many derivatives were written.
+pub fn regenerate(conn: &mut Connection, cfg: &DerivedConfig, defects: &[Defect]) -> Result<u64> {
+    let mut written = 0;
+    let tx = conn.transaction()?;
+    let select = format!("SELECT {} FROM images WHERE id = ?1", cas::data(&tx)?);
+    for defect in defects {
+        let Some(expected) = cfg.sizes.get(&defect.size) else {
+            continue;
+        };
+        let original: Option<Vec<u8>> = tx
+            .query_row(
+                &select,
+                [&defect.original],
+                |row| Ok(row.get(0).ok()),
+            )
+            .optional()?
+            .flatten();
+        let Some(img) = original.and_then(|data| load_from_memory(&data).ok()) else {
+            warn!(tag = "SKIP", id = %defect.original, size = %defect.size, "Original is unreadable, cannot regenerate");
+            continue;
+        };
+
+        let resized = octa_image::cover(&img, expected.width, expected.height);
+        let data = match octa_image::encode_jpeg(&resized, octa_image::QUALITY) {
+            Ok(data) => data,
+            Err(e) => {
+                error!(tag = "ERROR", id = %defect.original, size = %defect.size, reason = %e, "Could not encode derivative");
+                continue;
+            }
+        };
+
+        tx.execute(
+            &format!(
+                "DELETE FROM {} WHERE {} = ?1 AND {} = ?2",
+                quote(&cfg.table),
+                quote(&cfg.original_column),
+                quote(&cfg.size_column)
+            ),
+            [&defect.original, &defect.size],
+        )?;
+        tx.execute(
+            &format!(
+                "INSERT INTO {} ({}, {}, {}) VALUES (?1, ?2, ?3)",
+                quote(&cfg.table),
+                quote(&cfg.original_column),
+                quote(&cfg.size_column),
+                quote(&cfg.data_column)
+            ),
+            rusqlite::params![defect.original, defect.size, data],
+        )?;
+        info!(target: FINDING_TARGET, tag = "APPLY", id = %defect.original, size = %defect.size, "Derivative regenerated");
+        written += 1;
+    }
+    tx.commit()?;
+    Ok(written)
+}
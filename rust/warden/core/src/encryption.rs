@@ -0,0 +1,272 @@
+use crate::audit::has_column;
+use aes_gcm::aead::{Aead, Generate, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, TransactionBehavior};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::fmt;
+use std::process::Command;
+use tracing::{info, warn};
+
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// Rows rewritten per transaction by [`convert`].
+const BATCH_SIZE: usize = 500;
+
+/// BLOBs encrypted by the application before they are stored (`warden.encryption`).
+/// Each one is AES-256-GCM: a 12-byte nonce, the ciphertext, then the 16-byte
+/// tag, which is what Go's `gcm.Seal(nonce, nonce, plaintext, nil)` produces.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptionConfig {
+    /// Environment variable holding the key, base64 or hex.
+    #[serde(default = "default_key_env")]
+    pub key_env: String,
+    /// Command that prints the key instead, e.g. a KMS CLI unwrapping a data
+    /// key. Run once at startup, without a shell.
+    pub key_command: Option<Vec<String>>,
+}
+
+fn default_key_env() -> String {
+    "WARDEN_BLOB_KEY".to_string()
+}
+
+/// A loaded AES-256 key.
+pub struct Key(Aes256Gcm);
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+impl Key {
+    /// Fetches the key from `key_command` when set, otherwise from `key_env`.
+    pub fn load(cfg: &EncryptionConfig) -> Result<Self, String> {
+        let text = match &cfg.key_command {
+            Some(argv) => {
+                let (program, args) = argv
+                    .split_first()
+                    .ok_or("warden.encryption.key_command is empty")?;
+                let output = Command::new(program)
+                    .args(args)
+                    .output()
+                    .map_err(|e| format!("could not run {}: {}", program, e))?;
+                if !output.status.success() {
+                    return Err(format!(
+                        "{} exited with {}: {}",
+                        program,
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ));
+                }
+                String::from_utf8(output.stdout)
+                    .map_err(|_| format!("{} printed a key that is not text", program))?
+            }
+            None => {
+                std::env::var(&cfg.key_env).map_err(|_| format!("{} is not set", cfg.key_env))?
+            }
+        };
+        Self::parse(text.trim())
+    }
+
+    /// Accepts 32 bytes as 64 hex digits or as base64.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let bytes = if text.len() == 64 && text.bytes().all(|b| b.is_ascii_hexdigit()) {
+            (0..64)
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&text[i..i + 2], 16))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| e.to_string())?
+        } else {
+            STANDARD
+                .decode(text)
+                .map_err(|_| "key is neither hex nor base64".to_string())?
+        };
+        Aes256Gcm::new_from_slice(&bytes)
+            .map(Key)
+            .map_err(|_| format!("key is {} bytes, AES-256 needs 32", bytes.len()))
+    }
+
+    /// Encrypts with a fresh random nonce, in the layout [`Key::open`] reads.
+    pub fn seal(&self, plain: &[u8]) -> Result<Vec<u8>, String> {
+        let nonce = Nonce::generate();
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, plain)
+            .map_err(|_| "encryption failed".to_string())?;
+        let mut blob = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        blob.extend_from_slice(&nonce);
+        blob.extend_from_slice(&ciphertext);
+        Ok(blob)
+    }
+
+    /// Decrypts one stored BLOB.
+    pub fn open(&self, blob: &[u8]) -> Result<Vec<u8>, String> {
+        if blob.len() < NONCE_LEN + TAG_LEN {
+            return Err(format!(
+                "{} bytes is shorter than nonce and tag ({} bytes)",
+                blob.len(),
+                NONCE_LEN + TAG_LEN
+            ));
+        }
+        let (nonce, ciphertext) = blob.split_at(NONCE_LEN);
+        let nonce = Nonce::try_from(nonce).map_err(|_| "invalid nonce".to_string())?;
+        self.0.decrypt(&nonce, ciphertext).map_err(|_| {
+            if image::load_from_memory(blob).is_ok() {
+                "stored unencrypted (the bytes are a plain image)".to_string()
+            } else {
+                "authentication failed (wrong key, or the ciphertext is damaged)".to_string()
+            }
+        })
+    }
+}
+
+/// The bytes to validate: decrypted when a key is configured, as stored otherwise.
+pub fn plaintext<'a>(key: Option<&Key>, blob: &'a [u8]) -> Result<Cow<'a, [u8]>, String> {
+    match key {
+        Some(key) => key.open(blob).map(Cow::Owned),
+        None => Ok(Cow::Borrowed(blob)),
+    }
+}
+
+/// Which way [`convert`] rewrites BLOBs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// `--encrypt-at-rest`: plain images become ciphertext.
+    Encrypt,
+    /// `--decrypt`: ciphertext becomes plain images again.
+    Decrypt,
+}
+
+/// A BLOB column to rewrite, addressed by rowid.
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub table: String,
+    pub column: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ConversionSummary {
+    /// Rewritten (or, in a dry run, to be rewritten).
+    pub converted: u64,
+    /// Already in the target form, e.g. done by an earlier, interrupted run.
+    pub done: u64,
+    /// Neither plain nor decryptable; left untouched.
+    pub skipped: u64,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+enum Outcome {
+    Convert(Vec<u8>),
+    Done,
+    Skip(String),
+}
+
+/// Rewrites every BLOB of the targets in `direction`, committing every
+/// [`BATCH_SIZE`] rows. Rows already in the target form are left alone, so an
+/// interrupted run simply continues when started again. Without `execute`
+/// nothing is written and the summary says what would change.
+pub fn convert(
+    conn: &mut Connection,
+    key: &Key,
+    direction: Direction,
+    targets: &[Target],
+    execute: bool,
+) -> rusqlite::Result<ConversionSummary> {
+    let mut summary = ConversionSummary::default();
+    for target in targets {
+        if !has_column(conn, &target.table, &target.column)? {
+            warn!(tag = "WARN", table = %target.table, column = %target.column, "Column not found, skipping");
+            continue;
+        }
+        let table = quote(&target.table);
+        let column = quote(&target.column);
+        let select = format!(
+            "SELECT rowid, {} FROM {} WHERE rowid > ?1 ORDER BY rowid LIMIT {}",
+            column, table, BATCH_SIZE
+        );
+        let update = format!("UPDATE {} SET {} = ?1 WHERE rowid = ?2", table, column);
+
+        let mut last = i64::MIN;
+        loop {
+            // Immediate: take the write lock up front instead of failing to upgrade a read.
+            let behavior = if execute {
+                TransactionBehavior::Immediate
+            } else {
+                TransactionBehavior::Deferred
+            };
+            let tx = conn.transaction_with_behavior(behavior)?;
+            let rows = {
+                let mut stmt = tx.prepare(&select)?;
+                let rows = stmt.query_map([last], |row| {
+                    let blob = match row.get_ref(1)? {
+                        ValueRef::Blob(blob) => Some(blob.to_vec()),
+                        _ => None,
+                    };
+                    Ok((row.get::<_, i64>(0)?, blob))
+                })?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()?
+            };
+            let Some((end, _)) = rows.last() else {
+                break;
+            };
+            last = *end;
+
+            let mut changed = 0;
+            for (rowid, blob) in rows {
+                // NULL and non-BLOB values are not assets to convert.
+                let Some(blob) = blob else { continue };
+                match outcome(key, direction, &blob) {
+                    Outcome::Convert(converted) => {
+                        summary.converted += 1;
+                        summary.bytes_before += blob.len() as u64;
+                        summary.bytes_after += converted.len() as u64;
+                        if execute {
+                            tx.execute(&update, rusqlite::params![converted, rowid])?;
+                            changed += 1;
+                        }
+                    }
+                    Outcome::Done => summary.done += 1,
+                    Outcome::Skip(reason) => {
+                        warn!(tag = "SKIP", table = %target.table, column = %target.column, rowid, reason = %reason, "Left unchanged");
+                        summary.skipped += 1;
+                    }
+                }
+            }
+            tx.commit()?;
+            if changed > 0 {
+                info!(tag = "→", table = %target.table, column = %target.column, rows = changed, total = summary.converted, "Batch committed");
+            }
+        }
+    }
+    Ok(summary)
+}
+
+fn outcome(key: &Key, direction: Direction, blob: &[u8]) -> Outcome {
+    let decrypted = key.open(blob);
+    // Only the signature is checked: a truncated image is still plaintext worth encrypting.
+    let plain = image::guess_format(blob).is_ok();
+    match (direction, decrypted) {
+        (Direction::Encrypt, Ok(_)) => Outcome::Done,
+        (Direction::Encrypt, Err(_)) if plain => match key.seal(blob) {
+            Ok(sealed) => Outcome::Convert(sealed),
+            Err(e) => Outcome::Skip(e),
+        },
+        (Direction::Encrypt, Err(_)) => {
+            Outcome::Skip("neither decrypts nor looks like an image".to_string())
+        }
+        (Direction::Decrypt, Ok(plain)) => Outcome::Convert(plain),
+        (Direction::Decrypt, Err(_)) if plain => Outcome::Done,
+        (Direction::Decrypt, Err(e)) => Outcome::Skip(e),
+    }
+}
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
@@ -0,0 +1,273 @@
+use crate::export::sha256_hex;
+use chrono::{SecondsFormat, Utc};
+use hmac::{Hmac, KeyInit, Mac};
+use rusqlite::{Connection, Result};
+use serde::Serialize;
+use sha2::Sha256;
+use std::path::Path;
+
+/// One place an erased asset could still live.
+#[derive(Serialize)]
+pub struct Location {
+    pub name: String,
+    /// False when the table does not exist in this database.
+    pub present: bool,
+}
+
+#[derive(Serialize)]
+pub struct Entry {
+    pub id: String,
+    /// Locations that still hold the key or asset ID; empty means erased.
+    pub found_in: Vec<String>,
+}
+
+/// The signed part of the report.
+#[derive(Serialize)]
+pub struct Attestation {
+    pub generated_at: String,
+    pub tool: String,
+    pub database: String,
+    pub ids_file: String,
+    pub ids_file_sha256: String,
+    pub locations: Vec<Location>,
+    pub checked: usize,
+    pub still_present: usize,
+    /// Blobs no asset refers to. Any of them may hold the bytes of an erased
+    /// asset, which can no longer be traced to its ID, so they fail the
+    /// attestation.
+    pub orphaned_blobs: usize,
+    pub verified: bool,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Serialize)]
+struct Signature {
+    algorithm: &'static str,
+    value: String,
+}
+
+#[derive(Serialize)]
+struct SignedReport<'a> {
+    attestation: &'a Attestation,
+    signature: Signature,
+}
+
+/// Identifiers from a purge list: one key or asset ID per line. Blank lines
+/// and `#` comments are ignored.
+pub fn parse_ids(raw: &str) -> Vec<String> {
+    raw.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Looks every identifier up as a key and as an asset ID in the live tables,
+/// the quarantine table written by `triage --apply`, any thumbnail table and
+/// the admin ledger's entries, when one is configured. A content-addressed
+/// database is also checked for blobs no asset refers to.
+pub fn verify(
+    conn: &Connection,
+    database: &str,
+    ids_file: &Path,
+    raw: &str,
+    ledger: Option<&[octa_ledger::Entry]>,
+) -> Result<Attestation> {
+    let quarantine = table_exists(conn, "quarantine")?;
+    let thumbnails = thumbnail_tables(conn)?;
+    let blobs = table_exists(conn, "blobs")?;
+
+    let mut locations = vec![
+        Location {
+            name: "key_mappings".to_string(),
+            present: true,
+        },
+        Location {
+            name: "images".to_string(),
+            present: true,
+        },
+        Location {
+            name: "quarantine".to_string(),
+            present: quarantine,
+        },
+    ];
+    if thumbnails.is_empty() {
+        // Resized variants are only cached in server memory unless a table persists them.
+        locations.push(Location {
+            name: "thumbnails".to_string(),
+            present: false,
+        });
+    }
+    for (table, _) in &thumbnails {
+        locations.push(Location {
+            name: table.clone(),
+            present: true,
+        });
+    }
+    locations.push(Location {
+        name: "blobs".to_string(),
+        present: blobs,
+    });
+    locations.push(Location {
+        name: "ledger".to_string(),
+        present: ledger.is_some(),
+    });
+
+    let mut entries = Vec::new();
+    for id in parse_ids(raw) {
+        let normalized = octa_key::normalize(&id);
+        let candidates: Vec<&str> = if normalized == id {
+            vec![&id]
+        } else {
+            vec![&id, &normalized]
+        };
+
+        let mut found_in = Vec::new();
+        if any_match(conn, "key_mappings", &["key", "image_id"], &candidates)? {
+            found_in.push("key_mappings".to_string());
+        }
+        if any_match(conn, "images", &["id"], &candidates)? {
+            found_in.push("images".to_string());
+        }
+        if quarantine && in_quarantine(conn, &candidates)? {
+            found_in.push("quarantine".to_string());
+        }
+        for (table, columns) in &thumbnails {
+            let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+            if any_match(conn, table, &columns, &candidates)? {
+                found_in.push(table.clone());
+            }
+        }
+        // The ledger is append-only: a key it names stays there for good.
+        if ledger.is_some_and(|entries| in_ledger(entries, &candidates)) {
+            found_in.push("ledger".to_string());
+        }
+        entries.push(Entry { id, found_in });
+    }
+
+    let still_present = entries.iter().filter(|e| !e.found_in.is_empty()).count();
+    let orphaned_blobs = match blobs {
+        true => conn.query_row(
+            "SELECT COUNT(*) FROM blobs b
+             WHERE NOT EXISTS (SELECT 1 FROM images i WHERE i.blob_hash = b.hash)",
+            [],
+            |row| row.get::<_, i64>(0).map(|n| n as usize),
+        )?,
+        false => 0,
+    };
+    Ok(Attestation {
+        generated_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        tool: format!("octa-warden {}", env!("CARGO_PKG_VERSION")),
+        database: database.to_string(),
+        ids_file: ids_file.display().to_string(),
+        ids_file_sha256: sha256_hex(raw.as_bytes()),
+        locations,
+        checked: entries.len(),
+        still_present,
+        orphaned_blobs,
+        verified: still_present == 0 && orphaned_blobs == 0,
+        entries,
+    })
+}
+
+/// Writes `{"attestation": ..., "signature": ...}`. The signature is
+/// HMAC-SHA256 over the compact JSON serialization of `attestation`.
+pub fn write_signed(path: &Path, attestation: &Attestation, key: &str) -> std::io::Result<()> {
+    let payload = serde_json::to_vec(attestation)?;
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(&payload);
+    let value = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+
+    let report = SignedReport {
+        attestation,
+        signature: Signature {
+            algorithm: "HMAC-SHA256",
+            value,
+        },
+    };
+    std::fs::write(path, serde_json::to_vec_pretty(&report)?)
+}
+
+fn table_exists(conn: &Connection, name: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+        [name],
+        |row| row.get(0),
+    )
+}
+
+/// Tables whose name mentions thumbnails, with the columns that can hold a
+/// key or an asset ID.
+fn thumbnail_tables(conn: &Connection) -> Result<Vec<(String, Vec<String>)>> {
+    let names: Vec<String> = conn
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name LIKE '%thumb%'")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+
+    let mut tables = Vec::new();
+    for name in names {
+        let columns: Vec<String> = conn
+            .prepare(&format!(
+                "PRAGMA table_info(\"{}\")",
+                name.replace('"', "\"\"")
+            ))?
+            .query_map([], |row| row.get::<_, String>(1))?
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|c| matches!(c.as_str(), "key" | "image_id" | "id"))
+            .collect();
+        if !columns.is_empty() {
+            tables.push((name, columns));
+        }
+    }
+    Ok(tables)
+}
+
+fn any_match(conn: &Connection, table: &str, columns: &[&str], values: &[&str]) -> Result<bool> {
+    let table = table.replace('"', "\"\"");
+    for column in columns {
+        let sql = format!(
+            "SELECT EXISTS (SELECT 1 FROM \"{}\" WHERE \"{}\" = ?1)",
+            table, column
+        );
+        for value in values {
+            if conn.query_row(&sql, [value], |row| row.get::<_, bool>(0))? {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Quarantined rows keep their former keys as a comma-separated list.
+fn in_quarantine(conn: &Connection, values: &[&str]) -> Result<bool> {
+    for value in values {
+        let found = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM quarantine
+                            WHERE id = ?1 OR instr(',' || keys || ',', ',' || ?1 || ',') > 0)",
+            [value],
+            |row| row.get::<_, bool>(0),
+        )?;
+        if found {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Ledger entries name assets and keys in their targets and details.
+fn in_ledger(entries: &[octa_ledger::Entry], values: &[&str]) -> bool {
+    entries.iter().any(|entry| {
+        entry
+            .targets
+            .iter()
+            .chain(entry.detail.values())
+            .any(|v| values.contains(&v.as_str()))
+    })
+}
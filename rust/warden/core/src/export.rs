@@ -0,0 +1,182 @@
+use chrono::Local;
+use rusqlite::Connection;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use tar::{Builder, Header};
+
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Serialize)]
+struct ManifestEntry {
+    id: String,
+    path: String,
+    bytes: u64,
+    sha256: String,
+    format: Option<&'static str>,
+    keys: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Manifest {
+    created_at: String,
+    /// Database path or storage root the assets were read from.
+    source: String,
+    /// False when the audit stopped early; the archive then covers only part of the table.
+    complete: bool,
+    assets: Vec<ManifestEntry>,
+}
+
+pub struct ExportSummary {
+    pub assets: usize,
+    pub bytes: u64,
+}
+
+/// Streams verified-healthy assets into a `.tar.zst` archive while the audit
+/// runs. Every asset goes to `assets/<id>.<ext>`; `manifest.json` is appended
+/// last, once the set of assets is known.
+pub struct Exporter {
+    tar: Builder<zstd::Encoder<'static, BufWriter<File>>>,
+    entries: Vec<ManifestEntry>,
+    bytes: u64,
+    /// First write error. Later assets are ignored so the audit itself can finish.
+    error: Option<io::Error>,
+}
+
+impl Exporter {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        let encoder = zstd::Encoder::new(file, ZSTD_LEVEL)?;
+        Ok(Self {
+            tar: Builder::new(encoder),
+            entries: Vec::new(),
+            bytes: 0,
+            error: None,
+        })
+    }
+
+    pub fn add(&mut self, id: &str, blob: &[u8]) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let (name, format) = asset_file_name(id, blob);
+        let path = format!("assets/{}", name);
+
+        if let Err(e) = append(&mut self.tar, &path, blob) {
+            self.error = Some(e);
+            return;
+        }
+
+        self.bytes += blob.len() as u64;
+        self.entries.push(ManifestEntry {
+            id: id.to_string(),
+            path,
+            bytes: blob.len() as u64,
+            sha256: sha256_hex(blob),
+            format,
+            keys: Vec::new(),
+        });
+    }
+
+    /// Attaches each asset's keys, writes the manifest and closes the archive.
+    pub fn finish(
+        mut self,
+        mut keys: HashMap<String, Vec<String>>,
+        source: &str,
+        complete: bool,
+    ) -> io::Result<ExportSummary> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+
+        for entry in &mut self.entries {
+            entry.keys = keys.remove(&entry.id).unwrap_or_default();
+        }
+
+        let summary = ExportSummary {
+            assets: self.entries.len(),
+            bytes: self.bytes,
+        };
+        let manifest = Manifest {
+            created_at: Local::now().to_rfc3339(),
+            source: source.to_string(),
+            complete,
+            assets: self.entries,
+        };
+        let raw = serde_json::to_vec_pretty(&manifest)?;
+        append(&mut self.tar, "manifest.json", &raw)?;
+
+        let encoder = self.tar.into_inner()?;
+        let mut file = encoder.finish()?;
+        io::Write::flush(&mut file)?;
+        Ok(summary)
+    }
+}
+
+fn append<W: io::Write>(tar: &mut Builder<W>, path: &str, data: &[u8]) -> io::Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Local::now().timestamp() as u64);
+    header.set_cksum();
+    tar.append_data(&mut header, path, data)
+}
+
+/// Keys per image ID, for the manifest.
+pub fn load_keys(conn: &Connection) -> rusqlite::Result<HashMap<String, Vec<String>>> {
+    let mut keys: HashMap<String, Vec<String>> = HashMap::new();
+    let mut stmt = conn.prepare("SELECT image_id, key FROM key_mappings ORDER BY key")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    for row in rows {
+        let (image_id, key): (String, String) = row?;
+        keys.entry(image_id).or_default().push(key);
+    }
+    Ok(keys)
+}
+
+/// Keys of the given image IDs, for the assets a fix just rewrote.
+pub fn keys_of(conn: &Connection, ids: &[String]) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT key FROM key_mappings WHERE image_id = ?1")?;
+    let mut keys = Vec::new();
+    for id in ids {
+        for key in stmt.query_map([id], |row| row.get(0))? {
+            keys.push(key?);
+        }
+    }
+    Ok(keys)
+}
+
+/// `<id>.<ext>` for an asset, with the extension taken from the detected
+/// format, plus that format. Asset IDs are UUIDs, but never trust them as
+/// path components.
+pub fn asset_file_name(id: &str, blob: &[u8]) -> (String, Option<&'static str>) {
+    let format = image::guess_format(blob)
+        .ok()
+        .and_then(|f| f.extensions_str().first().copied());
+    let ext = format.unwrap_or("bin");
+
+    let name: String = id
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+
+    if name.ends_with(&format!(".{}", ext)) {
+        (name, format)
+    } else {
+        (format!("{}.{}", name, ext), format)
+    }
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
@@ -0,0 +1,158 @@
+use crate::db::{self, OpenOptions};
+use crate::storage::StorageConfig;
+use octa_store::cas;
+use octa_storage::fs::walk;
+use std::fs;
+use std::path::Path;
+use tracing::warn;
+
+/// Warn once the disk is projected to fill up within this many days.
+pub const WARN_DAYS: f64 = 30.0;
+
+/// Size of the asset store at the end of a run.
+#[derive(Debug, Clone, Copy)]
+pub struct Footprint {
+    pub rows: u64,
+    /// Sum of the stored image bytes.
+    pub bytes: u64,
+    /// Free space on the filesystem holding the store, when it can be determined.
+    pub disk_free: Option<u64>,
+}
+
+/// Measures the configured store. Object storage has no disk to fill and is
+/// not measured; failures are logged and only cost the growth data point.
+pub fn measure(
+    storage: &StorageConfig,
+    db_path: &str,
+    open_opts: &OpenOptions,
+) -> Option<Footprint> {
+    let measured = match storage {
+        StorageConfig::Sqlite => sqlite(db_path, open_opts).map_err(|e| e.to_string()),
+        StorageConfig::Fs { root } => Ok(tree(root)),
+        StorageConfig::S3(_) => return None,
+    };
+    match measured {
+        Ok(footprint) => Some(footprint),
+        Err(e) => {
+            warn!(tag = "WARN", reason = %e, "Could not measure storage size");
+            None
+        }
+    }
+}
+
+fn sqlite(db_path: &str, open_opts: &OpenOptions) -> rusqlite::Result<Footprint> {
+    let conn = db::open_read_only(db_path, open_opts)?;
+    let rows: i64 = conn.query_row("SELECT COUNT(*) FROM images", [], |row| row.get(0))?;
+    let bytes = cas::stored_bytes(&conn)?;
+    let dir = Path::new(db_path)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty());
+    Ok(Footprint {
+        rows: rows as u64,
+        bytes,
+        disk_free: disk_free(dir.unwrap_or(Path::new("."))),
+    })
+}
+
+fn tree(root: &Path) -> Footprint {
+    let files = walk(root);
+    Footprint {
+        rows: files.len() as u64,
+        bytes: files
+            .iter()
+            .filter_map(|(_, path)| fs::metadata(path).ok())
+            .map(|m| m.len())
+            .sum(),
+        disk_free: disk_free(root),
+    }
+}
+
+/// Space available to unprivileged users on the filesystem holding `path`.
+#[cfg(unix)]
+pub fn disk_free(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is NUL-terminated and `stat` is a valid out pointer.
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn disk_free(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Growth between the first and last measured run.
+pub struct Growth {
+    pub days: f64,
+    pub rows_per_day: f64,
+    pub bytes_per_day: f64,
+    /// Relative byte growth over 30 days, e.g. `0.5` for +50%.
+    pub monthly_ratio: Option<f64>,
+    /// Days until the free space is used up at the current rate (naive, linear).
+    pub days_to_full: Option<f64>,
+}
+
+/// `samples` are `(finished_at, footprint)` pairs, oldest first.
+pub fn trend(samples: &[(i64, Footprint)]) -> Option<Growth> {
+    let (first, last) = match (samples.first(), samples.last()) {
+        (Some(first), Some(last)) if samples.len() > 1 => (first, last),
+        _ => return None,
+    };
+    let days = ((last.0 - first.0) as f64 / 86400.0).max(1.0 / 24.0);
+    let rows_per_day = (last.1.rows as f64 - first.1.rows as f64) / days;
+    let bytes_per_day = (last.1.bytes as f64 - first.1.bytes as f64) / days;
+
+    Some(Growth {
+        days,
+        rows_per_day,
+        bytes_per_day,
+        monthly_ratio: (first.1.bytes > 0).then(|| bytes_per_day * 30.0 / first.1.bytes as f64),
+        days_to_full: match last.1.disk_free {
+            Some(free) if bytes_per_day > 0.0 => Some(free as f64 / bytes_per_day),
+            _ => None,
+        },
+    })
+}
+
+/// `5GB`, `512 MB`, `1024`: binary units, case-insensitive, as the servers
+/// read `database.max_size` and `image.max_upload_size`.
+pub fn parse_bytes(value: &str) -> Result<u64, String> {
+    let trimmed = value.trim().to_uppercase();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let invalid = || format!("'{}' is not a size like 5GB", value);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let shift = match unit.trim() {
+        "" | "B" => 0,
+        "KB" => 10,
+        "MB" => 20,
+        "GB" => 30,
+        "TB" => 40,
+        _ => return Err(invalid()),
+    };
+    number.checked_mul(1 << shift).ok_or_else(invalid)
+}
+
+/// `1.5 GiB`-style sizes for the console.
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value.abs() >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{:.0} {}", value, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
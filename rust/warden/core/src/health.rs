@@ -0,0 +1,169 @@
+use crate::audit::{AuditResult, Severity};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::process::ExitCode;
+
+/// `warden.health` section of config.yaml.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    /// Per-kind severity overrides, e.g. `schema_mismatch: critical`.
+    pub severity: HashMap<String, Severity>,
+    /// Exceeding any of these makes the verdict at least ATTENTION REQUIRED.
+    pub warning: ThresholdRule,
+    /// Exceeding any of these makes the verdict CRITICAL.
+    pub critical: ThresholdRule,
+}
+
+/// A set of "more than N" limits; a rule is triggered when any limit is exceeded.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThresholdRule {
+    /// More than N corrupted blobs.
+    pub corrupted: Option<u64>,
+    /// More than P percent of the scanned assets corrupted.
+    pub corrupted_percent: Option<f64>,
+    /// More than N schema errors.
+    pub schema_errors: Option<u64>,
+    /// More than N findings classified as warning.
+    pub warning_findings: Option<u64>,
+    /// More than N findings classified as critical.
+    pub critical_findings: Option<u64>,
+}
+
+impl Default for HealthConfig {
+    /// Any finding needs attention; only corruption above 1% is critical.
+    fn default() -> Self {
+        Self {
+            severity: HashMap::new(),
+            warning: ThresholdRule {
+                corrupted: Some(0),
+                schema_errors: Some(0),
+                warning_findings: Some(0),
+                critical_findings: Some(0),
+                ..Default::default()
+            },
+            critical: ThresholdRule {
+                corrupted_percent: Some(1.0),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verdict {
+    Healthy,
+    Warning,
+    Critical,
+}
+
+impl Verdict {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Verdict::Healthy => "SYSTEM HEALTHY",
+            Verdict::Warning => "ATTENTION REQUIRED",
+            Verdict::Critical => "CRITICAL",
+        }
+    }
+
+    /// 0 = healthy, 1 = attention required, 2 = critical.
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(*self as u8)
+    }
+}
+
+pub struct Assessment {
+    pub verdict: Verdict,
+    /// Human-readable list of the limits that were exceeded.
+    pub reasons: Vec<String>,
+}
+
+/// Applies the configured severity overrides to every finding.
+pub fn classify(result: &mut AuditResult, cfg: &HealthConfig) {
+    if cfg.severity.is_empty() {
+        return;
+    }
+    for f in &mut result.findings {
+        if let Some(severity) = cfg.severity.get(f.kind.as_str()) {
+            f.severity = *severity;
+        }
+    }
+}
+
+/// Turns the run's counts into a verdict using the configured thresholds.
+pub fn assess(result: &AuditResult, cfg: &HealthConfig) -> Assessment {
+    let mut critical = triggered(&cfg.critical, result);
+    if let Some(reason) = &result.aborted {
+        critical.insert(0, format!("widespread corruption detected: {}", reason));
+    }
+    if !critical.is_empty() {
+        return Assessment {
+            verdict: Verdict::Critical,
+            reasons: critical,
+        };
+    }
+
+    let warning = triggered(&cfg.warning, result);
+    let verdict = if warning.is_empty() {
+        Verdict::Healthy
+    } else {
+        Verdict::Warning
+    };
+    Assessment {
+        verdict,
+        reasons: warning,
+    }
+}
+
+fn triggered(rule: &ThresholdRule, result: &AuditResult) -> Vec<String> {
+    let stats = &result.stats;
+    let count = |severity: Severity| {
+        result
+            .findings
+            .iter()
+            .filter(|f| f.severity == severity)
+            .count() as u64
+    };
+    let corrupted_percent = if stats.total_scanned > 0 {
+        stats.corrupted_blob as f64 * 100.0 / stats.total_scanned as f64
+    } else {
+        0.0
+    };
+
+    let mut reasons = Vec::new();
+    if let Some(limit) = rule.corrupted {
+        if stats.corrupted_blob > limit {
+            reasons.push(format!(
+                "corrupted blobs {} > {}",
+                stats.corrupted_blob, limit
+            ));
+        }
+    }
+    if let Some(limit) = rule.corrupted_percent {
+        if corrupted_percent > limit {
+            reasons.push(format!("corrupted {:.3}% > {}%", corrupted_percent, limit));
+        }
+    }
+    if let Some(limit) = rule.schema_errors {
+        if stats.db_schema_error > limit {
+            reasons.push(format!(
+                "schema errors {} > {}",
+                stats.db_schema_error, limit
+            ));
+        }
+    }
+    if let Some(limit) = rule.warning_findings {
+        let n = count(Severity::Warning);
+        if n > limit {
+            reasons.push(format!("warning findings {} > {}", n, limit));
+        }
+    }
+    if let Some(limit) = rule.critical_findings {
+        let n = count(Severity::Critical);
+        if n > limit {
+            reasons.push(format!("critical findings {} > {}", n, limit));
+        }
+    }
+    reasons
+}
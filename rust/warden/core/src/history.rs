@@ -0,0 +1,469 @@
+use crate::audit::{AuditResult, Finding};
+use crate::growth::{self, Footprint};
+use chrono::{Local, TimeZone};
+use console::style;
+use rusqlite::{params, Connection, Result};
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// Local SQLite file recording every audit run, so a single snapshot
+/// ("37 corrupt blobs") can be read as a trend ("up from 3 last month").
+pub struct History {
+    conn: Connection,
+}
+
+pub struct RunRecord {
+    pub id: i64,
+    pub finished_at: i64,
+    pub full_scan: bool,
+    pub scanned: u64,
+    pub healthy: u64,
+    pub corrupted: u64,
+    pub schema_errors: u64,
+    pub duration_ms: u64,
+    /// Store size after the run; missing for runs recorded before it was tracked.
+    pub footprint: Option<Footprint>,
+}
+
+impl History {
+    /// Opens (or creates) the history database, which Warden owns outright.
+    pub fn open(path: &str) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS runs (
+                id            INTEGER PRIMARY KEY AUTOINCREMENT,
+                finished_at   INTEGER NOT NULL,
+                full_scan     INTEGER NOT NULL,
+                duration_ms   INTEGER NOT NULL,
+                scanned       INTEGER NOT NULL,
+                healthy       INTEGER NOT NULL,
+                corrupted     INTEGER NOT NULL,
+                schema_errors INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS findings (
+                run_id   INTEGER NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+                asset_id TEXT,
+                kind     TEXT NOT NULL,
+                severity TEXT NOT NULL,
+                reason   TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_findings_run_id ON findings(run_id);",
+        )?;
+
+        // Growth columns were added later; older history files get them on open.
+        let columns: Vec<String> = conn
+            .prepare("PRAGMA table_info(runs)")?
+            .query_map([], |row| row.get(1))?
+            .collect::<Result<_>>()?;
+        for column in ["rows_total", "bytes_total", "disk_free"] {
+            if !columns.iter().any(|c| c == column) {
+                conn.execute_batch(&format!("ALTER TABLE runs ADD COLUMN {} INTEGER", column))?;
+            }
+        }
+        Ok(Self { conn })
+    }
+
+    /// The history database itself, for exports.
+    pub fn conn(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Stores the run and its findings in one transaction. Returns the run ID.
+    pub fn record(
+        &mut self,
+        result: &AuditResult,
+        duration: Duration,
+        full_scan: bool,
+        footprint: Option<&Footprint>,
+    ) -> Result<i64> {
+        let finished_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let stats = &result.stats;
+
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO runs (finished_at, full_scan, duration_ms, scanned, healthy, corrupted, schema_errors,
+                               rows_total, bytes_total, disk_free)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                finished_at,
+                full_scan,
+                duration.as_millis() as i64,
+                stats.total_scanned as i64,
+                stats.healthy as i64,
+                stats.corrupted_blob as i64,
+                stats.db_schema_error as i64,
+                footprint.map(|f| f.rows as i64),
+                footprint.map(|f| f.bytes as i64),
+                footprint.and_then(|f| f.disk_free).map(|f| f as i64),
+            ],
+        )?;
+        let run_id = tx.last_insert_rowid();
+
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO findings (run_id, asset_id, kind, severity, reason) VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for f in &result.findings {
+                stmt.execute(params![
+                    run_id,
+                    f.id,
+                    f.kind.as_str(),
+                    f.severity.as_str(),
+                    f.reason
+                ])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(run_id)
+    }
+
+    /// Most recent runs, oldest first.
+    pub fn recent(&self, limit: usize) -> Result<Vec<RunRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, finished_at, full_scan, scanned, healthy, corrupted, schema_errors, duration_ms,
+                    rows_total, bytes_total, disk_free
+             FROM runs ORDER BY id DESC LIMIT ?1",
+        )?;
+        let mut runs = stmt
+            .query_map([limit as i64], |row| {
+                Ok(RunRecord {
+                    id: row.get(0)?,
+                    finished_at: row.get(1)?,
+                    full_scan: row.get(2)?,
+                    scanned: row.get::<_, i64>(3)? as u64,
+                    healthy: row.get::<_, i64>(4)? as u64,
+                    corrupted: row.get::<_, i64>(5)? as u64,
+                    schema_errors: row.get::<_, i64>(6)? as u64,
+                    duration_ms: row.get::<_, i64>(7)? as u64,
+                    footprint: match (row.get::<_, Option<i64>>(8)?, row.get::<_, Option<i64>>(9)?)
+                    {
+                        (Some(rows), Some(bytes)) => Some(Footprint {
+                            rows: rows as u64,
+                            bytes: bytes as u64,
+                            disk_free: row.get::<_, Option<i64>>(10)?.map(|f| f as u64),
+                        }),
+                        _ => None,
+                    },
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        runs.reverse();
+        Ok(runs)
+    }
+}
+
+fn format_time(unix: i64) -> String {
+    Local
+        .timestamp_opt(unix, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| unix.to_string())
+}
+
+/// Prints the run table plus a corruption trend computed over full scans only
+/// (incremental scans cover a varying subset and are not comparable).
+pub fn render_trend(runs: &[RunRecord]) {
+    if !octa_logging::is_human() {
+        for r in runs {
+            info!(
+                tag = "HISTORY",
+                run = r.id,
+                finished_at = r.finished_at,
+                full_scan = r.full_scan,
+                scanned = r.scanned,
+                healthy = r.healthy,
+                corrupted_blobs = r.corrupted,
+                schema_errors = r.schema_errors,
+                duration_ms = r.duration_ms,
+                rows_total = r.footprint.map(|f| f.rows),
+                bytes_total = r.footprint.map(|f| f.bytes),
+                disk_free = r.footprint.and_then(|f| f.disk_free),
+                "Audit run"
+            );
+        }
+        if let Some(g) = growth::trend(&samples(runs)) {
+            info!(
+                tag = "GROWTH",
+                days = g.days,
+                rows_per_day = g.rows_per_day,
+                bytes_per_day = g.bytes_per_day,
+                monthly_growth = g.monthly_ratio,
+                days_to_full = g.days_to_full,
+                "Storage growth"
+            );
+        }
+        return;
+    }
+
+    println!("\n{}", style("WARDEN AUDIT HISTORY").bold().underlined());
+    if runs.is_empty() {
+        println!("{}", style("No audit runs recorded yet.").dim());
+        return;
+    }
+
+    println!(
+        "{:>5}  {:<16}  {:<11}  {:>10}  {:>9}  {:>7}",
+        "Run", "Finished", "Scan", "Scanned", "Corrupted", "Schema"
+    );
+    println!("{}", "-".repeat(68));
+
+    let mut previous_full: Option<u64> = None;
+    for r in runs {
+        let delta = match (r.full_scan, previous_full) {
+            (true, Some(prev)) if r.corrupted > prev => {
+                style(format!(" (+{})", r.corrupted - prev))
+                    .red()
+                    .to_string()
+            }
+            (true, Some(prev)) if r.corrupted < prev => {
+                style(format!(" (-{})", prev - r.corrupted))
+                    .green()
+                    .to_string()
+            }
+            _ => String::new(),
+        };
+        if r.full_scan {
+            previous_full = Some(r.corrupted);
+        }
+
+        // Pad before styling: ANSI codes would otherwise count towards the width.
+        let corrupted = format!("{:>9}", r.corrupted);
+        let corrupted = if r.corrupted > 0 {
+            style(corrupted).red().bold().to_string()
+        } else {
+            style(corrupted).dim().to_string()
+        };
+
+        println!(
+            "{:>5}  {:<16}  {:<11}  {:>10}  {}  {:>7}{}",
+            r.id,
+            format_time(r.finished_at),
+            if r.full_scan { "full" } else { "incremental" },
+            r.scanned,
+            corrupted,
+            r.schema_errors,
+            delta
+        );
+    }
+    println!("{}", "-".repeat(68));
+
+    let full: Vec<&RunRecord> = runs.iter().filter(|r| r.full_scan).collect();
+    match (full.first(), full.last()) {
+        (Some(first), Some(last)) if full.len() > 1 => {
+            let days = ((last.finished_at - first.finished_at) as f64 / 86400.0).max(1.0 / 24.0);
+            let change = last.corrupted as i64 - first.corrupted as i64;
+            let rate = change as f64 / days;
+
+            let verdict = if change > 0 {
+                style(format!("GROWING ({:+.2} corrupt blobs/day)", rate))
+                    .red()
+                    .bold()
+            } else if change < 0 {
+                style(format!("SHRINKING ({:+.2} corrupt blobs/day)", rate)).green()
+            } else {
+                style("STABLE".to_string()).green()
+            };
+
+            println!(
+                "Corruption trend: {} → {} over {} full scans ({:.1} days)",
+                first.corrupted,
+                last.corrupted,
+                full.len(),
+                days
+            );
+            println!("Status          : {}", verdict);
+        }
+        _ => println!("{}", style("Trend needs at least two full scans.").dim()),
+    }
+
+    render_growth(runs);
+}
+
+/// Runs that recorded a footprint, as `(finished_at, footprint)`.
+fn samples(runs: &[RunRecord]) -> Vec<(i64, Footprint)> {
+    runs.iter()
+        .filter_map(|r| Some((r.finished_at, r.footprint?)))
+        .collect()
+}
+
+/// Rows and bytes per day between the first and last measured run, and when
+/// the disk fills up if that rate holds. Any scan type counts: the store is
+/// measured separately from what the audit covered.
+fn render_growth(runs: &[RunRecord]) {
+    let samples = samples(runs);
+    let Some(g) = growth::trend(&samples) else {
+        println!(
+            "{}",
+            style("Growth needs at least two runs with size data.").dim()
+        );
+        return;
+    };
+    let (first, last) = (samples[0].1, samples[samples.len() - 1].1);
+
+    println!(
+        "Storage growth  : {} → {} rows, {} → {} over {:.1} days",
+        first.rows,
+        last.rows,
+        growth::format_bytes(first.bytes as f64),
+        growth::format_bytes(last.bytes as f64),
+        g.days
+    );
+    let monthly = g
+        .monthly_ratio
+        .map(|r| format!(", {:+.0}% per 30 days", r * 100.0))
+        .unwrap_or_default();
+    println!(
+        "Rate            : {:+.1} rows/day, {}/day{}",
+        g.rows_per_day,
+        growth::format_bytes(g.bytes_per_day),
+        monthly
+    );
+
+    match (g.days_to_full, last.disk_free) {
+        (Some(days), Some(free)) => {
+            let full_at = chrono::Duration::try_days(days as i64)
+                .and_then(|d| Local::now().checked_add_signed(d))
+                .map(|t| t.format("%Y-%m-%d").to_string())
+                .unwrap_or_else(|| "far future".to_string());
+            let line = format!(
+                "{} free, full in ~{:.0} days ({})",
+                growth::format_bytes(free as f64),
+                days,
+                full_at
+            );
+            let line = if days < growth::WARN_DAYS {
+                style(line).red().bold()
+            } else {
+                style(line).green()
+            };
+            println!("Disk projection : {}", line);
+        }
+        (None, Some(free)) => println!(
+            "Disk projection : {} free, not shrinking",
+            growth::format_bytes(free as f64)
+        ),
+        _ => println!("{}", style("Disk projection : free space unknown").dim()),
+    }
+}
+
+/// Records a run when history is enabled. Failures are logged; they never fail the audit.
+pub fn record_run(
+    path: Option<&str>,
+    result: &AuditResult,
+    duration: Duration,
+    full_scan: bool,
+    footprint: Option<&Footprint>,
+) {
+    let Some(path) = path else {
+        return;
+    };
+    let recorded = History::open(path).and_then(|mut h| {
+        let run_id = h.record(result, duration, full_scan, footprint)?;
+        Ok((run_id, h.recent(GROWTH_WINDOW)?))
+    });
+    match recorded {
+        Ok((run_id, runs)) => {
+            info!(tag = "HISTORY", run = run_id, path, "Run recorded");
+            warn_disk_full(&runs);
+        }
+        Err(e) => warn!(tag = "WARN", path, error = %e, "Could not record run in history"),
+    }
+}
+
+/// Runs the disk-full projection after each run looks back over.
+const GROWTH_WINDOW: usize = 30;
+
+fn warn_disk_full(runs: &[RunRecord]) {
+    let Some(days) = growth::trend(&samples(runs)).and_then(|g| g.days_to_full) else {
+        return;
+    };
+    if days < growth::WARN_DAYS {
+        warn!(
+            tag = "WARN",
+            days_to_full = days.round() as u64,
+            "Disk projected to fill up at the current growth rate"
+        );
+    }
+}
+
+/// Current findings split into newly appeared and already known problems.
+pub struct FindingsDiff {
+    /// Findings not present in the previous audits (new damage).
+    pub new: Vec<Finding>,
+    /// Findings already reported by earlier runs.
+    pub known: usize,
+    /// (asset ID, kind) pairs that were known but are gone. Only computed for full scans.
+    pub resolved: Vec<(String, String)>,
+}
+
+impl History {
+    /// Findings considered "known": everything reported by the last full scan
+    /// and by any incremental scans after it. Findings without an asset ID are ignored.
+    pub fn known_findings(&self) -> Result<HashSet<(String, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT DISTINCT asset_id, kind FROM findings
+             WHERE asset_id IS NOT NULL
+               AND run_id >= IFNULL((SELECT MAX(id) FROM runs WHERE full_scan = 1), 0)",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    fn has_runs(&self) -> Result<bool> {
+        self.conn
+            .query_row("SELECT EXISTS(SELECT 1 FROM runs)", [], |row| row.get(0))
+    }
+}
+
+/// Compares the current run with what the history already knows.
+/// Returns `None` when history is disabled or empty (there is nothing to compare against).
+/// Must be called before the current run is recorded.
+pub fn compare(path: Option<&str>, result: &AuditResult, full_scan: bool) -> Option<FindingsDiff> {
+    let path = path?;
+    let known = match History::open(path).and_then(|h| {
+        if h.has_runs()? {
+            h.known_findings().map(Some)
+        } else {
+            Ok(None)
+        }
+    }) {
+        Ok(Some(known)) => known,
+        Ok(None) => return None,
+        Err(e) => {
+            warn!(tag = "WARN", path, error = %e, "Could not read history for comparison");
+            return None;
+        }
+    };
+
+    let mut diff = FindingsDiff {
+        new: Vec::new(),
+        known: 0,
+        resolved: Vec::new(),
+    };
+    let mut current = HashSet::new();
+
+    for f in &result.findings {
+        let Some(id) = &f.id else {
+            continue;
+        };
+        let key = (id.clone(), f.kind.as_str().to_string());
+        if known.contains(&key) {
+            diff.known += 1;
+        } else {
+            diff.new.push(f.clone());
+        }
+        current.insert(key);
+    }
+
+    if full_scan {
+        diff.resolved = known.difference(&current).cloned().collect();
+        diff.resolved.sort();
+    }
+
+    Some(diff)
+}
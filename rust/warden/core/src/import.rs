@@ -0,0 +1,180 @@
+use crate::migrate::KEYS_FILE;
+use image::load_from_memory;
+use octa_logging::FINDING_TARGET;
+use octa_storage::{Added, Backend, Error, NewAsset, Sqlite};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+pub struct ImportOptions {
+    /// Assets inserted per transaction.
+    pub batch_size: usize,
+    /// TSV with one line per source file that was not imported.
+    pub report: Option<PathBuf>,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub imported: u64,
+    /// Valid images whose key is already mapped in Octa.
+    pub skipped: u64,
+    /// Unreadable files, undecodable data or names that are not valid keys.
+    pub invalid: u64,
+}
+
+/// Keys recorded by `migrate` in the source's `.warden/keys.tsv`, by file
+/// path. Lets a migrated tree or bucket come back with its original keys.
+pub fn migrated_keys(source: &dyn Backend) -> HashMap<String, Vec<String>> {
+    let mut keys: HashMap<String, Vec<String>> = HashMap::new();
+    let Ok(Some(raw)) = source.read(KEYS_FILE) else {
+        return keys;
+    };
+    for line in String::from_utf8_lossy(&raw).lines().skip(1) {
+        if let Some((key, path)) = line.split_once('\t') {
+            keys.entry(path.to_string())
+                .or_default()
+                .push(key.to_string());
+        }
+    }
+    keys
+}
+
+/// Validates every asset of `source` and inserts it into `db`, committing
+/// every `batch_size` assets. Keys come from `known_keys` when the asset is
+/// listed there, otherwise from its name without the extension
+/// (`users/42.png` -> `users/42`). Fails when the source cannot be listed
+/// or a batch cannot be committed.
+pub fn run(
+    db: &Sqlite,
+    source: &dyn Backend,
+    known_keys: &HashMap<String, Vec<String>>,
+    opts: &ImportOptions,
+) -> Result<ImportSummary, Error> {
+    let ids = source.list()?;
+    info!(tag = "→", assets = ids.len(), source = %source.describe(), "Import starting");
+
+    let batch_size = opts.batch_size.max(1);
+    let mut progress = Progress::default();
+    let mut failed = None;
+
+    source.stream(&ids, &mut |fetched| {
+        let name = fetched.id.to_string();
+        let keys = match known_keys.get(&name) {
+            Some(keys) => keys.clone(),
+            None => key_for(&name).into_iter().collect(),
+        };
+
+        match fetched.data {
+            Err(e) => progress.reject(name, "invalid", format!("read failed: {}", e)),
+            Ok(None) => progress.reject(name, "invalid", "deleted after listing".to_string()),
+            Ok(Some(_)) if keys.is_empty() => {
+                progress.reject(name, "invalid", "name is not a valid Octa key".to_string())
+            }
+            Ok(Some(data)) => match load_from_memory(&data) {
+                Err(e) => progress.reject(name, "invalid", e.to_string()),
+                Ok(_) => progress.batch.push((name, keys, data)),
+            },
+        }
+
+        if progress.batch.len() >= batch_size {
+            if let Err(e) = progress.commit(db) {
+                failed = Some(e);
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    });
+    if let Some(e) = failed {
+        return Err(e);
+    }
+    progress.commit(db)?;
+
+    if let Some(path) = &opts.report {
+        if let Err(e) = write_report(path, &progress.rejected) {
+            warn!(tag = "WARN", path = %path.display(), reason = %e, "Could not write import report");
+        }
+    }
+
+    Ok(progress.summary)
+}
+
+/// An import so far.
+#[derive(Default)]
+struct Progress {
+    summary: ImportSummary,
+    /// File, status and reason of every asset not imported.
+    rejected: Vec<(String, &'static str, String)>,
+    /// Valid assets waiting for the next commit: name, keys and bytes.
+    batch: Vec<(String, Vec<String>, Vec<u8>)>,
+}
+
+impl Progress {
+    fn reject(&mut self, name: String, status: &'static str, reason: String) {
+        if status == "skipped" {
+            self.summary.skipped += 1;
+        } else {
+            self.summary.invalid += 1;
+        }
+        warn!(target: FINDING_TARGET, tag = "SKIP", file = %name, status, reason = %reason, "Not imported");
+        self.rejected.push((name, status, reason));
+    }
+
+    /// Inserts the waiting assets in one transaction.
+    fn commit(&mut self, db: &Sqlite) -> Result<(), Error> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let batch = std::mem::take(&mut self.batch);
+        let assets: Vec<NewAsset> = batch
+            .iter()
+            .map(|(_, keys, data)| NewAsset {
+                data,
+                keys,
+                at: None,
+            })
+            .collect();
+        let added = db.add(&assets)?;
+
+        for ((name, _, _), added) in batch.into_iter().zip(added) {
+            match added {
+                Added::Inserted(_) => self.summary.imported += 1,
+                Added::Taken { key, id } => self.reject(
+                    name,
+                    "skipped",
+                    format!("key '{}' already maps to {}", key, id),
+                ),
+                Added::Invalid(reason) => self.reject(name, "invalid", reason),
+            }
+        }
+        info!(tag = "→", imported = self.summary.imported, "Batch committed");
+        Ok(())
+    }
+}
+
+/// The key the server would store for a file: its path without extension,
+/// when that is a legal key (see [`octa_key`]).
+fn key_for(name: &str) -> Option<String> {
+    let stem = match name.rsplit_once('.') {
+        Some((stem, ext)) if !ext.contains('/') => stem,
+        _ => name,
+    };
+    octa_key::parse(stem).ok()
+}
+
+fn write_report(path: &Path, rejected: &[(String, &'static str, String)]) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "file\tstatus\treason")?;
+    for (name, status, reason) in rejected {
+        writeln!(
+            out,
+            "{}\t{}\t{}",
+            name,
+            status,
+            reason.replace(['\t', '\n'], " ")
+        )?;
+    }
+    out.flush()
+}
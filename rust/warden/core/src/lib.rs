@@ -0,0 +1,119 @@
+//! Scanning pipeline of octa-warden: storage backends, BLOB validators,
+//! statistics, health rules and report renderers. The `octa-warden` binary
+//! is a thin CLI over this crate; services embed it to audit without
+//! shelling out.
+//!
+//! ```no_run
+//! use octa_warden_core::{audit::RunOptions, config, db::OpenOptions};
+//! use std::time::Duration;
+//!
+//! let config = config::load(Some("config.yaml"), None).expect("valid config");
+//! let open = OpenOptions { busy_timeout: Duration::from_secs(5), immutable: false };
+//! let (result, assessment) =
+//!     octa_warden_core::run_audit(&config, &open, true, &RunOptions::default()).unwrap();
+//! println!("{} findings, {}", result.findings.len(), assessment.verdict.label());
+//! ```
+//!
+//! Progress and findings are emitted as `tracing` events (per-row events use
+//! [`octa_logging::FINDING_TARGET`]); install a subscriber to see them, or
+//! call [`octa_logging::init`] for the CLI's console output.
+
+pub mod analytics;
+pub mod audit;
+pub mod backend;
+pub mod base64_blob;
+pub mod bundle;
+pub mod cause;
+pub mod color;
+pub mod compression;
+pub mod config;
+pub mod db;
+pub mod dedup;
+pub mod derived;
+pub mod encryption;
+pub mod erasure;
+pub mod export;
+pub mod growth;
+pub mod health;
+pub mod history;
+pub mod import;
+pub mod metrics;
+pub mod migrate;
+pub mod notify;
+pub mod parquet;
+pub mod partition;
+pub mod plan;
+pub mod report;
+pub mod restore;
+pub mod retention;
+pub mod scaffold;
+pub mod schedule;
+pub mod storage;
+pub mod stream;
+pub mod tenants;
+pub mod timestamps;
+pub mod watch;
+
+/// The canonical schema and the content-addressed layout, kept with the
+/// rest of the storage layer.
+pub use octa_store::{cas, schema};
+/// The S3 client and its request signing, kept with the other storage
+/// backends.
+pub use octa_storage::{s3, sigv4};
+
+use audit::{AuditResult, RunOptions};
+use config::Config;
+use health::Assessment;
+use octa_storage::{Fs, S3};
+use std::path::Path;
+use storage::StorageConfig;
+
+/// One full audit of the configured store, as a manual run does it: scan,
+/// derived-size check (when configured) and health classification. Nothing
+/// is printed, recorded or sent; pass the result to [`report`], [`history`]
+/// or [`notify`] as needed.
+pub fn run_audit(
+    config: &Config,
+    open: &db::OpenOptions,
+    snapshot: bool,
+    opts: &RunOptions,
+) -> Result<(AuditResult, Assessment), String> {
+    let mut result = match &config.warden.storage {
+        StorageConfig::Sqlite => {
+            let path = &config.database.path;
+            if !Path::new(path).exists() {
+                return Err(format!("database file not found: {}", path));
+            }
+            let target = db::attach(path, open, snapshot).map_err(|e| e.to_string())?;
+            let mut result =
+                audit::run(&target.conn, &audit::Scope::Full, opts).map_err(|e| e.to_string())?;
+            if let (Some(cfg), None) = (&config.warden.derived, &result.aborted) {
+                derived::check(
+                    &target.conn,
+                    cfg,
+                    opts.decryption.as_deref(),
+                    opts.findings_stream.as_ref(),
+                    &mut result,
+                )
+                .map_err(|e| e.to_string())?;
+            }
+            if result.aborted.is_none() {
+                timestamps::check(&target.conn, opts.findings_stream.as_ref(), &mut result)
+                    .map_err(|e| e.to_string())?;
+            }
+            result
+        }
+        StorageConfig::Fs { root } if !root.is_dir() => {
+            return Err(format!("storage root not found: {}", root.display()))
+        }
+        StorageConfig::Fs { root } => backend::run(&Fs::new(root), opts, &mut |_, _| {})?,
+        StorageConfig::S3(cfg) => {
+            let s3 = S3::new(cfg).map_err(|e| e.to_string())?;
+            backend::run(&s3, opts, &mut |_, _| {})?
+        }
+    };
+
+    health::classify(&mut result, &config.warden.health);
+    let assessment = health::assess(&result, &config.warden.health);
+    Ok((result, assessment))
+}
@@ -0,0 +1,223 @@
+use crate::audit::AuditStats;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+
+type CountFn = fn(&AuditStats) -> u64;
+
+/// State of the watch loop, exposed in Prometheus text format.
+#[derive(Default)]
+struct State {
+    /// Counts of the last full and last incremental scan, kept separately so
+    /// a clean incremental cycle does not hide corruption found by the last full scan.
+    full: Option<AuditStats>,
+    incremental: Option<AuditStats>,
+    duration: Duration,
+    timestamp: u64,
+    full_scan: bool,
+    success: bool,
+    cycles: u64,
+}
+
+#[derive(Default, Clone)]
+pub struct Metrics {
+    state: Arc<Mutex<State>>,
+}
+
+impl Metrics {
+    pub fn record_success(
+        &self,
+        stats: &AuditStats,
+        duration: Duration,
+        timestamp: u64,
+        full_scan: bool,
+        cycles: u64,
+    ) {
+        if let Ok(mut state) = self.state.lock() {
+            if full_scan {
+                state.full = Some(stats.clone());
+            } else {
+                state.incremental = Some(stats.clone());
+            }
+            state.duration = duration;
+            state.timestamp = timestamp;
+            state.full_scan = full_scan;
+            state.success = true;
+            state.cycles = cycles;
+        }
+    }
+
+    /// A failed cycle keeps the previous counts but flips the success gauge,
+    /// so dashboards do not show a failing daemon as a clean database.
+    pub fn record_failure(&self, timestamp: u64, cycles: u64) {
+        if let Ok(mut state) = self.state.lock() {
+            state.timestamp = timestamp;
+            state.success = false;
+            state.cycles = cycles;
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let Ok(state) = self.state.lock() else {
+            return out;
+        };
+        if state.cycles == 0 {
+            return out;
+        }
+
+        let counts: [(&str, &str, CountFn); 4] = [
+            (
+                "octa_warden_assets_scanned",
+                "Assets scanned by the last audit of this scan type.",
+                |s| s.total_scanned,
+            ),
+            (
+                "octa_warden_assets_healthy",
+                "Assets that decoded successfully.",
+                |s| s.healthy,
+            ),
+            (
+                "octa_warden_assets_corrupted",
+                "Assets whose BLOB failed to decode.",
+                |s| s.corrupted_blob,
+            ),
+            (
+                "octa_warden_schema_errors",
+                "Rows with column type mismatches.",
+                |s| s.db_schema_error,
+            ),
+        ];
+
+        for (name, help, value) in counts {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            for (scan, stats) in [("full", &state.full), ("incremental", &state.incremental)] {
+                if let Some(stats) = stats {
+                    let _ = writeln!(out, "{}{{scan=\"{}\"}} {}", name, scan, value(stats));
+                }
+            }
+        }
+
+        let healthy = state.success
+            && state.full.as_ref().is_none_or(AuditStats::is_healthy)
+            && state
+                .incremental
+                .as_ref()
+                .is_none_or(AuditStats::is_healthy);
+
+        let gauges: [(&str, &str, f64); 6] = [
+            (
+                "octa_warden_last_run_duration_seconds",
+                "Wall time of the last audit cycle.",
+                state.duration.as_secs_f64(),
+            ),
+            (
+                "octa_warden_last_run_timestamp_seconds",
+                "Unix time the last audit cycle finished.",
+                state.timestamp as f64,
+            ),
+            (
+                "octa_warden_last_run_success",
+                "1 if the last audit cycle completed, 0 if it failed.",
+                bool_gauge(state.success),
+            ),
+            (
+                "octa_warden_last_run_full_scan",
+                "1 if the last audit cycle was a full scan, 0 if incremental.",
+                bool_gauge(state.full_scan),
+            ),
+            (
+                "octa_warden_healthy",
+                "1 if neither the last full nor the last incremental scan found problems.",
+                bool_gauge(healthy),
+            ),
+            (
+                "octa_warden_cycles_total",
+                "Audit cycles run since the state file was created.",
+                state.cycles as f64,
+            ),
+        ];
+
+        for (name, help, value) in gauges {
+            let kind = if name.ends_with("_total") {
+                "counter"
+            } else {
+                "gauge"
+            };
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+        out
+    }
+
+    /// Writes the metrics for node_exporter's textfile collector.
+    pub fn write_textfile(&self, path: &Path) {
+        write_textfile(path, &self.render());
+    }
+
+    /// Serves `GET /metrics` on a background thread.
+    pub fn serve(&self, addr: &str) -> std::io::Result<()> {
+        let metrics = self.clone();
+        serve(addr, move || metrics.render())
+    }
+}
+
+/// Serves `GET /metrics` on a background thread, answering with whatever
+/// `render` returns at the time of the request.
+pub fn serve(addr: &str, render: impl Fn() -> String + Send + 'static) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+
+            let mut buf = [0u8; 1024];
+            let n = stream.read(&mut buf).unwrap_or(0);
+            let request = String::from_utf8_lossy(&buf[..n]);
+
+            let (status, body) = if request.starts_with("GET /metrics") {
+                ("200 OK", render())
+            } else {
+                ("404 Not Found", "not found\n".to_string())
+            };
+
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+        }
+    });
+
+    Ok(())
+}
+
+/// Writes `body` for node_exporter's textfile collector, to a temp file
+/// renamed into place so the collector never reads a partial file.
+pub fn write_textfile(path: &Path, body: &str) {
+    let tmp = path.with_extension("prom.tmp");
+    let result = fs::write(&tmp, body).and_then(|_| fs::rename(&tmp, path));
+
+    if let Err(e) = result {
+        warn!(tag = "WARN", path = %path.display(), error = %e, "Could not write metrics file");
+    }
+}
+
+pub fn bool_gauge(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
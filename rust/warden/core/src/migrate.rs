@@ -0,0 +1,98 @@
+use crate::export::{asset_file_name, sha256_hex};
+use octa_storage::{Backend, Error};
+use std::collections::HashMap;
+use tracing::error;
+
+/// `key`, `path` of every migrated asset. Hidden, so no backend lists it
+/// as an asset; `import` reads it to restore the keys.
+pub const KEYS_FILE: &str = ".warden/keys.tsv";
+
+#[derive(Debug, Default)]
+pub struct MigrateSummary {
+    pub migrated: u64,
+    /// Already migrated by an earlier (interrupted) run.
+    pub resumed: u64,
+    pub failed: u64,
+    pub bytes: u64,
+}
+
+/// Writes healthy assets into `<shard>/<id>.<ext>` of a file or object
+/// store, where the shard is the first two characters of the ID, and
+/// verifies every one by reading it back and comparing SHA-256 hashes.
+///
+/// Names follow from the asset, so an interrupted migration resumes where
+/// it stopped: an asset already there with the same size is not written
+/// again.
+pub struct Migrator {
+    target: Box<dyn Backend>,
+    /// Asset ID -> name in the target, including earlier runs.
+    done: HashMap<String, String>,
+    summary: MigrateSummary,
+}
+
+impl Migrator {
+    pub fn new(target: Box<dyn Backend>) -> Self {
+        Self {
+            target,
+            done: HashMap::new(),
+            summary: MigrateSummary::default(),
+        }
+    }
+
+    pub fn add(&mut self, id: &str, blob: &[u8]) {
+        let (name, _) = asset_file_name(id, blob);
+        let shard: String = name.chars().take(2).collect::<String>().to_lowercase();
+        let relative = format!("{}/{}", shard, name);
+
+        if let Ok(Some(stat)) = self.target.stat(&relative) {
+            if stat.size == blob.len() as u64 {
+                self.summary.resumed += 1;
+                self.done.insert(id.to_string(), relative);
+                return;
+            }
+        }
+
+        match self.write(&relative, blob) {
+            Ok(()) => {
+                self.summary.migrated += 1;
+                self.summary.bytes += blob.len() as u64;
+                self.done.insert(id.to_string(), relative);
+            }
+            Err(e) => {
+                self.summary.failed += 1;
+                error!(tag = "ERROR", id, reason = %e, "Could not migrate asset");
+            }
+        }
+    }
+
+    fn write(&self, relative: &str, blob: &[u8]) -> Result<(), Error> {
+        self.target.write(relative, blob)?;
+
+        let expected = sha256_hex(blob);
+        let written = self.target.read(relative)?.unwrap_or_default();
+        if sha256_hex(&written) != expected {
+            let _ = self.target.delete(relative);
+            return Err(Error::Invalid("read-back hash does not match".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Writes [`KEYS_FILE`] (key, asset path) so the server-side key
+    /// mappings survive the move.
+    pub fn finish(self, keys: &HashMap<String, Vec<String>>) -> Result<MigrateSummary, Error> {
+        let mut rows: Vec<(&String, &String)> = keys
+            .iter()
+            .filter_map(|(id, keys)| Some((keys, self.done.get(id)?)))
+            .flat_map(|(keys, path)| keys.iter().map(move |k| (k, path)))
+            .collect();
+        rows.sort();
+
+        let mut out = String::from("key\tpath\n");
+        for (key, path) in rows {
+            out.push_str(&format!("{}\t{}\n", key, path));
+        }
+        self.target.write(KEYS_FILE, out.as_bytes())?;
+
+        Ok(self.summary)
+    }
+}
@@ -0,0 +1,187 @@
+use crate::audit::{AuditResult, FindingKind, Severity};
+use crate::history::FindingsDiff;
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// `warden.notify` section of config.yaml.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifyConfig {
+    /// Generic endpoint receiving the full JSON summary.
+    pub webhook_url: Option<String>,
+    /// Slack incoming-webhook URL receiving a formatted message.
+    pub slack_webhook_url: Option<String>,
+    /// Lowest finding severity that triggers a notification ("warning" or "critical").
+    pub min_severity: Severity,
+    /// Maximum number of asset IDs listed in a notification.
+    pub max_ids: usize,
+    /// Stay silent unless the run found something not seen by previous audits
+    /// (needs history; without it every qualifying run notifies).
+    pub only_new: bool,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            webhook_url: None,
+            slack_webhook_url: None,
+            min_severity: Severity::Warning,
+            max_ids: 50,
+            only_new: false,
+        }
+    }
+}
+
+impl NotifyConfig {
+    fn is_enabled(&self) -> bool {
+        self.webhook_url.is_some() || self.slack_webhook_url.is_some()
+    }
+}
+
+/// Posts a summary of the audit to the configured targets when its findings
+/// reach the severity threshold. Delivery failures are logged, never fatal.
+pub fn dispatch(
+    cfg: &NotifyConfig,
+    result: &AuditResult,
+    diff: Option<&FindingsDiff>,
+    context: &str,
+) {
+    if !cfg.is_enabled() {
+        return;
+    }
+    match result.max_severity() {
+        Some(severity) if severity >= cfg.min_severity => {}
+        _ => return,
+    }
+    if cfg.only_new && diff.is_some_and(|d| d.new.is_empty()) {
+        return;
+    }
+    let new_ids: Vec<&str> = diff
+        .map(|d| {
+            d.new
+                .iter()
+                .filter_map(|f| f.id.as_deref())
+                .take(cfg.max_ids)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let stats = &result.stats;
+    let corrupted_ids: Vec<&str> = result
+        .findings
+        .iter()
+        .filter(|f| f.kind == FindingKind::CorruptBlob)
+        .filter_map(|f| f.id.as_deref())
+        .take(cfg.max_ids)
+        .collect();
+    let severity = result.max_severity().map(|s| s.as_str()).unwrap_or("none");
+    let host = hostname();
+
+    if let Some(url) = &cfg.webhook_url {
+        let payload = json!({
+            "source": "octa-warden",
+            "host": host,
+            "context": context,
+            "status": "ATTENTION REQUIRED",
+            "severity": severity,
+            "stats": {
+                "scanned": stats.total_scanned,
+                "healthy": stats.healthy,
+                "corrupted_blobs": stats.corrupted_blob,
+                "schema_errors": stats.db_schema_error,
+            },
+            "corrupted_ids": corrupted_ids,
+            "new_ids": new_ids,
+            "new_count": diff.map(|d| d.new.len()),
+            "known_count": diff.map(|d| d.known),
+            "resolved_count": diff.map(|d| d.resolved.len()),
+            "findings": result.findings.iter().take(cfg.max_ids).map(|f| json!({
+                "id": f.id,
+                "kind": f.kind.as_str(),
+                "severity": f.severity.as_str(),
+                "reason": f.reason,
+                "column": f.column,
+            })).collect::<Vec<_>>(),
+            "corrupted_ids_truncated": stats.corrupted_blob as usize > corrupted_ids.len(),
+        });
+        post(url, &payload, "webhook");
+    }
+
+    if let Some(url) = &cfg.slack_webhook_url {
+        let mut text = format!(
+            ":rotating_light: *Octa Warden: ATTENTION REQUIRED* ({}) on `{}` — {}\n\
+             Scanned: {} | Healthy: {} | Corrupted: *{}* | Schema errors: *{}*",
+            severity,
+            host,
+            context,
+            stats.total_scanned,
+            stats.healthy,
+            stats.corrupted_blob,
+            stats.db_schema_error
+        );
+        if let Some(d) = diff {
+            text.push_str(&format!(
+                "\nNew since last audit: *{}* | Known: {} | Resolved: {}",
+                d.new.len(),
+                d.known,
+                d.resolved.len()
+            ));
+            if !new_ids.is_empty() {
+                text.push_str("\nNew IDs: ");
+                text.push_str(
+                    &new_ids
+                        .iter()
+                        .map(|id| format!("`{}`", id))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                );
+            }
+        }
+        if !corrupted_ids.is_empty() {
+            text.push_str("\nCorrupted IDs: ");
+            text.push_str(
+                &corrupted_ids
+                    .iter()
+                    .map(|id| format!("`{}`", id))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            );
+            if stats.corrupted_blob as usize > corrupted_ids.len() {
+                text.push_str(&format!(
+                    " …and {} more",
+                    stats.corrupted_blob as usize - corrupted_ids.len()
+                ));
+            }
+        }
+        post(url, &json!({ "text": text }), "Slack");
+    }
+}
+
+/// POSTs `payload` as JSON; `target` names the receiver in the log. Failures
+/// are logged, never returned.
+pub fn post(url: &str, payload: &serde_json::Value, target: &str) {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(10)))
+        .build()
+        .into();
+
+    match agent
+        .post(url)
+        .header("Content-Type", "application/json")
+        .send(payload.to_string())
+    {
+        Ok(_) => info!(tag = "NOTIFY", target, "Notification sent"),
+        Err(e) => warn!(tag = "WARN", target, error = %e, "Failed to send notification"),
+    }
+}
+
+pub fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "unknown-host".to_string())
+}
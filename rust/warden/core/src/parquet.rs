@@ -0,0 +1,390 @@
+//! A minimal Parquet writer: flat schemas of strings, integers, doubles
+//! and UTC timestamps, each column one zstd-compressed PLAIN data page per
+//! row group. Enough for DuckDB, Spark and pandas to read the exports
+//! without pulling the Arrow stack into Warden.
+//!
+//! The footer is Thrift's compact protocol, written by hand below; see
+//! <https://github.com/apache/parquet-format> for the structures.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Rows buffered before a row group is written out.
+const ROW_GROUP_ROWS: usize = 100_000;
+
+/// zstd level of the data pages.
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    /// UTF-8 text (`BYTE_ARRAY` / `UTF8`).
+    Text,
+    /// `INT64`.
+    Int,
+    /// `DOUBLE`.
+    Double,
+    /// Microseconds since the Unix epoch, UTC (`INT64` / `TIMESTAMP_MICROS`).
+    Timestamp,
+}
+
+/// One column of the schema. Every column is nullable.
+#[derive(Debug, Clone, Copy)]
+pub struct Column {
+    pub name: &'static str,
+    pub kind: ColumnType,
+}
+
+impl Column {
+    pub const fn new(name: &'static str, kind: ColumnType) -> Self {
+        Self { name, kind }
+    }
+}
+
+/// One value of a row, in schema order.
+#[derive(Debug, Clone, Copy)]
+pub enum Cell<'a> {
+    Null,
+    Text(&'a str),
+    /// For [`ColumnType::Int`] and [`ColumnType::Timestamp`].
+    Int(i64),
+    Double(f64),
+}
+
+#[derive(Default)]
+struct Buffer {
+    /// Definition level of every row: present or null.
+    present: Vec<bool>,
+    /// PLAIN-encoded present values.
+    values: Vec<u8>,
+}
+
+struct ChunkMeta {
+    offset: u64,
+    uncompressed: u64,
+    compressed: u64,
+    values: usize,
+}
+
+struct GroupMeta {
+    rows: usize,
+    chunks: Vec<ChunkMeta>,
+}
+
+/// Streams rows into a Parquet file, a row group at a time.
+pub struct Writer {
+    out: BufWriter<File>,
+    offset: u64,
+    columns: Vec<Column>,
+    buffers: Vec<Buffer>,
+    rows: usize,
+    groups: Vec<GroupMeta>,
+}
+
+impl Writer {
+    pub fn create(path: &Path, columns: &[Column]) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(b"PAR1")?;
+        Ok(Self {
+            out,
+            offset: 4,
+            columns: columns.to_vec(),
+            buffers: columns.iter().map(|_| Buffer::default()).collect(),
+            rows: 0,
+            groups: Vec::new(),
+        })
+    }
+
+    /// Appends one row; a cell that does not fit its column is an error.
+    pub fn push(&mut self, row: &[Cell]) -> io::Result<()> {
+        if row.len() != self.columns.len() {
+            return Err(invalid(format!(
+                "row has {} cells, the schema {} columns",
+                row.len(),
+                self.columns.len()
+            )));
+        }
+        for ((column, buffer), cell) in self.columns.iter().zip(&mut self.buffers).zip(row) {
+            match (column.kind, cell) {
+                (_, Cell::Null) => {}
+                (ColumnType::Text, Cell::Text(text)) => {
+                    buffer
+                        .values
+                        .extend_from_slice(&(text.len() as u32).to_le_bytes());
+                    buffer.values.extend_from_slice(text.as_bytes());
+                }
+                (ColumnType::Int | ColumnType::Timestamp, Cell::Int(n)) => {
+                    buffer.values.extend_from_slice(&n.to_le_bytes())
+                }
+                (ColumnType::Double, Cell::Double(x)) => {
+                    buffer.values.extend_from_slice(&x.to_le_bytes())
+                }
+                (kind, cell) => {
+                    return Err(invalid(format!(
+                        "column {} is {:?}, got {:?}",
+                        column.name, kind, cell
+                    )))
+                }
+            }
+            buffer.present.push(!matches!(cell, Cell::Null));
+        }
+        self.rows += 1;
+        if self.rows == ROW_GROUP_ROWS {
+            self.flush_group()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the last row group and the footer. Returns the rows written.
+    pub fn finish(mut self) -> io::Result<usize> {
+        if self.rows > 0 || self.groups.is_empty() {
+            self.flush_group()?;
+        }
+        let footer = self.footer();
+        self.out.write_all(&footer)?;
+        self.out.write_all(&(footer.len() as u32).to_le_bytes())?;
+        self.out.write_all(b"PAR1")?;
+        self.out
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        Ok(self.groups.iter().map(|g| g.rows).sum())
+    }
+
+    fn flush_group(&mut self) -> io::Result<()> {
+        let mut chunks = Vec::with_capacity(self.buffers.len());
+        for buffer in &mut self.buffers {
+            let mut page = Vec::new();
+            let levels = bit_packed(&buffer.present);
+            page.extend_from_slice(&(levels.len() as u32).to_le_bytes());
+            page.extend_from_slice(&levels);
+            page.extend_from_slice(&buffer.values);
+            let compressed = zstd::bulk::compress(&page, ZSTD_LEVEL)?;
+
+            let mut header = Compact::default();
+            header.i32(1, 0); // type: DATA_PAGE
+            header.i32(2, page.len() as i32);
+            header.i32(3, compressed.len() as i32);
+            header.begin(5); // data_page_header
+            header.i32(1, buffer.present.len() as i32);
+            header.i32(2, 0); // encoding: PLAIN
+            header.i32(3, 3); // definition levels: RLE
+            header.i32(4, 3); // repetition levels: RLE
+            header.end();
+            header.stop();
+
+            let offset = self.offset;
+            self.out.write_all(&header.buf)?;
+            self.out.write_all(&compressed)?;
+            self.offset += (header.buf.len() + compressed.len()) as u64;
+            chunks.push(ChunkMeta {
+                offset,
+                uncompressed: (header.buf.len() + page.len()) as u64,
+                compressed: (header.buf.len() + compressed.len()) as u64,
+                values: buffer.present.len(),
+            });
+            *buffer = Buffer::default();
+        }
+        self.groups.push(GroupMeta {
+            rows: self.rows,
+            chunks,
+        });
+        self.rows = 0;
+        Ok(())
+    }
+
+    /// `FileMetaData`.
+    fn footer(&self) -> Vec<u8> {
+        let mut t = Compact::default();
+        t.i32(1, 1); // version
+        t.list(2, self.columns.len() + 1);
+        // The root of the schema, then one leaf per column.
+        t.element();
+        t.binary(4, b"schema");
+        t.i32(5, self.columns.len() as i32);
+        t.end();
+        for column in &self.columns {
+            t.element();
+            t.i32(1, physical_type(column.kind));
+            t.i32(3, 1); // repetition: OPTIONAL
+            t.binary(4, column.name.as_bytes());
+            match column.kind {
+                ColumnType::Text => t.i32(6, 0),       // UTF8
+                ColumnType::Timestamp => t.i32(6, 10), // TIMESTAMP_MICROS
+                ColumnType::Int | ColumnType::Double => {}
+            }
+            t.end();
+        }
+        t.i64(3, self.groups.iter().map(|g| g.rows as i64).sum());
+        t.list(4, self.groups.len());
+        for group in &self.groups {
+            t.element();
+            t.list(1, group.chunks.len());
+            for (column, chunk) in self.columns.iter().zip(&group.chunks) {
+                t.element();
+                t.i64(2, chunk.offset as i64); // file_offset
+                t.begin(3); // meta_data
+                t.i32(1, physical_type(column.kind));
+                t.i32_list(2, &[0, 3]); // PLAIN, RLE
+                t.string_list(3, &[column.name]);
+                t.i32(4, 6); // codec: ZSTD
+                t.i64(5, chunk.values as i64);
+                t.i64(6, chunk.uncompressed as i64);
+                t.i64(7, chunk.compressed as i64);
+                t.i64(9, chunk.offset as i64);
+                t.end();
+                t.end();
+            }
+            t.i64(2, group.chunks.iter().map(|c| c.uncompressed as i64).sum());
+            t.i64(3, group.rows as i64);
+            t.end();
+        }
+        t.binary(
+            6,
+            concat!("octa-warden ", env!("CARGO_PKG_VERSION")).as_bytes(),
+        );
+        t.stop();
+        t.buf
+    }
+}
+
+fn physical_type(kind: ColumnType) -> i32 {
+    match kind {
+        ColumnType::Text => 6,                        // BYTE_ARRAY
+        ColumnType::Int | ColumnType::Timestamp => 2, // INT64
+        ColumnType::Double => 5,                      // DOUBLE
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Definition levels (bit width 1) as one bit-packed run of the RLE/bit
+/// packing hybrid, padded to a multiple of eight values.
+fn bit_packed(bits: &[bool]) -> Vec<u8> {
+    let groups = bits.len().div_ceil(8);
+    let mut out = Vec::with_capacity(groups + 5);
+    varint(&mut out, ((groups as u64) << 1) | 1);
+    for group in bits.chunks(8) {
+        let byte = group
+            .iter()
+            .enumerate()
+            .fold(0u8, |byte, (i, &bit)| byte | (u8::from(bit) << i));
+        out.push(byte);
+    }
+    out
+}
+
+fn varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+/// Thrift compact protocol, as much of it as the footer needs.
+#[derive(Default)]
+struct Compact {
+    buf: Vec<u8>,
+    /// Last field id of each open struct; field headers are deltas.
+    last: Vec<i16>,
+    field: i16,
+}
+
+impl Compact {
+    const I32: u8 = 5;
+    const I64: u8 = 6;
+    const BINARY: u8 = 8;
+    const LIST: u8 = 9;
+    const STRUCT: u8 = 12;
+
+    fn header(&mut self, id: i16, kind: u8) {
+        let delta = id - self.field;
+        if (1..=15).contains(&delta) {
+            self.buf.push(((delta as u8) << 4) | kind);
+        } else {
+            self.buf.push(kind);
+            varint(&mut self.buf, zigzag(id as i64));
+        }
+        self.field = id;
+    }
+
+    fn i32(&mut self, id: i16, n: i32) {
+        self.header(id, Self::I32);
+        varint(&mut self.buf, zigzag(n as i64));
+    }
+
+    fn i64(&mut self, id: i16, n: i64) {
+        self.header(id, Self::I64);
+        varint(&mut self.buf, zigzag(n));
+    }
+
+    fn binary(&mut self, id: i16, bytes: &[u8]) {
+        self.header(id, Self::BINARY);
+        self.raw_binary(bytes);
+    }
+
+    fn raw_binary(&mut self, bytes: &[u8]) {
+        varint(&mut self.buf, bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn list_header(&mut self, id: i16, len: usize, kind: u8) {
+        self.header(id, Self::LIST);
+        if len < 15 {
+            self.buf.push(((len as u8) << 4) | kind);
+        } else {
+            self.buf.push(0xF0 | kind);
+            varint(&mut self.buf, len as u64);
+        }
+    }
+
+    /// A list of structs: each element between [`element`](Self::element)
+    /// and [`end`](Self::end).
+    fn list(&mut self, id: i16, len: usize) {
+        self.list_header(id, len, Self::STRUCT);
+    }
+
+    fn i32_list(&mut self, id: i16, values: &[i32]) {
+        self.list_header(id, values.len(), Self::I32);
+        for &n in values {
+            varint(&mut self.buf, zigzag(n as i64));
+        }
+    }
+
+    fn string_list(&mut self, id: i16, values: &[&str]) {
+        self.list_header(id, values.len(), Self::BINARY);
+        for value in values {
+            self.raw_binary(value.as_bytes());
+        }
+    }
+
+    /// Opens a struct field.
+    fn begin(&mut self, id: i16) {
+        self.header(id, Self::STRUCT);
+        self.element();
+    }
+
+    /// Opens a struct in a list; structs number their fields afresh.
+    fn element(&mut self) {
+        self.last.push(self.field);
+        self.field = 0;
+    }
+
+    /// Closes the struct opened last.
+    fn end(&mut self) {
+        self.buf.push(0);
+        self.field = self.last.pop().unwrap_or(0);
+    }
+
+    /// Ends the top-level struct.
+    fn stop(&mut self) {
+        self.buf.push(0);
+    }
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
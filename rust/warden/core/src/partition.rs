@@ -0,0 +1,236 @@
+use crate::audit::{Batches, Inspected, Parallel, RawRow, RowQuery, Tally, WorkerRole, WorkerStats};
+use crate::db;
+use rusqlite::Result;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, Span};
+
+/// Live counters of one worker. Atomic, so they can be read while the scan runs.
+struct Counters {
+    role: WorkerRole,
+    reader: usize,
+    index: usize,
+    rows: AtomicU64,
+    bytes: AtomicU64,
+    busy_ns: AtomicU64,
+    waiting_ns: AtomicU64,
+}
+
+impl Counters {
+    fn new(role: WorkerRole, reader: usize, index: usize) -> Self {
+        Self {
+            role,
+            reader,
+            index,
+            rows: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            busy_ns: AtomicU64::new(0),
+            waiting_ns: AtomicU64::new(0),
+        }
+    }
+
+    fn busy(&self, since: Instant) {
+        self.busy_ns
+            .fetch_add(since.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    fn waiting(&self, since: Instant) {
+        self.waiting_ns
+            .fetch_add(since.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    fn row(&self, bytes: u64) {
+        self.rows.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> WorkerStats {
+        WorkerStats {
+            role: self.role,
+            reader: self.reader,
+            index: self.index,
+            rows: self.rows.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            busy: Duration::from_nanos(self.busy_ns.load(Ordering::Relaxed)),
+            waiting: Duration::from_nanos(self.waiting_ns.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Splits `images` into contiguous rowid ranges, one per reader. Each reader
+/// has its own read-only connection and its own decode workers, so reading
+/// and decoding overlap across the whole machine. Findings reach the tally in
+/// completion order, not rowid order (or the requested `--order`).
+/// Returns what every worker did, readers first.
+pub fn scan(
+    path: &str,
+    parallel: &Parallel,
+    query: &RowQuery,
+    tally: &mut Tally,
+) -> Result<Vec<WorkerStats>> {
+    let conn = db::open_read_only(path, &parallel.open)?;
+    let bounds: (Option<i64>, Option<i64>) =
+        conn.query_row("SELECT MIN(rowid), MAX(rowid) FROM images", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+    drop(conn);
+    let (Some(min), Some(max)) = bounds else {
+        return Ok(Vec::new());
+    };
+
+    let mut ranges = split(min, max, parallel.readers);
+    if query.newest_first() {
+        // Start the newest range first; each reader also walks its range newest first.
+        ranges.reverse();
+    }
+    let jobs = parallel
+        .jobs
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+    let decoders = (jobs / ranges.len()).max(1);
+    info!(
+        tag = "→",
+        readers = ranges.len(),
+        decoders_per_reader = decoders,
+        "Partitioned scan"
+    );
+
+    let readers: Vec<Counters> = (0..ranges.len())
+        .map(|r| Counters::new(WorkerRole::Reader, r, 0))
+        .collect();
+    let workers: Vec<Vec<Counters>> = (0..ranges.len())
+        .map(|r| {
+            (0..decoders)
+                .map(|d| Counters::new(WorkerRole::Decoder, r, d))
+                .collect()
+        })
+        .collect();
+
+    let stop = AtomicBool::new(false);
+    let (tx, rx) = mpsc::sync_channel(ranges.len() * decoders * 2);
+    // The scan's span, for the readers' batches on their own threads.
+    let parent = Span::current();
+
+    thread::scope(|scope| {
+        for (r, range) in ranges.into_iter().enumerate() {
+            // Bounded, so a slow decoder holds back its reader instead of buffering the table.
+            let (raw_tx, raw_rx) = mpsc::sync_channel::<Result<RawRow>>(decoders * 2);
+            let raw_rx = Arc::new(Mutex::new(raw_rx));
+            for counters in &workers[r] {
+                let (tx, raw_rx, stop) = (tx.clone(), Arc::clone(&raw_rx), &stop);
+                scope.spawn(move || decode(&raw_rx, &tx, stop, counters));
+            }
+
+            let (tx, stop, counters, parent) = (tx.clone(), &stop, &readers[r], &parent);
+            scope.spawn(move || {
+                let _entered = parent.enter();
+                if let Err(e) = read(path, parallel, query, range, &raw_tx, stop, counters) {
+                    let _ = tx.send(Err(e));
+                }
+            });
+        }
+        drop(tx);
+
+        for item in rx {
+            match item {
+                Ok(inspected) => tally.apply(inspected),
+                Err(e) => tally.row_failure(None, e),
+            }
+
+            if tally.should_stop() {
+                stop.store(true, Ordering::Relaxed);
+                break;
+            }
+        }
+    });
+
+    Ok(readers
+        .iter()
+        .chain(workers.iter().flatten())
+        .map(Counters::snapshot)
+        .collect())
+}
+
+/// `readers` contiguous, inclusive ranges covering `min..=max`.
+fn split(min: i64, max: i64, readers: usize) -> Vec<(i64, i64)> {
+    let span = (max as i128 - min as i128 + 1) as u128;
+    let readers = (readers as u128).clamp(1, span);
+    let chunk = span.div_ceil(readers);
+    (0..readers)
+        .map(|i| {
+            let from = min as i128 + (i * chunk) as i128;
+            let to = (from + chunk as i128 - 1).min(max as i128);
+            (from as i64, to as i64)
+        })
+        .filter(|(from, to)| from <= to)
+        .collect()
+}
+
+fn read(
+    path: &str,
+    parallel: &Parallel,
+    query: &RowQuery,
+    range: (i64, i64),
+    raw_tx: &mpsc::SyncSender<Result<RawRow>>,
+    stop: &AtomicBool,
+    counters: &Counters,
+) -> Result<()> {
+    let mut batches = Batches::new(&Span::current(), counters.reader);
+    let conn = db::open_read_only(path, &parallel.open)?;
+    let mut stmt = conn.prepare(&query.sql(Some(range)))?;
+    let mut rows = stmt.query_map(query.params(Some(range)), |row| Ok(query.read(row)))?;
+    loop {
+        let started = Instant::now();
+        let Some(mut row) = rows.next() else {
+            break;
+        };
+        batches.row();
+        counters.busy(started);
+        if let Ok(raw) = &mut row {
+            raw.record_read(started.elapsed());
+            counters.row(raw.bytes());
+        }
+
+        let started = Instant::now();
+        if stop.load(Ordering::Relaxed) || raw_tx.send(row).is_err() {
+            break;
+        }
+        counters.waiting(started);
+    }
+    Ok(())
+}
+
+fn decode(
+    raw_rx: &Mutex<Receiver<Result<RawRow>>>,
+    tx: &mpsc::SyncSender<Result<Inspected>>,
+    stop: &AtomicBool,
+    counters: &Counters,
+) {
+    loop {
+        // Hold the lock only while waiting, so the siblings decode in parallel.
+        let started = Instant::now();
+        let next = raw_rx.lock().expect("decoder panicked").recv();
+        counters.waiting(started);
+        let Ok(row) = next else {
+            break;
+        };
+        // Keep draining after a stop, so the reader is never stuck on a full channel.
+        if stop.load(Ordering::Relaxed) {
+            continue;
+        }
+
+        let started = Instant::now();
+        let bytes = row.as_ref().map_or(0, RawRow::bytes);
+        let inspected = row.map(RawRow::inspect);
+        counters.busy(started);
+        counters.row(bytes);
+
+        let started = Instant::now();
+        if tx.send(inspected).is_err() {
+            stop.store(true, Ordering::Relaxed);
+        }
+        counters.waiting(started);
+    }
+}
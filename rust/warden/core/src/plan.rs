@@ -0,0 +1,201 @@
+use crate::db::{self, OpenOptions};
+use chrono::Local;
+use image::load_from_memory;
+use octa_store::cas;
+use rusqlite::{params, Connection, OptionalExtension, Result, Transaction};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use tracing::{error, info, warn};
+
+/// What to do with a damaged asset. Chosen interactively in `triage`,
+/// executed by `triage --apply`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Move the row (and its keys) into the `quarantine` table.
+    Quarantine,
+    /// Remove the image and its key mappings, like the Console delete does.
+    Delete,
+    /// Rewrite the column in place when the data itself is intact.
+    Repair,
+}
+
+impl Action {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::Quarantine => "quarantine",
+            Action::Delete => "delete",
+            Action::Repair => "repair",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanEntry {
+    pub id: String,
+    pub action: Action,
+    /// The finding that led to the decision, kept for review.
+    pub kind: String,
+    pub reason: String,
+}
+
+/// Reviewable list of actions, written as JSON so it can be checked into a
+/// ticket or edited by hand before it is applied.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plan {
+    pub database: String,
+    pub created_at: String,
+    pub entries: Vec<PlanEntry>,
+}
+
+impl Plan {
+    pub fn new(database: &str, entries: Vec<PlanEntry>) -> Self {
+        Self {
+            database: database.to_string(),
+            created_at: Local::now().to_rfc3339(),
+            entries,
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let raw = fs::read_to_string(path)?;
+        serde_json::from_str(&raw).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let raw = serde_json::to_string_pretty(self)?;
+        fs::write(path, raw)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ApplySummary {
+    pub applied: u64,
+    pub skipped: u64,
+    /// Keys of the assets acted on, read before they were moved or deleted.
+    pub keys: Vec<String>,
+}
+
+/// Executes the plan against the live database, one transaction per asset,
+/// so a failing entry never leaves an image half-moved.
+pub fn apply(db_path: &str, opts: &OpenOptions, plan: &Plan) -> Result<ApplySummary> {
+    if plan.database != db_path {
+        warn!(
+            tag = "WARN",
+            plan = %plan.database,
+            database = %db_path,
+            "Plan was created for a different database path"
+        );
+    }
+
+    let mut conn = db::open_read_write(db_path, opts)?;
+    ensure_quarantine_table(&conn)?;
+
+    let mut summary = ApplySummary::default();
+    for entry in &plan.entries {
+        let tx = conn.transaction()?;
+        let keys = keys_of(&tx, &entry.id)?;
+        let outcome = match entry.action {
+            Action::Quarantine => quarantine(&tx, entry),
+            Action::Delete => {
+                octa_store::delete(&tx, &entry.id).map(|removed| removed.then_some(()))
+            }
+            Action::Repair => repair(&tx, &entry.id),
+        };
+
+        match outcome {
+            Ok(Some(())) => {
+                tx.commit()?;
+                summary.applied += 1;
+                summary.keys.extend(keys);
+                info!(tag = "APPLY", id = %entry.id, action = entry.action.as_str(), "Action applied");
+            }
+            Ok(None) => {
+                summary.skipped += 1;
+                warn!(tag = "SKIP", id = %entry.id, action = entry.action.as_str(), "Nothing to do (asset missing or not repairable)");
+            }
+            Err(e) => {
+                summary.skipped += 1;
+                error!(tag = "ERROR", id = %entry.id, action = entry.action.as_str(), reason = %e, "Action failed");
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+fn ensure_quarantine_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS quarantine (
+            id             TEXT PRIMARY KEY,
+            data           BLOB,
+            width          INTEGER,
+            height         INTEGER,
+            format         TEXT,
+            size           INTEGER,
+            updated_at     DATETIME,
+            created_at     DATETIME,
+            keys           TEXT NOT NULL,
+            reason         TEXT NOT NULL,
+            quarantined_at DATETIME NOT NULL
+        );",
+    )
+}
+
+fn keys_of(tx: &Transaction, id: &str) -> Result<Vec<String>> {
+    tx.prepare("SELECT key FROM key_mappings WHERE image_id = ?1")?
+        .query_map([id], |row| row.get(0))?
+        .collect()
+}
+
+fn quarantine(tx: &Transaction, entry: &PlanEntry) -> Result<Option<()>> {
+    let keys = keys_of(tx, &entry.id)?;
+
+    // The bytes themselves: a content-addressed asset's blob is released below.
+    let moved = tx.execute(
+        &format!(
+            "INSERT OR REPLACE INTO quarantine
+                (id, data, width, height, format, size, updated_at, created_at, keys, reason, quarantined_at)
+             SELECT id, {}, width, height, format, size, updated_at, created_at, ?2, ?3, datetime('now')
+             FROM images WHERE id = ?1",
+            cas::data(tx)?
+        ),
+        params![entry.id, keys.join(","), entry.reason],
+    )?;
+    if moved == 0 {
+        return Ok(None);
+    }
+
+    octa_store::delete(tx, &entry.id)?;
+    Ok(Some(()))
+}
+
+/// Only fixes what can be fixed without guessing: image bytes stored with the
+/// wrong column type (TEXT instead of BLOB). Undecodable data is left alone.
+fn repair(tx: &Transaction, id: &str) -> Result<Option<()>> {
+    let data = cas::data(tx)?;
+    let row: Option<(String, Vec<u8>)> = tx
+        .query_row(
+            &format!("SELECT typeof({data}), CAST({data} AS BLOB) FROM images WHERE id = ?1"),
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    let bytes = match row {
+        Some((kind, bytes)) if kind == "text" => bytes,
+        _ => return Ok(None),
+    };
+    if load_from_memory(&bytes).is_err() {
+        return Ok(None);
+    }
+
+    cas::rewrite(tx, id, &bytes)?;
+    tx.execute(
+        "UPDATE images SET size = ?2 WHERE id = ?1",
+        params![id, bytes.len() as i64],
+    )?;
+    Ok(Some(()))
+}
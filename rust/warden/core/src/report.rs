@@ -0,0 +1,456 @@
+use crate::audit::{AuditResult, AuditStats, WorkerRole, WorkerStats};
+use crate::cause;
+use crate::compression::Survey;
+use crate::growth;
+use crate::health::{Assessment, Verdict};
+use crate::history::FindingsDiff;
+use console::style;
+use octa_image::stages::Timings;
+use octa_logging::FINDING_TARGET;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+pub fn render_report(result: &AuditResult, duration: Duration, assessment: &Assessment) {
+    let stats = &result.stats;
+    let perf = &result.performance;
+
+    // Log pipelines get the report as one structured event instead of a table.
+    if !octa_logging::is_human() {
+        for (stage, s) in perf.stages.iter().flat_map(Timings::ran) {
+            info!(
+                tag = "STAGE",
+                stage = stage.as_str(),
+                assets = s.count,
+                total_ms = s.total.as_millis() as u64,
+                mean_us = s.mean().as_micros() as u64,
+                p50_us = s.percentile(0.5).as_micros() as u64,
+                p95_us = s.percentile(0.95).as_micros() as u64,
+                max_us = s.max.as_micros() as u64,
+                "Stage summary"
+            );
+        }
+        for w in &result.workers {
+            info!(
+                tag = "WORKER",
+                role = w.role.as_str(),
+                reader = w.reader,
+                index = w.index,
+                rows = w.rows,
+                bytes = w.bytes,
+                busy_ms = w.busy.as_millis() as u64,
+                waiting_ms = w.waiting.as_millis() as u64,
+                "Worker summary"
+            );
+        }
+        info!(
+            tag = "REPORT",
+            elapsed_ms = duration.as_millis() as u64,
+            scanned = stats.total_scanned,
+            healthy = stats.healthy,
+            corrupted_blobs = stats.corrupted_blob,
+            schema_errors = stats.db_schema_error,
+            derived_issues = stats.derived_issues,
+            processing_errors = stats.processing_errors,
+            base64_blobs = stats.base64_blobs,
+            decrypt_failures = stats.decrypt_failures,
+            color_issues = stats.color_issues,
+            timestamp_issues = stats.timestamp_issues,
+            corrupt_causes = %causes_summary(stats),
+            likely_origin = cause::likely_origin(&stats.corrupt_causes),
+            status = assessment.verdict.label(),
+            aborted = result.aborted.is_some(),
+            reasons = %assessment.reasons.join("; "),
+            bottleneck = bottleneck(&result.workers),
+            rows_per_sec = perf.rows_per_sec(stats.total_scanned, duration),
+            mb_per_sec = perf.mb_per_sec(duration),
+            bytes_read = perf.bytes_read,
+            decode_ms = perf.decoding.as_millis() as u64,
+            io_wait_ms = perf.io_wait.as_millis() as u64,
+            peak_memory_bytes = perf.peak_memory,
+            "Warden audit report"
+        );
+        return;
+    }
+
+    print!("{}", text_report(result, duration, assessment));
+}
+
+/// The console report as text, styled for the terminal. Strip it with
+/// `console::strip_ansi_codes` before writing it to a file.
+pub fn text_report(result: &AuditResult, duration: Duration, assessment: &Assessment) -> String {
+    let stats = &result.stats;
+    let mut lines = vec![
+        String::new(),
+        style("WARDEN AUDIT REPORT").bold().underlined().to_string(),
+        format!("Time Elapsed   : {:?}", duration),
+        format!("Assets Scanned : {}", stats.total_scanned),
+    ];
+    if let Some(reason) = &result.aborted {
+        lines.push(format!(
+            "Scan Aborted   : {}",
+            style(format!("WIDESPREAD CORRUPTION DETECTED ({})", reason))
+                .red()
+                .bold()
+        ));
+    }
+    lines.push("--------------------------------".to_string());
+    lines.push(format!("Healthy Assets : {}", style(stats.healthy).green()));
+
+    if stats.corrupted_blob > 0 {
+        lines.push(format!(
+            "Corrupted Blobs: {}",
+            style(stats.corrupted_blob).red().bold()
+        ));
+        for (cause, count) in &stats.corrupt_causes {
+            lines.push(format!("  {:<13}: {}", cause.as_str(), count));
+        }
+        if let Some(origin) = cause::likely_origin(&stats.corrupt_causes) {
+            lines.push(format!(
+                "  {:<13}: {}",
+                "likely origin",
+                style(origin).bold()
+            ));
+        }
+    } else {
+        lines.push(format!("Corrupted Blobs: {}", style("0").dim()));
+    }
+
+    if stats.db_schema_error > 0 {
+        lines.push(format!(
+            "Schema Errors  : {}",
+            style(stats.db_schema_error).magenta().bold()
+        ));
+    } else {
+        lines.push(format!("Schema Errors  : {}", style("0").dim()));
+    }
+
+    if stats.decrypt_failures > 0 {
+        lines.push(format!(
+            "Decrypt Fails  : {}",
+            style(stats.decrypt_failures).red().bold()
+        ));
+        // Nothing decrypting at all points at the key, not at the data.
+        if stats.healthy == 0 && stats.corrupted_blob == 0 {
+            lines.push(format!(
+                "  {}",
+                style("every asset failed: check warden.encryption and the key").red()
+            ));
+        }
+    }
+
+    if stats.processing_errors > 0 {
+        lines.push(format!(
+            "Processing Bugs: {}",
+            style(stats.processing_errors).yellow().bold()
+        ));
+    }
+
+    if stats.base64_blobs > 0 {
+        lines.push(format!(
+            "Base64 Blobs   : {}",
+            style(stats.base64_blobs).yellow().bold()
+        ));
+    }
+
+    if stats.color_issues > 0 {
+        lines.push(format!(
+            "Color Profiles : {}",
+            style(stats.color_issues).yellow().bold()
+        ));
+    }
+
+    if stats.timestamp_issues > 0 {
+        lines.push(format!(
+            "Timestamps     : {}",
+            style(stats.timestamp_issues).yellow().bold()
+        ));
+    }
+
+    if stats.derived_issues > 0 {
+        lines.push(format!(
+            "Derived Issues : {}",
+            style(stats.derived_issues).yellow().bold()
+        ));
+    }
+
+    render_performance(&mut lines, result, duration);
+    render_workers(&mut lines, &result.workers);
+    lines.push("--------------------------------".to_string());
+
+    let status = assessment.verdict.label();
+    let status = match assessment.verdict {
+        Verdict::Healthy => style(status).green(),
+        Verdict::Warning => style(status).yellow(),
+        Verdict::Critical => style(status).red(),
+    };
+    lines.push(format!("Status         : {}", status.bold().on_black()));
+    for reason in &assessment.reasons {
+        lines.push(format!("Reason         : {}", style(reason).dim()));
+    }
+
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Throughput and where the time went, to tell a grown database from a
+/// slower tool when a scan suddenly takes longer.
+fn render_performance(lines: &mut Vec<String>, result: &AuditResult, duration: Duration) {
+    let perf = &result.performance;
+    lines.push("--------------------------------".to_string());
+    lines.push(format!(
+        "Throughput     : {:.1} rows/s, {:.1} MB/s",
+        perf.rows_per_sec(result.stats.total_scanned, duration),
+        perf.mb_per_sec(duration)
+    ));
+    lines.push(format!(
+        "Bytes Read     : {}",
+        growth::format_bytes(perf.bytes_read as f64)
+    ));
+    lines.push(format!(
+        "Decode Time    : {:.2?} (I/O wait {:.2?})",
+        perf.decoding, perf.io_wait
+    ));
+    if let Some(peak) = perf.peak_memory {
+        lines.push(format!(
+            "Peak Memory    : {}",
+            growth::format_bytes(peak as f64)
+        ));
+    }
+    if let Some(stages) = &perf.stages {
+        render_stages(lines, stages);
+    }
+}
+
+/// Per-stage table of `--profile-stages`: which stage the time goes to,
+/// and whether a few slow assets or all of them make it slow.
+fn render_stages(lines: &mut Vec<String>, stages: &Timings) {
+    lines.push("--------------------------------".to_string());
+    lines.push(format!(
+        "{:<10} {:>9} {:>11} {:>10} {:>10} {:>10} {:>10}",
+        "Stage", "Assets", "Total", "Mean", "p50", "p95", "Max"
+    ));
+    for (stage, s) in stages.ran() {
+        lines.push(format!(
+            "{:<10} {:>9} {:>11} {:>10} {:>10} {:>10} {:>10}",
+            stage.as_str(),
+            s.count,
+            format!("{:.2?}", s.total),
+            format!("{:.2?}", s.mean()),
+            format!("{:.2?}", s.percentile(0.5)),
+            format!("{:.2?}", s.percentile(0.95)),
+            format!("{:.2?}", s.max)
+        ));
+    }
+}
+
+/// Per-worker table of a `--readers` scan, to tell I/O-bound from decode-bound runs.
+fn render_workers(lines: &mut Vec<String>, workers: &[WorkerStats]) {
+    if workers.is_empty() {
+        return;
+    }
+    lines.push("--------------------------------".to_string());
+    lines.push(format!(
+        "{:<14} {:>9} {:>11} {:>11} {:>11}",
+        "Worker", "Rows", "Bytes", "Busy", "Waiting"
+    ));
+    for w in workers {
+        let name = match w.role {
+            WorkerRole::Reader => format!("reader {}", w.reader),
+            WorkerRole::Decoder => format!("decoder {}.{}", w.reader, w.index),
+        };
+        lines.push(format!(
+            "{:<14} {:>9} {:>11} {:>11} {:>11}",
+            name,
+            w.rows,
+            growth::format_bytes(w.bytes as f64),
+            format!("{:.2?}", w.busy),
+            format!("{:.2?}", w.waiting)
+        ));
+    }
+    if let Some(bottleneck) = bottleneck(workers) {
+        lines.push(format!("Bottleneck     : {}", style(bottleneck).cyan()));
+    }
+}
+
+/// Readers stuck on full queues mean decoding is the limit; decoders idling on
+/// empty queues mean reading is.
+/// Cause counts as `cause=n` pairs, for the structured report event.
+fn causes_summary(stats: &AuditStats) -> String {
+    stats
+        .corrupt_causes
+        .iter()
+        .map(|(cause, count)| format!("{}={}", cause.as_str(), count))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn bottleneck(workers: &[WorkerStats]) -> Option<&'static str> {
+    let waiting_share = |role: WorkerRole| {
+        let (busy, waiting) = workers
+            .iter()
+            .filter(|w| w.role == role)
+            .fold((0.0, 0.0), |(b, wt), w| {
+                (b + w.busy.as_secs_f64(), wt + w.waiting.as_secs_f64())
+            });
+        (busy + waiting > 0.0).then(|| waiting / (busy + waiting))
+    };
+    let readers = waiting_share(WorkerRole::Reader)?;
+    let decoders = waiting_share(WorkerRole::Decoder)?;
+    Some(if readers > decoders {
+        "decode-bound (readers wait on decoders: raise --jobs)"
+    } else {
+        "I/O-bound (decoders wait on readers: raise --readers)"
+    })
+}
+
+/// Separates genuinely new damage from long-standing findings.
+pub fn render_diff(diff: &FindingsDiff) {
+    for f in &diff.new {
+        warn!(
+            target: FINDING_TARGET,
+            tag = "NEW",
+            id = f.id.as_deref().unwrap_or("-"),
+            kind = f.kind.as_str(),
+            reason = %f.reason,
+            "New since last audit"
+        );
+    }
+
+    if !octa_logging::is_human() {
+        info!(
+            tag = "DIFF",
+            new = diff.new.len(),
+            known = diff.known,
+            resolved = diff.resolved.len(),
+            "Findings compared with previous audits"
+        );
+        return;
+    }
+
+    println!("--------------------------------");
+    if diff.new.is_empty() {
+        println!("New Findings   : {}", style("0").dim());
+    } else {
+        println!("New Findings   : {}", style(diff.new.len()).red().bold());
+    }
+    println!("Known Findings : {}", diff.known);
+    println!("Resolved       : {}", style(diff.resolved.len()).green());
+}
+
+/// Projected savings of re-encoding the healthy assets, from `--compression-audit`.
+pub fn render_survey(survey: &Survey) {
+    if !octa_logging::is_human() {
+        for (target, p) in &survey.projections {
+            info!(
+                tag = "COMPRESSION",
+                format = target.as_str(),
+                sampled = p.sampled,
+                sample_bytes = p.sample_bytes,
+                encoded_bytes = p.encoded_bytes,
+                saved_percent = p.saved_percent(),
+                population_bytes = p.population_bytes,
+                projected_savings = p.projected_savings,
+                "Compression projection"
+            );
+        }
+        return;
+    }
+
+    println!("--------------------------------");
+    println!(
+        "Compression    : {} sampled of {} assets ({}% sample, {})",
+        survey
+            .projections
+            .values()
+            .map(|p| p.sampled)
+            .max()
+            .unwrap_or(0),
+        survey.assets,
+        survey.percent,
+        growth::format_bytes(survey.bytes as f64)
+    );
+    if survey.projections.is_empty() {
+        println!("  {}", style("no sampled asset could be re-encoded").dim());
+    }
+    for (target, p) in &survey.projections {
+        println!(
+            "  {:<13}: {} -> {} ({:.1}% smaller), projected savings {} of {}",
+            target.as_str(),
+            growth::format_bytes(p.sample_bytes as f64),
+            growth::format_bytes(p.encoded_bytes as f64),
+            p.saved_percent(),
+            style(growth::format_bytes(p.projected_savings as f64))
+                .green()
+                .bold(),
+            growth::format_bytes(p.population_bytes as f64)
+        );
+    }
+    if survey.failed > 0 {
+        println!(
+            "  {:<13}: {}",
+            "not encodable",
+            style(survey.failed).yellow()
+        );
+    }
+}
+
+/// Writes every finding as tab-separated `kind, severity, id, reason, column` lines,
+/// so the details survive even when `--quiet` keeps them off the terminal.
+pub fn write_details(path: &Path, result: &AuditResult) {
+    let write = || -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "kind\tseverity\tid\treason\tcolumn")?;
+        for f in &result.findings {
+            writeln!(
+                out,
+                "{}\t{}\t{}\t{}\t{}",
+                f.kind.as_str(),
+                f.severity.as_str(),
+                f.id.as_deref().unwrap_or("-"),
+                f.reason.trim().replace(['\t', '\n'], " "),
+                f.column.as_deref().unwrap_or("-")
+            )?;
+        }
+        out.flush()
+    };
+
+    match write() {
+        Ok(()) => info!(
+            tag = "OK",
+            path = %path.display(),
+            findings = result.findings.len(),
+            "Finding details written"
+        ),
+        Err(e) => {
+            error!(tag = "ERROR", path = %path.display(), reason = %e, "Could not write finding details")
+        }
+    }
+}
+
+/// The stage times of `--profile-stages` as folded stacks under a
+/// `warden` root, one frame per format and stage.
+pub fn write_folded(path: &Path, result: &AuditResult) {
+    let Some(stages) = &result.performance.stages else {
+        return;
+    };
+    let write = || -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        stages.write_folded("warden", &mut out)?;
+        out.flush()
+    };
+
+    match write() {
+        Ok(()) => info!(
+            tag = "OK",
+            path = %path.display(),
+            assets = stages.assets,
+            "Folded stacks written"
+        ),
+        Err(e) => {
+            error!(tag = "ERROR", path = %path.display(), reason = %e, "Could not write folded stacks")
+        }
+    }
+}
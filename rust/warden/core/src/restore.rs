@@ -0,0 +1,259 @@
+use crate::audit::has_column;
+use crate::encryption::{self, Key};
+use crate::export::sha256_hex;
+use octa_logging::FINDING_TARGET;
+use octa_store::cas;
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{Connection, OptionalExtension, Result, TransactionBehavior};
+use tracing::{info, warn};
+
+/// Columns of `images` that may hold the SHA-256 (hex) of the stored image.
+const CHECKSUM_COLUMNS: [&str; 2] = ["sha256", "checksum"];
+
+/// Columns copied along with `data` when both databases have them.
+const METADATA_COLUMNS: [&str; 4] = ["size", "width", "height", "format"];
+
+#[derive(Debug, Default)]
+pub struct RepairSummary {
+    /// Assets restored from the backup (or, in a dry run, that would be).
+    pub repaired: u64,
+    /// Assets without a usable copy in the backup.
+    pub irreparable: u64,
+    /// Keys of the restored assets (executed runs only).
+    pub keys: Vec<String>,
+}
+
+/// A usable copy of one asset in the backup.
+struct Copy {
+    backup_id: String,
+    /// How it was found: `key '<key>'` or `id`.
+    via: String,
+    data: Vec<u8>,
+    metadata: Vec<(&'static str, Value)>,
+}
+
+/// Restores each asset in `ids` (corrupt in `primary`) from `backup`. The
+/// backup copy is looked up by the asset's keys, then by its ID, and is only
+/// used if it decodes and matches the row's stored checksum, when there is
+/// one. Without `execute`, only reports what would be restored; `primary`
+/// may then be read-only.
+pub fn repair(
+    primary: &mut Connection,
+    backup: &Connection,
+    ids: &[String],
+    key: Option<&Key>,
+    execute: bool,
+) -> Result<RepairSummary> {
+    let mut checksum_column = None;
+    for column in CHECKSUM_COLUMNS {
+        if has_column(primary, "images", column)? {
+            checksum_column = Some(column);
+            break;
+        }
+    }
+    let mut metadata_columns = Vec::new();
+    for column in METADATA_COLUMNS {
+        if has_column(primary, "images", column)? && has_column(backup, "images", column)? {
+            metadata_columns.push(column);
+        }
+    }
+    let mapped = table_exists(primary, "key_mappings")? && table_exists(backup, "key_mappings")?;
+
+    let mut summary = RepairSummary::default();
+    for id in ids {
+        let checksum: Option<String> = match checksum_column {
+            Some(column) => primary
+                .query_row(
+                    &format!("SELECT {} FROM images WHERE id = ?1", quote(column)),
+                    [id],
+                    |row| row.get::<_, Option<String>>(0),
+                )
+                .optional()?
+                .flatten(),
+            None => None,
+        };
+        let keys: Vec<String> = if mapped {
+            primary
+                .prepare("SELECT key FROM key_mappings WHERE image_id = ?1 ORDER BY key")?
+                .query_map([id], |row| row.get(0))?
+                .collect::<Result<_>>()?
+        } else {
+            Vec::new()
+        };
+
+        let mut candidates: Vec<(String, String)> = Vec::new();
+        for k in &keys {
+            let backup_id: Option<String> = backup
+                .query_row(
+                    "SELECT image_id FROM key_mappings WHERE key = ?1",
+                    [k],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(backup_id) = backup_id {
+                candidates.push((backup_id, format!("key '{}'", k)));
+            }
+        }
+        if !candidates.iter().any(|(backup_id, _)| backup_id == id) {
+            candidates.push((id.clone(), "id".to_string()));
+        }
+
+        let mut rejected = Vec::new();
+        let mut found = None;
+        for (backup_id, via) in candidates {
+            match verify(
+                backup,
+                &backup_id,
+                key,
+                checksum.as_deref(),
+                &metadata_columns,
+            )? {
+                Ok((data, metadata)) => {
+                    found = Some(Copy {
+                        backup_id,
+                        via,
+                        data,
+                        metadata,
+                    });
+                    break;
+                }
+                Err(reason) => rejected.push(format!("{}: {}", via, reason)),
+            }
+        }
+
+        let Some(copy) = found else {
+            let reason = if rejected.is_empty() {
+                "not in the backup".to_string()
+            } else {
+                rejected.join("; ")
+            };
+            warn!(target: FINDING_TARGET, tag = "IRREPARABLE", id = %id, reason = %reason, "No usable copy in the backup");
+            summary.irreparable += 1;
+            continue;
+        };
+
+        if !execute {
+            info!(tag = "REPAIR", id = %id, backup_id = %copy.backup_id, via = %copy.via, bytes = copy.data.len(), "Would restore from backup");
+            summary.repaired += 1;
+            continue;
+        }
+        if restore(primary, id, &copy, key)? {
+            info!(target: FINDING_TARGET, tag = "APPLY", id = %id, backup_id = %copy.backup_id, via = %copy.via, bytes = copy.data.len(), "Restored from backup");
+            summary.repaired += 1;
+            summary.keys.extend(keys);
+        } else {
+            warn!(tag = "SKIP", id = %id, "Asset decodes now or is gone, left untouched");
+        }
+    }
+    Ok(summary)
+}
+
+/// The backup row's data and metadata, or why it cannot be used.
+type Verified = std::result::Result<(Vec<u8>, Vec<(&'static str, Value)>), String>;
+
+fn verify(
+    backup: &Connection,
+    backup_id: &str,
+    key: Option<&Key>,
+    checksum: Option<&str>,
+    metadata_columns: &[&'static str],
+) -> Result<Verified> {
+    let select: Vec<String> = std::iter::once(cas::data(backup)?.to_string())
+        .chain(metadata_columns.iter().map(|column| quote(column)))
+        .collect();
+    let row = backup
+        .query_row(
+            &format!("SELECT {} FROM images WHERE id = ?1", select.join(", ")),
+            [backup_id],
+            |row| {
+                let data = match row.get_ref(0)? {
+                    ValueRef::Blob(data) => Some(data.to_vec()),
+                    _ => None,
+                };
+                let metadata = metadata_columns
+                    .iter()
+                    .enumerate()
+                    .map(|(i, column)| Ok((*column, row.get::<_, Value>(i + 1)?)))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok((data, metadata))
+            },
+        )
+        .optional()?;
+
+    let Some((data, metadata)) = row else {
+        return Ok(Err("not in the backup".to_string()));
+    };
+    let Some(data) = data else {
+        return Ok(Err("backup data is not a BLOB".to_string()));
+    };
+    let plain = match encryption::plaintext(key, &data) {
+        Ok(plain) => plain,
+        Err(reason) => return Ok(Err(format!("backup copy does not decrypt ({})", reason))),
+    };
+    if let Err(e) = image::load_from_memory(&plain) {
+        return Ok(Err(format!("backup copy does not decode ({})", e)));
+    }
+    if let Some(expected) = checksum {
+        let expected = expected.trim().to_ascii_lowercase();
+        if sha256_hex(&data) != expected && sha256_hex(&plain) != expected {
+            return Ok(Err(
+                "backup copy does not match the stored checksum".to_string()
+            ));
+        }
+    }
+    Ok(Ok((data, metadata)))
+}
+
+/// Writes the copy in one transaction, after checking the primary row is
+/// still there and still does not decode. Returns whether it was written.
+fn restore(primary: &mut Connection, id: &str, copy: &Copy, key: Option<&Key>) -> Result<bool> {
+    let tx = primary.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let select = format!("SELECT {} FROM images WHERE id = ?1", cas::data(&tx)?);
+    let broken = tx
+        .query_row(&select, [id], |row| {
+            Ok(match row.get_ref(0)? {
+                ValueRef::Blob(data) => encryption::plaintext(key, data)
+                    .map_err(|_| ())
+                    .and_then(|plain| image::load_from_memory(&plain).map_err(|_| ()))
+                    .is_err(),
+                _ => true,
+            })
+        })
+        .optional()?;
+    if broken != Some(true) {
+        return Ok(false);
+    }
+
+    cas::rewrite(&tx, id, &copy.data)?;
+    if !copy.metadata.is_empty() {
+        let mut assignments = Vec::new();
+        let mut values = Vec::new();
+        for (column, value) in &copy.metadata {
+            values.push(value.clone());
+            assignments.push(format!("{} = ?{}", quote(column), values.len()));
+        }
+        values.push(Value::Text(id.to_string()));
+        tx.execute(
+            &format!(
+                "UPDATE images SET {} WHERE id = ?{}",
+                assignments.join(", "),
+                values.len()
+            ),
+            rusqlite::params_from_iter(values),
+        )?;
+    }
+    tx.commit()?;
+    Ok(true)
+}
+
+fn table_exists(conn: &Connection, table: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+        [table],
+        |row| row.get(0),
+    )
+}
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
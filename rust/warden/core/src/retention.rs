@@ -0,0 +1,170 @@
+use crate::schedule::parse_interval;
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, Result, TransactionBehavior};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Assets deleted per transaction, so the server is never locked out for long.
+const DELETE_BATCH: usize = 200;
+
+/// One `warden.retention` entry: keys matching `pattern` expire `max_age`
+/// after the asset was last written.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionRule {
+    /// Key glob; `*` matches any run of characters (including `/`), `?` exactly one.
+    pub pattern: String,
+    /// Same syntax as `watch --interval`, e.g. `30d` or `12h`.
+    pub max_age: String,
+}
+
+struct Rule<'a> {
+    pattern: &'a str,
+    max_age: Duration,
+}
+
+/// An asset every one of whose keys is past its rule's age limit.
+#[derive(Debug)]
+pub struct Expired {
+    pub id: String,
+    pub keys: Vec<String>,
+    pub bytes: u64,
+    pub age: Duration,
+    /// Pattern that matched the first key.
+    pub rule: String,
+    /// `updated_at` as listed. [`delete`] leaves the asset alone when it
+    /// changed, so a re-upload after the listing is never lost.
+    updated_at: Value,
+}
+
+/// What [`delete`] did, by asset ID.
+#[derive(Debug, Default)]
+pub struct Deleted {
+    pub removed: Vec<String>,
+    /// Written or already gone since [`find_expired`] listed them.
+    pub skipped: Vec<String>,
+}
+
+/// Lists the assets the rules would remove, oldest first.
+///
+/// An asset only expires when *every* key mapped to it matches a rule and is
+/// older than that rule allows: deleting it must never break a key that is
+/// meant to live on. The first matching rule in config order applies to a key.
+/// Rows without a parseable timestamp are never selected.
+pub fn find_expired(conn: &Connection, rules: &[RetentionRule]) -> Result<Vec<Expired>, String> {
+    let rules = rules
+        .iter()
+        .map(|r| {
+            parse_interval(&r.max_age)
+                .map(|max_age| Rule {
+                    pattern: &r.pattern,
+                    max_age,
+                })
+                .map_err(|e| format!("rule '{}': {}", r.pattern, e))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT k.image_id, k.key, COALESCE(i.size, 0),
+                    (julianday('now') - julianday(COALESCE(i.updated_at, i.created_at))) * 86400,
+                    i.updated_at
+             FROM key_mappings k JOIN images i ON i.id = k.image_id
+             ORDER BY k.image_id, k.key",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, Option<f64>>(3)?,
+                row.get::<_, Value>(4)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+
+    // `None` once any key keeps the asset alive.
+    let mut assets: BTreeMap<String, Option<Expired>> = BTreeMap::new();
+    for row in rows {
+        let (id, key, bytes, age, updated_at) = row.map_err(|e| e.to_string())?;
+        let entry = assets.entry(id.clone()).or_insert_with(|| {
+            Some(Expired {
+                id,
+                keys: Vec::new(),
+                bytes: bytes.max(0) as u64,
+                age: Duration::ZERO,
+                rule: String::new(),
+                updated_at,
+            })
+        });
+        let Some(expired) = entry else { continue };
+
+        let age = age.filter(|a| *a >= 0.0).map(Duration::from_secs_f64);
+        let rule = rules.iter().find(|r| glob_match(r.pattern, &key));
+        match (rule, age) {
+            (Some(rule), Some(age)) if age > rule.max_age => {
+                if expired.keys.is_empty() {
+                    expired.rule = rule.pattern.to_string();
+                }
+                expired.age = age;
+                expired.keys.push(key);
+            }
+            _ => *entry = None,
+        }
+    }
+
+    let mut expired: Vec<Expired> = assets.into_values().flatten().collect();
+    expired.sort_by(|a, b| b.age.cmp(&a.age).then_with(|| a.id.cmp(&b.id)));
+    Ok(expired)
+}
+
+/// Deletes the assets and their key mappings (mappings first, like the
+/// server), in batches. An asset whose `updated_at` changed since it was
+/// listed is skipped.
+pub fn delete(conn: &mut Connection, expired: &[Expired]) -> Result<Deleted> {
+    let mut deleted = Deleted::default();
+    for batch in expired.chunks(DELETE_BATCH) {
+        // Immediate: nothing may be written between the check and the delete.
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        for asset in batch {
+            let unchanged = tx
+                .prepare_cached("SELECT 1 FROM images WHERE id = ?1 AND updated_at IS ?2")?
+                .exists(params![asset.id, asset.updated_at])?;
+            if unchanged && octa_store::delete(&tx, &asset.id)? {
+                deleted.removed.push(asset.id.clone());
+            } else {
+                deleted.skipped.push(asset.id.clone());
+            }
+        }
+        tx.commit()?;
+    }
+    Ok(deleted)
+}
+
+/// `*` matches any run of characters, `?` exactly one; everything else is literal.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
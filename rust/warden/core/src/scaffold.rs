@@ -0,0 +1,369 @@
+use crate::derived::Dimensions;
+use image::GenericImageView;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, Result};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// BLOBs sampled to tell images from ciphertext.
+const SAMPLE_ROWS: i64 = 20;
+
+/// Largest number of variants read from a derived table.
+const MAX_SIZES: i64 = 20;
+
+/// Columns every query of the audit relies on.
+const REQUIRED: [&str; 2] = ["id", "data"];
+
+/// What `octa-warden init` found in a database.
+#[derive(Debug, Default)]
+pub struct Layout {
+    /// Columns of `images`; `None` when the table does not exist.
+    pub images: Option<Vec<String>>,
+    pub rows: u64,
+    /// BLOB columns of `images` besides `data`.
+    pub extra_blob_columns: Vec<String>,
+    pub derived: Option<DerivedGuess>,
+    pub key_mappings: bool,
+    /// Most common first path segments of the keys, for retention examples.
+    pub key_prefixes: Vec<String>,
+    /// BLOBs sampled, and how many of them are recognizable images.
+    pub sampled: u64,
+    pub images_found: u64,
+}
+
+/// A table that looks like pre-generated sizes of `images`.
+#[derive(Debug)]
+pub struct DerivedGuess {
+    pub table: String,
+    pub original_column: String,
+    pub size_column: String,
+    pub data_column: String,
+    /// `None` where no stored variant could be decoded to measure it.
+    pub sizes: BTreeMap<String, Option<Dimensions>>,
+}
+
+impl Layout {
+    pub fn missing_columns(&self) -> Vec<&'static str> {
+        let Some(columns) = &self.images else {
+            return Vec::new();
+        };
+        REQUIRED
+            .into_iter()
+            .filter(|c| !columns.iter().any(|have| have == c))
+            .collect()
+    }
+
+    /// Every sampled BLOB is something other than an image: likely encrypted.
+    pub fn looks_encrypted(&self) -> bool {
+        self.sampled > 0 && self.images_found == 0
+    }
+}
+
+/// Reads the schema and a few rows; writes nothing.
+pub fn inspect(conn: &Connection) -> Result<Layout> {
+    let mut layout = Layout::default();
+    let tables: Vec<String> = conn
+        .prepare(
+            "SELECT name FROM sqlite_master
+             WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+        )?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+
+    if tables.iter().any(|t| t == "images") {
+        let columns = table_info(conn, "images")?;
+        layout.extra_blob_columns = columns
+            .iter()
+            .filter(|(name, declared)| name != "data" && declared.contains("BLOB"))
+            .map(|(name, _)| name.clone())
+            .collect();
+        layout.images = Some(columns.into_iter().map(|(name, _)| name).collect());
+        layout.rows = conn.query_row("SELECT COUNT(*) FROM images", [], |row| {
+            row.get::<_, i64>(0)
+        })? as u64;
+
+        if layout.missing_columns().is_empty() {
+            let mut stmt = conn.prepare(
+                "SELECT data FROM images WHERE typeof(data) = 'blob' ORDER BY rowid LIMIT ?1",
+            )?;
+            let mut rows = stmt.query([SAMPLE_ROWS])?;
+            while let Some(row) = rows.next()? {
+                if let ValueRef::Blob(blob) = row.get_ref(0)? {
+                    layout.sampled += 1;
+                    if image::guess_format(blob).is_ok() {
+                        layout.images_found += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    layout.key_mappings = tables.iter().any(|t| t == "key_mappings");
+    if layout.key_mappings {
+        layout.key_prefixes = conn
+            .prepare(
+                "SELECT substr(key, 1, instr(key, '/')) AS prefix FROM key_mappings
+                 WHERE instr(key, '/') > 1 GROUP BY prefix ORDER BY COUNT(*) DESC LIMIT 3",
+            )?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_>>()?;
+    }
+
+    for table in tables
+        .iter()
+        .filter(|t| !matches!(t.as_str(), "images" | "key_mappings"))
+    {
+        if let Some(guess) = guess_derived(conn, table, layout.looks_encrypted())? {
+            layout.derived = Some(guess);
+            break;
+        }
+    }
+    Ok(layout)
+}
+
+/// `(name, declared type in upper case)` of every column.
+fn table_info(conn: &Connection, table: &str) -> Result<Vec<(String, String)>> {
+    conn.prepare(&format!("PRAGMA table_info({})", quote(table)))?
+        .query_map([], |row| {
+            Ok((row.get(1)?, row.get::<_, String>(2)?.to_uppercase()))
+        })?
+        .collect()
+}
+
+/// A table with a reference to `images`, a BLOB column and a variant name
+/// column is taken for derived sizes; each variant is measured from one row.
+fn guess_derived(conn: &Connection, table: &str, encrypted: bool) -> Result<Option<DerivedGuess>> {
+    let columns = table_info(conn, table)?;
+    let foreign: Option<String> = conn
+        .prepare(&format!(
+            "SELECT \"from\" FROM pragma_foreign_key_list({}) WHERE \"table\" = 'images'",
+            quote_literal(table)
+        ))?
+        .query_map([], |row| row.get(0))?
+        .next()
+        .transpose()?;
+    let original = foreign.or_else(|| {
+        ["image_id", "original_id", "original"]
+            .into_iter()
+            .find(|name| columns.iter().any(|(c, _)| c == name))
+            .map(str::to_string)
+    });
+    let data = columns
+        .iter()
+        .find(|(_, declared)| declared.contains("BLOB"))
+        .map(|(name, _)| name.clone());
+    let size = ["size", "variant", "name", "label"]
+        .into_iter()
+        .find(|name| columns.iter().any(|(c, _)| c == name))
+        .map(str::to_string);
+    let (Some(original), Some(data), Some(size)) = (original, data, size) else {
+        return Ok(None);
+    };
+
+    let mut sizes = BTreeMap::new();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {size}, (SELECT {data} FROM {table} AS v WHERE v.{size} = d.{size} LIMIT 1)
+         FROM (SELECT DISTINCT {size} FROM {table} WHERE {size} IS NOT NULL) AS d LIMIT ?1",
+        size = quote(&size),
+        data = quote(&data),
+        table = quote(table),
+    ))?;
+    let mut rows = stmt.query([MAX_SIZES])?;
+    while let Some(row) = rows.next()? {
+        let Ok(name) = row.get::<_, String>(0) else {
+            continue;
+        };
+        let dimensions = match row.get_ref(1)? {
+            ValueRef::Blob(blob) if !encrypted => image::load_from_memory(blob)
+                .ok()
+                .map(|img| img.dimensions())
+                .map(|(width, height)| Dimensions { width, height }),
+            _ => None,
+        };
+        sizes.insert(name, dimensions);
+    }
+    Ok(Some(DerivedGuess {
+        table: table.to_string(),
+        original_column: original,
+        size_column: size,
+        data_column: data,
+        sizes,
+    }))
+}
+
+/// Sections `init` has nothing to detect for, commented out with examples.
+const REFERENCE: &str = r#"  # Record every run; enables `octa-warden history` and growth trends.
+  # history_path: HISTORY
+
+  # Verdict thresholds ("more than N") and per-kind severity overrides.
+  # health:
+  #   severity:
+  #     schema_mismatch: critical
+  #   warning:
+  #     corrupted: 0
+  #   critical:
+  #     corrupted_percent: 1.0
+
+  # Where a run reports its findings.
+  # notify:
+  #   slack_webhook_url: "https://hooks.slack.com/services/..."
+  #   min_severity: warning
+
+"#;
+
+const DERIVED_EXAMPLE: &str = r#"  # Pre-generated sizes, for schemas that store them.
+  # derived:
+  #   table: thumbnails
+  #   original_column: image_id
+  #   size_column: size
+  #   data_column: data
+  #   sizes:
+  #     sm: "128x128"
+"#;
+
+/// The config file: what was detected is set, the rest is there commented
+/// out, so the file doubles as a reference.
+pub fn render(db_path: &str, layout: &Layout) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "# Octa-Warden configuration, generated by `octa-warden init`."
+    );
+    let _ = writeln!(out, "# Run with: octa-warden -c <this file>\n");
+    let _ = writeln!(out, "database:\n  path: {}", yaml_string(db_path));
+    match &layout.images {
+        None => {
+            out.push_str("  # No images table found. Warden audits images(id, data);\n");
+            out.push_str("  # point this at the Octa database, or see warden.storage.\n");
+        }
+        Some(columns) => {
+            let _ = writeln!(
+                out,
+                "  # images: {} rows; columns: {}",
+                layout.rows,
+                columns.join(", ")
+            );
+            for column in layout.missing_columns() {
+                let _ = writeln!(
+                    out,
+                    "  # images.{} is missing: the audit needs it (see --migrate-schema)",
+                    column
+                );
+            }
+        }
+    }
+
+    out.push_str("\nwarden:\n");
+    out.push_str(&REFERENCE.replace(
+        "HISTORY",
+        &yaml_string(&sibling(db_path, "warden-history.db")),
+    ));
+
+    if layout.extra_blob_columns.is_empty() {
+        out.push_str("  # More BLOB columns of images to validate next to data.\n");
+        out.push_str("  # extra_blob_columns: [thumb]\n\n");
+    } else {
+        let columns: Vec<String> = layout
+            .extra_blob_columns
+            .iter()
+            .map(|c| yaml_string(c))
+            .collect();
+        out.push_str("  # BLOB columns of images found next to data.\n");
+        let _ = writeln!(out, "  extra_blob_columns: [{}]\n", columns.join(", "));
+    }
+
+    match &layout.derived {
+        Some(guess) => {
+            let _ = writeln!(
+                out,
+                "  # Table '{}' looks like pre-generated sizes. Check the expected sizes.",
+                guess.table
+            );
+            out.push_str("  derived:\n");
+            let _ = writeln!(out, "    table: {}", yaml_string(&guess.table));
+            let _ = writeln!(
+                out,
+                "    original_column: {}",
+                yaml_string(&guess.original_column)
+            );
+            let _ = writeln!(out, "    size_column: {}", yaml_string(&guess.size_column));
+            let _ = writeln!(out, "    data_column: {}", yaml_string(&guess.data_column));
+            if guess.sizes.values().all(Option::is_none) {
+                out.push_str("    sizes: {}\n");
+            } else {
+                out.push_str("    sizes:\n");
+            }
+            for (name, dims) in &guess.sizes {
+                match dims {
+                    Some(dims) => {
+                        let _ = writeln!(out, "      {}: \"{}\"", yaml_string(name), dims);
+                    }
+                    None => {
+                        let _ = writeln!(
+                            out,
+                            "      # {}: \"WIDTHxHEIGHT\"  (no decodable sample)",
+                            yaml_string(name)
+                        );
+                    }
+                }
+            }
+        }
+        None => out.push_str(DERIVED_EXAMPLE),
+    }
+    out.push('\n');
+
+    if layout.key_mappings {
+        out.push_str("  # Age limits per key pattern, applied by --enforce-retention.\n");
+        out.push_str("  # retention:\n");
+        let prefixes: Vec<&str> = if layout.key_prefixes.is_empty() {
+            vec!["tmp/"]
+        } else {
+            layout.key_prefixes.iter().map(String::as_str).collect()
+        };
+        for prefix in prefixes {
+            let _ = writeln!(
+                out,
+                "  #   - pattern: {}",
+                yaml_string(&format!("{}*", prefix))
+            );
+            out.push_str("  #     max_age: \"365d\"\n");
+        }
+    } else {
+        out.push_str("  # No key_mappings table found: retention rules match keys there.\n");
+    }
+    out.push('\n');
+
+    if layout.looks_encrypted() {
+        let _ = writeln!(
+            out,
+            "  # None of {} sampled BLOBs is a recognizable image. If the application",
+            layout.sampled
+        );
+        out.push_str("  # encrypts them (AES-256-GCM), give Warden the key:\n");
+    } else {
+        out.push_str("  # For deployments that encrypt BLOBs (AES-256-GCM) before storing them.\n");
+    }
+    out.push_str("  # encryption:\n  #   key_env: WARDEN_BLOB_KEY\n");
+    out
+}
+
+/// `name` in the directory of `path`.
+fn sibling(path: &str, name: &str) -> String {
+    match std::path::Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.join(name).display().to_string(),
+        _ => name.to_string(),
+    }
+}
+
+/// Double-quoted, so any path or name survives YAML.
+fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
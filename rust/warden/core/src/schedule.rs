@@ -0,0 +1,98 @@
+use chrono::{DateTime, Local};
+use croner::Cron;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// When watch mode runs its audits.
+pub enum Schedule {
+    /// Fixed gap between the end of one cycle and the start of the next.
+    Interval(Duration),
+    /// Standard 5-field cron expression, evaluated in local time.
+    Cron(Box<Cron>),
+}
+
+impl Schedule {
+    /// Time to wait before the next cycle. Interval schedules start with an
+    /// immediate audit; cron schedules always wait for the next matching slot.
+    pub fn next_delay(&self, first_cycle: bool) -> Duration {
+        match self {
+            Schedule::Interval(_) if first_cycle => Duration::ZERO,
+            Schedule::Interval(d) => *d,
+            Schedule::Cron(cron) => next_occurrence(cron)
+                .and_then(|next| (next - Local::now()).to_std().ok())
+                .unwrap_or(Duration::ZERO),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Schedule::Interval(d) => format!("every {:?}", d),
+            Schedule::Cron(cron) => format!("cron \"{}\"", cron.pattern),
+        }
+    }
+}
+
+fn next_occurrence(cron: &Cron) -> Option<DateTime<Local>> {
+    cron.find_next_occurrence(&Local::now(), false).ok()
+}
+
+/// Parses intervals in the same style as config.yaml ("30s", "5m", "6h", "1d").
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (num, unit) = value.split_at(split);
+
+    let num: u64 = num
+        .parse()
+        .map_err(|_| format!("invalid interval '{}'", value))?;
+    let factor: u64 = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => {
+            return Err(format!(
+                "unknown interval unit '{}' (use s, m, h or d)",
+                unit
+            ))
+        }
+    };
+    let secs = num
+        .checked_mul(factor)
+        .ok_or_else(|| format!("interval '{}' is too long", value))?;
+
+    if secs == 0 {
+        return Err("interval must be greater than zero".to_string());
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+/// Like `parse_interval`, but zero is a valid jitter.
+pub fn parse_jitter(value: &str) -> Result<Duration, String> {
+    match value.trim().trim_end_matches(['s', 'm', 'h', 'd']) {
+        "0" => Ok(Duration::ZERO),
+        _ => parse_interval(value),
+    }
+}
+
+/// Parses a 5-field cron expression ("0 3 * * *").
+pub fn parse_cron(value: &str) -> Result<Box<Cron>, String> {
+    value
+        .parse::<Cron>()
+        .map(Box::new)
+        .map_err(|e| format!("invalid cron expression '{}': {}", value, e))
+}
+
+/// Random delay in `[0, max)` so a fleet sharing one schedule does not hit
+/// its storage at the same second. Uses std's randomly seeded hasher to avoid a rand dependency.
+pub fn jitter(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(max.as_nanos() as u64);
+    Duration::from_millis(hasher.finish() % max.as_millis().max(1) as u64)
+}
@@ -0,0 +1,26 @@
+use crate::s3::S3Config;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// Where the assets live (`warden.storage` in config.yaml).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "backend", rename_all = "lowercase", deny_unknown_fields)]
+pub enum StorageConfig {
+    /// BLOBs in the Octa SQLite database at `database.path`.
+    #[default]
+    Sqlite,
+    /// Image files in a directory tree. The asset ID is the path relative to `root`.
+    Fs { root: PathBuf },
+    /// Objects in an S3-compatible bucket. The asset ID is the key without `prefix`.
+    S3(S3Config),
+}
+
+impl StorageConfig {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StorageConfig::Sqlite => "sqlite",
+            StorageConfig::Fs { .. } => "fs",
+            StorageConfig::S3(_) => "s3",
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use crate::audit::Finding;
+use serde_json::json;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// Writes every finding as one JSON line the moment it is found
+/// (`--findings-stream`), so a scan that runs for hours can be tailed or
+/// piped into other tools. Clones share the same output.
+#[derive(Clone)]
+pub struct FindingStream {
+    out: Arc<Mutex<Box<dyn Write + Send>>>,
+    /// Set after the first failed write; the rest of the run is not streamed.
+    broken: Arc<AtomicBool>,
+}
+
+impl fmt::Debug for FindingStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FindingStream").finish_non_exhaustive()
+    }
+}
+
+impl FindingStream {
+    /// `-` streams to stdout; anything else is a file, created or truncated.
+    pub fn open(target: &str) -> io::Result<Self> {
+        let out: Box<dyn Write + Send> = if target == "-" {
+            Box::new(io::stdout())
+        } else {
+            Box::new(BufWriter::new(File::create(Path::new(target))?))
+        };
+        Ok(Self {
+            out: Arc::new(Mutex::new(out)),
+            broken: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// The severity is the kind's default; `warden.health` overrides are only
+    /// applied to the final report.
+    pub fn emit(&self, finding: &Finding) {
+        if self.broken.load(Ordering::Relaxed) {
+            return;
+        }
+        let line = json!({
+            "at": chrono::Utc::now().to_rfc3339(),
+            "kind": finding.kind.as_str(),
+            "severity": finding.severity.as_str(),
+            "id": finding.id,
+            "column": finding.column,
+            "reason": finding.reason,
+        });
+        let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+        // Flushed per line: a reader tailing the file sees each finding at once.
+        let written = writeln!(out, "{}", line).and_then(|_| out.flush());
+        if let Err(e) = written {
+            self.broken.store(true, Ordering::Relaxed);
+            warn!(tag = "WARN", error = %e, "Could not write the findings stream, it stops here");
+        }
+    }
+}
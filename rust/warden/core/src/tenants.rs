@@ -0,0 +1,87 @@
+//! Which tenant a key belongs to, and what each tenant stores. Kept with
+//! the warden's scanning code so that every tool reporting per tenant
+//! (octa-quota) attributes keys the same way.
+//!
+//! A key belongs to the tenant of the longest configured prefix it starts
+//! with; without one, its first `depth` path segments name the tenant
+//! (`acme/users/42` is `acme` at depth 1). Keys with no more segments than
+//! that (`alice`) belong to no tenant.
+
+use rusqlite::{Connection, Result};
+use std::collections::{BTreeMap, HashSet};
+
+/// How keys map to tenants.
+#[derive(Debug, Clone)]
+pub struct Attribution {
+    /// `(prefix, tenant)`, longest prefix first.
+    prefixes: Vec<(String, String)>,
+    depth: usize,
+}
+
+impl Attribution {
+    /// `prefixes` as `(prefix, tenant)`; empty prefixes are ignored.
+    pub fn new(prefixes: impl IntoIterator<Item = (String, String)>, depth: usize) -> Self {
+        let mut prefixes: Vec<(String, String)> = prefixes
+            .into_iter()
+            .filter(|(prefix, _)| !prefix.is_empty())
+            .collect();
+        prefixes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        Self { prefixes, depth }
+    }
+
+    /// The tenant of `key`, if any.
+    pub fn tenant<'a>(&'a self, key: &'a str) -> Option<&'a str> {
+        if let Some((_, tenant)) = self
+            .prefixes
+            .iter()
+            .find(|(p, _)| key.starts_with(p.as_str()))
+        {
+            return Some(tenant);
+        }
+        if self.depth == 0 {
+            return None;
+        }
+        // The end of the `depth`th segment, when more follow.
+        let end = key.match_indices('/').nth(self.depth - 1)?.0;
+        (end > 0).then(|| &key[..end])
+    }
+}
+
+/// What one tenant stores.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Distinct assets reachable through the tenant's keys.
+    pub assets: u64,
+    pub keys: u64,
+    /// Stored bytes of those assets.
+    pub bytes: u64,
+}
+
+/// Usage per tenant, by name; `None` collects the keys of no tenant. An
+/// asset shared by keys of several tenants counts fully for each.
+pub fn usage(
+    conn: &Connection,
+    attribution: &Attribution,
+) -> Result<BTreeMap<Option<String>, Usage>> {
+    // length() reads the BLOB size from the record header, not the data itself.
+    let mut stmt = conn.prepare(
+        "SELECT k.key, k.image_id, IFNULL(length(i.data), 0)
+         FROM key_mappings k JOIN images i ON i.id = k.image_id",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut usage: BTreeMap<Option<String>, Usage> = BTreeMap::new();
+    let mut counted: HashSet<(Option<String>, String)> = HashSet::new();
+    while let Some(row) = rows.next()? {
+        let key: String = row.get(0)?;
+        let image_id: String = row.get(1)?;
+        let bytes: i64 = row.get(2)?;
+        let tenant = attribution.tenant(&key).map(str::to_string);
+        let entry = usage.entry(tenant.clone()).or_default();
+        entry.keys += 1;
+        if counted.insert((tenant, image_id)) {
+            entry.assets += 1;
+            entry.bytes += bytes.max(0) as u64;
+        }
+    }
+    Ok(usage)
+}
@@ -0,0 +1,98 @@
+use crate::audit::{has_column, AuditResult, Finding, FindingKind};
+use crate::stream::FindingStream;
+use octa_logging::FINDING_TARGET;
+use rusqlite::{Connection, Result};
+use tracing::warn;
+
+/// Clock skew tolerated before a timestamp counts as in the future.
+const FUTURE_SKEW_SECONDS: i64 = 300;
+
+/// `julianday()` of the Unix epoch and of Go's zero `time.Time`, the values
+/// a timestamp gets when the writer never set it.
+const ZERO_DAYS: [f64; 2] = [2440587.5, 1721425.5];
+
+/// Checks `images.created_at` and `images.updated_at` the way retention reads
+/// them (SQLite's `julianday()`): NULL, unparseable, zero, in the future, or
+/// updated before created. Each affected row is one `bad_timestamp` finding,
+/// also sent to `stream`.
+pub fn check(
+    conn: &Connection,
+    stream: Option<&FindingStream>,
+    result: &mut AuditResult,
+) -> Result<()> {
+    let mut columns = Vec::new();
+    for column in ["created_at", "updated_at"] {
+        if has_column(conn, "images", column)? {
+            columns.push(column);
+        }
+    }
+    if columns.is_empty() {
+        warn!(
+            tag = "WARN",
+            "images has no created_at or updated_at column, skipping timestamp check"
+        );
+        return Ok(());
+    }
+
+    let select: Vec<String> = columns
+        .iter()
+        .map(|c| format!("{c}, typeof({c}), julianday({c})"))
+        .collect();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, julianday('now'), {} FROM images",
+        select.join(", ")
+    ))?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let Ok(id) = row.get::<_, String>(0) else {
+            continue;
+        };
+        let now: f64 = row.get(1)?;
+
+        let mut problems = Vec::new();
+        let mut days = Vec::new();
+        for (i, column) in columns.iter().enumerate() {
+            let at = 2 + i * 3;
+            let text: Option<String> = row.get_ref(at)?.as_str().ok().map(str::to_string);
+            let kind: String = row.get(at + 1)?;
+            let parsed: Option<f64> = row.get(at + 2)?;
+            let problem = match (kind.as_str(), parsed) {
+                ("null", _) => Some("is NULL".to_string()),
+                ("integer" | "real", Some(0.0)) => Some("is zero".to_string()),
+                ("text", Some(d)) if ZERO_DAYS.contains(&d) => {
+                    Some(format!("is a zero value ({})", text.unwrap_or_default()))
+                }
+                ("text", Some(d)) if (d - now) * 86400.0 > FUTURE_SKEW_SECONDS as f64 => {
+                    Some(format!("is in the future ({})", text.unwrap_or_default()))
+                }
+                ("text", Some(d)) => {
+                    days.push(d);
+                    None
+                }
+                ("text", None) => Some(format!("is not a date ('{}')", text.unwrap_or_default())),
+                (kind, _) => Some(format!("is not a date ({})", kind)),
+            };
+            if let Some(problem) = problem {
+                problems.push(format!("{} {}", column, problem));
+            }
+        }
+        // Both columns present and valid: the row cannot be updated before it existed.
+        if let [created, updated] = days[..] {
+            if updated < created {
+                problems.push("updated_at is earlier than created_at".to_string());
+            }
+        }
+
+        if !problems.is_empty() {
+            let reason = problems.join("; ");
+            warn!(target: FINDING_TARGET, tag = "TIME", id = %id, reason = %reason, "Timestamp problem");
+            result.stats.timestamp_issues += 1;
+            let finding = Finding::new(Some(id), FindingKind::BadTimestamp, reason);
+            if let Some(stream) = stream {
+                stream.emit(&finding);
+            }
+            result.findings.push(finding);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,403 @@
+use crate::audit::{self, Scope};
+use crate::backend;
+use crate::bundle;
+use crate::config::Config;
+use crate::db::{self, OpenOptions};
+use crate::derived::{self, DerivedConfig};
+use crate::growth;
+use crate::health::{self, HealthConfig};
+use crate::history;
+use crate::metrics::Metrics;
+use crate::notify::{self, NotifyConfig};
+use crate::report::{self, render_report};
+use crate::schedule::{self, Schedule};
+use crate::storage::StorageConfig;
+use crate::timestamps;
+use chrono::Local;
+use octa_storage::{Fs, S3};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{error, info, warn};
+
+/// Times a cycle reopens a database file replaced under it before it gives
+/// up and reports the aborted scan.
+const MAX_REOPENS: u32 = 3;
+
+/// `warden.watch` section of config.yaml.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchConfig {
+    /// Time between audits ("30m", "6h", "1d").
+    pub interval: String,
+    /// Cron expression for audit times in local time (overrides `interval`).
+    pub schedule: Option<String>,
+    /// Upper bound of the random delay added before every cycle ("0s" = none).
+    pub jitter: String,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            interval: "6h".to_string(),
+            schedule: None,
+            jitter: "0s".to_string(),
+        }
+    }
+}
+
+impl WatchConfig {
+    /// The cron `schedule` if set, else the `interval`. Both are checked by
+    /// the config's validate().
+    pub fn schedule(&self) -> Schedule {
+        match self.schedule.as_deref().map(schedule::parse_cron) {
+            Some(Ok(cron)) => Schedule::Cron(cron),
+            _ => Schedule::Interval(
+                schedule::parse_interval(&self.interval).unwrap_or(Duration::from_secs(6 * 3600)),
+            ),
+        }
+    }
+
+    pub fn jitter(&self) -> Duration {
+        schedule::parse_jitter(&self.jitter).unwrap_or_default()
+    }
+}
+
+pub struct WatchOptions {
+    pub schedule: Schedule,
+    /// Upper bound of the random delay added before every cycle.
+    pub jitter: Duration,
+    /// `--interval` or `--schedule` was given: reloads keep `schedule`.
+    pub fixed_schedule: bool,
+    /// `--jitter` was given: reloads keep `jitter`.
+    pub fixed_jitter: bool,
+    pub state_path: PathBuf,
+    /// Force a full scan every N cycles (0 = only the first cycle is full).
+    pub full_every: u64,
+    pub snapshot: bool,
+    /// Address for the Prometheus `/metrics` endpoint (e.g. 0.0.0.0:9464).
+    pub metrics_addr: Option<String>,
+    /// Path for node_exporter's textfile collector (e.g. /var/lib/node_exporter/warden.prom).
+    pub metrics_file: Option<PathBuf>,
+    pub notify: NotifyConfig,
+    pub history_path: Option<String>,
+    /// Findings of the latest cycle are written here (overwritten every cycle).
+    pub details_path: Option<PathBuf>,
+    /// Stage times of the latest cycle as folded stacks (`--folded`).
+    pub folded_path: Option<PathBuf>,
+    /// Every cycle writes its report bundle into a new directory under this one.
+    pub out_dir: Option<PathBuf>,
+    pub health: HealthConfig,
+    pub storage: StorageConfig,
+    /// Checked on full cycles only; incremental cycles see too few originals.
+    pub derived: Option<DerivedConfig>,
+    pub run: audit::RunOptions,
+    /// The config file, reloaded when it changes (see [`WatchOptions::apply`]).
+    pub reload: Option<octa_config::Watch<Config>>,
+}
+
+impl WatchOptions {
+    /// Takes the reloadable parts of a changed config: `warden.watch`
+    /// (unless its flags were given), `warden.notify`, `warden.health` and
+    /// `warden.derived`. Called between cycles, so a cycle is classified,
+    /// reported and notified under one config. The storage, the database
+    /// and the scan options are fixed at start.
+    pub fn apply(&mut self, config: Config) {
+        if !self.fixed_schedule {
+            self.schedule = config.warden.watch.schedule();
+        }
+        if !self.fixed_jitter {
+            self.jitter = config.warden.watch.jitter();
+        }
+        self.notify = config.warden.notify;
+        self.health = config.warden.health;
+        self.derived = config.warden.derived;
+    }
+
+    /// Applies the newest reload, if the file changed; a config that does
+    /// not load leaves the running one in place.
+    fn reload(&mut self) {
+        let Some(reload) = self.reload.as_ref().and_then(|watch| watch.changed()) else {
+            return;
+        };
+        match reload {
+            Ok(config) => {
+                self.apply(config);
+                info!(
+                    tag = "RELOAD",
+                    schedule = %self.schedule.describe(),
+                    jitter = ?self.jitter,
+                    "Configuration reloaded (watch, notify, health, derived)"
+                );
+            }
+            Err(e) => {
+                warn!(tag = "RELOAD", reason = %e, "Config change rejected, keeping the running config")
+            }
+        }
+    }
+}
+
+/// Rolling state persisted between cycles (and across restarts) so that
+/// each cycle only has to audit what changed since the previous one.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct WatchState {
+    cycles: u64,
+    /// Highest `updated_at` seen at the start of the last successful cycle.
+    watermark: Option<String>,
+    last_run_at: Option<u64>,
+    last_full_scan_at: Option<u64>,
+    /// Database file the last successful cycle scanned. Another file at the
+    /// same path (e.g. a restored backup) invalidates the watermark.
+    db_file: Option<db::FileId>,
+}
+
+impl WatchState {
+    fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, path: &Path) {
+        let result = serde_json::to_string_pretty(self)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(path, json).map_err(|e| e.to_string()));
+
+        if let Err(e) = result {
+            warn!(tag = "WARN", path = %path.display(), error = %e, "Could not persist watch state");
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Stays resident and audits the database on the configured schedule.
+/// A failing cycle is reported and retried on the next tick; it never stops the loop.
+pub fn run(db_path: &str, open_opts: &OpenOptions, mut opts: WatchOptions) {
+    let mut state = WatchState::load(&opts.state_path);
+
+    info!(
+        tag = "OK",
+        schedule = %opts.schedule.describe(),
+        jitter = ?opts.jitter,
+        state = %opts.state_path.display(),
+        reload = opts.reload.as_ref().map(|watch| watch.path().display().to_string()),
+        "Watch mode active"
+    );
+
+    let metrics = Metrics::default();
+    if let Some(addr) = &opts.metrics_addr {
+        match metrics.serve(addr) {
+            Ok(()) => info!(tag = "OK", "Metrics exposed at http://{}/metrics", addr),
+            Err(e) => {
+                error!(tag = "ERROR", addr = %addr, error = %e, "Could not bind metrics endpoint")
+            }
+        }
+    }
+
+    let mut first_cycle = true;
+
+    loop {
+        // Before the wait too, so a schedule edited during a cycle sets the
+        // wait that follows it.
+        if !first_cycle {
+            opts.reload();
+        }
+        let delay = opts.schedule.next_delay(first_cycle) + schedule::jitter(opts.jitter);
+        if !delay.is_zero() {
+            let at = Local::now() + chrono::Duration::from_std(delay).unwrap_or_default();
+            info!(
+                tag = "→",
+                at = %at.format("%Y-%m-%d %H:%M:%S"),
+                in_seconds = delay.as_secs(),
+                "Next audit scheduled"
+            );
+            thread::sleep(delay);
+        }
+        first_cycle = false;
+        opts.reload();
+
+        state.cycles += 1;
+        let mut full = state.watermark.is_none()
+            || (opts.full_every > 0 && state.cycles.is_multiple_of(opts.full_every));
+        if let (StorageConfig::Sqlite, Some(previous)) = (&opts.storage, state.db_file) {
+            if db::file_id(Path::new(db_path)).is_some_and(|current| current != previous) {
+                warn!(tag = "ROTATE", path = %db_path, "Database file was replaced since the last cycle, scanning it in full");
+                full = true;
+            }
+        }
+
+        info!(
+            tag = "CYCLE",
+            cycle = state.cycles,
+            scan = if full { "full" } else { "incremental" },
+            "Audit cycle starting"
+        );
+
+        let start = Instant::now();
+        match run_cycle(db_path, open_opts, &opts, &state, &mut full) {
+            Ok((mut result, watermark, db_file)) => {
+                let elapsed = start.elapsed();
+                health::classify(&mut result, &opts.health);
+                let assessment = health::assess(&result, &opts.health);
+                render_report(&result, elapsed, &assessment);
+                if let Some(path) = &opts.details_path {
+                    report::write_details(path, &result);
+                }
+                if let Some(path) = &opts.folded_path {
+                    report::write_folded(path, &result);
+                }
+                if let Some(dir) = &opts.out_dir {
+                    bundle::write(dir, &result, elapsed, &assessment);
+                }
+
+                let now = unix_now();
+                metrics.record_success(&result.stats, elapsed, now, full, state.cycles);
+                let diff = history::compare(opts.history_path.as_deref(), &result, full);
+                if let Some(diff) = &diff {
+                    report::render_diff(diff);
+                }
+                if opts.history_path.is_some() {
+                    let footprint = growth::measure(&opts.storage, db_path, open_opts);
+                    history::record_run(
+                        opts.history_path.as_deref(),
+                        &result,
+                        elapsed,
+                        full,
+                        footprint.as_ref(),
+                    );
+                }
+                notify::dispatch(
+                    &opts.notify,
+                    &result,
+                    diff.as_ref(),
+                    &format!("watch cycle #{}", state.cycles),
+                );
+                state.watermark = watermark.or(state.watermark.take());
+                state.last_run_at = Some(now);
+                if full {
+                    state.last_full_scan_at = Some(now);
+                }
+                if let StorageConfig::Sqlite = opts.storage {
+                    state.db_file = db_file;
+                }
+            }
+            Err(e) => {
+                metrics.record_failure(unix_now(), state.cycles);
+                error!(tag = "ERROR", cycle = state.cycles, error = %e, "Audit cycle failed");
+            }
+        }
+
+        state.save(&opts.state_path);
+        if let Some(path) = &opts.metrics_file {
+            metrics.write_textfile(path);
+        }
+    }
+}
+
+/// Scan result, new watermark and the database file that was scanned.
+type Cycle = (audit::AuditResult, Option<String>, Option<db::FileId>);
+
+/// `full` is set when a replaced database file forced a full scan.
+fn run_cycle(
+    db_path: &str,
+    open_opts: &OpenOptions,
+    opts: &WatchOptions,
+    state: &WatchState,
+    full: &mut bool,
+) -> Result<Cycle, String> {
+    // Files and objects carry no updated_at watermark, so every cycle is a full scan.
+    match &opts.storage {
+        StorageConfig::Sqlite => {
+            sqlite_cycle(db_path, open_opts, opts, state, full).map_err(|e| e.to_string())
+        }
+        StorageConfig::Fs { root } => {
+            backend::run(&Fs::new(root), &opts.run, &mut |_, _| {}).map(|r| (r, None, None))
+        }
+        StorageConfig::S3(cfg) => {
+            let s3 = S3::new(cfg).map_err(|e| e.to_string())?;
+            backend::run(&s3, &opts.run, &mut |_, _| {}).map(|r| (r, None, None))
+        }
+    }
+}
+
+/// Scans the database, reopening it when its file is replaced mid-scan (a
+/// restore renaming a copy into place): the open handle would keep reading
+/// the old file and report on a database that no longer exists. A snapshot
+/// is a private copy and is never reopened.
+fn sqlite_cycle(
+    db_path: &str,
+    open_opts: &OpenOptions,
+    opts: &WatchOptions,
+    state: &WatchState,
+    full: &mut bool,
+) -> rusqlite::Result<Cycle> {
+    let mut run_opts = opts.run.clone();
+    let mut reopens = 0;
+    loop {
+        // Taken before opening: a file swapped in between is caught as a
+        // replacement rather than scanned under the old identity.
+        let source = db::Source::new(db_path);
+        run_opts.source = source.clone().filter(|_| !opts.snapshot);
+        let (result, watermark) = sqlite_scan(db_path, open_opts, opts, &run_opts, state, *full)?;
+        if result.source_replaced && reopens < MAX_REOPENS {
+            reopens += 1;
+            warn!(tag = "ROTATE", path = %db_path, attempt = reopens, "Database file replaced mid-scan, reopening and scanning it in full");
+            *full = true;
+            continue;
+        }
+        let db_file = source.map(|source| source.id);
+        return Ok((result, watermark, db_file));
+    }
+}
+
+fn sqlite_scan(
+    db_path: &str,
+    open_opts: &OpenOptions,
+    opts: &WatchOptions,
+    run_opts: &audit::RunOptions,
+    state: &WatchState,
+    full: bool,
+) -> rusqlite::Result<(audit::AuditResult, Option<String>)> {
+    let target = db::attach(db_path, open_opts, opts.snapshot)?;
+
+    // Read the watermark before scanning: rows written during the scan are
+    // picked up again next cycle rather than silently skipped.
+    let watermark = audit::latest_update(&target.conn)?;
+
+    let scope = match (&state.watermark, full) {
+        (Some(mark), false) => Scope::UpdatedAfter(mark.clone()),
+        _ => Scope::Full,
+    };
+
+    let mut result = audit::run(&target.conn, &scope, run_opts)?;
+    if let (Scope::Full, Some(cfg)) = (&scope, &opts.derived) {
+        if result.aborted.is_none() {
+            derived::check(
+                &target.conn,
+                cfg,
+                run_opts.decryption.as_deref(),
+                run_opts.findings_stream.as_ref(),
+                &mut result,
+            )?;
+        }
+    }
+    if let (Scope::Full, None) = (&scope, &result.aborted) {
+        timestamps::check(&target.conn, run_opts.findings_stream.as_ref(), &mut result)?;
+    }
+
+    // An aborted scan did not see every row; keep the old watermark so
+    // the next cycle covers them.
+    if result.aborted.is_some() {
+        return Ok((result, None));
+    }
+    Ok((result, watermark))
+}
@@ -0,0 +1,24 @@
+//! A stored BLOB through the scan: decoding, ICC profile checks, the upload
+//! mode check, base64 detection and corruption causes. The first byte picks
+//! the recorded mode (or none); the rest is the BLOB.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use octa_warden_core::audit;
+
+const MODES: [Option<&str>; 6] = [
+    None,
+    Some("square"),
+    Some("circle"),
+    Some("fit"),
+    Some("scale"),
+    Some("original"),
+];
+
+fuzz_target!(|input: &[u8]| {
+    let [mode, blob @ ..] = input else {
+        return;
+    };
+    audit::inspect_blob(blob, MODES[*mode as usize % MODES.len()]);
+});
@@ -0,0 +1,39 @@
+//! An upload through `octa_image::validate` and `process`, as octa-server
+//! handles it. The first three bytes pick the mode, size and scale; the rest
+//! is the file. Whatever a mode stores must pass warden's upload mode check.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use octa_image::{Mode, Profile, DEFAULT_MAX_UPLOAD, SCALES, SIZES};
+
+const MODES: [Mode; 5] = [
+    Mode::Square,
+    Mode::Circle,
+    Mode::Fit,
+    Mode::Scale,
+    Mode::Original,
+];
+
+fuzz_target!(|input: &[u8]| {
+    let [mode, size, scale, data @ ..] = input else {
+        return;
+    };
+    let profile = Profile {
+        mode: MODES[*mode as usize % MODES.len()],
+        size: (SIZES.start() + *size as u32 * 8).min(*SIZES.end()),
+        scale: SCALES.start() + *scale as u32 % SCALES.end(),
+    };
+    if octa_image::validate(data, DEFAULT_MAX_UPLOAD).is_err() {
+        return;
+    }
+    let Ok(processed) = octa_image::process(data.to_vec(), &profile) else {
+        return;
+    };
+    let format = octa_image::sniff(&processed.data);
+    if let Some(problem) =
+        octa_image::verify(profile.mode, processed.width, processed.height, format)
+    {
+        panic!("{:?} stored an image warden rejects: {}", profile, problem);
+    }
+});
@@ -0,0 +1,1813 @@
+use clap::{ArgGroup, CommandFactory, Parser, Subcommand};
+use console::style;
+use octa_cdn::{CdnConfig, Purger};
+use octa_errors::Kind;
+use octa_ledger::{Ledger, LedgerConfig};
+use octa_storage::{Fs, Location, Sqlite, S3};
+use rusqlite::Result;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn, Level};
+
+mod triage;
+
+use octa_warden_core::storage::StorageConfig;
+use octa_warden_core::stream::FindingStream;
+use octa_warden_core::{
+    analytics, audit, backend, base64_blob, bundle, cas, color, compression, config, db, dedup,
+    derived, encryption, erasure, export, growth, health, history, import, migrate, notify, plan,
+    report, restore, retention, scaffold, schedule, schema, timestamps, watch,
+};
+
+/*
+OCTA-WARDEN: SQLite Integrity Auditor
+=============================================
+Mission: Audit SQLite BLOB assets without service interruption.
+Safety:  Uses READ_ONLY mode and fail-safe iteration. The only writes to the
+         Octa database are explicit `triage --apply`, `import`,
+         `--enforce-retention --execute`, `--encrypt-at-rest --execute`,
+         `--decrypt --execute`, `--content-address --execute`,
+         `--inline --execute`, `--repair-from --execute`,
+         `--migrate-schema` and `--fix` runs.
+*/
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Database Integrity Guard for Octa")]
+#[command(group(ArgGroup::new("dry_run").args(["enforce_retention", "encrypt_at_rest", "decrypt", "content_address", "inline", "fix", "repair_from"])))]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long, global = true)]
+    config: Option<String>,
+
+    /// SQLite database to audit (overrides database.path; the config file becomes optional)
+    #[arg(long = "db", global = true, env = "OCTA_DB_PATH", value_name = "PATH")]
+    db_path: Option<String>,
+
+    /// Milliseconds to wait on a locked database before giving up (SQLITE_BUSY)
+    #[arg(long, global = true, default_value_t = 5000)]
+    busy_timeout: u64,
+
+    /// Open the database as immutable (no locking, WAL ignored). Only for offline copies
+    #[arg(long, global = true, conflicts_with = "snapshot")]
+    immutable: bool,
+
+    /// Audit a point-in-time copy taken via the SQLite backup API instead of the live file
+    #[arg(long, global = true)]
+    snapshot: bool,
+
+    /// Stop early once this many findings (e.g. 500) or this share of rows (e.g. 2%) is reached
+    #[arg(long, global = true, value_parser = audit::parse_finding_limit)]
+    max_findings: Option<audit::FindingLimit>,
+
+    /// Order of the SQLite scan (newest-first: recently written assets are checked first)
+    #[arg(long, global = true, value_enum, default_value = "table")]
+    order: audit::ScanOrder,
+
+    /// What newest means for --order newest-first
+    #[arg(long, global = true, value_enum, default_value = "rowid")]
+    order_by: audit::OrderKey,
+
+    /// Split SQLite scans into this many rowid ranges, each on its own connection and decoders
+    #[arg(long, global = true, default_value_t = 1, value_name = "N")]
+    readers: usize,
+
+    /// Decode workers shared between the --readers (default: one per CPU core)
+    #[arg(long, global = true, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: Option<u16>,
+
+    /// SQLite file recording every run (overrides warden.history_path in the config)
+    #[arg(long, global = true)]
+    history: Option<String>,
+
+    /// Summary only: hide per-row findings and progress lines, print just the final report
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Write every finding of the run to this file as TSV (useful together with --quiet)
+    #[arg(long, global = true)]
+    details: Option<PathBuf>,
+
+    /// Write each finding as a JSON line the moment it is found, to FILE or - (stdout; logs move to stderr)
+    #[arg(long, global = true, value_name = "FILE|-")]
+    findings_stream: Option<String>,
+
+    /// Time the read, sniff and decode of every asset and report statistics per stage
+    #[arg(long, global = true)]
+    profile_stages: bool,
+
+    /// Write the stage times as folded stacks to this file, for flamegraph.pl, inferno or speedscope (implies --profile-stages)
+    #[arg(long, global = true, value_name = "FILE")]
+    folded: Option<PathBuf>,
+
+    /// Write the text, JSON, CSV and HTML reports into a timestamped directory under DIR
+    #[arg(long, global = true, value_name = "DIR")]
+    out: Option<PathBuf>,
+
+    /// Stream every asset that passes the audit into this .tar.zst archive, with a manifest
+    #[arg(long, value_name = "FILE")]
+    export_healthy: Option<PathBuf>,
+
+    /// Estimate savings of re-encoding as WebP/AVIF from a sample of PERCENT of healthy assets
+    #[arg(long, value_name = "PERCENT", num_args = 0..=1, default_missing_value = "5", value_parser = parse_percent)]
+    compression_audit: Option<f64>,
+
+    /// Repair findings: regenerate (warden.derived sizes), decode-base64 (base64 text in data), dedup or srgb (ICC profiles)
+    #[arg(long, value_enum, value_name = "MODE")]
+    fix: Option<audit::FixMode>,
+
+    /// Restore corrupt assets from the same keys in this backup database (dry-run)
+    #[arg(long, value_name = "BACKUP_DB")]
+    repair_from: Option<PathBuf>,
+
+    /// List assets past the warden.retention age limits instead of auditing (dry-run)
+    #[arg(long)]
+    enforce_retention: bool,
+
+    /// Encrypt plaintext BLOBs with the warden.encryption key (dry-run; resumable)
+    #[arg(long)]
+    encrypt_at_rest: bool,
+
+    /// Turn encrypted BLOBs back into plain images (dry-run; resumable)
+    #[arg(long)]
+    decrypt: bool,
+
+    /// Store image bytes content-addressed, one blob per distinct image (dry-run; resumable)
+    #[arg(long)]
+    content_address: bool,
+
+    /// Move content-addressed image bytes back into images.data, for the Go server (dry-run; resumable)
+    #[arg(long)]
+    inline: bool,
+
+    /// Actually write what --enforce-retention, --encrypt-at-rest, --decrypt, --content-address, --inline, --fix dedup or --repair-from list
+    #[arg(long, requires = "dry_run")]
+    execute: bool,
+
+    /// Hash every content-addressed blob again and check the references to them
+    #[arg(long, conflicts_with = "dry_run")]
+    verify_blobs: bool,
+
+    /// Upgrade the database to the server's canonical schema (backup first, one transaction)
+    #[arg(long, conflicts_with = "dry_run")]
+    migrate_schema: bool,
+
+    /// Output format for logs and the final report
+    #[arg(long, env = "OCTA_LOG_FORMAT", global = true, value_enum, default_value = "pretty")]
+    log_format: octa_logging::LogFormat,
+
+    /// Minimum log level (error, warn, info, debug, trace)
+    #[arg(long, global = true, default_value = "info")]
+    log_level: Level,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Stay resident and run audits on a schedule
+    Watch {
+        /// Time between audits, e.g. 30m, 6h, 1d (overrides warden.watch.interval; default 6h)
+        #[arg(long, value_parser = schedule::parse_interval)]
+        interval: Option<Duration>,
+
+        /// Cron expression for audit times in local time, e.g. "0 3 * * *" (overrides --interval and warden.watch.schedule)
+        #[arg(long, value_parser = schedule::parse_cron)]
+        schedule: Option<Box<croner::Cron>>,
+
+        /// Random delay of up to this long before each audit, e.g. 15m, to spread a fleet (overrides warden.watch.jitter)
+        #[arg(long, value_parser = schedule::parse_jitter)]
+        jitter: Option<Duration>,
+
+        /// File used to keep rolling state between cycles and restarts
+        #[arg(long, default_value = "warden-state.json")]
+        state: PathBuf,
+
+        /// Force a full scan every N cycles (0 = incremental after the first cycle)
+        #[arg(long, default_value_t = 0)]
+        full_every: u64,
+
+        /// Serve Prometheus metrics on this address (e.g. 0.0.0.0:9464)
+        #[arg(long)]
+        metrics_addr: Option<String>,
+
+        /// Write Prometheus metrics to this file after every cycle (textfile collector)
+        #[arg(long)]
+        metrics_file: Option<PathBuf>,
+    },
+    /// Browse findings interactively and mark assets for quarantine, delete or repair
+    Triage {
+        /// Action plan written by the browser (and resumed from, if it exists)
+        #[arg(long, default_value = "warden-plan.json")]
+        plan: PathBuf,
+
+        /// Execute the plan against the database instead of opening the browser
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Copy every healthy asset out of the SQLite database into another storage backend
+    Migrate {
+        /// Destination, e.g. fs:/var/lib/octa/assets or s3:bucket/prefix/
+        #[arg(long, value_parser = Location::parse)]
+        to: Location,
+    },
+    /// Load a directory tree or bucket prefix into the SQLite database
+    Import {
+        /// Source, e.g. fs:/srv/dump or s3:bucket/prefix/
+        #[arg(long, value_parser = Location::parse)]
+        from: Location,
+
+        /// Assets inserted per transaction
+        #[arg(long, default_value_t = 500)]
+        batch_size: usize,
+
+        /// Write every file that was not imported, with the reason, to this TSV file
+        #[arg(long)]
+        report: Option<PathBuf>,
+    },
+    /// Confirm that purged keys or asset IDs are gone and write a signed attestation
+    VerifyDeleted {
+        /// File with one key or asset ID per line (blank lines and # comments are ignored)
+        #[arg(long)]
+        ids_file: PathBuf,
+
+        /// Where to write the attestation
+        #[arg(long, default_value = "deletion-attestation.json")]
+        out: PathBuf,
+    },
+    /// Write asset metadata and recorded findings as Parquet files, for DuckDB or Spark
+    Export {
+        /// Directory for assets.parquet, and runs.parquet and findings.parquet from the history
+        #[arg(long, default_value = "warden-export")]
+        out: PathBuf,
+    },
+    /// Show recorded audit runs and the corruption trend
+    History {
+        /// Number of most recent runs to show
+        #[arg(long, default_value_t = 30)]
+        limit: usize,
+    },
+    /// Write a commented config file for the database given with --db
+    Init {
+        /// Where to write the config
+        #[arg(long, default_value = "warden.yaml")]
+        out: PathBuf,
+
+        /// Overwrite the file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print a shell completion script (bash, zsh, fish, elvish, powershell)
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+}
+
+/// The octa-warden command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa warden` the arguments it was given.
+pub fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    match run(args) {
+        Ok(code) => code,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Database error");
+            octa_errors::Error::from(e).exit_code()
+        }
+    }
+}
+
+/// What the commands after setup share: the checked config and how the
+/// database is opened and scanned.
+struct Context {
+    config: config::Config,
+    open_opts: db::OpenOptions,
+    run_opts: audit::RunOptions,
+    history_path: Option<String>,
+    snapshot: bool,
+    start: Instant,
+}
+
+impl Context {
+    fn db_path(&self) -> &str {
+        &self.config.database.path
+    }
+}
+
+/// Exits 0, 1 or 2 for a healthy, warning or critical database; any
+/// failure to run exits with its [`Kind`]'s code instead.
+fn run(args: impl IntoIterator<Item = std::ffi::OsString>) -> Result<ExitCode> {
+    let mut args = Args::parse_from(args);
+    let start = Instant::now();
+
+    // Completion scripts go to stdout as-is: no banner, no logging.
+    if let Some(Command::Completions { shell }) = args.command {
+        clap_complete::generate(
+            shell,
+            &mut Args::command(),
+            "octa-warden",
+            &mut std::io::stdout(),
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    octa_logging::init(
+        args.log_format,
+        args.log_level,
+        args.quiet,
+        args.findings_stream.as_deref() == Some("-"),
+    );
+    if octa_logging::is_human() && !octa_logging::is_quiet() {
+        print_banner();
+    }
+
+    // Runs before any config exists, so the database comes from --db only.
+    if let Some(Command::Init { out, force }) = &args.command {
+        return init_config(args.db_path.as_deref(), out, *force, args.busy_timeout);
+    }
+
+    let Some(config) = config::load(args.config.as_deref(), args.db_path.as_deref()) else {
+        return Ok(Kind::Config.exit_code());
+    };
+    let _telemetry = telemetry(&config.telemetry);
+
+    let history_path = args.history.take().or(config.warden.history_path.clone());
+
+    if let Some(Command::History { limit }) = args.command {
+        return show_history(history_path.as_deref(), limit);
+    }
+
+    let db_path = &config.database.path;
+    let storage = &config.warden.storage;
+
+    // Imports always write into the SQLite database, whatever backend is audited.
+    if let Some(Command::Import {
+        from,
+        batch_size,
+        report,
+    }) = args
+        .command
+        .take_if(|command| matches!(command, Command::Import { .. }))
+    {
+        if !Path::new(db_path).exists() {
+            error!(tag = "FATAL", path = %db_path, "Database file not found");
+            return Ok(Kind::NoInput.exit_code());
+        }
+        let opts = import::ImportOptions { batch_size, report };
+        let busy_timeout = Duration::from_millis(args.busy_timeout);
+        return import_assets(db_path, busy_timeout, storage, from, &opts);
+    }
+
+    match storage {
+        StorageConfig::Sqlite if !Path::new(db_path).exists() => {
+            error!(tag = "FATAL", path = %db_path, "Database file not found");
+            return Ok(Kind::NoInput.exit_code());
+        }
+        StorageConfig::Fs { root } if !root.is_dir() => {
+            error!(tag = "FATAL", path = %root.display(), "Storage root not found");
+            return Ok(Kind::NoInput.exit_code());
+        }
+        _ => {}
+    }
+
+    if let Some(operation) = sqlite_only(&args) {
+        if !matches!(storage, StorageConfig::Sqlite) {
+            error!(
+                tag = "FATAL",
+                backend = storage.name(),
+                operation,
+                "Works on the SQLite database only"
+            );
+            return Ok(Kind::Usage.exit_code());
+        }
+    }
+
+    if let Some(code) = check_fixes(&args, &config) {
+        return Ok(code);
+    }
+
+    let ctx = match context(&args, config, history_path, start) {
+        Ok(ctx) => ctx,
+        Err(code) => return Ok(code),
+    };
+    let config = &ctx.config;
+    let db_path = ctx.db_path();
+
+    if args.enforce_retention {
+        return enforce_retention(
+            db_path,
+            &ctx.open_opts,
+            &config.warden.retention,
+            config,
+            args.execute,
+        );
+    }
+
+    if args.encrypt_at_rest || args.decrypt {
+        let Some(key) = &ctx.run_opts.decryption else {
+            error!(
+                tag = "FATAL",
+                "No key configured. Add a warden.encryption section"
+            );
+            return Ok(Kind::Config.exit_code());
+        };
+        let direction = if args.decrypt {
+            encryption::Direction::Decrypt
+        } else {
+            encryption::Direction::Encrypt
+        };
+        return convert_encryption(
+            db_path,
+            &ctx.open_opts,
+            config,
+            key,
+            direction,
+            args.execute,
+        );
+    }
+
+    if args.verify_blobs {
+        return verify_blobs(db_path, &ctx.open_opts);
+    }
+
+    if args.content_address || args.inline {
+        let direction = if args.inline {
+            cas::Direction::Inline
+        } else {
+            cas::Direction::ContentAddressed
+        };
+        return convert_layout(db_path, &ctx.open_opts, direction, args.execute);
+    }
+
+    if args.migrate_schema {
+        let mut image_columns = vec!["data".to_string()];
+        image_columns.extend(config.warden.extra_blob_columns.iter().cloned());
+        return migrate_schema(db_path, &ctx.open_opts, &image_columns);
+    }
+
+    match args.command.take() {
+        Some(Command::Triage { plan, apply: true }) => {
+            apply_plan(db_path, &ctx.open_opts, &plan, config)
+        }
+        Some(Command::Triage { plan, apply: false }) => triage(&ctx, &plan),
+        Some(Command::Migrate { to }) => migrate_to(&ctx, &to),
+        Some(Command::Export { out }) => export_parquet(
+            db_path,
+            &ctx.open_opts,
+            ctx.snapshot,
+            ctx.history_path.as_deref(),
+            &out,
+        ),
+        Some(Command::VerifyDeleted { ids_file, out }) => verify_deleted(&ctx, &ids_file, &out),
+        Some(command @ Command::Watch { .. }) => Ok(watch(ctx, &args, command)),
+        _ => audit(&ctx, &args),
+    }
+}
+
+/// Loads the encryption key and opens the findings stream: what every
+/// command past the checks runs with.
+fn context(
+    args: &Args,
+    config: config::Config,
+    history_path: Option<String>,
+    start: Instant,
+) -> std::result::Result<Context, ExitCode> {
+    let decryption = match &config.warden.encryption {
+        Some(cfg) => match encryption::Key::load(cfg) {
+            Ok(key) => Some(Arc::new(key)),
+            Err(reason) => {
+                error!(tag = "FATAL", reason = %reason, "Could not load the blob encryption key");
+                return Err(Kind::Config.exit_code());
+            }
+        },
+        None => None,
+    };
+
+    let findings_stream = match &args.findings_stream {
+        Some(target) => match FindingStream::open(target) {
+            Ok(stream) => Some(stream),
+            Err(e) => {
+                error!(tag = "FATAL", path = %target, reason = %e, "Could not open the findings stream");
+                return Err(Kind::Io.exit_code());
+            }
+        },
+        None => None,
+    };
+
+    let open_opts = db::OpenOptions {
+        busy_timeout: Duration::from_millis(args.busy_timeout),
+        immutable: args.immutable,
+    };
+
+    let run_opts = audit::RunOptions {
+        max_findings: args.max_findings,
+        extra_blob_columns: config.warden.extra_blob_columns.clone(),
+        order: args.order,
+        order_by: args.order_by,
+        parallel: (args.readers > 1).then(|| audit::Parallel {
+            readers: args.readers,
+            jobs: args.jobs.map(usize::from),
+            open: open_opts.clone(),
+        }),
+        decryption,
+        source: None,
+        findings_stream,
+        profile_stages: args.profile_stages || args.folded.is_some(),
+    };
+
+    Ok(Context {
+        config,
+        open_opts,
+        run_opts,
+        history_path,
+        snapshot: args.snapshot,
+        start,
+    })
+}
+
+/// The flag or subcommand of `args` that needs the SQLite database as its
+/// storage, if any.
+fn sqlite_only(args: &Args) -> Option<&'static str> {
+    let flags = [
+        (args.fix.is_some(), "--fix"),
+        (args.repair_from.is_some(), "--repair-from"),
+        (args.enforce_retention, "--enforce-retention"),
+        (args.encrypt_at_rest, "--encrypt-at-rest"),
+        (args.decrypt, "--decrypt"),
+        (args.content_address, "--content-address"),
+        (args.inline, "--inline"),
+        (args.verify_blobs, "--verify-blobs"),
+        (args.migrate_schema, "--migrate-schema"),
+    ];
+    if let Some((_, flag)) = flags.iter().find(|(set, _)| *set) {
+        return Some(flag);
+    }
+    match args.command {
+        Some(Command::Triage { .. }) => Some("triage"),
+        Some(Command::Migrate { .. }) => Some("migrate"),
+        Some(Command::Export { .. }) => Some("export"),
+        Some(Command::VerifyDeleted { .. }) => Some("verify-deleted"),
+        _ => None,
+    }
+}
+
+/// Refuses a write run the config cannot support, before anything is
+/// written: `--fix` modes without what they need, a missing backup, an
+/// unwritable ledger.
+fn check_fixes(args: &Args, config: &config::Config) -> Option<ExitCode> {
+    if let Some(backup) = args.repair_from.as_ref().filter(|backup| !backup.is_file()) {
+        error!(tag = "FATAL", path = %backup.display(), "Backup database not found");
+        return Some(Kind::NoInput.exit_code());
+    }
+
+    // Repairs write plain image bytes, which an encrypting deployment cannot read back.
+    if matches!(
+        args.fix,
+        Some(audit::FixMode::Regenerate | audit::FixMode::DecodeBase64 | audit::FixMode::Srgb)
+    ) && config.warden.encryption.is_some()
+    {
+        error!(
+            tag = "FATAL",
+            "--fix writes unencrypted BLOBs and is not available with warden.encryption"
+        );
+        return Some(Kind::Usage.exit_code());
+    }
+
+    if args.fix == Some(audit::FixMode::Regenerate) && config.warden.derived.is_none() {
+        error!(
+            tag = "FATAL",
+            "--fix needs a warden.derived section describing the derived sizes"
+        );
+        return Some(Kind::Config.exit_code());
+    }
+
+    let writes = args.fix.is_some()
+        || args.execute
+        || matches!(&args.command, Some(Command::Triage { apply: true, .. }));
+    if writes {
+        if let Err(e) = Ledger::open(&config.ledger, "octa-warden") {
+            error!(tag = "FATAL", reason = %e, "Could not open the ledger");
+            return Some(Kind::Io.exit_code());
+        }
+    }
+    None
+}
+
+/// `history`: the most recent runs and the corruption trend.
+fn show_history(history_path: Option<&str>, limit: usize) -> Result<ExitCode> {
+    let Some(path) = history_path else {
+        error!(
+            tag = "FATAL",
+            "No history database configured. Use --history or warden.history_path"
+        );
+        return Ok(Kind::Config.exit_code());
+    };
+    let runs = history::History::open(path)?.recent(limit)?;
+    history::render_trend(&runs);
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `watch`: audits on the schedule until stopped.
+fn watch(ctx: Context, args: &Args, command: Command) -> ExitCode {
+    let Command::Watch {
+        interval,
+        schedule,
+        jitter,
+        state,
+        full_every,
+        metrics_addr,
+        metrics_file,
+    } = command
+    else {
+        unreachable!("called for watch only");
+    };
+    let configured = &ctx.config.warden.watch;
+    let fixed_schedule = schedule.is_some() || interval.is_some();
+    let opts = watch::WatchOptions {
+        schedule: match (schedule, interval) {
+            (Some(cron), _) => schedule::Schedule::Cron(cron),
+            (None, Some(interval)) => schedule::Schedule::Interval(interval),
+            (None, None) => configured.schedule(),
+        },
+        fixed_schedule,
+        fixed_jitter: jitter.is_some(),
+        jitter: jitter.unwrap_or_else(|| configured.jitter()),
+        state_path: state,
+        full_every,
+        snapshot: ctx.snapshot,
+        metrics_addr,
+        metrics_file,
+        notify: ctx.config.warden.notify.clone(),
+        history_path: ctx.history_path,
+        details_path: args.details.clone(),
+        folded_path: args.folded.clone(),
+        out_dir: args.out.clone(),
+        health: ctx.config.warden.health.clone(),
+        storage: ctx.config.warden.storage.clone(),
+        derived: ctx.config.warden.derived.clone(),
+        run: ctx.run_opts,
+        // Reloaded as it was loaded: same file, same --db.
+        reload: config::path(args.config.as_deref())
+            .map(|path| config::watch(&path, args.db_path.clone())),
+    };
+    watch::run(&ctx.config.database.path, &ctx.open_opts, opts);
+    ExitCode::SUCCESS
+}
+
+/// `triage`: audits the database and opens the browser on its findings.
+fn triage(ctx: &Context, plan: &Path) -> Result<ExitCode> {
+    let target = db::attach(ctx.db_path(), &ctx.open_opts, ctx.snapshot)?;
+    let mut result = audit::run(&target.conn, &audit::Scope::Full, &ctx.run_opts)?;
+    health::classify(&mut result, &ctx.config.warden.health);
+
+    if result.findings.is_empty() {
+        info!(tag = "OK", "No findings, nothing to triage");
+        return Ok(ExitCode::SUCCESS);
+    }
+    match triage::run(&target.conn, ctx.db_path(), result.findings, plan) {
+        Ok(marked) => info!(
+            tag = "OK",
+            plan = %plan.display(),
+            actions = marked,
+            "Triage finished. Review the plan, then run triage --apply"
+        ),
+        Err(e) => {
+            error!(tag = "ERROR", reason = %e, "Triage browser failed");
+            return Ok(Kind::Io.exit_code());
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `migrate`: copies every asset that passes the audit to `to`.
+fn migrate_to(ctx: &Context, to: &Location) -> Result<ExitCode> {
+    let mut migrator = match to.open(None) {
+        Ok(target) => migrate::Migrator::new(target),
+        Err(e) => {
+            error!(tag = "FATAL", target = %to.describe(), reason = %e, "Could not prepare migration target");
+            return Ok(Kind::Io.exit_code());
+        }
+    };
+
+    let target = db::attach(ctx.db_path(), &ctx.open_opts, ctx.snapshot)?;
+    info!(tag = "OK", target = %to.describe(), "Database connected. Migration starting...");
+    let mut result = audit::scan(
+        &target.conn,
+        &audit::Scope::Full,
+        &ctx.run_opts,
+        &mut |id, blob| migrator.add(id, blob),
+    )?;
+    let keys = export::load_keys(&target.conn)?;
+    drop(target);
+
+    let health = &ctx.config.warden.health;
+    health::classify(&mut result, health);
+    let assessment = health::assess(&result, health);
+    report::render_report(&result, ctx.start.elapsed(), &assessment);
+
+    Ok(match migrator.finish(&keys) {
+        Ok(summary) => {
+            info!(
+                tag = "MIGRATE",
+                migrated = summary.migrated,
+                resumed = summary.resumed,
+                failed = summary.failed,
+                bytes = summary.bytes,
+                skipped_findings = result.findings.len(),
+                "Migration finished"
+            );
+            if result.aborted.is_some() {
+                warn!(
+                    tag = "WARN",
+                    "Audit stopped early: run migrate again to continue"
+                );
+            }
+            if summary.failed > 0 || result.aborted.is_some() {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            }
+        }
+        Err(e) => {
+            error!(tag = "ERROR", reason = %e, "Could not finalize migration");
+            ExitCode::FAILURE
+        }
+    })
+}
+
+/// `verify-deleted`: looks the purged identifiers up and writes the signed
+/// attestation.
+fn verify_deleted(ctx: &Context, ids_file: &Path, out: &Path) -> Result<ExitCode> {
+    let config = &ctx.config;
+    let key = config
+        .warden
+        .attestation_key
+        .clone()
+        .or_else(|| std::env::var("WARDEN_ATTESTATION_KEY").ok())
+        .filter(|k| !k.is_empty());
+    let Some(key) = key else {
+        error!(
+            tag = "FATAL",
+            "No signing key configured. Set warden.attestation_key or WARDEN_ATTESTATION_KEY"
+        );
+        return Ok(Kind::Config.exit_code());
+    };
+    let raw = match std::fs::read_to_string(ids_file) {
+        Ok(raw) => raw,
+        Err(e) => {
+            error!(tag = "FATAL", path = %ids_file.display(), reason = %e, "Could not read IDs file");
+            return Ok(octa_errors::Error::from(e).exit_code());
+        }
+    };
+
+    let ledger = match &config.ledger.path {
+        Some(path) => match octa_ledger::read(path) {
+            Ok(entries) => Some(entries),
+            Err(e) => {
+                error!(tag = "FATAL", reason = %e, "Could not read the ledger");
+                return Ok(Kind::Io.exit_code());
+            }
+        },
+        None => None,
+    };
+    let target = db::attach(ctx.db_path(), &ctx.open_opts, ctx.snapshot)?;
+    let attestation = erasure::verify(
+        &target.conn,
+        ctx.db_path(),
+        ids_file,
+        &raw,
+        ledger.as_deref(),
+    )?;
+    drop(target);
+
+    for entry in attestation
+        .entries
+        .iter()
+        .filter(|e| !e.found_in.is_empty())
+    {
+        warn!(
+            target: octa_logging::FINDING_TARGET,
+            tag = "WARN",
+            id = %entry.id,
+            found_in = %entry.found_in.join(","),
+            "Still present"
+        );
+    }
+    if let Err(e) = erasure::write_signed(out, &attestation, &key) {
+        error!(tag = "ERROR", path = %out.display(), reason = %e, "Could not write attestation");
+        return Ok(ExitCode::FAILURE);
+    }
+    info!(
+        tag = if attestation.verified { "OK" } else { "ERROR" },
+        checked = attestation.checked,
+        still_present = attestation.still_present,
+        path = %out.display(),
+        "Deletion attestation written"
+    );
+    if attestation.orphaned_blobs > 0 {
+        warn!(
+            tag = "WARN",
+            blobs = attestation.orphaned_blobs,
+            "Blobs no asset refers to may still hold erased images"
+        );
+    }
+    Ok(if attestation.verified {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// The default run: audits the configured storage, applies `--fix` and
+/// `--repair-from`, then reports, records and notifies.
+fn audit(ctx: &Context, args: &Args) -> Result<ExitCode> {
+    let config = &ctx.config;
+    let storage = &config.warden.storage;
+    let run_opts = &ctx.run_opts;
+
+    let mut exporter = match &args.export_healthy {
+        Some(path) => match export::Exporter::create(path) {
+            Ok(exporter) => Some(exporter),
+            Err(e) => {
+                error!(tag = "FATAL", path = %path.display(), reason = %e, "Could not create export archive");
+                return Ok(Kind::Io.exit_code());
+            }
+        },
+        None => None,
+    };
+    let mut sampler = args.compression_audit.map(|percent| {
+        let jobs = args
+            .jobs
+            .map(usize::from)
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+        compression::Sampler::start(percent, jobs, run_opts.decryption.clone())
+    });
+    let mut on_healthy = |id: &str, blob: &[u8]| {
+        if let Some(exporter) = exporter.as_mut() {
+            exporter.add(id, blob);
+        }
+        if let Some(sampler) = sampler.as_mut() {
+            sampler.offer(id, blob);
+        }
+    };
+
+    let (mut result, source, keys) = match storage {
+        StorageConfig::Sqlite => {
+            let (result, keys) = audit_sqlite(ctx, args, &mut on_healthy)?;
+            (result, ctx.db_path().to_string(), keys)
+        }
+        StorageConfig::Fs { root } => {
+            info!(
+                tag = "OK",
+                root = %root.display(),
+                "Storage root found. Filesystem audit starting..."
+            );
+            match backend::run(&Fs::new(root), run_opts, &mut on_healthy) {
+                Ok(result) => (result, root.display().to_string(), HashMap::new()),
+                Err(e) => {
+                    error!(tag = "FATAL", reason = %e, "Filesystem audit failed");
+                    return Ok(Kind::Io.exit_code());
+                }
+            }
+        }
+        StorageConfig::S3(s3_cfg) => {
+            info!(
+                tag = "OK",
+                bucket = %s3_cfg.describe(),
+                "Object storage audit starting..."
+            );
+            let result = S3::new(s3_cfg)
+                .map_err(|e| e.to_string())
+                .and_then(|s3| backend::run(&s3, run_opts, &mut on_healthy));
+            match result {
+                Ok(result) => (result, s3_cfg.describe(), HashMap::new()),
+                Err(e) => {
+                    error!(tag = "FATAL", reason = %e, "Object storage audit failed");
+                    return Ok(Kind::Unavailable.exit_code());
+                }
+            }
+        }
+    };
+
+    if let (Some(exporter), Some(path)) = (exporter, &args.export_healthy) {
+        finish_export(exporter, keys, &source, path, result.aborted.is_none());
+    }
+
+    let elapsed = ctx.start.elapsed();
+    health::classify(&mut result, &config.warden.health);
+    let assessment = health::assess(&result, &config.warden.health);
+    let survey = sampler.map(|sampler| {
+        info!(
+            tag = "→",
+            "Waiting for the compression sample to finish encoding..."
+        );
+        sampler.finish()
+    });
+    report::render_report(&result, elapsed, &assessment);
+    if let Some(survey) = &survey {
+        report::render_survey(survey);
+    }
+    if let Some(path) = &args.details {
+        report::write_details(path, &result);
+    }
+    if let Some(path) = &args.folded {
+        report::write_folded(path, &result);
+    }
+    if let Some(dir) = &args.out {
+        bundle::write(dir, &result, elapsed, &assessment);
+    }
+    let history_path = ctx.history_path.as_deref();
+    let diff = history::compare(history_path, &result, true);
+    if let Some(diff) = &diff {
+        report::render_diff(diff);
+    }
+    let footprint =
+        history_path.and_then(|_| growth::measure(storage, ctx.db_path(), &ctx.open_opts));
+    history::record_run(history_path, &result, elapsed, true, footprint.as_ref());
+    notify::dispatch(
+        &config.warden.notify,
+        &result,
+        diff.as_ref(),
+        "manual audit",
+    );
+
+    Ok(assessment.verdict.exit_code())
+}
+
+/// Scans the SQLite database, with the derived and timestamp checks, then
+/// runs the `--fix` mode and `--repair-from` on what was found. The result
+/// and the keys for `--export-healthy`.
+fn audit_sqlite(
+    ctx: &Context,
+    args: &Args,
+    on_healthy: &mut dyn FnMut(&str, &[u8]),
+) -> Result<(audit::AuditResult, HashMap<String, Vec<String>>)> {
+    let config = &ctx.config;
+    let run_opts = &ctx.run_opts;
+    if ctx.snapshot {
+        info!(
+            tag = "→",
+            "Taking point-in-time snapshot of the database..."
+        );
+    }
+    let target = db::attach(ctx.db_path(), &ctx.open_opts, ctx.snapshot)?;
+
+    info!(
+        tag = "OK",
+        "Database connected. Integrity audit starting..."
+    );
+
+    let mut result = audit::scan(&target.conn, &audit::Scope::Full, run_opts, on_healthy)?;
+    let defects = match &config.warden.derived {
+        Some(cfg) if result.aborted.is_none() => derived::check(
+            &target.conn,
+            cfg,
+            run_opts.decryption.as_deref(),
+            run_opts.findings_stream.as_ref(),
+            &mut result,
+        )?,
+        _ => Vec::new(),
+    };
+    if result.aborted.is_none() {
+        timestamps::check(&target.conn, run_opts.findings_stream.as_ref(), &mut result)?;
+    }
+    let keys = match args.export_healthy {
+        Some(_) => export::load_keys(&target.conn)?,
+        None => HashMap::new(),
+    };
+
+    // Release the connection (and remove any snapshot) before reporting.
+    drop(target);
+
+    match args.fix {
+        Some(audit::FixMode::Regenerate) => regenerate_derived(ctx, &defects)?,
+        Some(mode @ (audit::FixMode::DecodeBase64 | audit::FixMode::Srgb)) => {
+            repair_findings(ctx, mode, &result)?
+        }
+        Some(audit::FixMode::Dedup) => fix_duplicates(
+            ctx.db_path(),
+            &ctx.open_opts,
+            config,
+            run_opts,
+            args.execute,
+        )?,
+        None => {}
+    }
+    if let Some(backup) = &args.repair_from {
+        repair_from_backup(
+            ctx.db_path(),
+            backup,
+            &ctx.open_opts,
+            &result,
+            run_opts,
+            config,
+            args.execute,
+        )?;
+    }
+    Ok((result, keys))
+}
+
+/// `--fix regenerate`: rewrites the derived sizes the audit found missing or
+/// stale.
+fn regenerate_derived(ctx: &Context, defects: &[derived::Defect]) -> Result<()> {
+    let Some(cfg) = &ctx.config.warden.derived else {
+        return Ok(());
+    };
+    if defects.is_empty() {
+        return Ok(());
+    }
+    let mut conn = db::open_read_write(ctx.db_path(), &ctx.open_opts)?;
+    let written = derived::regenerate(&mut conn, cfg, defects)?;
+    info!(
+        tag = "OK",
+        regenerated = written,
+        found = defects.len(),
+        "Derivatives regenerated. The report below shows the state before the fix"
+    );
+    let mut originals: Vec<String> = defects.iter().map(|d| d.original.clone()).collect();
+    originals.dedup();
+    record(
+        &ctx.config.ledger,
+        "fix.regenerate",
+        &originals,
+        &[("regenerated", written.to_string())],
+    );
+    Ok(())
+}
+
+/// `--fix decode-base64` and `--fix srgb`: rewrites the `images.data` of
+/// every asset with a finding of the mode's kind.
+fn repair_findings(ctx: &Context, mode: audit::FixMode, result: &audit::AuditResult) -> Result<()> {
+    let kind = match mode {
+        audit::FixMode::DecodeBase64 => audit::FindingKind::Base64Blob,
+        _ => audit::FindingKind::ColorProfile,
+    };
+    let ids: Vec<String> = result
+        .findings
+        .iter()
+        .filter(|f| f.kind == kind && f.column.is_none())
+        .filter_map(|f| f.id.clone())
+        .collect();
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let mut conn = db::open_read_write(ctx.db_path(), &ctx.open_opts)?;
+    let (action, detail, written) = match mode {
+        audit::FixMode::DecodeBase64 => {
+            let written = base64_blob::repair(&mut conn, &ids)?;
+            info!(
+                tag = "OK",
+                decoded = written,
+                found = ids.len(),
+                "Base64 rows rewritten as BLOBs. The report below shows the state before the fix"
+            );
+            ("fix.decode-base64", "decoded", written)
+        }
+        _ => {
+            let written = color::repair(&mut conn, &ids)?;
+            info!(
+                tag = "OK",
+                converted = written,
+                found = ids.len(),
+                "Images converted to sRGB. The report below shows the state before the fix"
+            );
+            ("fix.srgb", "converted", written)
+        }
+    };
+    let keys = export::keys_of(&conn, &ids)?;
+    record(
+        &ctx.config.ledger,
+        action,
+        &[ids, keys.clone()].concat(),
+        &[(detail, written.to_string())],
+    );
+    purge_cdn(&ctx.config.cdn, &keys);
+    Ok(())
+}
+
+/// `export`: assets.parquet from the database (or its --snapshot), and
+/// runs.parquet and findings.parquet from the history, when there is one.
+fn export_parquet(
+    db_path: &str,
+    open_opts: &db::OpenOptions,
+    snapshot: bool,
+    history_path: Option<&str>,
+    out: &Path,
+) -> Result<ExitCode> {
+    if let Err(e) = std::fs::create_dir_all(out) {
+        error!(tag = "FATAL", path = %out.display(), reason = %e, "Could not create export directory");
+        return Ok(octa_errors::Error::from(e).exit_code());
+    }
+    let written = |file: &str, result: std::result::Result<usize, analytics::Error>| match result {
+        Ok(rows) => {
+            info!(tag = "OK", path = %out.join(file).display(), rows, "Parquet file written");
+            true
+        }
+        Err(e) => {
+            error!(tag = "ERROR", path = %out.join(file).display(), reason = %e, "Parquet export failed");
+            false
+        }
+    };
+
+    let target = db::attach(db_path, open_opts, snapshot)?;
+    let mut ok = written(
+        "assets.parquet",
+        analytics::write_assets(&target.conn, &out.join("assets.parquet")),
+    );
+    drop(target);
+
+    match history_path.filter(|path| Path::new(path).exists()) {
+        Some(path) => {
+            let history = history::History::open(path)?;
+            ok &= written(
+                "runs.parquet",
+                analytics::write_runs(history.conn(), &out.join("runs.parquet")),
+            );
+            ok &= written(
+                "findings.parquet",
+                analytics::write_findings(history.conn(), &out.join("findings.parquet")),
+            );
+        }
+        None => warn!(
+            tag = "WARN",
+            "No history database: findings not exported. Record audits with --history or warden.history_path"
+        ),
+    }
+    Ok(if ok {
+        ExitCode::SUCCESS
+    } else {
+        Kind::Io.exit_code()
+    })
+}
+
+fn finish_export(
+    exporter: export::Exporter,
+    keys: HashMap<String, Vec<String>>,
+    source: &str,
+    path: &Path,
+    complete: bool,
+) {
+    match exporter.finish(keys, source, complete) {
+        Ok(summary) => {
+            info!(
+                tag = "EXPORT",
+                path = %path.display(),
+                assets = summary.assets,
+                bytes = summary.bytes,
+                "Healthy assets exported"
+            );
+            if !complete {
+                warn!(
+                    tag = "WARN",
+                    path = %path.display(),
+                    "Audit stopped early: the export only covers the rows scanned so far"
+                );
+            }
+        }
+        Err(e) => {
+            error!(tag = "ERROR", path = %path.display(), reason = %e, "Export failed");
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+fn import_assets(
+    db_path: &str,
+    busy_timeout: Duration,
+    storage: &StorageConfig,
+    from: Location,
+    opts: &import::ImportOptions,
+) -> Result<ExitCode> {
+    if let Location::Fs(root) = &from {
+        if !root.is_dir() {
+            error!(tag = "FATAL", path = %root.display(), "Import source not found");
+            return Ok(Kind::NoInput.exit_code());
+        }
+    }
+    let db = match Sqlite::open(db_path, busy_timeout) {
+        Ok(db) => db,
+        Err(e) => {
+            error!(tag = "FATAL", path = %db_path, reason = %e, "Could not open the database");
+            return Ok(Kind::Io.exit_code());
+        }
+    };
+    let base = match storage {
+        StorageConfig::S3(cfg) => Some(cfg),
+        _ => None,
+    };
+    let source = match from.open(base) {
+        Ok(source) => source,
+        Err(e) => {
+            error!(tag = "FATAL", source = %from.describe(), reason = %e, "Could not open import source");
+            return Ok(Kind::Unavailable.exit_code());
+        }
+    };
+
+    let keys = import::migrated_keys(source.as_ref());
+    let summary = match import::run(&db, source.as_ref(), &keys, opts) {
+        Ok(summary) => summary,
+        Err(e @ octa_storage::Error::Remote(_)) => {
+            error!(tag = "FATAL", source = %from.describe(), reason = %e, "Import failed");
+            return Ok(Kind::Unavailable.exit_code());
+        }
+        Err(e) => {
+            error!(tag = "FATAL", source = %from.describe(), reason = %e, "Import failed");
+            return Ok(Kind::Io.exit_code());
+        }
+    };
+
+    info!(
+        tag = "IMPORT",
+        imported = summary.imported,
+        skipped = summary.skipped,
+        invalid = summary.invalid,
+        "Import finished"
+    );
+    Ok(if summary.invalid > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn enforce_retention(
+    db_path: &str,
+    open_opts: &db::OpenOptions,
+    rules: &[retention::RetentionRule],
+    config: &config::Config,
+    execute: bool,
+) -> Result<ExitCode> {
+    if rules.is_empty() {
+        error!(
+            tag = "FATAL",
+            "No retention rules configured. Add them under warden.retention"
+        );
+        return Ok(Kind::Config.exit_code());
+    }
+
+    let mut conn = if execute {
+        db::open_read_write(db_path, open_opts)?
+    } else {
+        db::open_read_only(db_path, open_opts)?
+    };
+    let expired = match retention::find_expired(&conn, rules) {
+        Ok(expired) => expired,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Could not evaluate retention rules");
+            return Ok(Kind::Io.exit_code());
+        }
+    };
+
+    let verb = if execute { "Deleting" } else { "Would delete" };
+    for asset in &expired {
+        info!(
+            tag = "EXPIRE",
+            id = %asset.id,
+            keys = %asset.keys.join(","),
+            bytes = asset.bytes,
+            age_days = asset.age.as_secs() / 86400,
+            rule = %asset.rule,
+            "{}",
+            verb
+        );
+    }
+    let bytes: u64 = expired.iter().map(|a| a.bytes).sum();
+
+    if !execute {
+        info!(
+            tag = "OK",
+            assets = expired.len(),
+            bytes,
+            "Dry run: nothing deleted. Re-run with --execute to remove these assets"
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let deleted = retention::delete(&mut conn, &expired)?;
+    for id in &deleted.skipped {
+        warn!(tag = "SKIP", id = %id, "Changed since it was listed and left alone");
+    }
+    let gone: HashSet<&str> = deleted.removed.iter().map(String::as_str).collect();
+    let removed: Vec<&retention::Expired> = expired
+        .iter()
+        .filter(|a| gone.contains(a.id.as_str()))
+        .collect();
+    let bytes: u64 = removed.iter().map(|a| a.bytes).sum();
+    info!(
+        tag = "OK",
+        assets = removed.len(),
+        skipped = deleted.skipped.len(),
+        bytes,
+        "Expired assets deleted"
+    );
+    let keys: Vec<String> = removed.iter().flat_map(|a| a.keys.clone()).collect();
+    record(
+        &config.ledger,
+        "retention.delete",
+        &[deleted.removed.clone(), keys.clone()].concat(),
+        &[
+            ("assets", removed.len().to_string()),
+            ("bytes", bytes.to_string()),
+        ],
+    );
+    purge_cdn(&config.cdn, &keys);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn fix_duplicates(
+    db_path: &str,
+    open_opts: &db::OpenOptions,
+    config: &config::Config,
+    run_opts: &audit::RunOptions,
+    execute: bool,
+) -> Result<()> {
+    let key = run_opts.decryption.as_deref();
+    let mut conn = if execute {
+        db::open_read_write(db_path, open_opts)?
+    } else {
+        db::open_read_only(db_path, open_opts)?
+    };
+    let groups = dedup::find(&conn, key)?;
+    let duplicates: usize = groups.iter().map(|g| g.duplicates.len()).sum();
+    let bytes: u64 = groups.iter().map(dedup::Group::savings).sum();
+
+    if !execute {
+        for group in &groups {
+            info!(
+                tag = "DEDUP",
+                canonical = %group.canonical,
+                duplicates = %group.duplicates.join(","),
+                bytes = group.savings(),
+                "Would merge"
+            );
+        }
+        info!(
+            tag = "OK",
+            groups = groups.len(),
+            duplicates,
+            bytes,
+            "Dry run: nothing merged. Re-run with --execute to merge these duplicates"
+        );
+        return Ok(());
+    }
+
+    let summary = dedup::merge(&mut conn, &groups, key, config.warden.derived.as_ref())?;
+    info!(
+        tag = "OK",
+        groups = summary.groups,
+        removed = summary.removed,
+        bytes = summary.bytes,
+        skipped = summary.skipped,
+        "Duplicates merged (VACUUM to return the space to the file system). The report below shows the state before the fix"
+    );
+    // Every candidate; a group skipped because a row changed is counted in `skipped`.
+    let duplicates: Vec<String> = groups.iter().flat_map(|g| g.duplicates.clone()).collect();
+    record(
+        &config.ledger,
+        "fix.dedup",
+        &duplicates,
+        &[
+            ("groups", summary.groups.to_string()),
+            ("removed", summary.removed.to_string()),
+            ("skipped", summary.skipped.to_string()),
+            ("bytes", summary.bytes.to_string()),
+        ],
+    );
+    Ok(())
+}
+
+fn repair_from_backup(
+    db_path: &str,
+    backup: &Path,
+    open_opts: &db::OpenOptions,
+    result: &audit::AuditResult,
+    run_opts: &audit::RunOptions,
+    config: &config::Config,
+    execute: bool,
+) -> Result<()> {
+    let ids: Vec<String> = result
+        .findings
+        .iter()
+        .filter(|f| {
+            matches!(
+                f.kind,
+                audit::FindingKind::CorruptBlob | audit::FindingKind::DecryptFailed
+            ) && f.column.is_none()
+        })
+        .filter_map(|f| f.id.clone())
+        .collect();
+    if ids.is_empty() {
+        info!(tag = "OK", "No corrupt assets to repair");
+        return Ok(());
+    }
+
+    let backup_conn = db::open_read_only(&backup.display().to_string(), open_opts)?;
+    let mut conn = if execute {
+        db::open_read_write(db_path, open_opts)?
+    } else {
+        db::open_read_only(db_path, open_opts)?
+    };
+    let summary = restore::repair(
+        &mut conn,
+        &backup_conn,
+        &ids,
+        run_opts.decryption.as_deref(),
+        execute,
+    )?;
+    if execute {
+        info!(
+            tag = "OK",
+            repaired = summary.repaired,
+            irreparable = summary.irreparable,
+            "Assets restored from the backup. The report below shows the state before the repair"
+        );
+        record(
+            &config.ledger,
+            "repair.from-backup",
+            &[ids, summary.keys.clone()].concat(),
+            &[
+                ("backup", backup.display().to_string()),
+                ("repaired", summary.repaired.to_string()),
+                ("irreparable", summary.irreparable.to_string()),
+            ],
+        );
+        purge_cdn(&config.cdn, &summary.keys);
+    } else {
+        info!(
+            tag = "OK",
+            repairable = summary.repaired,
+            irreparable = summary.irreparable,
+            "Dry run: nothing restored. Re-run with --execute to restore these assets"
+        );
+    }
+    Ok(())
+}
+
+fn convert_encryption(
+    db_path: &str,
+    open_opts: &db::OpenOptions,
+    config: &config::Config,
+    key: &encryption::Key,
+    direction: encryption::Direction,
+    execute: bool,
+) -> Result<ExitCode> {
+    let mut targets: Vec<encryption::Target> = std::iter::once("data")
+        .chain(config.warden.extra_blob_columns.iter().map(String::as_str))
+        .map(|column| encryption::Target {
+            table: "images".to_string(),
+            column: column.to_string(),
+        })
+        .collect();
+    if let Some(derived) = &config.warden.derived {
+        targets.push(encryption::Target {
+            table: derived.table.clone(),
+            column: derived.data_column.clone(),
+        });
+    }
+
+    let mut conn = if execute {
+        db::open_read_write(db_path, open_opts)?
+    } else {
+        db::open_read_only(db_path, open_opts)?
+    };
+    // A blob is named by its content, so it cannot be rewritten in place.
+    if cas::enabled(&conn)? {
+        error!(
+            tag = "FATAL",
+            "The database is content-addressed. Convert it back with --inline first"
+        );
+        return Ok(Kind::Usage.exit_code());
+    }
+    let summary = encryption::convert(&mut conn, key, direction, &targets, execute)?;
+
+    let verb = match (direction, execute) {
+        (encryption::Direction::Encrypt, true) => "BLOBs encrypted",
+        (encryption::Direction::Decrypt, true) => "BLOBs decrypted",
+        (encryption::Direction::Encrypt, false) => "Dry run: BLOBs that would be encrypted",
+        (encryption::Direction::Decrypt, false) => "Dry run: BLOBs that would be decrypted",
+    };
+    info!(
+        tag = "OK",
+        converted = summary.converted,
+        already_done = summary.done,
+        skipped = summary.skipped,
+        bytes_before = summary.bytes_before,
+        bytes_after = summary.bytes_after,
+        "{}",
+        verb
+    );
+    if !execute {
+        info!(
+            tag = "OK",
+            "Nothing written. Re-run with --execute to convert"
+        );
+    }
+
+    Ok(if execute && summary.skipped > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn convert_layout(
+    db_path: &str,
+    open_opts: &db::OpenOptions,
+    direction: cas::Direction,
+    execute: bool,
+) -> Result<ExitCode> {
+    let mut conn = if execute {
+        db::open_read_write(db_path, open_opts)?
+    } else {
+        db::open_read_only(db_path, open_opts)?
+    };
+    let summary = cas::convert(&mut conn, direction, execute)?;
+
+    let verb = match (direction, execute) {
+        (cas::Direction::ContentAddressed, true) => "Assets moved into blobs",
+        (cas::Direction::Inline, true) => "Assets moved back inline",
+        (cas::Direction::ContentAddressed, false) => "Dry run: assets that would move into blobs",
+        (cas::Direction::Inline, false) => "Dry run: assets that would move back inline",
+    };
+    info!(
+        tag = "OK",
+        converted = summary.converted,
+        already_done = summary.done,
+        bytes = summary.bytes,
+        written = summary.written,
+        "{}",
+        verb
+    );
+    if direction == cas::Direction::ContentAddressed && summary.bytes > summary.written {
+        info!(
+            tag = "OK",
+            bytes = summary.bytes - summary.written,
+            "Duplicate bytes shared; VACUUM returns them to the file system"
+        );
+    }
+    if !execute {
+        info!(
+            tag = "OK",
+            "Nothing written. Re-run with --execute to convert"
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn verify_blobs(db_path: &str, open_opts: &db::OpenOptions) -> Result<ExitCode> {
+    let conn = db::open_read_only(db_path, open_opts)?;
+    if !cas::enabled(&conn)? {
+        info!(tag = "OK", "Database is not content-addressed, no blobs to verify");
+        return Ok(ExitCode::SUCCESS);
+    }
+    let verification = cas::verify(&conn)?;
+    for hash in &verification.corrupt {
+        warn!(tag = "CORRUPT", hash = %hash, "Blob does not match its hash");
+    }
+    for (id, hash) in &verification.dangling {
+        warn!(tag = "MISSING", asset = %id, hash = %hash, "Asset refers to a blob that does not exist");
+    }
+    if !verification.orphaned.is_empty() {
+        info!(
+            tag = "INFO",
+            blobs = verification.orphaned.len(),
+            "Blobs no asset refers to"
+        );
+    }
+    info!(
+        tag = if verification.is_clean() { "OK" } else { "WARN" },
+        blobs = verification.blobs,
+        corrupt = verification.corrupt.len(),
+        dangling = verification.dangling.len(),
+        "Blobs verified"
+    );
+    Ok(if verification.is_clean() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn migrate_schema(
+    db_path: &str,
+    open_opts: &db::OpenOptions,
+    image_columns: &[String],
+) -> Result<ExitCode> {
+    let steps = schema::diagnose(&db::open_read_only(db_path, open_opts)?, image_columns)?;
+    if steps.is_empty() {
+        info!(tag = "OK", "Schema already matches the server's");
+        return Ok(ExitCode::SUCCESS);
+    }
+    for step in &steps {
+        info!(tag = "SCHEMA", step = %step, "Schema drift");
+    }
+
+    let backup = schema::backup_path(db_path);
+    db::backup(db_path, open_opts, &backup)?;
+    info!(tag = "OK", path = %backup.display(), "Pre-migration backup written");
+
+    let mut conn = db::open_read_write(db_path, open_opts)?;
+    match schema::migrate(&mut conn, &steps) {
+        Ok(()) => {
+            info!(tag = "OK", steps = steps.len(), "Schema migrated");
+            Ok(ExitCode::SUCCESS)
+        }
+        Err(e) => {
+            error!(tag = "ERROR", reason = %e, "Migration rolled back, the database is unchanged");
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
+
+fn apply_plan(
+    db_path: &str,
+    open_opts: &db::OpenOptions,
+    path: &Path,
+    config: &config::Config,
+) -> Result<ExitCode> {
+    let plan = match plan::Plan::load(path) {
+        Ok(plan) => plan,
+        Err(e) => {
+            error!(tag = "FATAL", path = %path.display(), reason = %e, "Could not read action plan");
+            return Ok(Kind::Data.exit_code());
+        }
+    };
+
+    info!(
+        tag = "→",
+        path = %path.display(),
+        actions = plan.entries.len(),
+        "Applying action plan"
+    );
+    let summary = plan::apply(db_path, open_opts, &plan)?;
+    info!(
+        tag = "OK",
+        applied = summary.applied,
+        skipped = summary.skipped,
+        "Action plan applied"
+    );
+    let mut detail = vec![
+        ("plan", path.display().to_string()),
+        ("applied", summary.applied.to_string()),
+        ("skipped", summary.skipped.to_string()),
+    ];
+    for action in [
+        plan::Action::Quarantine,
+        plan::Action::Delete,
+        plan::Action::Repair,
+    ] {
+        let count = plan.entries.iter().filter(|e| e.action == action).count();
+        if count > 0 {
+            detail.push((action.as_str(), count.to_string()));
+        }
+    }
+    let ids: Vec<String> = plan.entries.iter().map(|e| e.id.clone()).collect();
+    record(
+        &config.ledger,
+        "triage.apply",
+        &[ids, summary.keys.clone()].concat(),
+        &detail,
+    );
+    purge_cdn(&config.cdn, &summary.keys);
+
+    Ok(if summary.skipped > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Appends a write that already happened to the ledger, when `ledger.path`
+/// is set. Failures are logged, never fatal, like purges.
+fn record(ledger: &LedgerConfig, action: &str, targets: &[String], detail: &[(&str, String)]) {
+    let recorded = match Ledger::open(ledger, "octa-warden") {
+        Ok(Some(ledger)) => ledger.record(action, targets, detail),
+        Ok(None) => return,
+        Err(e) => Err(e),
+    };
+    match recorded {
+        Ok(entry) => info!(
+            tag = "LEDGER",
+            seq = entry.seq,
+            action,
+            targets = targets.len(),
+            "Recorded"
+        ),
+        Err(e) => error!(tag = "FAIL", action, reason = %e, "Change not recorded in the ledger"),
+    }
+}
+
+/// Invalidates the edge copies of keys whose asset was just rewritten or
+/// removed, when `cdn` is configured. Failures are logged, never fatal: the
+/// database change has already happened.
+fn purge_cdn(cdn: &CdnConfig, keys: &[String]) {
+    if keys.is_empty() {
+        return;
+    }
+    let purger = match Purger::new(cdn, Duration::from_secs(30)) {
+        Ok(Some(purger)) => purger,
+        Ok(None) => return,
+        Err(e) => {
+            warn!(tag = "WARN", reason = %e, keys = keys.len(), "Edge cache not purged; run octa-ctl purge for these keys");
+            return;
+        }
+    };
+    match purger.purge_keys(keys) {
+        Ok(purged) => info!(
+            tag = "PURGE",
+            provider = purger.provider().as_str(),
+            keys = keys.len(),
+            urls = purged.urls,
+            "Edge cache purged"
+        ),
+        Err(e) => {
+            warn!(tag = "WARN", reason = %e, keys = keys.len(), "Edge cache not purged; run octa-ctl purge for these keys")
+        }
+    }
+}
+
+fn parse_percent(value: &str) -> Result<f64, String> {
+    match value.trim_end_matches('%').parse::<f64>() {
+        Ok(pct) if pct > 0.0 && pct <= 100.0 => Ok(pct),
+        _ => Err(format!("'{}' is not a percentage between 0 and 100", value)),
+    }
+}
+
+fn init_config(
+    db_path: Option<&str>,
+    out: &Path,
+    force: bool,
+    busy_timeout: u64,
+) -> Result<ExitCode> {
+    let Some(db_path) = db_path else {
+        error!(
+            tag = "FATAL",
+            "init needs the database to inspect: pass --db or set OCTA_DB_PATH"
+        );
+        return Ok(Kind::Usage.exit_code());
+    };
+    if !Path::new(db_path).exists() {
+        error!(tag = "FATAL", path = %db_path, "Database file not found");
+        return Ok(Kind::NoInput.exit_code());
+    }
+    if out.exists() && !force {
+        error!(tag = "FATAL", path = %out.display(), "Config file already exists, use --force to overwrite it");
+        return Ok(Kind::Usage.exit_code());
+    }
+
+    let open_opts = db::OpenOptions {
+        busy_timeout: Duration::from_millis(busy_timeout),
+        immutable: false,
+    };
+    let conn = db::open_read_only(db_path, &open_opts)?;
+    let layout = scaffold::inspect(&conn)?;
+    if let Err(e) = std::fs::write(out, scaffold::render(db_path, &layout)) {
+        error!(tag = "FATAL", path = %out.display(), reason = %e, "Could not write config file");
+        return Ok(octa_errors::Error::from(e).exit_code());
+    }
+
+    if layout.images.is_none() {
+        warn!(
+            tag = "WARN",
+            "No images table found; the config needs editing before an audit can run"
+        );
+    }
+    for column in layout.missing_columns() {
+        warn!(
+            tag = "WARN",
+            column, "images lacks a column the audit needs"
+        );
+    }
+    info!(
+        tag = "OK",
+        path = %out.display(),
+        rows = layout.rows,
+        extra_blob_columns = layout.extra_blob_columns.len(),
+        derived = layout.derived.as_ref().map(|d| d.table.as_str()),
+        "Config written. Review it, then run octa-warden -c {}",
+        out.display()
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Exports the scan spans (`warden.scan`, a `warden.batch` per 1000 rows)
+/// while the returned guard lives; `None` without `telemetry.endpoint`.
+fn telemetry(config: &config::TelemetryConfig) -> Option<octa_logging::otel::Telemetry> {
+    let endpoint = config.endpoint()?;
+    match octa_logging::otel::start("octa-warden", endpoint, config.sample_ratio) {
+        Ok(telemetry) => {
+            info!(tag = "→", endpoint, "Exporting traces");
+            Some(telemetry)
+        }
+        Err(e) => {
+            warn!(tag = "WARN", endpoint, reason = %e, "Tracing disabled");
+            None
+        }
+    }
+}
+
+fn print_banner() {
+    println!("{}\n", style("Octa Warden - Database Health Check").dim());
+}
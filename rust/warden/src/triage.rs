@@ -0,0 +1,466 @@
+use image::{load_from_memory, RgbImage};
+use octa_warden_core::audit::{Finding, Severity};
+use octa_warden_core::plan::{Action, Plan, PlanEntry};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use rusqlite::{Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+const HEX_PREVIEW_BYTES: usize = 64;
+
+/// What the detail pane shows for the selected asset. Loaded lazily and cached.
+struct Detail {
+    storage: String,
+    stored_bytes: i64,
+    declared_size: Option<i64>,
+    format: Option<String>,
+    dimensions: Option<(i64, i64)>,
+    keys: Vec<String>,
+    head: Vec<u8>,
+    /// Decoded image, when the bytes decode at all.
+    thumbnail: Option<RgbImage>,
+}
+
+struct App<'a> {
+    conn: &'a Connection,
+    findings: Vec<Finding>,
+    visible: Vec<usize>,
+    list: ListState,
+    filter: String,
+    editing_filter: bool,
+    marks: HashMap<String, PlanEntry>,
+    details: HashMap<String, Option<Detail>>,
+    dirty: bool,
+    status: String,
+}
+
+/// Opens the interactive browser over `findings`. Marks are loaded from and
+/// saved to `plan_path`, so a triage session can be resumed.
+pub fn run(
+    conn: &Connection,
+    db_path: &str,
+    findings: Vec<Finding>,
+    plan_path: &Path,
+) -> io::Result<usize> {
+    let mut marks = HashMap::new();
+    if plan_path.exists() {
+        for entry in Plan::load(plan_path)?.entries {
+            marks.insert(entry.id.clone(), entry);
+        }
+    }
+
+    let mut app = App {
+        conn,
+        visible: (0..findings.len()).collect(),
+        findings,
+        list: ListState::default().with_selected(Some(0)),
+        filter: String::new(),
+        editing_filter: false,
+        marks,
+        details: HashMap::new(),
+        dirty: false,
+        status: format!("Plan file: {}", plan_path.display()),
+    };
+
+    let mut terminal = ratatui::init();
+    let result = app.event_loop(&mut terminal, db_path, plan_path);
+    ratatui::restore();
+    result?;
+
+    Ok(app.marks.len())
+}
+
+impl App<'_> {
+    fn event_loop(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        db_path: &str,
+        plan_path: &Path,
+    ) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            if self.editing_filter {
+                match key.code {
+                    KeyCode::Enter => self.editing_filter = false,
+                    KeyCode::Esc => {
+                        self.editing_filter = false;
+                        self.filter.clear();
+                        self.apply_filter();
+                    }
+                    KeyCode::Backspace => {
+                        self.filter.pop();
+                        self.apply_filter();
+                    }
+                    KeyCode::Char(c) => {
+                        self.filter.push(c);
+                        self.apply_filter();
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+
+            let ctrl_c =
+                key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c');
+            match key.code {
+                _ if ctrl_c => break,
+                KeyCode::Esc => break,
+                KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+                KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+                KeyCode::PageDown => self.move_by(20),
+                KeyCode::PageUp => self.move_by(-20),
+                KeyCode::Home | KeyCode::Char('g') => self.list.select_first(),
+                KeyCode::End | KeyCode::Char('G') => self.list.select_last(),
+                KeyCode::Char('/') => self.editing_filter = true,
+                KeyCode::Char('q') => self.mark(Some(Action::Quarantine)),
+                KeyCode::Char('d') => self.mark(Some(Action::Delete)),
+                KeyCode::Char('r') => self.mark(Some(Action::Repair)),
+                KeyCode::Char('c') => self.mark(None),
+                KeyCode::Char('s') => self.save(db_path, plan_path),
+                _ => {}
+            }
+        }
+
+        if self.dirty {
+            self.save(db_path, plan_path);
+        }
+        Ok(())
+    }
+
+    fn selected(&self) -> Option<&Finding> {
+        let idx = *self.visible.get(self.list.selected()?)?;
+        self.findings.get(idx)
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let current = self.list.selected().unwrap_or(0) as isize;
+        let next = (current + delta).clamp(0, self.visible.len() as isize - 1);
+        self.list.select(Some(next as usize));
+    }
+
+    fn apply_filter(&mut self) {
+        let needle = self.filter.to_lowercase();
+        self.visible = self
+            .findings
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| {
+                needle.is_empty()
+                    || f.id
+                        .as_deref()
+                        .unwrap_or("")
+                        .to_lowercase()
+                        .contains(&needle)
+                    || f.kind.as_str().contains(&needle)
+                    || f.severity.as_str().contains(&needle)
+                    || f.reason.to_lowercase().contains(&needle)
+            })
+            .map(|(i, _)| i)
+            .collect();
+        self.list.select((!self.visible.is_empty()).then_some(0));
+    }
+
+    fn mark(&mut self, action: Option<Action>) {
+        let Some(finding) = self.selected().cloned() else {
+            return;
+        };
+        let Some(id) = finding.id else {
+            self.status = "This finding has no asset ID and cannot be acted on".to_string();
+            return;
+        };
+        match action {
+            Some(action) => {
+                self.status = format!("{} marked for {}", id, action.as_str());
+                let entry = PlanEntry {
+                    id: id.clone(),
+                    action,
+                    kind: finding.kind.as_str().to_string(),
+                    reason: finding.reason,
+                };
+                self.marks.insert(id, entry);
+            }
+            None => {
+                self.status = format!("{} unmarked", id);
+                self.marks.remove(&id);
+            }
+        }
+        self.dirty = true;
+        self.move_by(1);
+    }
+
+    fn save(&mut self, db_path: &str, plan_path: &Path) {
+        let mut entries: Vec<PlanEntry> = self.marks.values().cloned().collect();
+        entries.sort_by(|a, b| a.id.cmp(&b.id));
+
+        self.status = match Plan::new(db_path, entries).save(plan_path) {
+            Ok(()) => {
+                self.dirty = false;
+                format!(
+                    "Saved {} action(s) to {}",
+                    self.marks.len(),
+                    plan_path.display()
+                )
+            }
+            Err(e) => format!("Could not save plan: {}", e),
+        };
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, footer] =
+            Layout::vertical([Constraint::Min(5), Constraint::Length(2)]).areas(frame.area());
+        let [left, right] =
+            Layout::horizontal([Constraint::Percentage(45), Constraint::Percentage(55)])
+                .areas(main);
+
+        self.draw_list(frame, left);
+        self.draw_detail(frame, right);
+
+        let filter = if self.editing_filter {
+            Line::from(vec![
+                Span::raw("Filter: ").bold(),
+                Span::raw(format!("{}_", self.filter)),
+            ])
+        } else {
+            Line::from(self.status.clone()).dim()
+        };
+        let help = Line::from(
+            "↑/↓ move  / filter  q quarantine  d delete  r repair  c clear  s save  Esc quit",
+        )
+        .dim();
+        frame.render_widget(Paragraph::new(vec![filter, help]), footer);
+    }
+
+    fn draw_list(&mut self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .visible
+            .iter()
+            .map(|&i| {
+                let f = &self.findings[i];
+                let id = f.id.as_deref().unwrap_or("-");
+                let mark = match f
+                    .id
+                    .as_ref()
+                    .and_then(|id| self.marks.get(id))
+                    .map(|e| e.action)
+                {
+                    Some(Action::Quarantine) => Span::raw("[Q] ").yellow(),
+                    Some(Action::Delete) => Span::raw("[D] ").red(),
+                    Some(Action::Repair) => Span::raw("[R] ").green(),
+                    None => Span::raw("    "),
+                };
+                ListItem::new(Line::from(vec![
+                    mark,
+                    Span::styled(
+                        format!("{:<8} ", f.severity.as_str()),
+                        severity_style(f.severity),
+                    ),
+                    Span::raw(id.to_string()),
+                    Span::raw(format!("  {}", f.kind.as_str())).dim(),
+                ]))
+            })
+            .collect();
+
+        let title = format!(
+            " Findings {}/{} | marked {} ",
+            self.visible.len(),
+            self.findings.len(),
+            self.marks.len()
+        );
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, area, &mut self.list);
+    }
+
+    fn draw_detail(&mut self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title(" Asset ");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let Some(finding) = self.selected().cloned() else {
+            frame.render_widget(Paragraph::new("No findings match the filter.").dim(), inner);
+            return;
+        };
+
+        let mut lines = vec![
+            field("ID", finding.id.as_deref().unwrap_or("-")),
+            field("Kind", finding.kind.as_str()),
+            field("Column", finding.column.as_deref().unwrap_or("data")),
+            Line::from(vec![
+                Span::raw("Severity: ").bold(),
+                Span::styled(finding.severity.as_str(), severity_style(finding.severity)),
+            ]),
+            field("Error", &finding.reason),
+        ];
+
+        let detail = match &finding.id {
+            Some(id) => {
+                let conn = self.conn;
+                self.details
+                    .entry(id.clone())
+                    .or_insert_with(|| load_detail(conn, id))
+                    .as_ref()
+            }
+            None => None,
+        };
+
+        let Some(detail) = detail else {
+            lines.push(Line::from("Row is no longer readable.").dim());
+            frame.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }), inner);
+            return;
+        };
+
+        lines.push(field("Storage", &detail.storage));
+        lines.push(field("Bytes", &detail.stored_bytes.to_string()));
+        if let Some(size) = detail.declared_size {
+            lines.push(field("Size col", &size.to_string()));
+        }
+        if let Some(format) = &detail.format {
+            lines.push(field("Format", format));
+        }
+        if let Some((w, h)) = detail.dimensions {
+            lines.push(field("Dimensions", &format!("{}x{}", w, h)));
+        }
+        lines.push(field(
+            "Keys",
+            &if detail.keys.is_empty() {
+                "-".to_string()
+            } else {
+                detail.keys.join(", ")
+            },
+        ));
+        lines.push(Line::from(""));
+        lines.push(Line::from("Hex preview").bold());
+        for chunk in detail.head.chunks(16) {
+            lines.push(Line::from(hex_line(chunk)).dim());
+        }
+
+        let text_height = lines.len() as u16 + 1;
+        let [text_area, thumb_area] =
+            Layout::vertical([Constraint::Length(text_height), Constraint::Min(0)]).areas(inner);
+        frame.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }), text_area);
+
+        match &detail.thumbnail {
+            Some(img) if thumb_area.height > 1 => {
+                frame.render_widget(Paragraph::new(thumbnail_lines(img, thumb_area)), thumb_area)
+            }
+            Some(_) => {}
+            None => frame.render_widget(
+                Paragraph::new("(no preview: data does not decode)").dim(),
+                thumb_area,
+            ),
+        }
+    }
+}
+
+fn field<'a>(name: &'a str, value: &str) -> Line<'a> {
+    Line::from(vec![
+        Span::raw(format!("{}: ", name)).bold(),
+        Span::raw(value.to_string()),
+    ])
+}
+
+fn severity_style(severity: Severity) -> Style {
+    match severity {
+        Severity::Critical => Style::new().fg(Color::Red),
+        Severity::Warning => Style::new().fg(Color::Yellow),
+        Severity::Info => Style::new().fg(Color::DarkGray),
+    }
+}
+
+fn hex_line(bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    let ascii: String = bytes
+        .iter()
+        .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+        .collect();
+    format!("{:<48} {}", hex.join(" "), ascii)
+}
+
+fn load_detail(conn: &Connection, id: &str) -> Option<Detail> {
+    let row = conn
+        .query_row(
+            "SELECT typeof(data), length(CAST(data AS BLOB)), CAST(data AS BLOB),
+                    size, format, width, height
+             FROM images WHERE id = ?1",
+            [id],
+            |row| {
+                let width: Option<i64> = row.get(5)?;
+                let height: Option<i64> = row.get(6)?;
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<i64>>(1)?.unwrap_or(0),
+                    row.get::<_, Option<Vec<u8>>>(2)?.unwrap_or_default(),
+                    row.get::<_, Option<i64>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    width.zip(height),
+                ))
+            },
+        )
+        .optional()
+        .ok()??;
+
+    let keys = conn
+        .prepare("SELECT key FROM key_mappings WHERE image_id = ?1")
+        .and_then(|mut stmt| {
+            stmt.query_map([id], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()
+        })
+        .unwrap_or_default();
+
+    let (storage, stored_bytes, data, declared_size, format, dimensions) = row;
+    Some(Detail {
+        storage,
+        stored_bytes,
+        declared_size,
+        format,
+        dimensions,
+        keys,
+        head: data.iter().take(HEX_PREVIEW_BYTES).copied().collect(),
+        thumbnail: load_from_memory(&data)
+            .ok()
+            .map(|img| img.thumbnail(128, 128).to_rgb8()),
+    })
+}
+
+/// Renders the image with half blocks: each cell shows two pixels stacked
+/// (foreground = upper, background = lower).
+fn thumbnail_lines(img: &RgbImage, area: Rect) -> Vec<Line<'static>> {
+    let scaled = image::imageops::thumbnail(img, area.width as u32, area.height as u32 * 2);
+    let (w, h) = scaled.dimensions();
+
+    (0..h.div_ceil(2))
+        .map(|row| {
+            let spans: Vec<Span> = (0..w)
+                .map(|x| {
+                    let top = scaled.get_pixel(x, row * 2);
+                    let bottom = scaled.get_pixel_checked(x, row * 2 + 1).unwrap_or(top);
+                    Span::styled(
+                        "▀",
+                        Style::new()
+                            .fg(Color::Rgb(top[0], top[1], top[2]))
+                            .bg(Color::Rgb(bottom[0], bottom[1], bottom[2])),
+                    )
+                })
+                .collect();
+            Line::from(spans)
+        })
+        .collect()
+}
@@ -0,0 +1,43 @@
+use octa_warden_core::db::{self, OpenOptions};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read};
+
+/// Every key in `key_mappings`, in key order. The database is opened
+/// read-only, so this is safe against the live file.
+pub fn from_database(path: &str, opts: &OpenOptions, prefix: &str) -> Result<Vec<String>, String> {
+    let conn = db::open_read_only(path, opts).map_err(|e| e.to_string())?;
+    let mut stmt = conn
+        .prepare("SELECT key FROM key_mappings WHERE substr(key, 1, length(?1)) = ?1 ORDER BY key")
+        .map_err(|e| e.to_string())?;
+    let keys = stmt
+        .query_map([prefix], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<String>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(keys)
+}
+
+/// One key per line (`-` reads stdin); blank lines and `#` comments are
+/// skipped, keys are normalized the way the server stores them and repeats
+/// are dropped.
+pub fn from_file(path: &str, prefix: &str) -> Result<Vec<String>, String> {
+    let text = if path == "-" {
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .map_err(|e| format!("stdin: {}", e))?;
+        text
+    } else {
+        fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?
+    };
+    let mut seen = HashSet::new();
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(octa_key::normalize)
+        .filter(|key| !key.is_empty() && key.starts_with(prefix))
+        .filter(|key| seen.insert(key.clone()))
+        .collect())
+}
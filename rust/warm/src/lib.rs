@@ -0,0 +1,268 @@
+use clap::Parser;
+use octa_config::{ConfigError, DatabaseConfig, ServerConfig, Validate};
+use octa_errors::Kind;
+use octa_logging::LogFormat;
+use octa_warden_core::db::OpenOptions;
+use octa_warden_core::growth::format_bytes;
+use serde::Deserialize;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn, Level};
+
+mod keys;
+mod warm;
+
+/*
+OCTA-WARM: Cache and thumbnail pre-warmer
+=============================================
+Mission: Request every key (from the database or a key list) in each
+         configured serving variant against a running instance, so caches
+         and derived sizes are hot before a traffic spike or after a wipe.
+Safety:  Reads only. The database is opened read-only and the target only
+         sees GET requests, at most --concurrency at a time.
+*/
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Pre-warm an Octa instance's caches by requesting every asset"
+)]
+struct Args {
+    /// Path to the configuration file (default: $OCTA_CONFIG, else config.yaml in this or a parent directory)
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Instance to warm (overrides base_url)
+    #[arg(long, env = "OCTA_WARM_URL")]
+    url: Option<String>,
+
+    /// SQLite database to read keys from (overrides database.path)
+    #[arg(long = "db", env = "OCTA_DB_PATH", value_name = "PATH")]
+    db_path: Option<String>,
+
+    /// Read keys from this file instead of the database, one per line (`-` for stdin)
+    #[arg(long, value_name = "PATH")]
+    keys: Option<String>,
+
+    /// Only keys starting with this
+    #[arg(long, default_value = "")]
+    prefix: String,
+
+    /// Query string to request for every key, e.g. `size=64` (repeatable; overrides warm.variants)
+    #[arg(long = "variant", value_name = "QUERY")]
+    variants: Vec<String>,
+
+    /// Requests in flight at once (overrides warm.concurrency)
+    #[arg(long)]
+    concurrency: Option<usize>,
+
+    /// Seconds before a request is abandoned
+    #[arg(long, default_value_t = 30)]
+    timeout: u64,
+
+    /// Milliseconds to wait on a locked database before giving up (SQLITE_BUSY)
+    #[arg(long, default_value_t = 5000)]
+    busy_timeout: u64,
+
+    /// List the URLs instead of requesting them
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Log format
+    #[arg(long, env = "OCTA_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+}
+
+/// The parts of config.yaml octa-warm reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    server: ServerConfig,
+    database: DatabaseConfig,
+    base_url: Option<String>,
+    warm: WarmConfig,
+}
+
+/// `warm:`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct WarmConfig {
+    /// Query strings requested for every key; `""` is the stored image.
+    variants: Vec<String>,
+    concurrency: usize,
+}
+
+impl Default for WarmConfig {
+    fn default() -> Self {
+        Self {
+            variants: vec![String::new()],
+            concurrency: 8,
+        }
+    }
+}
+
+impl Validate for FileConfig {
+    fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = self.server.validate();
+        problems.extend(octa_config::check_url("base_url", self.base_url.as_deref()));
+        if self.warm.variants.is_empty() {
+            problems.push((
+                "warm.variants".to_string(),
+                "needs at least one entry (\"\" for the stored image)".to_string(),
+            ));
+        }
+        if self.warm.concurrency == 0 {
+            problems.push((
+                "warm.concurrency".to_string(),
+                "must be at least 1".to_string(),
+            ));
+        }
+        problems
+    }
+}
+
+/// Without a file, the environment alone can configure warm (e.g. in CI).
+fn load_config(path: Option<&str>) -> Result<FileConfig, ConfigError> {
+    match octa_config::discover(path.map(Path::new)) {
+        Some(path) => octa_config::load(&path),
+        None => {
+            let config: FileConfig = octa_config::from_env()?;
+            octa_config::check(Path::new("<environment>"), config)
+        }
+    }
+}
+
+/// `<base>/u/<key>`, with the variant as query string.
+fn url(base: &str, key: &str, variant: &str) -> String {
+    match variant.trim_start_matches('?') {
+        "" => format!("{}/u/{}", base, key),
+        query => format!("{}/u/{}?{}", base, key, query),
+    }
+}
+
+/// The octa-warm command line, on `args` with the program name first: the
+/// binary passes `std::env::args_os()`, `octa warm` the arguments it was given.
+#[tokio::main]
+pub async fn cli(args: impl IntoIterator<Item = std::ffi::OsString>) -> ExitCode {
+    let args = Args::parse_from(args);
+    octa_logging::init(args.log_format, Level::INFO, false, args.dry_run);
+
+    let config = match load_config(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Invalid config");
+            return Kind::Config.exit_code();
+        }
+    };
+    if let Some((field, problem)) = octa_config::check_url("--url", args.url.as_deref()) {
+        error!(tag = "FATAL", reason = %format!("{}: {}", field, problem), "Invalid arguments");
+        return Kind::Usage.exit_code();
+    }
+    if args.concurrency == Some(0) {
+        error!(
+            tag = "FATAL",
+            reason = "--concurrency: must be at least 1",
+            "Invalid arguments"
+        );
+        return Kind::Usage.exit_code();
+    }
+    let base_url = octa_config::base_url(
+        args.url.as_deref().or(config.base_url.as_deref()),
+        &config.server,
+    );
+
+    let keys = match &args.keys {
+        Some(path) => keys::from_file(path, &args.prefix),
+        None => {
+            let db_path = args.db_path.as_deref().unwrap_or(&config.database.path);
+            if !Path::new(db_path).exists() {
+                error!(tag = "FATAL", path = %db_path, "Database file not found (pass --db, or --keys for a key list)");
+                return Kind::NoInput.exit_code();
+            }
+            let opts = OpenOptions {
+                busy_timeout: Duration::from_millis(args.busy_timeout),
+                immutable: false,
+            };
+            keys::from_database(db_path, &opts, &args.prefix)
+        }
+    };
+    let keys = match keys {
+        Ok(keys) => keys,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Could not read keys");
+            return Kind::Io.exit_code();
+        }
+    };
+
+    let variants = if args.variants.is_empty() {
+        &config.warm.variants
+    } else {
+        &args.variants
+    };
+    let urls: Vec<String> = keys
+        .iter()
+        .flat_map(|key| variants.iter().map(|v| url(&base_url, key, v)))
+        .collect();
+    if urls.is_empty() {
+        info!(tag = "OK", "No keys to warm");
+        return ExitCode::SUCCESS;
+    }
+    if args.dry_run {
+        for url in &urls {
+            println!("{}", url);
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(args.timeout))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            error!(tag = "FATAL", reason = %e, "Could not create HTTP client");
+            return Kind::Internal.exit_code();
+        }
+    };
+    if let Err(e) = client.get(&base_url).send().await {
+        error!(tag = "FATAL", url = %base_url, reason = %e, "Target is unreachable");
+        return Kind::Unavailable.exit_code();
+    }
+
+    let concurrency = args.concurrency.unwrap_or(config.warm.concurrency);
+    info!(
+        tag = "→",
+        url = %base_url,
+        keys = keys.len(),
+        variants = variants.len(),
+        concurrency,
+        "Warming"
+    );
+    let started = Instant::now();
+    let stats = warm::run(&client, urls, concurrency).await;
+    let elapsed = started.elapsed();
+
+    info!(
+        tag = if stats.failed == 0 { "OK" } else { "WARN" },
+        requests = stats.requests,
+        ok = stats.ok,
+        missing = stats.missing,
+        failed = stats.failed,
+        size = %format_bytes(stats.bytes as f64),
+        p50 = ?stats.percentile(50),
+        p99 = ?stats.percentile(99),
+        elapsed = ?elapsed,
+        "Warm-up finished"
+    );
+    if stats.failed > 0 {
+        warn!(
+            tag = "WARN",
+            failed = stats.failed,
+            "Some requests failed; their caches are still cold"
+        );
+        return Kind::Unavailable.exit_code();
+    }
+    ExitCode::SUCCESS
+}
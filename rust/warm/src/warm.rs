@@ -0,0 +1,89 @@
+use futures::stream::{self, StreamExt};
+use reqwest::{Client, StatusCode};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// How one pass went.
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub requests: u64,
+    pub ok: u64,
+    /// 404: the key is gone (or was never there) on the target.
+    pub missing: u64,
+    /// Other statuses and transport errors.
+    pub failed: u64,
+    pub bytes: u64,
+    latencies: Vec<Duration>,
+}
+
+impl Stats {
+    /// The `q`th latency percentile (0-100).
+    pub fn percentile(&self, q: usize) -> Duration {
+        let mut sorted = self.latencies.clone();
+        sorted.sort();
+        match sorted.len() {
+            0 => Duration::ZERO,
+            n => sorted[(n * q / 100).min(n - 1)],
+        }
+    }
+}
+
+enum Outcome {
+    Ok(u64),
+    Missing,
+    Failed(String),
+}
+
+/// Requests every URL, at most `concurrency` at a time, reading each body to
+/// the end so the server finishes (and caches) the response.
+pub async fn run(client: &Client, urls: Vec<String>, concurrency: usize) -> Stats {
+    let total = urls.len() as u64;
+    let step = (total / 10).max(1);
+    let mut stats = Stats::default();
+
+    let mut responses = stream::iter(urls)
+        .map(|url| async move {
+            let started = Instant::now();
+            let outcome = fetch(client, &url).await;
+            (url, outcome, started.elapsed())
+        })
+        .buffer_unordered(concurrency.max(1));
+
+    while let Some((url, outcome, elapsed)) = responses.next().await {
+        stats.requests += 1;
+        stats.latencies.push(elapsed);
+        match outcome {
+            Outcome::Ok(bytes) => {
+                stats.ok += 1;
+                stats.bytes += bytes;
+            }
+            Outcome::Missing => {
+                stats.missing += 1;
+                warn!(tag = "MISS", url = %url, "Not found on the target");
+            }
+            Outcome::Failed(reason) => {
+                stats.failed += 1;
+                warn!(tag = "FAIL", url = %url, reason = %reason, "Request failed");
+            }
+        }
+        if stats.requests % step == 0 && stats.requests < total {
+            info!(tag = "→", done = stats.requests, total, "Warming");
+        }
+    }
+    stats
+}
+
+async fn fetch(client: &Client, url: &str) -> Outcome {
+    let response = match client.get(url).send().await {
+        Ok(response) => response,
+        Err(e) => return Outcome::Failed(e.to_string()),
+    };
+    match response.status() {
+        StatusCode::NOT_FOUND => Outcome::Missing,
+        status if status.is_success() => match response.bytes().await {
+            Ok(body) => Outcome::Ok(body.len() as u64),
+            Err(e) => Outcome::Failed(e.to_string()),
+        },
+        status => Outcome::Failed(format!("HTTP {}", status)),
+    }
+}